@@ -1,3 +1,34 @@
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+/// Embed the SHA-256 of the bundled sidecar so the app can check the installed copy before
+/// running it. Builds without the binary (it's fetched separately) just don't embed one.
+fn embed_sidecar_digest() {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let extension = if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    let path = PathBuf::from("binaries").join(format!("zerobyte-server-{}{}", target, extension));
+    println!("cargo:rerun-if-changed={}", path.display());
+    match std::fs::read(&path) {
+        Ok(bytes) => {
+            let digest: String = Sha256::digest(&bytes)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            println!("cargo:rustc-env=ZEROBYTE_SIDECAR_SHA256={}", digest);
+        }
+        Err(e) => println!(
+            "cargo:warning=Not embedding a sidecar digest, {} unreadable: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
 fn main() {
+    embed_sidecar_digest();
     tauri_build::build()
 }
@@ -0,0 +1,352 @@
+//! Classification of backup source paths the backend can't read, and the ACL change
+//! offered to fix them on Windows.
+
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+/// Why a path can or can't be read by the current process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathAccess {
+    Ok,
+    NotFound,
+    AccessDenied,
+    /// Denied, and the path is a system folder or another user's profile that only an
+    /// administrator (or the service account) can normally read
+    RequiresElevation,
+    /// A cloud-synced file that isn't downloaded; reading it triggers a download
+    CloudPlaceholder,
+    /// Any other I/O error
+    Error,
+}
+
+/// Access check result for one path
+#[derive(Debug, Clone, Serialize)]
+pub struct PathAccessResult {
+    pub path: String,
+    pub status: PathAccess,
+    pub detail: Option<String>,
+    /// What the user can do about it, if anything
+    pub remediation: Option<String>,
+    /// Controlled Folder Access would block the backend writing here (e.g. a restore)
+    pub controlled_folder_access: bool,
+}
+
+/// Try to enumerate `path` as the current process and classify the outcome
+pub fn check_path(path: &Path, current_user: &str, using_service: bool) -> PathAccessResult {
+    let (status, detail) = match probe(path) {
+        Ok(()) if is_cloud_placeholder(path) => (PathAccess::CloudPlaceholder, None),
+        Ok(()) => (PathAccess::Ok, None),
+        Err(e) => {
+            let status = classify_error(&e, path, current_user);
+            (status, Some(e.to_string()))
+        }
+    };
+
+    PathAccessResult {
+        path: path.display().to_string(),
+        status,
+        detail,
+        remediation: remediation(status, using_service).map(str::to_string),
+        controlled_folder_access: false,
+    }
+}
+
+/// Flag a result for a folder Controlled Folder Access keeps the backend from writing to
+pub fn mark_controlled_folder(result: &mut PathAccessResult) {
+    result.controlled_folder_access = true;
+    if result.remediation.is_none() {
+        result.remediation = Some(
+            "Windows Controlled Folder Access blocks restores into this folder. Allow the backup engine through it in settings.".to_string(),
+        );
+    }
+}
+
+/// Read a directory entry (or open a file) the way a backup would
+fn probe(path: &Path) -> io::Result<()> {
+    let metadata = std::fs::metadata(path)?;
+    if metadata.is_dir() {
+        // Listing the first entry is enough to prove we can enumerate it
+        std::fs::read_dir(path)?.next().transpose()?;
+    } else {
+        std::fs::File::open(path)?;
+    }
+    Ok(())
+}
+
+/// Map an I/O error to an access classification
+pub fn classify_error(error: &io::Error, path: &Path, current_user: &str) -> PathAccess {
+    match error.kind() {
+        io::ErrorKind::NotFound => PathAccess::NotFound,
+        io::ErrorKind::PermissionDenied if is_privileged_location(path, current_user) => {
+            PathAccess::RequiresElevation
+        }
+        io::ErrorKind::PermissionDenied => PathAccess::AccessDenied,
+        _ => PathAccess::Error,
+    }
+}
+
+/// System folders and other users' profiles, which standard users can't read
+pub fn is_privileged_location(path: &Path, current_user: &str) -> bool {
+    let normalized = path
+        .to_string_lossy()
+        .replace('/', "\\")
+        .to_lowercase();
+    let normalized = normalized.trim_end_matches('\\');
+
+    // Strip the drive letter so "C:\Windows" and "D:\Windows" compare alike
+    let without_drive = match normalized.split_once(":\\") {
+        Some((drive, rest)) if drive.len() == 1 => rest,
+        _ => normalized,
+    };
+
+    const SYSTEM_ROOTS: &[&str] = &[
+        "windows",
+        "program files",
+        "program files (x86)",
+        "programdata",
+        "system volume information",
+        "$recycle.bin",
+    ];
+    let first = without_drive.split('\\').next().unwrap_or_default();
+    if SYSTEM_ROOTS.contains(&first) {
+        return true;
+    }
+
+    let mut components = without_drive.split('\\');
+    if components.next() == Some("users") {
+        if let Some(profile) = components.next() {
+            return !profile.is_empty()
+                && profile != current_user.to_lowercase()
+                && profile != "public";
+        }
+    }
+
+    // Unix equivalents
+    let unix = path.to_string_lossy();
+    ["/root", "/etc", "/var/lib", "/proc", "/sys"]
+        .iter()
+        .any(|root| unix == *root || unix.starts_with(&format!("{}/", root)))
+}
+
+/// Suggested fix for a failed check
+pub fn remediation(status: PathAccess, using_service: bool) -> Option<&'static str> {
+    match (status, using_service) {
+        (PathAccess::Ok, _) => None,
+        (PathAccess::NotFound, _) => Some("Check that the path exists and the drive is connected."),
+        (PathAccess::CloudPlaceholder, _) => Some(
+            "This file is only stored in the cloud. Mark the folder as \"Always keep on this device\" so backups don't download it every time.",
+        ),
+        (PathAccess::RequiresElevation, false) => Some(
+            "This folder is protected. Install and switch to the Windows Service, which can read system folders, or grant read access to your account.",
+        ),
+        (PathAccess::RequiresElevation, true) | (PathAccess::AccessDenied, true) => Some(
+            "The service account can't read this folder. Grant it read access.",
+        ),
+        (PathAccess::AccessDenied, false) => {
+            Some("Your account can't read this folder. Grant read access to your account.")
+        }
+        (PathAccess::Error, _) => Some("The path could not be read; see the details."),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn is_cloud_placeholder(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x0000_1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+    std::fs::symlink_metadata(path)
+        .map(|m| {
+            m.file_attributes()
+                & (FILE_ATTRIBUTE_OFFLINE
+                    | FILE_ATTRIBUTE_RECALL_ON_OPEN
+                    | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+                != 0
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_cloud_placeholder(_path: &Path) -> bool {
+    false
+}
+
+/// Build the `icacls` command line granting `account` read access to `path`.
+/// Directories grant inheritable read/execute so new files are covered too.
+/// The result is meant for a batch script, so `%` is escaped.
+pub fn grant_read_command(path: &Path, account: &str, is_dir: bool) -> Result<String, String> {
+    let path = path.to_string_lossy();
+    if path.contains('"') || account.contains('"') {
+        return Err("Paths and account names cannot contain quotes".to_string());
+    }
+
+    let permission = if is_dir { "(OI)(CI)RX" } else { "RX" };
+    let recurse = if is_dir { " /T" } else { "" };
+    Ok(format!(
+        "icacls \"{}\" /grant \"{}:{}\"{} /C",
+        path.replace('%', "%%"),
+        account.replace('%', "%%"),
+        permission,
+        recurse
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privileged_locations() {
+        let cases = [
+            (r"C:\Windows\System32", true),
+            (r"d:\windows", true),
+            (r"C:\Program Files (x86)\App", true),
+            (r"C:\ProgramData\", true),
+            (r"C:\$Recycle.Bin", true),
+            (r"C:\System Volume Information", true),
+            (r"C:\Users\bob\Documents", true),
+            (r"C:\Users\Alice\Documents", false),
+            (r"C:\Users\Public\Music", false),
+            (r"C:\Users", false),
+            (r"C:\Windowsill\plants", false),
+            (r"E:\Backups", false),
+            ("/root/.ssh", true),
+            ("/etc", true),
+            ("/var/lib/docker", true),
+            ("/etcetera", false),
+            ("/home/alice", false),
+        ];
+        for (path, privileged) in cases {
+            assert_eq!(
+                is_privileged_location(Path::new(path), "alice"),
+                privileged,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn errors_are_classified_by_kind_and_location() {
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        let cases = [
+            (
+                io::Error::from(io::ErrorKind::NotFound),
+                "/home/alice/x",
+                PathAccess::NotFound,
+            ),
+            (denied(), "/home/alice/x", PathAccess::AccessDenied),
+            (denied(), "/root/x", PathAccess::RequiresElevation),
+            (denied(), r"C:\Users\bob", PathAccess::RequiresElevation),
+            (
+                io::Error::from(io::ErrorKind::InvalidData),
+                "/x",
+                PathAccess::Error,
+            ),
+        ];
+        for (error, path, expected) in cases {
+            assert_eq!(
+                classify_error(&error, Path::new(path), "alice"),
+                expected,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn remediation_depends_on_service_mode() {
+        assert_eq!(remediation(PathAccess::Ok, false), None);
+        let standalone = remediation(PathAccess::RequiresElevation, false).unwrap();
+        assert!(standalone.contains("Windows Service"));
+        let service = remediation(PathAccess::RequiresElevation, true).unwrap();
+        assert!(service.contains("service account"));
+        assert_eq!(remediation(PathAccess::AccessDenied, true), Some(service));
+        for status in [
+            PathAccess::NotFound,
+            PathAccess::AccessDenied,
+            PathAccess::CloudPlaceholder,
+            PathAccess::Error,
+        ] {
+            assert!(remediation(status, false).is_some(), "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn existing_and_missing_paths_are_checked() {
+        let dir = std::env::temp_dir().join(format!("access-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file.txt");
+        std::fs::write(&file, b"x").unwrap();
+
+        let results = [
+            check_path(&dir, "alice", false),
+            check_path(&file, "alice", false),
+            check_path(&dir.join("missing"), "alice", false),
+        ];
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(results[0].status, PathAccess::Ok);
+        assert_eq!(results[0].remediation, None);
+        assert_eq!(results[1].status, PathAccess::Ok);
+        assert_eq!(results[2].status, PathAccess::NotFound);
+        assert!(results[2].detail.is_some());
+        assert!(results[2].remediation.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directory_is_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("access-denied-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o000)).unwrap();
+        let result = check_path(&dir, "alice", false);
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Root reads anything, so only a denial can be checked
+        if result.status != PathAccess::Ok {
+            assert_eq!(result.status, PathAccess::AccessDenied);
+        }
+    }
+
+    #[test]
+    fn controlled_folder_keeps_an_existing_remediation() {
+        let mut ok = check_path(&std::env::temp_dir(), "alice", false);
+        mark_controlled_folder(&mut ok);
+        assert!(ok.controlled_folder_access);
+        assert!(ok.remediation.unwrap().contains("Controlled Folder Access"));
+
+        let mut missing = check_path(Path::new("/definitely/not/here"), "alice", false);
+        let before = missing.remediation.clone();
+        mark_controlled_folder(&mut missing);
+        assert_eq!(missing.remediation, before);
+    }
+
+    #[test]
+    fn grant_commands_for_folders_inherit_and_recurse() {
+        let dir = grant_read_command(Path::new(r"C:\Data\100%"), r"PC\alice", true).unwrap();
+        assert_eq!(
+            dir,
+            r#"icacls "C:\Data\100%%" /grant "PC\alice:(OI)(CI)RX" /T /C"#
+        );
+        let file =
+            grant_read_command(Path::new(r"C:\a.txt"), r"NT AUTHORITY\SYSTEM", false).unwrap();
+        assert_eq!(
+            file,
+            r#"icacls "C:\a.txt" /grant "NT AUTHORITY\SYSTEM:RX" /C"#
+        );
+    }
+
+    #[test]
+    fn grant_commands_refuse_quotes() {
+        assert!(grant_read_command(Path::new(r#"C:\a" & del"#), "alice", true).is_err());
+        assert!(grant_read_command(Path::new(r"C:\a"), r#"alice" /T"#, true).is_err());
+    }
+}
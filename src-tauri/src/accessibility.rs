@@ -0,0 +1,394 @@
+//! OS accessibility preferences for the pages the desktop ships itself.
+//!
+//! The splash/error page and the quick actions palette are bundled with the app, so they
+//! have to follow the OS's light/dark, high-contrast, reduced-motion and
+//! reduced-transparency settings on their own. A [`Detector`] reads them from the
+//! platform; the result is injected into every bundled page when it loads, as classes on
+//! `<html>` (`a11y-light`, `a11y-high-contrast`, `a11y-reduced-motion`,
+//! `a11y-reduced-transparency`) plus custom properties and a `zerobyte-a11y` DOM event.
+//!
+//! None of the platforms offers a change notification the webview shell can subscribe to
+//! without a window procedure of our own, so the preferences are read again on a theme
+//! change and otherwise every 30 seconds; a change is re-injected and announced as
+//! `accessibility-preferences-changed`.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::Manager;
+use tracing::info;
+
+/// How often the preferences are read again
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Global bundled pages can read the preferences from
+pub const WINDOW_GLOBAL: &str = "__ZEROBYTE_A11Y__";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AccessibilityPreferences {
+    pub dark: bool,
+    pub high_contrast: bool,
+    pub reduced_motion: bool,
+    pub reduced_transparency: bool,
+}
+
+/// Where preferences come from; the OS in the app, a stub elsewhere
+pub trait Detector: Send + Sync {
+    fn detect(&self) -> AccessibilityPreferences;
+}
+
+/// Reads the preferences from the running platform
+pub struct SystemDetector;
+
+impl Detector for SystemDetector {
+    fn detect(&self) -> AccessibilityPreferences {
+        platform::detect()
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::AccessibilityPreferences;
+    use std::ffi::c_void;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{BOOL, ERROR_SUCCESS};
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+    use windows::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION, SPI_GETHIGHCONTRAST,
+        SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    };
+
+    const PERSONALIZE: PCWSTR =
+        w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize");
+
+    fn personalize_dword(value: PCWSTR) -> Option<u32> {
+        let mut data: u32 = 0;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                PERSONALIZE,
+                value,
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut data as *mut u32 as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        (status == ERROR_SUCCESS).then_some(data)
+    }
+
+    pub fn detect() -> AccessibilityPreferences {
+        let mut contrast = HIGHCONTRASTW {
+            cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32,
+            ..Default::default()
+        };
+        let high_contrast = unsafe {
+            SystemParametersInfoW(
+                SPI_GETHIGHCONTRAST,
+                contrast.cbSize,
+                Some(&mut contrast as *mut HIGHCONTRASTW as *mut c_void),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
+        }
+        .is_ok()
+            && (contrast.dwFlags & HCF_HIGHCONTRASTON).0 != 0;
+
+        let mut animations = BOOL(1);
+        let reduced_motion = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCLIENTAREAANIMATION,
+                0,
+                Some(&mut animations as *mut BOOL as *mut c_void),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
+        }
+        .is_ok()
+            && !animations.as_bool();
+
+        AccessibilityPreferences {
+            dark: personalize_dword(w!("AppsUseLightTheme")) == Some(0),
+            high_contrast,
+            reduced_motion,
+            reduced_transparency: personalize_dword(w!("EnableTransparency")) == Some(0),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::AccessibilityPreferences;
+
+    fn defaults(domain: &str, key: &str) -> Option<String> {
+        let output = std::process::Command::new("defaults")
+            .args(["read", domain, key])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    pub fn detect() -> AccessibilityPreferences {
+        let enabled =
+            |key: &str| defaults("com.apple.universalaccess", key).as_deref() == Some("1");
+        AccessibilityPreferences {
+            dark: defaults("-g", "AppleInterfaceStyle").as_deref() == Some("Dark"),
+            high_contrast: enabled("increaseContrast"),
+            reduced_motion: enabled("reduceMotion"),
+            reduced_transparency: enabled("reduceTransparency"),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+mod platform {
+    use super::AccessibilityPreferences;
+
+    fn gsettings(schema: &str, key: &str) -> Option<String> {
+        let output = std::process::Command::new("gsettings")
+            .args(["get", schema, key])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// GNOME settings; other desktops report nothing and get the defaults
+    pub fn detect() -> AccessibilityPreferences {
+        let interface = "org.gnome.desktop.interface";
+        AccessibilityPreferences {
+            dark: gsettings(interface, "color-scheme").as_deref() == Some("'prefer-dark'"),
+            high_contrast: gsettings("org.gnome.desktop.a11y.interface", "high-contrast")
+                .as_deref()
+                == Some("true"),
+            reduced_motion: gsettings(interface, "enable-animations").as_deref()
+                == Some("false"),
+            reduced_transparency: false,
+        }
+    }
+}
+
+/// The detector and the preferences it last reported
+pub struct AccessibilityState {
+    detector: Box<dyn Detector>,
+    current: Mutex<AccessibilityPreferences>,
+}
+
+impl AccessibilityState {
+    pub fn new(detector: Box<dyn Detector>) -> Self {
+        let current = detector.detect();
+        Self {
+            detector,
+            current: Mutex::new(current),
+        }
+    }
+
+    pub fn current(&self) -> AccessibilityPreferences {
+        *self.current.lock()
+    }
+
+    /// Read the preferences again; returns them if they changed
+    pub fn refresh(&self) -> Option<AccessibilityPreferences> {
+        let detected = self.detector.detect();
+        let mut current = self.current.lock();
+        (*current != detected).then(|| {
+            *current = detected;
+            detected
+        })
+    }
+}
+
+impl Default for AccessibilityState {
+    fn default() -> Self {
+        Self::new(Box::new(SystemDetector))
+    }
+}
+
+/// Script applying `preferences` to a bundled page, now and once its DOM exists
+pub fn page_script(preferences: &AccessibilityPreferences) -> String {
+    let classes = [
+        ("a11y-light", !preferences.dark),
+        ("a11y-high-contrast", preferences.high_contrast),
+        ("a11y-reduced-motion", preferences.reduced_motion),
+        ("a11y-reduced-transparency", preferences.reduced_transparency),
+    ]
+    .iter()
+    .map(|(class, on)| format!("['{}',{}]", class, on))
+    .collect::<Vec<_>>()
+    .join(",");
+    format!(
+        concat!(
+            "(function(){{var p={json};window.{global}=Object.freeze(p);",
+            "function apply(){{var r=document.documentElement;if(!r)return;",
+            "[{classes}].forEach(function(c){{r.classList.toggle(c[0],c[1]);}});",
+            "r.style.setProperty('--a11y-motion-scale',p.reduced_motion?'0':'1');",
+            "r.style.setProperty('--a11y-surface-opacity',p.reduced_transparency?'1':'0.9');",
+            "window.dispatchEvent(new CustomEvent('zerobyte-a11y',{{detail:p}}));}}",
+            "apply();document.addEventListener('DOMContentLoaded',apply);}})();"
+        ),
+        json = serde_json::to_string(preferences).unwrap_or_else(|_| "{}".to_string()),
+        global = WINDOW_GLOBAL,
+        classes = classes,
+    )
+}
+
+/// Script to inject into the page at `url`; only bundled pages get one
+fn injection(url: &tauri::Url, preferences: &AccessibilityPreferences) -> Option<String> {
+    crate::status_server::is_bundled_page(url).then(|| page_script(preferences))
+}
+
+/// Apply the current preferences to a bundled page that started loading in any window
+pub fn on_page_load(webview: &tauri::Webview, url: &tauri::Url) {
+    let preferences = webview.state::<AccessibilityState>().current();
+    if let Some(script) = injection(url, &preferences) {
+        let _ = webview.eval(script);
+    }
+}
+
+/// Read the preferences again and push them to every bundled page if they changed
+pub fn refresh(app: &tauri::AppHandle) {
+    let Some(preferences) = app.state::<AccessibilityState>().refresh() else {
+        return;
+    };
+    info!("Accessibility preferences changed: {:?}", preferences);
+    for window in app.webview_windows().values() {
+        if let Some(script) = window.url().ok().and_then(|url| injection(&url, &preferences)) {
+            let _ = window.eval(script);
+        }
+    }
+    crate::events::emit(app, "accessibility-preferences-changed", preferences);
+}
+
+/// Follow the OS preferences for as long as the app runs
+pub async fn watch(app: tauri::AppHandle) {
+    let mut ticker = app.state::<crate::AppState>().activity.register(
+        "accessibility",
+        crate::activity::TaskClass::Standard,
+        POLL_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+        let handle = app.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || refresh(&handle)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Reports whatever the test last set, and counts how often it was asked
+    #[derive(Clone, Default)]
+    struct StubDetector {
+        preferences: Arc<Mutex<AccessibilityPreferences>>,
+        calls: Arc<Mutex<usize>>,
+    }
+
+    impl StubDetector {
+        fn set(&self, preferences: AccessibilityPreferences) {
+            *self.preferences.lock() = preferences;
+        }
+    }
+
+    impl Detector for StubDetector {
+        fn detect(&self) -> AccessibilityPreferences {
+            *self.calls.lock() += 1;
+            *self.preferences.lock()
+        }
+    }
+
+    fn url(url: &str) -> tauri::Url {
+        url.parse().unwrap()
+    }
+
+    const DARK_HIGH_CONTRAST: AccessibilityPreferences = AccessibilityPreferences {
+        dark: true,
+        high_contrast: true,
+        reduced_motion: false,
+        reduced_transparency: false,
+    };
+
+    #[test]
+    fn preferences_are_read_when_the_state_is_created() {
+        let detector = StubDetector::default();
+        detector.set(DARK_HIGH_CONTRAST);
+        let state = AccessibilityState::new(Box::new(detector.clone()));
+        assert_eq!(state.current(), DARK_HIGH_CONTRAST);
+        assert_eq!(*detector.calls.lock(), 1);
+    }
+
+    #[test]
+    fn only_changes_are_reported_on_refresh() {
+        let detector = StubDetector::default();
+        let state = AccessibilityState::new(Box::new(detector.clone()));
+
+        assert_eq!(state.refresh(), None);
+        detector.set(DARK_HIGH_CONTRAST);
+        assert_eq!(state.refresh(), Some(DARK_HIGH_CONTRAST));
+        assert_eq!(state.current(), DARK_HIGH_CONTRAST);
+        assert_eq!(state.refresh(), None);
+        detector.set(AccessibilityPreferences::default());
+        assert_eq!(state.refresh(), Some(AccessibilityPreferences::default()));
+        assert_eq!(*detector.calls.lock(), 5);
+    }
+
+    #[test]
+    fn only_bundled_pages_are_injected() {
+        let preferences = AccessibilityPreferences::default();
+        let cases = [
+            ("tauri://localhost/index.html", true),
+            ("http://tauri.localhost/palette.html", true),
+            ("https://tauri.localhost/", true),
+            ("http://localhost:4096/", false),
+            ("http://127.0.0.1:4096/settings", false),
+            ("https://example.com/", false),
+        ];
+        for (page, injected) in cases {
+            assert_eq!(
+                injection(&url(page), &preferences).is_some(),
+                injected,
+                "{}",
+                page
+            );
+        }
+    }
+
+    #[test]
+    fn the_injected_script_carries_the_detected_preferences() {
+        let detector = StubDetector::default();
+        detector.set(DARK_HIGH_CONTRAST);
+        let state = AccessibilityState::new(Box::new(detector.clone()));
+        let script = injection(&url("tauri://localhost/"), &state.current()).unwrap();
+
+        assert!(script.contains(concat!(
+            r#"var p={"dark":true,"high_contrast":true,"#,
+            r#""reduced_motion":false,"reduced_transparency":false};"#
+        )));
+        assert!(script.contains("window.__ZEROBYTE_A11Y__=Object.freeze(p)"));
+        assert!(script.contains(
+            "[['a11y-light',false],['a11y-high-contrast',true],\
+             ['a11y-reduced-motion',false],['a11y-reduced-transparency',false]]"
+        ));
+        assert!(script.contains("new CustomEvent('zerobyte-a11y',{detail:p})"));
+        assert!(script.contains("document.addEventListener('DOMContentLoaded',apply)"));
+
+        // A change comes back as a script with the new classes
+        detector.set(AccessibilityPreferences {
+            reduced_motion: true,
+            reduced_transparency: true,
+            ..AccessibilityPreferences::default()
+        });
+        let changed = state.refresh().unwrap();
+        let script = injection(&url("tauri://localhost/"), &changed).unwrap();
+        assert!(script.contains(
+            "[['a11y-light',true],['a11y-high-contrast',false],\
+             ['a11y-reduced-motion',true],['a11y-reduced-transparency',true]]"
+        ));
+    }
+}
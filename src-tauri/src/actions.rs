@@ -0,0 +1,563 @@
+//! Declarative table of desktop actions shared by the tray and the quick actions palette.
+//!
+//! Besides the table, the palette lists "back up now" once per backup plan, from the plans
+//! last fetched into [`PlanCache`]. Those entries share one action and differ in the
+//! arguments they carry.
+
+use crate::capabilities::{self, Availability, Feature};
+use crate::http::HttpPolicy;
+use crate::AppState;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use tauri::Manager;
+use tracing::info;
+
+/// Backend endpoint listing the backup plans
+pub const PLANS_PATH: &str = "/api/v1/backups";
+
+/// What an action does when executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    /// Show and focus the main window
+    ShowWindow,
+    /// Navigate the main window to a backend page
+    OpenPage(&'static str),
+    /// Start the Windows Service
+    StartService,
+    /// Stop the Windows Service
+    StopService,
+    /// Restart the sidecar backend
+    RestartBackend,
+    /// Run a backup plan now; takes the plan as a `plan_id` argument
+    BackUpNow,
+    /// Pause scheduled backups, queued until the backend is back if it's away
+    PauseBackups,
+    /// Resume scheduled backups, queued like [`ActionKind::PauseBackups`]
+    ResumeBackups,
+    /// Save the system report in the exports folder and open the folder
+    ExportDiagnostics,
+    /// Open or close devtools for the main window (support mode)
+    ToggleDevtools,
+    /// Stop the backend and exit the app
+    Quit,
+}
+
+/// Static description of a desktop action
+pub struct ActionSpec {
+    pub id: &'static str,
+    pub title: &'static str,
+    /// Extra words the palette search should match
+    pub keywords: &'static [&'static str],
+    pub kind: ActionKind,
+}
+
+/// Every action the desktop app offers, in display order
+pub const ACTIONS: &[ActionSpec] = &[
+    ActionSpec {
+        id: "show",
+        title: "Show window",
+        keywords: &["open", "focus"],
+        kind: ActionKind::ShowWindow,
+    },
+    ActionSpec {
+        id: "page:volumes",
+        title: "Open Volumes",
+        keywords: &["sources", "mounts"],
+        kind: ActionKind::OpenPage("volumes"),
+    },
+    ActionSpec {
+        id: "page:repositories",
+        title: "Open Repositories",
+        keywords: &["destinations", "restic"],
+        kind: ActionKind::OpenPage("repositories"),
+    },
+    ActionSpec {
+        id: "page:backups",
+        title: "Open Backups",
+        keywords: &["plans", "schedules", "jobs"],
+        kind: ActionKind::OpenPage("backups"),
+    },
+    ActionSpec {
+        id: "page:notifications",
+        title: "Open Notifications",
+        keywords: &["alerts"],
+        kind: ActionKind::OpenPage("notifications"),
+    },
+    ActionSpec {
+        id: "page:settings",
+        title: "Open Settings",
+        keywords: &["preferences", "options"],
+        kind: ActionKind::OpenPage("settings"),
+    },
+    ActionSpec {
+        id: "backup:run",
+        title: "Back up now",
+        keywords: &["run", "start", "plan"],
+        kind: ActionKind::BackUpNow,
+    },
+    ActionSpec {
+        id: "backups:pause",
+        title: "Pause backups",
+        keywords: &["stop", "schedule", "hold"],
+        kind: ActionKind::PauseBackups,
+    },
+    ActionSpec {
+        id: "backups:resume",
+        title: "Resume backups",
+        keywords: &["continue", "schedule", "unpause"],
+        kind: ActionKind::ResumeBackups,
+    },
+    ActionSpec {
+        id: "service:start",
+        title: "Start Windows Service",
+        keywords: &["service", "background"],
+        kind: ActionKind::StartService,
+    },
+    ActionSpec {
+        id: "service:stop",
+        title: "Stop Windows Service",
+        keywords: &["service", "background"],
+        kind: ActionKind::StopService,
+    },
+    ActionSpec {
+        id: "backend:restart",
+        title: "Restart backend",
+        keywords: &["server", "sidecar", "reload"],
+        kind: ActionKind::RestartBackend,
+    },
+    ActionSpec {
+        id: "diagnostics:export",
+        title: "Export diagnostics",
+        keywords: &["report", "support", "logs", "save"],
+        kind: ActionKind::ExportDiagnostics,
+    },
+    ActionSpec {
+        id: "devtools",
+        title: "Toggle developer tools",
+        keywords: &["support", "console", "debug", "inspect"],
+        kind: ActionKind::ToggleDevtools,
+    },
+    ActionSpec {
+        id: "quit",
+        title: "Quit",
+        keywords: &["exit", "close"],
+        kind: ActionKind::Quit,
+    },
+];
+
+/// Runtime facts that decide which actions are currently available
+#[derive(Debug, Clone, Copy)]
+pub struct ActionContext {
+    pub backend_ready: bool,
+    pub using_service: bool,
+    /// Viewing a backend owned by another session
+    pub read_only: bool,
+    pub devtools_allowed: bool,
+    /// The backend can pause its scheduler, or is away and the request will be queued
+    pub can_pause: bool,
+}
+
+impl ActionContext {
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            backend_ready: state.backend_ready.load(Ordering::SeqCst),
+            using_service: state.using_service.load(Ordering::SeqCst),
+            read_only: state.connection_mode.lock().is_viewer(),
+            devtools_allowed: crate::devtools::permission().allowed(),
+            can_pause: !state.backend_ready.load(Ordering::SeqCst)
+                || capabilities::availability(Feature::TrayPause, state.capabilities())
+                    == Availability::Available,
+        }
+    }
+}
+
+impl ActionSpec {
+    /// Whether the action can run right now
+    pub fn is_enabled(&self, ctx: &ActionContext) -> bool {
+        match self.kind {
+            ActionKind::ShowWindow | ActionKind::Quit | ActionKind::ExportDiagnostics => true,
+            ActionKind::OpenPage(_) => ctx.backend_ready,
+            ActionKind::ToggleDevtools => ctx.devtools_allowed,
+            ActionKind::StartService => {
+                cfg!(target_os = "windows") && !ctx.using_service && !ctx.read_only
+            }
+            ActionKind::StopService => {
+                cfg!(target_os = "windows") && ctx.using_service && !ctx.read_only
+            }
+            ActionKind::RestartBackend => !ctx.using_service && !ctx.read_only,
+            ActionKind::BackUpNow => ctx.backend_ready && !ctx.read_only,
+            ActionKind::PauseBackups | ActionKind::ResumeBackups => ctx.can_pause && !ctx.read_only,
+        }
+    }
+
+    /// How well `query` matches the title or keywords, or None if it doesn't
+    fn score(&self, query: &str, title: &str) -> Option<i64> {
+        let title_score = fuzzy_score(query, title);
+        // Keyword hits count, but never outrank a title hit
+        let keyword_score = self
+            .keywords
+            .iter()
+            .filter_map(|keyword| fuzzy_score(query, keyword))
+            .max()
+            .map(|score| score / 2);
+        title_score.max(keyword_score)
+    }
+}
+
+/// A backup plan, as the palette lists it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PlanEntry {
+    pub id: u64,
+    pub name: String,
+}
+
+/// Backup plans last fetched from the backend, for the per-plan palette entries
+#[derive(Default)]
+pub struct PlanCache(Mutex<Vec<PlanEntry>>);
+
+impl PlanCache {
+    pub fn plans(&self) -> Vec<PlanEntry> {
+        self.0.lock().clone()
+    }
+}
+
+/// Fetch the backup plans again and refresh an open palette. Keeps the last list while the
+/// backend is away.
+pub async fn refresh_plans(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    if !state.backend_ready.load(Ordering::SeqCst) {
+        return;
+    }
+    let url = format!(
+        "http://localhost:{}{}",
+        state.backend_port.load(Ordering::SeqCst),
+        PLANS_PATH
+    );
+    let plans = match state
+        .http
+        .send(HttpPolicy::BACKGROUND, |client| client.get(&url))
+        .await
+    {
+        Ok(response) if response.status().is_success() => {
+            response.json::<Vec<PlanEntry>>().await.ok()
+        }
+        _ => None,
+    };
+    if let Some(plans) = plans {
+        *app.state::<PlanCache>().0.lock() = plans;
+        crate::palette::notify_actions_changed(app);
+    }
+}
+
+/// An action as presented to the palette
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionEntry {
+    pub id: String,
+    pub title: String,
+    pub enabled: bool,
+    pub score: i64,
+    /// Passed back to `execute_action` along with the id
+    pub args: Option<Value>,
+}
+
+/// List the actions matching `query`, best match first, with a "back up now" entry for
+/// each of `plans`
+pub fn list(query: &str, ctx: &ActionContext, plans: &[PlanEntry]) -> Vec<ActionEntry> {
+    let mut entries = Vec::new();
+    for action in ACTIONS {
+        if action.kind == ActionKind::BackUpNow {
+            for plan in plans {
+                let title = format!("Back up {} now", plan.name);
+                if let Some(score) = action.score(query, &title) {
+                    entries.push(ActionEntry {
+                        id: action.id.to_string(),
+                        title,
+                        enabled: action.is_enabled(ctx),
+                        score,
+                        args: Some(serde_json::json!({ "plan_id": plan.id })),
+                    });
+                }
+            }
+        } else if let Some(score) = action.score(query, action.title) {
+            entries.push(ActionEntry {
+                id: action.id.to_string(),
+                title: action.title.to_string(),
+                enabled: action.is_enabled(ctx),
+                score,
+                args: None,
+            });
+        }
+    }
+
+    // Stable sort keeps the table order for equal scores
+    entries.sort_by(|a, b| b.enabled.cmp(&a.enabled).then(b.score.cmp(&a.score)));
+    entries
+}
+
+/// Look up an action by id
+pub fn find(id: &str) -> Option<&'static ActionSpec> {
+    ACTIONS.iter().find(|action| action.id == id)
+}
+
+/// Score how well `query` fuzzily matches `text`, or None if it doesn't match at all.
+/// Every query character must appear in order; consecutive runs and word starts score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0i64;
+    let mut query_idx = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (idx, ch) in text.iter().enumerate() {
+        if query_idx == query.len() {
+            break;
+        }
+        if !ch.to_lowercase().eq(std::iter::once(query[query_idx])) {
+            continue;
+        }
+
+        score += 1;
+        let word_start = idx == 0 || !text[idx - 1].is_alphanumeric();
+        if word_start {
+            score += 8;
+        }
+        match last_match {
+            Some(prev) if prev + 1 == idx => score += 5,
+            // Penalize the gap before the first match, capped so long titles aren't buried
+            None => score -= idx.min(5) as i64,
+            _ => {}
+        }
+
+        last_match = Some(idx);
+        query_idx += 1;
+    }
+
+    (query_idx == query.len()).then_some(score)
+}
+
+/// Run an action by id, with the arguments its palette entry carried
+pub async fn execute(app: &tauri::AppHandle, id: &str, args: Option<Value>) -> Result<(), String> {
+    let action = find(id).ok_or_else(|| format!("Unknown action: {}", id))?;
+
+    let ctx = ActionContext::from_state(&app.state::<AppState>());
+    if !action.is_enabled(&ctx) {
+        return Err(format!("Action is not available right now: {}", id));
+    }
+
+    match action.kind {
+        ActionKind::ShowWindow => crate::show_main_window(app),
+        ActionKind::OpenPage(route) => crate::navigate_main_window(app, route),
+        ActionKind::StartService => {
+            crate::commands::service::start_service(app.state::<AppState>())
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        ActionKind::StopService => {
+            crate::commands::service::stop_service(app.state::<AppState>())
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        ActionKind::RestartBackend => {
+            crate::restart_sidecar(app)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        ActionKind::BackUpNow => {
+            let plan_id = args
+                .as_ref()
+                .and_then(|args| args.get("plan_id"))
+                .and_then(Value::as_u64)
+                .ok_or_else(|| format!("{} needs a plan_id", id))?;
+            run_backup(app, plan_id).await?;
+        }
+        ActionKind::PauseBackups | ActionKind::ResumeBackups => {
+            let action = if action.kind == ActionKind::PauseBackups {
+                crate::outbox::BackendAction::PauseBackups
+            } else {
+                crate::outbox::BackendAction::ResumeBackups
+            };
+            crate::commands::outbox::queue_backend_action(
+                app.state::<AppState>(),
+                app.state::<crate::outbox::Outbox>(),
+                action,
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        ActionKind::ExportDiagnostics => {
+            let path = export_diagnostics(app)?;
+            info!("Exported diagnostics to {}", path.display());
+            if let Some(dir) = path.parent() {
+                crate::commands::open_folder(dir)?;
+            }
+        }
+        ActionKind::ToggleDevtools => {
+            let window = app
+                .get_webview_window("main")
+                .ok_or_else(|| "Main window not found".to_string())?;
+            crate::devtools::toggle(&window)?;
+        }
+        ActionKind::Quit => crate::quit_app(app),
+    }
+
+    Ok(())
+}
+
+/// Ask the backend to run a backup plan now
+async fn run_backup(app: &tauri::AppHandle, plan_id: u64) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let url = format!(
+        "http://localhost:{}{}/{}/run",
+        state.backend_port.load(Ordering::SeqCst),
+        PLANS_PATH,
+        plan_id
+    );
+    let body = serde_json::json!({ "reason": "manual" });
+    let response = state
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| {
+            client.post(&url).json(&body)
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Backend answered {} to running plan {}",
+            response.status(),
+            plan_id
+        ));
+    }
+    info!("Started a backup of plan {}", plan_id);
+    Ok(())
+}
+
+/// Save the system report as JSON in the exports folder, where retention looks after it
+fn export_diagnostics(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let report = crate::report::build(app);
+    let json = serde_json::to_vec_pretty(&report).map_err(|e| e.to_string())?;
+    let path = app
+        .state::<AppState>()
+        .paths()
+        .data_dir
+        .join(crate::retention::EXPORTS_DIR)
+        .join(format!("diagnostics-{}.json", crate::health::now()));
+    crate::persist::atomic_write(&path, &json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> ActionContext {
+        ActionContext {
+            backend_ready: true,
+            using_service: false,
+            read_only: false,
+            devtools_allowed: false,
+            can_pause: true,
+        }
+    }
+
+    #[test]
+    fn every_query_character_must_match_in_order() {
+        assert!(fuzzy_score("rsb", "Restart backend").is_some());
+        assert!(fuzzy_score("bsr", "Restart backend").is_none());
+        assert!(fuzzy_score("xyz", "Restart backend").is_none());
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(fuzzy_score("", "Quit"), Some(0));
+        assert_eq!(fuzzy_score("  ", "Quit"), Some(0));
+    }
+
+    #[test]
+    fn matching_ignores_case_and_spaces() {
+        assert_eq!(
+            fuzzy_score("OPEN back", "Open Backups"),
+            fuzzy_score("openback", "open backups")
+        );
+    }
+
+    #[test]
+    fn word_starts_and_runs_outrank_scattered_hits() {
+        let word_starts = fuzzy_score("rb", "Restart backend").unwrap();
+        let scattered = fuzzy_score("ae", "Restart backend").unwrap();
+        assert!(word_starts > scattered);
+
+        let run = fuzzy_score("back", "Open Backups").unwrap();
+        let spread = fuzzy_score("bkps", "Open Backups").unwrap();
+        assert!(run > spread);
+    }
+
+    #[test]
+    fn title_hits_outrank_keyword_hits() {
+        // "Show window" only matches through its "open" keyword
+        let entries = list("open", &ctx(), &[]);
+        let show = entries.iter().position(|e| e.id == "show").unwrap();
+        assert!(show > 0);
+        assert!(entries[..show].iter().all(|e| e.title.starts_with("Open ")));
+    }
+
+    #[test]
+    fn back_up_now_is_listed_once_per_plan() {
+        let plans = [
+            PlanEntry {
+                id: 3,
+                name: "Documents".into(),
+            },
+            PlanEntry {
+                id: 7,
+                name: "Photos".into(),
+            },
+        ];
+        let entries = list("photos", &ctx(), &plans);
+        let entry = entries.iter().find(|e| e.id == "backup:run").unwrap();
+        assert_eq!(entry.title, "Back up Photos now");
+        assert_eq!(entry.args, Some(serde_json::json!({ "plan_id": 7 })));
+
+        let all = list("", &ctx(), &plans);
+        assert_eq!(all.iter().filter(|e| e.id == "backup:run").count(), 2);
+        assert!(list("", &ctx(), &[]).iter().all(|e| e.id != "backup:run"));
+    }
+
+    #[test]
+    fn unavailable_actions_sort_last() {
+        let ctx = ActionContext {
+            using_service: true,
+            ..ctx()
+        };
+        let entries = list("", &ctx, &[]);
+        let restart = entries.iter().find(|e| e.id == "backend:restart").unwrap();
+        assert!(!restart.enabled);
+        let first_disabled = entries.iter().position(|e| !e.enabled).unwrap();
+        assert!(entries[first_disabled..].iter().all(|e| !e.enabled));
+    }
+
+    #[test]
+    fn viewers_cannot_run_or_pause_backups() {
+        let viewer = ActionContext {
+            read_only: true,
+            ..ctx()
+        };
+        for id in ["backup:run", "backups:pause", "backups:resume"] {
+            assert!(find(id).unwrap().is_enabled(&ctx()));
+            assert!(!find(id).unwrap().is_enabled(&viewer));
+        }
+        assert!(find("diagnostics:export").unwrap().is_enabled(&viewer));
+    }
+}
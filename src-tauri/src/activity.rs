@@ -0,0 +1,286 @@
+//! App-wide throttling of background activity ("battery saver").
+//!
+//! Every background poller registers with the [`ActivityGovernor`] under a priority class and
+//! waits on its handle instead of sleeping a fixed interval. When the user switches modes the
+//! handles are notified immediately: reduced mode stretches or stops non-essential work,
+//! suspended mode leaves only critical safety tasks running at a slow pace.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How much background work the app does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityMode {
+    #[default]
+    Normal,
+    Reduced,
+    Suspended,
+}
+
+impl ActivityMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            ActivityMode::Normal => "normal",
+            ActivityMode::Reduced => "reduced",
+            ActivityMode::Suspended => "suspended",
+        }
+    }
+}
+
+/// Priority of a background task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskClass {
+    /// Safety work (watchdog, shutdown handling) that never stops, only slows down
+    Critical,
+    /// Regular polling; stretched when reduced, stopped when suspended
+    Standard,
+    /// Nice-to-have samplers and probes; stopped unless the mode is normal
+    Optional,
+}
+
+/// Slowdown applied to stretched intervals
+const STRETCH_FACTOR: u32 = 4;
+
+/// Interval a task of `class` should use in `mode`, or None if it must not run at all
+pub fn interval_for(class: TaskClass, mode: ActivityMode, base: Duration) -> Option<Duration> {
+    match (class, mode) {
+        (_, ActivityMode::Normal) => Some(base),
+        (TaskClass::Critical, ActivityMode::Reduced) => Some(base),
+        (TaskClass::Critical, ActivityMode::Suspended) => Some(base * STRETCH_FACTOR),
+        (TaskClass::Standard, ActivityMode::Reduced) => Some(base * STRETCH_FACTOR),
+        (TaskClass::Standard, ActivityMode::Suspended) => None,
+        (TaskClass::Optional, _) => None,
+    }
+}
+
+/// A registered background task, as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisteredTask {
+    pub name: &'static str,
+    pub class: TaskClass,
+}
+
+/// Coordinates every background poller through a shared mode
+pub struct ActivityGovernor {
+    mode: watch::Sender<ActivityMode>,
+    tasks: Mutex<Vec<RegisteredTask>>,
+}
+
+impl Default for ActivityGovernor {
+    fn default() -> Self {
+        Self {
+            mode: watch::Sender::new(ActivityMode::Normal),
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl ActivityGovernor {
+    pub fn mode(&self) -> ActivityMode {
+        *self.mode.borrow()
+    }
+
+    /// Switch modes and notify every registered task. Returns whether the mode changed.
+    pub fn set_mode(&self, mode: ActivityMode) -> bool {
+        self.mode.send_if_modified(|current| {
+            let changed = *current != mode;
+            *current = mode;
+            changed
+        })
+    }
+
+    /// Register a background task that normally runs every `base`
+    pub fn register(&self, name: &'static str, class: TaskClass, base: Duration) -> ActivityTicker {
+        let mut tasks = self.tasks.lock();
+        // A supervised task registers again when it's restarted
+        tasks.retain(|task| task.name != name);
+        tasks.push(RegisteredTask { name, class });
+        drop(tasks);
+        ActivityTicker {
+            class,
+            base,
+            mode: self.mode.subscribe(),
+        }
+    }
+
+    pub fn tasks(&self) -> Vec<RegisteredTask> {
+        self.tasks.lock().clone()
+    }
+}
+
+/// A task's view of the governor
+pub struct ActivityTicker {
+    class: TaskClass,
+    base: Duration,
+    mode: watch::Receiver<ActivityMode>,
+}
+
+impl ActivityTicker {
+    /// The interval under the current mode, or None while the task is paused
+    pub fn interval(&self) -> Option<Duration> {
+        interval_for(self.class, *self.mode.borrow(), self.base)
+    }
+
+    /// Wait until the task should run again. A mode change restarts the wait with the new
+    /// interval; while the task is paused this only returns once a mode lets it run.
+    pub async fn tick(&mut self) {
+        loop {
+            let mode = *self.mode.borrow_and_update();
+            match interval_for(self.class, mode, self.base) {
+                Some(interval) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(interval) => return,
+                        changed = self.mode.changed() => {
+                            if changed.is_err() {
+                                // Governor gone: keep the last cadence
+                                tokio::time::sleep(interval).await;
+                                return;
+                            }
+                        }
+                    }
+                }
+                None => {
+                    if self.mode.changed().await.is_err() {
+                        std::future::pending::<()>().await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::time::Instant;
+
+    const BASE: Duration = Duration::from_secs(10);
+
+    /// A background task that records when (in seconds since `start`) each tick returned
+    fn fake_task(
+        governor: &ActivityGovernor,
+        name: &'static str,
+        class: TaskClass,
+        start: Instant,
+    ) -> Arc<Mutex<Vec<u64>>> {
+        let mut ticker = governor.register(name, class, BASE);
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ticks.clone();
+        tokio::spawn(async move {
+            loop {
+                ticker.tick().await;
+                recorded.lock().push(start.elapsed().as_secs());
+            }
+        });
+        ticks
+    }
+
+    async fn sleep_until(start: Instant, secs: u64) {
+        tokio::time::sleep_until(start + Duration::from_secs(secs)).await;
+    }
+
+    #[test]
+    fn intervals_by_class_and_mode() {
+        use ActivityMode::*;
+        use TaskClass::*;
+        let cases = [
+            (Critical, Normal, Some(BASE)),
+            (Critical, Reduced, Some(BASE)),
+            (Critical, Suspended, Some(BASE * STRETCH_FACTOR)),
+            (Standard, Normal, Some(BASE)),
+            (Standard, Reduced, Some(BASE * STRETCH_FACTOR)),
+            (Standard, Suspended, None),
+            (Optional, Normal, Some(BASE)),
+            (Optional, Reduced, None),
+            (Optional, Suspended, None),
+        ];
+        for (class, mode, expected) in cases {
+            assert_eq!(
+                interval_for(class, mode, BASE),
+                expected,
+                "{:?} {:?}",
+                class,
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn restarted_tasks_register_once() {
+        let governor = ActivityGovernor::default();
+        let _ = governor.register("drift", TaskClass::Standard, BASE);
+        let _ = governor.register("heartbeat", TaskClass::Critical, BASE);
+        let _ = governor.register("drift", TaskClass::Optional, BASE);
+
+        let tasks: Vec<_> = governor
+            .tasks()
+            .into_iter()
+            .map(|task| (task.name, task.class))
+            .collect();
+        assert_eq!(
+            tasks,
+            [
+                ("heartbeat", TaskClass::Critical),
+                ("drift", TaskClass::Optional)
+            ]
+        );
+    }
+
+    #[test]
+    fn set_mode_reports_changes_only() {
+        let governor = ActivityGovernor::default();
+        let ticker = governor.register("drift", TaskClass::Standard, BASE);
+        assert!(!governor.set_mode(ActivityMode::Normal));
+        assert!(governor.set_mode(ActivityMode::Reduced));
+        assert!(!governor.set_mode(ActivityMode::Reduced));
+        assert_eq!(governor.mode(), ActivityMode::Reduced);
+        // Tickers see the new mode right away
+        assert_eq!(ticker.interval(), Some(BASE * STRETCH_FACTOR));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mode_changes_reach_running_tasks_immediately() {
+        let governor = ActivityGovernor::default();
+        let start = Instant::now();
+        let critical = fake_task(&governor, "watchdog", TaskClass::Critical, start);
+        let standard = fake_task(&governor, "drift", TaskClass::Standard, start);
+        let optional = fake_task(&governor, "timezone", TaskClass::Optional, start);
+
+        sleep_until(start, 25).await;
+        governor.set_mode(ActivityMode::Reduced);
+        // Standard restarts its wait with the stretched interval, optional pauses
+        sleep_until(start, 70).await;
+        assert_eq!(*critical.lock(), [10, 20, 35, 45, 55, 65]);
+        assert_eq!(*standard.lock(), [10, 20, 65]);
+        assert_eq!(*optional.lock(), [10, 20]);
+
+        governor.set_mode(ActivityMode::Suspended);
+        sleep_until(start, 100).await;
+        // Only critical work is still scheduled, slowed down
+        assert_eq!(critical.lock().len(), 6);
+        assert_eq!(standard.lock().len(), 3);
+
+        governor.set_mode(ActivityMode::Normal);
+        sleep_until(start, 115).await;
+        assert_eq!(critical.lock()[6..], [110]);
+        assert_eq!(standard.lock()[3..], [110]);
+        assert_eq!(optional.lock()[2..], [110]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ticker_keeps_its_cadence_without_the_governor() {
+        let governor = ActivityGovernor::default();
+        let mut ticker = governor.register("drift", TaskClass::Standard, BASE);
+        let start = Instant::now();
+        drop(governor);
+
+        ticker.tick().await;
+        ticker.tick().await;
+        assert_eq!(start.elapsed(), BASE * 2);
+    }
+}
@@ -0,0 +1,184 @@
+//! Command-line options of the desktop app.
+//!
+//! `run()` parses them once into [`LaunchOptions`] and keeps them for the rest of the process
+//! (see [`current`]); the UI reads them through `get_launch_options`. Flags this app doesn't
+//! know stop startup with the usage on stderr, so a typo like `--minimised` isn't silently
+//! ignored. A launch that finds the app already running hands its arguments to the running
+//! instance, which parses them the same way and acts on `--page`, `--minimized` and a file.
+
+use crate::{config, devtools, profile};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Start hidden in the tray; the autostart entry passes it
+pub const MINIMIZED_FLAG: &str = "--minimized";
+
+/// Run only the backend, without creating the main window
+pub const HEADLESS_FLAG: &str = "--headless";
+
+/// Leave out the tray icon
+pub const NO_TRAY_FLAG: &str = "--no-tray";
+
+/// Sidecar port for this run, as `--port <n>` or `--port=<n>`
+pub const PORT_FLAG: &str = "--port";
+
+/// Named profile to run, as `--profile <name>` or `--profile=<name>`
+pub const PROFILE_FLAG: &str = "--profile";
+
+/// Backend page to open, as `--page <route>` or `--page=<route>`
+pub const PAGE_FLAG: &str = "--page";
+
+/// Printed with `--help` and after an invalid option
+pub const USAGE: &str = "\
+Usage: zerobyte [options] [file]
+
+Options:
+  --minimized         Start hidden in the tray
+  --headless          Run only the backend, without opening a window
+  --no-tray           Don't show the tray icon
+  --port <n>          Run the backend on port n (also ZEROBYTE_PORT)
+  --profile <name>    Run the named profile instead of the default one
+  --page <route>      Open the window on this page, e.g. backups
+  --support           Allow devtools for a support session
+  -h, --help          Show this help";
+
+static CURRENT: OnceLock<LaunchOptions> = OnceLock::new();
+
+/// How the app was started
+#[derive(Debug, Clone, Serialize)]
+pub struct LaunchOptions {
+    pub minimized: bool,
+    pub headless: bool,
+    pub no_tray: bool,
+    pub support: bool,
+    /// Sidecar port from `--port`, else from [`config::PORT_ENV`]
+    pub port: Option<u16>,
+    pub profile: String,
+    /// Backend route from `--page`, without the leading `/`
+    pub page: Option<String>,
+    /// File given as a bare argument, as given; relative to the launch's working directory
+    pub file: Option<PathBuf>,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        Self {
+            minimized: false,
+            headless: false,
+            no_tray: false,
+            support: false,
+            port: None,
+            profile: profile::DEFAULT.to_string(),
+            page: None,
+            file: None,
+        }
+    }
+}
+
+/// Parse `args`, without the program name. None when help was asked for.
+pub fn parse<I>(args: I) -> Result<Option<LaunchOptions>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = LaunchOptions::default();
+    let mut port = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let switch = matches!(
+            flag,
+            MINIMIZED_FLAG | HEADLESS_FLAG | NO_TRAY_FLAG | devtools::SUPPORT_FLAG | "--help"
+        );
+        if switch && inline.is_some() {
+            return Err(format!("{} doesn't take a value", flag));
+        }
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            MINIMIZED_FLAG => options.minimized = true,
+            HEADLESS_FLAG => options.headless = true,
+            NO_TRAY_FLAG => options.no_tray = true,
+            devtools::SUPPORT_FLAG => options.support = true,
+            PORT_FLAG => {
+                port = Some(
+                    inline
+                        .or_else(|| args.next())
+                        .ok_or("--port needs a port number")?,
+                );
+            }
+            PROFILE_FLAG => {
+                let name = inline
+                    .or_else(|| args.next())
+                    .ok_or("--profile needs a profile name")?;
+                profile::check_name(&name)?;
+                options.profile = name;
+            }
+            PAGE_FLAG => {
+                let route = inline
+                    .or_else(|| args.next())
+                    .ok_or("--page needs a page, e.g. backups")?;
+                options.page = Some(check_page(&route)?);
+            }
+            // Finder used to add a process serial number when launching apps on macOS
+            _ if flag.starts_with("-psn_") => {}
+            _ if flag.starts_with('-') => return Err(format!("Unknown option {:?}", arg)),
+            _ if options.file.is_none() => options.file = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+    // A window started hidden is only brought back from the tray
+    if options.minimized && options.no_tray {
+        return Err(format!(
+            "{} can't be combined with {}",
+            MINIMIZED_FLAG, NO_TRAY_FLAG
+        ));
+    }
+    options.port = config::port_override(port)?;
+    Ok(Some(options))
+}
+
+/// `route` without its leading `/`. It's appended to the backend's address, so it's kept to
+/// printable ASCII.
+fn check_page(route: &str) -> Result<String, String> {
+    let route = route.trim_start_matches('/');
+    if !route.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(format!(
+            "--page takes a route such as backups, not {:?}",
+            route
+        ));
+    }
+    Ok(route.to_string())
+}
+
+/// Parse this process's arguments. Prints the usage and exits when they ask for help or
+/// aren't valid.
+pub fn from_env() -> LaunchOptions {
+    match parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            tracing::error!("Invalid startup option: {}", e);
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Make `options` this process's launch options; only the first call counts
+pub fn set(options: LaunchOptions) {
+    let _ = CURRENT.set(options);
+}
+
+/// The options this process was started with; the defaults before [`set`]
+pub fn current() -> &'static LaunchOptions {
+    static DEFAULT: OnceLock<LaunchOptions> = OnceLock::new();
+    CURRENT
+        .get()
+        .unwrap_or_else(|| DEFAULT.get_or_init(LaunchOptions::default))
+}
@@ -0,0 +1,169 @@
+//! The contract between the desktop app and the static assets the sidecar serves.
+//!
+//! The server looks for its web client and migrations relative to an asset root. Before
+//! spawning it we check that every root listed in `sidecar-assets.json` (shipped with the
+//! server) exists under that directory, so a broken install fails with a clear error instead
+//! of a blank window full of 404s.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Manifest listing the asset roots the server expects, relative to the asset dir
+pub const MANIFEST_FILE: &str = "sidecar-assets.json";
+
+/// Environment variable used to hand the asset root to the server
+pub const ASSET_DIR_ENV: &str = "ZEROBYTE_ASSET_DIR";
+
+/// Asset roots the server expects
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub roots: Vec<String>,
+}
+
+impl Default for AssetManifest {
+    /// Layout used by servers that predate the manifest
+    fn default() -> Self {
+        Self {
+            roots: vec!["dist/client".to_string(), "assets/migrations".to_string()],
+        }
+    }
+}
+
+impl AssetManifest {
+    /// Read the manifest from `asset_dir`, falling back to the legacy layout if there is none
+    pub fn load(asset_dir: &Path) -> Result<Self, String> {
+        let path = asset_dir.join(MANIFEST_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid asset manifest {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+}
+
+/// Payload of the `sidecar-assets-missing` event and error
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+#[error("Sidecar assets missing under {}: {}", asset_dir.display(), missing.join(", "))]
+pub struct AssetsMissing {
+    pub asset_dir: PathBuf,
+    pub missing: Vec<String>,
+}
+
+/// Check that every root in `manifest` is a directory under `asset_dir`
+pub fn check_contract(asset_dir: &Path, manifest: &AssetManifest) -> Result<(), AssetsMissing> {
+    let missing: Vec<String> = manifest
+        .roots
+        .iter()
+        .filter(|root| !asset_dir.join(root).is_dir())
+        .cloned()
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(AssetsMissing {
+            asset_dir: asset_dir.to_path_buf(),
+            missing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("assets-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn manifest(roots: &[&str]) -> AssetManifest {
+        AssetManifest {
+            roots: roots.iter().map(|root| root.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn contract_holds_when_every_root_is_a_directory() {
+        let root = temp_root("complete");
+        std::fs::create_dir_all(root.join("dist/client")).unwrap();
+        std::fs::create_dir_all(root.join("assets/migrations")).unwrap();
+
+        let result = check_contract(&root, &AssetManifest::default());
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn missing_roots_are_listed_in_manifest_order() {
+        let root = temp_root("missing");
+        std::fs::create_dir_all(root.join("dist/client")).unwrap();
+        // A file where a directory is expected doesn't count
+        std::fs::write(root.join("public"), b"").unwrap();
+
+        let result = check_contract(
+            &root,
+            &manifest(&["public", "dist/client", "assets/migrations"]),
+        );
+        let _ = std::fs::remove_dir_all(&root);
+
+        let error = result.unwrap_err();
+        assert_eq!(error.missing, ["public", "assets/migrations"]);
+        assert_eq!(error.asset_dir, root);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Sidecar assets missing under {}: public, assets/migrations",
+                root.display()
+            )
+        );
+    }
+
+    #[test]
+    fn empty_manifest_always_holds() {
+        let root = std::env::temp_dir().join("assets-does-not-exist");
+        assert_eq!(check_contract(&root, &manifest(&[])), Ok(()));
+    }
+
+    #[test]
+    fn manifest_falls_back_to_the_legacy_layout() {
+        let root = temp_root("legacy");
+        let loaded = AssetManifest::load(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(loaded, Ok(AssetManifest::default()));
+    }
+
+    #[test]
+    fn manifest_is_read_from_the_asset_dir() {
+        let root = temp_root("manifest");
+        std::fs::write(
+            root.join(MANIFEST_FILE),
+            r#"{"roots":["web","db/migrations"]}"#,
+        )
+        .unwrap();
+        let loaded = AssetManifest::load(&root);
+        std::fs::write(root.join(MANIFEST_FILE), "{\"roots\":").unwrap();
+        let invalid = AssetManifest::load(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(loaded, Ok(manifest(&["web", "db/migrations"])));
+        assert!(invalid.unwrap_err().starts_with("Invalid asset manifest"));
+    }
+
+    #[test]
+    fn missing_assets_serialize_for_the_event() {
+        let payload = AssetsMissing {
+            asset_dir: PathBuf::from("/opt/zerobyte"),
+            missing: vec!["dist/client".to_string()],
+        };
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({ "asset_dir": "/opt/zerobyte", "missing": ["dist/client"] })
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Live sidecar output for the web UI.
+//!
+//! Every line the sidecar prints is redacted, kept in a ring buffer of the most recent
+//! lines and queued for the webviews. The queue is flushed as one `backend-log` event every
+//! 250 ms, so a chatty backend costs a few IPC messages a second rather than one per line.
+//! Lines are tagged with the generation of the sidecar that printed them, so output from
+//! before and after a restart can be told apart.
+//!
+//! The same lines are appended to `logs/backend.log` in the app data directory, so the
+//! output of a crashed sidecar survives. The file is rotated by size, keeping a handful of
+//! older files next to it.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{Emitter, Manager};
+use tracing::warn;
+
+/// Lines kept for `get_backend_logs`
+pub const RECENT_LINES: usize = 1000;
+
+/// How often queued lines are sent to the webviews
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Event carrying a batch of lines
+pub const EVENT: &str = "backend-log";
+
+/// Directory in the app data directory the backend log is written to
+pub const LOGS_DIR: &str = "logs";
+
+/// Name of the current backend log file; rotated files get `.1`, `.2`, ... appended
+pub const LOG_FILE: &str = "backend.log";
+
+/// Size at which the backend log is rotated
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Rotated files kept besides the current one
+const KEEP_ROTATED: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+impl LogStream {
+    fn label(self) -> &'static str {
+        match self {
+            LogStream::Stdout => "stdout",
+            LogStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// One line of sidecar output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendLogLine {
+    /// Sidecar generation that printed it
+    pub generation: u64,
+    pub stream: LogStream,
+    pub line: String,
+    /// Unix milliseconds when the desktop read the line
+    pub ts: u64,
+}
+
+/// Recent sidecar output and the lines not yet sent to the webviews
+#[derive(Default)]
+pub struct BackendLog {
+    recent: Mutex<VecDeque<BackendLogLine>>,
+    pending: Mutex<VecDeque<BackendLogLine>>,
+}
+
+impl BackendLog {
+    /// Record an already redacted line; returns it as queued
+    pub fn push(&self, generation: u64, stream: LogStream, line: String) -> BackendLogLine {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let entry = BackendLogLine {
+            generation,
+            stream,
+            line,
+            ts,
+        };
+        {
+            let mut recent = self.recent.lock();
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(entry.clone());
+        }
+        let mut pending = self.pending.lock();
+        // Nobody may be flushing yet (e.g. during setup); don't let the queue grow unbounded
+        if pending.len() == RECENT_LINES {
+            pending.pop_front();
+        }
+        pending.push_back(entry.clone());
+        entry
+    }
+
+    /// The most recent lines, oldest first
+    pub fn recent(&self) -> Vec<BackendLogLine> {
+        self.tail(RECENT_LINES)
+    }
+
+    /// The last `limit` lines, oldest first
+    pub fn tail(&self, limit: usize) -> Vec<BackendLogLine> {
+        let recent = self.recent.lock();
+        let skip = recent.len().saturating_sub(limit);
+        recent.iter().skip(skip).cloned().collect()
+    }
+
+    fn take_pending(&self) -> Vec<BackendLogLine> {
+        self.pending.lock().drain(..).collect()
+    }
+}
+
+/// Send queued lines to the webviews every `FLUSH_INTERVAL`, for as long as the app runs
+pub async fn flush(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let batch = app.state::<crate::AppState>().backend_log.take_pending();
+        if !batch.is_empty() {
+            // Not through `events::emit`: log lines are too many to retain for replay
+            let _ = app.emit(EVENT, batch);
+        }
+    }
+}
+
+/// `backend.log` and its rotated predecessors. Writes are buffered; call `flush` when the
+/// sidecar exits.
+pub struct LogFile {
+    dir: PathBuf,
+    writer: Option<BufWriter<File>>,
+    written: u64,
+}
+
+impl LogFile {
+    /// Open (appending to) the log in `dir`. Failures are logged and turn writes into no-ops,
+    /// so a read-only data directory never stops the output from being read.
+    pub fn open(dir: &Path) -> Self {
+        let mut file = Self {
+            dir: dir.to_path_buf(),
+            writer: None,
+            written: 0,
+        };
+        file.reopen();
+        file
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(LOG_FILE),
+            _ => self.dir.join(format!("{}.{}", LOG_FILE, index)),
+        }
+    }
+
+    fn reopen(&mut self) {
+        let opened = std::fs::create_dir_all(&self.dir).and_then(|()| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path(0))
+        });
+        match opened {
+            Ok(file) => {
+                self.written = file.metadata().map(|m| m.len()).unwrap_or(0);
+                self.writer = Some(BufWriter::new(file));
+            }
+            Err(e) => {
+                warn!("Failed to open {}: {}", self.path(0).display(), e);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Shift `backend.log` to `.1`, `.1` to `.2` and so on, dropping the oldest
+    fn rotate(&mut self) {
+        self.flush();
+        self.writer = None;
+        let _ = std::fs::remove_file(self.path(KEEP_ROTATED));
+        for index in (0..KEEP_ROTATED).rev() {
+            let _ = std::fs::rename(self.path(index), self.path(index + 1));
+        }
+        self.reopen();
+    }
+
+    /// Append an already redacted line
+    pub fn write(&mut self, entry: &BackendLogLine) {
+        if self.written >= MAX_FILE_BYTES {
+            self.rotate();
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let record = format!(
+            "{} #{} [{}] {}\n",
+            entry.ts,
+            entry.generation,
+            entry.stream.label(),
+            entry.line
+        );
+        match writer.write_all(record.as_bytes()) {
+            Ok(()) => self.written += record.len() as u64,
+            Err(e) => {
+                warn!("Failed to write the backend log: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
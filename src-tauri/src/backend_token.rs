@@ -0,0 +1,266 @@
+//! The shared secret that keeps other local programs off the backend.
+//!
+//! Anything running on the machine can reach `localhost`, including `/api/shutdown`. The
+//! desktop makes up a token when it starts and hands it to the sidecar in [`TOKEN_ENV`];
+//! every request the desktop sends to the local backend carries it in [`TOKEN_HEADER`], and
+//! `get_backend_url` passes it to the webview so the UI can do the same. The Windows Service
+//! makes up its own each time it starts and writes it to [`service_token_path`], where the
+//! desktop reads it when it connects to the service. ProgramData lets every user read what's
+//! created in it, so the token file gets its own DACL ([`service_token_sddl`]): besides
+//! SYSTEM and Administrators, only the user who installed the service may read it. The
+//! desktop passes that user's SID to the service at install time ([`OWNER_ARG`]). Other
+//! users of the machine, interactive or not, can't read the token.
+//!
+//! Without a token there is no protection at all, so neither the desktop nor the service
+//! starts a backend when no token can be made.
+
+use std::path::PathBuf;
+use tracing::error;
+
+/// Environment variable the backend reads its token from
+pub const TOKEN_ENV: &str = "ZEROBYTE_TOKEN";
+
+/// Header requests to the backend carry the token in
+pub const TOKEN_HEADER: &str = "x-zerobyte-token";
+
+/// File the service writes its token to, in the service data directory
+pub const SERVICE_TOKEN_FILE: &str = "backend.token";
+
+/// Service argument naming the SID of the user whose desktop may read the token
+pub const OWNER_ARG: &str = "--owner";
+
+/// Who may open the service's token file: SYSTEM and Administrators fully, the user with
+/// SID `owner` (the desktop) read-only. Protected, so nothing is inherited from ProgramData.
+/// None if `owner` isn't a SID, so nothing else can end up in the descriptor.
+pub fn service_token_sddl(owner: &str) -> Option<String> {
+    is_sid(owner).then(|| format!("D:P(A;;FA;;;SY)(A;;FA;;;BA)(A;;FR;;;{})", owner))
+}
+
+/// Whether `value` is a SID in string form, e.g. `S-1-5-21-1004336348-1177238915-682003330-1001`
+fn is_sid(value: &str) -> bool {
+    let Some(rest) = value.strip_prefix("S-1-") else {
+        return false;
+    };
+    rest.split('-')
+        .all(|part| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+/// The owner SID the service was registered with, from its command line
+pub fn owner_from_args(args: &[String]) -> Option<String> {
+    let position = args.iter().position(|arg| arg == OWNER_ARG)?;
+    args.get(position + 1)
+        .filter(|owner| is_sid(owner))
+        .cloned()
+}
+
+/// A new random token; None if the OS has no randomness, in which case no backend may start
+pub fn generate() -> Option<String> {
+    match crate::status_server::new_token() {
+        Ok(token) => Some(token),
+        Err(e) => {
+            error!(
+                "Couldn't make a backend token, the backend won't start: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// SID of the user running this process, in string form
+#[cfg(target_os = "windows")]
+pub fn current_user_sid() -> Option<String> {
+    use std::ffi::c_void;
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL};
+    use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+    use windows::Win32::Security::{GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER};
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).ok()?;
+        let mut size = 0u32;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut size);
+        let mut buffer = vec![0u8; size as usize];
+        let queried = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as *mut c_void),
+            size,
+            &mut size,
+        );
+        let _ = CloseHandle(token);
+        queried.ok()?;
+
+        let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &mut sid).ok()?;
+        let converted = sid.to_string().ok();
+        let _ = LocalFree(HLOCAL(sid.0 as *mut c_void));
+        converted
+    }
+}
+
+/// Where the service keeps its token; None off Windows, where there is no service
+pub fn service_token_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        let program_data =
+            std::env::var("PROGRAMDATA").unwrap_or_else(|_| r"C:\ProgramData".to_string());
+        Some(
+            PathBuf::from(program_data)
+                .join("C3i Backup ONE")
+                .join(SERVICE_TOKEN_FILE),
+        )
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        None
+    }
+}
+
+/// The token the running service wrote, if it wrote one
+pub fn read_service_token() -> Option<String> {
+    let contents = std::fs::read_to_string(service_token_path()?).ok()?;
+    let token = contents.trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Write the service's token to `path` with [`service_token_sddl`] for `owner` as its DACL.
+/// The file is created with it and renamed into place, so the token is never readable under
+/// the inherited one.
+#[cfg(target_os = "windows")]
+pub fn write_service_token(
+    path: &std::path::Path,
+    token: &str,
+    owner: &str,
+) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::windows::io::FromRawHandle;
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::{LocalFree, GENERIC_WRITE, HLOCAL};
+    use windows::Win32::Security::Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+    use windows::Win32::Storage::FileSystem::{
+        CreateFileW, CREATE_NEW, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_NONE,
+    };
+
+    let sddl = service_token_sddl(owner).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a SID", owner),
+        )
+    })?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // The security attributes only apply to a file that's created, not one that's reused
+    let tmp = path.with_extension("token.tmp");
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            &HSTRING::from(sddl),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )?;
+    }
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    let created = unsafe {
+        CreateFileW(
+            &HSTRING::from(tmp.as_os_str()),
+            GENERIC_WRITE.0,
+            FILE_SHARE_NONE,
+            Some(&attributes),
+            CREATE_NEW,
+            FILE_ATTRIBUTE_NORMAL,
+            None,
+        )
+    };
+    unsafe {
+        let _ = LocalFree(HLOCAL(descriptor.0));
+    }
+    let mut file = unsafe { std::fs::File::from_raw_handle(created?.0 as _) };
+
+    let written = file
+        .write_all(token.as_bytes())
+        .and_then(|()| file.sync_all());
+    drop(file);
+    if let Err(e) = written.and_then(|()| std::fs::rename(&tmp, path)) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SID: &str = "S-1-5-21-1004336348-1177238915-682003330-1001";
+
+    #[test]
+    fn only_the_owner_may_read_the_service_token() {
+        let sddl = service_token_sddl(SID).unwrap();
+        assert_eq!(
+            sddl,
+            format!("D:P(A;;FA;;;SY)(A;;FA;;;BA)(A;;FR;;;{})", SID)
+        );
+        // No ACE for interactive users, everyone or authenticated users
+        for group in ["IU", "WD", "AU", "BU"] {
+            assert!(!sddl.contains(&format!(";;;{})", group)), "{}", sddl);
+        }
+    }
+
+    #[test]
+    fn owners_that_are_not_sids_are_refused() {
+        for owner in [
+            "",
+            "IU",
+            "S-1-",
+            "S-1-5--21",
+            "S-1-5-21-1)(A;;FA;;;WD",
+            "s-1-5-21",
+        ] {
+            assert_eq!(service_token_sddl(owner), None, "{:?}", owner);
+        }
+    }
+
+    #[test]
+    fn owner_is_read_from_the_service_command_line() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            owner_from_args(&args(&["zerobyte-service.exe", OWNER_ARG, SID])),
+            Some(SID.to_string())
+        );
+        assert_eq!(owner_from_args(&args(&["zerobyte-service.exe"])), None);
+        assert_eq!(
+            owner_from_args(&args(&["zerobyte-service.exe", OWNER_ARG])),
+            None
+        );
+        assert_eq!(
+            owner_from_args(&args(&["zerobyte-service.exe", OWNER_ARG, "IU"])),
+            None
+        );
+    }
+
+    #[test]
+    fn generated_tokens_are_fresh() {
+        let first = generate().unwrap();
+        assert!(!first.is_empty());
+        assert_ne!(generate().unwrap(), first);
+    }
+}
@@ -0,0 +1,494 @@
+//! Switching the backend's bandwidth limit profiles from the tray and on a schedule.
+//!
+//! The backend owns the profiles (e.g. "Work hours: 10 Mbps", "Night: unlimited") and which
+//! one is active; the desktop lists them in a tray submenu and can switch between them. An
+//! optional schedule in the desktop settings picks one profile during work hours and another
+//! outside them. The schedule only acts at the boundaries of its periods: switching by hand
+//! holds until the next boundary, after which the schedule takes over again. Scheduled
+//! switches wait while a restore is running so it isn't throttled halfway through.
+
+use crate::http::HttpPolicy;
+use crate::schedule::{MINUTES_PER_DAY, MINUTES_PER_WEEK};
+use crate::AppState;
+use chrono::{Datelike, Timelike, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tauri::menu::{CheckMenuItem, MenuItem, Submenu};
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// How often the schedule is evaluated
+pub const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Prefix of the tray menu ids of profile items
+pub const MENU_PREFIX: &str = "bandwidth:";
+
+const PROFILES_PATH: &str = "/api/v1/rate-limits/profiles";
+const ACTIVE_PATH: &str = "/api/v1/rate-limits/active";
+
+/// A rate-limit profile as the backend reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthProfile {
+    pub id: String,
+    pub name: String,
+    /// Upload limit in kilobits per second; None means unlimited
+    #[serde(default)]
+    pub limit_kbps: Option<u64>,
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Which profile applies during work hours and which outside them, in local time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthSchedule {
+    pub work_profile: String,
+    pub off_profile: String,
+    /// Minute of the day work hours start
+    pub start_minute: u32,
+    /// Minute of the day work hours end; earlier than the start for night shifts
+    pub end_minute: u32,
+    /// Days work hours start on (bit 0 = Sunday)
+    pub days: u8,
+}
+
+impl BandwidthSchedule {
+    /// Minutes of the week at which a period starts, and whether it's work hours
+    fn boundaries(&self) -> Vec<(u32, bool)> {
+        let start = self.start_minute % MINUTES_PER_DAY;
+        let end = self.end_minute % MINUTES_PER_DAY;
+        let duration = (end + MINUTES_PER_DAY - start) % MINUTES_PER_DAY;
+        let mut boundaries = Vec::new();
+        if duration == 0 {
+            return boundaries;
+        }
+        for day in (0..7).filter(|day| self.days & (1 << day) != 0) {
+            let begins = day * MINUTES_PER_DAY + start;
+            boundaries.push((begins % MINUTES_PER_WEEK, true));
+            boundaries.push(((begins + duration) % MINUTES_PER_WEEK, false));
+        }
+        boundaries.sort();
+        boundaries
+    }
+
+    /// The profile the schedule wants at `minute_of_week`, and the minute of the week the
+    /// current period began (which identifies it)
+    pub fn period_at(&self, minute_of_week: u32) -> (&str, u32) {
+        let minute_of_week = minute_of_week % MINUTES_PER_WEEK;
+        let boundaries = self.boundaries();
+        // The latest boundary at or before now, wrapping into last week
+        let current = boundaries
+            .iter()
+            .rev()
+            .find(|(minute, _)| *minute <= minute_of_week)
+            .or_else(|| boundaries.last());
+        match current {
+            Some((began, true)) => (self.work_profile.as_str(), *began),
+            Some((began, false)) => (self.off_profile.as_str(), *began),
+            None => (self.off_profile.as_str(), 0),
+        }
+    }
+}
+
+/// The profile to switch to at `minute_of_week`, if the schedule hasn't handled the current
+/// period yet. `handled` is the start of the last period that was switched for or overridden
+/// by hand.
+pub fn due_switch(
+    schedule: &BandwidthSchedule,
+    minute_of_week: u32,
+    handled: Option<u32>,
+) -> Option<(String, u32)> {
+    let (profile, began) = schedule.period_at(minute_of_week);
+    (handled != Some(began)).then(|| (profile.to_string(), began))
+}
+
+/// Who switched the profile, for the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchSource {
+    Manual,
+    Tray,
+    Schedule,
+}
+
+/// Profiles last fetched from the backend and the schedule's progress
+#[derive(Default)]
+pub struct BandwidthState {
+    profiles: Mutex<Vec<BandwidthProfile>>,
+    /// Start (minute of week) of the schedule period already switched for or overridden
+    handled_period: Mutex<Option<u32>>,
+}
+
+impl BandwidthState {
+    pub fn profiles(&self) -> Vec<BandwidthProfile> {
+        self.profiles.lock().clone()
+    }
+
+    pub fn active(&self) -> Option<BandwidthProfile> {
+        self.profiles
+            .lock()
+            .iter()
+            .find(|profile| profile.active)
+            .cloned()
+    }
+}
+
+/// The tray's bandwidth submenu
+pub struct BandwidthMenu(pub Submenu<tauri::Wry>);
+
+/// Minute of the week (0 = Sunday 00:00) in the desktop's current zone
+fn local_minute_of_week() -> u32 {
+    let now = Utc::now().with_timezone(&crate::timezone::desktop_zone());
+    now.weekday().num_days_from_sunday() * MINUTES_PER_DAY + now.hour() * 60 + now.minute()
+}
+
+fn backend_url(app: &tauri::AppHandle, path: &str) -> String {
+    let port = app.state::<AppState>().backend_port.load(Ordering::SeqCst);
+    format!("http://localhost:{}{}", port, path)
+}
+
+/// Fetch the profiles from the backend and update the tray
+pub async fn refresh(app: &tauri::AppHandle) -> Result<Vec<BandwidthProfile>, String> {
+    let url = backend_url(app, PROFILES_PATH);
+    let response = app
+        .state::<AppState>()
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| client.get(&url))
+        .await
+        .map_err(|e| format!("Failed to reach the backend: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Backend refused to list bandwidth profiles (HTTP {})",
+            response.status()
+        ));
+    }
+    let profiles: Vec<BandwidthProfile> = response
+        .json()
+        .await
+        .map_err(|e| format!("Unexpected bandwidth profiles: {}", e))?;
+    *app.state::<BandwidthState>().profiles.lock() = profiles.clone();
+    rebuild_tray(app);
+    crate::refresh_tray_tooltip(app);
+    Ok(profiles)
+}
+
+/// Make `profile_id` the backend's active profile
+pub async fn activate(
+    app: &tauri::AppHandle,
+    profile_id: &str,
+    source: SwitchSource,
+) -> Result<(), String> {
+    let url = backend_url(app, ACTIVE_PATH);
+    let body = serde_json::json!({ "profileId": profile_id });
+    let response = app
+        .state::<AppState>()
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| client.put(&url).json(&body))
+        .await
+        .map_err(|e| format!("Failed to reach the backend: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Backend refused to switch bandwidth profile (HTTP {})",
+            response.status()
+        ));
+    }
+    info!(
+        target: "zerobyte::audit",
+        "Bandwidth profile switched to {} ({:?})",
+        profile_id,
+        source
+    );
+
+    let bandwidth = app.state::<BandwidthState>();
+    if source != SwitchSource::Schedule {
+        // A switch by hand holds until the schedule's next period
+        if let Some(schedule) = app
+            .state::<crate::settings::SettingsStore>()
+            .get()
+            .bandwidth_schedule
+        {
+            let (_, began) = schedule.period_at(local_minute_of_week());
+            *bandwidth.handled_period.lock() = Some(began);
+        }
+    }
+    for profile in bandwidth.profiles.lock().iter_mut() {
+        profile.active = profile.id == profile_id;
+    }
+    rebuild_tray(app);
+    crate::refresh_tray_tooltip(app);
+    crate::events::emit(app, "bandwidth-profile-changed", profile_id);
+    Ok(())
+}
+
+/// Replace the tray submenu's items with the cached profiles
+pub fn rebuild_tray(app: &tauri::AppHandle) {
+    let Some(menu) = app.try_state::<BandwidthMenu>() else {
+        return;
+    };
+    for item in menu.0.items().unwrap_or_default() {
+        let _ = menu.0.remove(&item);
+    }
+
+    let profiles = app.state::<BandwidthState>().profiles();
+    if profiles.is_empty() {
+        if let Ok(item) = MenuItem::with_id(
+            app,
+            "bandwidth_unavailable",
+            "No profiles",
+            false,
+            None::<&str>,
+        ) {
+            let _ = menu.0.append(&item);
+        }
+        return;
+    }
+    for profile in profiles {
+        let label = crate::text::fit(
+            app,
+            crate::text::Surface::MenuItem,
+            &profile.name,
+            crate::text::Ellipsis::End,
+        );
+        let id = format!("{}{}", MENU_PREFIX, profile.id);
+        match CheckMenuItem::with_id(app, id, label, true, profile.active, None::<&str>) {
+            Ok(item) => {
+                let _ = menu.0.append(&item);
+            }
+            Err(e) => warn!("Failed to add bandwidth profile to the tray: {}", e),
+        }
+    }
+}
+
+/// Handle a click on a profile in the tray
+pub fn on_menu_event(app: &tauri::AppHandle, profile_id: &str) {
+    let viewer = app
+        .state::<AppState>()
+        .connection_mode
+        .lock()
+        .is_viewer();
+    if viewer {
+        // Another session owns the backend; undo the check mark the click toggled
+        rebuild_tray(app);
+        return;
+    }
+    let app = app.clone();
+    let profile_id = profile_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = activate(&app, &profile_id, SwitchSource::Tray).await {
+            warn!("{}", e);
+            // Put the check marks back the way the backend has them
+            rebuild_tray(&app);
+        }
+    });
+}
+
+/// Load the profiles once the backend is up, then follow the schedule for as long as the
+/// app runs
+pub async fn run(app: tauri::AppHandle) {
+    if let Err(e) = refresh(&app).await {
+        info!("Bandwidth profiles unavailable: {}", e);
+    }
+    let mut ticker = app.state::<AppState>().activity.register(
+        "bandwidth-schedule",
+        crate::activity::TaskClass::Standard,
+        SCHEDULE_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+        let Some(schedule) = app
+            .state::<crate::settings::SettingsStore>()
+            .get()
+            .bandwidth_schedule
+        else {
+            continue;
+        };
+        let handled = *app.state::<BandwidthState>().handled_period.lock();
+        let Some((profile_id, began)) = due_switch(&schedule, local_minute_of_week(), handled)
+        else {
+            continue;
+        };
+        if !app.state::<AppState>().restore_sessions.list().is_empty() {
+            // Try again on the next tick, once the restore is done
+            continue;
+        }
+        match activate(&app, &profile_id, SwitchSource::Schedule).await {
+            Ok(()) => {
+                *app.state::<BandwidthState>().handled_period.lock() = Some(began)
+            }
+            Err(e) => warn!("Scheduled bandwidth switch failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONDAY: u32 = 1;
+    const FRIDAY: u32 = 5;
+    const SATURDAY: u32 = 6;
+    const SUNDAY: u32 = 0;
+    const WEEKDAYS: u8 = 0b0111110;
+
+    fn at(day: u32, hour: u32, minute: u32) -> u32 {
+        day * MINUTES_PER_DAY + hour * 60 + minute
+    }
+
+    fn schedule(start: (u32, u32), end: (u32, u32), days: u8) -> BandwidthSchedule {
+        BandwidthSchedule {
+            work_profile: "work".into(),
+            off_profile: "night".into(),
+            start_minute: start.0 * 60 + start.1,
+            end_minute: end.0 * 60 + end.1,
+            days,
+        }
+    }
+
+    #[test]
+    fn office_hours_pick_the_work_profile() {
+        let office = schedule((9, 0), (17, 30), WEEKDAYS);
+        let cases = [
+            (at(MONDAY, 8, 59), "night", at(FRIDAY, 17, 30)),
+            (at(MONDAY, 9, 0), "work", at(MONDAY, 9, 0)),
+            (at(MONDAY, 17, 29), "work", at(MONDAY, 9, 0)),
+            (at(MONDAY, 17, 30), "night", at(MONDAY, 17, 30)),
+            (at(FRIDAY, 12, 0), "work", at(FRIDAY, 9, 0)),
+            (at(SATURDAY, 12, 0), "night", at(FRIDAY, 17, 30)),
+            // Before Monday's first boundary the period is last Friday evening's
+            (at(SUNDAY, 12, 0), "night", at(FRIDAY, 17, 30)),
+            (
+                at(MONDAY, 8, 0) + MINUTES_PER_WEEK,
+                "night",
+                at(FRIDAY, 17, 30),
+            ),
+        ];
+        for (minute, profile, began) in cases {
+            assert_eq!(
+                office.period_at(minute),
+                (profile, began),
+                "minute {}",
+                minute
+            );
+        }
+    }
+
+    #[test]
+    fn night_shifts_run_past_midnight_and_the_week_boundary() {
+        // Starts Saturday 22:00, ends Sunday 06:00 in the next week
+        let night = schedule((22, 0), (6, 0), 1 << SATURDAY);
+        let cases = [
+            (at(SATURDAY, 21, 59), "night", at(SUNDAY, 6, 0)),
+            (at(SATURDAY, 22, 0), "work", at(SATURDAY, 22, 0)),
+            (at(SATURDAY, 23, 59), "work", at(SATURDAY, 22, 0)),
+            (at(SUNDAY, 0, 0), "work", at(SATURDAY, 22, 0)),
+            (at(SUNDAY, 5, 59), "work", at(SATURDAY, 22, 0)),
+            (at(SUNDAY, 6, 0), "night", at(SUNDAY, 6, 0)),
+            (at(MONDAY, 12, 0), "night", at(SUNDAY, 6, 0)),
+        ];
+        for (minute, profile, began) in cases {
+            assert_eq!(
+                night.period_at(minute),
+                (profile, began),
+                "minute {}",
+                minute
+            );
+        }
+    }
+
+    #[test]
+    fn empty_schedules_stay_off() {
+        for empty in [
+            schedule((9, 0), (17, 0), 0),
+            schedule((9, 0), (9, 0), WEEKDAYS),
+        ] {
+            for minute in [0, at(MONDAY, 12, 0), MINUTES_PER_WEEK - 1] {
+                assert_eq!(empty.period_at(minute), ("night", 0));
+            }
+        }
+    }
+
+    /// Step a minute at a time from `from` to `to` the way the scheduler loop does, switching
+    /// whenever one is due, and return each switch as (minute, profile)
+    fn run(
+        schedule: &BandwidthSchedule,
+        from: u32,
+        to: u32,
+        handled: &mut Option<u32>,
+    ) -> Vec<(u32, String)> {
+        let mut switches = Vec::new();
+        for minute in from..to {
+            if let Some((profile, began)) = due_switch(schedule, minute, *handled) {
+                *handled = Some(began);
+                switches.push((minute, profile));
+            }
+        }
+        switches
+    }
+
+    #[test]
+    fn the_schedule_switches_once_per_boundary() {
+        let office = schedule((9, 0), (17, 0), WEEKDAYS);
+        let mut handled = None;
+        let switches = run(
+            &office,
+            at(MONDAY, 8, 0),
+            at(MONDAY + 2, 12, 0),
+            &mut handled,
+        );
+        assert_eq!(
+            switches,
+            [
+                (at(MONDAY, 8, 0), "night".to_string()),
+                (at(MONDAY, 9, 0), "work".to_string()),
+                (at(MONDAY, 17, 0), "night".to_string()),
+                (at(MONDAY + 1, 9, 0), "work".to_string()),
+                (at(MONDAY + 1, 17, 0), "night".to_string()),
+                (at(MONDAY + 2, 9, 0), "work".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_manual_switch_holds_until_the_next_boundary() {
+        let office = schedule((9, 0), (17, 0), WEEKDAYS);
+        // Switched by hand at 10:00, which marks the current period as handled
+        let (_, began) = office.period_at(at(MONDAY, 10, 0));
+        let mut handled = Some(began);
+
+        assert_eq!(due_switch(&office, at(MONDAY, 10, 1), handled), None);
+        assert_eq!(due_switch(&office, at(MONDAY, 16, 59), handled), None);
+        let switches = run(&office, at(MONDAY, 10, 0), at(MONDAY, 18, 0), &mut handled);
+        assert_eq!(switches, [(at(MONDAY, 17, 0), "night".to_string())]);
+    }
+
+    #[test]
+    fn a_deferred_switch_stays_due_until_it_happens() {
+        let office = schedule((9, 0), (17, 0), WEEKDAYS);
+        let handled = Some(at(SUNDAY, 0, 0));
+        // A restore kept the 9:00 switch from happening; it's still due later in the period
+        for minute in [at(MONDAY, 9, 0), at(MONDAY, 9, 1), at(MONDAY, 16, 59)] {
+            assert_eq!(
+                due_switch(&office, minute, handled),
+                Some(("work".to_string(), at(MONDAY, 9, 0)))
+            );
+        }
+    }
+
+    #[test]
+    fn active_profile_comes_from_the_cache() {
+        let state = BandwidthState::default();
+        assert_eq!(state.active(), None);
+        let profiles: Vec<BandwidthProfile> = serde_json::from_str(
+            r#"[{"id":"work","name":"Work hours","limitKbps":10000},
+                {"id":"night","name":"Night","active":true}]"#,
+        )
+        .unwrap();
+        *state.profiles.lock() = profiles;
+        let active = state.active().unwrap();
+        assert_eq!((active.id.as_str(), active.limit_kbps), ("night", None));
+        assert_eq!(state.profiles()[0].limit_kbps, Some(10_000));
+    }
+}
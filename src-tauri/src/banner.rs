@@ -0,0 +1,306 @@
+//! What the sidecar says it's using, read from its startup banner.
+//!
+//! We ask the server for a port and data directory through its environment, but its own
+//! config file can override either. At startup it prints its listening address, version and
+//! data directory; those lines are picked out of stdout here (tolerating log prefixes, ANSI
+//! colors, interleaved log lines and output split mid-character) and compared with what we
+//! requested. The listening line ends the readiness wait's backoff early and, when it names a
+//! different port, moves the wait over to the real one; a different data directory is
+//! reported as `config-divergence`.
+
+use regex::Regex;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Facts the sidecar reported about itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BannerFacts {
+    pub port: Option<u16>,
+    pub version: Option<String>,
+    pub data_dir: Option<PathBuf>,
+}
+
+/// Something the sidecar uses that differs from what we asked for
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigDivergence {
+    pub setting: &'static str,
+    pub requested: String,
+    pub actual: String,
+}
+
+struct Patterns {
+    ansi: Regex,
+    listening: Regex,
+    version: Regex,
+    data_dir: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        ansi: Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").unwrap(),
+        // "Listening on http://localhost:4096", "Server running at 0.0.0.0:4096",
+        // "started on port 4096"
+        listening: Regex::new(concat!(
+            r"(?i)\b(?:listening|running|started|serving)\b.*?",
+            r"(?:(?:[\w.\-]+|\[[0-9a-f:]+\]):|\bport\s*[:=]?\s*)(\d{2,5})\b",
+        ))
+        .unwrap(),
+        // "Zerobyte v0.14.2", "version: 0.14.2-beta.1"
+        version: Regex::new(r"(?i)(?:zerobyte\s+v|\bversion\s*[:=]?\s*v?)(\d+\.\d+\.\d+[\w.\-+]*)")
+            .unwrap(),
+        // "Data directory: /var/lib/...", "data dir = C:\..."
+        data_dir: Regex::new(r"(?i)\bdata\s*(?:dir(?:ectory)?|path)\s*[:=]\s*(.+?)\s*$").unwrap(),
+    })
+}
+
+/// Splits sidecar output into lines, keeping an incomplete trailing line (or UTF-8 sequence)
+/// until the rest arrives
+#[derive(Default)]
+pub struct LineAssembler {
+    pending: Vec<u8>,
+}
+
+/// Longest line kept while waiting for its end; anything longer isn't a banner line
+const MAX_PENDING: usize = 64 * 1024;
+
+impl LineAssembler {
+    /// Add a chunk of output and return the lines it completed. A chunk that ends without a
+    /// newline is held back unless it ends at a character boundary and `chunk_is_line` says
+    /// the producer already split on newlines.
+    pub fn push(&mut self, chunk: &[u8], chunk_is_line: bool) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(decode(&line));
+        }
+        let complete_utf8 = std::str::from_utf8(&self.pending).is_ok();
+        if (chunk_is_line && complete_utf8 && !self.pending.is_empty())
+            || self.pending.len() > MAX_PENDING
+        {
+            let line = std::mem::take(&mut self.pending);
+            lines.push(decode(&line));
+        }
+        lines
+    }
+}
+
+fn decode(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(['\r', '\n'])
+        .to_string()
+}
+
+impl BannerFacts {
+    /// Record whatever `line` reports. Returns whether a fact was learned; the first value
+    /// seen for each fact wins, so later log lines that mention ports don't override it.
+    pub fn observe(&mut self, line: &str) -> bool {
+        let patterns = patterns();
+        let line = patterns.ansi.replace_all(line, "");
+        let mut learned = false;
+
+        if self.port.is_none() {
+            if let Some(port) = patterns
+                .listening
+                .captures(&line)
+                .and_then(|captures| captures[1].parse::<u16>().ok())
+                .filter(|port| *port != 0)
+            {
+                self.port = Some(port);
+                learned = true;
+            }
+        }
+        if self.version.is_none() {
+            if let Some(captures) = patterns.version.captures(&line) {
+                self.version = Some(captures[1].to_string());
+                learned = true;
+            }
+        }
+        if self.data_dir.is_none() {
+            if let Some(captures) = patterns.data_dir.captures(&line) {
+                let path = captures[1].trim_matches(['"', '\'']);
+                if !path.is_empty() {
+                    self.data_dir = Some(PathBuf::from(path));
+                    learned = true;
+                }
+            }
+        }
+        learned
+    }
+
+    /// Reported values that differ from what we asked for
+    pub fn divergences(
+        &self,
+        requested_port: u16,
+        requested_data_dir: Option<&std::path::Path>,
+    ) -> Vec<ConfigDivergence> {
+        let mut divergences = Vec::new();
+        if let Some(port) = self.port.filter(|port| *port != requested_port) {
+            divergences.push(ConfigDivergence {
+                setting: "port",
+                requested: requested_port.to_string(),
+                actual: port.to_string(),
+            });
+        }
+        if let (Some(actual), Some(requested)) = (&self.data_dir, requested_data_dir) {
+            if !crate::registrations::same_path(actual, requested) {
+                divergences.push(ConfigDivergence {
+                    setting: "data_dir",
+                    requested: requested.display().to_string(),
+                    actual: actual.display().to_string(),
+                });
+            }
+        }
+        divergences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Startup output as the sidecar prints it: colored log prefixes, migrations and
+    /// scheduler lines interleaved with the banner
+    const SAMPLE: &str = concat!(
+        "\x1b[90m2024-11-02 09:14:03\x1b[0m \x1b[32mINFO\x1b[0m Zerobyte v0.14.2 starting\n",
+        "\x1b[90m2024-11-02 09:14:03\x1b[0m \x1b[32mINFO\x1b[0m Data directory: ",
+        "C:\\ProgramData\\Zerobyte Données\n",
+        "\x1b[90m2024-11-02 09:14:03\x1b[0m \x1b[32mINFO\x1b[0m Running 3 pending migrations\n",
+        "\x1b[90m2024-11-02 09:14:04\x1b[0m \x1b[33mWARN\x1b[0m restic cache at port 9000 ",
+        "is stale\n",
+        "\x1b[90m2024-11-02 09:14:04\x1b[0m \x1b[32mINFO\x1b[0m Server listening on ",
+        "http://localhost:4097\r\n",
+        "\x1b[90m2024-11-02 09:14:05\x1b[0m \x1b[32mINFO\x1b[0m Scheduler running at ",
+        "0.0.0.0:5000\n",
+    );
+
+    fn expected() -> BannerFacts {
+        BannerFacts {
+            port: Some(4097),
+            version: Some("0.14.2".into()),
+            data_dir: Some(PathBuf::from("C:\\ProgramData\\Zerobyte Données")),
+        }
+    }
+
+    fn parse_chunks(chunks: &[&[u8]], chunk_is_line: bool) -> BannerFacts {
+        let mut assembler = LineAssembler::default();
+        let mut facts = BannerFacts::default();
+        for chunk in chunks {
+            for line in assembler.push(chunk, chunk_is_line) {
+                facts.observe(&line);
+            }
+        }
+        facts
+    }
+
+    #[test]
+    fn sample_output_yields_the_banner_facts() {
+        assert_eq!(parse_chunks(&[SAMPLE.as_bytes()], false), expected());
+    }
+
+    #[test]
+    fn output_split_anywhere_yields_the_same_facts() {
+        let bytes = SAMPLE.as_bytes();
+        for split in 0..=bytes.len() {
+            let facts = parse_chunks(&[&bytes[..split], &bytes[split..]], false);
+            assert_eq!(facts, expected(), "split at byte {}", split);
+        }
+    }
+
+    #[test]
+    fn split_multibyte_character_is_held_back() {
+        let mut assembler = LineAssembler::default();
+        let text = "Data directory: /srv/zerobyte-é\n".as_bytes();
+        let cut = text.len() - 2; // inside the two-byte é
+        assert!(assembler.push(&text[..cut], true).is_empty());
+        assert_eq!(
+            assembler.push(&text[cut..], true),
+            vec!["Data directory: /srv/zerobyte-é".to_string()]
+        );
+    }
+
+    #[test]
+    fn line_split_producer_completes_lines_without_newlines() {
+        let mut assembler = LineAssembler::default();
+        assert_eq!(
+            assembler.push(b"Listening on :4096", true),
+            vec!["Listening on :4096".to_string()]
+        );
+        // Without that promise the same chunk waits for its newline
+        let mut assembler = LineAssembler::default();
+        assert!(assembler.push(b"Listening on :4096", false).is_empty());
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced_not_dropped() {
+        let mut assembler = LineAssembler::default();
+        let lines = assembler.push(b"version: 1.2.3 \xff\xfe\n", false);
+        assert_eq!(lines.len(), 1);
+        let mut facts = BannerFacts::default();
+        assert!(facts.observe(&lines[0]));
+        assert_eq!(facts.version.as_deref(), Some("1.2.3"));
+    }
+
+    #[test]
+    fn overlong_line_is_flushed() {
+        let mut assembler = LineAssembler::default();
+        let lines = assembler.push(&vec![b'x'; MAX_PENDING + 1], false);
+        assert_eq!(lines.len(), 1);
+        assert!(assembler
+            .push(b"\n", false)
+            .iter()
+            .all(|line| line.is_empty()));
+    }
+
+    #[test]
+    fn listening_line_formats() {
+        let port = |line: &str| {
+            let mut facts = BannerFacts::default();
+            facts.observe(line);
+            facts.port
+        };
+        assert_eq!(port("Listening on http://localhost:4096"), Some(4096));
+        assert_eq!(port("Server running at 0.0.0.0:4096"), Some(4096));
+        assert_eq!(port("started on port 4096"), Some(4096));
+        assert_eq!(port("Serving on http://[::1]:4100/"), Some(4100));
+        assert_eq!(port("listening on port: 0"), None);
+        assert_eq!(port("Connected to database at port 5432"), None);
+        assert_eq!(port("Listening on http://localhost:99999"), None);
+    }
+
+    #[test]
+    fn first_report_of_each_fact_wins() {
+        let mut facts = BannerFacts::default();
+        assert!(facts.observe("Listening on http://localhost:4096"));
+        assert!(!facts.observe("Listening on http://localhost:5000"));
+        assert!(!facts.observe("Job 12 running"));
+        assert_eq!(facts.port, Some(4096));
+    }
+
+    #[test]
+    fn quoted_data_dir_is_unquoted() {
+        let mut facts = BannerFacts::default();
+        facts.observe(r#"data dir = "/var/lib/zerobyte" "#);
+        assert_eq!(facts.data_dir, Some(PathBuf::from("/var/lib/zerobyte")));
+    }
+
+    #[test]
+    fn divergences_compare_with_the_request() {
+        let facts = BannerFacts {
+            port: Some(4097),
+            version: None,
+            data_dir: Some(PathBuf::from("/srv/zerobyte")),
+        };
+        let divergences = facts.divergences(4096, Some(std::path::Path::new("/var/lib/zb")));
+        let settings: Vec<_> = divergences.iter().map(|d| d.setting).collect();
+        assert_eq!(settings, vec!["port", "data_dir"]);
+        assert_eq!(divergences[0].actual, "4097");
+
+        assert!(facts
+            .divergences(4097, Some(std::path::Path::new("/srv/zerobyte")))
+            .is_empty());
+        assert!(BannerFacts::default().divergences(4096, None).is_empty());
+    }
+}
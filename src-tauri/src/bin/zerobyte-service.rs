@@ -15,7 +15,13 @@ mod windows_service {
     use std::process::{Child, Command, Stdio};
     use std::sync::mpsc::{self, Receiver};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+
+    use serde::Deserialize;
+    use zerobyte_lib::backend_token;
+    use zerobyte_lib::graceful::{GracefulWait, WaitOutcome};
+    use zerobyte_lib::proctree::{self, ProcessTree};
+    use zerobyte_lib::readiness::{self, HealthState, ReadinessDeadline};
 
     /// Port used for Windows Service mode
     const SERVICE_PORT: u16 = 4097;
@@ -24,7 +30,9 @@ mod windows_service {
         ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
         ServiceType,
     };
-    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::service_control_handler::{
+        self, ServiceControlHandlerResult, ServiceStatusHandle,
+    };
     use windows_service::{define_windows_service, service_dispatcher};
 
     const SERVICE_NAME: &str = "C3iBackupONE";
@@ -32,6 +40,46 @@ mod windows_service {
 
     define_windows_service!(ffi_service_main, service_main);
 
+    /// Settings read from `service.toml` in the service data directory
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    struct ServiceConfig {
+        /// Hard cap on how long to wait for the server to exit after a shutdown request
+        stop_timeout_secs: u64,
+    }
+
+    impl Default for ServiceConfig {
+        fn default() -> Self {
+            Self {
+                stop_timeout_secs: 20,
+            }
+        }
+    }
+
+    impl ServiceConfig {
+        fn load() -> Self {
+            let path = service_data_dir().join("service.toml");
+            match std::fs::read_to_string(&path) {
+                Ok(content) => match toml::from_str(&content) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        eprintln!("Invalid {}, using defaults: {}", path.display(), e);
+                        Self::default()
+                    }
+                },
+                Err(_) => Self::default(),
+            }
+        }
+    }
+
+    /// Directory where the service keeps its data (%PROGRAMDATA%\C3i Backup ONE)
+    fn service_data_dir() -> PathBuf {
+        env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+            .join("C3i Backup ONE")
+    }
+
     pub fn run() -> Result<(), windows_service::Error> {
         // Register and start the service
         service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
@@ -45,13 +93,19 @@ mod windows_service {
     }
 
     fn run_service() -> Result<(), Box<dyn std::error::Error>> {
+        let config = ServiceConfig::load();
+        // Without a token anything on the machine could drive the server
+        let token = backend_token::generate().ok_or("no backend token could be made")?;
+        let owner = backend_token::owner_from_args(&env::args().collect::<Vec<_>>());
+        write_token(Some(&token), owner.as_deref());
+
         // Create a channel to receive stop events
         let (shutdown_tx, shutdown_rx) = mpsc::channel();
 
         // Register the service control handler
         let event_handler = move |control_event| -> ServiceControlHandlerResult {
             match control_event {
-                ServiceControl::Stop => {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
                     let _ = shutdown_tx.send(());
                     ServiceControlHandlerResult::NoError
                 }
@@ -63,27 +117,27 @@ mod windows_service {
         let status_handle = service_control_handler::register(SERVICE_NAME, event_handler)?;
 
         // Report that we're starting
-        status_handle.set_service_status(ServiceStatus {
-            service_type: SERVICE_TYPE,
-            current_state: ServiceState::StartPending,
-            controls_accepted: ServiceControlAccept::empty(),
-            exit_code: ServiceExitCode::Win32(0),
-            checkpoint: 0,
-            wait_hint: Duration::from_secs(10),
-            process_id: None,
-        })?;
+        status_handle.set_service_status(start_pending_status(0))?;
 
         // Find the server executable
         let server_exe = find_server_executable()?;
 
         // Start the server process with service mode enabled
-        let mut server_process = start_server_process(&server_exe)?;
+        // Stay in StartPending with fresh checkpoints until the server is actually ready,
+        // which can take minutes while it migrates a large database on first start
+        let report_checkpoint = |checkpoint| {
+            if let Err(e) = status_handle.set_service_status(start_pending_status(checkpoint)) {
+                eprintln!("Failed to report start checkpoint {}: {}", checkpoint, e);
+            }
+        };
+        let (mut server_process, tree) =
+            start_server_process(&server_exe, Some(&token), report_checkpoint)?;
 
         // Report that we're running
         status_handle.set_service_status(ServiceStatus {
             service_type: SERVICE_TYPE,
             current_state: ServiceState::Running,
-            controls_accepted: ServiceControlAccept::STOP,
+            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
             exit_code: ServiceExitCode::Win32(0),
             checkpoint: 0,
             wait_hint: Duration::default(),
@@ -94,18 +148,17 @@ mod windows_service {
         wait_for_shutdown(shutdown_rx, &mut server_process);
 
         // Report that we're stopping
-        status_handle.set_service_status(ServiceStatus {
-            service_type: SERVICE_TYPE,
-            current_state: ServiceState::StopPending,
-            controls_accepted: ServiceControlAccept::empty(),
-            exit_code: ServiceExitCode::Win32(0),
-            checkpoint: 0,
-            wait_hint: Duration::from_secs(10),
-            process_id: None,
-        })?;
+        status_handle.set_service_status(stop_pending_status(0, STOP_WAIT_HINT))?;
 
         // Stop the server gracefully
-        stop_server_gracefully(&mut server_process);
+        stop_server_gracefully(
+            &mut server_process,
+            &tree,
+            &status_handle,
+            &config,
+            Some(&token),
+        );
+        write_token(None, None);
 
         // Report that we've stopped
         status_handle.set_service_status(ServiceStatus {
@@ -121,6 +174,30 @@ mod windows_service {
         Ok(())
     }
 
+    /// Publish the server's token for `owner`'s desktop to read, or remove it once the server
+    /// stops. A service registered without an owner publishes nothing.
+    fn write_token(token: Option<&str>, owner: Option<&str>) {
+        let path = service_data_dir().join(backend_token::SERVICE_TOKEN_FILE);
+        let result = match (token, owner) {
+            (Some(token), Some(owner)) => backend_token::write_service_token(&path, token, owner),
+            (Some(_), None) => {
+                eprintln!(
+                    "Service registered without {}, not publishing the token; reinstall \
+                     the service from the desktop app so it can connect",
+                    backend_token::OWNER_ARG
+                );
+                return;
+            }
+            (None, _) => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to update {}: {}", path.display(), e);
+        }
+    }
+
     fn find_server_executable() -> Result<PathBuf, Box<dyn std::error::Error>> {
         // Look for the server executable in the same directory as this service
         let current_exe = env::current_exe()?;
@@ -144,35 +221,110 @@ mod windows_service {
         .into())
     }
 
-    fn start_server_process(server_exe: &PathBuf) -> Result<Child, Box<dyn std::error::Error>> {
+    /// Wait hint reported with each StartPending checkpoint
+    const START_WAIT_HINT: Duration = Duration::from_secs(10);
+
+    /// How long a server that reports no startup progress gets to become ready
+    const STARTUP_BASE_WAIT: Duration = Duration::from_secs(15);
+
+    /// Extra time granted each time the server reports startup progress (e.g. migrations)
+    const STARTUP_PROGRESS_EXTENSION: Duration = Duration::from_secs(120);
+
+    /// Upper bound on the startup wait, even while progress keeps advancing
+    const STARTUP_MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+    fn start_pending_status(checkpoint: u32) -> ServiceStatus {
+        ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: ServiceState::StartPending,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint,
+            wait_hint: START_WAIT_HINT,
+            process_id: None,
+        }
+    }
+
+    /// Spawn the server and wait until it is ready, calling `report_checkpoint` on every
+    /// poll so the service can stay in StartPending for as long as startup takes
+    fn start_server_process(
+        server_exe: &PathBuf,
+        token: Option<&str>,
+        mut report_checkpoint: impl FnMut(u32),
+    ) -> Result<(Child, ProcessTree), Box<dyn std::error::Error>> {
         // Set environment variables for service mode
-        let child = Command::new(server_exe)
+        let mut command = Command::new(server_exe);
+        command
             .env("ZEROBYTE_SERVICE_MODE", "1")
             .env("PORT", SERVICE_PORT.to_string())
             .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
+            .stderr(Stdio::null());
+        if let Some(token) = token {
+            command.env(backend_token::TOKEN_ENV, token);
+        }
+        let mut child = command.spawn()?;
+        // Helpers the server starts belong to its tree from the start
+        let tree = ProcessTree::adopt(child.id());
 
         // Wait for the server to be ready
         let client = reqwest::blocking::Client::builder()
             .timeout(Duration::from_secs(2))
             .build()?;
 
-        let url = format!("http://localhost:{}/healthcheck", SERVICE_PORT);
-        for attempt in 1..=30 {
-            match client.get(&url).send() {
-                Ok(response) if response.status().is_success() => {
-                    println!("Server is ready (attempt {})", attempt);
-                    return Ok(child);
-                }
-                _ => {
-                    if attempt < 30 {
-                        thread::sleep(Duration::from_millis(500));
+        let url = format!("http://localhost:{}/healthcheck?detail=1", SERVICE_PORT);
+        let mut deadline = ReadinessDeadline::new(
+            STARTUP_BASE_WAIT,
+            STARTUP_PROGRESS_EXTENSION,
+            STARTUP_MAX_WAIT,
+        );
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            report_checkpoint(attempt);
+
+            let mut request = client.get(&url);
+            if let Some(token) = token {
+                request = request.header(backend_token::TOKEN_HEADER, token);
+            }
+            if let Ok(response) = request.send() {
+                let success = response.status().is_success();
+                let body = response.text().unwrap_or_default();
+                match readiness::parse_health_response(success, &body) {
+                    HealthState::Ready => {
+                        println!("Server is ready (attempt {})", attempt);
+                        return Ok((child, tree));
+                    }
+                    HealthState::Starting(Some(progress)) => {
+                        if deadline.observe(Instant::now(), &progress) {
+                            println!(
+                                "Server is starting: {} ({}s left before giving up)",
+                                progress,
+                                deadline.remaining(Instant::now()).as_secs()
+                            );
+                        }
                     }
+                    HealthState::Starting(None) => {}
                 }
             }
+
+            if let Ok(Some(status)) = child.try_wait() {
+                tree.kill_leftovers();
+                return Err(format!("Server exited during startup with status: {}", status).into());
+            }
+
+            if deadline.expired(Instant::now()) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(500));
         }
 
+        eprintln!(
+            "Server did not become ready after {} attempts, stopping it",
+            attempt
+        );
+        kill_tree(&tree);
+        let _ = child.wait();
         Err("Server failed to start within timeout".into())
     }
 
@@ -205,35 +357,121 @@ mod windows_service {
         }
     }
 
-    fn stop_server_gracefully(server_process: &mut Child) {
+    /// Wait hint reported with each StopPending checkpoint, comfortably above the checkpoint interval
+    const STOP_WAIT_HINT: Duration = Duration::from_secs(6);
+
+    fn stop_pending_status(checkpoint: u32, wait_hint: Duration) -> ServiceStatus {
+        ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: ServiceState::StopPending,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint,
+            wait_hint,
+            process_id: None,
+        }
+    }
+
+    fn stop_server_gracefully(
+        server_process: &mut Child,
+        tree: &ProcessTree,
+        status_handle: &ServiceStatusHandle,
+        config: &ServiceConfig,
+        token: Option<&str>,
+    ) {
         // Try to send a graceful shutdown request
-        let client = reqwest::blocking::Client::builder()
+        let requested = reqwest::blocking::Client::builder()
             .timeout(Duration::from_secs(5))
-            .build();
+            .build()
+            .map(|client| {
+                let url = format!("http://localhost:{}/api/shutdown", SERVICE_PORT);
+                let mut request = client.post(&url);
+                if let Some(token) = token {
+                    request = request.header(backend_token::TOKEN_HEADER, token);
+                }
+                request.send().is_ok()
+            })
+            .unwrap_or(false);
+
+        if !requested {
+            eprintln!("Shutdown sequence: graceful shutdown request failed");
+            force_kill(server_process, tree);
+            return;
+        }
 
-        if let Ok(client) = client {
-            let url = format!("http://localhost:{}/api/shutdown", SERVICE_PORT);
-            let _ = client.post(&url).send();
-            // Wait for graceful shutdown
-            thread::sleep(Duration::from_secs(3));
+        println!("Shutdown sequence: graceful shutdown requested, waiting for server to exit");
+
+        // Keep the SCM informed with incremental checkpoints so it doesn't give up on us
+        // while the server is still flushing its database
+        let wait = GracefulWait::with_hard_cap(Duration::from_secs(config.stop_timeout_secs));
+        let outcome = wait.wait(server_process, |progress| {
+            println!(
+                "Shutdown sequence: server still running (checkpoint {}, {:.1}s elapsed, {:.1}s left)",
+                progress.checkpoint,
+                progress.elapsed.as_secs_f64(),
+                progress.remaining.as_secs_f64()
+            );
+            if let Err(e) = status_handle
+                .set_service_status(stop_pending_status(progress.checkpoint, STOP_WAIT_HINT))
+            {
+                eprintln!("Failed to report stop checkpoint {}: {}", progress.checkpoint, e);
+            }
+        });
+
+        match outcome {
+            WaitOutcome::Exited { elapsed, .. } => {
+                println!(
+                    "Server stopped gracefully after {:.1}s",
+                    elapsed.as_secs_f64()
+                );
+            }
+            WaitOutcome::CapReached {
+                elapsed,
+                checkpoints,
+            } => {
+                eprintln!(
+                    "Shutdown sequence: stop cap of {}s reached during graceful wait ({:.1}s elapsed, {} checkpoints reported)",
+                    config.stop_timeout_secs,
+                    elapsed.as_secs_f64(),
+                    checkpoints
+                );
+                force_kill(server_process, tree);
+            }
         }
+    }
 
-        // Check if process is still running
+    fn force_kill(server_process: &mut Child, tree: &ProcessTree) {
         match server_process.try_wait() {
             Ok(Some(_)) => {
-                println!("Server stopped gracefully");
+                println!("Server already exited");
+                tree.kill_leftovers();
             }
             Ok(None) => {
-                // Force kill if still running
-                println!("Force killing server process");
-                let _ = server_process.kill();
+                println!("Shutdown sequence: force killing server process tree");
+                kill_tree(tree);
                 let _ = server_process.wait();
             }
             Err(e) => {
                 eprintln!("Error waiting for server: {}", e);
-                let _ = server_process.kill();
+                kill_tree(tree);
             }
         }
+
+        let holders = proctree::port_holders(SERVICE_PORT, Duration::from_secs(2));
+        if !holders.is_empty() {
+            eprintln!(
+                "Port {} is still held after the kill by pid(s) {:?}",
+                SERVICE_PORT, holders
+            );
+        }
+    }
+
+    /// Kill the server and every helper it started
+    fn kill_tree(tree: &ProcessTree) {
+        let report = tree.kill();
+        if !report.survivors.is_empty() {
+            eprintln!("Server processes survived the kill: {:?}", report.survivors);
+        }
     }
 }
 
@@ -243,9 +481,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() > 1 && args[1] == "--install" {
-        // Install the service
+        // Install the service for the user named with --owner, or else the one running this
         println!("Installing C3i Backup ONE service...");
-        install_service()?;
+        let owner = zerobyte_lib::backend_token::owner_from_args(&args)
+            .or_else(zerobyte_lib::backend_token::current_user_sid)
+            .ok_or("Can't tell which user the service belongs to")?;
+        install_service(&owner)?;
         println!("Service installed successfully");
         return Ok(());
     }
@@ -263,7 +504,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[cfg(windows)]
-fn install_service() -> Result<(), Box<dyn std::error::Error>> {
+fn install_service(owner: &str) -> Result<(), Box<dyn std::error::Error>> {
     use std::process::Command;
 
     let current_exe = std::env::current_exe()?;
@@ -273,7 +514,12 @@ fn install_service() -> Result<(), Box<dyn std::error::Error>> {
         .args([
             "create",
             "C3iBackupONE",
-            &format!("binPath= \"{}\"", exe_path),
+            &format!(
+                "binPath= \"\\\"{}\\\" {} {}\"",
+                exe_path,
+                zerobyte_lib::backend_token::OWNER_ARG,
+                owner
+            ),
             "start= auto",
             "DisplayName= C3i Backup ONE Service",
         ])
@@ -305,8 +551,13 @@ fn uninstall_service() -> Result<(), Box<dyn std::error::Error>> {
         .args(["stop", "C3iBackupONE"])
         .output();
 
-    // Wait a bit
-    std::thread::sleep(std::time::Duration::from_secs(2));
+    // Wait for the server to go instead of guessing how long stopping takes
+    let port = zerobyte_lib::config::DEFAULT_SERVICE_PORT;
+    let outcome =
+        zerobyte_lib::graceful::wait_for_port_closed(port, std::time::Duration::from_secs(30));
+    if let zerobyte_lib::graceful::WaitOutcome::CapReached { elapsed, .. } = outcome {
+        eprintln!("Service still serving on port {} after {:.1}s", port, elapsed.as_secs_f64());
+    }
 
     // Delete the service
     let output = Command::new("sc")
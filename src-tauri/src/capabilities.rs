@@ -0,0 +1,326 @@
+//! What the connected backend's API supports, and how desktop features degrade without it.
+//!
+//! After the backend is ready the desktop asks it for a capability descriptor
+//! (`GET /api/capabilities`). Older backends don't have one, so capabilities are inferred from
+//! the version in the healthcheck, or assumed to be the baseline when that's missing too.
+//! Features that depend on an endpoint consult [`availability`] instead of calling it blindly.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Backend endpoint serving the capability descriptor
+pub const DESCRIPTOR_PATH: &str = "/api/capabilities";
+
+/// Set of API features the backend supports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BackendCapabilities(u32);
+
+impl BackendCapabilities {
+    pub const NONE: Self = Self(0);
+    /// Pausing and resuming all backups
+    pub const PAUSE: Self = Self(1 << 0);
+    /// Listing queued and running jobs
+    pub const JOB_QUEUE: Self = Self(1 << 1);
+    /// Server-sent event stream for the desktop
+    pub const EVENT_STREAM: Self = Self(1 << 2);
+    /// Listing backup schedules
+    pub const SCHEDULES: Self = Self(1 << 3);
+    /// Acknowledging failure events
+    pub const ACKNOWLEDGE: Self = Self(1 << 4);
+    /// Storing desktop client state
+    pub const CLIENT_STATE: Self = Self(1 << 5);
+    /// Startup progress in `/healthcheck?detail=1`
+    pub const HEALTH_DETAIL: Self = Self(1 << 6);
+    /// Unlocking an encrypted repository with a passphrase
+    pub const UNLOCK: Self = Self(1 << 7);
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Names of the contained capabilities, in table order
+    pub fn names(self) -> Vec<&'static str> {
+        CAPABILITY_NAMES
+            .iter()
+            .filter(|(_, capability)| self.contains(*capability))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl fmt::Display for BackendCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.names().join(", "))
+    }
+}
+
+/// Wire names used by the capability descriptor
+pub const CAPABILITY_NAMES: &[(&str, BackendCapabilities)] = &[
+    ("pause", BackendCapabilities::PAUSE),
+    ("job_queue", BackendCapabilities::JOB_QUEUE),
+    ("event_stream", BackendCapabilities::EVENT_STREAM),
+    ("schedules", BackendCapabilities::SCHEDULES),
+    ("acknowledge", BackendCapabilities::ACKNOWLEDGE),
+    ("client_state", BackendCapabilities::CLIENT_STATE),
+    ("health_detail", BackendCapabilities::HEALTH_DETAIL),
+    ("unlock", BackendCapabilities::UNLOCK),
+];
+
+/// Capabilities assumed for backends that report neither a descriptor nor a version
+pub const BASELINE: BackendCapabilities = BackendCapabilities::NONE;
+
+/// Capabilities introduced by each backend version, oldest first. Every released backend
+/// predates the descriptor endpoint and only offers the baseline; versions that add an
+/// endpoint without a descriptor get an entry here.
+const VERSION_CAPABILITIES: &[((u64, u64, u64), BackendCapabilities)] = &[];
+
+#[derive(Deserialize)]
+struct Descriptor {
+    capabilities: Vec<String>,
+}
+
+/// Parse a `GET /api/capabilities` body. Unknown names are ignored so newer backends work.
+pub fn parse_descriptor(body: &str) -> Option<BackendCapabilities> {
+    let descriptor: Descriptor = serde_json::from_str(body).ok()?;
+    Some(
+        descriptor
+            .capabilities
+            .iter()
+            .filter_map(|name| {
+                CAPABILITY_NAMES
+                    .iter()
+                    .find(|(known, _)| known == name)
+                    .map(|(_, capability)| *capability)
+            })
+            .fold(BackendCapabilities::NONE, BackendCapabilities::union),
+    )
+}
+
+/// Parse "1.2.3", "v1.2" or "1.2.3-beta" into a comparable triple
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// Infer capabilities from a backend version string
+pub fn infer_from_version(version: &str) -> BackendCapabilities {
+    let Some(version) = parse_version(version) else {
+        return BASELINE;
+    };
+    VERSION_CAPABILITIES
+        .iter()
+        .filter(|(since, _)| version >= *since)
+        .fold(BASELINE, |caps, (_, added)| caps.union(*added))
+}
+
+/// Desktop features that depend on backend endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    TrayPause,
+    JobQueue,
+    EventBridge,
+    ScheduleConflicts,
+    AcknowledgeFailure,
+    ClientState,
+    RepositoryUnlock,
+}
+
+/// What a feature does when the backend lacks what it needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    /// Hide the entry point (menu item, action)
+    Hide,
+    /// Fall back to polling existing endpoints
+    Poll,
+    /// Refuse with an `UnsupportedByBackend` error
+    Unsupported,
+}
+
+/// Whether a feature can run against the current backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "degradation", rename_all = "snake_case")]
+pub enum Availability {
+    Available,
+    Degraded(Degradation),
+}
+
+/// Requirement and fallback for each feature
+const FEATURE_REQUIREMENTS: &[(Feature, BackendCapabilities, Degradation)] = &[
+    (Feature::TrayPause, BackendCapabilities::PAUSE, Degradation::Hide),
+    (Feature::JobQueue, BackendCapabilities::JOB_QUEUE, Degradation::Hide),
+    (Feature::EventBridge, BackendCapabilities::EVENT_STREAM, Degradation::Poll),
+    (
+        Feature::ScheduleConflicts,
+        BackendCapabilities::SCHEDULES,
+        Degradation::Unsupported,
+    ),
+    (
+        Feature::AcknowledgeFailure,
+        BackendCapabilities::ACKNOWLEDGE,
+        Degradation::Unsupported,
+    ),
+    (
+        Feature::ClientState,
+        BackendCapabilities::CLIENT_STATE,
+        Degradation::Unsupported,
+    ),
+    (
+        Feature::RepositoryUnlock,
+        BackendCapabilities::UNLOCK,
+        Degradation::Unsupported,
+    ),
+];
+
+/// Decide how `feature` behaves against a backend with `capabilities`
+pub fn availability(feature: Feature, capabilities: BackendCapabilities) -> Availability {
+    FEATURE_REQUIREMENTS
+        .iter()
+        .find(|(f, _, _)| *f == feature)
+        .map(|(_, required, degradation)| {
+            if capabilities.contains(*required) {
+                Availability::Available
+            } else {
+                Availability::Degraded(*degradation)
+            }
+        })
+        .unwrap_or(Availability::Available)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Caps = BackendCapabilities;
+
+    #[test]
+    fn descriptors_are_parsed_by_wire_name() {
+        let cases: &[(&str, Option<Caps>)] = &[
+            (r#"{"capabilities":[]}"#, Some(Caps::NONE)),
+            (r#"{"capabilities":["pause"]}"#, Some(Caps::PAUSE)),
+            (
+                r#"{"capabilities":["unlock","job_queue","pause"]}"#,
+                Some(Caps::PAUSE.union(Caps::JOB_QUEUE).union(Caps::UNLOCK)),
+            ),
+            // Newer backends may know more than we do
+            (
+                r#"{"capabilities":["schedules","teleport"],"time_zone":"UTC"}"#,
+                Some(Caps::SCHEDULES),
+            ),
+            (r#"{"capabilities":["pause","pause"]}"#, Some(Caps::PAUSE)),
+            (r#"{"capabilities":"pause"}"#, None),
+            (r#"{"features":["pause"]}"#, None),
+            ("Not Found", None),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(parse_descriptor(body), *expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn every_capability_has_a_distinct_wire_name() {
+        let all = CAPABILITY_NAMES
+            .iter()
+            .fold(Caps::NONE, |caps, (_, capability)| caps.union(*capability));
+        assert_eq!(all.bits().count_ones() as usize, CAPABILITY_NAMES.len());
+        let names: Vec<_> = CAPABILITY_NAMES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(all.names(), names);
+        let body = serde_json::json!({ "capabilities": names }).to_string();
+        assert_eq!(parse_descriptor(&body), Some(all));
+    }
+
+    #[test]
+    fn versions_are_compared_by_their_numeric_core() {
+        let cases = [
+            ("1.2.3", Some((1, 2, 3))),
+            ("v1.2", Some((1, 2, 0))),
+            (" 2 ", Some((2, 0, 0))),
+            ("1.2.3-beta.4", Some((1, 2, 3))),
+            ("1.10.0+build.7", Some((1, 10, 0))),
+            ("1.x", None),
+            ("", None),
+            ("nightly", None),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(parse_version(version), expected, "{}", version);
+        }
+        assert!(parse_version("1.10.0") > parse_version("1.9.9"));
+    }
+
+    #[test]
+    fn released_versions_only_offer_the_baseline() {
+        for version in ["0.1.0", "1.0.0", "v99.0", "garbage"] {
+            assert_eq!(infer_from_version(version), BASELINE, "{}", version);
+        }
+    }
+
+    #[test]
+    fn features_degrade_without_their_capability() {
+        use Degradation::*;
+        let cases = [
+            (Feature::TrayPause, Caps::PAUSE, Hide),
+            (Feature::JobQueue, Caps::JOB_QUEUE, Hide),
+            (Feature::EventBridge, Caps::EVENT_STREAM, Poll),
+            (Feature::ScheduleConflicts, Caps::SCHEDULES, Unsupported),
+            (Feature::AcknowledgeFailure, Caps::ACKNOWLEDGE, Unsupported),
+            (Feature::ClientState, Caps::CLIENT_STATE, Unsupported),
+            (Feature::RepositoryUnlock, Caps::UNLOCK, Unsupported),
+        ];
+        let everything = Caps::from_bits(u32::MAX);
+        for (feature, required, degradation) in cases {
+            assert_eq!(
+                availability(feature, BASELINE),
+                Availability::Degraded(degradation),
+                "{:?}",
+                feature
+            );
+            assert_eq!(availability(feature, required), Availability::Available);
+            assert_eq!(availability(feature, everything), Availability::Available);
+            // Another capability doesn't stand in for the missing one
+            let others = Caps::from_bits(everything.bits() & !required.bits());
+            assert_eq!(
+                availability(feature, others),
+                Availability::Degraded(degradation)
+            );
+        }
+    }
+
+    #[test]
+    fn capabilities_display_their_names() {
+        assert_eq!(Caps::NONE.to_string(), "[]");
+        assert_eq!(
+            Caps::UNLOCK.union(Caps::PAUSE).to_string(),
+            "[pause, unlock]"
+        );
+    }
+
+    #[test]
+    fn availability_serializes_for_the_ui() {
+        assert_eq!(
+            serde_json::to_value(Availability::Available).unwrap(),
+            serde_json::json!({ "status": "available" })
+        );
+        assert_eq!(
+            serde_json::to_value(Availability::Degraded(Degradation::Poll)).unwrap(),
+            serde_json::json!({ "status": "degraded", "degradation": "poll" })
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Windows Defender Controlled Folder Access (CFA) detection.
+//!
+//! With CFA on, Defender silently blocks writes into protected folders (Documents, Pictures,
+//! Desktop, ...) from apps that aren't on its allow list, and a restore into one fails with a
+//! bare permission error. The state comes from `Get-MpPreference`; on systems without
+//! Defender, or where PowerShell can't query it, the status is `Unavailable` and nothing else
+//! changes. Adding the backend executables to the allow list is an elevated command built by
+//! [`allowlist_command`].
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Whether CFA is blocking writes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state", content = "reason")]
+pub enum CfaState {
+    Disabled,
+    /// Writes from apps not on the allow list are blocked
+    Enabled,
+    /// Blocked writes are only logged
+    AuditOnly,
+    /// Only raw disk sector writes are blocked (or audited), not folders
+    DiskModificationOnly,
+    /// Defender isn't there or couldn't be queried
+    Unavailable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CfaStatus {
+    #[serde(flatten)]
+    pub state: CfaState,
+    pub allowed_apps: Vec<PathBuf>,
+    /// Folders the user added plus the ones Windows always protects
+    pub protected_folders: Vec<PathBuf>,
+}
+
+impl CfaStatus {
+    fn unavailable(reason: impl Into<String>) -> Self {
+        Self {
+            state: CfaState::Unavailable(reason.into()),
+            allowed_apps: Vec::new(),
+            protected_folders: Vec::new(),
+        }
+    }
+
+    /// Whether every one of `executables` is on the allow list
+    pub fn allows(&self, executables: &[PathBuf]) -> bool {
+        executables.iter().all(|exe| {
+            self.allowed_apps
+                .iter()
+                .any(|allowed| crate::registrations::same_path(allowed, exe))
+        })
+    }
+
+    /// Whether `path` lies in a protected folder
+    pub fn is_protected(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('/', "\\").to_lowercase();
+        self.protected_folders.iter().any(|folder| {
+            let folder = folder.to_string_lossy().replace('/', "\\").to_lowercase();
+            let folder = folder.trim_end_matches('\\');
+            path == folder || path.starts_with(&format!("{}\\", folder))
+        })
+    }
+
+    /// Whether writes by `executables` into `path` would be blocked
+    pub fn blocks(&self, path: &Path, executables: &[PathBuf]) -> bool {
+        self.state == CfaState::Enabled && self.is_protected(path) && !self.allows(executables)
+    }
+}
+
+/// A value `ConvertTo-Json` wrote as null, a single string or a list
+#[derive(Debug, Default, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn into_paths(self) -> Vec<PathBuf> {
+        match self {
+            OneOrMany::None => Vec::new(),
+            OneOrMany::One(value) => vec![PathBuf::from(value)],
+            OneOrMany::Many(values) => values.into_iter().map(PathBuf::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MpPreference {
+    enable_controlled_folder_access: Option<u32>,
+    #[serde(default)]
+    controlled_folder_access_allowed_applications: Option<OneOrMany>,
+    #[serde(default)]
+    controlled_folder_access_protected_folders: Option<OneOrMany>,
+}
+
+/// PowerShell that prints the CFA preferences as JSON
+pub const QUERY_SCRIPT: &str = concat!(
+    "Get-MpPreference | Select-Object EnableControlledFolderAccess, ",
+    "ControlledFolderAccessAllowedApplications, ControlledFolderAccessProtectedFolders ",
+    "| ConvertTo-Json -Compress",
+);
+
+/// Build the status from the query's output; `default_folders` are always protected
+pub fn parse_preferences(output: &str, default_folders: Vec<PathBuf>) -> CfaStatus {
+    let preference: MpPreference = match serde_json::from_str(output.trim()) {
+        Ok(preference) => preference,
+        Err(e) => return CfaStatus::unavailable(format!("Unexpected Defender output: {}", e)),
+    };
+    let state = match preference.enable_controlled_folder_access {
+        Some(0) => CfaState::Disabled,
+        Some(1) => CfaState::Enabled,
+        Some(2) => CfaState::AuditOnly,
+        Some(3) | Some(4) => CfaState::DiskModificationOnly,
+        Some(other) => return CfaStatus::unavailable(format!("Unknown CFA mode {}", other)),
+        None => return CfaStatus::unavailable("Defender didn't report a CFA mode"),
+    };
+
+    let mut protected_folders = default_folders;
+    protected_folders.extend(
+        preference
+            .controlled_folder_access_protected_folders
+            .unwrap_or_default()
+            .into_paths(),
+    );
+    CfaStatus {
+        state,
+        allowed_apps: preference
+            .controlled_folder_access_allowed_applications
+            .unwrap_or_default()
+            .into_paths(),
+        protected_folders,
+    }
+}
+
+/// Folders CFA protects in the current user's profile without being configured to
+pub fn default_protected_folders() -> Vec<PathBuf> {
+    let Some(profile) = std::env::var_os("USERPROFILE") else {
+        return Vec::new();
+    };
+    let profile = PathBuf::from(profile);
+    ["Documents", "Pictures", "Videos", "Music", "Desktop", "Favorites"]
+        .iter()
+        .map(|folder| profile.join(folder))
+        .collect()
+}
+
+/// PowerShell that adds `executables` to the allow list
+pub fn allowlist_command(executables: &[PathBuf]) -> String {
+    let quoted: Vec<String> = executables
+        .iter()
+        .map(|exe| format!("'{}'", exe.display().to_string().replace('\'', "''")))
+        .collect();
+    format!(
+        "Add-MpPreference -ControlledFolderAccessAllowedApplications {}",
+        quoted.join(",")
+    )
+}
+
+/// Executables that write restore output on the backend's behalf
+pub fn backend_executables(binaries_dir: &Path) -> Vec<PathBuf> {
+    vec![
+        binaries_dir.join("zerobyte-server.exe"),
+        binaries_dir.join("zerobyte-service.exe"),
+    ]
+}
+
+/// Query Defender. Blocks while PowerShell runs.
+#[cfg(target_os = "windows")]
+pub fn probe() -> CfaStatus {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", QUERY_SCRIPT])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => parse_preferences(
+            &String::from_utf8_lossy(&output.stdout),
+            default_protected_folders(),
+        ),
+        Ok(output) => CfaStatus::unavailable(format!(
+            "Get-MpPreference failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => CfaStatus::unavailable(format!("Failed to run PowerShell: {}", e)),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn probe() -> CfaStatus {
+    CfaStatus::unavailable("Controlled Folder Access is a Windows feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Get-MpPreference` output captured on machines in each state
+    const ENABLED: &str = include_str!("../testdata/cfa/enabled.json");
+    const ENABLED_SINGLE: &str = include_str!("../testdata/cfa/enabled-single.json");
+    const DISABLED: &str = include_str!("../testdata/cfa/disabled.json");
+    const AUDIT: &str = include_str!("../testdata/cfa/audit.json");
+    const DISK_AUDIT: &str = include_str!("../testdata/cfa/disk-audit.json");
+    const THIRD_PARTY_ANTIVIRUS: &str = include_str!("../testdata/cfa/third-party-antivirus.json");
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    fn defaults() -> Vec<PathBuf> {
+        paths(&[r"C:\Users\alice\Documents", r"C:\Users\alice\Desktop"])
+    }
+
+    #[test]
+    fn modes_are_parsed_from_captured_output() {
+        let cases = [
+            (ENABLED, CfaState::Enabled),
+            (ENABLED_SINGLE, CfaState::Enabled),
+            (DISABLED, CfaState::Disabled),
+            (AUDIT, CfaState::AuditOnly),
+            (DISK_AUDIT, CfaState::DiskModificationOnly),
+        ];
+        for (output, state) in cases {
+            assert_eq!(
+                parse_preferences(output, defaults()).state,
+                state,
+                "{}",
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn lists_merge_with_the_default_folders() {
+        let status = parse_preferences(ENABLED, defaults());
+        assert_eq!(
+            status.allowed_apps,
+            paths(&[
+                r"C:\Program Files\C3i Backup ONE\binaries\zerobyte-server.exe",
+                r"C:\Tools\sync.exe",
+            ])
+        );
+        assert_eq!(
+            status.protected_folders,
+            paths(&[
+                r"C:\Users\alice\Documents",
+                r"C:\Users\alice\Desktop",
+                r"D:\Photos",
+                r"\\nas\share\Scans",
+            ])
+        );
+
+        // A single entry comes out as a string, an empty list as null
+        let single = parse_preferences(ENABLED_SINGLE, defaults());
+        assert_eq!(single.allowed_apps, paths(&[r"C:\Tools\sync.exe"]));
+        assert_eq!(single.protected_folders, defaults());
+    }
+
+    #[test]
+    fn unusable_output_means_unavailable() {
+        let cases = [
+            (THIRD_PARTY_ANTIVIRUS, "didn't report a CFA mode"),
+            (
+                r#"{"EnableControlledFolderAccess":7}"#,
+                "Unknown CFA mode 7",
+            ),
+            ("", "Unexpected Defender output"),
+            (
+                "Get-MpPreference : The term 'Get-MpPreference' is not recognized",
+                "Unexpected Defender output",
+            ),
+        ];
+        for (output, reason) in cases {
+            let status = parse_preferences(output, defaults());
+            let CfaState::Unavailable(actual) = &status.state else {
+                panic!(
+                    "expected unavailable for {:?}, got {:?}",
+                    output, status.state
+                );
+            };
+            assert!(actual.contains(reason), "{}", actual);
+            assert!(status.protected_folders.is_empty());
+        }
+    }
+
+    #[test]
+    fn writes_are_blocked_only_into_protected_folders_by_unlisted_apps() {
+        // Spelled out rather than joined so the separators match off Windows
+        let executables = paths(&[
+            r"C:\Program Files\C3i Backup ONE\binaries\zerobyte-server.exe",
+            r"C:\Program Files\C3i Backup ONE\binaries\zerobyte-service.exe",
+        ]);
+        let mut status = parse_preferences(ENABLED, defaults());
+
+        assert!(status.is_protected(Path::new(r"c:\users\ALICE\documents\taxes")));
+        assert!(status.is_protected(Path::new("D:/Photos")));
+        assert!(!status.is_protected(Path::new(r"D:\Photos2")));
+        assert!(!status.is_protected(Path::new(r"C:\Users\alice")));
+
+        // Only the server is allowed, not the service
+        assert!(!status.allows(&executables));
+        assert!(status.allows(&executables[..1]));
+        assert!(status.blocks(Path::new(r"D:\Photos\2024"), &executables));
+        assert!(!status.blocks(Path::new(r"E:\Restore"), &executables));
+
+        status.allowed_apps.push(executables[1].clone());
+        assert!(!status.blocks(Path::new(r"D:\Photos\2024"), &executables));
+
+        let audit = parse_preferences(AUDIT, paths(&[r"D:\Photos"]));
+        assert!(!audit.blocks(Path::new(r"D:\Photos\2024"), &executables));
+    }
+
+    #[test]
+    fn allowlist_command_quotes_each_executable() {
+        let command = allowlist_command(&paths(&[
+            r"C:\Program Files\C3i\zerobyte-server.exe",
+            r"C:\Users\o'brien\zerobyte-service.exe",
+        ]));
+        assert_eq!(
+            command,
+            "Add-MpPreference -ControlledFolderAccessAllowedApplications \
+             'C:\\Program Files\\C3i\\zerobyte-server.exe',\
+             'C:\\Users\\o''brien\\zerobyte-service.exe'"
+        );
+    }
+
+    #[test]
+    fn backend_executables_cover_server_and_service() {
+        let binaries = Path::new("binaries");
+        assert_eq!(
+            backend_executables(binaries),
+            vec![
+                binaries.join("zerobyte-server.exe"),
+                binaries.join("zerobyte-service.exe")
+            ]
+        );
+    }
+
+    #[test]
+    fn status_serializes_with_a_flat_state() {
+        let status = parse_preferences(DISABLED, Vec::new());
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({ "state": "disabled", "allowed_apps": [], "protected_folders": [] })
+        );
+        let status = parse_preferences(THIRD_PARTY_ANTIVIRUS, Vec::new());
+        assert_eq!(
+            serde_json::to_value(&status).unwrap()["reason"],
+            "Defender didn't report a CFA mode"
+        );
+    }
+}
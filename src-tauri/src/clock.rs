@@ -0,0 +1,195 @@
+//! Clock skew estimation between the desktop and the backend host.
+//!
+//! Each successful healthcheck yields a sample (backend `Date` header minus the local time at
+//! the midpoint of the request). Samples are smoothed with an exponential moving average so a
+//! single slow response doesn't flip the warning on and off.
+
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Skew above which the user is warned
+pub const DEFAULT_SKEW_THRESHOLD: Duration = Duration::from_secs(120);
+
+/// Weight given to each new sample
+const SMOOTHING: f64 = 0.3;
+
+/// Payload of the `clock-skew-detected` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockSkewWarning {
+    pub skew_ms: i64,
+    pub threshold_ms: i64,
+    pub message: String,
+}
+
+/// Smoothed estimate of how far the backend clock is ahead (positive) or behind (negative)
+#[derive(Debug)]
+pub struct SkewEstimator {
+    estimate_ms: Option<f64>,
+    threshold: Duration,
+    warned: bool,
+}
+
+impl Default for SkewEstimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_SKEW_THRESHOLD)
+    }
+}
+
+impl SkewEstimator {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            estimate_ms: None,
+            threshold,
+            warned: false,
+        }
+    }
+
+    /// Current smoothed skew in milliseconds, if any sample was recorded
+    pub fn skew_ms(&self) -> Option<i64> {
+        self.estimate_ms.map(|ms| ms.round() as i64)
+    }
+
+    /// Record a raw skew sample. Returns a warning the first time the smoothed
+    /// estimate crosses the threshold; it re-arms once the skew drops back below it.
+    pub fn observe(&mut self, sample_ms: i64) -> Option<ClockSkewWarning> {
+        let estimate = match self.estimate_ms {
+            Some(prev) => prev + SMOOTHING * (sample_ms as f64 - prev),
+            None => sample_ms as f64,
+        };
+        self.estimate_ms = Some(estimate);
+
+        let threshold_ms = self.threshold.as_millis() as f64;
+        if estimate.abs() <= threshold_ms {
+            self.warned = false;
+            return None;
+        }
+        if self.warned {
+            return None;
+        }
+
+        self.warned = true;
+        let skew_ms = estimate.round() as i64;
+        Some(ClockSkewWarning {
+            skew_ms,
+            threshold_ms: threshold_ms as i64,
+            message: format!(
+                "The backend clock is {} {} this computer. Enable automatic time synchronization on both machines so schedules and backup times display correctly.",
+                describe_duration(skew_ms.unsigned_abs()),
+                if skew_ms > 0 { "ahead of" } else { "behind" }
+            ),
+        })
+    }
+
+    /// Convert a backend timestamp into local time using the current estimate
+    pub fn to_local(&self, backend_time: SystemTime) -> SystemTime {
+        match self.skew_ms() {
+            Some(ms) if ms > 0 => backend_time - Duration::from_millis(ms as u64),
+            Some(ms) => backend_time + Duration::from_millis(ms.unsigned_abs()),
+            None => backend_time,
+        }
+    }
+}
+
+/// Compute a skew sample from a response `Date` header and the local send/receive times
+pub fn sample_from_date_header(
+    date: &str,
+    sent_at: SystemTime,
+    received_at: SystemTime,
+) -> Option<i64> {
+    let backend = httpdate::parse_http_date(date).ok()?;
+    let sent = millis_since_epoch(sent_at)?;
+    let received = millis_since_epoch(received_at)?;
+    let local_midpoint = sent + (received - sent) / 2;
+    // The Date header has one second resolution; report the middle of that second
+    Some(millis_since_epoch(backend)? + 500 - local_midpoint)
+}
+
+fn millis_since_epoch(time: SystemTime) -> Option<i64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_millis() as i64)
+}
+
+fn describe_duration(ms: u64) -> String {
+    let minutes = ms / 60_000;
+    if minutes >= 120 {
+        format!("about {} hours", minutes / 60)
+    } else {
+        format!("about {} minutes", minutes.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE_MS: i64 = 60_000;
+
+    #[test]
+    fn first_sample_is_taken_as_is() {
+        let mut estimator = SkewEstimator::default();
+        assert_eq!(estimator.skew_ms(), None);
+        estimator.observe(1500);
+        assert_eq!(estimator.skew_ms(), Some(1500));
+    }
+
+    #[test]
+    fn one_outlier_moves_the_estimate_only_partly() {
+        let mut estimator = SkewEstimator::default();
+        estimator.observe(0);
+        // A single slow response shouldn't trip a 2 minute threshold on its own
+        assert!(estimator.observe(3 * MINUTE_MS).is_none());
+        assert_eq!(estimator.skew_ms(), Some(54_000));
+    }
+
+    #[test]
+    fn steady_skew_converges_and_warns_once() {
+        let mut estimator = SkewEstimator::default();
+        estimator.observe(0);
+        let warnings: Vec<_> = (0..20)
+            .filter_map(|_| estimator.observe(-5 * MINUTE_MS))
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].skew_ms < -2 * MINUTE_MS);
+        assert_eq!(warnings[0].threshold_ms, 2 * MINUTE_MS);
+        assert!(warnings[0].message.contains("behind"));
+        assert!((estimator.skew_ms().unwrap() + 5 * MINUTE_MS).abs() < 1000);
+    }
+
+    #[test]
+    fn warning_rearms_once_the_skew_recovers() {
+        let mut estimator = SkewEstimator::new(Duration::from_secs(60));
+        assert!(estimator.observe(10 * MINUTE_MS).is_some());
+        assert!(estimator.observe(10 * MINUTE_MS).is_none());
+        while estimator.skew_ms().unwrap() > MINUTE_MS {
+            assert!(estimator.observe(0).is_none());
+        }
+        let again = estimator.observe(10 * MINUTE_MS);
+        assert!(again.is_some_and(|warning| warning.message.contains("ahead of")));
+    }
+
+    #[test]
+    fn backend_times_are_shifted_by_the_estimate() {
+        let mut estimator = SkewEstimator::default();
+        let backend = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(estimator.to_local(backend), backend);
+        estimator.observe(5000);
+        assert_eq!(
+            estimator.to_local(backend),
+            backend - Duration::from_millis(5000)
+        );
+    }
+
+    #[test]
+    fn sample_compares_the_header_with_the_request_midpoint() {
+        let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let received = sent + Duration::from_millis(200);
+        let date = httpdate::fmt_http_date(sent + Duration::from_secs(60));
+        // 60 s ahead, plus half a second for the header's resolution, minus half the round trip
+        assert_eq!(
+            sample_from_date_header(&date, sent, received),
+            Some(60_000 + 500 - 100)
+        );
+        assert_eq!(sample_from_date_header("yesterday", sent, received), None);
+    }
+}
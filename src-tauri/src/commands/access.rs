@@ -0,0 +1,290 @@
+use crate::access::{self, PathAccessResult};
+use crate::cfa::{self, CfaStatus};
+#[cfg(target_os = "windows")]
+use crate::dialogs::{self, Prompt};
+use crate::elevation::{BusyPolicy, ElevationClass};
+use crate::error::AppError;
+use crate::AppState;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+/// Check whether the backend can read each backup source path
+/// Each result carries a classification and a suggested fix for the source picker
+#[tauri::command]
+pub async fn check_path_access(
+    state: tauri::State<'_, AppState>,
+    paths: Vec<String>,
+) -> Result<Vec<PathAccessResult>, AppError> {
+    let using_service = state.using_service.load(Ordering::SeqCst);
+    let user = std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default();
+
+    let executables = cfa::backend_executables(&state.paths().binaries_dir);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        // Defender only exists on Windows; elsewhere there's nothing to ask
+        let cfa = cfg!(target_os = "windows").then(cfa::probe);
+        paths
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                let mut result = access::check_path(path, &user, using_service);
+                if cfa.as_ref().is_some_and(|cfa| cfa.blocks(path, &executables)) {
+                    access::mark_controlled_folder(&mut result);
+                }
+                result
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Failed to check path access: {}", e).into())
+}
+
+/// Grant the backup account read access to a folder (requires elevation)
+/// Asks the user to confirm in a native dialog before prompting for UAC
+#[tauri::command]
+pub async fn grant_path_access(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<(), AppError> {
+    let using_service = state.using_service.load(Ordering::SeqCst);
+    state
+        .elevation
+        .run(
+            ElevationClass::FileAccess,
+            "grant_path_access",
+            BusyPolicy::Queue,
+            grant_path_access_elevated(app, using_service, path),
+        )
+        .await
+}
+
+#[cfg(target_os = "windows")]
+async fn grant_path_access_elevated(
+    app: tauri::AppHandle,
+    using_service: bool,
+    path: String,
+) -> Result<(), AppError> {
+    use access::PathAccess;
+    use tracing::info;
+
+    let target = std::path::PathBuf::from(&path);
+    let is_dir = std::fs::metadata(&target)
+        .map(|m| m.is_dir())
+        .map_err(|e| format!("Cannot read {}: {}", path, e))?;
+
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    let domain = std::env::var("USERDOMAIN").ok();
+    let account = grant_account(using_service, &user, domain.as_deref());
+
+    let command = access::grant_read_command(&target, &account, is_dir)?;
+
+    let prompt = Prompt::GrantPathAccess {
+        account: account.clone(),
+        path: path.clone(),
+    };
+    dialogs::confirm(&app, prompt, false, None).await?;
+
+    let log_path = std::env::temp_dir().join("zerobyte_grant_access.log");
+    let _ = std::fs::remove_file(&log_path);
+
+    let script = grant_script(&command, &log_path);
+
+    super::service::execute_elevated_script(
+        "zerobyte_grant_access.bat",
+        script,
+        &log_path,
+        "Access granted",
+    )
+    .await?;
+
+    info!("Granted {} read access to {}", account, path);
+
+    // The sidecar runs as this user, so we can verify the grant directly
+    if !using_service {
+        let result = access::check_path(&target, &user, using_service);
+        if result.status != PathAccess::Ok {
+            return Err(format!(
+                "Access is still denied after granting permissions: {}",
+                result.detail.unwrap_or_default()
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Account the backend reads files as: the service runs as LocalSystem, the sidecar as
+/// the logged-in user
+#[cfg(any(target_os = "windows", test))]
+fn grant_account(using_service: bool, user: &str, domain: Option<&str>) -> String {
+    if using_service {
+        return r"NT AUTHORITY\SYSTEM".to_string();
+    }
+    match domain {
+        Some(domain) => format!(r"{}\{}", domain, user),
+        None => user.to_string(),
+    }
+}
+
+/// Batch script running the `icacls` grant, logging its outcome to `log`
+#[cfg(any(target_os = "windows", test))]
+fn grant_script(command: &str, log: &Path) -> String {
+    format!(
+        r#"@echo off
+echo Granting access... > "{log}"
+{command} >> "{log}" 2>&1
+if %errorlevel% neq 0 (
+    echo ERROR: Failed to grant access >> "{log}"
+    exit /b %errorlevel%
+)
+echo Access granted >> "{log}"
+"#,
+        command = command,
+        log = log.display()
+    )
+}
+
+#[cfg(not(target_os = "windows"))]
+async fn grant_path_access_elevated(
+    _app: tauri::AppHandle,
+    _using_service: bool,
+    _path: String,
+) -> Result<(), AppError> {
+    Err("Granting folder access is only supported on Windows".into())
+}
+
+/// Controlled Folder Access state and whether the backend is on its allow list
+#[tauri::command]
+pub async fn get_controlled_folder_access() -> Result<CfaStatus, AppError> {
+    tauri::async_runtime::spawn_blocking(cfa::probe)
+        .await
+        .map_err(|e| format!("Failed to query Controlled Folder Access: {}", e).into())
+}
+
+/// Add the backend executables to the Controlled Folder Access allow list (requires
+/// elevation). Asks the user to confirm in a native dialog before prompting for UAC.
+#[tauri::command]
+pub async fn request_cfa_allowlist(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), AppError> {
+    let executables = cfa::backend_executables(&state.paths().binaries_dir);
+    state
+        .elevation
+        .run(
+            ElevationClass::FileAccess,
+            "request_cfa_allowlist",
+            BusyPolicy::Queue,
+            request_cfa_allowlist_elevated(app, executables),
+        )
+        .await
+}
+
+#[cfg(target_os = "windows")]
+async fn request_cfa_allowlist_elevated(
+    app: tauri::AppHandle,
+    executables: Vec<std::path::PathBuf>,
+) -> Result<(), AppError> {
+    let executables: Vec<_> = executables.into_iter().filter(|exe| exe.exists()).collect();
+    if executables.is_empty() {
+        return Err("No backend executables found to allow".into());
+    }
+    let command = cfa::allowlist_command(&executables);
+
+    let prompt = Prompt::CfaAllowlist {
+        executables: executables
+            .iter()
+            .map(|exe| exe.display().to_string())
+            .collect(),
+    };
+    dialogs::confirm(&app, prompt, false, None).await?;
+
+    let log_path = std::env::temp_dir().join("zerobyte_cfa_allowlist.log");
+    let _ = std::fs::remove_file(&log_path);
+
+    // cmd expands %...% even inside quotes
+    let script = format!(
+        r#"@echo off
+echo Updating the Controlled Folder Access allow list... > "{log}"
+powershell -NoProfile -NonInteractive -ExecutionPolicy Bypass -Command "{command}" >> "{log}" 2>&1
+if %errorlevel% neq 0 (
+    echo ERROR: Failed to update the allow list >> "{log}"
+    exit /b %errorlevel%
+)
+echo Allow list updated >> "{log}"
+"#,
+        command = command.replace('%', "%%"),
+        log = log_path.display()
+    );
+
+    super::service::execute_elevated_script(
+        "zerobyte_cfa_allowlist.bat",
+        script,
+        &log_path,
+        "Allow list updated",
+    )
+    .await?;
+
+    let status = tauri::async_runtime::spawn_blocking(cfa::probe)
+        .await
+        .map_err(|e| format!("Failed to query Controlled Folder Access: {}", e))?;
+    if !status.allows(&executables) {
+        return Err("The backend is still not on the Controlled Folder Access allow list".into());
+    }
+    tracing::info!("Added the backend to the Controlled Folder Access allow list");
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+async fn request_cfa_allowlist_elevated(
+    _app: tauri::AppHandle,
+    _executables: Vec<std::path::PathBuf>,
+) -> Result<(), AppError> {
+    Err("Controlled Folder Access is a Windows feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grant_account_matches_the_backend_process() {
+        let cases = [
+            (true, Some("OFFICE"), r"NT AUTHORITY\SYSTEM"),
+            (true, None, r"NT AUTHORITY\SYSTEM"),
+            (false, Some("OFFICE"), r"OFFICE\alice"),
+            (false, None, "alice"),
+        ];
+        for (using_service, domain, expected) in cases {
+            assert_eq!(grant_account(using_service, "alice", domain), expected);
+        }
+    }
+
+    #[test]
+    fn grant_script_runs_the_acl_command_and_reports_success() {
+        let command = access::grant_read_command(
+            Path::new(r"C:\Data\100%"),
+            &grant_account(false, "alice", Some("OFFICE")),
+            true,
+        )
+        .unwrap();
+        let script = grant_script(&command, Path::new(r"C:\Temp\grant.log"));
+        let lines: Vec<_> = script.lines().collect();
+
+        assert_eq!(lines[0], "@echo off");
+        assert_eq!(
+            lines[2],
+            r#"icacls "C:\Data\100%%" /grant "OFFICE\alice:(OI)(CI)RX" /T /C >> "C:\Temp\grant.log" 2>&1"#
+        );
+        assert!(script.contains("exit /b %errorlevel%"));
+        // execute_elevated_script looks for this marker in the log
+        assert_eq!(
+            lines.last().unwrap(),
+            &r#"echo Access granted >> "C:\Temp\grant.log""#
+        );
+    }
+}
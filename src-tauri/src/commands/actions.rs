@@ -0,0 +1,45 @@
+use crate::actions::{self, ActionContext, ActionEntry, PlanCache};
+use crate::error::AppError;
+use crate::{palette, AppState};
+
+/// List desktop actions matching a fuzzy query, best match first
+/// Disabled actions are included so the palette can show them greyed out
+#[tauri::command]
+pub async fn list_actions(
+    state: tauri::State<'_, AppState>,
+    plans: tauri::State<'_, PlanCache>,
+    query: Option<String>,
+) -> Result<Vec<ActionEntry>, AppError> {
+    let ctx = ActionContext::from_state(&state);
+    Ok(actions::list(
+        query.as_deref().unwrap_or_default(),
+        &ctx,
+        &plans.plans(),
+    ))
+}
+
+/// Execute a desktop action by id, with the arguments its entry carried, and close the palette
+#[tauri::command]
+pub async fn execute_action(
+    app: tauri::AppHandle,
+    id: String,
+    args: Option<serde_json::Value>,
+) -> Result<(), AppError> {
+    palette::hide(&app);
+    actions::execute(&app, &id, args).await?;
+    palette::notify_actions_changed(&app);
+    Ok(())
+}
+
+/// Show or hide the quick actions palette
+#[tauri::command]
+pub async fn toggle_palette(app: tauri::AppHandle) -> Result<(), AppError> {
+    palette::toggle(&app).map_err(|e| e.to_string().into())
+}
+
+/// Hide the quick actions palette
+#[tauri::command]
+pub async fn hide_palette(app: tauri::AppHandle) -> Result<(), AppError> {
+    palette::hide(&app);
+    Ok(())
+}
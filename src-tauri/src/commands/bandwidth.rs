@@ -0,0 +1,36 @@
+use crate::bandwidth::{self, BandwidthProfile, BandwidthSchedule, SwitchSource};
+use crate::error::AppError;
+use crate::settings::SettingsStore;
+
+/// The backend's bandwidth limit profiles, with the active one marked
+#[tauri::command]
+pub async fn get_bandwidth_profiles(
+    app: tauri::AppHandle,
+) -> Result<Vec<BandwidthProfile>, AppError> {
+    Ok(bandwidth::refresh(&app).await?)
+}
+
+/// Make a profile active. Holds until the bandwidth schedule's next period, if there is one.
+#[tauri::command]
+pub async fn set_active_bandwidth_profile(
+    app: tauri::AppHandle,
+    profile_id: String,
+) -> Result<(), AppError> {
+    bandwidth::activate(&app, &profile_id, SwitchSource::Manual).await?;
+    Ok(())
+}
+
+/// Set or clear (None) the schedule that switches profiles at work-hour boundaries
+#[tauri::command]
+pub async fn set_bandwidth_schedule(
+    settings: tauri::State<'_, SettingsStore>,
+    schedule: Option<BandwidthSchedule>,
+) -> Result<(), AppError> {
+    if let Some(schedule) = &schedule {
+        if schedule.work_profile.is_empty() || schedule.off_profile.is_empty() {
+            return Err("Both schedule profiles are required".into());
+        }
+    }
+    settings.update(|settings| settings.bandwidth_schedule = schedule)?;
+    Ok(())
+}
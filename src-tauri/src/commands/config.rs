@@ -0,0 +1,133 @@
+use crate::config::{self, DesktopConfig};
+use crate::error::AppError;
+use crate::{paths, relocate, AppState};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+
+/// Result of `update_settings`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigUpdate {
+    pub config: DesktopConfig,
+    /// Port changes only apply once the backend is restarted
+    pub restart_required: bool,
+}
+
+/// Where the sidecar keeps its data
+#[derive(Debug, Clone, Serialize)]
+pub struct DataDirectory {
+    /// The directory in use; None if the default couldn't be determined
+    pub path: Option<PathBuf>,
+    /// `data_dir` from `zerobyte.toml`; None means the server's default
+    pub configured: Option<PathBuf>,
+    /// ZEROBYTE_DATA_DIR in the app's environment overrides the setting
+    pub overridden_by_env: bool,
+}
+
+impl DataDirectory {
+    fn of(config: &DesktopConfig) -> Self {
+        Self {
+            path: paths::backend_data_dir(config.data_dir.as_deref()),
+            configured: config.data_dir.clone(),
+            overridden_by_env: std::env::var_os(paths::DATA_DIR_ENV).is_some(),
+        }
+    }
+}
+
+/// Write `config` to `zerobyte.toml` and make it current; returns the previous one
+fn store(state: &AppState, config: DesktopConfig) -> Result<DesktopConfig, AppError> {
+    let path = state.paths().config_dir.join(config::CONFIG_FILE);
+    config::save(&path, &config)?;
+    Ok(std::mem::replace(
+        &mut *state.config.write(),
+        config,
+    ))
+}
+
+/// The ports and timeouts from `zerobyte.toml`
+#[tauri::command]
+pub async fn get_settings(state: tauri::State<'_, AppState>) -> Result<DesktopConfig, AppError> {
+    Ok(state.config())
+}
+
+/// Validate and save `zerobyte.toml`. Timeouts and close-to-tray apply right away.
+#[tauri::command]
+pub async fn update_settings(
+    state: tauri::State<'_, AppState>,
+    config: DesktopConfig,
+) -> Result<ConfigUpdate, AppError> {
+    config.validate()?;
+    let previous = store(&state, config.clone())?;
+    Ok(ConfigUpdate {
+        restart_required: previous.needs_backend_restart(&config),
+        config,
+    })
+}
+
+/// The sidecar's data directory
+#[tauri::command]
+pub async fn get_data_directory(
+    state: tauri::State<'_, AppState>,
+) -> Result<DataDirectory, AppError> {
+    Ok(DataDirectory::of(&state.config()))
+}
+
+/// Keep the sidecar's data in `path` (the server's default when None) and restart the backend.
+/// With `move_existing` the current data is moved there first; the new directory must then be
+/// empty. The Windows Service keeps its own data directory, so while it's connected only the
+/// setting (and the data, if asked) changes.
+#[tauri::command]
+pub async fn set_data_directory(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: Option<PathBuf>,
+    move_existing: bool,
+) -> Result<DataDirectory, AppError> {
+    if std::env::var_os(paths::DATA_DIR_ENV).is_some() {
+        return Err(format!(
+            "{} is set in the environment and overrides this setting",
+            paths::DATA_DIR_ENV
+        )
+        .into());
+    }
+    let previous = state.config();
+    let updated = DesktopConfig {
+        data_dir: path.clone(),
+        ..previous.clone()
+    };
+    updated.validate()?;
+    let target = path
+        .or_else(|| crate::profile::backend_data_dir(&state.paths().data_dir))
+        .or_else(paths::default_backend_data_dir)
+        .ok_or("Could not determine the default data directory")?;
+    let current = paths::backend_data_dir(previous.data_dir.as_deref());
+    if current.as_deref() == Some(target.as_path()) {
+        store(&state, updated)?;
+        return Ok(DataDirectory::of(&state.config()));
+    }
+
+    let source = current.filter(|_| move_existing);
+    let (checked_target, checked_source) = (target.clone(), source.clone());
+    tauri::async_runtime::spawn_blocking(move || {
+        relocate::check_target(&checked_target, checked_source.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let state_ref: &AppState = &state;
+    let relocate_and_store = async move {
+        if let Some(source) = source {
+            tauri::async_runtime::spawn_blocking(move || relocate::move_contents(&source, &target))
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| format!("Failed to move the data: {}", e))?;
+        }
+        store(state_ref, updated).map(|_| ())
+    };
+    if state.using_service.load(Ordering::SeqCst) {
+        relocate_and_store.await?;
+    } else {
+        crate::restart_sidecar_with(&app, relocate_and_store).await?;
+    }
+    Ok(DataDirectory::of(&state.config()))
+}
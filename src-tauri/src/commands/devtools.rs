@@ -0,0 +1,24 @@
+use crate::devtools::{self, DevtoolsStatus};
+use crate::error::AppError;
+
+/// Report whether devtools can be opened for the calling window
+/// Used by the About view and palette to decide whether to offer the toggle
+#[tauri::command]
+pub async fn get_devtools_status(window: tauri::WebviewWindow) -> Result<DevtoolsStatus, AppError> {
+    Ok(DevtoolsStatus {
+        compiled: devtools::COMPILED,
+        permission: devtools::permission(),
+        open: devtools::is_open(&window),
+    })
+}
+
+/// Open or close devtools for the calling window (support mode only)
+#[tauri::command]
+pub async fn toggle_devtools(window: tauri::WebviewWindow) -> Result<DevtoolsStatus, AppError> {
+    let open = devtools::toggle(&window)?;
+    Ok(DevtoolsStatus {
+        compiled: devtools::COMPILED,
+        permission: devtools::permission(),
+        open,
+    })
+}
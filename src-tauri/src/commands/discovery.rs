@@ -0,0 +1,67 @@
+use crate::discovery::{self, DiscoveredBackend, DiscoveryControl};
+use crate::error::AppError;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Look for other zerobyte servers on the local network (mDNS, then a /24 probe)
+/// Only runs when the user asks for it from the remote backend settings page
+#[tauri::command]
+pub async fn discover_backends(
+    control: tauri::State<'_, DiscoveryControl>,
+    timeout_secs: u64,
+) -> Result<Vec<DiscoveredBackend>, AppError> {
+    let cancel = control
+        .begin()
+        .ok_or_else(|| "Discovery is already running".to_string())?;
+    let timeout = Duration::from_secs(timeout_secs.max(1)).min(discovery::MAX_TIMEOUT);
+    info!("Discovering backends on the local network for {:?}", timeout);
+
+    let mdns_cancel = cancel.clone();
+    let browse = tauri::async_runtime::spawn_blocking(move || {
+        discovery::browse_mdns(timeout, &mdns_cancel)
+    });
+
+    // Probe the subnet while mDNS is listening, bounded by the same timeout
+    let local = discovery::local_ipv4();
+    let probe = async {
+        let Some(local) = local else {
+            info!("No private IPv4 address, skipping subnet probe");
+            return Vec::new();
+        };
+        info!("Probing {}/24 on port {}", local, discovery::DEFAULT_PORT);
+        let candidates = discovery::subnet_candidates(local);
+        tokio::time::timeout(timeout, discovery::verify_all(candidates, cancel.clone()))
+            .await
+            .unwrap_or_default()
+    };
+    let (browsed, mut results) = tokio::join!(browse, probe);
+
+    match browsed {
+        Ok(Ok(candidates)) => {
+            info!("mDNS found {} candidate address(es)", candidates.len());
+            results.extend(discovery::verify_all(candidates, cancel.clone()).await);
+        }
+        Ok(Err(e)) => warn!("mDNS discovery failed: {}", e),
+        Err(e) => warn!("mDNS discovery task failed: {}", e),
+    }
+
+    let cancelled = cancel.load(std::sync::atomic::Ordering::SeqCst);
+    control.finish();
+    if cancelled {
+        info!("Backend discovery cancelled");
+        return Err("Discovery was cancelled".into());
+    }
+
+    let results = discovery::dedupe(results);
+    info!("Discovered {} backend(s)", results.len());
+    Ok(results)
+}
+
+/// Stop a running discovery; the pending discover_backends call returns early
+#[tauri::command]
+pub async fn cancel_discovery(
+    control: tauri::State<'_, DiscoveryControl>,
+) -> Result<(), AppError> {
+    control.cancel();
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use crate::error::AppError;
+use crate::estimate::{self, BackupEstimate, Estimator, Excluder};
+use std::path::PathBuf;
+use tracing::info;
+
+/// Estimate the size of a backup of `paths` with the plan's exclusion rules. Progress is
+/// emitted as `backup-estimate-progress`; an unchanged set of sources is answered from cache.
+#[tauri::command]
+pub async fn estimate_backup_size(
+    app: tauri::AppHandle,
+    estimator: tauri::State<'_, Estimator>,
+    paths: Vec<PathBuf>,
+    exclude_patterns: Vec<String>,
+    exclude_hidden: Option<bool>,
+) -> Result<BackupEstimate, AppError> {
+    if paths.is_empty() {
+        return Err("No source paths given".into());
+    }
+    let exclude_hidden = exclude_hidden.unwrap_or(false);
+    let cancel = estimator
+        .begin()
+        .ok_or_else(|| "An estimate is already running".to_string())?;
+
+    let excluder = Excluder::new(&exclude_patterns, exclude_hidden);
+    let key = estimate::cache_key(&paths, &exclude_patterns, exclude_hidden);
+    let units = {
+        let (paths, excluder) = (paths.clone(), excluder.clone());
+        tauri::async_runtime::spawn_blocking(move || estimate::plan_units(&paths, &excluder))
+            .await
+            .map_err(|e| {
+                estimator.finish();
+                format!("Failed to list the sources: {}", e)
+            })?
+    };
+
+    if let Some(cached) = estimator.cached(&key, &units) {
+        estimator.finish();
+        return Ok(cached);
+    }
+
+    info!("Estimating backup size of {} source(s)", paths.len());
+    let result = estimate::run(&app, units.clone(), excluder, cancel).await;
+    estimator.finish();
+    let estimate = result.ok_or_else(|| "The estimate was cancelled".to_string())?;
+    estimator.store(key, &units, &estimate);
+    Ok(estimate)
+}
+
+/// Stop a running estimate; the pending estimate_backup_size call returns early
+#[tauri::command]
+pub async fn cancel_backup_estimate(
+    estimator: tauri::State<'_, Estimator>,
+) -> Result<(), AppError> {
+    estimator.cancel();
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::elevation::{BusyPolicy, ElevationClass};
+use crate::error::AppError;
+use crate::legacy::{self, LegacyInstall, MigrationReport, SystemLocations};
+use crate::AppState;
+use std::sync::atomic::Ordering;
+use tracing::{info, warn};
+
+/// The legacy install found on this machine, if any
+#[tauri::command]
+pub async fn get_legacy_install() -> Result<Option<LegacyInstall>, AppError> {
+    tauri::async_runtime::spawn_blocking(|| legacy::detect(&SystemLocations))
+        .await
+        .map_err(|e| e.to_string().into())
+}
+
+/// The last migration report, if a migration ran
+#[tauri::command]
+pub async fn get_legacy_migration_report(
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<MigrationReport>, AppError> {
+    Ok(legacy::load_report(&state.paths().data_dir.join(legacy::REPORT_FILE)))
+}
+
+/// Copy the legacy install's data into the current backend. With `dry_run` nothing is
+/// changed and the report lists what would be copied. The backend is stopped for the copy and
+/// started again afterwards; legacy services are disabled (with administrator approval) only
+/// when `disable_legacy_service` is set.
+#[tauri::command]
+pub async fn migrate_from_legacy(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    dry_run: bool,
+    disable_legacy_service: bool,
+) -> Result<MigrationReport, AppError> {
+    let install = tauri::async_runtime::spawn_blocking(|| legacy::detect(&SystemLocations))
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "No legacy install found".to_string())?;
+    let target = crate::paths::backend_data_dir(state.config().data_dir.as_deref())
+        .ok_or_else(|| "Could not determine the backend data directory".to_string())?;
+    let items = legacy::plan(&install.root, &target)
+        .map_err(|e| format!("Failed to read {}: {}", install.root.display(), e))?;
+
+    let mut report = MigrationReport::new(install, target, items);
+    if dry_run {
+        return Ok(report);
+    }
+
+    // The service owns its own data directory; migrating into ours would go unnoticed
+    if state.using_service.load(Ordering::SeqCst) {
+        return Err("Stop the Windows Service before migrating a legacy install"
+            .to_string()
+            .into());
+    }
+
+    info!(
+        "Migrating {} files from {}",
+        report.items.len(),
+        report.legacy.root.display()
+    );
+    crate::stop_sidecar(&state)
+        .await
+        .map_err(|e| format!("Failed to stop the backend: {}", e))?;
+    let items = report.items.clone();
+    let copied = tauri::async_runtime::spawn_blocking(move || legacy::apply(&items))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to copy legacy data: {}", e));
+    // Bring the backend back whether or not the copy worked
+    match crate::start_sidecar(&app, &state).await {
+        Ok(port) => crate::renavigate_main_window(&app, port),
+        Err(e) => warn!("Failed to restart the backend after migration: {}", e),
+    }
+    copied?;
+
+    report.dry_run = false;
+    report.completed_at = Some(legacy::now());
+    if disable_legacy_service && !report.legacy.services.is_empty() {
+        let services = report.legacy.services.clone();
+        match state
+            .elevation
+            .run(
+                ElevationClass::Service,
+                "migrate_from_legacy",
+                BusyPolicy::Reject,
+                legacy::disable_services(services.clone()),
+            )
+            .await
+        {
+            Ok(()) => report.disabled_services = services,
+            Err(e) => warn!("Failed to disable legacy services: {}", e),
+        }
+    }
+
+    legacy::save_report(&state.paths().data_dir.join(legacy::REPORT_FILE), &report)?;
+    Ok(report)
+}
+
+/// Delete what `migrate_from_legacy` copied from the legacy install, and its services
+/// (with administrator approval). Files that weren't migrated, such as local repositories,
+/// are left alone. The user types the install folder's name in the UI and confirms again in a
+/// native dialog.
+#[tauri::command]
+pub async fn cleanup_legacy_install(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    typed_confirmation: Option<String>,
+    skip_confirmation: Option<bool>,
+) -> Result<MigrationReport, AppError> {
+    let report_path = state.paths().data_dir.join(legacy::REPORT_FILE);
+    let mut report = legacy::load_report(&report_path)
+        .filter(|report| !report.dry_run)
+        .ok_or_else(|| "Migrate the legacy install before cleaning it up".to_string())?;
+
+    let prompt = crate::dialogs::Prompt::CleanupLegacyInstall {
+        root: report.legacy.root.display().to_string(),
+    };
+    crate::dialogs::confirm(
+        &app,
+        prompt,
+        skip_confirmation.unwrap_or(false),
+        typed_confirmation.as_deref(),
+    )
+    .await?;
+
+    if !report.legacy.services.is_empty() {
+        state
+            .elevation
+            .run(
+                ElevationClass::Service,
+                "cleanup_legacy_install",
+                BusyPolicy::Reject,
+                legacy::delete_services(report.legacy.services.clone()),
+            )
+            .await?;
+    }
+
+    let items = report.items.clone();
+    tauri::async_runtime::spawn_blocking(move || legacy::remove_sources(&items))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to remove legacy files: {}", e))?;
+
+    info!("Removed legacy install at {}", report.legacy.root.display());
+    report.cleaned_up = true;
+    legacy::save_report(&report_path, &report)?;
+    Ok(report)
+}
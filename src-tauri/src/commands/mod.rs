@@ -1,47 +1,607 @@
+pub mod access;
+pub mod actions;
+pub mod bandwidth;
+pub mod config;
+pub mod devtools;
+pub mod discovery;
+pub mod estimate;
+pub mod legacy;
+pub mod notifications;
+pub mod outbox;
+pub mod passphrase;
+pub mod redaction;
+pub mod registrations;
+pub mod restore;
+pub mod retention;
+pub mod schedule;
+pub mod scratch;
 pub mod service;
+pub mod watch;
 
+use crate::activity::ActivityMode;
+use crate::banner::BannerFacts;
+use crate::desktop::DesktopCapabilities;
+use crate::error::AppError;
+use crate::events::EventReplay;
+use crate::ownership::{BackendMode, ConnectionMode};
+use crate::paths::Paths;
+use crate::priority::BackendPriority;
+use crate::sandbox::Confinement;
+use crate::settings::SettingsStore;
+use crate::startup::StageRecord;
 use crate::AppState;
 use serde::Serialize;
 use std::sync::atomic::Ordering;
 use tauri::Manager;
 
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendUrl {
+    pub url: String,
+    /// Shared secret to send in `header`; None when the backend wasn't given one
+    pub token: Option<String>,
+    pub header: &'static str,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct BackendInfo {
     pub url: String,
     pub port: u16,
     pub using_service: bool,
+    /// Whether the service requires the desktop to pair before it can be used
+    pub needs_pairing: bool,
+    /// How far the backend clock is ahead of this machine (negative if behind)
+    pub clock_skew_ms: Option<i64>,
+    /// API features the backend supports
+    pub capabilities: Vec<&'static str>,
+    /// What the sidecar reported in its startup banner
+    pub banner: BannerFacts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppInfo {
+    pub name: String,
+    pub version: String,
+    pub identifier: String,
+    pub paths: Paths,
+    pub background_activity: ActivityMode,
 }
 
 /// Show the main window and bring it to focus
 /// Used when app starts minimized but user needs to log in
 #[tauri::command]
-pub async fn show_window(app: tauri::AppHandle) -> Result<(), String> {
+pub async fn show_window(app: tauri::AppHandle) -> Result<(), AppError> {
     if let Some(window) = app.get_webview_window("main") {
         window.show().map_err(|e| e.to_string())?;
         window.set_focus().map_err(|e| e.to_string())?;
         Ok(())
     } else {
-        Err("Main window not found".to_string())
+        Err("Main window not found".into())
     }
 }
 
-/// Get the URL of the backend server
+/// Get the URL of the backend server and the shared secret to send it
 /// Returns the service URL if connected to service, otherwise the sidecar URL
 #[tauri::command]
-pub async fn get_backend_url(state: tauri::State<'_, AppState>) -> Result<String, String> {
+pub async fn get_backend_url(state: tauri::State<'_, AppState>) -> Result<BackendUrl, AppError> {
     let port = state.backend_port.load(Ordering::SeqCst);
-    Ok(format!("http://localhost:{}", port))
+    Ok(BackendUrl {
+        url: format!("http://localhost:{}", port),
+        token: state.http.backend_token(),
+        header: crate::backend_token::TOKEN_HEADER,
+    })
+}
+
+/// Restart the sidecar (e.g. after changing backend settings) and return its new port.
+/// The main window follows it once it's back.
+#[tauri::command]
+pub async fn restart_backend(app: tauri::AppHandle) -> Result<u16, AppError> {
+    crate::restart_sidecar(&app).await
+}
+
+/// Kill a hung sidecar without waiting for a graceful shutdown and start it again. Returns
+/// its new port; refused while the Windows Service provides the backend.
+#[tauri::command]
+pub async fn force_restart_backend(app: tauri::AppHandle) -> Result<u16, AppError> {
+    crate::force_restart_sidecar(&app).await
+}
+
+/// Pair with a service backend that requires authentication, using an account's
+/// credentials or an API token. The resulting token is kept in the OS keyring.
+#[tauri::command]
+pub async fn pair_with_backend(
+    app: tauri::AppHandle,
+    credentials: crate::pairing::Credentials,
+) -> Result<(), AppError> {
+    let port = app.state::<AppState>().backend_port.load(Ordering::SeqCst);
+    crate::pairing::pair(&app, port, credentials).await?;
+    crate::refresh_capabilities(&app).await;
+    Ok(())
+}
+
+/// The last lines the sidecar printed, oldest first; `backend-log` events carry the rest
+#[tauri::command]
+pub async fn get_recent_backend_logs(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<crate::backend_log::BackendLogLine>, AppError> {
+    Ok(state.backend_log.recent())
+}
+
+/// The last `limit` lines the sidecar printed (all that are kept if unset), oldest first and
+/// tagged with the generation of the sidecar that printed them. For support requests.
+#[tauri::command]
+pub async fn get_backend_logs(
+    state: tauri::State<'_, AppState>,
+    limit: Option<usize>,
+) -> Result<Vec<crate::backend_log::BackendLogLine>, AppError> {
+    let limit = limit.unwrap_or(crate::backend_log::RECENT_LINES);
+    Ok(state.backend_log.tail(limit))
+}
+
+/// Saved reports of sidecar crashes, newest first
+#[tauri::command]
+pub async fn list_crash_reports(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<crate::crashes::CrashSummary>, AppError> {
+    Ok(crate::crashes::list(&crate::crashes::dir(
+        &state.paths().data_dir,
+    )))
+}
+
+/// One crash report with the output the sidecar printed before it exited
+#[tauri::command]
+pub async fn read_crash_report(
+    state: tauri::State<'_, AppState>,
+    id: String,
+) -> Result<crate::crashes::CrashReport, AppError> {
+    Ok(crate::crashes::read(
+        &crate::crashes::dir(&state.paths().data_dir),
+        &id,
+    )?)
+}
+
+/// Open the folder with the backend's log files in the OS file manager
+#[tauri::command]
+pub async fn open_backend_log_folder(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    let dir = state.paths().data_dir.join(crate::backend_log::LOGS_DIR);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    open_folder(&dir)?;
+    Ok(())
+}
+
+/// Open `dir` in the system file manager
+pub(crate) fn open_folder(dir: &std::path::Path) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+
+    let mut child = std::process::Command::new(program)
+        .arg(dir)
+        .spawn()
+        .map_err(|e| format!("Failed to open {}: {}", dir.display(), e))?;
+    // Reap the launcher once it hands over to the file manager
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// The OS accessibility preferences the bundled pages follow
+#[tauri::command]
+pub async fn get_accessibility_preferences(
+    preferences: tauri::State<'_, crate::accessibility::AccessibilityState>,
+) -> Result<crate::accessibility::AccessibilityPreferences, AppError> {
+    Ok(preferences.current())
+}
+
+/// Every supervised background task, with its status and the last panic if it had one
+#[tauri::command]
+pub async fn get_task_health(
+    health: tauri::State<'_, crate::supervisor::TaskHealth>,
+) -> Result<Vec<crate::supervisor::TaskRecord>, AppError> {
+    Ok(health.snapshot())
+}
+
+/// Check the discovery, outbox and watcher files; with `repair`, move damaged ones aside
+/// and write good ones in their place
+#[tauri::command]
+pub async fn verify_runtime_state(
+    app: tauri::AppHandle,
+    repair: bool,
+) -> Result<Vec<crate::runtime_state::ArtifactReport>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || crate::runtime_state::verify(&app, repair))
+        .await
+        .map_err(|e| format!("Runtime state check failed: {}", e).into())
 }
 
 /// Get detailed backend connection info
 /// Returns port, URL, and whether connected to service or sidecar
 #[tauri::command]
-pub async fn get_backend_info(state: tauri::State<'_, AppState>) -> Result<BackendInfo, String> {
+pub async fn get_backend_info(
+    state: tauri::State<'_, AppState>,
+) -> Result<BackendInfo, AppError> {
     let port = state.backend_port.load(Ordering::SeqCst);
     let using_service = state.using_service.load(Ordering::SeqCst);
+    let clock_skew_ms = state.clock_skew.lock().skew_ms();
     Ok(BackendInfo {
         url: format!("http://localhost:{}", port),
         port,
         using_service,
+        needs_pairing: state.needs_pairing.load(Ordering::SeqCst),
+        clock_skew_ms,
+        capabilities: state.capabilities().names(),
+        banner: state.banner.lock().clone(),
+    })
+}
+
+/// Get the app version and the directories it uses
+/// Shown in the About/diagnostics view and useful in support requests
+#[tauri::command]
+pub async fn get_app_info(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<AppInfo, AppError> {
+    let package = app.package_info();
+    Ok(AppInfo {
+        name: package.name.clone(),
+        version: package.version.to_string(),
+        identifier: app.config().identifier.clone(),
+        paths: state.paths().clone(),
+        background_activity: state.activity.mode(),
     })
 }
+
+/// Get whether this instance owns the backend or is viewing one owned by another session
+/// The UI uses this on load, then follows `connection-mode` events
+#[tauri::command]
+pub async fn get_connection_mode(
+    state: tauri::State<'_, AppState>,
+) -> Result<ConnectionMode, AppError> {
+    Ok(state.connection_mode.lock().clone())
+}
+
+/// Set how much background work the app does: "normal", "reduced" or "suspended"
+/// Persisted, and mirrored by the tray's battery saver toggle
+#[tauri::command]
+pub async fn set_background_activity(
+    app: tauri::AppHandle,
+    mode: ActivityMode,
+) -> Result<(), AppError> {
+    crate::set_background_activity(&app, mode)?;
+    Ok(())
+}
+
+/// When each startup stage ran, for diagnosing slow starts
+#[tauri::command]
+pub async fn get_startup_stages(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<StageRecord>, AppError> {
+    Ok(state.startup.records())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendProcessInfo {
+    /// Sidecar process id (None when using the service or another instance's backend)
+    pub pid: Option<u32>,
+    /// Generation of the most recently spawned sidecar (0 if none was spawned)
+    pub generation: u64,
+    pub using_service: bool,
+    /// How the sidecar is confined; empty when it runs unconfined
+    pub confinement: Confinement,
+}
+
+/// Get the sidecar's process id, generation and the confinement it was launched with
+#[tauri::command]
+pub async fn get_backend_process_info(
+    state: tauri::State<'_, AppState>,
+) -> Result<BackendProcessInfo, AppError> {
+    let pid = state
+        .sidecar_handle
+        .lock()
+        .await
+        .as_ref()
+        .map(|process| process.child.pid());
+    Ok(BackendProcessInfo {
+        pid,
+        generation: state.sidecar_generation.load(Ordering::SeqCst),
+        using_service: state.using_service.load(Ordering::SeqCst),
+        confinement: state.confinement.lock().clone(),
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendStatus {
+    pub mode: BackendMode,
+    pub port: u16,
+    /// Sidecar process id; None for the service and servers this instance didn't start
+    pub pid: Option<u32>,
+    /// How long the sidecar has been running; None when this instance didn't start it
+    pub uptime_secs: Option<u64>,
+    /// Whether the backend answered a healthcheck just now
+    pub healthy: bool,
+    /// Priority the sidecar runs at; None when `pid` is
+    pub priority: Option<BackendPriority>,
+}
+
+/// Whether the UI talks to our sidecar, the service or someone else's server, on which
+/// port, since when, and whether it answers right now. For the Settings page's backend panel.
+#[tauri::command]
+pub async fn get_backend_status(
+    state: tauri::State<'_, AppState>,
+) -> Result<BackendStatus, AppError> {
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let using_service = state.using_service.load(Ordering::SeqCst);
+    let process = state
+        .sidecar_handle
+        .lock()
+        .await
+        .as_ref()
+        .map(|process| (process.child.pid(), process.started.elapsed()));
+    let healthy = crate::monitor::probe(&state, port).await.is_some();
+    let pid = process.filter(|_| !using_service).map(|(pid, _)| pid);
+    let priority = match pid {
+        Some(pid) => tauri::async_runtime::spawn_blocking(move || crate::priority::current(pid))
+            .await
+            .ok()
+            .flatten(),
+        None => None,
+    };
+    Ok(BackendStatus {
+        mode: crate::backend_mode(&state).await,
+        port,
+        pid,
+        uptime_secs: process
+            .filter(|_| !using_service)
+            .map(|(_, uptime)| uptime.as_secs()),
+        healthy,
+        priority,
+    })
+}
+
+/// Whether the UI is served by our sidecar, the Windows Service or a server this instance
+/// doesn't control (a dev server or another session's sidecar). `backend-mode-changed`
+/// carries the same value whenever it changes.
+#[tauri::command]
+pub async fn get_backend_mode(state: tauri::State<'_, AppState>) -> Result<BackendMode, AppError> {
+    Ok(crate::backend_mode(&state).await)
+}
+
+/// The app's version, the backend version it bundles and the one the connected backend
+/// reports
+#[tauri::command]
+pub async fn get_versions(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<crate::versions::Versions, AppError> {
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let backend = crate::versions::fetch(&state.http, port)
+        .await
+        .or_else(|| state.banner.lock().version.clone());
+    Ok(crate::versions::Versions {
+        app: app.package_info().version.to_string(),
+        bundled_backend: crate::versions::bundled_backend(&app),
+        backend,
+    })
+}
+
+/// The command-line options the app was started with, e.g. so the UI knows it started
+/// minimized
+#[tauri::command]
+pub async fn get_launch_options() -> Result<crate::args::LaunchOptions, AppError> {
+    Ok(crate::args::current().clone())
+}
+
+/// CPU, memory and open handles of the backend process: our sidecar (or the one adopted from
+/// an earlier run), or whatever listens on the service port in service mode. Takes about
+/// half a second to measure CPU use.
+#[tauri::command]
+pub async fn get_backend_resource_usage(
+    state: tauri::State<'_, AppState>,
+) -> Result<crate::usage::ResourceUsage, AppError> {
+    let pid = if state.using_service.load(Ordering::SeqCst) {
+        let port = state.config().service_port;
+        let holders = tauri::async_runtime::spawn_blocking(move || {
+            crate::proctree::port_holders(port, std::time::Duration::ZERO)
+        })
+        .await
+        .map_err(|e| AppError::Message(e.to_string()))?;
+        match holders.as_slice() {
+            [pid] => *pid,
+            [] => {
+                return Err(AppError::BackendProcessUnknown(format!(
+                    "nothing listens on the service port {}",
+                    port
+                )))
+            }
+            _ => {
+                return Err(AppError::BackendProcessUnknown(format!(
+                    "several processes listen on the service port {}",
+                    port
+                )))
+            }
+        }
+    } else {
+        sidecar_pid(&state).await.ok_or_else(|| {
+            AppError::BackendProcessUnknown(
+                "this instance didn't start the backend it's connected to".to_string(),
+            )
+        })?
+    };
+    tauri::async_runtime::spawn_blocking(move || crate::usage::sample(pid))
+        .await
+        .map_err(|e| AppError::Message(e.to_string()))?
+        .ok_or_else(|| AppError::BackendProcessUnknown(format!("process {} can't be read", pid)))
+}
+
+/// PID of our sidecar, or of the one adopted from an earlier run
+async fn sidecar_pid(state: &AppState) -> Option<u32> {
+    let spawned = state
+        .sidecar_handle
+        .lock()
+        .await
+        .as_ref()
+        .map(|process| process.child.pid());
+    spawned.or_else(|| {
+        state
+            .adopted_sidecar
+            .lock()
+            .as_ref()
+            .map(|adopted| adopted.tree.pid())
+    })
+}
+
+/// Turn sidecar confinement on or off (Linux only), with the repository hosts it may reach
+/// Takes effect the next time the sidecar starts
+#[tauri::command]
+pub async fn set_backend_sandbox(
+    settings: tauri::State<'_, SettingsStore>,
+    enabled: bool,
+    allowed_hosts: Vec<String>,
+) -> Result<(), AppError> {
+    settings.update(|settings| {
+        settings.sandbox = enabled;
+        settings.sandbox_allowed_hosts = allowed_hosts;
+    })?;
+    Ok(())
+}
+
+/// Run the sidecar at `level` (`normal` or `low`), now and whenever it starts. Returns the
+/// priority it actually runs at: on Linux and macOS going back to normal only takes effect
+/// from the next start.
+#[tauri::command]
+pub async fn set_backend_priority(
+    state: tauri::State<'_, AppState>,
+    settings: tauri::State<'_, SettingsStore>,
+    level: BackendPriority,
+) -> Result<BackendPriority, AppError> {
+    if state.using_service.load(Ordering::SeqCst) {
+        return Err(AppError::NeedsElevation(
+            "Changing the backend priority".to_string(),
+        ));
+    }
+    settings.update(|settings| settings.backend_priority = level)?;
+    let Some(pid) = sidecar_pid(&state).await else {
+        return Ok(level);
+    };
+    let current = tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = crate::priority::apply(pid, level) {
+            tracing::warn!("Failed to change the backend's priority: {}", e);
+        }
+        crate::priority::current(pid)
+    })
+    .await
+    .map_err(|e| AppError::Message(e.to_string()))?;
+    Ok(current.unwrap_or(level))
+}
+
+/// The desktop's log filter directive
+#[tauri::command]
+pub async fn get_log_level() -> Result<String, AppError> {
+    Ok(crate::logging::current())
+}
+
+/// The desktop's current log file, for support requests
+#[tauri::command]
+pub async fn get_desktop_log_path() -> Result<std::path::PathBuf, AppError> {
+    crate::logging::file_path().ok_or_else(|| "The desktop isn't writing a log file".into())
+}
+
+/// Change the desktop's log filter (a level such as `debug`, or directives such as
+/// `zerobyte=debug,tauri=info`) now and for later starts. Levels above info also make the
+/// sidecar verbose from its next restart.
+#[tauri::command]
+pub async fn set_log_level(
+    settings: tauri::State<'_, SettingsStore>,
+    level: String,
+) -> Result<(), AppError> {
+    crate::logging::parse(&level)?;
+    settings.update(|settings| settings.log_level = Some(level.trim().to_string()))?;
+    crate::logging::set(&level)?;
+    tracing::info!("Log level set to {}", level.trim());
+    Ok(())
+}
+
+/// Retained lifecycle events for `names` (all of them when empty), in emission order
+/// Call after subscribing to `bus-event`, and drop live events up to the returned `last_seq`
+#[tauri::command]
+pub async fn sync_events(
+    state: tauri::State<'_, AppState>,
+    names: Vec<String>,
+) -> Result<EventReplay, AppError> {
+    Ok(state.events.replay(&names))
+}
+
+/// Which optional desktop features this shell offers, and why the others are unavailable
+#[tauri::command]
+pub async fn get_desktop_capabilities(
+    state: tauri::State<'_, AppState>,
+) -> Result<DesktopCapabilities, AppError> {
+    let mode = state.connection_mode.lock().clone();
+    Ok(crate::desktop::capabilities(&mode))
+}
+
+/// Stop waiting for running backups; the operation that started the drain is abandoned
+/// Returns whether a drain was in progress
+#[tauri::command]
+pub async fn cancel_drain(state: tauri::State<'_, AppState>) -> Result<bool, AppError> {
+    Ok(state.drain.cancel())
+}
+
+/// Inject latency, request failures and full disks for QA (debug and fault-injection builds)
+/// Pass null to clear; the profile never outlives the process
+#[tauri::command]
+pub async fn set_fault_profile(
+    profile: Option<crate::faults::FaultProfile>,
+) -> Result<(), AppError> {
+    crate::faults::set_profile(profile)?;
+    Ok(())
+}
+
+/// Live facts about the app, backend and machine for the System page, from cached state only
+#[tauri::command]
+pub async fn get_system_report(
+    app: tauri::AppHandle,
+) -> Result<crate::report::SystemReport, AppError> {
+    Ok(crate::report::build(&app))
+}
+
+/// Check whether backups are actually protected, as a scored checklist with a quick action
+/// for each problem; the result also goes into the system report
+#[tauri::command]
+pub async fn run_protection_audit(
+    app: tauri::AppHandle,
+) -> Result<crate::protection::ProtectionAudit, AppError> {
+    Ok(crate::protection::run(&app).await)
+}
+
+/// Called every few seconds by the script injected into each page; silence means the
+/// renderer died
+#[tauri::command]
+pub async fn webview_heartbeat(
+    window: tauri::WebviewWindow,
+    state: tauri::State<'_, AppState>,
+    url: String,
+) -> Result<(), AppError> {
+    state
+        .heartbeats
+        .beat(window.label(), &url, std::time::Instant::now());
+    Ok(())
+}
+
+/// Backend restarts, crashes and watchdog incidents in `range` (everything kept if omitted)
+#[tauri::command]
+pub async fn get_health_history(
+    health: tauri::State<'_, crate::health::HealthLog>,
+    range: Option<crate::health::HistoryRange>,
+) -> Result<Vec<crate::health::HealthEntry>, AppError> {
+    Ok(health.snapshot().range(range.unwrap_or_default()))
+}
+
+/// The values the metrics endpoint would serve, whether or not it's enabled
+#[tauri::command]
+pub async fn get_metrics_snapshot(
+    app: tauri::AppHandle,
+) -> Result<Vec<crate::metrics::Metric>, AppError> {
+    Ok(crate::metrics::collect(&app).await)
+}
@@ -0,0 +1,36 @@
+use crate::error::AppError;
+use crate::notifier::{self, PlanMute};
+use crate::settings::SettingsStore;
+use std::time::Duration;
+
+/// Silence notifications for one backup plan, for `duration_secs` or until unmuted
+/// Returns when the mute ends (seconds since the Unix epoch)
+#[tauri::command]
+pub async fn mute_plan_notifications(
+    settings: tauri::State<'_, SettingsStore>,
+    plan_id: String,
+    duration_secs: Option<u64>,
+) -> Result<Option<u64>, AppError> {
+    Ok(notifier::mute_plan(
+        &settings,
+        &plan_id,
+        duration_secs.map(Duration::from_secs),
+    )?)
+}
+
+/// Let a muted plan's notifications through again
+#[tauri::command]
+pub async fn unmute_plan_notifications(
+    settings: tauri::State<'_, SettingsStore>,
+    plan_id: String,
+) -> Result<bool, AppError> {
+    Ok(notifier::unmute_plan(&settings, &plan_id)?)
+}
+
+/// Plans whose notifications are currently muted
+#[tauri::command]
+pub async fn list_notification_mutes(
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<Vec<PlanMute>, AppError> {
+    Ok(notifier::list_mutes(&settings)?)
+}
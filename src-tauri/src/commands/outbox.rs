@@ -0,0 +1,45 @@
+use crate::capabilities::{self, Availability};
+use crate::error::AppError;
+use crate::outbox::{self, BackendAction, Outbox, PendingAction};
+use crate::AppState;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// Queue an idempotent backend action to be applied as soon as the backend is healthy
+/// A newer action replaces pending ones it supersedes (e.g. resume replaces pause)
+#[tauri::command]
+pub async fn queue_backend_action(
+    state: tauri::State<'_, AppState>,
+    outbox: tauri::State<'_, Outbox>,
+    action: BackendAction,
+    ttl_secs: Option<u64>,
+) -> Result<PendingAction, AppError> {
+    // Capabilities are only known while the backend is up; otherwise queue and decide later
+    if state.backend_ready.load(Ordering::SeqCst) {
+        let feature = action.feature();
+        if capabilities::availability(feature, state.capabilities()) != Availability::Available {
+            return Err(AppError::UnsupportedByBackend(format!("{:?}", feature)));
+        }
+    }
+
+    let ttl = ttl_secs.map(Duration::from_secs).unwrap_or(outbox::DEFAULT_TTL);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let (entry, superseded) = outbox.enqueue(action, ttl, now)?;
+    for dropped in &superseded {
+        info!("Backend action {} superseded by {}", dropped.id, entry.id);
+    }
+    Ok(entry)
+}
+
+/// List backend actions waiting for the backend, in the order they'll be applied
+#[tauri::command]
+pub async fn get_pending_backend_actions(
+    outbox: tauri::State<'_, Outbox>,
+) -> Result<Vec<PendingAction>, AppError> {
+    Ok(outbox.pending())
+}
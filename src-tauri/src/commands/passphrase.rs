@@ -0,0 +1,352 @@
+use crate::capabilities::{self, Availability, Feature};
+use crate::error::AppError;
+use crate::http::{self, HttpPolicy};
+use crate::passphrase::{self, SecretStore};
+use crate::settings::SettingsStore;
+use crate::AppState;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnlockOutcome {
+    /// The passphrase came from the OS keyring rather than the dialog
+    pub from_keyring: bool,
+    /// The passphrase was saved to the OS keyring for next time
+    pub stored: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnlockResult {
+    Unlocked,
+    /// The backend refused the passphrase
+    Rejected,
+}
+
+/// Send a passphrase to the backend's unlock endpoint
+async fn unlock(
+    state: &AppState,
+    repo_id: &str,
+    passphrase: &Zeroizing<String>,
+) -> Result<UnlockResult, AppError> {
+    let body = passphrase::unlock_body(passphrase)?;
+    let url = unlock_url(state.backend_port.load(Ordering::SeqCst), repo_id)?;
+
+    let response = state
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| {
+            client
+                .post(url.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+        })
+        .await
+        .map_err(|e| AppError::Message(format!("Failed to reach the backend: {}", e)))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(UnlockResult::Unlocked)
+    } else if matches!(status.as_u16(), 400 | 401 | 403 | 422) {
+        Ok(UnlockResult::Rejected)
+    } else {
+        Err(AppError::Message(format!("Backend failed to unlock repository: {}", status)))
+    }
+}
+
+/// The unlock endpoint of repository `repo_id`, escaped as a single path segment. Ids that
+/// URL parsing would resolve away (empty, `.` and `..`) are refused.
+fn unlock_url(port: u16, repo_id: &str) -> Result<reqwest::Url, AppError> {
+    if matches!(repo_id, "" | "." | "..") {
+        return Err(AppError::Message(format!("Invalid repository id {:?}", repo_id)));
+    }
+    let mut url = reqwest::Url::parse(&http::local_url(port, "/"))
+        .map_err(|e| AppError::Message(format!("Invalid backend URL: {}", e)))?;
+    url.path_segments_mut()
+        .map_err(|()| AppError::Message("Invalid backend URL".to_string()))?
+        .pop_if_empty()
+        .extend(["api", "v1", "repositories", repo_id, "unlock"]);
+    Ok(url)
+}
+
+/// Unlock `repo_id` with its stored passphrase when it's remembered, or else with the one
+/// `prompt` asks for, which is then stored if it's remembered. `unlock` sends a passphrase
+/// to the backend.
+async fn unlock_repository<S, P, U, F>(
+    store: Arc<S>,
+    repo_id: &str,
+    remember: bool,
+    prompt: P,
+    unlock: U,
+) -> Result<UnlockOutcome, AppError>
+where
+    S: SecretStore,
+    P: FnOnce() -> Result<Option<Zeroizing<String>>, String> + Send + 'static,
+    U: Fn(Zeroizing<String>) -> F,
+    F: Future<Output = Result<UnlockResult, AppError>>,
+{
+    if remember {
+        let (lookup, key) = (store.clone(), repo_id.to_string());
+        let stored = tauri::async_runtime::spawn_blocking(move || lookup.load(&key))
+            .await
+            .map_err(|e| AppError::Message(e.to_string()))?;
+        match stored {
+            Ok(Some(stored)) => match unlock(stored).await? {
+                UnlockResult::Unlocked => {
+                    info!("Unlocked repository {} with the stored passphrase", repo_id);
+                    return Ok(UnlockOutcome {
+                        from_keyring: true,
+                        stored: false,
+                    });
+                }
+                UnlockResult::Rejected => {
+                    warn!("Stored passphrase for repository {} was rejected", repo_id);
+                    let _ = store.forget(repo_id);
+                }
+            },
+            Ok(None) => {}
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    let entered = tauri::async_runtime::spawn_blocking(prompt)
+        .await
+        .map_err(|e| AppError::Message(e.to_string()))??
+        .ok_or_else(|| AppError::Message("Cancelled by user".to_string()))?;
+
+    match unlock(entered.clone()).await? {
+        UnlockResult::Unlocked => {}
+        UnlockResult::Rejected => {
+            return Err(AppError::Message("Incorrect passphrase".to_string()));
+        }
+    }
+    info!("Unlocked repository {}", repo_id);
+
+    let mut stored = false;
+    if remember {
+        let key = repo_id.to_string();
+        let result =
+            tauri::async_runtime::spawn_blocking(move || store.store(&key, &entered))
+                .await
+                .map_err(|e| AppError::Message(e.to_string()))?;
+        match result {
+            Ok(()) => stored = true,
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    Ok(UnlockOutcome {
+        from_keyring: false,
+        stored,
+    })
+}
+
+/// Ask for a repository passphrase in a native masked dialog and hand it to the backend
+/// The passphrase never reaches the webview; a remembered one is tried from the keyring first
+#[tauri::command]
+pub async fn prompt_repository_passphrase(
+    state: tauri::State<'_, AppState>,
+    settings: tauri::State<'_, SettingsStore>,
+    repo_id: String,
+    repo_name: Option<String>,
+    reason: Option<String>,
+) -> Result<UnlockOutcome, AppError> {
+    if capabilities::availability(Feature::RepositoryUnlock, state.capabilities())
+        != Availability::Available
+    {
+        return Err(AppError::UnsupportedByBackend("repository unlock".to_string()));
+    }
+
+    let remember = settings.get().remembered_passphrases.contains(&repo_id);
+    let name = repo_name.unwrap_or_else(|| repo_id.clone());
+    let reason = reason.unwrap_or_else(|| "The backend needs it to continue.".to_string());
+    let state = &*state;
+    let repo = repo_id.as_str();
+    unlock_repository(
+        Arc::new(passphrase::Keyring),
+        &repo_id,
+        remember,
+        move || passphrase::prompt(&name, &reason),
+        |passphrase| async move { unlock(state, repo, &passphrase).await },
+    )
+    .await
+}
+
+/// Choose whether a repository's passphrase is kept in the OS keyring
+/// Turning it off also removes the stored passphrase
+#[tauri::command]
+pub async fn set_remember_passphrase(
+    settings: tauri::State<'_, SettingsStore>,
+    repo_id: String,
+    remember: bool,
+) -> Result<(), AppError> {
+    settings.update(|settings| {
+        settings.remembered_passphrases.retain(|id| id != &repo_id);
+        if remember {
+            settings.remembered_passphrases.push(repo_id.clone());
+        }
+    })?;
+
+    if !remember {
+        tauri::async_runtime::spawn_blocking(move || passphrase::Keyring.forget(&repo_id))
+            .await
+            .map_err(|e| e.to_string())??;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    /// A keyring in memory, optionally refusing to save
+    #[derive(Default)]
+    struct MemoryStore {
+        secrets: Mutex<HashMap<String, String>>,
+        refuse_store: bool,
+    }
+
+    impl SecretStore for MemoryStore {
+        fn load(&self, repo_id: &str) -> Result<Option<Zeroizing<String>>, String> {
+            Ok(self
+                .secrets
+                .lock()
+                .get(repo_id)
+                .cloned()
+                .map(Zeroizing::new))
+        }
+
+        fn store(&self, repo_id: &str, passphrase: &str) -> Result<(), String> {
+            if self.refuse_store {
+                return Err("keyring locked".to_string());
+            }
+            self.secrets
+                .lock()
+                .insert(repo_id.to_string(), passphrase.to_string());
+            Ok(())
+        }
+
+        fn forget(&self, repo_id: &str) -> Result<(), String> {
+            self.secrets.lock().remove(repo_id);
+            Ok(())
+        }
+    }
+
+    fn store_with(repo_id: &str, passphrase: &str) -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::default());
+        store.store(repo_id, passphrase).unwrap();
+        store
+    }
+
+    /// Run the flow against a backend that only accepts "right", with the dialog answering
+    /// `entered`. Returns the outcome and the passphrases the backend saw.
+    async fn run(
+        store: Arc<MemoryStore>,
+        remember: bool,
+        entered: Option<&'static str>,
+    ) -> (Result<UnlockOutcome, AppError>, Vec<String>) {
+        let attempts = Mutex::new(Vec::new());
+        let outcome = unlock_repository(
+            store,
+            "repo",
+            remember,
+            move || Ok(entered.map(|p| Zeroizing::new(p.to_string()))),
+            |passphrase| {
+                attempts.lock().push(passphrase.to_string());
+                async move {
+                    Ok(if passphrase.as_str() == "right" {
+                        UnlockResult::Unlocked
+                    } else {
+                        UnlockResult::Rejected
+                    })
+                }
+            },
+        )
+        .await;
+        (outcome, attempts.into_inner())
+    }
+
+    #[test]
+    fn repository_ids_stay_one_path_segment() {
+        let cases = [
+            ("abc", "/api/v1/repositories/abc/unlock"),
+            ("a/b", "/api/v1/repositories/a%2Fb/unlock"),
+            (
+                "../../shutdown",
+                "/api/v1/repositories/..%2F..%2Fshutdown/unlock",
+            ),
+            ("x?y#z", "/api/v1/repositories/x%3Fy%23z/unlock"),
+            ("with space", "/api/v1/repositories/with%20space/unlock"),
+        ];
+        for (repo_id, path) in cases {
+            let url = unlock_url(4096, repo_id).unwrap();
+            assert_eq!(url.host_str(), Some("localhost"));
+            assert_eq!(url.port(), Some(4096));
+            assert_eq!(url.path(), path, "{:?}", repo_id);
+            assert_eq!(url.query(), None);
+        }
+        for repo_id in ["", ".", ".."] {
+            assert!(unlock_url(4096, repo_id).is_err(), "{:?}", repo_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn stored_passphrase_unlocks_without_asking() {
+        let store = store_with("repo", "right");
+        let (outcome, attempts) = run(store, true, None).await;
+        let outcome = outcome.unwrap();
+        assert!(outcome.from_keyring);
+        assert!(!outcome.stored);
+        assert_eq!(attempts, ["right"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_stored_passphrase_is_replaced_by_the_entered_one() {
+        let store = store_with("repo", "stale");
+        let (outcome, attempts) = run(store.clone(), true, Some("right")).await;
+        let outcome = outcome.unwrap();
+        assert!(!outcome.from_keyring);
+        assert!(outcome.stored);
+        assert_eq!(attempts, ["stale", "right"]);
+        assert_eq!(store.load("repo").unwrap().as_deref().unwrap(), "right");
+    }
+
+    #[tokio::test]
+    async fn passphrases_are_not_touched_unless_remembered() {
+        let store = store_with("repo", "right");
+        let (outcome, attempts) = run(store.clone(), false, Some("right")).await;
+        assert!(!outcome.unwrap().stored);
+        // Only the entered one was tried
+        assert_eq!(attempts, ["right"]);
+
+        let store = Arc::new(MemoryStore::default());
+        run(store.clone(), false, Some("right")).await.0.unwrap();
+        assert!(store.secrets.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn wrong_or_cancelled_entry_fails_without_storing() {
+        let store = Arc::new(MemoryStore::default());
+        let (outcome, _) = run(store.clone(), true, Some("wrong")).await;
+        assert_eq!(outcome.unwrap_err().to_string(), "Incorrect passphrase");
+
+        let (outcome, attempts) = run(store.clone(), true, None).await;
+        assert_eq!(outcome.unwrap_err().to_string(), "Cancelled by user");
+        assert!(attempts.is_empty());
+        assert!(store.secrets.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keyring_failure_still_unlocks() {
+        let store = Arc::new(MemoryStore {
+            refuse_store: true,
+            ..MemoryStore::default()
+        });
+        let (outcome, _) = run(store, true, Some("right")).await;
+        assert!(!outcome.unwrap().stored);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::error::AppError;
+use crate::redact::{InvalidRule, Redactor};
+use crate::settings::SettingsStore;
+use crate::AppState;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactionPreview {
+    pub redacted: String,
+    pub rules: Vec<String>,
+    pub invalid_rules: Vec<InvalidRule>,
+}
+
+/// Show how a sample would be redacted, using `rules` if given or the saved custom rules
+/// Lets users check their patterns before saving them
+#[tauri::command]
+pub async fn preview_redaction(
+    settings: tauri::State<'_, SettingsStore>,
+    sample_text: String,
+    rules: Option<Vec<String>>,
+) -> Result<RedactionPreview, AppError> {
+    let rules = rules.unwrap_or_else(|| settings.get().redaction_rules);
+    let (redactor, invalid_rules) = Redactor::new(&rules);
+    Ok(RedactionPreview {
+        redacted: redactor.redact(&sample_text).into_owned(),
+        rules: redactor.rule_names(),
+        invalid_rules,
+    })
+}
+
+/// Save custom redaction rules and apply them right away
+/// Invalid patterns are rejected so a typo can't silently disable redaction
+#[tauri::command]
+pub async fn set_redaction_rules(
+    state: tauri::State<'_, AppState>,
+    settings: tauri::State<'_, SettingsStore>,
+    rules: Vec<String>,
+) -> Result<(), AppError> {
+    let (redactor, invalid_rules) = Redactor::new(&rules);
+    if let Some(invalid) = invalid_rules.first() {
+        return Err(format!(
+            "Invalid redaction rule {:?}: {}",
+            invalid.pattern, invalid.error
+        )
+        .into());
+    }
+
+    settings.update(|settings| settings.redaction_rules = rules)?;
+    *state.redactor.write() = redactor;
+    Ok(())
+}
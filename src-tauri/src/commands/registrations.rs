@@ -0,0 +1,76 @@
+use crate::elevation::{BusyPolicy, ElevationClass};
+use crate::error::AppError;
+use crate::registrations::{self, RegistrationKind, RepairResult, StaleRegistration};
+use crate::AppState;
+use tracing::{info, warn};
+
+/// List autostart and service registrations that point somewhere other than this install
+#[tauri::command]
+pub async fn get_stale_registrations(
+    app: tauri::AppHandle,
+) -> Result<Vec<StaleRegistration>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || registrations::find_stale(&app))
+        .await
+        .map_err(|e| e.to_string().into())
+}
+
+/// Point stale registrations at this install. The autostart entry is fixed right away; the
+/// service path needs administrator approval and runs through the elevation coordinator.
+#[tauri::command]
+pub async fn repair_registrations(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RepairResult>, AppError> {
+    let finder = app.clone();
+    let stale = tauri::async_runtime::spawn_blocking(move || registrations::find_stale(&finder))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    // Non-elevated repairs first, so a declined prompt doesn't hold them up
+    for registration in stale.iter().filter(|r| !r.needs_elevation) {
+        let outcome = match registration.kind {
+            RegistrationKind::Autostart => registrations::repair_autostart(&app),
+            RegistrationKind::Service => continue,
+        };
+        results.push(result(registration.kind, outcome));
+    }
+    for registration in stale.iter().filter(|r| r.needs_elevation) {
+        let outcome = state
+            .elevation
+            .run(
+                ElevationClass::Service,
+                "repair_registrations",
+                BusyPolicy::Reject,
+                registrations::repair_service(
+                    registration.recorded.clone(),
+                    registration.expected.clone(),
+                ),
+            )
+            .await
+            .map_err(|e| e.to_string());
+        results.push(result(registration.kind, outcome));
+    }
+    Ok(results)
+}
+
+fn result(kind: RegistrationKind, outcome: Result<(), String>) -> RepairResult {
+    match outcome {
+        Ok(()) => {
+            info!("Repaired {:?} registration", kind);
+            RepairResult {
+                kind,
+                repaired: true,
+                error: None,
+            }
+        }
+        Err(e) => {
+            warn!("Failed to repair {:?} registration: {}", kind, e);
+            RepairResult {
+                kind,
+                repaired: false,
+                error: Some(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::error::AppError;
+use crate::restore::{self, EndReason, RestoreSession};
+use crate::AppState;
+
+/// Boot a read-only backend instance on a snapshot and open a window to browse and restore
+/// from it
+#[tauri::command]
+pub async fn start_restore_session(
+    app: tauri::AppHandle,
+    repo_id: String,
+    snapshot_id: String,
+) -> Result<RestoreSession, AppError> {
+    Ok(restore::start(&app, repo_id, snapshot_id).await?)
+}
+
+/// List running restore sessions
+#[tauri::command]
+pub async fn list_restore_sessions(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<RestoreSession>, AppError> {
+    Ok(state.restore_sessions.list())
+}
+
+/// Stop a restore session's instance and close its window
+#[tauri::command]
+pub async fn end_restore_session(app: tauri::AppHandle, id: u64) -> Result<(), AppError> {
+    restore::end(&app, id, EndReason::Requested).await?;
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::error::AppError;
+use crate::retention::{self, ArtifactClass, RetentionPlan, RetentionRule};
+use crate::settings::SettingsStore;
+use std::collections::HashMap;
+
+/// What a cleanup would delete under the current retention settings, without deleting it
+#[tauri::command]
+pub async fn preview_retention_cleanup(
+    app: tauri::AppHandle,
+) -> Result<RetentionPlan, AppError> {
+    tauri::async_runtime::spawn_blocking(move || retention::plan(&app))
+        .await
+        .map_err(|e| format!("Failed to plan the cleanup: {}", e).into())
+}
+
+/// Run a cleanup now and let later automatic runs delete without asking; returns the number
+/// of files deleted
+#[tauri::command]
+pub async fn run_retention_cleanup(
+    app: tauri::AppHandle,
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<usize, AppError> {
+    settings.update(|settings| settings.retention_confirmed = true)?;
+    tauri::async_runtime::spawn_blocking(move || retention::apply(&retention::plan(&app)))
+        .await
+        .map_err(|e| format!("Failed to run the cleanup: {}", e).into())
+}
+
+/// Override the caps for some artifact classes (None restores the default), and whether
+/// quarantined files may be deleted automatically
+#[tauri::command]
+pub async fn set_retention_rules(
+    settings: tauri::State<'_, SettingsStore>,
+    rules: HashMap<ArtifactClass, Option<RetentionRule>>,
+    delete_quarantined: Option<bool>,
+) -> Result<(), AppError> {
+    settings.update(|settings| {
+        for (class, rule) in rules {
+            match rule {
+                Some(rule) => settings.retention_rules.insert(class, rule),
+                None => settings.retention_rules.remove(&class),
+            };
+        }
+        if let Some(delete_quarantined) = delete_quarantined {
+            settings.retention_delete_quarantined = delete_quarantined;
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use crate::error::AppError;
+use crate::schedule::{self, CronSchedule, MaintenanceWindow, NamedSchedule, ScheduleConflict};
+use crate::timezone::{self, ConvertedTime, ZoneSnapshot};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+
+/// A backup schedule as the web UI knows it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleInput {
+    pub name: String,
+    pub cron_expression: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleConflictReport {
+    /// OS maintenance windows that were detected
+    pub windows: Vec<MaintenanceWindow>,
+    pub conflicts: Vec<ScheduleConflict>,
+    /// Schedules whose cron expression could not be parsed
+    pub invalid: Vec<String>,
+}
+
+/// The desktop's zone and the one the backend reports times in
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeZones {
+    pub desktop: ZoneSnapshot,
+    pub backend: Option<String>,
+}
+
+/// The backend's zone as given by the caller, or as the backend reported it
+fn backend_zone(
+    state: &AppState,
+    backend_time_zone: Option<String>,
+) -> Result<Option<chrono_tz::Tz>, String> {
+    match backend_time_zone.or_else(|| state.backend_time_zone.lock().clone()) {
+        Some(name) => timezone::parse_zone(&name)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown time zone \"{}\"", name)),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub async fn get_time_zones(state: tauri::State<'_, AppState>) -> Result<TimeZones, AppError> {
+    Ok(TimeZones {
+        desktop: ZoneSnapshot::current(),
+        backend: state.backend_time_zone.lock().clone(),
+    })
+}
+
+/// Convert backend timestamps into the desktop's current zone for display. Times without an
+/// offset are read in `backend_time_zone`, or the zone the backend reported.
+#[tauri::command]
+pub async fn convert_backend_times(
+    state: tauri::State<'_, AppState>,
+    times: Vec<String>,
+    backend_time_zone: Option<String>,
+) -> Result<Vec<ConvertedTime>, AppError> {
+    let backend = backend_zone(&state, backend_time_zone)?;
+    let desktop = timezone::desktop_zone();
+    let now = chrono::Utc::now();
+    let converted = times
+        .iter()
+        .map(|time| timezone::convert(time, backend, desktop, now))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(converted)
+}
+
+/// Compare backup schedules against the OS maintenance and update restart windows
+/// `offset_minutes` converts backend schedule times into the desktop's local time; without
+/// it the offset between the backend's zone and the desktop's right now is used (0 when the
+/// backend doesn't report a zone, e.g. on the same machine)
+#[tauri::command]
+pub async fn get_schedule_conflicts(
+    state: tauri::State<'_, AppState>,
+    schedules: Vec<ScheduleInput>,
+    offset_minutes: Option<i32>,
+    backend_time_zone: Option<String>,
+) -> Result<ScheduleConflictReport, AppError> {
+    let offset_minutes = match offset_minutes {
+        Some(offset) => offset,
+        None => backend_zone(&state, backend_time_zone)?
+            .map(|backend| {
+                timezone::offset_minutes(backend, timezone::desktop_zone(), chrono::Utc::now())
+            })
+            .unwrap_or(0),
+    };
+
+    let mut parsed = Vec::new();
+    let mut invalid = Vec::new();
+    for input in schedules {
+        match CronSchedule::parse(&input.cron_expression) {
+            Ok(cron) => parsed.push(NamedSchedule {
+                name: input.name,
+                cron,
+            }),
+            Err(e) => invalid.push(format!("{}: {}", input.name, e)),
+        }
+    }
+
+    let windows = tauri::async_runtime::spawn_blocking(os_maintenance_windows)
+        .await
+        .map_err(|e| format!("Failed to read maintenance windows: {}", e))?;
+
+    let conflicts = schedule::find_conflicts(&parsed, &windows, offset_minutes);
+
+    Ok(ScheduleConflictReport {
+        windows,
+        conflicts,
+        invalid,
+    })
+}
+
+/// Best-effort detection of the OS maintenance windows in local time
+fn os_maintenance_windows() -> Vec<MaintenanceWindow> {
+    #[cfg(target_os = "windows")]
+    {
+        windows_maintenance_windows()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        unattended_upgrades_window().into_iter().collect()
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn windows_maintenance_windows() -> Vec<MaintenanceWindow> {
+    let mut windows = Vec::new();
+
+    // Automatic maintenance runs daily at the activation boundary (2 AM unless changed)
+    let maintenance_start = reg_query(
+        r"HKLM\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Schedule\Maintenance",
+        "Activation Boundary",
+    )
+    .and_then(|boundary| parse_activation_boundary(&boundary))
+    .unwrap_or(2 * 60);
+    windows.push(MaintenanceWindow::daily(
+        "automatic-maintenance",
+        "Windows automatic maintenance",
+        maintenance_start,
+        60,
+    ));
+
+    // Windows Update may restart the machine any time outside active hours
+    let active_start = reg_query(
+        r"HKLM\SOFTWARE\Microsoft\WindowsUpdate\UX\Settings",
+        "ActiveHoursStart",
+    )
+    .and_then(|v| parse_reg_dword(&v))
+    .unwrap_or(8);
+    let active_end = reg_query(
+        r"HKLM\SOFTWARE\Microsoft\WindowsUpdate\UX\Settings",
+        "ActiveHoursEnd",
+    )
+    .and_then(|v| parse_reg_dword(&v))
+    .unwrap_or(17);
+    let restart_hours = (active_start + 24 - active_end) % 24;
+    if restart_hours > 0 {
+        windows.push(MaintenanceWindow::daily(
+            "update-restart",
+            "Windows Update restarts (outside active hours)",
+            active_end * 60,
+            restart_hours * 60,
+        ));
+    }
+
+    windows
+}
+
+/// Read a registry value's data using `reg query`
+#[cfg(target_os = "windows")]
+fn reg_query(key: &str, value: &str) -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+
+    // CREATE_NO_WINDOW flag to hide console window
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let output = Command::new("reg")
+        .args(["query", key, "/v", value])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Output looks like "    Activation Boundary    REG_SZ    2000-01-01T02:00:00"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            let (_, rest) = line.split_once("REG_")?;
+            let (_, data) = rest.split_once(char::is_whitespace)?;
+            Some(data.trim().to_string())
+        })
+}
+
+/// "2000-01-01T02:00:00" -> minute of day
+#[cfg(target_os = "windows")]
+fn parse_activation_boundary(value: &str) -> Option<u32> {
+    let (_, time) = value.split_once('T')?;
+    let mut parts = time.split(':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next()?.parse().ok()?;
+    (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+}
+
+/// "0x8" -> 8
+#[cfg(target_os = "windows")]
+fn parse_reg_dword(value: &str) -> Option<u32> {
+    let hex = value.trim().trim_start_matches("0x");
+    u32::from_str_radix(hex, 16).ok().filter(|h| *h < 24)
+}
+
+/// Automatic reboot time configured for unattended-upgrades on Debian/Ubuntu
+#[cfg(target_os = "linux")]
+fn unattended_upgrades_window() -> Option<MaintenanceWindow> {
+    let config =
+        std::fs::read_to_string("/etc/apt/apt.conf.d/50unattended-upgrades").ok()?;
+    let active_lines: Vec<&str> = config
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("//"))
+        .collect();
+
+    let reboot_enabled = active_lines
+        .iter()
+        .any(|line| line.starts_with("Unattended-Upgrade::Automatic-Reboot \"true\""));
+    if !reboot_enabled {
+        return None;
+    }
+
+    let time = active_lines.iter().find_map(|line| {
+        let rest = line.strip_prefix("Unattended-Upgrade::Automatic-Reboot-Time")?;
+        let value = rest.trim().trim_end_matches(';').trim_matches('"');
+        let (hour, minute) = value.split_once(':')?;
+        let hour: u32 = hour.parse().ok()?;
+        let minute: u32 = minute.parse().ok()?;
+        (hour < 24 && minute < 60).then_some(hour * 60 + minute)
+    })?;
+
+    Some(MaintenanceWindow::daily(
+        "unattended-upgrades",
+        "Automatic reboot after unattended upgrades",
+        time,
+        30,
+    ))
+}
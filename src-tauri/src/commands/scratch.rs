@@ -0,0 +1,19 @@
+use crate::error::AppError;
+use crate::scratch::{Scratch, ScratchPurpose, ScratchUsage};
+
+/// Space used by previews, diagnostics and downloads, per purpose, against the quota
+#[tauri::command]
+pub async fn get_scratch_usage(
+    scratch: tauri::State<'_, Scratch>,
+) -> Result<ScratchUsage, AppError> {
+    Ok(scratch.usage())
+}
+
+/// Delete scratch files for `purpose` (everything if omitted); returns the bytes freed
+#[tauri::command]
+pub async fn clear_scratch(
+    scratch: tauri::State<'_, Scratch>,
+    purpose: Option<ScratchPurpose>,
+) -> Result<u64, AppError> {
+    Ok(scratch.clear(purpose)?)
+}
@@ -1,10 +1,17 @@
+use crate::elevation::{BusyPolicy, ElevationClass, InFlightOperation};
+use crate::error::AppError;
+use crate::http::HttpPolicy;
+use crate::service_access::{self, ActionCapability, ServiceAction};
+use crate::AppState;
 use serde::{Deserialize, Serialize};
+#[cfg(target_os = "windows")]
 use std::time::Duration;
 #[cfg(target_os = "windows")]
-use tracing::info;
+use tracing::{info, warn};
 
-/// Port used for Windows Service mode
-const SERVICE_PORT: u16 = 4097;
+/// How long a stopped service gets to close its port before its status is checked
+#[cfg(target_os = "windows")]
+const SERVICE_STOP_WAIT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceStatus {
@@ -15,12 +22,12 @@ pub struct ServiceStatus {
 
 #[cfg(target_os = "windows")]
 /// Helper to create and execute an elevated batch script for service operations
-async fn execute_elevated_script(
+pub(crate) async fn execute_elevated_script(
     script_name: &str,
     script_content: String,
     log_path: &std::path::Path,
     success_message: &str,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     use tokio::time::sleep;
 
     // Create script in temp directory
@@ -54,7 +61,8 @@ async fn execute_elevated_script(
             return Err(format!(
                 "Operation failed. Check log file for details: {}",
                 log_path.display()
-            ));
+            )
+            .into());
         }
     }
 
@@ -63,7 +71,7 @@ async fn execute_elevated_script(
 
 /// Get the current status of the Windows Service
 #[tauri::command]
-pub async fn get_service_status() -> Result<ServiceStatus, String> {
+pub async fn get_service_status() -> Result<ServiceStatus, AppError> {
     #[cfg(target_os = "windows")]
     {
         use std::os::windows::process::CommandExt;
@@ -77,7 +85,7 @@ pub async fn get_service_status() -> Result<ServiceStatus, String> {
             .args(["query", "C3iBackupONE"])
             .creation_flags(CREATE_NO_WINDOW)
             .output()
-            .map_err(|e| format!("Failed to query service: {}", e))?;
+            .map_err(|e| AppError::ServiceUnavailable(format!("sc query failed: {}", e)))?;
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -130,16 +138,21 @@ pub async fn get_service_status() -> Result<ServiceStatus, String> {
     }
 }
 
+/// For each service action: whether it's allowed, whether it will prompt for administrator
+/// approval, and why not if it can't be taken
+#[tauri::command]
+pub async fn get_service_capabilities() -> Result<Vec<ActionCapability>, AppError> {
+    Ok(service_access::matrix(&service_access::probe()))
+}
+
 /// Check if the Windows Service is running by trying to connect to its port
 #[tauri::command]
-pub async fn is_service_running() -> Result<bool, String> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-    let url = format!("http://localhost:{}/healthcheck", SERVICE_PORT);
-    match client.get(&url).send().await {
+pub async fn is_service_running(state: tauri::State<'_, AppState>) -> Result<bool, AppError> {
+    match state
+        .http
+        .healthcheck(state.config().service_port, HttpPolicy::INTERACTIVE)
+        .await
+    {
         Ok(response) => Ok(response.status().is_success()),
         Err(_) => Ok(false),
     }
@@ -147,27 +160,39 @@ pub async fn is_service_running() -> Result<bool, String> {
 
 /// Install the Windows Service (requires elevation)
 #[tauri::command]
-pub async fn install_service(app: tauri::AppHandle) -> Result<(), String> {
+pub async fn install_service(state: tauri::State<'_, AppState>) -> Result<(), AppError> {
+    service_access::check(ServiceAction::Install)?;
+    state
+        .elevation
+        .run(
+            ElevationClass::Service,
+            "install_service",
+            BusyPolicy::Reject,
+            install_service_elevated(state.paths().binaries_dir.clone()),
+        )
+        .await
+}
+
+async fn install_service_elevated(binaries_dir: std::path::PathBuf) -> Result<(), AppError> {
     #[cfg(target_os = "windows")]
     {
         use std::env;
-        use tauri::Manager;
 
         // Get the path to the service executable
-        let exe_dir = app
-            .path()
-            .resource_dir()
-            .map_err(|e| format!("Failed to get resource directory: {}", e))?;
-
-        let service_exe = exe_dir.join("binaries").join("zerobyte-service.exe");
+        let service_exe = binaries_dir.join("zerobyte-service.exe");
 
         if !service_exe.exists() {
             return Err(format!(
                 "Service executable not found at: {}",
                 service_exe.display()
-            ));
+            )
+            .into());
         }
 
+        // Only this user's desktop gets to read the token the service makes up
+        let owner = crate::backend_token::current_user_sid()
+            .ok_or("Can't tell which user the service is being installed for")?;
+
         info!("Installing service from: {}", service_exe.display());
 
         let temp_dir = env::temp_dir();
@@ -180,7 +205,7 @@ pub async fn install_service(app: tauri::AppHandle) -> Result<(), String> {
         let script = format!(
             r#"@echo off
 echo Installing service... > "{log}"
-sc create C3iBackupONE binPath= "{exe}" start= auto DisplayName= "C3i Backup ONE Service" >> "{log}" 2>&1
+sc create C3iBackupONE binPath= "\"{exe}\" {owner_arg} {owner}" start= auto DisplayName= "C3i Backup ONE Service" >> "{log}" 2>&1
 if %errorlevel% neq 0 (
     echo ERROR: Failed to create service >> "{log}"
     exit /b %errorlevel%
@@ -190,6 +215,8 @@ sc start C3iBackupONE >> "{log}" 2>&1
 echo Installation complete >> "{log}"
 "#,
             exe = service_exe.display(),
+            owner_arg = crate::backend_token::OWNER_ARG,
+            owner = owner,
             log = log_path.display()
         );
 
@@ -211,7 +238,8 @@ echo Installation complete >> "{log}"
             return Err(format!(
                 "Service installation failed. Details:\n{}",
                 error_details
-            ));
+            )
+            .into());
         }
 
         info!("Service installed successfully");
@@ -220,14 +248,38 @@ echo Installation complete >> "{log}"
 
     #[cfg(not(target_os = "windows"))]
     {
-        let _ = app;
-        Err("Windows Service is only supported on Windows".to_string())
+        let _ = binaries_dir;
+        Err(AppError::ServiceActionRefused(service_access::CapabilityReason::Unsupported))
     }
 }
 
-/// Uninstall the Windows Service (requires elevation)
+/// Uninstall the Windows Service (requires elevation, after a native confirmation)
 #[tauri::command]
-pub async fn uninstall_service() -> Result<(), String> {
+pub async fn uninstall_service(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    skip_confirmation: Option<bool>,
+) -> Result<(), AppError> {
+    service_access::check(ServiceAction::Uninstall)?;
+    crate::dialogs::confirm(
+        &app,
+        crate::dialogs::Prompt::UninstallService,
+        skip_confirmation.unwrap_or(false),
+        None,
+    )
+    .await?;
+    state
+        .elevation
+        .run(
+            ElevationClass::Service,
+            "uninstall_service",
+            BusyPolicy::Reject,
+            uninstall_service_elevated(),
+        )
+        .await
+}
+
+async fn uninstall_service_elevated() -> Result<(), AppError> {
     #[cfg(target_os = "windows")]
     {
         use std::env;
@@ -273,7 +325,8 @@ echo Uninstallation complete >> "{log}"
             return Err(format!(
                 "Service uninstallation failed. Details:\n{}",
                 error_details
-            ));
+            )
+            .into());
         }
 
         info!("Service uninstalled successfully");
@@ -282,13 +335,28 @@ echo Uninstallation complete >> "{log}"
 
     #[cfg(not(target_os = "windows"))]
     {
-        Err("Windows Service is only supported on Windows".to_string())
+        Err(AppError::ServiceActionRefused(service_access::CapabilityReason::Unsupported))
     }
 }
 
 /// Start the Windows Service (requires elevation)
 #[tauri::command]
-pub async fn start_service() -> Result<(), String> {
+pub async fn start_service(
+    state: tauri::State<'_, AppState>,
+) -> Result<(), AppError> {
+    service_access::check(ServiceAction::Start)?;
+    state
+        .elevation
+        .run(
+            ElevationClass::Service,
+            "start_service",
+            BusyPolicy::Queue,
+            start_service_elevated(),
+        )
+        .await
+}
+
+async fn start_service_elevated() -> Result<(), AppError> {
     #[cfg(target_os = "windows")]
     {
         use std::env;
@@ -331,7 +399,8 @@ echo Service started >> "{log}"
             return Err(format!(
                 "Failed to start service. Details:\n{}",
                 error_details
-            ));
+            )
+            .into());
         }
 
         info!("Service started successfully");
@@ -340,13 +409,28 @@ echo Service started >> "{log}"
 
     #[cfg(not(target_os = "windows"))]
     {
-        Err("Windows Service is only supported on Windows".to_string())
+        Err(AppError::ServiceActionRefused(service_access::CapabilityReason::Unsupported))
     }
 }
 
 /// Stop the Windows Service (requires elevation)
 #[tauri::command]
-pub async fn stop_service() -> Result<(), String> {
+pub async fn stop_service(
+    state: tauri::State<'_, AppState>,
+) -> Result<(), AppError> {
+    service_access::check(ServiceAction::Stop)?;
+    state
+        .elevation
+        .run(
+            ElevationClass::Service,
+            "stop_service",
+            BusyPolicy::Queue,
+            stop_service_elevated(state.config().service_port),
+        )
+        .await
+}
+
+async fn stop_service_elevated(port: u16) -> Result<(), AppError> {
     #[cfg(target_os = "windows")]
     {
         use std::env;
@@ -380,6 +464,16 @@ echo Service stopped >> "{log}"
         )
         .await?;
 
+        // `sc stop` returns while the service is still flushing; wait for its server to go
+        let outcome = tokio::task::spawn_blocking(move || {
+            crate::graceful::wait_for_port_closed(port, SERVICE_STOP_WAIT)
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        if let crate::graceful::WaitOutcome::CapReached { elapsed, .. } = outcome {
+            warn!("Service still serving on port {} after {:?}", port, elapsed);
+        }
+
         // Check if the service is stopped
         let status = get_service_status().await?;
 
@@ -389,7 +483,8 @@ echo Service stopped >> "{log}"
             return Err(format!(
                 "Failed to stop service. Details:\n{}",
                 error_details
-            ));
+            )
+            .into());
         }
 
         info!("Service stopped successfully");
@@ -398,13 +493,32 @@ echo Service stopped >> "{log}"
 
     #[cfg(not(target_os = "windows"))]
     {
-        Err("Windows Service is only supported on Windows".to_string())
+        let _ = port;
+        Err(AppError::ServiceActionRefused(service_access::CapabilityReason::Unsupported))
     }
 }
 
+/// Move the UI between the sidecar and a running Windows Service without restarting the app
+/// (e.g. right after installing the service). Returns the port now in use.
+#[tauri::command]
+pub async fn switch_backend_mode(
+    app: tauri::AppHandle,
+    target: crate::ownership::BackendMode,
+) -> Result<u16, AppError> {
+    crate::switch::switch(&app, target).await
+}
+
+/// List elevated operations currently in flight
+#[tauri::command]
+pub async fn get_elevation_status(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<InFlightOperation>, AppError> {
+    Ok(state.elevation.status())
+}
+
 /// Run a command with UAC elevation using ShellExecuteW
 #[cfg(target_os = "windows")]
-fn run_elevated(command: &str) -> Result<(), String> {
+fn run_elevated(command: &str) -> Result<(), AppError> {
     use std::ffi::OsStr;
     use std::iter::once;
     use std::os::windows::ffi::OsStrExt;
@@ -413,6 +527,8 @@ fn run_elevated(command: &str) -> Result<(), String> {
     use windows::Win32::UI::Shell::ShellExecuteW;
     use windows::Win32::UI::WindowsAndMessaging::SW_HIDE;
 
+    const SE_ERR_ACCESSDENIED: usize = 5;
+
     fn to_wide(s: &str) -> Vec<u16> {
         OsStr::new(s).encode_wide().chain(once(0)).collect()
     }
@@ -431,14 +547,16 @@ fn run_elevated(command: &str) -> Result<(), String> {
             SW_HIDE,
         );
 
-        // ShellExecuteW returns a value > 32 on success
-        if result.0 as usize > 32 {
-            Ok(())
-        } else {
-            Err(format!(
+        // ShellExecuteW returns a value > 32 on success, and SE_ERR_ACCESSDENIED when the
+        // user declines the UAC prompt
+        match result.0 as usize {
+            code if code > 32 => Ok(()),
+            SE_ERR_ACCESSDENIED => Err(AppError::ElevationDeclined),
+            code => Err(format!(
                 "Failed to execute elevated command. Error code: {}",
-                result.0 as usize
-            ))
+                code
+            )
+            .into()),
         }
     }
 }
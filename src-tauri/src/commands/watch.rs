@@ -0,0 +1,40 @@
+use crate::error::AppError;
+use crate::watch::{self, WatchRegistration, WatcherManager, WatcherStatus};
+use std::path::PathBuf;
+
+/// Trigger `plan_id` whenever files under `paths` change, once changes settle for
+/// `debounce_secs`. Replaces the plan's previous watcher and survives app restarts.
+#[tauri::command]
+pub async fn watch_paths_for_plan(
+    app: tauri::AppHandle,
+    plan_id: u64,
+    paths: Vec<PathBuf>,
+    debounce_secs: Option<u64>,
+) -> Result<WatcherStatus, AppError> {
+    if paths.is_empty() {
+        return Err("No paths to watch".into());
+    }
+    let registration = WatchRegistration {
+        plan_id,
+        paths,
+        debounce_secs: debounce_secs.unwrap_or(watch::DEFAULT_DEBOUNCE.as_secs()),
+    };
+    Ok(watch::watch(&app, registration).await?)
+}
+
+/// Stop watching for a plan
+#[tauri::command]
+pub async fn unwatch_plan(
+    manager: tauri::State<'_, WatcherManager>,
+    plan_id: u64,
+) -> Result<bool, AppError> {
+    Ok(manager.unwatch(plan_id)?)
+}
+
+/// List running watchers with their descriptor usage and pending changes
+#[tauri::command]
+pub async fn list_active_watchers(
+    manager: tauri::State<'_, WatcherManager>,
+) -> Result<Vec<WatcherStatus>, AppError> {
+    Ok(manager.list())
+}
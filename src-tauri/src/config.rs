@@ -0,0 +1,231 @@
+//! Startup and lifecycle knobs kept in `zerobyte.toml` in the app config directory.
+//!
+//! Unlike `settings.json`, which the UI owns, this file is meant to be edited by hand too
+//! (ports clashing with another program, a slow disk that needs a longer startup timeout),
+//! so it's TOML and is created with the defaults spelled out on the first start. A file that
+//! doesn't parse is left alone for the user to fix and the defaults are used meanwhile.
+
+use crate::persist;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// File name of the config in the app config directory
+pub const CONFIG_FILE: &str = "zerobyte.toml";
+
+/// Port the sidecar asks for unless configured otherwise
+pub const DEFAULT_SIDECAR_PORT: u16 = 4096;
+
+/// Port the Windows Service listens on unless configured otherwise
+pub const DEFAULT_SERVICE_PORT: u16 = 4097;
+
+/// Environment variable that moves the sidecar off its configured port for one run
+pub const PORT_ENV: &str = "ZEROBYTE_PORT";
+
+/// Ports after the configured one tried when it's taken by another program
+pub const SIDECAR_PORT_SPAN: u16 = 14;
+
+/// Longest configurable grace period; the shutdown barrier grows with it
+pub const MAX_SHUTDOWN_GRACE_SECS: u64 = 60;
+
+/// Missed healthchecks in a row after which a running sidecar counts as hung; six checks
+/// 15 seconds apart make about a minute and a half
+pub const DEFAULT_UNRESPONSIVE_AFTER: u32 = 6;
+
+/// Longest configurable startup timeout
+pub const MAX_STARTUP_TIMEOUT_SECS: u64 = 10 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesktopConfig {
+    /// Port the sidecar is started on (applies the next time it starts)
+    pub sidecar_port: u16,
+    /// Port the Windows Service is looked for on (applies the next time the backend starts)
+    pub service_port: u16,
+    /// How long a silent sidecar gets to answer its healthcheck
+    pub startup_timeout_secs: u64,
+    /// How long the sidecar gets to exit after a graceful shutdown request before it's killed.
+    /// It's only waited for as long as it actually takes to exit.
+    pub shutdown_grace_secs: u64,
+    /// Hide the main window to the tray when it's closed instead of quitting
+    pub close_to_tray: bool,
+    /// Restart the sidecar when it stops answering its healthcheck while still running
+    pub restart_when_unresponsive: bool,
+    /// Missed healthchecks in a row after which the backend counts as unresponsive
+    pub unresponsive_after: u32,
+    /// Where the sidecar keeps its database; the server's default when unset. Change it with
+    /// `set_data_directory`, which can move the existing data along. The Windows Service
+    /// keeps its data in %PROGRAMDATA% regardless.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        Self {
+            sidecar_port: DEFAULT_SIDECAR_PORT,
+            service_port: DEFAULT_SERVICE_PORT,
+            startup_timeout_secs: 15,
+            shutdown_grace_secs: 10,
+            close_to_tray: true,
+            restart_when_unresponsive: true,
+            unresponsive_after: DEFAULT_UNRESPONSIVE_AFTER,
+            data_dir: None,
+        }
+    }
+}
+
+impl DesktopConfig {
+    /// Ports the sidecar may take, the configured one first
+    pub fn sidecar_ports(&self) -> std::ops::RangeInclusive<u16> {
+        self.sidecar_port..=self.sidecar_port.saturating_add(SIDECAR_PORT_SPAN)
+    }
+
+    /// [`sidecar_ports`](Self::sidecar_ports) without the service's port and `reserved` (the
+    /// desktop's own listeners). A sidecar on the service's port would later be taken for the
+    /// service.
+    pub fn sidecar_port_candidates(&self, reserved: &[u16]) -> Vec<u16> {
+        self.sidecar_ports()
+            .filter(|port| *port != self.service_port && !reserved.contains(port))
+            .collect()
+    }
+
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout_secs)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sidecar_port == 0 || self.service_port == 0 {
+            return Err("Ports must be between 1 and 65535".to_string());
+        }
+        if self.sidecar_port == self.service_port {
+            return Err("The sidecar and the service need different ports".to_string());
+        }
+        if !(1..=MAX_STARTUP_TIMEOUT_SECS).contains(&self.startup_timeout_secs) {
+            return Err(format!(
+                "The startup timeout must be between 1 and {} seconds",
+                MAX_STARTUP_TIMEOUT_SECS
+            ));
+        }
+        if self.shutdown_grace_secs > MAX_SHUTDOWN_GRACE_SECS {
+            return Err(format!(
+                "The shutdown grace period can be at most {} seconds",
+                MAX_SHUTDOWN_GRACE_SECS
+            ));
+        }
+        if self.unresponsive_after == 0 {
+            return Err("unresponsive_after must be at least 1".to_string());
+        }
+        if self.data_dir.as_ref().is_some_and(|dir| !dir.is_absolute()) {
+            return Err("The data directory must be an absolute path".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether changing from `self` to `updated` only takes effect once the backend restarts
+    pub fn needs_backend_restart(&self, updated: &DesktopConfig) -> bool {
+        self.sidecar_port != updated.sidecar_port
+            || self.service_port != updated.service_port
+            || self.data_dir != updated.data_dir
+    }
+}
+
+/// Sidecar port from `flag`, the value of `--port` on the command line, else from
+/// [`PORT_ENV`]. It replaces `sidecar_port` for this run without being saved.
+pub fn port_override(flag: Option<String>) -> Result<Option<u16>, String> {
+    let (source, value) = match flag {
+        Some(value) => ("--port", value),
+        None => match std::env::var(PORT_ENV) {
+            Ok(value) => (PORT_ENV, value),
+            Err(_) => return Ok(None),
+        },
+    };
+    let port = value
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(|| format!("{} must be between 1 and 65535, not {:?}", source, value))?;
+    if port == DEFAULT_SERVICE_PORT {
+        return Err(format!(
+            "{} {} is the Windows Service's port; choose another one",
+            source, port
+        ));
+    }
+    Ok(Some(port))
+}
+
+/// The close-to-tray choice from `settings.json`, where it lived before this file existed
+fn legacy_close_to_tray(settings_path: &Path) -> Option<bool> {
+    let contents = std::fs::read(settings_path).ok()?;
+    let settings: serde_json::Value = serde_json::from_slice(&contents).ok()?;
+    settings.get("close_to_tray")?.as_bool()
+}
+
+/// Load the config from `path`, writing the defaults there if it doesn't exist yet
+pub fn load_or_create(path: &Path, settings_path: &Path) -> DesktopConfig {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let config = toml::from_str::<DesktopConfig>(&contents)
+                .map_err(|e| e.to_string())
+                .and_then(|config| config.validate().map(|()| config));
+            match config {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Invalid {}, using defaults: {}", path.display(), e);
+                    DesktopConfig::default()
+                }
+            }
+        }
+        Err(_) => {
+            let config = DesktopConfig {
+                close_to_tray: legacy_close_to_tray(settings_path).unwrap_or(true),
+                ..DesktopConfig::default()
+            };
+            match save(path, &config) {
+                Ok(()) => info!("Wrote default config to {}", path.display()),
+                Err(e) => warn!("{}", e),
+            }
+            config
+        }
+    }
+}
+
+pub fn save(path: &Path, config: &DesktopConfig) -> Result<(), String> {
+    let contents = toml::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+    persist::atomic_write(path, contents.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_candidates_skip_the_service_and_reserved_ports() {
+        let config = DesktopConfig::default();
+        let candidates = config.sidecar_port_candidates(&[4100]);
+        assert_eq!(candidates.first(), Some(&DEFAULT_SIDECAR_PORT));
+        assert!(!candidates.contains(&DEFAULT_SERVICE_PORT));
+        assert!(!candidates.contains(&4100));
+        assert_eq!(candidates.len(), usize::from(SIDECAR_PORT_SPAN) + 1 - 2);
+    }
+
+    #[test]
+    fn sidecar_candidates_stop_at_the_last_port() {
+        let config = DesktopConfig {
+            sidecar_port: u16::MAX - 1,
+            ..DesktopConfig::default()
+        };
+        assert_eq!(
+            config.sidecar_port_candidates(&[]),
+            vec![u16::MAX - 1, u16::MAX]
+        );
+    }
+}
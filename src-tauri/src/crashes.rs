@@ -0,0 +1,203 @@
+//! Reports of sidecar crashes, kept so "the backend just disappeared" can be looked into.
+//!
+//! When the sidecar exits on its own with a code other than 0, a [`CrashReport`] with the
+//! exit code, uptime, versions and the last lines it printed is written as JSON to
+//! [`CRASHES_DIR`] in the data directory, and `backend-crashed` tells the UI its id. The
+//! watchdog writes one too before it kills a sidecar that stopped answering. Only the newest
+//! [`KEEP_REPORTS`] reports are kept.
+
+use crate::backend_log::{self, BackendLogLine};
+use crate::persist;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tracing::{error, warn};
+
+/// Directory in the data directory the reports are written to
+pub const CRASHES_DIR: &str = "crashes";
+
+/// Reports kept; older ones are deleted when a new one is written
+pub const KEEP_REPORTS: usize = 20;
+
+/// Lines of sidecar output a report includes
+pub const OUTPUT_LINES: usize = 200;
+
+/// What happened to the sidecar
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashCause {
+    /// It exited on its own
+    #[default]
+    Exited,
+    /// It was still running but stopped answering, and the watchdog killed it
+    Unresponsive,
+}
+
+/// Everything known about one crash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub id: String,
+    /// Missing from reports written before there was more than one cause
+    #[serde(default)]
+    pub cause: CrashCause,
+    /// None when the process was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Unix milliseconds of the exit
+    pub at_ms: u64,
+    pub uptime_secs: u64,
+    /// Sidecar generation that crashed
+    pub generation: u64,
+    pub app_version: String,
+    /// What the sidecar's banner said, if it got that far
+    pub backend_version: Option<String>,
+    /// Its last lines of output, oldest first
+    pub output: Vec<BackendLogLine>,
+}
+
+/// A report without its output, for listing
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashSummary {
+    pub id: String,
+    pub cause: CrashCause,
+    pub exit_code: Option<i32>,
+    pub at_ms: u64,
+    pub uptime_secs: u64,
+    pub backend_version: Option<String>,
+}
+
+/// Payload of `backend-crashed`
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendCrashed {
+    pub report_id: String,
+    pub exit_code: Option<i32>,
+}
+
+pub fn dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(CRASHES_DIR)
+}
+
+/// Id of a report written at `at_ms` for `generation`; sorts by time
+pub fn report_id(at_ms: u64, generation: u64) -> String {
+    format!("{:013}-{}", at_ms, generation)
+}
+
+fn report_path(dir: &Path, id: &str) -> Result<PathBuf, String> {
+    // Ids come from the UI; keep them from naming anything outside the directory
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit() || c == '-') {
+        return Err(format!("No crash report {:?}", id));
+    }
+    Ok(dir.join(format!("{}.json", id)))
+}
+
+/// Write `report` to `dir` and delete all but the newest [`KEEP_REPORTS`]
+pub fn write(dir: &Path, report: &CrashReport) -> Result<(), String> {
+    let path = report_path(dir, &report.id)?;
+    let bytes = serde_json::to_vec_pretty(report).map_err(|e| e.to_string())?;
+    persist::atomic_write(&path, &bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    for id in ids(dir).into_iter().skip(KEEP_REPORTS) {
+        if let Ok(path) = report_path(dir, &id) {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ids of the reports in `dir`, newest first
+fn ids(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut ids: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let id = name.strip_suffix(".json")?;
+            report_path(dir, id).is_ok().then(|| id.to_string())
+        })
+        .collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    ids
+}
+
+pub fn read(dir: &Path, id: &str) -> Result<CrashReport, String> {
+    let path = report_path(dir, id)?;
+    let bytes = std::fs::read(&path).map_err(|_| format!("No crash report {:?}", id))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Crash report {} is damaged: {}", id, e))
+}
+
+/// Summaries of the reports in `dir`, newest first; damaged ones are skipped
+pub fn list(dir: &Path) -> Vec<CrashSummary> {
+    ids(dir)
+        .iter()
+        .filter_map(|id| read(dir, id).ok())
+        .map(|report| CrashSummary {
+            id: report.id,
+            cause: report.cause,
+            exit_code: report.exit_code,
+            at_ms: report.at_ms,
+            uptime_secs: report.uptime_secs,
+            backend_version: report.backend_version,
+        })
+        .collect()
+}
+
+/// Write a report for sidecar `generation`, which exited with `exit_code` after `uptime`, and
+/// emit `backend-crashed` with its id
+pub fn record(app: &tauri::AppHandle, generation: u64, uptime: Duration, exit_code: Option<i32>) {
+    let Some(report_id) = save(app, generation, uptime, exit_code, CrashCause::Exited) else {
+        return;
+    };
+    crate::events::emit(
+        app,
+        "backend-crashed",
+        BackendCrashed {
+            report_id,
+            exit_code,
+        },
+    );
+}
+
+/// Write a report for sidecar `generation` and return its id; None, logged, if that failed
+pub fn save(
+    app: &tauri::AppHandle,
+    generation: u64,
+    uptime: Duration,
+    exit_code: Option<i32>,
+    cause: CrashCause,
+) -> Option<String> {
+    let state = app.state::<AppState>();
+    let at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut output: Vec<BackendLogLine> = state
+        .backend_log
+        .tail(backend_log::RECENT_LINES)
+        .into_iter()
+        .filter(|line| line.generation == generation)
+        .collect();
+    output.drain(..output.len().saturating_sub(OUTPUT_LINES));
+    let report = CrashReport {
+        id: report_id(at_ms, generation),
+        cause,
+        exit_code,
+        at_ms,
+        uptime_secs: uptime.as_secs(),
+        generation,
+        app_version: app.package_info().version.to_string(),
+        backend_version: state.banner.lock().version.clone(),
+        output,
+    };
+    match write(&dir(&state.paths().data_dir), &report) {
+        Ok(()) => Some(report.id),
+        Err(e) => {
+            error!("Failed to save the crash report: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Optional desktop features, as seen by the web UI.
+//!
+//! The same web UI is also served to plain browsers, so it needs to know which desktop
+//! features exist in this shell without probing `invoke`. Every optional feature is listed
+//! once in [`FEATURES`] with the commands behind it; its availability follows from the
+//! platform, the build, and policy. Policy is the viewer-mode gate: a feature none of whose
+//! commands are in [`VIEWER_COMMANDS`](crate::ownership::VIEWER_COMMANDS) is unavailable while
+//! another session owns the backend, so the matrix and the command guard can't disagree.
+//!
+//! The matrix is returned by `get_desktop_capabilities` and also assigned to
+//! `window.__ZEROBYTE_DESKTOP__` whenever a page loads in the main window (and again when the
+//! connection mode changes), so the UI can read it synchronously.
+
+use crate::ownership::{self, ConnectionMode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Bumped when the payload shape changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Global the payload is assigned to in the webview
+pub const WINDOW_GLOBAL: &str = "__ZEROBYTE_DESKTOP__";
+
+/// Why a feature isn't available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unavailable {
+    /// Not supported on this operating system
+    Platform,
+    /// Not compiled into this build
+    Build,
+    /// Another session owns the backend and this feature changes it
+    ReadOnly,
+}
+
+/// One optional feature and what it needs
+pub struct FeatureSpec {
+    pub name: &'static str,
+    /// Commands the UI calls for this feature
+    pub commands: &'static [&'static str],
+    /// Whether this platform supports it
+    pub platform: bool,
+    /// Whether this build includes it
+    pub built: bool,
+}
+
+const DESKTOP: bool = cfg!(not(any(target_os = "android", target_os = "ios")));
+
+/// Every optional desktop feature
+pub const FEATURES: &[FeatureSpec] = &[
+    FeatureSpec {
+        name: "native_pickers",
+        commands: &[],
+        platform: DESKTOP,
+        // Only message dialogs are used; folder access is granted, not picked
+        built: false,
+    },
+    FeatureSpec {
+        name: "notifications",
+        commands: &[
+            "mute_plan_notifications",
+            "unmute_plan_notifications",
+            "list_notification_mutes",
+        ],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "service_management",
+        commands: &[
+            "install_service",
+            "uninstall_service",
+            "start_service",
+            "stop_service",
+            "get_service_capabilities",
+        ],
+        platform: cfg!(target_os = "windows"),
+        built: true,
+    },
+    FeatureSpec {
+        name: "global_shortcuts",
+        commands: &[],
+        platform: DESKTOP,
+        built: true,
+    },
+    FeatureSpec {
+        name: "keyring",
+        commands: &["prompt_repository_passphrase", "set_remember_passphrase"],
+        platform: DESKTOP,
+        built: true,
+    },
+    FeatureSpec {
+        name: "controlled_folder_access",
+        commands: &["get_controlled_folder_access", "request_cfa_allowlist"],
+        platform: cfg!(target_os = "windows"),
+        built: true,
+    },
+    FeatureSpec {
+        name: "vss",
+        commands: &[],
+        platform: cfg!(target_os = "windows"),
+        built: false,
+    },
+    FeatureSpec {
+        name: "deep_links",
+        commands: &[],
+        platform: DESKTOP,
+        built: false,
+    },
+    FeatureSpec {
+        name: "restore_sessions",
+        commands: &["start_restore_session", "list_restore_sessions", "end_restore_session"],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "file_watchers",
+        commands: &["watch_paths_for_plan", "unwatch_plan", "list_active_watchers"],
+        platform: DESKTOP,
+        built: true,
+    },
+    FeatureSpec {
+        name: "backend_sandbox",
+        commands: &["set_backend_sandbox"],
+        platform: cfg!(target_os = "linux"),
+        built: true,
+    },
+    FeatureSpec {
+        name: "scratch_storage",
+        commands: &["get_scratch_usage", "clear_scratch"],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "retention",
+        commands: &[
+            "preview_retention_cleanup",
+            "run_retention_cleanup",
+            "set_retention_rules",
+        ],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "metrics",
+        commands: &["get_metrics_snapshot"],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "bandwidth_profiles",
+        commands: &[
+            "get_bandwidth_profiles",
+            "set_active_bandwidth_profile",
+            "set_bandwidth_schedule",
+        ],
+        platform: true,
+        built: true,
+    },
+    FeatureSpec {
+        name: "pairing",
+        commands: &["pair_with_backend"],
+        // Tokens live in the OS keyring
+        platform: DESKTOP,
+        built: true,
+    },
+    FeatureSpec {
+        name: "devtools",
+        commands: &["get_devtools_status", "toggle_devtools"],
+        platform: true,
+        built: crate::devtools::COMPILED,
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureAvailability {
+    pub available: bool,
+    pub reason: Option<Unavailable>,
+    pub commands: &'static [&'static str],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DesktopCapabilities {
+    pub version: u32,
+    pub platform: &'static str,
+    pub features: BTreeMap<&'static str, FeatureAvailability>,
+}
+
+impl FeatureSpec {
+    fn availability(&self, mode: &ConnectionMode) -> FeatureAvailability {
+        let read_only = mode.is_viewer()
+            && !self.commands.is_empty()
+            && self
+                .commands
+                .iter()
+                .all(|command| !ownership::allowed_for_viewer(command));
+        let reason = if !self.platform {
+            Some(Unavailable::Platform)
+        } else if !self.built {
+            Some(Unavailable::Build)
+        } else if read_only {
+            Some(Unavailable::ReadOnly)
+        } else {
+            None
+        };
+        FeatureAvailability {
+            available: reason.is_none(),
+            reason,
+            commands: self.commands,
+        }
+    }
+}
+
+/// The matrix for the current connection mode
+pub fn capabilities(mode: &ConnectionMode) -> DesktopCapabilities {
+    DesktopCapabilities {
+        version: SCHEMA_VERSION,
+        platform: std::env::consts::OS,
+        features: FEATURES
+            .iter()
+            .map(|feature| (feature.name, feature.availability(mode)))
+            .collect(),
+    }
+}
+
+/// Script assigning the matrix to the window global
+pub fn init_script(mode: &ConnectionMode) -> String {
+    let payload = serde_json::to_string(&capabilities(mode)).unwrap_or_else(|_| "null".into());
+    format!("window.{} = Object.freeze({});", WINDOW_GLOBAL, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The payload for this platform in both connection modes, as shipped to the UI
+    #[cfg(target_os = "linux")]
+    const SNAPSHOT: &str = include_str!("../testdata/desktop-capabilities.linux.json");
+    #[cfg(target_os = "windows")]
+    const SNAPSHOT: &str = include_str!("../testdata/desktop-capabilities.windows.json");
+    #[cfg(target_os = "macos")]
+    const SNAPSHOT: &str = include_str!("../testdata/desktop-capabilities.macos.json");
+
+    fn viewer() -> ConnectionMode {
+        ConnectionMode::Viewer {
+            owner: "bob".to_string(),
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+    #[test]
+    fn payload_matches_the_platform_snapshot() {
+        let mut expected: serde_json::Value = serde_json::from_str(SNAPSHOT).unwrap();
+        // Snapshots are taken from debug builds, which always have devtools
+        if !crate::devtools::COMPILED {
+            for mode in ["owner", "viewer"] {
+                let devtools = &mut expected[mode]["features"]["devtools"];
+                devtools["available"] = false.into();
+                devtools["reason"] = "build".into();
+            }
+        }
+        let actual = serde_json::json!({
+            "owner": capabilities(&ConnectionMode::Owner),
+            "viewer": capabilities(&viewer()),
+        });
+        assert_eq!(
+            actual,
+            expected,
+            "{}",
+            serde_json::to_string_pretty(&actual).unwrap()
+        );
+    }
+
+    #[test]
+    fn every_feature_is_listed_once() {
+        let mut names: Vec<_> = FEATURES.iter().map(|feature| feature.name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), FEATURES.len());
+        assert_eq!(
+            capabilities(&ConnectionMode::Owner).features.len(),
+            FEATURES.len()
+        );
+    }
+
+    #[test]
+    fn viewers_lose_only_features_they_cannot_call() {
+        let owner = capabilities(&ConnectionMode::Owner);
+        let viewer = capabilities(&viewer());
+        for feature in FEATURES {
+            let as_viewer = &viewer.features[feature.name];
+            if as_viewer.reason == Some(Unavailable::ReadOnly) {
+                assert!(owner.features[feature.name].available, "{}", feature.name);
+                assert!(feature
+                    .commands
+                    .iter()
+                    .all(|command| !ownership::allowed_for_viewer(command)));
+            } else {
+                assert_eq!(as_viewer, &owner.features[feature.name], "{}", feature.name);
+            }
+        }
+    }
+
+    #[test]
+    fn init_script_freezes_the_payload() {
+        let script = init_script(&ConnectionMode::Owner);
+        let payload = script
+            .strip_prefix("window.__ZEROBYTE_DESKTOP__ = Object.freeze(")
+            .and_then(|rest| rest.strip_suffix(");"))
+            .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(payload["version"], SCHEMA_VERSION);
+        assert_eq!(payload["platform"], std::env::consts::OS);
+    }
+}
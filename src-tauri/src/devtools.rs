@@ -0,0 +1,192 @@
+//! Runtime access to the webview devtools for support sessions.
+//!
+//! Debug builds always have devtools. Release builds only have them when built with the
+//! `devtools` cargo feature, and even then they stay closed unless the app was started with
+//! `--support` or `ZEROBYTE_ALLOW_DEVTOOLS=1`. Setting `ZEROBYTE_ALLOW_DEVTOOLS=0` (e.g. via
+//! machine policy) forbids them outright.
+
+use serde::Serialize;
+
+/// Environment variable that allows (`1`/`true`) or forbids (`0`/`false`) devtools
+pub const ALLOW_DEVTOOLS_ENV: &str = "ZEROBYTE_ALLOW_DEVTOOLS";
+
+/// Command-line flag that enables support mode
+pub const SUPPORT_FLAG: &str = "--support";
+
+/// Whether this build carries the devtools implementation
+pub const COMPILED: bool = cfg!(any(debug_assertions, feature = "devtools"));
+
+/// Whether devtools may be opened, and why not if they can't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DevtoolsPermission {
+    Allowed,
+    /// Built without devtools support
+    NotCompiled,
+    /// Explicitly forbidden by ZEROBYTE_ALLOW_DEVTOOLS
+    Forbidden,
+    /// Neither support mode nor ZEROBYTE_ALLOW_DEVTOOLS enabled them
+    NotEnabled,
+}
+
+impl DevtoolsPermission {
+    pub fn allowed(self) -> bool {
+        self == DevtoolsPermission::Allowed
+    }
+}
+
+/// Decide whether devtools may be opened. An explicit policy value wins over support mode,
+/// and debug builds are allowed unless forbidden.
+pub fn decide(
+    compiled: bool,
+    debug_build: bool,
+    policy: Option<&str>,
+    support_mode: bool,
+) -> DevtoolsPermission {
+    if !compiled {
+        return DevtoolsPermission::NotCompiled;
+    }
+
+    let policy = policy.map(|value| value.trim().to_ascii_lowercase());
+    match policy.as_deref() {
+        Some("0" | "false" | "no" | "off") => DevtoolsPermission::Forbidden,
+        Some("1" | "true" | "yes" | "on") => DevtoolsPermission::Allowed,
+        _ if debug_build || support_mode => DevtoolsPermission::Allowed,
+        _ => DevtoolsPermission::NotEnabled,
+    }
+}
+
+/// Permission for the running process
+pub fn permission() -> DevtoolsPermission {
+    decide(
+        COMPILED,
+        cfg!(debug_assertions),
+        std::env::var(ALLOW_DEVTOOLS_ENV).ok().as_deref(),
+        crate::args::current().support,
+    )
+}
+
+/// Devtools state reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct DevtoolsStatus {
+    pub compiled: bool,
+    pub permission: DevtoolsPermission,
+    /// Whether devtools are open for the calling window
+    pub open: bool,
+}
+
+/// Open devtools for `window` if they're closed, close them if open.
+/// Returns whether they're open afterwards.
+pub fn toggle(window: &tauri::WebviewWindow) -> Result<bool, String> {
+    let permission = permission();
+    if !permission.allowed() {
+        return Err(format!("Devtools are not available: {:?}", permission));
+    }
+
+    #[cfg(any(debug_assertions, feature = "devtools"))]
+    {
+        let open = !window.is_devtools_open();
+        if open {
+            window.open_devtools();
+        } else {
+            window.close_devtools();
+        }
+
+        let user = std::env::var("USERNAME")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or_default();
+        tracing::info!(
+            target: "zerobyte::audit",
+            "Devtools {} for window '{}' by {}",
+            if open { "opened" } else { "closed" },
+            window.label(),
+            user
+        );
+        Ok(open)
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "devtools")))]
+    {
+        let _ = window;
+        Err("Devtools are not available in this build".to_string())
+    }
+}
+
+/// Whether devtools are currently open for `window`
+pub fn is_open(window: &tauri::WebviewWindow) -> bool {
+    #[cfg(any(debug_assertions, feature = "devtools"))]
+    {
+        window.is_devtools_open()
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "devtools")))]
+    {
+        let _ = window;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DevtoolsPermission::*;
+
+    /// (compiled, debug build, policy, support mode, expected)
+    type Case = (bool, bool, Option<&'static str>, bool, DevtoolsPermission);
+
+    #[test]
+    fn permission_gate() {
+        let cases: &[Case] = &[
+            // Nothing overrides a build without devtools
+            (false, true, Some("1"), true, NotCompiled),
+            (false, false, None, false, NotCompiled),
+            // Debug builds are open unless forbidden
+            (true, true, None, false, Allowed),
+            (true, true, Some("0"), false, Forbidden),
+            (true, true, Some("0"), true, Forbidden),
+            // Release builds need support mode or the policy
+            (true, false, None, false, NotEnabled),
+            (true, false, None, true, Allowed),
+            (true, false, Some("1"), false, Allowed),
+            (true, false, Some("false"), true, Forbidden),
+            // Values are trimmed and case-insensitive
+            (true, false, Some(" YES "), false, Allowed),
+            (true, false, Some("Off"), true, Forbidden),
+            // Anything else is no opinion
+            (true, false, Some(""), false, NotEnabled),
+            (true, false, Some("maybe"), true, Allowed),
+            (true, false, Some("2"), false, NotEnabled),
+        ];
+        for &(compiled, debug_build, policy, support_mode, expected) in cases {
+            assert_eq!(
+                decide(compiled, debug_build, policy, support_mode),
+                expected,
+                "compiled={} debug={} policy={:?} support={}",
+                compiled,
+                debug_build,
+                policy,
+                support_mode
+            );
+        }
+    }
+
+    #[test]
+    fn only_allowed_is_allowed() {
+        assert!(Allowed.allowed());
+        for permission in [NotCompiled, Forbidden, NotEnabled] {
+            assert!(!permission.allowed());
+        }
+    }
+
+    #[test]
+    fn permission_serializes_for_the_ui() {
+        assert_eq!(
+            serde_json::to_string(&NotCompiled).unwrap(),
+            r#""not_compiled""#
+        );
+        assert_eq!(
+            serde_json::to_string(&NotEnabled).unwrap(),
+            r#""not_enabled""#
+        );
+    }
+}
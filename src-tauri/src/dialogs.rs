@@ -0,0 +1,320 @@
+//! Native confirmation before destructive desktop actions.
+//!
+//! Confirmations go through the dialog plugin rather than the webview, so they work while the
+//! backend (and with it the web UI) is down, and the OS dialog is fully keyboard operable.
+//! Buttons name the action ("Uninstall", "Quit anyway"), never a bare OK. The most destructive
+//! prompts also ask the user to type a name in the UI first; [`confirm`] checks it before the
+//! dialog is shown. Prompt text lives in [`Prompt`] only, so it can be translated in one place.
+//!
+//! Commands take a `skip_confirmation` flag for scripted use. It is only honored when the app
+//! was started for automation (`ZEROBYTE_AUTOMATION=1`); from a normal session the dialog is
+//! shown anyway. Every outcome is written to the `zerobyte::audit` log.
+
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tracing::{info, warn};
+
+/// Environment variable marking an automation session
+pub const AUTOMATION_ENV: &str = "ZEROBYTE_AUTOMATION";
+
+/// A destructive action to confirm
+#[derive(Debug, Clone)]
+pub enum Prompt {
+    UninstallService,
+    /// Delete a migrated legacy install
+    CleanupLegacyInstall { root: String },
+    QuitDuringBackup { running: usize },
+    GrantPathAccess { account: String, path: String },
+    CfaAllowlist { executables: Vec<String> },
+}
+
+impl Prompt {
+    /// Short name for the audit log
+    pub fn action(&self) -> &'static str {
+        match self {
+            Prompt::UninstallService => "uninstall_service",
+            Prompt::CleanupLegacyInstall { .. } => "cleanup_legacy_install",
+            Prompt::QuitDuringBackup { .. } => "quit_during_backup",
+            Prompt::GrantPathAccess { .. } => "grant_path_access",
+            Prompt::CfaAllowlist { .. } => "request_cfa_allowlist",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Prompt::UninstallService => "Uninstall the Windows Service",
+            Prompt::CleanupLegacyInstall { .. } => "Delete the old installation",
+            Prompt::QuitDuringBackup { .. } => "Backups are running",
+            Prompt::GrantPathAccess { .. } => "Grant folder access",
+            Prompt::CfaAllowlist { .. } => "Controlled Folder Access",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Prompt::UninstallService => concat!(
+                "The service will be stopped and removed. Scheduled backups will only run ",
+                "while the app is open.\n\nWindows will ask for administrator approval."
+            )
+            .to_string(),
+            Prompt::CleanupLegacyInstall { root } => format!(
+                "The old installation at\n\n{}\n\nwill be deleted. This can't be undone.",
+                root
+            ),
+            Prompt::QuitDuringBackup { running } => format!(
+                "{} backup(s) are still running. Quitting stops them before they finish.",
+                running
+            ),
+            Prompt::GrantPathAccess { account, path } => format!(
+                "Grant {} read access to:\n\n{}\n\nWindows will ask for administrator approval.",
+                account, path
+            ),
+            Prompt::CfaAllowlist { executables } => format!(
+                concat!(
+                    "Allow the backup engine to write to folders protected by Controlled ",
+                    "Folder Access?\n\n{}\n\nWindows will ask for administrator approval.",
+                ),
+                executables.join("\n")
+            ),
+        }
+    }
+
+    /// Label of the button that goes ahead
+    pub fn confirm_label(&self) -> &'static str {
+        match self {
+            Prompt::UninstallService => "Uninstall",
+            Prompt::CleanupLegacyInstall { .. } => "Delete",
+            Prompt::QuitDuringBackup { .. } => "Quit anyway",
+            Prompt::GrantPathAccess { .. } => "Grant access",
+            Prompt::CfaAllowlist { .. } => "Allow",
+        }
+    }
+
+    pub fn cancel_label(&self) -> &'static str {
+        match self {
+            Prompt::QuitDuringBackup { .. } => "Keep running",
+            _ => "Cancel",
+        }
+    }
+
+    /// What the user must type to confirm, for prompts that need it
+    pub fn typed_name(&self) -> Option<&str> {
+        match self {
+            Prompt::CleanupLegacyInstall { root } => Some(
+                std::path::Path::new(root)
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or(root),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Whether this process was started for automation
+pub fn automation() -> bool {
+    std::env::var(AUTOMATION_ENV).is_ok_and(|value| value == "1")
+}
+
+/// Whether `typed` confirms `expected`; surrounding whitespace is ignored, case is not
+pub fn typed_matches(expected: &str, typed: &str) -> bool {
+    let expected = expected.trim();
+    !expected.is_empty() && typed.trim() == expected
+}
+
+/// Whether a `skip_confirmation` request may be honored
+pub fn may_skip(skip_confirmation: bool, automation: bool) -> bool {
+    skip_confirmation && automation
+}
+
+/// Check the typed name for prompts that need one
+fn check_typed(prompt: &Prompt, typed: Option<&str>) -> Result<(), String> {
+    match prompt.typed_name() {
+        Some(expected) if !typed.is_some_and(|typed| typed_matches(expected, typed)) => {
+            Err(format!("Type \"{}\" to confirm", expected))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn audit(prompt: &Prompt, outcome: &str) {
+    let user = std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default();
+    info!(
+        target: "zerobyte::audit",
+        "Confirmation for {} {} by {}",
+        prompt.action(),
+        outcome,
+        user
+    );
+}
+
+/// Ask the user to confirm `prompt`. Errors if they decline, or if the prompt needs a typed
+/// name and `typed` doesn't match it.
+pub async fn confirm(
+    app: &tauri::AppHandle,
+    prompt: Prompt,
+    skip_confirmation: bool,
+    typed: Option<&str>,
+) -> Result<(), String> {
+    if may_skip(skip_confirmation, automation()) {
+        audit(&prompt, "skipped (automation)");
+        return Ok(());
+    }
+    if skip_confirmation {
+        warn!(
+            "Ignoring skip_confirmation for {} outside automation",
+            prompt.action()
+        );
+    }
+
+    if let Err(e) = check_typed(&prompt, typed) {
+        audit(&prompt, "refused (typed name didn't match)");
+        return Err(e);
+    }
+
+    let dialog = app
+        .dialog()
+        .message(prompt.message())
+        .title(prompt.title())
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            prompt.confirm_label().to_string(),
+            prompt.cancel_label().to_string(),
+        ));
+    // Blocks until answered, so keep it off the async runtime
+    let confirmed = tauri::async_runtime::spawn_blocking(move || dialog.blocking_show())
+        .await
+        .map_err(|e| format!("Failed to show confirmation: {}", e))?;
+
+    if confirmed {
+        audit(&prompt, "confirmed");
+        Ok(())
+    } else {
+        audit(&prompt, "declined");
+        Err("Cancelled by user".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cleanup(root: &str) -> Prompt {
+        Prompt::CleanupLegacyInstall { root: root.into() }
+    }
+
+    fn prompts() -> Vec<Prompt> {
+        vec![
+            Prompt::UninstallService,
+            cleanup("/opt/zerobyte"),
+            Prompt::QuitDuringBackup { running: 2 },
+            Prompt::GrantPathAccess {
+                account: "NT SERVICE\\ZerobyteService".into(),
+                path: "D:\\Photos".into(),
+            },
+            Prompt::CfaAllowlist {
+                executables: vec!["zerobyte-server.exe".into(), "zerobyte-service.exe".into()],
+            },
+        ]
+    }
+
+    #[test]
+    fn typed_names_match_exactly_up_to_surrounding_whitespace() {
+        let cases = [
+            ("zerobyte", "zerobyte", true),
+            ("zerobyte", "  zerobyte\n", true),
+            ("zerobyte", "Zerobyte", false),
+            ("zerobyte", "zero byte", false),
+            ("zerobyte", "zerobyte2", false),
+            ("zerobyte", "", false),
+            ("", "", false),
+            ("  ", "", false),
+        ];
+        for (expected, typed, matches) in cases {
+            assert_eq!(
+                typed_matches(expected, typed),
+                matches,
+                "{:?} typed as {:?}",
+                expected,
+                typed
+            );
+        }
+    }
+
+    #[test]
+    fn only_cleanup_needs_a_typed_name() {
+        for prompt in prompts() {
+            let name = prompt.typed_name().map(str::to_string);
+            match prompt {
+                Prompt::CleanupLegacyInstall { .. } => {
+                    assert_eq!(name.as_deref(), Some("zerobyte"))
+                }
+                _ => assert_eq!(name, None, "{}", prompt.action()),
+            }
+        }
+        // Without a folder name the whole root has to be typed
+        assert_eq!(cleanup("/").typed_name(), Some("/"));
+    }
+
+    #[test]
+    fn cleanup_is_refused_until_the_name_is_typed() {
+        let prompt = cleanup("/opt/zerobyte");
+        let refused = Err("Type \"zerobyte\" to confirm".to_string());
+        assert_eq!(check_typed(&prompt, None), refused);
+        assert_eq!(check_typed(&prompt, Some("opt")), refused);
+        assert_eq!(check_typed(&prompt, Some("/opt/zerobyte")), refused);
+        assert_eq!(check_typed(&prompt, Some(" zerobyte ")), Ok(()));
+
+        // Prompts without a typed name ignore whatever was typed
+        assert_eq!(check_typed(&Prompt::UninstallService, None), Ok(()));
+        assert_eq!(
+            check_typed(&Prompt::UninstallService, Some("nonsense")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn skipping_needs_both_the_flag_and_automation() {
+        let cases = [
+            (false, false, false),
+            (true, false, false),
+            (false, true, false),
+            (true, true, true),
+        ];
+        for (skip_confirmation, automation, skips) in cases {
+            assert_eq!(
+                may_skip(skip_confirmation, automation),
+                skips,
+                "skip {} automation {}",
+                skip_confirmation,
+                automation
+            );
+        }
+    }
+
+    #[test]
+    fn buttons_name_the_action() {
+        for prompt in prompts() {
+            for label in [prompt.confirm_label(), prompt.cancel_label()] {
+                assert!(!label.eq_ignore_ascii_case("ok"), "{}", prompt.action());
+                assert!(!label.is_empty());
+            }
+            assert!(!prompt.title().is_empty());
+        }
+        assert_eq!(
+            Prompt::QuitDuringBackup { running: 1 }.cancel_label(),
+            "Keep running"
+        );
+    }
+
+    #[test]
+    fn messages_include_the_details() {
+        let messages: Vec<String> = prompts().iter().map(Prompt::message).collect();
+        assert!(messages[1].contains("\n\n/opt/zerobyte\n\n"));
+        assert!(messages[2].starts_with("2 backup(s)"));
+        assert!(messages[3].contains("NT SERVICE\\ZerobyteService"));
+        assert!(messages[3].contains("D:\\Photos"));
+        assert!(messages[4].contains("zerobyte-server.exe\nzerobyte-service.exe"));
+    }
+}
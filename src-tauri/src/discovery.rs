@@ -0,0 +1,454 @@
+//! Opt-in discovery of other zerobyte servers on the local network.
+//!
+//! Candidates come from mDNS/DNS-SD (`_zerobyte._tcp`) and, as a fallback, a capped probe of
+//! the default port across the local /24. Every candidate is verified against its healthcheck
+//! before it's offered, and results are deduplicated by address. Discovery never runs on its
+//! own: it's started by the remote backend settings page and can be cancelled.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// DNS-SD service type advertised by zerobyte servers
+pub const SERVICE_TYPE: &str = "_zerobyte._tcp.local.";
+
+/// Port probed on each host of the local subnet
+pub const DEFAULT_PORT: u16 = 4096;
+
+/// Probes in flight at once during the subnet scan
+const PROBE_CONCURRENCY: usize = 16;
+
+/// Timeout for a single candidate healthcheck
+const PROBE_TIMEOUT: Duration = Duration::from_millis(800);
+
+/// Longest discovery the UI may ask for
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a candidate was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoverySource {
+    Mdns,
+    SubnetProbe,
+}
+
+/// An unverified address that might be a zerobyte server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub name: Option<String>,
+    pub host: IpAddr,
+    pub port: u16,
+    pub tls: bool,
+    pub source: DiscoverySource,
+}
+
+impl Candidate {
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        match self.host {
+            IpAddr::V6(host) => format!("{}://[{}]:{}", scheme, host, self.port),
+            IpAddr::V4(host) => format!("{}://{}:{}", scheme, host, self.port),
+        }
+    }
+}
+
+/// A verified server, as shown on the remote backend settings page
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiscoveredBackend {
+    pub name: String,
+    pub url: String,
+    pub version: Option<String>,
+    pub tls: bool,
+    pub source: DiscoverySource,
+}
+
+#[derive(Deserialize)]
+struct HealthIdentity {
+    status: String,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+/// Check that a healthcheck body comes from a zerobyte server. Returns its version if it
+/// reports one, or None if the body isn't a zerobyte healthcheck at all.
+pub fn parse_identity(body: &str) -> Option<Option<String>> {
+    let identity: HealthIdentity = serde_json::from_str(body).ok()?;
+    matches!(identity.status.as_str(), "ok" | "starting").then_some(identity.version)
+}
+
+/// Turn a verified candidate into a result
+pub fn verified(candidate: &Candidate, version: Option<String>) -> DiscoveredBackend {
+    DiscoveredBackend {
+        name: candidate
+            .name
+            .clone()
+            .unwrap_or_else(|| candidate.host.to_string()),
+        url: candidate.url(),
+        version,
+        tls: candidate.tls,
+        source: candidate.source,
+    }
+}
+
+/// Merge results that point at the same server. An mDNS result beats a probe hit (it has a
+/// real name), and a TLS endpoint beats a plain one.
+pub fn dedupe(results: Vec<DiscoveredBackend>) -> Vec<DiscoveredBackend> {
+    let mut by_address: HashMap<String, DiscoveredBackend> = HashMap::new();
+
+    for result in results {
+        let address = result
+            .url
+            .split_once("://")
+            .map(|(_, rest)| rest.to_string())
+            .unwrap_or_else(|| result.url.clone());
+
+        let rank = |r: &DiscoveredBackend| (r.source == DiscoverySource::Mdns, r.tls);
+        match by_address.get(&address) {
+            Some(existing) if rank(existing) >= rank(&result) => {}
+            _ => {
+                by_address.insert(address, result);
+            }
+        }
+    }
+
+    let mut results: Vec<_> = by_address.into_values().collect();
+    results.sort_by(|a, b| a.name.cmp(&b.name).then(a.url.cmp(&b.url)));
+    results
+}
+
+/// Cancellation flag for the discovery in progress
+#[derive(Default)]
+pub struct DiscoveryControl {
+    running: AtomicBool,
+    cancel: Arc<AtomicBool>,
+}
+
+impl DiscoveryControl {
+    /// Mark a discovery as started. Returns the cancel flag, or None if one is already running.
+    pub fn begin(&self) -> Option<Arc<AtomicBool>> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        self.cancel.store(false, Ordering::SeqCst);
+        Some(self.cancel.clone())
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The address this machine uses to reach the LAN. Connecting a UDP socket sends nothing,
+/// it only picks the outgoing interface.
+pub fn local_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if ip.is_private() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Every other host of the /24 containing `local`, on the default port
+pub fn subnet_candidates(local: Ipv4Addr) -> Vec<Candidate> {
+    let [a, b, c, own] = local.octets();
+    (1..=254u8)
+        .filter(|host| *host != own)
+        .map(|host| Candidate {
+            name: None,
+            host: IpAddr::V4(Ipv4Addr::new(a, b, c, host)),
+            port: DEFAULT_PORT,
+            tls: false,
+            source: DiscoverySource::SubnetProbe,
+        })
+        .collect()
+}
+
+/// Browse DNS-SD for `timeout` (blocking) and return the resolved candidates
+pub fn browse_mdns(timeout: Duration, cancel: &AtomicBool) -> Result<Vec<Candidate>, String> {
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| format!("Failed to browse {}: {}", SERVICE_TYPE, e))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut candidates = Vec::new();
+    while !cancel.load(Ordering::SeqCst) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let Ok(event) = receiver.recv_timeout(remaining.min(Duration::from_millis(250))) else {
+            continue;
+        };
+        if let ServiceEvent::ServiceResolved(info) = event {
+            let name = info
+                .get_fullname()
+                .trim_end_matches(SERVICE_TYPE)
+                .trim_end_matches('.')
+                .to_string();
+            let tls = info
+                .get_property_val_str("tls")
+                .is_some_and(|value| value == "1" || value == "true");
+            for host in info.get_addresses() {
+                candidates.push(Candidate {
+                    name: Some(name.clone()),
+                    host: *host,
+                    port: info.get_port(),
+                    tls,
+                    source: DiscoverySource::Mdns,
+                });
+            }
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(candidates)
+}
+
+/// Verify candidates against their healthcheck, a bounded number at a time
+pub async fn verify_all(
+    candidates: Vec<Candidate>,
+    cancel: Arc<AtomicBool>,
+) -> Vec<DiscoveredBackend> {
+    let client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        // Servers on the LAN commonly use self-signed certificates
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default();
+    let permits = Arc::new(tokio::sync::Semaphore::new(PROBE_CONCURRENCY));
+
+    let mut tasks = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        let client = client.clone();
+        let permits = permits.clone();
+        let cancel = cancel.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await.ok()?;
+            if cancel.load(Ordering::SeqCst) {
+                return None;
+            }
+            let response = client
+                .get(format!("{}/healthcheck", candidate.url()))
+                .send()
+                .await
+                .ok()?;
+            let body = response.text().await.ok()?;
+            let version = parse_identity(&body)?;
+            Some(verified(&candidate, version))
+        }));
+    }
+
+    let mut results = Vec::new();
+    for task in tasks {
+        if let Ok(Some(result)) = task.await {
+            results.push(result);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn candidate(name: Option<&str>, port: u16, tls: bool, source: DiscoverySource) -> Candidate {
+        Candidate {
+            name: name.map(String::from),
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            tls,
+            source,
+        }
+    }
+
+    fn backend(name: &str, url: &str, tls: bool, source: DiscoverySource) -> DiscoveredBackend {
+        DiscoveredBackend {
+            name: name.to_string(),
+            url: url.to_string(),
+            version: None,
+            tls,
+            source,
+        }
+    }
+
+    /// A local server answering every request with `body`. Returns its port.
+    async fn responder(body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    /// A port nothing listens on
+    async fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn identity_comes_from_a_zerobyte_healthcheck() {
+        let cases = [
+            (r#"{"status":"ok","version":"0.9.1"}"#, Some(Some("0.9.1"))),
+            (r#"{"status":"starting"}"#, Some(None)),
+            (r#"{"status":"down","version":"0.9.1"}"#, None),
+            (r#"{"version":"0.9.1"}"#, None),
+            ("<html>router login</html>", None),
+            ("", None),
+        ];
+        for (body, expected) in cases {
+            let expected = expected.map(|version| version.map(String::from));
+            assert_eq!(parse_identity(body), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn candidate_urls_bracket_ipv6() {
+        let mut c = candidate(None, 4096, false, DiscoverySource::SubnetProbe);
+        assert_eq!(c.url(), "http://127.0.0.1:4096");
+        c.host = "fe80::1".parse().unwrap();
+        c.tls = true;
+        assert_eq!(c.url(), "https://[fe80::1]:4096");
+    }
+
+    #[test]
+    fn subnet_scan_skips_this_host() {
+        let candidates = subnet_candidates(Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(candidates.len(), 253);
+        assert!(candidates
+            .iter()
+            .all(|c| c.port == DEFAULT_PORT && c.source == DiscoverySource::SubnetProbe));
+        let hosts: Vec<_> = candidates.iter().map(|c| c.host.to_string()).collect();
+        assert_eq!(hosts.first().unwrap(), "192.168.1.1");
+        assert_eq!(hosts.last().unwrap(), "192.168.1.254");
+        assert!(!hosts.contains(&"192.168.1.20".to_string()));
+    }
+
+    #[test]
+    fn dedupe_prefers_mdns_then_tls() {
+        use DiscoverySource::*;
+        let results = dedupe(vec![
+            backend("10.0.0.5", "http://10.0.0.5:4096", false, SubnetProbe),
+            backend("nas", "http://10.0.0.5:4096", false, Mdns),
+            backend("10.0.0.5", "http://10.0.0.5:4096", false, SubnetProbe),
+            backend("office", "http://10.0.0.7:4096", false, Mdns),
+            backend("office", "https://10.0.0.7:4096", true, Mdns),
+            backend("10.0.0.9", "http://10.0.0.9:4096", false, SubnetProbe),
+        ]);
+        assert_eq!(
+            results,
+            vec![
+                backend("10.0.0.9", "http://10.0.0.9:4096", false, SubnetProbe),
+                backend("nas", "http://10.0.0.5:4096", false, Mdns),
+                backend("office", "https://10.0.0.7:4096", true, Mdns),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_one_discovery_runs_at_a_time() {
+        let control = DiscoveryControl::default();
+        let cancel = control.begin().unwrap();
+        assert!(control.begin().is_none());
+        control.cancel();
+        assert!(cancel.load(Ordering::SeqCst));
+
+        control.finish();
+        let cancel = control.begin().unwrap();
+        // A new discovery starts uncancelled
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn only_zerobyte_servers_are_verified() {
+        use DiscoverySource::*;
+        let server = responder(r#"{"status":"ok","version":"0.9.1"}"#).await;
+        let starting = responder(r#"{"status":"starting"}"#).await;
+        let other = responder("<html>printer</html>").await;
+        let closed = closed_port().await;
+
+        let mut results = verify_all(
+            vec![
+                candidate(Some("nas"), server, false, Mdns),
+                candidate(None, starting, false, SubnetProbe),
+                candidate(Some("printer"), other, false, Mdns),
+                candidate(None, closed, false, SubnetProbe),
+            ],
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await;
+        results.sort_by_key(|result| result.url.clone());
+
+        let mut expected = vec![
+            DiscoveredBackend {
+                version: Some("0.9.1".to_string()),
+                ..backend("nas", &format!("http://127.0.0.1:{}", server), false, Mdns)
+            },
+            backend(
+                "127.0.0.1",
+                &format!("http://127.0.0.1:{}", starting),
+                false,
+                SubnetProbe,
+            ),
+        ];
+        expected.sort_by_key(|result| result.url.clone());
+        assert_eq!(results, expected);
+    }
+
+    #[tokio::test]
+    async fn duplicate_candidates_verify_to_one_result() {
+        use DiscoverySource::*;
+        let server = responder(r#"{"status":"ok"}"#).await;
+
+        let results = verify_all(
+            vec![
+                candidate(None, server, false, SubnetProbe),
+                candidate(Some("nas"), server, false, Mdns),
+            ],
+            Arc::new(AtomicBool::new(false)),
+        )
+        .await;
+        assert_eq!(results.len(), 2);
+
+        let results = dedupe(results);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "nas");
+        assert_eq!(results[0].source, Mdns);
+    }
+
+    #[tokio::test]
+    async fn cancelled_discovery_verifies_nothing() {
+        let server = responder(r#"{"status":"ok"}"#).await;
+        let results = verify_all(
+            vec![candidate(None, server, false, DiscoverySource::SubnetProbe)],
+            Arc::new(AtomicBool::new(true)),
+        )
+        .await;
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,501 @@
+//! Letting running backups finish before the backend is stopped on purpose.
+//!
+//! Updates, service upgrades and mode switches call [`drain`] before stopping the backend.
+//! It pauses the scheduler with a drain reason so nothing new starts, then polls the running
+//! jobs until they are done, reporting `drain-progress` along the way. If the deadline passes
+//! first, the caller's [`OnTimeout`] decides between giving up on the operation and stopping
+//! anyway. With `Abort`, a job whose ETA already lands past the deadline ends the wait early,
+//! since waiting for it can only end in the same abort.
+//!
+//! Cancelling the operation (or aborting) resumes the scheduler. After a completed drain the
+//! scheduler stays paused; the caller calls [`finish`] once the backend is back.
+
+use crate::capabilities::{self, BackendCapabilities, Feature};
+use crate::http::{self, HttpClient, HttpPolicy};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::Manager;
+// tokio's clock, so a drain can be run on paused time
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Deadline used when settings don't set one
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(10 * 60);
+
+/// How often running jobs are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What to do when jobs are still running at the deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnTimeout {
+    /// Give up on the operation and let the jobs finish
+    Abort,
+    /// Stop the backend anyway
+    ForceStop,
+}
+
+/// A job the backend is running
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunningJob {
+    pub id: String,
+    pub name: String,
+    pub percent: Option<f64>,
+    pub eta_secs: Option<u64>,
+}
+
+/// Payload of the `drain-progress` event
+#[derive(Debug, Clone, Serialize)]
+pub struct DrainProgress {
+    pub operation: String,
+    pub jobs: Vec<RunningJob>,
+    pub remaining_secs: u64,
+    /// Some job is expected to finish after the deadline
+    pub eta_past_deadline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainOutcome {
+    /// No jobs were running, or they all finished
+    Drained,
+    /// The deadline passed and the caller chose to stop anyway
+    Forced { jobs: Vec<RunningJob> },
+    /// The backend can't pause or list jobs, so there was nothing to wait on
+    Unsupported,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DrainError {
+    #[error("The operation was cancelled while waiting for running backups")]
+    Cancelled,
+    #[error("{} backup(s) still running at the deadline", jobs.len())]
+    DeadlineExceeded { jobs: Vec<RunningJob> },
+    #[error("{0}")]
+    Backend(String),
+}
+
+/// The backend calls a drain needs, abstracted so a scripted backend can stand in for it
+// Only used with concrete types inside the crate, so `Send` bounds on the futures don't matter
+#[allow(async_fn_in_trait)]
+pub trait DrainBackend {
+    async fn pause(&self, reason: &str) -> Result<(), String>;
+    async fn resume(&self) -> Result<(), String>;
+    async fn running_jobs(&self) -> Result<Vec<RunningJob>, String>;
+}
+
+/// The connected backend, over HTTP
+pub struct HttpBackend<'a> {
+    pub http: &'a HttpClient,
+    pub port: u16,
+}
+
+impl HttpBackend<'_> {
+    async fn post(&self, path: &str, body: serde_json::Value) -> Result<(), String> {
+        let url = http::local_url(self.port, path);
+        let response = self
+            .http
+            .send(HttpPolicy::INTERACTIVE, |client| client.post(&url).json(&body))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("{} answered HTTP {}", path, response.status()));
+        }
+        Ok(())
+    }
+}
+
+impl DrainBackend for HttpBackend<'_> {
+    async fn pause(&self, reason: &str) -> Result<(), String> {
+        self.post(
+            "/api/v1/system/backups/pause",
+            serde_json::json!({ "reason": reason }),
+        )
+        .await
+    }
+
+    async fn resume(&self) -> Result<(), String> {
+        self.post("/api/v1/system/backups/resume", serde_json::json!({}))
+            .await
+    }
+
+    async fn running_jobs(&self) -> Result<Vec<RunningJob>, String> {
+        let url = http::local_url(self.port, "/api/v1/jobs?status=running");
+        let response = self
+            .http
+            .send(HttpPolicy::INTERACTIVE, |client| client.get(&url))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Listing jobs answered HTTP {}", response.status()));
+        }
+        response.json().await.map_err(|e| e.to_string())
+    }
+}
+
+/// Whether a backend with `capabilities` can be drained at all
+pub fn supported(capabilities: BackendCapabilities) -> bool {
+    [Feature::TrayPause, Feature::JobQueue].iter().all(|feature| {
+        capabilities::availability(*feature, capabilities) == capabilities::Availability::Available
+    })
+}
+
+/// Whether some job's ETA lands after `remaining`
+fn eta_past_deadline(jobs: &[RunningJob], remaining: Duration) -> bool {
+    jobs.iter()
+        .any(|job| job.eta_secs.is_some_and(|eta| eta > remaining.as_secs()))
+}
+
+/// Pause the scheduler and wait for running jobs, up to `deadline`
+pub async fn drain<B: DrainBackend>(
+    backend: &B,
+    operation: &str,
+    deadline: Duration,
+    on_timeout: OnTimeout,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(DrainProgress),
+) -> Result<DrainOutcome, DrainError> {
+    backend
+        .pause(&format!("drain: {}", operation))
+        .await
+        .map_err(DrainError::Backend)?;
+    info!("Draining the backend before {}", operation);
+
+    let result = wait_for_jobs(backend, operation, deadline, on_timeout, cancel, on_progress).await;
+    if result.is_err() {
+        // The backend isn't going down after all; let it schedule again
+        if let Err(e) = backend.resume().await {
+            warn!("Failed to resume the scheduler after an aborted drain: {}", e);
+        }
+    }
+    result
+}
+
+async fn wait_for_jobs<B: DrainBackend>(
+    backend: &B,
+    operation: &str,
+    deadline: Duration,
+    on_timeout: OnTimeout,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(DrainProgress),
+) -> Result<DrainOutcome, DrainError> {
+    let started = Instant::now();
+    loop {
+        let jobs = backend.running_jobs().await.map_err(DrainError::Backend)?;
+        let remaining = deadline.saturating_sub(started.elapsed());
+        let past_deadline = eta_past_deadline(&jobs, remaining);
+        on_progress(DrainProgress {
+            operation: operation.to_string(),
+            jobs: jobs.clone(),
+            remaining_secs: remaining.as_secs(),
+            eta_past_deadline: past_deadline,
+        });
+
+        if jobs.is_empty() {
+            info!("Backend drained after {:?}", started.elapsed());
+            return Ok(DrainOutcome::Drained);
+        }
+        if remaining.is_zero() || (past_deadline && on_timeout == OnTimeout::Abort) {
+            return match on_timeout {
+                OnTimeout::Abort => Err(DrainError::DeadlineExceeded { jobs }),
+                OnTimeout::ForceStop => {
+                    warn!("Drain deadline passed with {} job(s) running", jobs.len());
+                    Ok(DrainOutcome::Forced { jobs })
+                }
+            };
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => return Err(DrainError::Cancelled),
+            _ = tokio::time::sleep(POLL_INTERVAL.min(remaining)) => {}
+        }
+    }
+}
+
+/// The drain in progress, so it can be cancelled from the UI
+#[derive(Default)]
+pub struct DrainState {
+    current: Mutex<Option<CancellationToken>>,
+}
+
+impl DrainState {
+    /// Token for a new drain, replacing (and cancelling) any earlier one
+    pub fn begin(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        if let Some(previous) = self.current.lock().replace(token.clone()) {
+            previous.cancel();
+        }
+        token
+    }
+
+    pub fn end(&self) {
+        self.current.lock().take();
+    }
+
+    /// Cancel the drain in progress; returns whether there was one
+    pub fn cancel(&self) -> bool {
+        match self.current.lock().take() {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Drain the connected backend before `operation`, with the deadline from settings.
+/// Backends that can't pause or list jobs are not waited on.
+pub async fn drain_backend(
+    app: &tauri::AppHandle,
+    operation: &str,
+    on_timeout: OnTimeout,
+) -> Result<DrainOutcome, DrainError> {
+    let state = app.state::<crate::AppState>();
+    if !supported(state.capabilities()) {
+        info!("Backend can't be drained, continuing with {}", operation);
+        return Ok(DrainOutcome::Unsupported);
+    }
+    let deadline = app
+        .state::<crate::settings::SettingsStore>()
+        .get()
+        .drain_deadline_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DEADLINE);
+
+    let backend = HttpBackend {
+        http: &state.http,
+        port: state.backend_port.load(std::sync::atomic::Ordering::SeqCst),
+    };
+    let cancel = state.drain.begin();
+    let emitter = app.clone();
+    let result = drain(&backend, operation, deadline, on_timeout, &cancel, |progress| {
+        crate::events::emit(&emitter, "drain-progress", progress)
+    })
+    .await;
+    state.drain.end();
+    result
+}
+
+/// Let the scheduler run again once the drained operation is over
+pub async fn finish(app: &tauri::AppHandle) {
+    let state = app.state::<crate::AppState>();
+    if !supported(state.capabilities()) {
+        return;
+    }
+    let backend = HttpBackend {
+        http: &state.http,
+        port: state.backend_port.load(std::sync::atomic::Ordering::SeqCst),
+    };
+    if let Err(e) = backend.resume().await {
+        warn!("Failed to resume the scheduler after draining: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    const DEADLINE: Duration = Duration::from_secs(60);
+
+    /// A backend answering each poll with the next scripted job list (repeating the last)
+    #[derive(Default)]
+    struct Scripted {
+        polls: RefCell<VecDeque<Vec<RunningJob>>>,
+        calls: RefCell<Vec<&'static str>>,
+        fail_pause: bool,
+    }
+
+    impl Scripted {
+        fn new(polls: Vec<Vec<RunningJob>>) -> Self {
+            Self {
+                polls: RefCell::new(polls.into()),
+                ..Self::default()
+            }
+        }
+
+        fn resumed(&self) -> bool {
+            self.calls.borrow().contains(&"resume")
+        }
+    }
+
+    impl DrainBackend for Scripted {
+        async fn pause(&self, _reason: &str) -> Result<(), String> {
+            self.calls.borrow_mut().push("pause");
+            if self.fail_pause {
+                return Err("pause answered HTTP 500".into());
+            }
+            Ok(())
+        }
+
+        async fn resume(&self) -> Result<(), String> {
+            self.calls.borrow_mut().push("resume");
+            Ok(())
+        }
+
+        async fn running_jobs(&self) -> Result<Vec<RunningJob>, String> {
+            self.calls.borrow_mut().push("poll");
+            let mut polls = self.polls.borrow_mut();
+            if polls.len() > 1 {
+                Ok(polls.pop_front().unwrap())
+            } else {
+                Ok(polls.front().cloned().unwrap_or_default())
+            }
+        }
+    }
+
+    fn job(eta_secs: Option<u64>) -> RunningJob {
+        RunningJob {
+            id: "1".into(),
+            name: "Documents".into(),
+            percent: Some(90.0),
+            eta_secs,
+        }
+    }
+
+    async fn run(
+        backend: &Scripted,
+        on_timeout: OnTimeout,
+        cancel: &CancellationToken,
+    ) -> (Result<DrainOutcome, DrainError>, Vec<DrainProgress>) {
+        let progress = RefCell::new(Vec::new());
+        let result = drain(backend, "update", DEADLINE, on_timeout, cancel, |p| {
+            progress.borrow_mut().push(p)
+        })
+        .await;
+        (result, progress.into_inner())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_backend_drains_at_once() {
+        let backend = Scripted::new(vec![vec![]]);
+        let (result, progress) = run(&backend, OnTimeout::Abort, &CancellationToken::new()).await;
+
+        assert_eq!(result, Ok(DrainOutcome::Drained));
+        assert_eq!(progress.len(), 1);
+        // The scheduler stays paused until the caller finishes the operation
+        assert_eq!(*backend.calls.borrow(), vec!["pause", "poll"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_running_jobs_to_finish() {
+        let backend = Scripted::new(vec![vec![job(Some(12))], vec![job(Some(7))], vec![]]);
+        let started = Instant::now();
+        let (result, progress) = run(&backend, OnTimeout::Abort, &CancellationToken::new()).await;
+
+        assert_eq!(result, Ok(DrainOutcome::Drained));
+        assert_eq!(started.elapsed(), 2 * POLL_INTERVAL);
+        let remaining: Vec<u64> = progress.iter().map(|p| p.remaining_secs).collect();
+        assert_eq!(remaining, vec![60, 55, 50]);
+        assert!(!backend.resumed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn force_stop_proceeds_at_the_deadline() {
+        let backend = Scripted::new(vec![vec![job(None)]]);
+        let started = Instant::now();
+        let (result, progress) =
+            run(&backend, OnTimeout::ForceStop, &CancellationToken::new()).await;
+
+        assert_eq!(
+            result,
+            Ok(DrainOutcome::Forced {
+                jobs: vec![job(None)]
+            })
+        );
+        assert_eq!(started.elapsed(), DEADLINE);
+        assert_eq!(progress.last().unwrap().remaining_secs, 0);
+        assert!(!backend.resumed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_at_the_deadline_resumes_the_scheduler() {
+        let backend = Scripted::new(vec![vec![job(None)]]);
+        let started = Instant::now();
+        let (result, _) = run(&backend, OnTimeout::Abort, &CancellationToken::new()).await;
+
+        assert_eq!(
+            result,
+            Err(DrainError::DeadlineExceeded {
+                jobs: vec![job(None)]
+            })
+        );
+        assert_eq!(started.elapsed(), DEADLINE);
+        assert!(backend.resumed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eta_moving_past_the_deadline_aborts_early() {
+        // Fits at first, then the job slows down and its ETA jumps past the deadline
+        let backend = Scripted::new(vec![
+            vec![job(Some(40))],
+            vec![job(Some(45))],
+            vec![job(Some(600))],
+        ]);
+        let started = Instant::now();
+        let (result, progress) = run(&backend, OnTimeout::Abort, &CancellationToken::new()).await;
+
+        assert!(matches!(result, Err(DrainError::DeadlineExceeded { .. })));
+        assert_eq!(started.elapsed(), 2 * POLL_INTERVAL);
+        let flags: Vec<bool> = progress.iter().map(|p| p.eta_past_deadline).collect();
+        assert_eq!(flags, vec![false, false, true]);
+        assert!(backend.resumed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn force_stop_keeps_waiting_on_a_late_eta() {
+        // The job might still beat its ETA, so a forced stop waits the deadline out
+        let backend = Scripted::new(vec![vec![job(Some(600))], vec![job(Some(600))], vec![]]);
+        let (result, progress) =
+            run(&backend, OnTimeout::ForceStop, &CancellationToken::new()).await;
+
+        assert_eq!(result, Ok(DrainOutcome::Drained));
+        assert!(progress[0].eta_past_deadline);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelling_resumes_the_scheduler() {
+        let backend = Scripted::new(vec![vec![job(None)]]);
+        let cancel = CancellationToken::new();
+        let cancel_later = async {
+            tokio::time::sleep(Duration::from_secs(12)).await;
+            cancel.cancel();
+        };
+        let ((result, progress), ()) =
+            tokio::join!(run(&backend, OnTimeout::ForceStop, &cancel), cancel_later);
+
+        assert_eq!(result, Err(DrainError::Cancelled));
+        assert_eq!(progress.len(), 3);
+        assert!(backend.resumed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_pause_leaves_nothing_to_undo() {
+        let backend = Scripted {
+            fail_pause: true,
+            ..Scripted::new(vec![vec![job(None)]])
+        };
+        let (result, progress) = run(&backend, OnTimeout::Abort, &CancellationToken::new()).await;
+
+        assert!(matches!(result, Err(DrainError::Backend(_))));
+        assert!(progress.is_empty());
+        assert_eq!(*backend.calls.borrow(), vec!["pause"]);
+    }
+
+    #[test]
+    fn a_new_drain_cancels_the_previous_one() {
+        let state = DrainState::default();
+        let first = state.begin();
+        let second = state.begin();
+        assert!(first.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        assert!(state.cancel());
+        assert!(second.is_cancelled());
+        assert!(!state.cancel());
+    }
+}
@@ -0,0 +1,350 @@
+//! Noticing when the backend's own settings stop matching what the desktop assumes.
+//!
+//! Users can change the backend's port, data directory or authentication from its web UI.
+//! The desktop keeps using what it knew at startup until something mysteriously fails, so the
+//! backend's config endpoint is polled and the fields the desktop depends on are compared with
+//! its expectations. Each difference is reported as `backend-config-drift` together with what
+//! the desktop does about it ([`DriftPolicy`]): a new port is adopted once the backend answers
+//! there, by moving the window over; a data directory change is only reported; enabling
+//! authentication asks the user to pair again. Backends without the endpoint aren't polled.
+
+use crate::http::HttpPolicy;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// How often the backend's config is compared
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Path of the backend's config endpoint
+const CONFIG_PATH: &str = "/api/v1/system/config";
+
+/// The backend settings the desktop depends on, as the backend reports them
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BackendConfig {
+    pub port: Option<u16>,
+    pub data_dir: Option<PathBuf>,
+    pub auth_required: Option<bool>,
+}
+
+/// What the desktop currently assumes about the backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectations {
+    pub port: u16,
+    pub data_dir: Option<PathBuf>,
+    pub auth_required: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftField {
+    Port,
+    DataDir,
+    Auth,
+}
+
+/// What the desktop does about a drifted field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftPolicy {
+    /// Switch to the new value on its own
+    Adopt,
+    /// Tell the user; nothing on the desktop side depends on it directly
+    Report,
+    /// The user has to pair the desktop with the backend again
+    RePair,
+}
+
+impl DriftField {
+    pub fn policy(self) -> DriftPolicy {
+        match self {
+            DriftField::Port => DriftPolicy::Adopt,
+            DriftField::DataDir => DriftPolicy::Report,
+            DriftField::Auth => DriftPolicy::RePair,
+        }
+    }
+}
+
+/// One field that no longer matches; payload of `backend-config-drift`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Drift {
+    pub field: DriftField,
+    pub expected: String,
+    pub actual: String,
+    pub policy: DriftPolicy,
+}
+
+impl Drift {
+    fn new(field: DriftField, expected: String, actual: String) -> Self {
+        Self {
+            field,
+            expected,
+            actual,
+            policy: field.policy(),
+        }
+    }
+}
+
+/// Fields of `actual` that differ from `expected`. Fields the backend doesn't report never
+/// count as drift.
+pub fn diff(expected: &Expectations, actual: &BackendConfig) -> Vec<Drift> {
+    let mut drift = Vec::new();
+    if let Some(port) = actual.port.filter(|port| *port != expected.port) {
+        drift.push(Drift::new(
+            DriftField::Port,
+            expected.port.to_string(),
+            port.to_string(),
+        ));
+    }
+    if let (Some(actual), Some(expected)) = (&actual.data_dir, &expected.data_dir) {
+        if !crate::registrations::same_path(actual, expected) {
+            drift.push(Drift::new(
+                DriftField::DataDir,
+                expected.display().to_string(),
+                actual.display().to_string(),
+            ));
+        }
+    }
+    if let Some(auth) = actual.auth_required.filter(|auth| *auth != expected.auth_required) {
+        drift.push(Drift::new(
+            DriftField::Auth,
+            expected.auth_required.to_string(),
+            auth.to_string(),
+        ));
+    }
+    drift
+}
+
+/// Poll the backend's config and react to drift, for as long as the app runs
+pub async fn watch_config(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "config-drift",
+        crate::activity::TaskClass::Standard,
+        POLL_INTERVAL,
+    );
+    // Drift already reported, so each change is announced once
+    let mut reported: Vec<Drift> = Vec::new();
+    loop {
+        ticker.tick().await;
+
+        let state = app.state::<AppState>();
+        if !state.backend_ready.load(Ordering::SeqCst) {
+            continue;
+        }
+        let port = state.backend_port.load(Ordering::SeqCst);
+        let url = format!("http://localhost:{}{}", port, CONFIG_PATH);
+        let response = match state
+            .http
+            .send(HttpPolicy::BACKGROUND, |client| client.get(&url))
+            .await
+        {
+            Ok(response) => response,
+            Err(_) => continue,
+        };
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            info!("Backend has no config endpoint, not watching for config drift");
+            return;
+        }
+        let Ok(config) = response.json::<BackendConfig>().await else {
+            continue;
+        };
+
+        let expected = Expectations {
+            port,
+            data_dir: crate::paths::backend_data_dir(state.config().data_dir.as_deref()),
+            auth_required: false,
+        };
+        let drift = diff(&expected, &config);
+        for change in drift.iter().filter(|change| !reported.contains(change)) {
+            warn!(
+                "Backend {:?} changed from {} to {} ({:?})",
+                change.field, change.expected, change.actual, change.policy
+            );
+            crate::events::emit(&app, "backend-config-drift", change);
+        }
+        for change in &drift {
+            if change.field == DriftField::Port {
+                adopt_port(&app, change).await;
+            }
+        }
+        reported = drift;
+    }
+}
+
+/// Move over to a new backend port once the backend answers there; until then the backend
+/// presumably hasn't restarted to apply it
+async fn adopt_port(app: &tauri::AppHandle, change: &Drift) {
+    let Ok(port) = change.actual.parse::<u16>() else {
+        return;
+    };
+    let state = app.state::<AppState>();
+    let answers = state
+        .http
+        .healthcheck(port, HttpPolicy::BACKGROUND)
+        .await
+        .is_ok_and(|response| response.status().is_success());
+    if !answers {
+        return;
+    }
+
+    info!("Backend moved to port {}, following it", port);
+    state.backend_port.store(port, Ordering::SeqCst);
+    let owns_sidecar = !state.using_service.load(Ordering::SeqCst)
+        && !state.connection_mode.lock().is_viewer();
+    if owns_sidecar {
+        if let Err(e) = crate::ownership::claim(port) {
+            warn!("Failed to record backend ownership: {}", e);
+        }
+    }
+
+    // Keep the user on the page they were on, without raising the window
+    if let Some(window) = app.get_webview_window("main") {
+        if let Ok(mut url) = window.url() {
+            if url.host_str() == Some("localhost") && url.set_port(Some(port)).is_ok() {
+                let _ = window.navigate(url);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expectations() -> Expectations {
+        Expectations {
+            port: 4096,
+            data_dir: Some(PathBuf::from("/var/lib/zerobyte")),
+            auth_required: false,
+        }
+    }
+
+    fn config(port: Option<u16>, data_dir: Option<&str>, auth: Option<bool>) -> BackendConfig {
+        BackendConfig {
+            port,
+            data_dir: data_dir.map(PathBuf::from),
+            auth_required: auth,
+        }
+    }
+
+    #[test]
+    fn each_field_has_its_policy() {
+        let cases = [
+            (DriftField::Port, DriftPolicy::Adopt),
+            (DriftField::DataDir, DriftPolicy::Report),
+            (DriftField::Auth, DriftPolicy::RePair),
+        ];
+        for (field, policy) in cases {
+            assert_eq!(field.policy(), policy, "{:?}", field);
+        }
+    }
+
+    #[test]
+    fn only_reported_fields_that_differ_drift() {
+        let zerobyte = Some("/var/lib/zerobyte");
+        // (backend config, drifted fields with what the backend now says)
+        let cases: [(BackendConfig, &[(DriftField, &str)]); 8] = [
+            (BackendConfig::default(), &[]),
+            (config(Some(4096), zerobyte, Some(false)), &[]),
+            (
+                config(Some(8080), None, None),
+                &[(DriftField::Port, "8080")],
+            ),
+            (
+                config(None, Some("/srv/zerobyte"), None),
+                &[(DriftField::DataDir, "/srv/zerobyte")],
+            ),
+            (
+                config(None, None, Some(true)),
+                &[(DriftField::Auth, "true")],
+            ),
+            (
+                config(Some(4096), zerobyte, Some(true)),
+                &[(DriftField::Auth, "true")],
+            ),
+            (
+                config(Some(8080), Some("/srv/zerobyte"), Some(true)),
+                &[
+                    (DriftField::Port, "8080"),
+                    (DriftField::DataDir, "/srv/zerobyte"),
+                    (DriftField::Auth, "true"),
+                ],
+            ),
+            (
+                config(Some(1), Some("/"), Some(false)),
+                &[(DriftField::Port, "1"), (DriftField::DataDir, "/")],
+            ),
+        ];
+        for (actual, expected) in cases {
+            let drift = diff(&expectations(), &actual);
+            let drift: Vec<(DriftField, &str)> = drift
+                .iter()
+                .map(|drift| (drift.field, drift.actual.as_str()))
+                .collect();
+            assert_eq!(drift, expected, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn an_unknown_data_dir_is_never_drift() {
+        let mut expected = expectations();
+        expected.data_dir = None;
+        assert!(diff(&expected, &config(None, Some("/srv/zerobyte"), None)).is_empty());
+    }
+
+    #[test]
+    fn drift_records_the_expected_value_and_policy() {
+        let drift = diff(&expectations(), &config(Some(8080), None, Some(true)));
+        assert_eq!(
+            drift,
+            vec![
+                Drift {
+                    field: DriftField::Port,
+                    expected: "4096".into(),
+                    actual: "8080".into(),
+                    policy: DriftPolicy::Adopt,
+                },
+                Drift {
+                    field: DriftField::Auth,
+                    expected: "false".into(),
+                    actual: "true".into(),
+                    policy: DriftPolicy::RePair,
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&drift[0]).unwrap(),
+            serde_json::json!({
+                "field": "port",
+                "expected": "4096",
+                "actual": "8080",
+                "policy": "adopt",
+            })
+        );
+    }
+
+    #[test]
+    fn config_parses_with_missing_and_extra_fields() {
+        let cases = [
+            ("{}", BackendConfig::default()),
+            (r#"{"port":8080}"#, config(Some(8080), None, None)),
+            (
+                r#"{"port":4096,"dataDir":"/srv/zerobyte","authRequired":true,"theme":"dark"}"#,
+                config(Some(4096), Some("/srv/zerobyte"), Some(true)),
+            ),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(
+                serde_json::from_str::<BackendConfig>(body).unwrap(),
+                expected,
+                "{}",
+                body
+            );
+        }
+    }
+}
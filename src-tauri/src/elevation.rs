@@ -0,0 +1,373 @@
+//! Single-flight coordination of operations that prompt for administrator approval.
+//!
+//! Only one operation per class may be in flight: a second request either fails fast with
+//! `ElevationBusy` or waits its turn, depending on the caller's policy. Operations that run
+//! past the global timeout are abandoned and their slot released.
+
+use crate::error::AppError;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// How long an elevated operation may take, including the time the UAC prompt is open
+const ELEVATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Operations in the same class conflict with each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElevationClass {
+    /// Installing, removing, starting, or stopping the Windows Service
+    Service,
+    /// Changing permissions on backup sources
+    FileAccess,
+}
+
+impl fmt::Display for ElevationClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElevationClass::Service => write!(f, "service"),
+            ElevationClass::FileAccess => write!(f, "file access"),
+        }
+    }
+}
+
+/// What to do when an operation of the same class is already running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusyPolicy {
+    Reject,
+    Queue,
+}
+
+struct InFlight {
+    id: u64,
+    operation: String,
+    started: Instant,
+}
+
+/// An elevated operation currently running, as reported by `get_elevation_status`
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightOperation {
+    pub class: ElevationClass,
+    pub operation: String,
+    pub running_secs: u64,
+}
+
+pub struct ElevationCoordinator {
+    in_flight: Mutex<HashMap<ElevationClass, InFlight>>,
+    released: Notify,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+impl Default for ElevationCoordinator {
+    fn default() -> Self {
+        Self::new(ELEVATION_TIMEOUT)
+    }
+}
+
+impl ElevationCoordinator {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+            released: Notify::new(),
+            next_id: AtomicU64::new(1),
+            timeout,
+        }
+    }
+
+    /// Operations currently holding a slot
+    pub fn status(&self) -> Vec<InFlightOperation> {
+        self.in_flight
+            .lock()
+            .iter()
+            .map(|(class, op)| InFlightOperation {
+                class: *class,
+                operation: op.operation.clone(),
+                running_secs: op.started.elapsed().as_secs(),
+            })
+            .collect()
+    }
+
+    /// Run `operation` once no other operation of the same class is in flight
+    pub async fn run<T, E, F>(
+        &self,
+        class: ElevationClass,
+        operation: &str,
+        policy: BusyPolicy,
+        future: F,
+    ) -> Result<T, AppError>
+    where
+        E: Into<AppError>,
+        F: Future<Output = Result<T, E>>,
+    {
+        let id = self.acquire(class, operation, policy).await?;
+
+        let result = tokio::time::timeout(self.timeout, future).await;
+
+        self.release(class, id);
+
+        match result {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => {
+                warn!(
+                    "Elevated operation {} timed out after {}s",
+                    operation,
+                    self.timeout.as_secs()
+                );
+                Err(AppError::ElevationTimedOut(operation.to_string()))
+            }
+        }
+    }
+
+    async fn acquire(
+        &self,
+        class: ElevationClass,
+        operation: &str,
+        policy: BusyPolicy,
+    ) -> Result<u64, AppError> {
+        loop {
+            // Register for the wakeup before checking, so a release in between isn't missed
+            let released = self.released.notified();
+
+            {
+                let mut in_flight = self.in_flight.lock();
+                let busy = in_flight
+                    .get(&class)
+                    .filter(|current| current.started.elapsed() < self.timeout);
+
+                match busy {
+                    None => {
+                        if let Some(stale) = in_flight.get(&class) {
+                            warn!(
+                                "Releasing stale elevated operation {} after {}s",
+                                stale.operation,
+                                stale.started.elapsed().as_secs()
+                            );
+                        }
+                        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                        in_flight.insert(
+                            class,
+                            InFlight {
+                                id,
+                                operation: operation.to_string(),
+                                started: Instant::now(),
+                            },
+                        );
+                        info!("Elevated operation {} started", operation);
+                        return Ok(id);
+                    }
+                    Some(current) if policy == BusyPolicy::Reject => {
+                        return Err(AppError::ElevationBusy {
+                            class,
+                            operation: current.operation.clone(),
+                            since_secs: current.started.elapsed().as_secs(),
+                        });
+                    }
+                    Some(current) => {
+                        info!(
+                            "Elevated operation {} queued behind {}",
+                            operation, current.operation
+                        );
+                    }
+                }
+            }
+
+            // Re-check periodically so an expired slot is taken over even without a release
+            let _ = tokio::time::timeout(Duration::from_secs(1), released).await;
+        }
+    }
+
+    fn release(&self, class: ElevationClass, id: u64) {
+        let mut in_flight = self.in_flight.lock();
+        // A timed-out operation may already have been replaced by a newer one
+        if in_flight.get(&class).map(|op| op.id) == Some(id) {
+            in_flight.remove(&class);
+        }
+        drop(in_flight);
+        self.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    async fn hold(concurrent: &AtomicUsize, peak: &AtomicUsize) -> Result<(), AppError> {
+        let now = concurrent.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        concurrent.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_request_is_rejected_while_busy() {
+        let coordinator = ElevationCoordinator::default();
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+
+        let first = coordinator.run(
+            ElevationClass::Service,
+            "install",
+            BusyPolicy::Reject,
+            async move {
+                released.await.ok();
+                Ok::<_, AppError>("installed")
+            },
+        );
+        let second = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let status = coordinator.status();
+            let result = coordinator
+                .run(
+                    ElevationClass::Service,
+                    "install",
+                    BusyPolicy::Reject,
+                    async { Ok::<_, AppError>("installed twice") },
+                )
+                .await;
+            release.send(()).unwrap();
+            (status, result)
+        };
+
+        let (first, (status, second)) = tokio::join!(first, second);
+        assert_eq!(first.unwrap(), "installed");
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].operation, "install");
+        match second {
+            Err(AppError::ElevationBusy {
+                class, operation, ..
+            }) => {
+                assert_eq!(class, ElevationClass::Service);
+                assert_eq!(operation, "install");
+            }
+            other => panic!("expected ElevationBusy, got {:?}", other.map(|_| ())),
+        }
+        assert!(coordinator.status().is_empty());
+    }
+
+    #[tokio::test]
+    async fn queued_requests_run_one_at_a_time() {
+        let coordinator = Arc::new(ElevationCoordinator::default());
+        let concurrent = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let coordinator = coordinator.clone();
+                let concurrent = concurrent.clone();
+                let peak = peak.clone();
+                tokio::spawn(async move {
+                    coordinator
+                        .run(
+                            ElevationClass::Service,
+                            "start",
+                            BusyPolicy::Queue,
+                            hold(&concurrent, &peak),
+                        )
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        assert!(coordinator.status().is_empty());
+    }
+
+    #[tokio::test]
+    async fn different_classes_do_not_conflict() {
+        let coordinator = ElevationCoordinator::default();
+        let concurrent = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let (service, access) = tokio::join!(
+            coordinator.run(
+                ElevationClass::Service,
+                "install",
+                BusyPolicy::Reject,
+                hold(&concurrent, &peak),
+            ),
+            coordinator.run(
+                ElevationClass::FileAccess,
+                "grant_path_access",
+                BusyPolicy::Reject,
+                hold(&concurrent, &peak),
+            ),
+        );
+
+        service.unwrap();
+        access.unwrap();
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn timed_out_operation_fails_and_releases_its_slot() {
+        let coordinator = ElevationCoordinator::new(Duration::from_millis(50));
+
+        let result = coordinator
+            .run(
+                ElevationClass::Service,
+                "install",
+                BusyPolicy::Reject,
+                async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    Ok::<_, AppError>(())
+                },
+            )
+            .await;
+        match result {
+            Err(AppError::ElevationTimedOut(operation)) => assert_eq!(operation, "install"),
+            other => panic!("expected ElevationTimedOut, got {:?}", other),
+        }
+
+        assert!(coordinator.status().is_empty());
+        coordinator
+            .run(
+                ElevationClass::Service,
+                "install",
+                BusyPolicy::Reject,
+                async { Ok::<_, AppError>(()) },
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn queued_request_takes_over_an_expired_slot() {
+        let coordinator = ElevationCoordinator::new(Duration::from_millis(50));
+        let id = coordinator
+            .acquire(ElevationClass::Service, "stop", BusyPolicy::Reject)
+            .await
+            .unwrap();
+
+        // The holder never releases; the waiter must not stay queued forever
+        let started = Instant::now();
+        coordinator
+            .run(ElevationClass::Service, "start", BusyPolicy::Queue, async {
+                Ok::<_, AppError>(())
+            })
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The stale holder releasing late must not free anyone else's slot
+        let current = coordinator
+            .acquire(ElevationClass::Service, "install", BusyPolicy::Reject)
+            .await
+            .unwrap();
+        coordinator.release(ElevationClass::Service, id);
+        assert_eq!(coordinator.status()[0].operation, "install");
+        coordinator.release(ElevationClass::Service, current);
+        assert!(coordinator.status().is_empty());
+    }
+}
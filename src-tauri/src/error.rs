@@ -0,0 +1,108 @@
+//! Errors returned to the frontend with a machine-readable code.
+
+use crate::elevation::ElevationClass;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("Another {class} operation is already waiting for administrator approval ({operation}, started {since_secs}s ago)")]
+    ElevationBusy {
+        class: ElevationClass,
+        operation: String,
+        since_secs: u64,
+    },
+    #[error("{0} did not finish in time and was abandoned")]
+    ElevationTimedOut(String),
+    #[error("Connected as a viewer; the backend is owned by {0}")]
+    ReadOnlyMode(String),
+    #[error("The connected backend does not support {0}")]
+    UnsupportedByBackend(String),
+    #[error("{}", .0.message())]
+    ServiceActionRefused(crate::service_access::CapabilityReason),
+    #[error("Port {0} is in use by another application")]
+    PortInUse(u16),
+    #[error("No free port between {first} and {last}; close the programs using them and try again")]
+    NoFreePort { first: u16, last: u16 },
+    #[error("Failed to start the backend: {0}")]
+    SidecarSpawnFailed(String),
+    #[error("No token to protect the backend could be made, so it wasn't started")]
+    NoBackendToken,
+    #[error("The backend files are incomplete: {0}")]
+    AssetsMissing(String),
+    #[error("The backend program failed its integrity check: {0}")]
+    SidecarIntegrity(String),
+    #[error("The backend on port {port} did not become healthy within {waited_secs}s")]
+    HealthcheckTimeout { port: u16, waited_secs: u64 },
+    #[error("Can't read the backend's resource use: {0}")]
+    BackendProcessUnknown(String),
+    #[error("The Windows Service isn't answering on port {0}")]
+    ServiceNotRunning(u16),
+    #[error("{0} backup(s) are running; switch once they finish")]
+    BackupsRunning(usize),
+    #[error("{0} isn't supported for the Windows Service without elevation")]
+    NeedsElevation(String),
+    #[error("Windows Service unavailable: {0}")]
+    ServiceUnavailable(String),
+    #[error("Administrator approval was declined")]
+    ElevationDeclined,
+    #[error("The backend runs as a Windows Service; restart the service instead")]
+    ManagedByService,
+    #[error("The backend is already being restarted")]
+    RestartInProgress,
+    #[error("The app is quitting")]
+    Quitting,
+    #[error("{0}")]
+    Message(String),
+}
+
+impl AppError {
+    /// Stable identifier the web UI maps to a localized message
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ElevationBusy { .. } => "elevation_busy",
+            AppError::ElevationTimedOut(_) => "elevation_timed_out",
+            AppError::ReadOnlyMode(_) => "read_only_mode",
+            AppError::UnsupportedByBackend(_) => "unsupported_by_backend",
+            AppError::ServiceActionRefused(reason) => reason.code(),
+            AppError::PortInUse(_) => "port_in_use",
+            AppError::NoFreePort { .. } => "no_free_port",
+            AppError::SidecarSpawnFailed(_) => "sidecar_spawn_failed",
+            AppError::NoBackendToken => "no_backend_token",
+            AppError::AssetsMissing(_) => "assets_missing",
+            AppError::SidecarIntegrity(_) => "sidecar_integrity_failed",
+            AppError::HealthcheckTimeout { .. } => "healthcheck_timeout",
+            AppError::BackendProcessUnknown(_) => "backend_process_unknown",
+            AppError::ServiceNotRunning(_) => "service_not_running",
+            AppError::BackupsRunning(_) => "backups_running",
+            AppError::NeedsElevation(_) => "needs_elevation",
+            AppError::ServiceUnavailable(_) => "service_unavailable",
+            AppError::ElevationDeclined => "elevation_declined",
+            AppError::ManagedByService => "managed_by_service",
+            AppError::RestartInProgress => "restart_in_progress",
+            AppError::Quitting => "quitting",
+            AppError::Message(_) => "error",
+        }
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Message(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Message(message.to_string())
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 2)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
@@ -0,0 +1,903 @@
+//! Estimating how much data a new backup plan will cover, before its first run.
+//!
+//! The sources are walked with the exclusion rules the backend applies (restic's): glob
+//! patterns where `*`, `?` and `[...]` match within one path component and `**` across any
+//! number of them; patterns starting with `/` are anchored to the filesystem root, others
+//! match at any depth; a trailing `/` only matches directories and a leading `!` re-includes
+//! what an earlier pattern excluded. Matching ignores case on Windows. Hidden files can be
+//! left out as well. Symlinks and reparse points are never followed, so link loops can't
+//! trap the walk.
+//!
+//! Each top-level directory of a source is walked as its own blocking task, a few at a time.
+//! Progress is emitted as `backup-estimate-progress`. Results are cached by the set of paths
+//! and rules and reused until the modification time of a source or one of its top-level
+//! entries changes, so reopening the plan wizard doesn't walk again.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BinaryHeap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::info;
+
+/// Top-level directories walked at the same time
+pub const PARALLELISM: usize = 4;
+
+/// Largest files reported
+pub const LARGEST_FILES: usize = 10;
+
+/// Estimates kept in the cache
+const CACHE_ENTRIES: usize = 16;
+
+/// Interval between progress events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Nesting beyond this is skipped rather than walked
+const MAX_DEPTH: usize = 256;
+
+/// One exclude pattern, split into path components
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    negated: bool,
+    dir_only: bool,
+    components: Vec<Vec<char>>,
+}
+
+/// Exclusion rules for a walk
+#[derive(Debug, Clone)]
+pub struct Excluder {
+    rules: Vec<Rule>,
+    exclude_hidden: bool,
+    case_insensitive: bool,
+}
+
+fn is_double_star(component: &[char]) -> bool {
+    component == ['*', '*']
+}
+
+/// Match one path component against a glob component
+fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_component(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_component(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some((&first, name_rest)) = name.split_first() else {
+                return false;
+            };
+            match match_class(rest, first) {
+                Some((matched, after)) => matched && match_component(after, name_rest),
+                // No closing bracket: a literal '['
+                None => first == '[' && match_component(rest, name_rest),
+            }
+        }
+        Some(('\\', rest)) if !rest.is_empty() && !cfg!(windows) => {
+            name.first() == Some(&rest[0]) && match_component(&rest[1..], &name[1..])
+        }
+        Some((literal, rest)) => {
+            name.first() == Some(literal) && match_component(rest, &name[1..])
+        }
+    }
+}
+
+/// Match `c` against the class starting after a '['. Returns whether it matched and the
+/// pattern after the closing ']', or None if the class isn't closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            [']', after @ ..] if !first => return Some((matched != negated, after)),
+            [low, '-', high, after @ ..] if *high != ']' => {
+                matched |= (*low..=*high).contains(&c);
+                rest = after;
+            }
+            [single, after @ ..] => {
+                matched |= *single == c;
+                rest = after;
+            }
+        }
+        first = false;
+    }
+}
+
+/// Match path components against glob components, where `**` spans any number of them
+fn match_components(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if is_double_star(first) => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => {
+            !path.is_empty()
+                && match_component(first, &path[0])
+                && match_components(rest, &path[1..])
+        }
+    }
+}
+
+/// Components of an absolute path as matched by patterns: `/home/me` is ["home", "me"],
+/// `C:\Users` is ["C:", "Users"]
+fn path_components(path: &Path, case_insensitive: bool) -> Vec<Vec<char>> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().into_owned()),
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .map(|name| fold_case(&name, case_insensitive).chars().collect())
+        .collect()
+}
+
+fn fold_case(value: &str, case_insensitive: bool) -> String {
+    if case_insensitive {
+        value.to_lowercase()
+    } else {
+        value.to_string()
+    }
+}
+
+impl Excluder {
+    /// Parse `patterns`; blank lines and `#` comments are ignored as in an exclude file
+    pub fn new(patterns: &[String], exclude_hidden: bool) -> Self {
+        Self::with_case(patterns, exclude_hidden, cfg!(windows))
+    }
+
+    pub fn with_case(patterns: &[String], exclude_hidden: bool, case_insensitive: bool) -> Self {
+        let rules = patterns
+            .iter()
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+            .map(|pattern| {
+                let pattern = if cfg!(windows) {
+                    pattern.replace('\\', "/")
+                } else {
+                    pattern.to_string()
+                };
+                let (negated, pattern) = match pattern.strip_prefix('!') {
+                    Some(rest) => (true, rest.to_string()),
+                    None => (false, pattern),
+                };
+                let dir_only = pattern.ends_with('/');
+                let anchored = pattern.starts_with('/');
+                let mut components: Vec<Vec<char>> = fold_case(&pattern, case_insensitive)
+                    .split('/')
+                    .filter(|component| !component.is_empty())
+                    .map(|component| component.chars().collect())
+                    .collect();
+                if !anchored {
+                    components.insert(0, vec!['*', '*']);
+                }
+                Rule {
+                    negated,
+                    dir_only,
+                    components,
+                }
+            })
+            .collect();
+        Self {
+            rules,
+            exclude_hidden,
+            case_insensitive,
+        }
+    }
+
+    /// Whether `path` is left out of the backup. The last matching pattern decides.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool, hidden: bool) -> bool {
+        if self.exclude_hidden && hidden {
+            return true;
+        }
+        let components = path_components(path, self.case_insensitive);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only) && match_components(&rule.components, &components)
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+/// Whether an entry counts as hidden: a dot name, or the hidden attribute on Windows
+fn is_hidden(path: &Path, metadata: &std::fs::Metadata) -> bool {
+    let dot = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        dot || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = metadata;
+        dot
+    }
+}
+
+/// Symlinks, junctions and other reparse points, which are never followed
+fn is_link(metadata: &std::fs::Metadata) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+        metadata.file_type().is_symlink()
+            || metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+    }
+    #[cfg(not(windows))]
+    {
+        metadata.file_type().is_symlink()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct SizedFile {
+    pub bytes: u64,
+    pub path: PathBuf,
+}
+
+/// Size of one top-level directory (or a source's own files)
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryShare {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub files: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupEstimate {
+    pub total_bytes: u64,
+    pub file_count: u64,
+    /// Largest files, biggest first
+    pub largest_files: Vec<SizedFile>,
+    /// Per top-level directory, biggest first
+    pub breakdown: Vec<DirectoryShare>,
+    /// Entries that couldn't be read, or were nested too deep
+    pub skipped: u64,
+    /// Served from the cache without walking
+    pub cached: bool,
+}
+
+/// Payload of `backup-estimate-progress`
+#[derive(Debug, Clone, Serialize)]
+pub struct EstimateProgress {
+    pub files: u64,
+    pub bytes: u64,
+    pub units_done: usize,
+    pub units_total: usize,
+}
+
+/// Counters shared by the walking tasks
+#[derive(Debug, Default)]
+pub struct Progress {
+    pub files: AtomicU64,
+    pub bytes: AtomicU64,
+}
+
+/// Keeps the `LARGEST_FILES` biggest files seen
+#[derive(Debug, Default)]
+struct Largest(BinaryHeap<std::cmp::Reverse<SizedFile>>);
+
+impl Largest {
+    fn offer(&mut self, file: SizedFile) {
+        self.0.push(std::cmp::Reverse(file));
+        if self.0.len() > LARGEST_FILES {
+            self.0.pop();
+        }
+    }
+
+    fn into_sorted(self) -> Vec<SizedFile> {
+        let mut files: Vec<SizedFile> = self.0.into_iter().map(|file| file.0).collect();
+        files.sort_by(|a, b| b.cmp(a));
+        files
+    }
+}
+
+/// A piece of the walk done by one task
+#[derive(Debug, Clone)]
+pub struct Unit {
+    /// Directory the sizes are reported under
+    pub share: PathBuf,
+    pub root: PathBuf,
+    /// Walk only the root's own files, not its subdirectories (a source's top level)
+    pub shallow: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct UnitResult {
+    pub share: PathBuf,
+    pub bytes: u64,
+    pub files: u64,
+    pub skipped: u64,
+    largest: Largest,
+}
+
+/// Split sources into units: each top-level directory of a source is walked on its own,
+/// and each source's own files form one more unit
+pub fn plan_units(sources: &[PathBuf], excluder: &Excluder) -> Vec<Unit> {
+    let mut units = Vec::new();
+    for source in sources {
+        let is_dir = std::fs::symlink_metadata(source).is_ok_and(|metadata| metadata.is_dir());
+        units.push(Unit {
+            share: source.clone(),
+            root: source.clone(),
+            shallow: true,
+        });
+        if !is_dir {
+            continue;
+        }
+        let Ok(children) = std::fs::read_dir(source) else {
+            continue;
+        };
+        for child in children.flatten() {
+            let path = child.path();
+            let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir()
+                && !is_link(&metadata)
+                && !excluder.is_excluded(&path, true, is_hidden(&path, &metadata))
+            {
+                units.push(Unit {
+                    share: path.clone(),
+                    root: path,
+                    shallow: false,
+                });
+            }
+        }
+    }
+    units
+}
+
+/// Walk one unit. Stops early (with a partial result) once `cancel` is set.
+pub fn walk(
+    unit: &Unit,
+    excluder: &Excluder,
+    progress: &Progress,
+    cancel: &AtomicBool,
+) -> UnitResult {
+    let mut result = UnitResult {
+        share: unit.share.clone(),
+        ..Default::default()
+    };
+    let count = |path: PathBuf, metadata: &std::fs::Metadata, result: &mut UnitResult| {
+        let bytes = metadata.len();
+        result.bytes += bytes;
+        result.files += 1;
+        progress.files.fetch_add(1, Ordering::Relaxed);
+        progress.bytes.fetch_add(bytes, Ordering::Relaxed);
+        result.largest.offer(SizedFile { bytes, path });
+    };
+
+    let Ok(root) = std::fs::symlink_metadata(&unit.root) else {
+        result.skipped += 1;
+        return result;
+    };
+    if !root.is_dir() {
+        let hidden = is_hidden(&unit.root, &root);
+        if !is_link(&root) && !excluder.is_excluded(&unit.root, false, hidden) {
+            count(unit.root.clone(), &root, &mut result);
+        }
+        return result;
+    }
+
+    let mut pending = vec![(unit.root.clone(), 0usize)];
+    while let Some((dir, depth)) = pending.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(children) = std::fs::read_dir(&dir) else {
+            result.skipped += 1;
+            continue;
+        };
+        for child in children {
+            let Ok(child) = child else {
+                result.skipped += 1;
+                continue;
+            };
+            let path = child.path();
+            let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                result.skipped += 1;
+                continue;
+            };
+            if is_link(&metadata)
+                || excluder.is_excluded(&path, metadata.is_dir(), is_hidden(&path, &metadata))
+            {
+                continue;
+            }
+            if metadata.is_dir() {
+                // A source's subdirectories are units of their own
+                if unit.shallow {
+                    continue;
+                }
+                if depth + 1 >= MAX_DEPTH {
+                    result.skipped += 1;
+                } else {
+                    pending.push((path, depth + 1));
+                }
+            } else if metadata.is_file() {
+                count(path, &metadata, &mut result);
+            }
+        }
+    }
+    result
+}
+
+/// Combine unit results into an estimate
+pub fn combine(results: Vec<UnitResult>) -> BackupEstimate {
+    let mut largest = Largest::default();
+    let mut breakdown = Vec::new();
+    let (mut total_bytes, mut file_count, mut skipped) = (0, 0, 0);
+    for result in results {
+        total_bytes += result.bytes;
+        file_count += result.files;
+        skipped += result.skipped;
+        for file in result.largest.into_sorted() {
+            largest.offer(file);
+        }
+        if result.files > 0 {
+            breakdown.push(DirectoryShare {
+                path: result.share,
+                bytes: result.bytes,
+                files: result.files,
+            });
+        }
+    }
+    breakdown.sort_by_key(|entry| std::cmp::Reverse(entry.bytes));
+    BackupEstimate {
+        total_bytes,
+        file_count,
+        largest_files: largest.into_sorted(),
+        breakdown,
+        skipped,
+        cached: false,
+    }
+}
+
+/// Cache key for a set of sources and rules
+pub fn cache_key(sources: &[PathBuf], patterns: &[String], exclude_hidden: bool) -> String {
+    let mut sources: Vec<String> = sources.iter().map(|path| path.display().to_string()).collect();
+    sources.sort();
+    let mut hasher = Sha256::new();
+    for source in &sources {
+        hasher.update(source.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update([1]);
+    for pattern in patterns {
+        hasher.update(pattern.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update([exclude_hidden as u8]);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Modification times an estimate depends on: the sources and their top-level entries
+pub fn stamps(units: &[Unit]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    units
+        .iter()
+        .map(|unit| {
+            let modified = std::fs::metadata(&unit.root)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (unit.root.clone(), modified)
+        })
+        .collect()
+}
+
+struct Cached {
+    estimate: BackupEstimate,
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+    stored: SystemTime,
+}
+
+/// The running estimate's cancel flag and the estimate cache
+#[derive(Default)]
+pub struct Estimator {
+    running: AtomicBool,
+    cancel: Arc<AtomicBool>,
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+impl Estimator {
+    /// Mark an estimate as started. Returns the cancel flag, or None if one is already running.
+    pub fn begin(&self) -> Option<Arc<AtomicBool>> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        self.cancel.store(false, Ordering::SeqCst);
+        Some(self.cancel.clone())
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    /// A cached estimate, if nothing it depends on changed since
+    pub fn cached(&self, key: &str, units: &[Unit]) -> Option<BackupEstimate> {
+        let cache = self.cache.lock();
+        let cached = cache.get(key)?;
+        (cached.stamps == stamps(units)).then(|| BackupEstimate {
+            cached: true,
+            ..cached.estimate.clone()
+        })
+    }
+
+    pub fn store(&self, key: String, units: &[Unit], estimate: &BackupEstimate) {
+        let mut cache = self.cache.lock();
+        if cache.len() >= CACHE_ENTRIES && !cache.contains_key(&key) {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.stored)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(
+            key,
+            Cached {
+                estimate: estimate.clone(),
+                stamps: stamps(units),
+                stored: SystemTime::now(),
+            },
+        );
+    }
+}
+
+/// Walk `units` at most [`PARALLELISM`] at a time, emitting progress. None if cancelled.
+pub async fn run(
+    app: &tauri::AppHandle,
+    units: Vec<Unit>,
+    excluder: Excluder,
+    cancel: Arc<AtomicBool>,
+) -> Option<BackupEstimate> {
+    let total = units.len();
+    let progress = Arc::new(Progress::default());
+    let excluder = Arc::new(excluder);
+    let permits = Arc::new(tokio::sync::Semaphore::new(PARALLELISM));
+    let mut tasks = tokio::task::JoinSet::new();
+    for unit in units {
+        let (progress, excluder, cancel, permits) =
+            (progress.clone(), excluder.clone(), cancel.clone(), permits.clone());
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await.ok()?;
+            tokio::task::spawn_blocking(move || walk(&unit, &excluder, &progress, &cancel))
+                .await
+                .ok()
+        });
+    }
+
+    let mut results = Vec::with_capacity(total);
+    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            joined = tasks.join_next() => match joined {
+                Some(Ok(Some(result))) => results.push(result),
+                Some(_) => {}
+                None => break,
+            },
+            _ = ticker.tick() => crate::events::emit(
+                app,
+                "backup-estimate-progress",
+                EstimateProgress {
+                    files: progress.files.load(Ordering::Relaxed),
+                    bytes: progress.bytes.load(Ordering::Relaxed),
+                    units_done: results.len(),
+                    units_total: total,
+                },
+            ),
+        }
+    }
+
+    if cancel.load(Ordering::SeqCst) {
+        return None;
+    }
+    let estimate = combine(results);
+    info!(
+        "Estimated {} bytes in {} files ({} skipped)",
+        estimate.total_bytes, estimate.file_count, estimate.skipped
+    );
+    Some(estimate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluder(patterns: &[&str]) -> Excluder {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Excluder::with_case(&patterns, false, false)
+    }
+
+    fn excludes_file(patterns: &[&str], path: &str) -> bool {
+        excluder(patterns).is_excluded(Path::new(path), false, false)
+    }
+
+    fn excludes_dir(patterns: &[&str], path: &str) -> bool {
+        excluder(patterns).is_excluded(Path::new(path), true, false)
+    }
+
+    #[test]
+    fn documented_pattern_syntax() {
+        // (pattern, path, excluded)
+        let cases: &[(&str, &str, bool)] = &[
+            // `*` and `?` match within one component
+            ("*.tmp", "/home/me/notes.tmp", true),
+            ("*.tmp", "/home/me/notes.tmp.txt", false),
+            ("*.tmp", "/home/me/tmp", false),
+            ("file?.log", "/var/log/file1.log", true),
+            ("file?.log", "/var/log/file10.log", false),
+            ("/home/*/cache", "/home/me/cache", true),
+            ("/home/*/cache", "/home/me/work/cache", false),
+            // `[...]` classes, ranges and negation
+            ("report[0-9].pdf", "/docs/report7.pdf", true),
+            ("report[0-9].pdf", "/docs/reportA.pdf", false),
+            ("[!a]*.iso", "/isos/b.iso", true),
+            ("[!a]*.iso", "/isos/a.iso", false),
+            ("[^a]*.iso", "/isos/a.iso", false),
+            ("[]x].txt", "/t/].txt", true),
+            ("[abc.txt", "/t/[abc.txt", true),
+            // `**` spans any number of components, including none
+            ("/home/**/node_modules", "/home/node_modules", true),
+            (
+                "/home/**/node_modules",
+                "/home/me/src/app/node_modules",
+                true,
+            ),
+            ("/home/**/node_modules", "/opt/node_modules", false),
+            ("/data/**", "/data/a/b/c", true),
+            // Unanchored patterns match at any depth, anchored ones only from the root
+            ("node_modules", "/home/me/app/node_modules", true),
+            ("me/app", "/home/me/app", true),
+            ("/me/app", "/home/me/app", false),
+            ("/home/me", "/home/me", true),
+            // A pattern names whole components
+            ("cache", "/home/me/cached", false),
+            ("cache", "/home/me/.cache", false),
+        ];
+        for (pattern, path, excluded) in cases {
+            assert_eq!(
+                excludes_file(&[pattern], path),
+                *excluded,
+                "{} against {}",
+                pattern,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        assert!(excludes_dir(&["build/"], "/src/app/build"));
+        assert!(!excludes_file(&["build/"], "/src/app/build"));
+        assert!(excludes_file(&["build"], "/src/app/build"));
+    }
+
+    #[test]
+    fn negation_reincludes_and_the_last_match_wins() {
+        let rules = ["*.log", "!important.log"];
+        assert!(excludes_file(&rules, "/var/app/debug.log"));
+        assert!(!excludes_file(&rules, "/var/app/important.log"));
+        // A later exclusion overrides an earlier re-inclusion
+        let rules = ["*.log", "!important.log", "/var/old/*"];
+        assert!(excludes_file(&rules, "/var/old/important.log"));
+        // A re-inclusion alone excludes nothing
+        assert!(!excludes_file(&["!keep"], "/a/other"));
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_ignored() {
+        let rules = ["", "   ", "# *.txt", "  *.bak  "];
+        assert!(!excludes_file(&rules, "/a/notes.txt"));
+        assert!(excludes_file(&rules, "/a/notes.bak"));
+        assert!(!excludes_file(&rules, "/a/# *.txt"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn backslash_escapes_a_special_character() {
+        assert!(excludes_file(&["\\*.txt"], "/a/*.txt"));
+        assert!(!excludes_file(&["\\*.txt"], "/a/b.txt"));
+        assert!(excludes_file(&["what\\?"], "/a/what?"));
+        assert!(!excludes_file(&["what\\?"], "/a/whats"));
+    }
+
+    #[test]
+    fn case_folding_follows_the_platform_rule() {
+        let patterns = vec!["*.JPG".to_string(), "/Users/Me/Cache".to_string()];
+        let sensitive = Excluder::with_case(&patterns, false, false);
+        let insensitive = Excluder::with_case(&patterns, false, true);
+        for path in ["/photos/a.jpg", "/users/me/cache"] {
+            assert!(
+                !sensitive.is_excluded(Path::new(path), false, false),
+                "{}",
+                path
+            );
+            assert!(
+                insensitive.is_excluded(Path::new(path), false, false),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn hidden_entries_are_left_out_only_when_asked() {
+        let path = Path::new("/home/me/.config");
+        assert!(!Excluder::with_case(&[], false, false).is_excluded(path, true, true));
+        assert!(Excluder::with_case(&[], true, false).is_excluded(path, true, true));
+        assert!(!Excluder::with_case(&[], true, false).is_excluded(path, true, false));
+    }
+
+    fn temp_tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("estimate-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn write(path: &Path, bytes: usize) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0u8; bytes]).unwrap();
+    }
+
+    fn estimate(sources: &[PathBuf], excluder: &Excluder) -> BackupEstimate {
+        let progress = Progress::default();
+        let cancel = AtomicBool::new(false);
+        let results = plan_units(sources, excluder)
+            .iter()
+            .map(|unit| walk(unit, excluder, &progress, &cancel))
+            .collect();
+        combine(results)
+    }
+
+    #[test]
+    fn walk_applies_exclusions_and_breaks_down_by_top_level_directory() {
+        let root = temp_tree("walk");
+        write(&root.join("top.txt"), 10);
+        write(&root.join("photos/a.jpg"), 300);
+        write(&root.join("photos/2024/b.jpg"), 200);
+        write(&root.join("photos/2024/b.tmp"), 5000);
+        write(&root.join("code/app/main.rs"), 40);
+        write(&root.join("code/app/node_modules/dep/index.js"), 9000);
+        write(&root.join(".hidden/secret"), 7000);
+
+        let patterns = vec!["*.tmp".to_string(), "node_modules/".to_string()];
+        let estimate = estimate(
+            std::slice::from_ref(&root),
+            &Excluder::with_case(&patterns, true, false),
+        );
+        assert_eq!(estimate.file_count, 4);
+        assert_eq!(estimate.total_bytes, 550);
+        assert_eq!(estimate.skipped, 0);
+        assert_eq!(estimate.largest_files[0].path, root.join("photos/a.jpg"));
+        let breakdown: Vec<(PathBuf, u64, u64)> = estimate
+            .breakdown
+            .iter()
+            .map(|share| (share.path.clone(), share.bytes, share.files))
+            .collect();
+        assert_eq!(
+            breakdown,
+            vec![
+                (root.join("photos"), 500, 2),
+                (root.join("code"), 40, 1),
+                (root.clone(), 10, 1),
+            ]
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_does_not_follow_symlink_loops() {
+        let root = temp_tree("loop");
+        write(&root.join("dir/file"), 8);
+        std::os::unix::fs::symlink(&root, root.join("dir/back")).unwrap();
+        std::os::unix::fs::symlink(root.join("dir/file"), root.join("link")).unwrap();
+
+        let estimate = estimate(std::slice::from_ref(&root), &excluder(&[]));
+        assert_eq!(estimate.file_count, 1);
+        assert_eq!(estimate.total_bytes, 8);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cancelled_walk_stops_early() {
+        let root = temp_tree("cancel");
+        write(&root.join("dir/file"), 8);
+        let excluder = excluder(&[]);
+        let unit = Unit {
+            share: root.join("dir"),
+            root: root.join("dir"),
+            shallow: false,
+        };
+        let result = walk(
+            &unit,
+            &excluder,
+            &Progress::default(),
+            &AtomicBool::new(true),
+        );
+        assert_eq!(result.files, 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn largest_files_keep_the_biggest() {
+        let mut largest = Largest::default();
+        for bytes in 0..(LARGEST_FILES as u64 * 2) {
+            largest.offer(SizedFile {
+                bytes,
+                path: PathBuf::from(format!("/f{}", bytes)),
+            });
+        }
+        let sorted = largest.into_sorted();
+        assert_eq!(sorted.len(), LARGEST_FILES);
+        assert_eq!(sorted[0].bytes, LARGEST_FILES as u64 * 2 - 1);
+        assert!(sorted.windows(2).all(|pair| pair[0].bytes >= pair[1].bytes));
+    }
+
+    #[test]
+    fn cache_key_ignores_source_order_but_not_rules() {
+        let a = PathBuf::from("/a");
+        let b = PathBuf::from("/b");
+        let rules = vec!["*.tmp".to_string()];
+        let key = cache_key(&[a.clone(), b.clone()], &rules, false);
+        assert_eq!(key, cache_key(&[b.clone(), a.clone()], &rules, false));
+        assert_ne!(key, cache_key(&[a.clone(), b.clone()], &rules, true));
+        assert_ne!(key, cache_key(&[a, b], &[], false));
+    }
+
+    #[test]
+    fn cache_is_invalidated_when_a_source_changes() {
+        let root = temp_tree("cache");
+        write(&root.join("dir/file"), 8);
+        let excluder = excluder(&[]);
+        let sources = vec![root.clone()];
+        let units = plan_units(&sources, &excluder);
+        let key = cache_key(&sources, &[], false);
+        let estimator = Estimator::default();
+        assert!(estimator.cached(&key, &units).is_none());
+
+        let fresh = estimate(&sources, &excluder);
+        estimator.store(key.clone(), &units, &fresh);
+        let hit = estimator.cached(&key, &units).unwrap();
+        assert!(hit.cached);
+        assert_eq!(hit.total_bytes, 8);
+
+        // A new entry moves the directory's modification time
+        std::thread::sleep(Duration::from_millis(20));
+        write(&root.join("dir/new"), 1);
+        assert!(estimator.cached(&key, &units).is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_one_estimate_runs_at_a_time() {
+        let estimator = Estimator::default();
+        let cancel = estimator.begin().unwrap();
+        assert!(estimator.begin().is_none());
+        estimator.cancel();
+        assert!(cancel.load(Ordering::SeqCst));
+        estimator.finish();
+        let cancel = estimator.begin().unwrap();
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+}
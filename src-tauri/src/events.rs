@@ -0,0 +1,249 @@
+//! Lifecycle events with a replay buffer for webviews that start listening late.
+//!
+//! The UI only attaches listeners once its page has loaded, by which time startup progress
+//! and the initial connection state have usually been emitted. Events sent through [`emit`]
+//! get a sequence number, are retained according to their [`Retention`] policy and go out
+//! twice: under their own name with the plain payload (for existing listeners), and wrapped
+//! with the sequence number as a `bus-event`.
+//!
+//! A page that wants the full picture listens to `bus-event`, then calls `sync_events`. The
+//! replay holds the retained events in order plus the last sequence number it covers; any
+//! `bus-event` at or below that number is already in the replay and is dropped. Recording
+//! and sequence assignment happen under one lock, so nothing falls between the two.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use tauri::{Emitter, Manager};
+
+/// Name of the envelope event carrying sequence numbers
+pub const BUS_EVENT: &str = "bus-event";
+
+/// How many past emissions of an event are kept for replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// State-like events: only the latest value matters
+    Latest,
+    /// Log-like events: the last N emissions, oldest first
+    Window(usize),
+}
+
+/// Retention of every event that goes through the bus
+pub const DEFAULT_POLICIES: &[(&str, Retention)] = &[
+    ("startup-progress", Retention::Window(50)),
+    ("loading-status", Retention::Latest),
+    ("sidecar-terminated", Retention::Window(10)),
+    ("backend-crashed", Retention::Window(5)),
+    ("connection-mode", Retention::Latest),
+    ("backend-mode-changed", Retention::Latest),
+    ("version-mismatch", Retention::Latest),
+    ("capabilities-changed", Retention::Latest),
+    ("background-activity-changed", Retention::Latest),
+    ("clock-skew-detected", Retention::Latest),
+    ("sidecar-assets-missing", Retention::Latest),
+    ("sidecar-integrity-failed", Retention::Latest),
+    ("sandbox-unavailable", Retention::Latest),
+    ("stale-registrations-detected", Retention::Latest),
+    ("legacy-install-detected", Retention::Latest),
+    ("drain-progress", Retention::Latest),
+    ("config-divergence", Retention::Window(5)),
+    ("backend-config-drift", Retention::Window(5)),
+    ("webview-recovered", Retention::Window(5)),
+    ("time-zone-changed", Retention::Latest),
+    ("backend-restarting", Retention::Latest),
+    ("backend-restarted", Retention::Latest),
+    ("backend-pairing-required", Retention::Latest),
+    ("bandwidth-profile-changed", Retention::Latest),
+    ("task-degraded", Retention::Window(10)),
+    ("accessibility-preferences-changed", Retention::Latest),
+    ("settings-recovered", Retention::Latest),
+    ("post-update", Retention::Latest),
+    ("quitting", Retention::Latest),
+    ("backend-health", Retention::Latest),
+    ("backend-unresponsive", Retention::Window(5)),
+    ("backend-watchdog-restart", Retention::Window(5)),
+    ("open-file", Retention::Window(5)),
+    ("backend-action-applied", Retention::Window(10)),
+    ("backend-action-expired", Retention::Window(10)),
+    ("backend-action-rejected", Retention::Window(10)),
+    ("restore-session-started", Retention::Window(10)),
+    ("restore-session-ended", Retention::Window(10)),
+    ("watcher-overflow", Retention::Window(10)),
+    ("watcher-triggered", Retention::Window(10)),
+    ("transfer-progress", Retention::Latest),
+];
+
+/// An emission as recorded by the bus
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BusEvent {
+    pub seq: u64,
+    pub name: String,
+    pub payload: serde_json::Value,
+}
+
+/// Retained events for the requested names, in emission order
+#[derive(Debug, Clone, Serialize)]
+pub struct EventReplay {
+    pub events: Vec<BusEvent>,
+    /// Every event with a sequence number up to this one is covered by the replay
+    pub last_seq: u64,
+}
+
+#[derive(Default)]
+struct BusState {
+    last_seq: u64,
+    retained: HashMap<String, VecDeque<BusEvent>>,
+}
+
+pub struct EventBus {
+    policies: HashMap<String, Retention>,
+    state: Mutex<BusState>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_POLICIES)
+    }
+}
+
+impl EventBus {
+    pub fn new(policies: &[(&str, Retention)]) -> Self {
+        Self {
+            policies: policies
+                .iter()
+                .map(|(name, retention)| (name.to_string(), *retention))
+                .collect(),
+            state: Mutex::new(BusState::default()),
+        }
+    }
+
+    /// Assign the next sequence number and retain the event per its policy. Events without a
+    /// policy are numbered but not kept.
+    pub fn record(&self, name: &str, payload: serde_json::Value) -> BusEvent {
+        let mut state = self.state.lock();
+        state.last_seq += 1;
+        let event = BusEvent {
+            seq: state.last_seq,
+            name: name.to_string(),
+            payload,
+        };
+
+        let limit = match self.policies.get(name) {
+            Some(Retention::Latest) => 1,
+            Some(Retention::Window(size)) => *size,
+            None => 0,
+        };
+        if limit > 0 {
+            let retained = state.retained.entry(name.to_string()).or_default();
+            retained.push_back(event.clone());
+            while retained.len() > limit {
+                retained.pop_front();
+            }
+        }
+        event
+    }
+
+    /// Retained events for `names` (all retained events when empty), ordered by sequence
+    pub fn replay(&self, names: &[String]) -> EventReplay {
+        let state = self.state.lock();
+        let mut events: Vec<BusEvent> = state
+            .retained
+            .iter()
+            .filter(|(name, _)| names.is_empty() || names.contains(name))
+            .flat_map(|(_, retained)| retained.iter().cloned())
+            .collect();
+        events.sort_by_key(|event| event.seq);
+        EventReplay {
+            events,
+            last_seq: state.last_seq,
+        }
+    }
+}
+
+/// Emit `name` to every webview, recording it for replay
+pub fn emit<S: Serialize + Clone>(app: &tauri::AppHandle, name: &str, payload: S) {
+    let value = serde_json::to_value(&payload).unwrap_or(serde_json::Value::Null);
+    let event = app.state::<crate::AppState>().events.record(name, value);
+    let _ = app.emit(name, payload);
+    let _ = app.emit(BUS_EVENT, event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bus() -> EventBus {
+        EventBus::new(&[("state", Retention::Latest), ("log", Retention::Window(3))])
+    }
+
+    #[test]
+    fn sequence_numbers_increase_across_names() {
+        let bus = bus();
+        let seqs: Vec<u64> = ["state", "log", "unknown", "state"]
+            .iter()
+            .map(|name| bus.record(name, json!(null)).seq)
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn state_events_keep_only_the_latest_value() {
+        let bus = bus();
+        for value in 1..=3 {
+            bus.record("state", json!(value));
+        }
+        let replay = bus.replay(&["state".to_string()]);
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].payload, json!(3));
+        assert_eq!(replay.last_seq, 3);
+    }
+
+    #[test]
+    fn log_events_keep_a_window_oldest_first() {
+        let bus = bus();
+        for value in 1..=5 {
+            bus.record("log", json!(value));
+        }
+        let payloads: Vec<_> = bus
+            .replay(&["log".to_string()])
+            .events
+            .into_iter()
+            .map(|event| event.payload)
+            .collect();
+        assert_eq!(payloads, vec![json!(3), json!(4), json!(5)]);
+    }
+
+    #[test]
+    fn events_without_a_policy_are_numbered_but_not_kept() {
+        let bus = bus();
+        bus.record("unknown", json!(1));
+        let replay = bus.replay(&[]);
+        assert!(replay.events.is_empty());
+        assert_eq!(replay.last_seq, 1);
+    }
+
+    #[test]
+    fn replay_interleaves_names_in_emission_order() {
+        let bus = bus();
+        bus.record("log", json!("a"));
+        bus.record("state", json!("b"));
+        bus.record("log", json!("c"));
+        let replay = bus.replay(&[]);
+        let seqs: Vec<u64> = replay.events.iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+
+        // A live event after the replay is numbered past what it covers
+        let live = bus.record("log", json!("d"));
+        assert!(live.seq > replay.last_seq);
+    }
+
+    #[test]
+    fn every_policy_is_listed_once() {
+        let mut names: Vec<&str> = DEFAULT_POLICIES.iter().map(|(name, _)| *name).collect();
+        names.sort_unstable();
+        let count = names.len();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+}
@@ -0,0 +1,350 @@
+//! Fault injection for QA: slow or failing backend calls and full disks, on demand.
+//!
+//! Debug builds and builds with the `fault-injection` cargo feature accept a profile through
+//! `set_fault_profile`. Its rules add latency to, or fail a share of, outbound HTTP calls whose
+//! URL contains a pattern, and make writes under given path prefixes fail as if the disk were
+//! full. Failures are spread deterministically (a 0.25 rate fails every fourth matching call),
+//! so a scenario replays the same way each time. The profile lives in memory only and is gone
+//! after a restart.
+//!
+//! Every injected fault is logged under the `zerobyte::fault_injection` target with an
+//! `[INJECTED]` prefix so captured logs can't be mistaken for real failures. In other builds
+//! the hooks are empty inline functions and the command only reports that the layer is absent.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Whether this build carries the fault-injection layer
+pub const COMPILED: bool = cfg!(any(debug_assertions, feature = "fault-injection"));
+
+/// Delay calls whose URL contains `pattern`
+#[derive(Debug, Clone, Deserialize)]
+pub struct LatencyRule {
+    pub pattern: String,
+    pub delay_ms: u64,
+}
+
+/// Fail a share of the calls whose URL contains `pattern`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorRule {
+    pub pattern: String,
+    /// Between 0 (never) and 1 (always)
+    pub rate: f64,
+}
+
+/// Faults to inject until the next restart
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FaultProfile {
+    pub latency: Vec<LatencyRule>,
+    pub errors: Vec<ErrorRule>,
+    /// Writes under these prefixes fail with a disk-full error
+    pub disk_full: Vec<PathBuf>,
+}
+
+#[cfg(any(debug_assertions, feature = "fault-injection"))]
+mod active {
+    use super::FaultProfile;
+    use parking_lot::Mutex;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const TARGET: &str = "zerobyte::fault_injection";
+
+    struct Injector {
+        profile: FaultProfile,
+        /// Matching calls seen so far, per error rule
+        calls: Vec<u64>,
+    }
+
+    static INJECTOR: Mutex<Option<Injector>> = Mutex::new(None);
+
+    pub fn set_profile(profile: Option<FaultProfile>) {
+        match &profile {
+            Some(profile) => {
+                tracing::warn!(target: TARGET, "[INJECTED] Fault profile set: {:?}", profile)
+            }
+            None => tracing::warn!(target: TARGET, "[INJECTED] Fault profile cleared"),
+        }
+        *INJECTOR.lock() = profile.map(|profile| Injector {
+            calls: vec![0; profile.errors.len()],
+            profile,
+        });
+    }
+
+    /// Whether the `n`th matching call (1-based) fails at `rate`
+    pub fn fails(n: u64, rate: f64) -> bool {
+        let rate = rate.clamp(0.0, 1.0);
+        (n as f64 * rate).floor() > ((n - 1) as f64 * rate).floor()
+    }
+
+    pub async fn before_request(url: &str) -> Result<(), String> {
+        let (delay, failure) = {
+            let mut injector = INJECTOR.lock();
+            let Some(injector) = injector.as_mut() else {
+                return Ok(());
+            };
+            let delay: u64 = injector
+                .profile
+                .latency
+                .iter()
+                .filter(|rule| url.contains(&rule.pattern))
+                .map(|rule| rule.delay_ms)
+                .sum();
+            let mut failure = None;
+            for (rule, calls) in injector.profile.errors.iter().zip(injector.calls.iter_mut()) {
+                if url.contains(&rule.pattern) {
+                    *calls += 1;
+                    if failure.is_none() && fails(*calls, rule.rate) {
+                        failure = Some(rule.pattern.clone());
+                    }
+                }
+            }
+            (delay, failure)
+        };
+
+        if delay > 0 {
+            tracing::warn!(target: TARGET, "[INJECTED] Delaying {} by {} ms", url, delay);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+        match failure {
+            Some(pattern) => {
+                tracing::warn!(target: TARGET, "[INJECTED] Failing {} (rule '{}')", url, pattern);
+                Err(format!("Injected failure for {}", url))
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn disk_full(path: &Path) -> Option<std::io::Error> {
+        let injector = INJECTOR.lock();
+        let prefix = injector
+            .as_ref()?
+            .profile
+            .disk_full
+            .iter()
+            .find(|prefix| path.starts_with(prefix))?;
+        tracing::warn!(
+            target: TARGET,
+            "[INJECTED] Disk full writing {} (prefix {})",
+            path.display(),
+            prefix.display()
+        );
+        #[cfg(target_os = "windows")]
+        const DISK_FULL: i32 = 112; // ERROR_DISK_FULL
+        #[cfg(not(target_os = "windows"))]
+        const DISK_FULL: i32 = 28; // ENOSPC
+        Some(std::io::Error::from_raw_os_error(DISK_FULL))
+    }
+}
+
+/// Install `profile` (or clear it with None)
+pub fn set_profile(profile: Option<FaultProfile>) -> Result<(), String> {
+    #[cfg(any(debug_assertions, feature = "fault-injection"))]
+    {
+        active::set_profile(profile);
+        Ok(())
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "fault-injection")))]
+    {
+        let _ = profile;
+        Err("Fault injection is not available in this build".to_string())
+    }
+}
+
+/// Apply latency and error rules to a call to `url`; an error means the call must fail
+#[inline(always)]
+pub async fn before_request(url: &str) -> Result<(), String> {
+    #[cfg(any(debug_assertions, feature = "fault-injection"))]
+    {
+        active::before_request(url).await
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "fault-injection")))]
+    {
+        let _ = url;
+        Ok(())
+    }
+}
+
+/// The error a write to `path` should fail with, if a disk-full rule covers it
+#[inline(always)]
+pub fn disk_full(path: &std::path::Path) -> Option<std::io::Error> {
+    #[cfg(any(debug_assertions, feature = "fault-injection"))]
+    {
+        active::disk_full(path)
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "fault-injection")))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(debug_assertions, feature = "fault-injection"))]
+    mod injected {
+        use super::super::*;
+        use crate::http::{HttpClient, HttpError, HttpPolicy};
+        use std::time::Duration;
+        use tokio::sync::Mutex;
+
+        /// The injector is global; tests that install a profile take turns
+        static SERIAL: Mutex<()> = Mutex::const_new(());
+
+        /// Matches no URL or path other tests use
+        const PATTERN: &str = "/fault-injection-test/";
+
+        fn errors(rate: f64) -> FaultProfile {
+            FaultProfile {
+                errors: vec![ErrorRule {
+                    pattern: PATTERN.to_string(),
+                    rate,
+                }],
+                ..FaultProfile::default()
+            }
+        }
+
+        #[test]
+        fn failures_are_spread_evenly_by_rate() {
+            let failing = |rate| {
+                (1..=8)
+                    .filter(|n| active::fails(*n, rate))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(failing(0.0), Vec::<u64>::new());
+            assert_eq!(failing(0.25), [4, 8]);
+            assert_eq!(failing(0.5), [2, 4, 6, 8]);
+            assert_eq!(failing(1.0), [1, 2, 3, 4, 5, 6, 7, 8]);
+            // Out-of-range rates are clamped
+            assert_eq!(failing(7.0), failing(1.0));
+            assert_eq!(failing(-1.0), failing(0.0));
+        }
+
+        #[tokio::test]
+        async fn error_rules_fail_matching_calls_at_their_rate() {
+            let _serial = SERIAL.lock().await;
+            set_profile(Some(errors(0.5))).unwrap();
+            let url = format!("http://localhost:1{}status", PATTERN);
+            let outcomes = [
+                before_request(&url).await.is_err(),
+                before_request("http://localhost:1/api/other")
+                    .await
+                    .is_err(),
+                before_request(&url).await.is_err(),
+                before_request(&url).await.is_err(),
+                before_request(&url).await.is_err(),
+            ];
+            set_profile(None).unwrap();
+            assert_eq!(outcomes, [false, false, true, false, true]);
+            assert!(before_request(&url).await.is_ok());
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn latency_rules_add_up_for_matching_calls() {
+            let _serial = SERIAL.lock().await;
+            set_profile(Some(FaultProfile {
+                latency: vec![
+                    LatencyRule {
+                        pattern: PATTERN.to_string(),
+                        delay_ms: 300,
+                    },
+                    LatencyRule {
+                        pattern: "slow".to_string(),
+                        delay_ms: 200,
+                    },
+                ],
+                ..FaultProfile::default()
+            }))
+            .unwrap();
+            let started = tokio::time::Instant::now();
+            before_request(&format!("http://localhost:1{}slow", PATTERN))
+                .await
+                .unwrap();
+            let delayed = started.elapsed();
+            set_profile(None).unwrap();
+            assert_eq!(delayed, Duration::from_millis(500));
+        }
+
+        #[test]
+        fn disk_full_covers_paths_under_its_prefixes() {
+            let _serial = SERIAL.blocking_lock();
+            let prefix = std::env::temp_dir().join(format!("faults-{}", std::process::id()));
+            set_profile(Some(FaultProfile {
+                disk_full: vec![prefix.clone()],
+                ..FaultProfile::default()
+            }))
+            .unwrap();
+            let inside = disk_full(&prefix.join("data/db.sqlite"));
+            let outside = disk_full(&std::env::temp_dir().join("elsewhere"));
+            // Through the path check the app runs on its directories too
+            let checked = crate::paths::ensure_writable(crate::paths::PathKind::Data, &prefix);
+            set_profile(None).unwrap();
+            let _ = std::fs::remove_dir_all(&prefix);
+
+            let error = inside.unwrap();
+            assert_eq!(
+                error.raw_os_error(),
+                Some(if cfg!(windows) { 112 } else { 28 })
+            );
+            assert!(outside.is_none());
+            assert!(checked.is_err());
+        }
+
+        #[tokio::test]
+        async fn http_calls_fail_with_the_injected_error() {
+            let _serial = SERIAL.lock().await;
+            set_profile(Some(errors(1.0))).unwrap();
+            let policy = HttpPolicy {
+                name: "test",
+                connect_timeout: Duration::from_secs(1),
+                request_timeout: Duration::from_secs(1),
+                retries: 2,
+                retry_delay: Duration::ZERO,
+                deadline: Duration::from_secs(5),
+                cancellable: true,
+            };
+            // Nothing listens there; the injected failure comes first
+            let url = format!("http://127.0.0.1:9{}x", PATTERN);
+            let result = HttpClient::default()
+                .send(policy, |client| client.get(&url))
+                .await;
+            set_profile(None).unwrap();
+            assert!(
+                matches!(result, Err(HttpError::Injected(_))),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn layer_is_compiled_only_into_debug_and_fault_injection_builds() {
+        assert_eq!(
+            COMPILED,
+            cfg!(debug_assertions) || cfg!(feature = "fault-injection")
+        );
+    }
+
+    /// Runs under `cargo test --release`: the hooks do nothing and profiles are refused
+    #[cfg(not(any(debug_assertions, feature = "fault-injection")))]
+    #[tokio::test]
+    async fn release_builds_refuse_profiles_and_inject_nothing() {
+        let profile = FaultProfile {
+            errors: vec![ErrorRule {
+                pattern: String::new(),
+                rate: 1.0,
+            }],
+            disk_full: vec![std::path::PathBuf::from("/")],
+            ..FaultProfile::default()
+        };
+        assert!(set_profile(Some(profile)).is_err());
+        assert!(before_request("http://localhost:1/").await.is_ok());
+        assert!(disk_full(std::path::Path::new("/any/path")).is_none());
+    }
+}
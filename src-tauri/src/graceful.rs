@@ -0,0 +1,216 @@
+//! Graceful shutdown helpers shared by the desktop app and the Windows Service.
+//!
+//! Waiting for a process to exit on its own is done in small poll steps, with a progress
+//! callback fired at a fixed interval so callers can keep the OS informed (SCM checkpoints,
+//! shutdown block reasons) while the server is still flushing its database. A server that
+//! isn't our child, like the Windows Service seen from the desktop, is waited on through its
+//! port with [`wait_for_port_closed`].
+
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+/// How long one connection attempt of [`PortClosed`] may take
+const PORT_PROBE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Something that can report whether a process has exited
+pub trait ExitProbe {
+    /// Returns true once the process is no longer running
+    fn has_exited(&mut self) -> bool;
+}
+
+impl ExitProbe for std::process::Child {
+    fn has_exited(&mut self) -> bool {
+        // An error means we can no longer observe the process, so there is nothing to wait on
+        !matches!(self.try_wait(), Ok(None))
+    }
+}
+
+/// A server's loopback port, which counts as exited once nothing accepts connections on it
+pub struct PortClosed(pub u16);
+
+impl ExitProbe for PortClosed {
+    fn has_exited(&mut self) -> bool {
+        let address = SocketAddr::from(([127, 0, 0, 1], self.0));
+        TcpStream::connect_timeout(&address, PORT_PROBE_TIMEOUT).is_err()
+    }
+}
+
+/// Block until nothing accepts connections on `port` or `timeout` passes
+pub fn wait_for_port_closed(port: u16, timeout: Duration) -> WaitOutcome {
+    GracefulWait::with_hard_cap(timeout).wait(&mut PortClosed(port), |_| {})
+}
+
+/// Progress reported while still waiting for the process to exit
+#[derive(Debug, Clone, Copy)]
+pub struct WaitProgress {
+    /// Incremented on every progress report, starting at 1
+    pub checkpoint: u32,
+    /// Time spent waiting so far
+    pub elapsed: Duration,
+    /// Time left before the hard cap is reached
+    pub remaining: Duration,
+}
+
+/// How a graceful wait ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitOutcome {
+    /// The process exited on its own
+    Exited { elapsed: Duration, checkpoints: u32 },
+    /// The hard cap was reached while the process was still running
+    CapReached { elapsed: Duration, checkpoints: u32 },
+}
+
+impl WaitOutcome {
+    pub fn exited(&self) -> bool {
+        matches!(self, WaitOutcome::Exited { .. })
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match self {
+            WaitOutcome::Exited { elapsed, .. } | WaitOutcome::CapReached { elapsed, .. } => {
+                *elapsed
+            }
+        }
+    }
+}
+
+/// Timing parameters for waiting on a process to exit gracefully
+#[derive(Debug, Clone, Copy)]
+pub struct GracefulWait {
+    /// How often to check whether the process has exited
+    pub poll_interval: Duration,
+    /// How often to report progress while the process is still running
+    pub progress_interval: Duration,
+    /// Stop waiting after this long, regardless of progress
+    pub hard_cap: Duration,
+}
+
+impl Default for GracefulWait {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(250),
+            progress_interval: Duration::from_secs(2),
+            hard_cap: Duration::from_secs(30),
+        }
+    }
+}
+
+impl GracefulWait {
+    pub fn with_hard_cap(hard_cap: Duration) -> Self {
+        Self {
+            hard_cap,
+            ..Self::default()
+        }
+    }
+
+    /// Block the current thread until the process exits or the hard cap is reached,
+    /// calling `on_progress` every `progress_interval` while waiting
+    pub fn wait<P, F>(&self, probe: &mut P, mut on_progress: F) -> WaitOutcome
+    where
+        P: ExitProbe + ?Sized,
+        F: FnMut(&WaitProgress),
+    {
+        let start = Instant::now();
+        let mut checkpoints = 0;
+        let mut next_progress = self.progress_interval;
+
+        loop {
+            let elapsed = start.elapsed();
+
+            if probe.has_exited() {
+                return WaitOutcome::Exited {
+                    elapsed,
+                    checkpoints,
+                };
+            }
+
+            if elapsed >= self.hard_cap {
+                return WaitOutcome::CapReached {
+                    elapsed,
+                    checkpoints,
+                };
+            }
+
+            if elapsed >= next_progress {
+                checkpoints += 1;
+                next_progress += self.progress_interval;
+                on_progress(&WaitProgress {
+                    checkpoint: checkpoints,
+                    elapsed,
+                    remaining: self.hard_cap - elapsed,
+                });
+            }
+
+            std::thread::sleep(self.poll_interval.min(self.hard_cap - elapsed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A child that exits after being polled a number of times
+    struct SlowChild {
+        polls_left: u32,
+    }
+
+    impl ExitProbe for SlowChild {
+        fn has_exited(&mut self) -> bool {
+            if self.polls_left == 0 {
+                return true;
+            }
+            self.polls_left -= 1;
+            false
+        }
+    }
+
+    fn quick_wait(hard_cap: Duration) -> GracefulWait {
+        GracefulWait {
+            poll_interval: Duration::from_millis(5),
+            progress_interval: Duration::from_millis(20),
+            hard_cap,
+        }
+    }
+
+    #[test]
+    fn slow_child_exits_with_progress_reported() {
+        let mut child = SlowChild { polls_left: 20 };
+        let mut reports = Vec::new();
+        let outcome =
+            quick_wait(Duration::from_secs(5)).wait(&mut child, |progress| reports.push(*progress));
+        assert!(outcome.exited());
+        let WaitOutcome::Exited { checkpoints, .. } = outcome else {
+            unreachable!()
+        };
+        assert!(checkpoints >= 1);
+        assert_eq!(reports.len() as u32, checkpoints);
+        for (i, report) in reports.iter().enumerate() {
+            assert_eq!(report.checkpoint, i as u32 + 1);
+            assert_eq!(report.elapsed + report.remaining, Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn child_that_never_exits_hits_the_cap() {
+        let mut child = SlowChild {
+            polls_left: u32::MAX,
+        };
+        let hard_cap = Duration::from_millis(60);
+        let outcome = quick_wait(hard_cap).wait(&mut child, |_| {});
+        assert!(!outcome.exited());
+        assert!(outcome.elapsed() >= hard_cap);
+    }
+
+    #[test]
+    fn exited_child_reports_no_progress() {
+        let mut child = SlowChild { polls_left: 0 };
+        let outcome = quick_wait(Duration::from_secs(5)).wait(&mut child, |_| {
+            panic!("progress reported for a child that had exited")
+        });
+        assert!(matches!(
+            outcome,
+            WaitOutcome::Exited { checkpoints: 0, .. }
+        ));
+    }
+}
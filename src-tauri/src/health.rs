@@ -0,0 +1,369 @@
+//! Backend health history that survives app restarts.
+//!
+//! Sidecar crashes, failed starts and watchdog incidents are appended to `health-history.json`
+//! in the app data directory. Without it, a backend that crashes every few minutes looks
+//! healthy again after each relaunch of the app, and crash-loop detection never engages; with
+//! it, [`HealthHistory::crash_loop`] counts what happened before this run too. Entries older
+//! than the retention window (a week unless settings say otherwise) are pruned on load and on
+//! every write, and the file is capped at [`MAX_ENTRIES`].
+
+use crate::persist;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File name of the history in the app data directory
+pub const HISTORY_FILE: &str = "health-history.json";
+
+/// How long entries are kept when settings don't say
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Most entries kept, oldest dropped first
+pub const MAX_ENTRIES: usize = 500;
+
+/// Crashes within this window...
+pub const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// ...at least this many times count as a crash loop
+pub const CRASH_LOOP_THRESHOLD: usize = 3;
+
+/// How the backend went down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+    /// Exited with status 0 without being asked to
+    UnexpectedExit,
+    /// Exited with a non-zero status
+    ErrorExit,
+    /// Killed by a signal or the OS
+    Killed,
+    /// Never became ready
+    StartupFailure,
+}
+
+impl CrashKind {
+    pub const ALL: [CrashKind; 4] = [
+        CrashKind::UnexpectedExit,
+        CrashKind::ErrorExit,
+        CrashKind::Killed,
+        CrashKind::StartupFailure,
+    ];
+
+    /// Name as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CrashKind::UnexpectedExit => "unexpected_exit",
+            CrashKind::ErrorExit => "error_exit",
+            CrashKind::Killed => "killed",
+            CrashKind::StartupFailure => "startup_failure",
+        }
+    }
+
+    /// Classify an exit the app didn't ask for
+    pub fn from_exit(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => CrashKind::UnexpectedExit,
+            Some(_) => CrashKind::ErrorExit,
+            None => CrashKind::Killed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HealthEvent {
+    /// The sidecar was (re)started
+    Restart { generation: u64 },
+    Crash {
+        kind: CrashKind,
+        generation: u64,
+        code: Option<i32>,
+    },
+    /// The watchdog found the backend unresponsive
+    WatchdogIncident { detail: String },
+}
+
+impl HealthEvent {
+    fn is_crash(&self) -> bool {
+        matches!(self, HealthEvent::Crash { .. })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthEntry {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: HealthEvent,
+}
+
+/// Time range for a history query, in seconds since the Unix epoch (both ends inclusive)
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryRange {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl HistoryRange {
+    fn contains(&self, at: u64) -> bool {
+        !matches!(self.since, Some(since) if at < since)
+            && !matches!(self.until, Some(until) if at > until)
+    }
+}
+
+/// Entries in the order they happened
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthHistory {
+    pub entries: Vec<HealthEntry>,
+}
+
+impl HealthHistory {
+    /// Drop entries older than `retention` and any beyond [`MAX_ENTRIES`]
+    pub fn prune(&mut self, now: u64, retention: Duration) {
+        let cutoff = now.saturating_sub(retention.as_secs());
+        self.entries.retain(|entry| entry.at >= cutoff);
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+    }
+
+    pub fn range(&self, range: HistoryRange) -> Vec<HealthEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| range.contains(entry.at))
+            .cloned()
+            .collect()
+    }
+
+    /// Crashes in the `window` before `now`
+    pub fn crashes_within(&self, now: u64, window: Duration) -> usize {
+        let since = now.saturating_sub(window.as_secs());
+        self.entries
+            .iter()
+            .filter(|entry| entry.at >= since && entry.event.is_crash())
+            .count()
+    }
+
+    /// Whether the backend crashed often enough lately to count as a crash loop
+    pub fn crash_loop(&self, now: u64) -> bool {
+        self.crashes_within(now, CRASH_LOOP_WINDOW) >= CRASH_LOOP_THRESHOLD
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The persisted history, saved back on every entry
+pub struct HealthLog {
+    path: PathBuf,
+    retention: Duration,
+    history: Mutex<HealthHistory>,
+}
+
+impl HealthLog {
+    /// Load the history from `path`, pruned to `retention`; a damaged file starts it over
+    pub fn load(path: PathBuf, retention: Duration) -> Self {
+        let (mut history, source) = persist::load_json::<HealthHistory>(&path);
+        if source.recovered() {
+            tracing::warn!("Health history {} was damaged ({:?})", path.display(), source);
+        }
+        history.prune(now(), retention);
+        Self {
+            path,
+            retention,
+            history: Mutex::new(history),
+        }
+    }
+
+    pub fn snapshot(&self) -> HealthHistory {
+        self.history.lock().clone()
+    }
+
+    /// Append an event. A failed write is logged; the entry still counts for this run.
+    pub fn record(&self, event: HealthEvent) {
+        let now = now();
+        let mut history = self.history.lock();
+        history.entries.push(HealthEntry { at: now, event });
+        history.prune(now, self.retention);
+        if let Err(e) = persist::save_json(&self.path, &*history) {
+            tracing::warn!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60;
+    const DAY: u64 = 24 * 60 * MINUTE;
+
+    fn crash(at: u64) -> HealthEntry {
+        HealthEntry {
+            at,
+            event: HealthEvent::Crash {
+                kind: CrashKind::ErrorExit,
+                generation: 1,
+                code: Some(1),
+            },
+        }
+    }
+
+    fn restart(at: u64) -> HealthEntry {
+        HealthEntry {
+            at,
+            event: HealthEvent::Restart { generation: 2 },
+        }
+    }
+
+    fn history(entries: Vec<HealthEntry>) -> HealthHistory {
+        HealthHistory { entries }
+    }
+
+    #[test]
+    fn entries_serialize_flat() {
+        let entry = HealthEntry {
+            at: 1_700_000_000,
+            event: HealthEvent::Crash {
+                kind: CrashKind::Killed,
+                generation: 3,
+                code: None,
+            },
+        };
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "at": 1_700_000_000u64,
+                "type": "crash",
+                "kind": "killed",
+                "generation": 3,
+                "code": null,
+            })
+        );
+        assert_eq!(serde_json::from_value::<HealthEntry>(json).unwrap(), entry);
+    }
+
+    #[test]
+    fn crash_kind_names_match_serde() {
+        for kind in CrashKind::ALL {
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::json!(kind.as_str())
+            );
+        }
+        assert_eq!(CrashKind::from_exit(Some(0)), CrashKind::UnexpectedExit);
+        assert_eq!(CrashKind::from_exit(Some(3)), CrashKind::ErrorExit);
+        assert_eq!(CrashKind::from_exit(None), CrashKind::Killed);
+    }
+
+    #[test]
+    fn prune_drops_entries_past_retention() {
+        let now = 30 * DAY;
+        let mut history = history(vec![crash(now - 8 * DAY), crash(now - 7 * DAY), crash(now)]);
+        history.prune(now, DEFAULT_RETENTION);
+        let kept: Vec<u64> = history.entries.iter().map(|entry| entry.at).collect();
+        assert_eq!(kept, vec![now - 7 * DAY, now]);
+    }
+
+    #[test]
+    fn prune_caps_the_entry_count_keeping_the_newest() {
+        let now = 30 * DAY;
+        let mut history = history(
+            (0..MAX_ENTRIES as u64 + 20)
+                .map(|i| restart(now - 1000 + i))
+                .collect(),
+        );
+        history.prune(now, DEFAULT_RETENTION);
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0].at, now - 1000 + 20);
+    }
+
+    #[test]
+    fn range_is_inclusive_at_both_ends() {
+        let history = history((1..=5).map(|i| restart(i * MINUTE)).collect());
+        let range = HistoryRange {
+            since: Some(2 * MINUTE),
+            until: Some(4 * MINUTE),
+        };
+        assert_eq!(history.range(range).len(), 3);
+        assert_eq!(history.range(HistoryRange::default()).len(), 5);
+    }
+
+    #[test]
+    fn crash_loop_needs_enough_recent_crashes() {
+        let now = 10 * DAY;
+        // A backend that crashes every 10 minutes, with restarts in between
+        let flapping = history(
+            (0..3)
+                .flat_map(|i| {
+                    [
+                        crash(now - i * 10 * MINUTE),
+                        restart(now - i * 10 * MINUTE + 5),
+                    ]
+                })
+                .collect(),
+        );
+        assert!(flapping.crash_loop(now));
+
+        // The same crashes spread over a day are not a loop
+        let spread = history((0..3).map(|i| crash(now - i * 8 * 60 * MINUTE)).collect());
+        assert!(!spread.crash_loop(now));
+
+        // Restarts alone never are
+        let restarts = history((0..10).map(|i| restart(now - i * MINUTE)).collect());
+        assert!(!restarts.crash_loop(now));
+    }
+
+    #[test]
+    fn crash_loop_is_detected_across_a_relaunch() {
+        let path =
+            std::env::temp_dir().join(format!("health-{}-relaunch.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+
+        let now = now();
+        let earlier = history(vec![crash(now - 20 * MINUTE), crash(now - 10 * MINUTE)]);
+        persist::save_json(&path, &earlier).unwrap();
+
+        // The previous run's crashes are loaded; one more crash in this run makes a loop
+        let log = HealthLog::load(path.clone(), DEFAULT_RETENTION);
+        assert!(!log.snapshot().crash_loop(now));
+        log.record(HealthEvent::Crash {
+            kind: CrashKind::ErrorExit,
+            generation: 1,
+            code: Some(1),
+        });
+        assert!(log.snapshot().crash_loop(now));
+
+        // And the new crash is on disk for the next run
+        let reloaded = HealthLog::load(path.clone(), DEFAULT_RETENTION);
+        assert_eq!(reloaded.snapshot().entries.len(), 3);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+    }
+
+    #[test]
+    fn load_prunes_an_old_history() {
+        let path = std::env::temp_dir().join(format!("health-{}-old.json", std::process::id()));
+        let now = now();
+        persist::save_json(
+            &path,
+            &history(vec![crash(now - 30 * DAY), crash(now - DAY)]),
+        )
+        .unwrap();
+
+        let log = HealthLog::load(path.clone(), DEFAULT_RETENTION);
+        assert_eq!(log.snapshot().entries, vec![crash(now - DAY)]);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+    }
+}
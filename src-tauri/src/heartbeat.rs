@@ -0,0 +1,315 @@
+//! Noticing a dead webview renderer and bringing the window back.
+//!
+//! When the WebView2 renderer crashes (usually a GPU driver problem) the window turns white
+//! and stays that way; Tauri doesn't surface the renderer's process-failed notification. So a
+//! script injected into each page pings `webview_heartbeat` with its URL every few seconds,
+//! and a monitor reloads a visible window whose pings stopped, at the URL it last reported.
+//! Hidden and minimized windows are skipped since browsers throttle their timers. Recoveries
+//! are capped per hour so a page that kills its renderer on load can't loop forever.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tracing::{error, info};
+
+/// How often pages ping
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Silence after which a visible window counts as dead
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Recoveries allowed per window within [`RECOVERY_WINDOW`]
+pub const MAX_RECOVERIES: usize = 3;
+
+pub const RECOVERY_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Script injected into every page of a monitored window
+pub fn script() -> String {
+    format!(
+        concat!(
+            "if (!window.__ZEROBYTE_HEARTBEAT__) {{",
+            " window.__ZEROBYTE_HEARTBEAT__ = setInterval(function () {{",
+            " var tauri = window.__TAURI_INTERNALS__;",
+            " if (tauri) tauri.invoke('webview_heartbeat', {{ url: location.href }})",
+            ".catch(function () {{}});",
+            " }}, {}); }}",
+        ),
+        INTERVAL.as_millis()
+    )
+}
+
+/// Caps how often a window is recovered
+#[derive(Debug, Default)]
+pub struct RecoveryLimiter {
+    attempts: VecDeque<Instant>,
+}
+
+impl RecoveryLimiter {
+    /// Record an attempt at `now` if one is still allowed
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        while self
+            .attempts
+            .front()
+            .is_some_and(|attempt| now.duration_since(*attempt) >= RECOVERY_WINDOW)
+        {
+            self.attempts.pop_front();
+        }
+        if self.attempts.len() >= MAX_RECOVERIES {
+            return false;
+        }
+        self.attempts.push_back(now);
+        true
+    }
+}
+
+#[derive(Debug)]
+struct WindowBeat {
+    last: Instant,
+    url: String,
+    limiter: RecoveryLimiter,
+    /// Out of recoveries; reported once, then left alone until it beats again
+    gave_up: bool,
+}
+
+/// Payload of the `webview-recovered` event
+#[derive(Debug, Clone, Serialize)]
+pub struct WebviewRecovered {
+    pub window: String,
+    pub url: String,
+    pub silent_secs: u64,
+}
+
+/// Last ping per window
+#[derive(Debug, Default)]
+pub struct HeartbeatMonitor {
+    windows: Mutex<HashMap<String, WindowBeat>>,
+}
+
+impl HeartbeatMonitor {
+    pub fn beat(&self, window: &str, url: &str, now: Instant) {
+        let mut windows = self.windows.lock();
+        let beat = windows
+            .entry(window.to_string())
+            .or_insert_with(|| WindowBeat {
+                last: now,
+                url: String::new(),
+                limiter: RecoveryLimiter::default(),
+                gave_up: false,
+            });
+        beat.last = now;
+        beat.url = url.to_string();
+        beat.gave_up = false;
+    }
+
+    /// Windows among `candidates` silent for `timeout` at `now`, with their last URL. Each is
+    /// returned once per silence: the clock restarts so a reload gets a full timeout to beat.
+    pub fn silent(
+        &self,
+        candidates: &[String],
+        now: Instant,
+        timeout: Duration,
+    ) -> Vec<(String, String, Duration)> {
+        let mut windows = self.windows.lock();
+        candidates
+            .iter()
+            .filter_map(|label| {
+                let beat = windows.get_mut(label)?;
+                let silence = now.duration_since(beat.last);
+                if silence < timeout {
+                    return None;
+                }
+                beat.last = now;
+                Some((label.clone(), beat.url.clone(), silence))
+            })
+            .collect()
+    }
+
+    /// Restart the clock of windows that aren't watched right now (hidden or minimized), so
+    /// they get a full timeout once they're shown again
+    pub fn hold(&self, windows: &[String], now: Instant) {
+        let mut beats = self.windows.lock();
+        for label in windows {
+            if let Some(beat) = beats.get_mut(label) {
+                beat.last = now;
+            }
+        }
+    }
+
+    /// Whether `window` may be recovered again; logs once when it's out of attempts
+    fn allow_recovery(&self, window: &str, now: Instant) -> bool {
+        let mut windows = self.windows.lock();
+        let Some(beat) = windows.get_mut(window) else {
+            return false;
+        };
+        if beat.limiter.try_acquire(now) {
+            return true;
+        }
+        if !beat.gave_up {
+            beat.gave_up = true;
+            error!(
+                "Window '{}' stopped responding again; giving up after {} recoveries this hour",
+                window, MAX_RECOVERIES
+            );
+        }
+        false
+    }
+}
+
+/// Reload windows whose renderer went quiet, for as long as the app runs
+pub async fn monitor(app: tauri::AppHandle) {
+    let mut ticker = app.state::<crate::AppState>().activity.register(
+        "webview-heartbeat",
+        crate::activity::TaskClass::Critical,
+        INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+
+        let (visible, hidden): (Vec<_>, Vec<_>) =
+            app.webview_windows().into_iter().partition(|(_, window)| {
+                window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(true)
+            });
+        let visible: Vec<String> = visible.into_iter().map(|(label, _)| label).collect();
+        let hidden: Vec<String> = hidden.into_iter().map(|(label, _)| label).collect();
+        let state = app.state::<crate::AppState>();
+        let now = Instant::now();
+        state.heartbeats.hold(&hidden, now);
+        for (label, url, silence) in state.heartbeats.silent(&visible, now, TIMEOUT) {
+            if !state.heartbeats.allow_recovery(&label, now) {
+                continue;
+            }
+            let (Some(window), Ok(target)) =
+                (app.get_webview_window(&label), url.parse::<tauri::Url>())
+            else {
+                continue;
+            };
+            // Logged as an error so it shows up in the system report
+            error!(
+                "Webview '{}' stopped responding for {:?}, reloading {}",
+                label, silence, url
+            );
+            match window.navigate(target) {
+                Ok(()) => {
+                    info!("Reloaded webview '{}'", label);
+                    crate::events::emit(
+                        &app,
+                        "webview-recovered",
+                        WebviewRecovered {
+                            window: label,
+                            url,
+                            silent_secs: silence.as_secs(),
+                        },
+                    );
+                }
+                Err(e) => error!("Failed to reload webview '{}': {}", label, e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn beating_window_is_not_silent() {
+        let monitor = HeartbeatMonitor::default();
+        let start = Instant::now();
+        for i in 0..10 {
+            monitor.beat("main", "tauri://localhost/", start + INTERVAL * i);
+        }
+        let now = start + INTERVAL * 10;
+        assert!(monitor.silent(&labels(&["main"]), now, TIMEOUT).is_empty());
+    }
+
+    #[test]
+    fn silent_window_is_reported_once_per_timeout() {
+        let monitor = HeartbeatMonitor::default();
+        let start = Instant::now();
+        monitor.beat("main", "http://localhost:4096/backups", start);
+
+        let main = labels(&["main"]);
+        assert!(monitor
+            .silent(&main, start + TIMEOUT - SECOND, TIMEOUT)
+            .is_empty());
+        assert_eq!(
+            monitor.silent(&main, start + TIMEOUT, TIMEOUT),
+            vec![(
+                "main".to_string(),
+                "http://localhost:4096/backups".to_string(),
+                TIMEOUT
+            )]
+        );
+        // The reload gets a full timeout before it's reported again
+        assert!(monitor
+            .silent(&main, start + TIMEOUT + 5 * SECOND, TIMEOUT)
+            .is_empty());
+        assert_eq!(monitor.silent(&main, start + 2 * TIMEOUT, TIMEOUT).len(), 1);
+    }
+
+    #[test]
+    fn windows_that_never_beat_are_ignored() {
+        let monitor = HeartbeatMonitor::default();
+        let later = Instant::now() + 10 * TIMEOUT;
+        assert!(monitor
+            .silent(&labels(&["settings"]), later, TIMEOUT)
+            .is_empty());
+    }
+
+    #[test]
+    fn held_windows_get_a_full_timeout_when_shown() {
+        let monitor = HeartbeatMonitor::default();
+        let start = Instant::now();
+        monitor.beat("main", "tauri://localhost/", start);
+
+        // Minimized for a while: its timers are throttled, so it's held rather than checked
+        monitor.hold(&labels(&["main"]), start + 5 * TIMEOUT);
+        let shown = start + 5 * TIMEOUT + SECOND;
+        assert!(monitor
+            .silent(&labels(&["main"]), shown, TIMEOUT)
+            .is_empty());
+    }
+
+    #[test]
+    fn limiter_allows_a_few_recoveries_per_window() {
+        let mut limiter = RecoveryLimiter::default();
+        let start = Instant::now();
+        for i in 0..MAX_RECOVERIES as u32 {
+            assert!(limiter.try_acquire(start + TIMEOUT * i));
+        }
+        assert!(!limiter.try_acquire(start + TIMEOUT * MAX_RECOVERIES as u32));
+
+        // The oldest attempt ages out of the window
+        assert!(limiter.try_acquire(start + RECOVERY_WINDOW));
+        assert!(!limiter.try_acquire(start + RECOVERY_WINDOW + SECOND));
+    }
+
+    #[test]
+    fn recovery_limits_are_per_window() {
+        let monitor = HeartbeatMonitor::default();
+        let now = Instant::now();
+        monitor.beat("main", "a", now);
+        monitor.beat("about", "b", now);
+        for _ in 0..MAX_RECOVERIES {
+            assert!(monitor.allow_recovery("main", now));
+        }
+        assert!(!monitor.allow_recovery("main", now));
+        assert!(monitor.allow_recovery("about", now));
+        assert!(!monitor.allow_recovery("unknown", now));
+    }
+
+    #[test]
+    fn script_pings_at_the_interval() {
+        let script = script();
+        assert!(script.contains("'webview_heartbeat'"));
+        assert!(script.contains(&format!("}}, {}); }}", INTERVAL.as_millis())));
+    }
+}
@@ -0,0 +1,499 @@
+//! Outbound HTTP with explicit timeout budgets and app-wide cancellation.
+//!
+//! Every call names an [`HttpPolicy`] preset that bounds connecting, each attempt, the number
+//! of retries and the total time spent. Calls are interrupted as soon as the app starts
+//! quitting, except the ones (like the shutdown request itself) whose policy opts out.
+
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// Timeouts and retries for one kind of outbound call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpPolicy {
+    pub name: &'static str,
+    pub connect_timeout: Duration,
+    /// Limit for a single attempt
+    pub request_timeout: Duration,
+    /// Extra attempts after a transport failure (HTTP error statuses are never retried)
+    pub retries: u32,
+    pub retry_delay: Duration,
+    /// Limit for all attempts together; an attempt never outlives it
+    pub deadline: Duration,
+    /// Whether quitting the app aborts the call
+    pub cancellable: bool,
+}
+
+impl HttpPolicy {
+    /// Single readiness probe while the backend starts (the caller owns the polling loop)
+    pub const STARTUP: HttpPolicy = HttpPolicy {
+        name: "startup",
+        connect_timeout: Duration::from_secs(1),
+        request_timeout: Duration::from_secs(2),
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        deadline: Duration::from_secs(2),
+        cancellable: true,
+    };
+
+    /// Periodic liveness checks; must fail fast so a hung backend is noticed
+    pub const WATCHDOG: HttpPolicy = HttpPolicy {
+        name: "watchdog",
+        connect_timeout: Duration::from_secs(1),
+        request_timeout: Duration::from_secs(2),
+        retries: 1,
+        retry_delay: Duration::from_millis(250),
+        deadline: Duration::from_secs(5),
+        cancellable: true,
+    };
+
+    /// Asking the backend to stop; sent while quitting, so it isn't cancellable
+    pub const SHUTDOWN: HttpPolicy = HttpPolicy {
+        name: "shutdown",
+        connect_timeout: Duration::from_millis(500),
+        request_timeout: Duration::from_secs(3),
+        retries: 0,
+        retry_delay: Duration::ZERO,
+        deadline: Duration::from_secs(3),
+        cancellable: false,
+    };
+
+    /// Calls the user is waiting on
+    pub const INTERACTIVE: HttpPolicy = HttpPolicy {
+        name: "interactive",
+        connect_timeout: Duration::from_secs(2),
+        request_timeout: Duration::from_secs(10),
+        retries: 1,
+        retry_delay: Duration::from_millis(500),
+        deadline: Duration::from_secs(15),
+        cancellable: true,
+    };
+
+    /// Work nobody is waiting on (queued actions, samplers)
+    pub const BACKGROUND: HttpPolicy = HttpPolicy {
+        name: "background",
+        connect_timeout: Duration::from_secs(2),
+        request_timeout: Duration::from_secs(5),
+        retries: 2,
+        retry_delay: Duration::from_secs(1),
+        deadline: Duration::from_secs(20),
+        cancellable: true,
+    };
+
+    /// Timeout for the next attempt given the time left before the deadline, or None if
+    /// the deadline has already passed
+    pub fn attempt_timeout(&self, remaining: Duration) -> Option<Duration> {
+        (!remaining.is_zero()).then(|| self.request_timeout.min(remaining))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    #[error("Request cancelled because the app is quitting")]
+    Cancelled,
+    #[error("Request did not complete within its {0:?} budget")]
+    DeadlineExceeded(Duration),
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+    /// Failure injected by a QA fault profile
+    #[cfg(any(debug_assertions, feature = "fault-injection"))]
+    #[error("{0}")]
+    Injected(String),
+}
+
+/// URL of `path` (starting with `/`) on the local backend listening on `port`
+pub fn local_url(port: u16, path: &str) -> String {
+    format!("http://localhost:{}{}", port, path)
+}
+
+/// The app's HTTP client: one reqwest client per connect timeout plus the quit token
+pub struct HttpClient {
+    clients: Mutex<HashMap<Duration, reqwest::Client>>,
+    cancel: CancellationToken,
+    /// Bearer token for a backend that requires pairing; only sent to loopback hosts
+    bearer: RwLock<Option<String>>,
+    /// Shared secret of the local backend (see `backend_token`); only sent to loopback hosts
+    backend_token: RwLock<Option<String>>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+            cancel: CancellationToken::new(),
+            bearer: RwLock::new(None),
+            backend_token: RwLock::new(None),
+        }
+    }
+}
+
+impl HttpClient {
+    /// Abort every in-flight and future cancellable call
+    pub fn cancel_all(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// A token that is cancelled when the app quits, for work that manages its own requests
+    pub fn child_token(&self) -> CancellationToken {
+        self.cancel.child_token()
+    }
+
+    /// Use `token` as the bearer token for calls to the local backend, or stop sending one
+    pub fn set_bearer(&self, token: Option<String>) {
+        *self.bearer.write() = token;
+    }
+
+    pub fn has_bearer(&self) -> bool {
+        self.bearer.read().is_some()
+    }
+
+    /// Send `token` as the local backend's shared secret, or stop sending one
+    pub fn set_backend_token(&self, token: Option<String>) {
+        *self.backend_token.write() = token;
+    }
+
+    pub fn backend_token(&self) -> Option<String> {
+        self.backend_token.read().clone()
+    }
+
+    /// Add the shared secret, and the bearer token unless the request carries its own
+    /// credentials, to a request for the local backend. Requests to other hosts (discovered
+    /// LAN servers) never get either.
+    fn authorize(&self, mut request: reqwest::Request) -> reqwest::Request {
+        let local = matches!(
+            request.url().host_str(),
+            Some("localhost" | "127.0.0.1" | "[::1]")
+        );
+        if !local {
+            return request;
+        }
+        if let Some(token) = self.backend_token.read().as_deref() {
+            if let Ok(value) = token.parse() {
+                request
+                    .headers_mut()
+                    .insert(crate::backend_token::TOKEN_HEADER, value);
+            }
+        }
+        if request.headers().contains_key(reqwest::header::AUTHORIZATION) {
+            return request;
+        }
+        if let Some(token) = self.bearer.read().as_deref() {
+            if let Ok(value) = format!("Bearer {}", token).parse() {
+                request
+                    .headers_mut()
+                    .insert(reqwest::header::AUTHORIZATION, value);
+            }
+        }
+        request
+    }
+
+    fn client(&self, connect_timeout: Duration) -> reqwest::Client {
+        self.clients
+            .lock()
+            .entry(connect_timeout)
+            .or_insert_with(|| {
+                reqwest::Client::builder()
+                    .connect_timeout(connect_timeout)
+                    .build()
+                    .unwrap_or_default()
+            })
+            .clone()
+    }
+
+    /// GET `/healthcheck` on the local backend listening on `port`
+    pub async fn healthcheck(
+        &self,
+        port: u16,
+        policy: HttpPolicy,
+    ) -> Result<reqwest::Response, HttpError> {
+        let url = local_url(port, "/healthcheck");
+        self.send(policy, |client| client.get(&url)).await
+    }
+
+    /// Ask the local backend listening on `port` to shut down
+    pub async fn shutdown(&self, port: u16) -> Result<reqwest::Response, HttpError> {
+        let url = local_url(port, "/api/shutdown");
+        self.send(HttpPolicy::SHUTDOWN, |client| client.post(&url))
+            .await
+    }
+
+    /// Send the request built by `build` under `policy`. `build` runs once per attempt.
+    pub async fn send(
+        &self,
+        policy: HttpPolicy,
+        build: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, HttpError> {
+        let client = self.client(policy.connect_timeout);
+        let started = Instant::now();
+        let deadline = started + policy.deadline;
+        let mut last_error = None;
+        #[cfg(any(debug_assertions, feature = "fault-injection"))]
+        let (fault_url, mut injected) = (
+            build(&client)
+                .build()
+                .map(|request| request.url().to_string())
+                .unwrap_or_default(),
+            None,
+        );
+
+        for attempt in 0..=policy.retries {
+            let Some(timeout) =
+                policy.attempt_timeout(deadline.saturating_duration_since(Instant::now()))
+            else {
+                break;
+            };
+
+            #[cfg(any(debug_assertions, feature = "fault-injection"))]
+            let skip = match crate::faults::before_request(&fault_url).await {
+                Ok(()) => false,
+                Err(e) => {
+                    injected = Some(e);
+                    true
+                }
+            };
+            #[cfg(not(any(debug_assertions, feature = "fault-injection")))]
+            let skip = false;
+
+            let result = if skip {
+                None
+            } else {
+                let request = async {
+                    let request = build(&client).timeout(timeout).build()?;
+                    client.execute(self.authorize(request)).await
+                };
+                Some(if policy.cancellable {
+                    tokio::select! {
+                        _ = self.cancel.cancelled() => return Err(HttpError::Cancelled),
+                        result = request => result,
+                    }
+                } else {
+                    request.await
+                })
+            };
+
+            match result {
+                None => {}
+                Some(Ok(response)) => {
+                    debug!(
+                        "HTTP {} call finished in {:?} (attempt {})",
+                        policy.name,
+                        started.elapsed(),
+                        attempt + 1
+                    );
+                    return Ok(response);
+                }
+                Some(Err(e)) => last_error = Some(e),
+            }
+
+            if attempt < policy.retries {
+                let delay = policy
+                    .retry_delay
+                    .min(deadline.saturating_duration_since(Instant::now()));
+                if policy.cancellable {
+                    tokio::select! {
+                        _ = self.cancel.cancelled() => return Err(HttpError::Cancelled),
+                        _ = tokio::time::sleep(delay) => {}
+                    }
+                } else {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+
+        debug!(
+            "HTTP {} call failed after {:?}",
+            policy.name,
+            started.elapsed()
+        );
+        #[cfg(any(debug_assertions, feature = "fault-injection"))]
+        if let (None, Some(e)) = (&last_error, injected) {
+            return Err(HttpError::Injected(e));
+        }
+        match last_error {
+            Some(e) if !e.is_timeout() => Err(HttpError::Request(e)),
+            _ => Err(HttpError::DeadlineExceeded(policy.deadline)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn policy(request_timeout: Duration, retries: u32, deadline: Duration) -> HttpPolicy {
+        HttpPolicy {
+            name: "test",
+            connect_timeout: Duration::from_secs(1),
+            request_timeout,
+            retries,
+            retry_delay: Duration::ZERO,
+            deadline,
+            cancellable: true,
+        }
+    }
+
+    /// A local server that answers the n-th connection (from 0) after `delay(n)` with
+    /// `status`. Returns its port and the number of connections accepted so far.
+    async fn slow_server(
+        status: u16,
+        delay: impl Fn(usize) -> Duration + Send + Sync + 'static,
+    ) -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let delay = Arc::new(delay);
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let delay = delay.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    tokio::time::sleep(delay(n)).await;
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                        status
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (port, accepted)
+    }
+
+    async fn get(
+        client: &HttpClient,
+        port: u16,
+        policy: HttpPolicy,
+    ) -> Result<reqwest::Response, HttpError> {
+        let url = format!("http://127.0.0.1:{}/healthcheck", port);
+        client.send(policy, |c| c.get(&url)).await
+    }
+
+    #[test]
+    fn attempt_timeout_is_bounded_by_the_remaining_deadline() {
+        let policy = policy(200 * MS, 0, Duration::from_secs(1));
+        assert_eq!(
+            policy.attempt_timeout(Duration::from_secs(5)),
+            Some(200 * MS)
+        );
+        assert_eq!(policy.attempt_timeout(50 * MS), Some(50 * MS));
+        assert_eq!(policy.attempt_timeout(Duration::ZERO), None);
+    }
+
+    #[tokio::test]
+    async fn fast_server_answers_on_the_first_attempt() {
+        let (port, accepted) = slow_server(200, |_| Duration::ZERO).await;
+        let response = get(&HttpClient::default(), port, policy(500 * MS, 2, 2000 * MS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_attempt_is_retried_within_the_deadline() {
+        let (port, accepted) = slow_server(200, |n| {
+            if n == 0 {
+                Duration::from_secs(5)
+            } else {
+                Duration::ZERO
+            }
+        })
+        .await;
+        let response = get(&HttpClient::default(), port, policy(200 * MS, 2, 2000 * MS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn deadline_caps_the_total_across_retries() {
+        let (port, accepted) = slow_server(200, |_| Duration::from_secs(5)).await;
+        let started = Instant::now();
+        let result = get(&HttpClient::default(), port, policy(200 * MS, 10, 500 * MS)).await;
+
+        assert!(matches!(result, Err(HttpError::DeadlineExceeded(d)) if d == 500 * MS));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= 450 * MS, "gave up early: {:?}", elapsed);
+        assert!(elapsed < 1500 * MS, "overran the deadline: {:?}", elapsed);
+        // 200 + 200 + the remaining 100, not all eleven attempts
+        assert!(accepted.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[tokio::test]
+    async fn deadline_shortens_a_longer_attempt_timeout() {
+        let (port, accepted) = slow_server(200, |_| Duration::from_secs(5)).await;
+        let started = Instant::now();
+        let result = get(
+            &HttpClient::default(),
+            port,
+            policy(Duration::from_secs(10), 3, 300 * MS),
+        )
+        .await;
+
+        assert!(matches!(result, Err(HttpError::DeadlineExceeded(_))));
+        assert!(started.elapsed() < 1500 * MS);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn error_status_is_not_retried() {
+        let (port, accepted) = slow_server(503, |_| Duration::ZERO).await;
+        let response = get(&HttpClient::default(), port, policy(500 * MS, 3, 2000 * MS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn quitting_interrupts_an_in_flight_call() {
+        let (port, _) = slow_server(200, |_| Duration::from_secs(5)).await;
+        let client = HttpClient::default();
+        let started = Instant::now();
+
+        let (result, _) = tokio::join!(
+            get(
+                &client,
+                port,
+                policy(Duration::from_secs(10), 0, Duration::from_secs(10))
+            ),
+            async {
+                tokio::time::sleep(100 * MS).await;
+                client.cancel_all();
+            }
+        );
+
+        assert!(matches!(result, Err(HttpError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn non_cancellable_call_outlives_quitting() {
+        let (port, _) = slow_server(200, |_| 200 * MS).await;
+        let client = HttpClient::default();
+        client.cancel_all();
+
+        let policy = HttpPolicy {
+            cancellable: false,
+            ..policy(Duration::from_secs(2), 0, Duration::from_secs(2))
+        };
+        let response = get(&client, port, policy).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+}
@@ -0,0 +1,74 @@
+//! Checking the sidecar binary before it's started.
+//!
+//! The sidecar runs with the user's rights and reads, writes and deletes backup data, so a
+//! binary that was corrupted or swapped after installation mustn't be run. `build.rs` hashes
+//! the sidecar the build bundles and embeds the digest as [`EXPECTED_SHA256`]; the file on
+//! disk is hashed again before every start. Debug builds skip the check when [`SKIP_ENV`] is
+//! `1`, for developers running a server they just built; release builds ignore it.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// SHA-256 (lowercase hex) of the sidecar this build bundled; None when `build.rs` didn't
+/// find the binary to hash
+pub const EXPECTED_SHA256: Option<&str> = option_env!("ZEROBYTE_SIDECAR_SHA256");
+
+/// Environment variable that turns the check off in debug builds
+pub const SKIP_ENV: &str = "ZEROBYTE_SKIP_SIDECAR_VERIFY";
+
+/// Payload of `sidecar-integrity-failed`
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityFailure {
+    pub path: PathBuf,
+    pub expected: String,
+    /// Digest of the file on disk, or why it couldn't be read
+    pub actual: String,
+}
+
+impl std::fmt::Display for IntegrityFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} doesn't match this build (expected SHA-256 {}, found {})",
+            self.path.display(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+/// How the check of the sidecar ended
+#[derive(Debug)]
+pub enum Check {
+    Verified,
+    /// Not checked, and why
+    Skipped(&'static str),
+    Failed(IntegrityFailure),
+}
+
+fn skip_requested() -> bool {
+    std::env::var(SKIP_ENV).is_ok_and(|value| value == "1")
+}
+
+/// Hash the sidecar at `path` and compare it with the digest embedded at build time
+pub fn check_sidecar(path: &Path) -> Check {
+    if skip_requested() {
+        if cfg!(debug_assertions) {
+            return Check::Skipped("turned off by ZEROBYTE_SKIP_SIDECAR_VERIFY");
+        }
+        tracing::warn!("{} is ignored in release builds", SKIP_ENV);
+    }
+    let Some(expected) = EXPECTED_SHA256 else {
+        return Check::Skipped("this build has no digest of the sidecar");
+    };
+    let actual = match crate::transfer::sha256_file(path) {
+        Ok(digest) if digest.eq_ignore_ascii_case(expected) => return Check::Verified,
+        Ok(digest) => digest,
+        Err(e) => format!("unreadable file ({})", e),
+    };
+    Check::Failed(IntegrityFailure {
+        path: path.to_path_buf(),
+        expected: expected.to_string(),
+        actual,
+    })
+}
@@ -0,0 +1,478 @@
+//! Migration from a legacy (pre-desktop) zerobyte install.
+//!
+//! The standalone builds kept their database and repository password file under a
+//! `zerobyte` data directory and could be registered as a `Zerobyte` service. On first run
+//! we look for such an install; `migrate_from_legacy` copies its `data/` files into the
+//! current backend's data directory (moving any files they replace aside as
+//! `*.pre-migration`) and records what it did in `legacy-migration.json`. Nothing in the
+//! legacy install is touched until the user asks for `cleanup_legacy_install`, and even then
+//! only the migrated files and the legacy service are removed: local repositories under the
+//! legacy directory stay where the migrated configuration expects them.
+
+use crate::persist;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the migration report in the app data directory
+pub const REPORT_FILE: &str = "legacy-migration.json";
+
+/// Suffix given to current files replaced by migrated ones
+const REPLACED_SUFFIX: &str = "pre-migration";
+
+/// Where legacy installs live, abstracted so fixtures can stand in for the real system
+pub trait LegacyLocations {
+    /// Directories a legacy install may have used, most likely first
+    fn candidate_dirs(&self) -> Vec<PathBuf>;
+    /// Legacy services that are still registered
+    fn installed_services(&self) -> Vec<String>;
+}
+
+/// Legacy locations on this machine
+pub struct SystemLocations;
+
+impl LegacyLocations for SystemLocations {
+    fn candidate_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(appdata) = std::env::var_os("APPDATA") {
+                dirs.push(PathBuf::from(appdata).join("Zerobyte"));
+            }
+            if let Some(programdata) = std::env::var_os("PROGRAMDATA") {
+                dirs.push(PathBuf::from(programdata).join("Zerobyte"));
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(PathBuf::from(home).join("Library/Application Support/Zerobyte"));
+        }
+
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        {
+            dirs.push(PathBuf::from("/var/lib/zerobyte"));
+            if let Some(home) = std::env::var_os("HOME") {
+                dirs.push(PathBuf::from(home).join(".local/share/zerobyte"));
+            }
+        }
+
+        dirs
+    }
+
+    fn installed_services(&self) -> Vec<String> {
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+
+            LEGACY_SERVICES
+                .iter()
+                .copied()
+                .filter(|name| {
+                    std::process::Command::new("sc")
+                        .args(["query", *name])
+                        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+                        .output()
+                        .map(|output| output.status.success())
+                        .unwrap_or(false)
+                })
+                .map(|name| name.to_string())
+                .collect()
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            Vec::new()
+        }
+    }
+}
+
+/// Service names used by legacy Windows installs
+#[cfg(target_os = "windows")]
+const LEGACY_SERVICES: &[&str] = &["Zerobyte", "ZerobyteService"];
+
+/// A legacy install that was found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LegacyInstall {
+    pub root: PathBuf,
+    pub services: Vec<String>,
+}
+
+/// Find the first candidate directory holding a legacy database
+pub fn detect(locations: &dyn LegacyLocations) -> Option<LegacyInstall> {
+    let root = locations
+        .candidate_dirs()
+        .into_iter()
+        .find(|dir| dir.join("data").join("zerobyte.db").is_file())?;
+    Some(LegacyInstall {
+        root,
+        services: locations.installed_services(),
+    })
+}
+
+/// One file to migrate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationItem {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub bytes: u64,
+    /// An existing file at the destination is moved aside first
+    pub replaces_existing: bool,
+}
+
+/// Files under the legacy `data/` directory and where they go in `target_root`
+pub fn plan(legacy_root: &Path, target_root: &Path) -> std::io::Result<Vec<MigrationItem>> {
+    let source_dir = legacy_root.join("data");
+    let target_dir = target_root.join("data");
+    let mut items = Vec::new();
+    for entry in std::fs::read_dir(&source_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let destination = target_dir.join(entry.file_name());
+        items.push(MigrationItem {
+            source: entry.path(),
+            replaces_existing: destination.exists(),
+            destination,
+            bytes: metadata.len(),
+        });
+    }
+    items.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(items)
+}
+
+/// What a migration did (or, for a dry run, would do)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub legacy: LegacyInstall,
+    pub target_root: PathBuf,
+    pub items: Vec<MigrationItem>,
+    pub dry_run: bool,
+    /// Legacy services that were disabled
+    pub disabled_services: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub completed_at: Option<u64>,
+    /// Set once `cleanup_legacy_install` removed the migrated files
+    pub cleaned_up: bool,
+}
+
+impl MigrationReport {
+    pub fn new(legacy: LegacyInstall, target_root: PathBuf, items: Vec<MigrationItem>) -> Self {
+        Self {
+            legacy,
+            target_root,
+            items,
+            dry_run: true,
+            disabled_services: Vec::new(),
+            completed_at: None,
+            cleaned_up: false,
+        }
+    }
+}
+
+/// Copy every planned file, moving files it replaces aside. The backend must not be running.
+pub fn apply(items: &[MigrationItem]) -> std::io::Result<()> {
+    for item in items {
+        if let Some(parent) = item.destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if item.destination.exists() {
+            let mut aside = item.destination.clone().into_os_string();
+            aside.push(format!(".{}", REPLACED_SUFFIX));
+            std::fs::rename(&item.destination, aside)?;
+        }
+        std::fs::copy(&item.source, &item.destination)?;
+    }
+    Ok(())
+}
+
+/// Remove the migrated files from the legacy install
+pub fn remove_sources(items: &[MigrationItem]) -> std::io::Result<()> {
+    for item in items {
+        match std::fs::remove_file(&item.source) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn load_report(path: &Path) -> Option<MigrationReport> {
+    let (report, source) = persist::load_json::<Option<MigrationReport>>(path);
+    if source.recovered() {
+        tracing::warn!("Migration report {} was damaged ({:?})", path.display(), source);
+    }
+    report
+}
+
+pub fn save_report(path: &Path, report: &MigrationReport) -> Result<(), String> {
+    persist::save_json(path, &Some(report))
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Stop the legacy services and keep them from starting again. Prompts for administrator
+/// approval.
+#[cfg(target_os = "windows")]
+pub async fn disable_services(services: Vec<String>) -> Result<(), String> {
+    run_service_script(
+        "zerobyte_disable_legacy.bat",
+        &services,
+        "sc stop {name}\r\nsc config {name} start= disabled",
+    )
+    .await
+}
+
+/// Remove the legacy services. Prompts for administrator approval.
+#[cfg(target_os = "windows")]
+pub async fn delete_services(services: Vec<String>) -> Result<(), String> {
+    run_service_script(
+        "zerobyte_remove_legacy.bat",
+        &services,
+        "sc stop {name}\r\nsc delete {name}",
+    )
+    .await
+}
+
+/// Batch script running `per_service` (with `{name}` filled in) for each service, logging
+/// the output to `log`
+#[cfg(any(target_os = "windows", test))]
+fn service_script(services: &[String], per_service: &str, log: &Path) -> String {
+    let log = log.display();
+    let mut script = format!("@echo off\r\necho Updating legacy services... > \"{}\"\r\n", log);
+    for name in services {
+        for line in per_service.replace("{name}", name).lines() {
+            script.push_str(&format!("{} >> \"{}\" 2>&1\r\n", line, log));
+        }
+    }
+    script.push_str(&format!("echo Legacy services updated >> \"{}\"\r\n", log));
+    script
+}
+
+#[cfg(target_os = "windows")]
+async fn run_service_script(
+    script_name: &str,
+    services: &[String],
+    per_service: &str,
+) -> Result<(), String> {
+    let log_path = std::env::temp_dir().join("zerobyte_legacy_service.log");
+    let _ = std::fs::remove_file(&log_path);
+
+    let script = service_script(services, per_service, &log_path);
+    crate::commands::service::execute_elevated_script(
+        script_name,
+        script,
+        &log_path,
+        "Legacy services updated",
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+pub async fn disable_services(_services: Vec<String>) -> Result<(), String> {
+    Err("Legacy services only exist on Windows".to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+pub async fn delete_services(_services: Vec<String>) -> Result<(), String> {
+    Err("Legacy services only exist on Windows".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Legacy locations backed by fixture directories
+    struct Fixture {
+        dirs: Vec<PathBuf>,
+        services: Vec<String>,
+    }
+
+    impl LegacyLocations for Fixture {
+        fn candidate_dirs(&self) -> Vec<PathBuf> {
+            self.dirs.clone()
+        }
+
+        fn installed_services(&self) -> Vec<String> {
+            self.services.clone()
+        }
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("legacy-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    /// A legacy install under `root` with `files` in its data directory
+    fn legacy_install(root: &Path, files: &[(&str, &str)]) {
+        std::fs::create_dir_all(root.join("data")).unwrap();
+        for (name, contents) in files {
+            std::fs::write(root.join("data").join(name), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn first_directory_with_a_database_is_detected() {
+        let root = temp_root("detect");
+        let empty = root.join("empty");
+        let no_db = root.join("no-db");
+        let found = root.join("found");
+        let also = root.join("also");
+        std::fs::create_dir_all(&empty).unwrap();
+        legacy_install(&no_db, &[("restic.pass", "x")]);
+        legacy_install(&found, &[("zerobyte.db", "db")]);
+        legacy_install(&also, &[("zerobyte.db", "db")]);
+        // A directory named like the database doesn't count
+        std::fs::create_dir_all(empty.join("data/zerobyte.db")).unwrap();
+
+        let fixture = Fixture {
+            dirs: vec![root.join("missing"), empty, no_db, found.clone(), also],
+            services: vec!["Zerobyte".to_string()],
+        };
+        let detected = detect(&fixture);
+        let nothing = detect(&Fixture {
+            dirs: vec![root.join("missing")],
+            services: vec!["Zerobyte".to_string()],
+        });
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(
+            detected,
+            Some(LegacyInstall {
+                root: found,
+                services: vec!["Zerobyte".to_string()],
+            })
+        );
+        assert_eq!(nothing, None);
+    }
+
+    #[test]
+    fn migration_copies_files_and_moves_replaced_ones_aside() {
+        let root = temp_root("migrate");
+        let legacy = root.join("legacy");
+        let target = root.join("target");
+        legacy_install(
+            &legacy,
+            &[("zerobyte.db", "legacy db"), ("restic.pass", "pw")],
+        );
+        std::fs::create_dir_all(legacy.join("data/repositories")).unwrap();
+        std::fs::create_dir_all(target.join("data")).unwrap();
+        std::fs::write(target.join("data/zerobyte.db"), "current db").unwrap();
+
+        let items = plan(&legacy, &target).unwrap();
+        let applied = apply(&items);
+        let read = |path: &str| std::fs::read_to_string(target.join(path)).unwrap();
+        let (db, aside, password) = (
+            read("data/zerobyte.db"),
+            read("data/zerobyte.db.pre-migration"),
+            read("data/restic.pass"),
+        );
+        let removed = remove_sources(&items);
+        let legacy_left: Vec<_> = std::fs::read_dir(legacy.join("data"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        let _ = std::fs::remove_dir_all(&root);
+
+        // Only files are planned, sorted, with the replaced one flagged
+        assert_eq!(
+            items,
+            vec![
+                MigrationItem {
+                    source: legacy.join("data/restic.pass"),
+                    destination: target.join("data/restic.pass"),
+                    bytes: 2,
+                    replaces_existing: false,
+                },
+                MigrationItem {
+                    source: legacy.join("data/zerobyte.db"),
+                    destination: target.join("data/zerobyte.db"),
+                    bytes: 9,
+                    replaces_existing: true,
+                },
+            ]
+        );
+        applied.unwrap();
+        assert_eq!(db, "legacy db");
+        assert_eq!(aside, "current db");
+        assert_eq!(password, "pw");
+        removed.unwrap();
+        // Repositories stay where the migrated configuration expects them
+        assert_eq!(legacy_left, ["repositories"]);
+    }
+
+    #[test]
+    fn removing_sources_twice_is_harmless() {
+        let root = temp_root("remove");
+        legacy_install(&root, &[("zerobyte.db", "db")]);
+        let items = plan(&root, &root.join("target")).unwrap();
+
+        let first = remove_sources(&items);
+        let second = remove_sources(&items);
+        let _ = std::fs::remove_dir_all(&root);
+
+        first.unwrap();
+        second.unwrap();
+    }
+
+    #[test]
+    fn report_round_trips() {
+        let root = temp_root("report");
+        let path = root.join(REPORT_FILE);
+        let mut report = MigrationReport::new(
+            LegacyInstall {
+                root: root.join("legacy"),
+                services: vec!["ZerobyteService".to_string()],
+            },
+            root.join("target"),
+            Vec::new(),
+        );
+        assert!(report.dry_run);
+        report.dry_run = false;
+        report.completed_at = Some(now());
+
+        let missing = load_report(&path);
+        save_report(&path, &report).unwrap();
+        let loaded = load_report(&path);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(missing, None);
+        assert_eq!(loaded, Some(report));
+    }
+
+    #[test]
+    fn service_script_runs_each_step_for_each_service() {
+        let services = ["Zerobyte".to_string(), "ZerobyteService".to_string()];
+        let script = service_script(
+            &services,
+            "sc stop {name}\r\nsc delete {name}",
+            Path::new(r"C:\Temp\legacy.log"),
+        );
+        let lines: Vec<_> = script.split("\r\n").collect();
+        assert_eq!(
+            lines,
+            [
+                "@echo off",
+                r#"echo Updating legacy services... > "C:\Temp\legacy.log""#,
+                r#"sc stop Zerobyte >> "C:\Temp\legacy.log" 2>&1"#,
+                r#"sc delete Zerobyte >> "C:\Temp\legacy.log" 2>&1"#,
+                r#"sc stop ZerobyteService >> "C:\Temp\legacy.log" 2>&1"#,
+                r#"sc delete ZerobyteService >> "C:\Temp\legacy.log" 2>&1"#,
+                r#"echo Legacy services updated >> "C:\Temp\legacy.log""#,
+                "",
+            ]
+        );
+    }
+}
@@ -1,100 +1,665 @@
+pub mod access;
+pub mod accessibility;
+pub mod actions;
+pub mod activity;
+pub mod args;
+pub mod assets;
+pub mod backend_log;
+pub mod backend_token;
+pub mod bandwidth;
+pub mod banner;
+pub mod capabilities;
+pub mod cfa;
+pub mod clock;
 pub mod commands;
+pub mod config;
+pub mod crashes;
+pub mod desktop;
+pub mod devtools;
+pub mod dialogs;
+pub mod discovery;
+pub mod drain;
+pub mod drift;
+pub mod elevation;
+pub mod error;
+pub mod estimate;
+pub mod events;
+pub mod faults;
+pub mod graceful;
+pub mod health;
+pub mod heartbeat;
+pub mod http;
+pub mod integrity;
+pub mod legacy;
+pub mod loading;
+pub mod logging;
+pub mod metrics;
+pub mod monitor;
+pub mod notifier;
+pub mod orphan;
+pub mod outbox;
+pub mod ownership;
+pub mod palette;
+pub mod passphrase;
+pub mod pairing;
+pub mod paths;
+pub mod persist;
+pub mod priority;
+pub mod proctree;
+pub mod profile;
+pub mod protection;
+pub mod readiness;
+pub mod recovery;
+pub mod redact;
+pub mod registrations;
+pub mod relocate;
+pub mod report;
+pub mod restore;
+pub mod retention;
+pub mod runtime_state;
+pub mod sandbox;
+pub mod service_access;
+pub mod scratch;
+pub mod schedule;
+pub mod session_end;
+pub mod settings;
+pub mod shutdown;
+pub mod startup;
+pub mod supervisor;
+pub mod status_server;
+pub mod switch;
+pub mod text;
+pub mod timezone;
+pub mod transfer;
+pub mod usage;
+pub mod versions;
+pub mod watch;
 
-use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
-use tauri::menu::{Menu, MenuItem};
+use error::AppError;
+use http::{HttpError, HttpPolicy};
+use loading::LoadingStatus;
+use readiness::{HealthState, ReadinessDeadline};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, Submenu};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
-use tauri::{Emitter, Manager};
+use tauri::webview::PageLoadEvent;
+use tauri::Manager;
 use tauri_plugin_shell::ShellExt;
 use tokio::sync::Mutex;
 use tracing::{error, info, warn};
 
-/// Port used for desktop sidecar mode
-const DESKTOP_PORT: u16 = 4096;
+/// Delay before the second healthcheck while waiting for the backend to start; it doubles
+/// from there up to [`STARTUP_POLL_MAX`]
+const STARTUP_POLL_INITIAL: Duration = Duration::from_millis(100);
 
-/// Port used for Windows Service mode
-const SERVICE_PORT: u16 = 4097;
+/// Longest delay between healthchecks while waiting for the backend to start
+const STARTUP_POLL_MAX: Duration = Duration::from_secs(2);
+
+/// How long a TCP connect may take before the port counts as not listening yet
+const PORT_PROBE_TIMEOUT: Duration = Duration::from_millis(75);
+
+/// Environment variable that makes debug builds always use the dev server, waiting for it
+/// (`1`/`true`), or never look for it (`0`/`false`). Unset, one that's already up is used.
+#[cfg(debug_assertions)]
+const DEV_SERVER_ENV: &str = "ZEROBYTE_DEV_SERVER";
+
+/// Connect attempts that look for an already running dev server, and the pause between them
+#[cfg(debug_assertions)]
+const DEV_SERVER_PROBES: u32 = 2;
+#[cfg(debug_assertions)]
+const DEV_SERVER_PROBE_GAP: Duration = Duration::from_millis(500);
+
+/// Extra time granted each time the backend reports startup progress
+const STARTUP_PROGRESS_EXTENSION: Duration = Duration::from_secs(60);
+
+/// Upper bound on the startup wait, even while progress keeps advancing
+const STARTUP_MAX_WAIT: Duration = Duration::from_secs(30 * 60);
+
+/// Waits before each retry of a sidecar spawn that failed for a reason that may pass, e.g. an
+/// antivirus scanner still holding a freshly installed binary
+const SPAWN_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(3),
+    Duration::from_secs(5),
+];
+
+/// How often a viewer checks whether the owning session released the backend
+const OWNER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often queued backend actions are retried
+const OUTBOX_DRAIN_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Id of the system tray icon
+const TRAY_ID: &str = "main";
+
+/// Tray entries that depend on the backend, shown in a degraded state until it's up
+struct TrayBackendItems {
+    /// Disabled status line at the top of the menu
+    status: MenuItem<tauri::Wry>,
+    /// Entries that open backend pages
+    pages: Vec<MenuItem<tauri::Wry>>,
+}
+
+/// What the tray's status line says about the backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TrayBackendState {
+    Running,
+    /// Stopped on purpose, or after a crash, and about to start again
+    Restarting,
+    Failed,
+}
+
+/// A spawned sidecar and the generation it was started as
+pub struct SidecarProcess {
+    pub generation: u64,
+    pub child: proctree::Child,
+    /// The sidecar and the helpers it starts, killed together
+    pub tree: proctree::ProcessTree,
+    pub started: Instant,
+    /// Resolves with the exit code once the output task sees the process terminate
+    pub exited: tokio::sync::oneshot::Receiver<Option<i32>>,
+}
+
+/// What a sidecar exiting means for the instance the app currently runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SidecarExit {
+    /// A newer generation was spawned since; the exit is a replaced instance going away
+    Superseded,
+    /// The current instance was stopped on purpose (a planned stop takes the handle first)
+    Stopped,
+    /// The current instance died while still holding the handle
+    Crashed,
+}
+
+/// Classify the exit of generation `generation`, given the most recently spawned generation
+/// and the generation whose handle is still held, if any
+fn classify_exit(generation: u64, current: u64, held: Option<u64>) -> SidecarExit {
+    if generation != current {
+        SidecarExit::Superseded
+    } else if held == Some(generation) {
+        SidecarExit::Crashed
+    } else {
+        SidecarExit::Stopped
+    }
+}
 
 /// Holds the state of the sidecar process
 pub struct AppState {
     /// The sidecar process handle (None if using service mode)
-    pub sidecar_handle: Arc<Mutex<Option<tauri_plugin_shell::process::CommandChild>>>,
+    pub sidecar_handle: Arc<Mutex<Option<SidecarProcess>>>,
+    /// Generation of the most recently spawned sidecar. Events from older generations belong
+    /// to instances that were already replaced and are ignored.
+    pub sidecar_generation: AtomicU64,
     /// Whether we're connected to the Windows Service instead of sidecar
     pub using_service: AtomicBool,
     /// The port the backend is running on
     pub backend_port: AtomicU16,
+    /// Whether the backend has answered its healthcheck since startup
+    pub backend_ready: AtomicBool,
+    /// Smoothed clock skew between this machine and the backend host
+    pub clock_skew: parking_lot::Mutex<clock::SkewEstimator>,
+    /// Serializes operations that prompt for administrator approval
+    pub elevation: elevation::ElevationCoordinator,
+    /// Application directories, resolved once during setup
+    pub paths: OnceLock<paths::Paths>,
+    /// Strips secrets and personal details from text before it's logged or exported
+    pub redactor: parking_lot::RwLock<redact::Redactor>,
+    /// API features the connected backend supports (a `BackendCapabilities` bitset)
+    pub capabilities: AtomicU32,
+    /// Tracks the shared shutdown sequence so every exit path runs it exactly once
+    pub shutdown: shutdown::ShutdownState,
+    /// Throttles every background poller according to the battery saver mode
+    pub activity: activity::ActivityGovernor,
+    /// Outbound HTTP shared by every call site; cancelled when the app quits
+    pub http: http::HttpClient,
+    /// Whether we own the backend or are only viewing one owned by another session
+    pub connection_mode: parking_lot::Mutex<ownership::ConnectionMode>,
+    /// Short-lived backend instances serving a snapshot for restore
+    pub restore_sessions: restore::RestoreSessions,
+    /// When each startup stage ran
+    pub startup: startup::StartupTracker,
+    /// Confinement the running sidecar was launched with
+    pub confinement: parking_lot::Mutex<sandbox::Confinement>,
+    /// Lifecycle events retained for webviews that start listening late
+    pub events: events::EventBus,
+    /// Drain in progress before a planned backend stop
+    pub drain: drain::DrainState,
+    /// Port, version and data directory the sidecar reported at startup
+    pub banner: parking_lot::Mutex<banner::BannerFacts>,
+    /// Notification toasts that later events of the same category collapse into
+    pub toasts: notifier::ToastLedger,
+    /// Last heartbeat of each webview, to notice a crashed renderer
+    pub heartbeats: heartbeat::HeartbeatMonitor,
+    /// Held while a command stops and starts the sidecar, so restarts don't overlap
+    pub lifecycle: Mutex<()>,
+    /// IANA zone the backend reports its wall-clock times in, if it names one
+    pub backend_time_zone: parking_lot::Mutex<Option<String>>,
+    /// Whether the service backend answered its healthcheck with 401/403 and the desktop
+    /// hasn't paired with it yet
+    pub needs_pairing: AtomicBool,
+    /// Recent sidecar output, and the lines not yet streamed to the webviews
+    pub backend_log: backend_log::BackendLog,
+    /// Set once the app starts quitting, so the sidecar exiting isn't taken for a crash
+    pub shutdown_requested: AtomicBool,
+    /// Restarts made after sidecar crashes
+    pub recovery: parking_lot::Mutex<recovery::CrashRecovery>,
+    /// Whether the previous run ended without finishing its shutdown sequence
+    pub unclean_shutdown: AtomicBool,
+    /// Ports and timeouts from `zerobyte.toml`, loaded during setup
+    pub config: parking_lot::RwLock<config::DesktopConfig>,
+    /// Shared secret handed to every sidecar this run starts; None without OS randomness,
+    /// in which case no sidecar is started
+    pub sidecar_token: Option<String>,
+    /// Profile this instance runs (see `profile`)
+    pub profile: String,
+    /// Sidecar port from `--port` or ZEROBYTE_PORT, used instead of the configured one
+    pub port_override: Option<u16>,
+    /// Sidecar left running by a run that crashed, taken over at startup
+    pub adopted_sidecar: parking_lot::Mutex<Option<orphan::AdoptedSidecar>>,
+    /// Backend page the tray or an action last sent the main window to; empty for the start
+    /// page. The window goes back there whenever the backend comes back.
+    pub last_route: parking_lot::Mutex<String>,
+    /// Backend mode last announced with `backend-mode-changed`
+    pub backend_mode: parking_lot::Mutex<Option<ownership::BackendMode>>,
+    /// Set by switching from the service to the sidecar; later starts don't look for the
+    /// service
+    pub sidecar_preferred: AtomicBool,
+}
+
+impl AppState {
+    /// API features the connected backend supports
+    pub fn capabilities(&self) -> capabilities::BackendCapabilities {
+        capabilities::BackendCapabilities::from_bits(self.capabilities.load(Ordering::SeqCst))
+    }
+
+    /// Application directories. Only valid once setup has run, which is before any
+    /// command or background task can reach the state.
+    pub fn paths(&self) -> &paths::Paths {
+        self.paths
+            .get()
+            .expect("application paths are resolved during setup")
+    }
+
+    /// Snapshot of the current `zerobyte.toml` settings
+    pub fn config(&self) -> config::DesktopConfig {
+        self.config.read().clone()
+    }
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             sidecar_handle: Arc::new(Mutex::new(None)),
+            sidecar_generation: AtomicU64::new(0),
             using_service: AtomicBool::new(false),
-            backend_port: AtomicU16::new(DESKTOP_PORT),
+            backend_port: AtomicU16::new(config::DEFAULT_SIDECAR_PORT),
+            backend_ready: AtomicBool::new(false),
+            clock_skew: parking_lot::Mutex::new(clock::SkewEstimator::default()),
+            elevation: elevation::ElevationCoordinator::default(),
+            paths: OnceLock::new(),
+            connection_mode: parking_lot::Mutex::new(ownership::ConnectionMode::Owner),
+            http: http::HttpClient::default(),
+            activity: activity::ActivityGovernor::default(),
+            shutdown: shutdown::ShutdownState::default(),
+            capabilities: AtomicU32::new(capabilities::BASELINE.bits()),
+            redactor: parking_lot::RwLock::new(redact::Redactor::default()),
+            restore_sessions: restore::RestoreSessions::default(),
+            startup: startup::StartupTracker::default(),
+            confinement: parking_lot::Mutex::new(sandbox::Confinement::default()),
+            events: events::EventBus::default(),
+            drain: drain::DrainState::default(),
+            banner: parking_lot::Mutex::new(banner::BannerFacts::default()),
+            toasts: notifier::ToastLedger::default(),
+            heartbeats: heartbeat::HeartbeatMonitor::default(),
+            lifecycle: Mutex::new(()),
+            backend_time_zone: parking_lot::Mutex::new(None),
+            needs_pairing: AtomicBool::new(false),
+            backend_log: backend_log::BackendLog::default(),
+            shutdown_requested: AtomicBool::new(false),
+            recovery: parking_lot::Mutex::new(recovery::CrashRecovery::default()),
+            unclean_shutdown: AtomicBool::new(false),
+            config: parking_lot::RwLock::new(config::DesktopConfig::default()),
+            sidecar_token: backend_token::generate(),
+            profile: profile::current().to_string(),
+            port_override: None,
+            adopted_sidecar: parking_lot::Mutex::new(None),
+            last_route: parking_lot::Mutex::new(String::new()),
+            backend_mode: parking_lot::Mutex::new(None),
+            sidecar_preferred: AtomicBool::new(false),
         }
     }
 }
 
-/// Check if the Windows Service is running by trying to connect to the service port
-async fn is_service_running() -> bool {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build()
-        .unwrap_or_default();
+/// Feed the Date header of a healthcheck response into the clock skew estimate
+fn record_clock_sample(app: &tauri::AppHandle, response: &reqwest::Response, sent_at: SystemTime) {
+    let Some(date) = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return;
+    };
+    let Some(sample) = clock::sample_from_date_header(date, sent_at, SystemTime::now()) else {
+        return;
+    };
 
-    let url = format!("http://localhost:{}/healthcheck", SERVICE_PORT);
-    match client.get(&url).send().await {
-        Ok(response) => response.status().is_success(),
-        Err(_) => false,
+    let state = app.state::<AppState>();
+    let warning = state.clock_skew.lock().observe(sample);
+    if let Some(warning) = warning {
+        warn!("Clock skew detected: {}", warning.message);
+        events::emit(app, "clock-skew-detected", warning);
     }
 }
 
-/// Check if the sidecar server is ready by checking the healthcheck endpoint
-async fn wait_for_server(port: u16, max_attempts: u32) -> bool {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
-        .build()
-        .unwrap_or_default();
+/// Switch between owning and viewing the backend, and tell the tray and UI about it
+fn set_connection_mode(app: &tauri::AppHandle, mode: ownership::ConnectionMode) {
+    let state = app.state::<AppState>();
+    {
+        let mut current = state.connection_mode.lock();
+        if *current == mode {
+            return;
+        }
+        *current = mode.clone();
+    }
+
+    info!("Connection mode: {:?}", mode);
+    refresh_tray_tooltip(app);
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.eval(desktop::init_script(&mode));
+    }
+    events::emit(app, "connection-mode", &mode);
+    palette::notify_actions_changed(app);
+}
+
+/// Describe the connection, any throttled background activity and the active bandwidth
+/// profile in the tray tooltip; once quitting, only that
+pub(crate) fn refresh_tray_tooltip(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    if state.shutdown_requested.load(Ordering::SeqCst) {
+        if let Some(tray) = app.tray_by_id(TRAY_ID) {
+            let tooltip = format!("{} - Shutting down...", profile::app_title());
+            let _ = tray.set_tooltip(Some(tooltip));
+        }
+        return;
+    }
+    let mut tooltip = state.connection_mode.lock().tooltip();
+    let activity = state.activity.mode();
+    if activity != activity::ActivityMode::Normal {
+        tooltip.push_str(&format!(" (background activity {})", activity.label()));
+    }
+    if let Some(profile) = app.state::<bandwidth::BandwidthState>().active() {
+        tooltip.push_str(&format!(" · Bandwidth: {}", profile.name));
+    }
+    let tooltip = text::fit(app, text::Surface::Tooltip, &tooltip, text::Ellipsis::End);
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+}
+
+/// Switch the battery saver mode, persist it and reflect it in the tray
+pub fn set_background_activity(
+    app: &tauri::AppHandle,
+    mode: activity::ActivityMode,
+) -> Result<(), String> {
+    app.state::<settings::SettingsStore>()
+        .update(|settings| settings.background_activity = mode)?;
 
-    let url = format!("http://localhost:{}/healthcheck", port);
+    let state = app.state::<AppState>();
+    if state.activity.set_mode(mode) {
+        info!("Background activity: {}", mode.label());
+    }
+    if let Some(item) = app.try_state::<CheckMenuItem<tauri::Wry>>() {
+        let _ = item.set_checked(mode == activity::ActivityMode::Suspended);
+    }
+    refresh_tray_tooltip(app);
+    events::emit(app, "background-activity-changed", mode);
+    Ok(())
+}
 
-    for attempt in 1..=max_attempts {
-        match client.get(&url).send().await {
-            Ok(response) if response.status().is_success() => {
-                info!("Server is ready on port {} (attempt {})", port, attempt);
-                return true;
+/// Check if the Windows Service is running by trying to connect to the service port. A
+/// service that requires authentication counts as running; see `pairing`.
+pub(crate) async fn probe_service(app: &tauri::AppHandle) -> pairing::ServiceProbe {
+    let state = app.state::<AppState>();
+    let sent_at = SystemTime::now();
+    let status = match state
+        .http
+        .healthcheck(state.config().service_port, HttpPolicy::STARTUP)
+        .await
+    {
+        Ok(response) => {
+            if response.status().is_success() {
+                record_clock_sample(app, &response, sent_at);
             }
-            Ok(response) => {
-                warn!(
-                    "Server returned status {} on attempt {}",
-                    response.status(),
-                    attempt
-                );
+            Some(response.status())
+        }
+        Err(_) => None,
+    };
+    pairing::classify(status)
+}
+
+/// Whether anything accepts connections on `port` yet
+async fn port_listening(port: u16) -> bool {
+    let connect = tokio::net::TcpStream::connect(("localhost", port));
+    matches!(
+        tokio::time::timeout(PORT_PROBE_TIMEOUT, connect).await,
+        Ok(Ok(_))
+    )
+}
+
+/// What [`DEV_SERVER_ENV`] asks for: Some(true) to always use the dev server, Some(false) to
+/// never, None to use one that's running
+#[cfg(debug_assertions)]
+fn dev_server_policy() -> Option<bool> {
+    let value = std::env::var(DEV_SERVER_ENV).ok()?;
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => {
+            warn!("Ignoring {}={:?}, expected 1 or 0", DEV_SERVER_ENV, value);
+            None
+        }
+    }
+}
+
+/// Whether a dev server answers on `port`. Unless `wait` is set, a couple of quick connects
+/// decide whether anything listens at all, so a missing dev server costs about a second
+/// rather than the whole startup timeout.
+#[cfg(debug_assertions)]
+async fn dev_server_ready(
+    app: &tauri::AppHandle,
+    port: u16,
+    wait: bool,
+    timeout: Duration,
+) -> bool {
+    if !wait {
+        let mut listening = false;
+        for probe in 0..DEV_SERVER_PROBES {
+            if probe > 0 {
+                tokio::time::sleep(DEV_SERVER_PROBE_GAP).await;
             }
-            Err(e) => {
-                if attempt < max_attempts {
-                    info!("Waiting for server (attempt {}): {}", attempt, e);
+            if port_listening(port).await {
+                listening = true;
+                break;
+            }
+        }
+        if !listening {
+            info!("No development server on port {}", port);
+            return false;
+        }
+    }
+    wait_for_server(app, port, timeout).await == readiness::ServerWait::Ready
+}
+
+/// Ask the server on `port` who it is. None if it doesn't answer its healthcheck at all.
+async fn verify_server_identity(
+    app: &tauri::AppHandle,
+    port: u16,
+) -> Option<readiness::ServerIdentity> {
+    let state = app.state::<AppState>();
+    let response = state
+        .http
+        .healthcheck(port, HttpPolicy::STARTUP)
+        .await
+        .ok()?;
+    let header = response
+        .headers()
+        .get(readiness::IDENTITY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.text().await.unwrap_or_default();
+    Some(readiness::identify(header.as_deref(), &body))
+}
+
+/// Wait for the server on `port` to answer its healthcheck as ready.
+/// Each round starts with a quick TCP connect and only sends the healthcheck once the port
+/// listens; rounds back off from [`STARTUP_POLL_INITIAL`] to [`STARTUP_POLL_MAX`]. `timeout`
+/// bounds the wait for a silent server; reported startup progress (e.g. first-start
+/// migrations) extends it while it keeps advancing.
+async fn wait_for_server(
+    app: &tauri::AppHandle,
+    port: u16,
+    timeout: Duration,
+) -> readiness::ServerWait {
+    let state = app.state::<AppState>();
+    let url = http::local_url(port, "/healthcheck?detail=1");
+    let mut deadline =
+        ReadinessDeadline::new(timeout, STARTUP_PROGRESS_EXTENSION, STARTUP_MAX_WAIT);
+    let mut backoff = readiness::Backoff::new(STARTUP_POLL_INITIAL, STARTUP_POLL_MAX);
+    let started = Instant::now();
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        loading::emit(app, LoadingStatus::WaitingForServer { attempt });
+        if state.http.is_cancelled() {
+            info!("Stopped waiting for server on port {}: quitting", port);
+            return readiness::ServerWait::Cancelled;
+        }
+        if port_listening(port).await {
+            let sent_at = SystemTime::now();
+            match state
+                .http
+                .send(HttpPolicy::STARTUP, |client| client.get(&url))
+                .await
+            {
+                Ok(response) => {
+                    let status = response.status();
+                    if matches!(
+                        status,
+                        reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::METHOD_NOT_ALLOWED
+                    ) {
+                        warn!("Port {} has no healthcheck (HTTP {}), not our server", port, status);
+                        return readiness::ServerWait::WrongServer;
+                    }
+                    if status.is_success() {
+                        record_clock_sample(app, &response, sent_at);
+                    }
+                    let body = response.text().await.unwrap_or_default();
+
+                    match readiness::parse_health_response(status.is_success(), &body) {
+                        HealthState::Ready => {
+                            info!(
+                                "Server is ready on port {} after {:?} (attempt {})",
+                                port,
+                                started.elapsed(),
+                                attempt
+                            );
+                            return readiness::ServerWait::Ready;
+                        }
+                        HealthState::Starting(Some(progress)) => {
+                            if deadline.observe(Instant::now(), &progress) {
+                                info!("Server is starting: {}", progress);
+                                events::emit(app, "startup-progress", &progress);
+                            }
+                        }
+                        HealthState::Starting(None) => {
+                            warn!("Server returned status {} on attempt {}", status, attempt);
+                        }
+                    }
+                }
+                Err(HttpError::Cancelled) => {
+                    info!("Stopped waiting for server on port {}: quitting", port);
+                    return readiness::ServerWait::Cancelled;
+                }
+                Err(e) => {
+                    if !deadline.expired(Instant::now()) {
+                        info!("Waiting for server (attempt {}): {}", attempt, e);
+                    }
                 }
             }
         }
-        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let now = Instant::now();
+        if deadline.expired(now) {
+            break;
+        }
+        tokio::time::sleep(backoff.next_delay().min(deadline.remaining(now))).await;
     }
 
-    error!("Server failed to start after {} attempts", max_attempts);
-    false
+    error!(
+        "Server on port {} not ready after {:?} ({} attempts)",
+        port,
+        started.elapsed(),
+        attempt
+    );
+    readiness::ServerWait::TimedOut
 }
 
-/// Request graceful shutdown of the server
-async fn request_graceful_shutdown(port: u16) -> bool {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .unwrap_or_default();
+/// Ask the backend what its API supports, falling back to its reported version, and tell the
+/// UI when that changed (e.g. after a restart or switching between sidecar and service)
+pub(crate) async fn refresh_capabilities(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let port = state.backend_port.load(Ordering::SeqCst);
+
+    let descriptor_url = http::local_url(port, capabilities::DESCRIPTOR_PATH);
+    let descriptor_body = match state
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| client.get(&descriptor_url))
+        .await
+    {
+        Ok(response) if response.status().is_success() => response.text().await.ok(),
+        _ => None,
+    };
+    let descriptor = descriptor_body
+        .as_deref()
+        .and_then(capabilities::parse_descriptor);
+    let mut time_zone = descriptor_body
+        .as_deref()
+        .and_then(|body| serde_json::from_str::<serde_json::Value>(body).ok())
+        .and_then(|body| timezone::reported_zone(&body));
+
+    let capabilities = match descriptor {
+        Some(capabilities) => capabilities,
+        None => {
+            let body = match state.http.healthcheck(port, HttpPolicy::INTERACTIVE).await {
+                Ok(response) => response.json::<serde_json::Value>().await.ok(),
+                Err(_) => None,
+            };
+            time_zone = time_zone.or_else(|| body.as_ref().and_then(timezone::reported_zone));
+            let version = body.and_then(|body| body.get("version")?.as_str().map(str::to_string));
+            version
+                .as_deref()
+                .map(capabilities::infer_from_version)
+                .unwrap_or(capabilities::BASELINE)
+        }
+    };
+
+    *state.backend_time_zone.lock() = time_zone;
 
-    let url = format!("http://localhost:{}/api/shutdown", port);
+    let previous = state.capabilities.swap(capabilities.bits(), Ordering::SeqCst);
+    if previous != capabilities.bits() {
+        info!("Backend capabilities: {}", capabilities);
+        events::emit(app, "capabilities-changed", capabilities.names());
+        palette::notify_actions_changed(app);
+    }
+}
 
-    match client.post(&url).send().await {
+/// Request graceful shutdown of the server. Like every request to the local backend it
+/// carries the shared secret, without which the backend refuses it.
+async fn request_graceful_shutdown(http: &http::HttpClient, port: u16) -> bool {
+    match http.shutdown(port).await {
         Ok(response) => {
             info!("Shutdown request sent, status: {}", response.status());
             response.status().is_success()
@@ -106,90 +671,607 @@ async fn request_graceful_shutdown(port: u16) -> bool {
     }
 }
 
-/// Start the sidecar server process
-/// Returns the port that the backend is running on
-pub async fn start_sidecar(
+/// The sidecar command, confined when the sandbox setting is on and the system supports it
+fn build_sidecar_command(
     app: &tauri::AppHandle,
     state: &AppState,
-) -> Result<u16, Box<dyn std::error::Error + Send + Sync>> {
-    // First, check if the Windows Service is running
-    if is_service_running().await {
-        info!("Windows Service detected on port {}, connecting to service instead of starting sidecar", SERVICE_PORT);
+) -> Result<(tauri_plugin_shell::process::Command, sandbox::Confinement), AppError> {
+    let shell = app.shell();
+    let settings = app.state::<settings::SettingsStore>().get();
+    if settings.sandbox {
+        let policy = sandbox::SandboxPolicy {
+            allowed_hosts: settings.sandbox_allowed_hosts.clone(),
+            writable_paths: sandbox::writable_paths(
+                &state.paths().data_dir,
+                state.config().data_dir.as_deref(),
+            ),
+        };
+        let sidecar_path = sandbox::sidecar_path()
+            .map_err(|e| AppError::SidecarSpawnFailed(format!("sidecar not found: {}", e)))?;
+        match sandbox::plan(&sidecar_path, &policy, sandbox::detect()) {
+            Some(plan) => {
+                info!("Sandboxing sidecar: {:?}", plan.confinement);
+                let command = shell.command(plan.program).args(plan.args);
+                return Ok((command, plan.confinement));
+            }
+            None => {
+                let reason = "neither systemd scopes nor user namespaces are available";
+                warn!("Sandbox requested but {}, running unconfined", reason);
+                events::emit(app, "sandbox-unavailable", reason);
+            }
+        }
+    }
+    let command = shell
+        .sidecar("zerobyte-server")
+        .map_err(|e| AppError::SidecarSpawnFailed(e.to_string()))?;
+    Ok((command, sandbox::Confinement::default()))
+}
+
+/// Whether a failed spawn may work if tried again shortly: the binary is there but locked,
+/// typically by an antivirus scanner that hasn't finished with it. A missing binary, a bad
+/// sidecar configuration or a denied permission won't fix itself.
+fn spawn_failure_is_transient(error: &tauri_plugin_shell::Error) -> bool {
+    let tauri_plugin_shell::Error::Io(e) = error else {
+        return false;
+    };
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION on Windows, ETXTBSY elsewhere
+    let locked: &[i32] = if cfg!(target_os = "windows") {
+        &[32, 33]
+    } else {
+        &[26]
+    };
+    e.raw_os_error().is_some_and(|code| locked.contains(&code))
+}
+
+/// Refuse to run a sidecar that isn't the binary this build bundled
+pub(crate) async fn verify_sidecar_binary(app: &tauri::AppHandle) -> Result<(), AppError> {
+    let sidecar_path = sandbox::sidecar_path()
+        .map_err(|e| AppError::SidecarSpawnFailed(format!("sidecar not found: {}", e)))?;
+    let check = tokio::task::spawn_blocking(move || integrity::check_sidecar(&sidecar_path))
+        .await
+        .map_err(|e| AppError::SidecarSpawnFailed(e.to_string()))?;
+    match check {
+        integrity::Check::Verified => info!("Sidecar binary verified"),
+        integrity::Check::Skipped(reason) => warn!("Sidecar binary not verified: {}", reason),
+        integrity::Check::Failed(failure) => {
+            error!("{}", failure);
+            events::emit(app, "sidecar-integrity-failed", &failure);
+            return Err(AppError::SidecarIntegrity(failure.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Stop a zerobyte server this instance neither started nor adopted: ask it to shut down,
+/// then kill whatever still holds `port` once the grace period is over
+#[cfg(not(debug_assertions))]
+async fn retire_server(state: &AppState, port: u16) {
+    request_graceful_shutdown(&state.http, port).await;
+    let grace = state.config().shutdown_grace();
+    let holders = tokio::task::spawn_blocking(move || {
+        for pid in proctree::port_holders(port, grace) {
+            warn!("Pid {} still holds port {}, killing it", pid, port);
+            let report = proctree::ProcessTree::adopt(pid).kill();
+            if !report.survivors.is_empty() {
+                warn!("Server processes survived the kill: {:?}", report.survivors);
+            }
+        }
+        proctree::port_holders(port, Duration::from_secs(2))
+    })
+    .await
+    .unwrap_or_default();
+    if !holders.is_empty() {
+        warn!("Port {} is still held by pid(s) {:?}", port, holders);
+    }
+}
+
+/// Take over the sidecar a crashed run of this app left serving, so quitting stops it. A
+/// record whose process is gone, or that no longer serves zerobyte on its port (the PID may
+/// have been reused), is removed instead.
+async fn adopt_orphan(app: &tauri::AppHandle, state: &AppState) -> Option<u16> {
+    let data_dir = state.paths().data_dir.clone();
+    let record = orphan::read(&data_dir)?;
+    if !proctree::is_alive(record.pid) {
+        info!("Sidecar pid {} from an earlier run is gone", record.pid);
+        orphan::remove(&data_dir);
+        return None;
+    }
+
+    state.http.set_backend_token(record.token.clone());
+    let identity = verify_server_identity(app, record.port).await;
+    let serving = match identity {
+        Some(readiness::ServerIdentity::Zerobyte { .. }) => true,
+        // Servers that don't name themselves yet count if it's that process on the port
+        Some(readiness::ServerIdentity::Foreign) => {
+            let port = record.port;
+            tokio::task::spawn_blocking(move || proctree::port_holders(port, Duration::ZERO))
+                .await
+                .unwrap_or_default()
+                .contains(&record.pid)
+        }
+        None => false,
+    };
+    if !serving {
+        warn!(
+            "Pid {} from an earlier run no longer serves zerobyte on port {}, forgetting it",
+            record.pid, record.port
+        );
+        state.http.set_backend_token(None);
+        orphan::remove(&data_dir);
+        return None;
+    }
+
+    warn!(
+        "Adopting sidecar pid {} on port {} left running by an earlier run",
+        record.pid, record.port
+    );
+    *state.adopted_sidecar.lock() = Some(orphan::AdoptedSidecar {
+        port: record.port,
+        tree: proctree::ProcessTree::adopt_group(record.pid),
+    });
+    state.backend_port.store(record.port, Ordering::SeqCst);
+    if let Err(e) = ownership::claim(record.port) {
+        warn!("Failed to record backend ownership: {}", e);
+    }
+    set_connection_mode(app, ownership::ConnectionMode::Owner);
+    Some(record.port)
+}
+
+/// First port in `range` nothing is listening on
+fn free_port(candidates: impl IntoIterator<Item = u16>) -> Option<u16> {
+    candidates
+        .into_iter()
+        .find(|port| std::net::TcpListener::bind(("127.0.0.1", *port)).is_ok())
+}
+
+/// What kind of backend this instance talks to: the service, a sidecar it started or took
+/// over from a run that crashed, or a server it doesn't control
+pub(crate) async fn backend_mode(state: &AppState) -> ownership::BackendMode {
+    let owns_sidecar = state.sidecar_handle.lock().await.is_some()
+        || state.adopted_sidecar.lock().is_some();
+    ownership::BackendMode::of(state.using_service.load(Ordering::SeqCst), owns_sidecar)
+}
+
+/// Emit `backend-mode-changed` if the mode differs from the one last announced
+pub(crate) async fn announce_backend_mode(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let mode = backend_mode(&state).await;
+    let previous = state.backend_mode.lock().replace(mode);
+    if previous != Some(mode) {
+        info!("Backend mode: {:?}", mode);
+        events::emit(app, "backend-mode-changed", mode);
+    }
+}
+
+/// Start the sidecar server process
+/// Returns the port that the backend is running on
+pub async fn start_sidecar(app: &tauri::AppHandle, state: &AppState) -> Result<u16, AppError> {
+    let port = connect_backend(app, state).await;
+    match &port {
+        Ok(port) => loading::emit(app, LoadingStatus::Ready { port: *port }),
+        Err(AppError::Quitting) => {}
+        Err(e) => loading::emit(app, LoadingStatus::error(e)),
+    }
+    announce_backend_mode(app).await;
+    port
+}
+
+/// Use the service or a zerobyte server that's already running, or else spawn the sidecar
+async fn connect_backend(app: &tauri::AppHandle, state: &AppState) -> Result<u16, AppError> {
+    let mut config = state.config();
+    if let Some(port) = state.port_override {
+        config.sidecar_port = port;
+    } else if !profile::is_default() {
+        // Named profiles run next to each other, so none of them can count on a fixed port
+        config.sidecar_port = restore::allocate_port()
+            .map_err(|e| AppError::SidecarSpawnFailed(format!("no free port: {}", e)))?;
+    }
+    let service_port = config.service_port;
+    let sidecar_port = config.sidecar_port;
+
+    // The service makes up its own token; use it for as long as we're looking at the service
+    state
+        .http
+        .set_backend_token(backend_token::read_service_token());
+
+    // First, check if the Windows Service is running; it only serves the default profile, and
+    // not after switching to the sidecar
+    let probe = if profile::is_default() && !state.sidecar_preferred.load(Ordering::SeqCst) {
+        loading::emit(app, LoadingStatus::CheckingService);
+        pairing::probe_refreshing(
+            state.http.has_bearer(),
+            || probe_service(app),
+            || pairing::refresh(app, service_port),
+        )
+        .await
+    } else {
+        pairing::ServiceProbe::Absent
+    };
+    if probe == pairing::ServiceProbe::NeedsPairing {
+        info!(
+            "Service on port {} requires authentication; waiting for pairing",
+            service_port
+        );
+        state.using_service.store(true, Ordering::SeqCst);
+        state.backend_port.store(service_port, Ordering::SeqCst);
+        pairing::mark_needs_pairing(app, service_port);
+        return Ok(service_port);
+    }
+    if probe == pairing::ServiceProbe::Ready {
+        state.needs_pairing.store(false, Ordering::SeqCst);
+        info!("Windows Service detected on port {}, connecting to service instead of starting sidecar", service_port);
         state.using_service.store(true, Ordering::SeqCst);
-        state.backend_port.store(SERVICE_PORT, Ordering::SeqCst);
-        return Ok(SERVICE_PORT);
+        state.backend_port.store(service_port, Ordering::SeqCst);
+        // Only reinstalling the service updates it
+        if let Some(actual) = versions::mismatch(app, &state.http, service_port, None).await {
+            versions::report(app, actual, ownership::BackendMode::Service);
+        }
+        return Ok(service_port);
+    }
+
+    // A server this run didn't start doesn't know our token
+    state.http.set_backend_token(None);
+
+    if let Some(port) = adopt_orphan(app, state).await {
+        let Some(actual) = versions::mismatch(app, &state.http, port, None).await else {
+            return Ok(port);
+        };
+        warn!(
+            "Sidecar left by an earlier run is version {}, replacing it with the bundled {}",
+            actual,
+            versions::bundled_backend(app)
+        );
+        if let Err(e) = stop_sidecar(state).await {
+            warn!("Failed to stop the earlier run's sidecar: {}", e);
+        }
     }
 
     // In dev mode only, check if the Vite dev server is already running
     #[cfg(debug_assertions)]
-    if wait_for_server(DESKTOP_PORT, 30).await {
-        info!(
-            "Development server already running on port {}, skipping sidecar",
-            DESKTOP_PORT
-        );
-        return Ok(DESKTOP_PORT);
+    {
+        let policy = dev_server_policy();
+        let wait = policy == Some(true);
+        if policy != Some(false)
+            && dev_server_ready(app, sidecar_port, wait, config.startup_timeout()).await
+        {
+            info!(
+                "Development server already running on port {}, skipping sidecar",
+                sidecar_port
+            );
+            return Ok(sidecar_port);
+        }
+        if policy == Some(true) {
+            return Err(AppError::HealthcheckTimeout {
+                port: sidecar_port,
+                waited_secs: config.startup_timeout().as_secs(),
+            });
+        }
     }
 
-    // In release mode, quick check if server is already running (e.g., from previous instance)
+    // In release mode, quick check if server is already running (e.g., from previous instance),
+    // on the port asked for with --port or ZEROBYTE_PORT, else the one its owner recorded
+    #[cfg(not(debug_assertions))]
+    let existing_port = match state.port_override {
+        Some(port) => port,
+        None => ownership::read_owner()
+            .map(|owner| owner.port)
+            .unwrap_or(sidecar_port),
+    };
+    #[cfg(not(debug_assertions))]
+    let existing = if wait_for_server(app, existing_port, Duration::from_secs(1)).await
+        == readiness::ServerWait::Ready
+    {
+        // Servers that don't name themselves yet still count when a desktop recorded
+        // starting one there and is still running
+        let recorded = ownership::read_owner()
+            .is_some_and(|owner| owner.port == existing_port && proctree::is_alive(owner.pid));
+        match verify_server_identity(app, existing_port).await {
+            Some(readiness::ServerIdentity::Foreign) if recorded => {
+                Some(readiness::ServerIdentity::Zerobyte { version: None })
+            }
+            identity => identity,
+        }
+    } else {
+        None
+    };
+    // Something else answering 200 there must not be adopted; start our own on a free port
+    #[cfg(not(debug_assertions))]
+    if existing == Some(readiness::ServerIdentity::Foreign) {
+        let status = LoadingStatus::PortTaken {
+            port: existing_port,
+        };
+        warn!("{}", status);
+        loading::emit(app, status);
+    }
     #[cfg(not(debug_assertions))]
-    if wait_for_server(DESKTOP_PORT, 2).await {
+    if let Some(readiness::ServerIdentity::Zerobyte { version }) = existing {
+        let stale = versions::mismatch(app, &state.http, existing_port, version.clone()).await;
+        // The recorded owner only owns this server if it recorded this port
+        let foreign_owner = ownership::foreign_owner().filter(|owner| owner.port == existing_port);
+        if let (Some(actual), None) = (&stale, &foreign_owner) {
+            // Most likely the previous version's sidecar, still running after an update
+            warn!(
+                "Server {} on port {} isn't the bundled {}, replacing it",
+                actual,
+                existing_port,
+                versions::bundled_backend(app)
+            );
+            retire_server(state, existing_port).await;
+        } else {
+            info!(
+                "Server {} already running on port {}, skipping sidecar",
+                version.as_deref().unwrap_or("(unknown version)"),
+                existing_port
+            );
+            state.backend_port.store(existing_port, Ordering::SeqCst);
+            // Another session's sidecar: attach without being able to change it
+            if let Some(owner) = foreign_owner {
+                warn!(
+                    "Backend is owned by {} (pid {}), connecting as a viewer",
+                    owner.session, owner.pid
+                );
+                set_connection_mode(
+                    app,
+                    ownership::ConnectionMode::Viewer {
+                        owner: owner.session,
+                    },
+                );
+            }
+            if let Some(actual) = stale {
+                versions::report(app, actual, ownership::BackendMode::External);
+            }
+            return Ok(existing_port);
+        }
+    }
+
+    // Without a token anything on the machine could drive the backend
+    let Some(token) = state.sidecar_token.clone() else {
+        error!("{}", AppError::NoBackendToken);
+        return Err(AppError::NoBackendToken);
+    };
+
+    // Another program may hold the configured port; take the first free one in the range,
+    // skipping the ports of the service and of our own listeners
+    let metrics_port = app.state::<settings::SettingsStore>().get().metrics_port;
+    let mut reserved = vec![metrics_port.unwrap_or(metrics::DEFAULT_PORT)];
+    reserved.extend(app.state::<status_server::StatusServer>().port());
+    let sidecar_ports = config.sidecar_ports();
+    let Some(requested_port) = free_port(config.sidecar_port_candidates(&reserved)) else {
+        let error = AppError::NoFreePort {
+            first: sidecar_port,
+            last: *sidecar_ports.end(),
+        };
+        error!("{}", error);
+        return Err(error);
+    };
+    if requested_port != sidecar_port {
         info!(
-            "Server already running on port {}, skipping sidecar",
-            DESKTOP_PORT
+            "Port {} is taken by another program, using {}",
+            sidecar_port, requested_port
         );
-        return Ok(DESKTOP_PORT);
     }
+    state.backend_port.store(requested_port, Ordering::SeqCst);
 
-    let shell = app.shell();
-
-    // Get the resource directory where Tauri bundles our static files
-    let resource_dir = app
-        .path()
-        .resource_dir()
-        .map_err(|e| format!("Failed to get resource directory: {}", e))?;
+    // The resource directory is where Tauri bundles our static files, unless a developer
+    // points us at a local build
+    let resource_dir = state.paths().resource_dir.clone();
+    let asset_dir = app
+        .state::<settings::SettingsStore>()
+        .get()
+        .asset_dir_override
+        .unwrap_or_else(|| resource_dir.clone());
 
     info!("Resource directory: {}", resource_dir.display());
+    info!("Asset directory: {}", asset_dir.display());
+
+    // Fail fast if the static assets the server needs aren't there
+    let manifest = assets::AssetManifest::load(&asset_dir).map_err(AppError::AssetsMissing)?;
+    if let Err(missing) = assets::check_contract(&asset_dir, &manifest) {
+        error!("{}", missing);
+        events::emit(app, "sidecar-assets-missing", &missing);
+        return Err(AppError::AssetsMissing(missing.to_string()));
+    }
+    verify_sidecar_binary(app).await?;
 
-    // Get the sidecar command and set the working directory to resource_dir
-    // This ensures the server can find dist/client for static files
-    let sidecar_command = shell.sidecar("zerobyte-server")?.current_dir(resource_dir);
+    // Hand the asset root to the server explicitly; the working directory stays the resource
+    // dir for servers that still resolve dist/client relative to it. Spawning consumes the
+    // command, so each attempt builds its own.
+    let data_dir_override = paths::sidecar_data_dir(state.config().data_dir.as_deref());
+    if let Some(dir) = &data_dir_override {
+        info!("Backend data directory: {}", dir.display());
+    }
+    let build_command = || -> Result<_, AppError> {
+        let (sidecar_command, confinement) = build_sidecar_command(app, state)?;
+        let mut sidecar_command = sidecar_command
+            .current_dir(&resource_dir)
+            .env(assets::ASSET_DIR_ENV, &asset_dir)
+            .env("PORT", requested_port.to_string())
+            .env(backend_token::TOKEN_ENV, &token);
+        if let Some(dir) = &data_dir_override {
+            sidecar_command = sidecar_command.env(paths::DATA_DIR_ENV, dir);
+        }
+        if let Some(level) = logging::sidecar_level() {
+            sidecar_command = sidecar_command.env(logging::SIDECAR_LEVEL_ENV, level);
+        }
+        Ok((sidecar_command, confinement))
+    };
+    state.http.set_backend_token(state.sidecar_token.clone());
 
     info!(
         "Starting zerobyte-server sidecar on port {}...",
-        DESKTOP_PORT
+        requested_port
     );
 
-    // Spawn the sidecar process
-    let (mut rx, child) = sidecar_command.spawn()?;
-
-    // Store the child handle
-    {
-        let mut handle = state.sidecar_handle.lock().await;
-        *handle = Some(child);
+    // Spawn and record the process under the handle lock: a quit that begins meanwhile
+    // either finds the process to stop or keeps it from being spawned
+    let max_attempts = SPAWN_RETRY_DELAYS.len() as u32 + 1;
+    loading::emit(
+        app,
+        LoadingStatus::StartingSidecar {
+            attempt: 1,
+            max: max_attempts,
+        },
+    );
+    let mut retries = SPAWN_RETRY_DELAYS.iter().zip(2..);
+    let (mut handle, (mut rx, child), confinement) = loop {
+        let (sidecar_command, confinement) = build_command()?;
+        let handle = state.sidecar_handle.lock().await;
+        if state.shutdown_requested.load(Ordering::SeqCst) {
+            info!("Quitting, not starting the sidecar");
+            return Err(AppError::Quitting);
+        }
+        let e = match proctree::spawn(sidecar_command) {
+            Ok(spawned) => break (handle, spawned, confinement),
+            Err(e) => e,
+        };
+        drop(handle);
+        let retry = retries.next().filter(|_| spawn_failure_is_transient(&e));
+        let Some((delay, attempt)) = retry else {
+            error!("Failed to start the sidecar: {}", e);
+            return Err(AppError::SidecarSpawnFailed(e.to_string()));
+        };
+        warn!(
+            "Failed to start the sidecar ({}), retrying in {:?}",
+            e, delay
+        );
+        loading::emit(
+            app,
+            LoadingStatus::StartingSidecar {
+                attempt,
+                max: max_attempts,
+            },
+        );
+        tokio::time::sleep(*delay).await;
+    };
+    let generation = state.sidecar_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    info!("Sidecar generation {} has pid {}", generation, child.pid());
+    let level = app.state::<settings::SettingsStore>().get().backend_priority;
+    if level != priority::BackendPriority::Normal {
+        let pid = child.pid();
+        let applied = tokio::task::spawn_blocking(move || priority::apply(pid, level)).await;
+        if let Ok(Err(e)) = applied {
+            warn!("Failed to lower the backend's priority: {}", e);
+        }
     }
+    let tree = proctree::ProcessTree::adopt_group(child.pid());
+    let data_dir = state.paths().data_dir.clone();
+    let record =
+        orphan::SidecarRecord::new(child.pid(), requested_port, state.sidecar_token.clone());
+    orphan::record(&data_dir, &record);
+    app.state::<health::HealthLog>()
+        .record(health::HealthEvent::Restart { generation });
+
+    let (exit_tx, exited) = tokio::sync::oneshot::channel();
+    *handle = Some(SidecarProcess {
+        generation,
+        child,
+        tree,
+        started: Instant::now(),
+        exited,
+    });
+    drop(handle);
+    *state.confinement.lock() = confinement;
+
+    *state.banner.lock() = banner::BannerFacts::default();
+    // Carries the port the banner says the server listens on, once it says so
+    let (port_tx, mut port_rx) = tokio::sync::watch::channel(None);
 
     // Spawn a task to handle sidecar output
     let app_handle = app.clone();
-    tokio::spawn(async move {
+    supervisor::spawn_once(app, "sidecar-output", async move {
         use tauri_plugin_shell::process::CommandEvent;
 
+        let mut stdout_lines = banner::LineAssembler::default();
+        let logs_dir = app_handle
+            .state::<AppState>()
+            .paths()
+            .data_dir
+            .join(backend_log::LOGS_DIR);
+        let mut log_file = backend_log::LogFile::open(&logs_dir);
+        let mut exit_tx = Some(exit_tx);
+
         while let Some(event) = rx.recv().await {
             match event {
-                CommandEvent::Stdout(line) => {
-                    let line_str = String::from_utf8_lossy(&line);
-                    info!("[sidecar stdout] {}", line_str);
+                CommandEvent::Stdout(chunk) => {
+                    let state = app_handle.state::<AppState>();
+                    for line in stdout_lines.push(&chunk, true) {
+                        let redacted = state.redactor.read().redact(&line).into_owned();
+                        info!("[sidecar #{} stdout] {}", generation, redacted);
+                        let entry = state
+                            .backend_log
+                            .push(generation, backend_log::LogStream::Stdout, redacted);
+                        log_file.write(&entry);
+                        if state.sidecar_generation.load(Ordering::SeqCst) == generation {
+                            observe_banner_line(&app_handle, &line, &port_tx, requested_port);
+                        }
+                    }
                 }
                 CommandEvent::Stderr(line) => {
                     let line_str = String::from_utf8_lossy(&line);
-                    warn!("[sidecar stderr] {}", line_str);
+                    let state = app_handle.state::<AppState>();
+                    let redactor = state.redactor.read();
+                    for line in line_str.lines() {
+                        let redacted = redactor.redact(line).into_owned();
+                        warn!("[sidecar #{} stderr] {}", generation, redacted);
+                        let entry = state
+                            .backend_log
+                            .push(generation, backend_log::LogStream::Stderr, redacted);
+                        log_file.write(&entry);
+                    }
                 }
                 CommandEvent::Error(err) => {
-                    error!("[sidecar error] {}", err);
+                    error!("[sidecar #{} error] {}", generation, err);
                 }
                 CommandEvent::Terminated(payload) => {
-                    info!("[sidecar] Process terminated with code: {:?}", payload.code);
-                    // Optionally emit an event to the frontend
-                    let _ = app_handle.emit("sidecar-terminated", payload.code);
+                    info!(
+                        "[sidecar #{}] Process terminated with code: {:?}",
+                        generation, payload.code
+                    );
+                    log_file.flush();
+                    // stop_sidecar may be waiting on this while it holds the handle
+                    if let Some(exit_tx) = exit_tx.take() {
+                        let _ = exit_tx.send(payload.code);
+                    }
+                    let state = app_handle.state::<AppState>();
+                    let current = state.sidecar_generation.load(Ordering::SeqCst);
+                    let handle = state.sidecar_handle.lock().await;
+                    let held = handle.as_ref().map(|process| process.generation);
+                    let exit = classify_exit(generation, current, held);
+                    // A replaced instance exiting late says nothing about the current one
+                    if exit == SidecarExit::Superseded {
+                        info!(
+                            "[sidecar #{}] Superseded by generation {}, ignoring exit",
+                            generation, current
+                        );
+                        break;
+                    }
+                    // Helpers the server started may outlive it and keep the port or locks
+                    let mut crash_uptime = None;
+                    if let (SidecarExit::Crashed, Some(process)) = (exit, handle.as_ref()) {
+                        process.tree.kill_leftovers();
+                        app_handle.state::<health::HealthLog>().record(
+                            health::HealthEvent::Crash {
+                                kind: health::CrashKind::from_exit(payload.code),
+                                generation,
+                                code: payload.code,
+                            },
+                        );
+                        crash_uptime = Some(process.started.elapsed());
+                    }
+                    drop(handle);
+                    events::emit(&app_handle, "sidecar-terminated", payload.code);
+                    let quitting = state.shutdown_requested.load(Ordering::SeqCst);
+                    if let (Some(uptime), false) = (crash_uptime, quitting) {
+                        if payload.code != Some(0) {
+                            crashes::record(&app_handle, generation, uptime, payload.code);
+                            spawn_crash_recovery(
+                                app_handle.clone(),
+                                generation,
+                                uptime,
+                                payload.code,
+                            );
+                        }
+                    }
                     break;
                 }
                 _ => {}
@@ -197,19 +1279,117 @@ pub async fn start_sidecar(
         }
     });
 
-    // Wait for the server to be ready
-    if !wait_for_server(DESKTOP_PORT, 30).await {
-        return Err("Failed to start zerobyte-server".into());
+    // Wait for the server to be ready, on whichever port its banner says it took. Its
+    // listening line starts a new wait right away, so the first healthcheck goes out then
+    // instead of after the next backoff delay; servers that print no such line are polled.
+    let mut port = requested_port;
+    loop {
+        tokio::select! {
+            outcome = wait_for_server(app, port, config.startup_timeout()) => {
+                let error = match outcome {
+                    readiness::ServerWait::Ready => break,
+                    readiness::ServerWait::WrongServer => AppError::PortInUse(port),
+                    readiness::ServerWait::TimedOut => AppError::HealthcheckTimeout {
+                        port,
+                        waited_secs: config.startup_timeout_secs,
+                    },
+                    readiness::ServerWait::Cancelled => AppError::Quitting,
+                };
+                app.state::<health::HealthLog>().record(health::HealthEvent::Crash {
+                    kind: health::CrashKind::StartupFailure,
+                    generation,
+                    code: None,
+                });
+                return Err(error);
+            }
+            Ok(()) = port_rx.changed() => {
+                if let Some(announced) = *port_rx.borrow_and_update() {
+                    info!("Sidecar announced it listens on port {}", announced);
+                    port = announced;
+                    state.backend_port.store(port, Ordering::SeqCst);
+                }
+            }
+        }
     }
 
     info!("Sidecar server started successfully");
-    Ok(DESKTOP_PORT)
+    if port != requested_port {
+        orphan::record(&data_dir, &orphan::SidecarRecord { port, ..record });
+    }
+    if let Err(e) = ownership::claim(port) {
+        warn!("Failed to record backend ownership: {}", e);
+    }
+    set_connection_mode(app, ownership::ConnectionMode::Owner);
+    Ok(port)
 }
 
-/// Stop the sidecar server process gracefully
-pub async fn stop_sidecar(
-    state: &AppState,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Learn from a sidecar stdout line and react to settings it didn't take from us
+fn observe_banner_line(
+    app: &tauri::AppHandle,
+    line: &str,
+    port_tx: &tokio::sync::watch::Sender<Option<u16>>,
+    requested_port: u16,
+) {
+    let state = app.state::<AppState>();
+    let (before, facts) = {
+        let mut facts = state.banner.lock();
+        let before = facts.clone();
+        if !facts.observe(line) {
+            return;
+        }
+        (before, facts.clone())
+    };
+    if let (None, Some(port)) = (before.port, facts.port) {
+        let _ = port_tx.send(Some(port));
+    }
+
+    // Only react to facts this line taught us; the others were handled when first seen
+    let requested_data_dir = paths::backend_data_dir(state.config().data_dir.as_deref());
+    let known = before.divergences(requested_port, requested_data_dir.as_deref());
+    for divergence in facts.divergences(requested_port, requested_data_dir.as_deref()) {
+        if known.contains(&divergence) {
+            continue;
+        }
+        match divergence.setting {
+            "port" => {
+                warn!(
+                    "Sidecar is listening on port {} instead of the requested {}; its own \
+                     config overrides PORT. Following it to the real port.",
+                    divergence.actual, divergence.requested
+                );
+            }
+            _ => {
+                warn!(
+                    "Sidecar uses {} {} instead of the requested {}",
+                    divergence.setting, divergence.actual, divergence.requested
+                );
+                events::emit(app, "config-divergence", &divergence);
+            }
+        }
+    }
+}
+
+/// Stop the sidecar server process gracefully, killing it only if it's still running once
+/// the configured grace period is over
+pub async fn stop_sidecar(state: &AppState) -> Result<(), AppError> {
+    stop_sidecar_within(state, state.config().shutdown_grace()).await
+}
+
+/// The sidecar's exit code if it is reported within `grace`. A closed channel only means the
+/// output task is gone (it may have panicked), not that the process has exited, so it counts
+/// as still running
+async fn wait_for_exit(
+    exited: &mut tokio::sync::oneshot::Receiver<Option<i32>>,
+    grace: Duration,
+) -> Option<Option<i32>> {
+    match tokio::time::timeout(grace, exited).await {
+        Ok(Ok(code)) => Some(code),
+        Ok(Err(_)) | Err(_) => None,
+    }
+}
+
+/// [`stop_sidecar`] with a grace period of `grace` instead of the configured one
+pub(crate) async fn stop_sidecar_within(state: &AppState, grace: Duration) -> Result<(), AppError> {
     // Don't stop anything if we're using the service
     if state.using_service.load(Ordering::SeqCst) {
         info!("Using Windows Service, not stopping sidecar");
@@ -217,23 +1397,76 @@ pub async fn stop_sidecar(
     }
 
     let mut handle = state.sidecar_handle.lock().await;
+    let adopted = state.adopted_sidecar.lock().take();
 
-    if let Some(child) = handle.take() {
-        info!("Requesting graceful shutdown...");
+    if let Some(SidecarProcess {
+        generation,
+        child,
+        tree,
+        mut exited,
+        ..
+    }) = handle.take()
+    {
+        info!("Requesting graceful shutdown of sidecar #{}...", generation);
 
-        // Try graceful shutdown first
-        let graceful = request_graceful_shutdown(state.backend_port.load(Ordering::SeqCst)).await;
+        // Try graceful shutdown first, then wait for the process to actually exit
+        let port = state.backend_port.load(Ordering::SeqCst);
+        let waiting = Instant::now();
+        let exit = if request_graceful_shutdown(&state.http, port).await {
+            wait_for_exit(&mut exited, grace).await
+        } else {
+            exited.try_recv().ok()
+        };
 
-        if graceful {
-            // Wait a bit for graceful shutdown
-            tokio::time::sleep(Duration::from_secs(2)).await;
-        }
+        let killed = match exit {
+            Some(code) => {
+                info!(
+                    "Sidecar #{} exited gracefully after {:?} with code {:?}",
+                    generation,
+                    waiting.elapsed(),
+                    code
+                );
+                false
+            }
+            None => {
+                warn!(
+                    "Sidecar #{} still running after {:?}, killing its process tree",
+                    generation,
+                    waiting.elapsed()
+                );
+                true
+            }
+        };
 
-        // Kill the process if still running
-        info!("Terminating sidecar process...");
-        let _ = child.kill();
+        let holders = tokio::task::spawn_blocking(move || {
+            if killed {
+                let report = tree.kill();
+                if !report.survivors.is_empty() {
+                    warn!("Sidecar processes survived the kill: {:?}", report.survivors);
+                }
+            } else {
+                // Helpers the server started may outlive it
+                tree.kill_leftovers();
+            }
+            proctree::port_holders(port, Duration::from_secs(2))
+        })
+        .await
+        .unwrap_or_default();
+        if killed {
+            let _ = child.kill();
+            info!("Sidecar #{} was killed", generation);
+        }
+        if !holders.is_empty() {
+            warn!("Port {} is still held after stopping the sidecar by pid(s) {:?}", port, holders);
+        }
+        ownership::release();
+        orphan::remove(&state.paths().data_dir);
 
         info!("Sidecar stopped");
+    } else if let Some(adopted) = adopted {
+        stop_adopted_sidecar(state, adopted, grace).await;
+        ownership::release();
+        orphan::remove(&state.paths().data_dir);
     } else {
         info!("No sidecar process to stop");
     }
@@ -241,184 +1474,1494 @@ pub async fn stop_sidecar(
     Ok(())
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("zerobyte=info".parse().unwrap())
-                .add_directive("tauri=info".parse().unwrap()),
-        )
-        .init();
-
-    tauri::Builder::default()
-        .plugin(tauri_plugin_notification::init())
-        // Single instance plugin must be registered first
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
-            // Focus the main window when a new instance tries to start
-            if let Some(window) = app.get_webview_window("main") {
-                let _ = window.show();
-                let _ = window.set_focus();
-            }
-        }))
-        .plugin(tauri_plugin_shell::init())
-        .plugin(tauri_plugin_process::init())
+/// Stop a sidecar taken over from an earlier run. There's no child to wait on, so its PID is
+/// polled for `grace` before its tree is killed.
+async fn stop_adopted_sidecar(state: &AppState, adopted: orphan::AdoptedSidecar, grace: Duration) {
+    let pid = adopted.tree.pid();
+    info!("Requesting graceful shutdown of adopted sidecar pid {}...", pid);
+    let waiting = Instant::now();
+    let requested = request_graceful_shutdown(&state.http, adopted.port).await;
+    while requested && proctree::is_alive(pid) && waiting.elapsed() < grace {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+
+    let exited = !proctree::is_alive(pid);
+    if exited {
+        info!("Adopted sidecar exited after {:?}", waiting.elapsed());
+    } else {
+        warn!(
+            "Adopted sidecar pid {} still running after {:?}, killing its process tree",
+            pid,
+            waiting.elapsed()
+        );
+    }
+    let _ = tokio::task::spawn_blocking(move || {
+        if exited {
+            adopted.tree.kill_leftovers();
+        } else {
+            let report = adopted.tree.kill();
+            if !report.survivors.is_empty() {
+                warn!("Sidecar processes survived the kill: {:?}", report.survivors);
+            }
+        }
+    })
+    .await;
+}
+
+/// Kill the sidecar's process tree without asking it to shut down first, for a backend too
+/// hung to answer `/api/shutdown`
+async fn kill_sidecar(state: &AppState) {
+    let mut handle = state.sidecar_handle.lock().await;
+    let adopted = state.adopted_sidecar.lock().take();
+    let (tree, child) = match (handle.take(), adopted) {
+        (Some(process), _) => {
+            warn!("Killing sidecar #{} without a graceful shutdown", process.generation);
+            (process.tree, Some(process.child))
+        }
+        (None, Some(adopted)) => {
+            warn!("Killing adopted sidecar pid {}", adopted.tree.pid());
+            (adopted.tree, None)
+        }
+        (None, None) => {
+            info!("No sidecar process to kill");
+            return;
+        }
+    };
+
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let holders = tokio::task::spawn_blocking(move || {
+        let report = tree.kill();
+        if !report.survivors.is_empty() {
+            warn!("Sidecar processes survived the kill: {:?}", report.survivors);
+        }
+        proctree::port_holders(port, Duration::from_secs(2))
+    })
+    .await
+    .unwrap_or_default();
+    if let Some(child) = child {
+        let _ = child.kill();
+    }
+    if !holders.is_empty() {
+        warn!("Port {} is still held after killing the sidecar by pid(s) {:?}", port, holders);
+    }
+    ownership::release();
+    orphan::remove(&state.paths().data_dir);
+}
+
+/// Kill the sidecar and start it again, skipping the graceful shutdown a hung backend would
+/// only let time out. Progress is reported as `loading-status`. Refused in service mode.
+pub async fn force_restart_sidecar(app: &tauri::AppHandle) -> Result<u16, AppError> {
+    let state = app.state::<AppState>();
+    if state.using_service.load(Ordering::SeqCst) {
+        return Err(AppError::ManagedByService);
+    }
+    let _lifecycle = state
+        .lifecycle
+        .try_lock()
+        .map_err(|_| AppError::RestartInProgress)?;
+
+    warn!("Force-restarting the backend");
+    state.backend_ready.store(false, Ordering::SeqCst);
+    set_tray_backend_state(app, TrayBackendState::Restarting);
+    loading::emit(app, LoadingStatus::StoppingBackend);
+    kill_sidecar(&state).await;
+    state.recovery.lock().reset();
+    bring_up_sidecar(app).await
+}
+
+/// Stop the sidecar and start it again, then move the main window to wherever it came up.
+/// Returns the new port. Refused in service mode, where the service manager owns the backend.
+pub async fn restart_sidecar(app: &tauri::AppHandle) -> Result<u16, AppError> {
+    restart_sidecar_with(app, async { Ok(()) })
+        .await
+        .map(|(port, ())| port)
+}
+
+/// Like [`restart_sidecar`], running `while_stopped` between stopping the sidecar and starting
+/// it again. The sidecar is started again even if `while_stopped` fails.
+pub async fn restart_sidecar_with<T>(
+    app: &tauri::AppHandle,
+    while_stopped: impl std::future::Future<Output = Result<T, AppError>>,
+) -> Result<(u16, T), AppError> {
+    let state = app.state::<AppState>();
+    if state.using_service.load(Ordering::SeqCst) {
+        return Err(AppError::ManagedByService);
+    }
+    let _lifecycle = state
+        .lifecycle
+        .try_lock()
+        .map_err(|_| AppError::RestartInProgress)?;
+
+    info!("Restarting the backend");
+    state.backend_ready.store(false, Ordering::SeqCst);
+    set_tray_backend_state(app, TrayBackendState::Restarting);
+    stop_sidecar(&state).await?;
+    state.recovery.lock().reset();
+    let outcome = while_stopped.await;
+    let port = bring_up_sidecar(app).await?;
+    Ok((port, outcome?))
+}
+
+/// Start the sidecar, then point the capabilities, tray and main window at it
+pub(crate) async fn bring_up_sidecar(app: &tauri::AppHandle) -> Result<u16, AppError> {
+    let state = app.state::<AppState>();
+    let port = start_sidecar(app, &state).await.map_err(|e| {
+        set_tray_backend_state(app, TrayBackendState::Failed);
+        e
+    })?;
+    state.backend_port.store(port, Ordering::SeqCst);
+    state.backend_ready.store(true, Ordering::SeqCst);
+    refresh_capabilities(app).await;
+    set_tray_backend_state(app, TrayBackendState::Running);
+    info!("Backend restarted on port {}", port);
+    events::emit(app, "backend-restarted", port);
+    renavigate_main_window(app, port);
+    Ok(port)
+}
+
+/// Run [`recover_from_crash`] in the background. It's boxed because recovery starts the
+/// sidecar again through `connect_backend`, the function this is called from, and the
+/// compiler can't tell whether a future that contains itself is Send.
+fn spawn_crash_recovery(
+    app: tauri::AppHandle,
+    generation: u64,
+    uptime: Duration,
+    exit_code: Option<i32>,
+) {
+    let recovery: std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> =
+        Box::pin(recover_from_crash(app, generation, uptime, exit_code));
+    tauri::async_runtime::spawn(recovery);
+}
+
+/// Start the sidecar again after generation `generation` crashed, within the limits of
+/// `recovery`
+async fn recover_from_crash(
+    app: tauri::AppHandle,
+    generation: u64,
+    uptime: Duration,
+    exit_code: Option<i32>,
+) {
+    let state = app.state::<AppState>();
+    state.backend_ready.store(false, Ordering::SeqCst);
+
+    let Some(attempt) = state.recovery.lock().on_crash(uptime) else {
+        set_tray_backend_state(&app, TrayBackendState::Failed);
+        error!(
+            "Sidecar #{} crashed; giving up after {} restart attempts",
+            generation,
+            recovery::MAX_ATTEMPTS
+        );
+        return;
+    };
+    warn!(
+        "Sidecar #{} crashed after {:?}; restarting in {:?} (attempt {} of {})",
+        generation,
+        uptime,
+        recovery::RESTART_DELAY,
+        attempt,
+        recovery::MAX_ATTEMPTS
+    );
+    set_tray_backend_state(&app, TrayBackendState::Restarting);
+    events::emit(
+        &app,
+        "backend-restarting",
+        recovery::Restarting {
+            attempt,
+            max_attempts: recovery::MAX_ATTEMPTS,
+            delay_secs: recovery::RESTART_DELAY.as_secs(),
+            exit_code,
+        },
+    );
+
+    let quitting = state.http.child_token();
+    tokio::select! {
+        _ = quitting.cancelled() => return,
+        _ = tokio::time::sleep(recovery::RESTART_DELAY) => {}
+    }
+    if state.shutdown_requested.load(Ordering::SeqCst) {
+        return;
+    }
+    // A restart the user asked for in the meantime takes precedence
+    let Ok(_lifecycle) = state.lifecycle.try_lock() else {
+        return;
+    };
+    {
+        let mut handle = state.sidecar_handle.lock().await;
+        if handle.as_ref().map(|process| process.generation) != Some(generation) {
+            return;
+        }
+        // The process is gone; only the handle is left
+        handle.take();
+    }
+    if let Err(e) = bring_up_sidecar(&app).await {
+        error!("Failed to restart the sidecar after a crash: {}", e);
+    }
+}
+
+/// While viewing another session's backend, wait for that session to release it, then
+/// start our own sidecar and take over
+async fn watch_backend_owner(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "backend_owner",
+        activity::TaskClass::Standard,
+        OWNER_POLL_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+
+        let state = app.state::<AppState>();
+        if !state.connection_mode.lock().is_viewer() {
+            return;
+        }
+
+        let port = state.backend_port.load(Ordering::SeqCst);
+        let released = ownership::foreign_owner().is_none()
+            || wait_for_server(&app, port, Duration::ZERO).await != readiness::ServerWait::Ready;
+        if !released {
+            continue;
+        }
+
+        info!("Owning session released the backend, taking over");
+        set_connection_mode(&app, ownership::ConnectionMode::Owner);
+        match start_sidecar(&app, &state).await {
+            Ok(port) => {
+                // Someone else may have claimed it first, in which case keep viewing
+                if state.connection_mode.lock().is_viewer() {
+                    continue;
+                }
+                refresh_capabilities(&app).await;
+                renavigate_main_window(&app, port);
+                return;
+            }
+            Err(e) => {
+                error!("Failed to take over the backend: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Replay queued backend actions in order whenever the backend is reachable, dropping
+/// entries whose TTL passed while it wasn't
+async fn drain_outbox(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "outbox",
+        activity::TaskClass::Standard,
+        OUTBOX_DRAIN_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+
+        let outbox = app.state::<outbox::Outbox>();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        match outbox.take_expired(now) {
+            Ok(expired) => {
+                for entry in expired {
+                    warn!("Backend action {} expired before it could be applied", entry.id);
+                    events::emit(&app, "backend-action-expired", &entry);
+                }
+            }
+            Err(e) => error!("{}", e),
+        }
+
+        let state = app.state::<AppState>();
+        if !state.backend_ready.load(Ordering::SeqCst) {
+            continue;
+        }
+        let port = state.backend_port.load(Ordering::SeqCst);
+
+        while let Some(entry) = outbox.front() {
+            // Don't call endpoints this backend doesn't have
+            let feature = entry.action.feature();
+            if capabilities::availability(feature, state.capabilities())
+                != capabilities::Availability::Available
+            {
+                warn!(
+                    "Dropping queued action {}: backend does not support {:?}",
+                    entry.id, feature
+                );
+                if let Err(e) = outbox.remove(entry.id) {
+                    error!("{}", e);
+                    break;
+                }
+                events::emit(&app, "backend-action-rejected", &entry);
+                continue;
+            }
+
+            let (method, path, body) = entry.action.request();
+            let url = http::local_url(port, &path);
+            let result = state
+                .http
+                .send(HttpPolicy::BACKGROUND, |client| {
+                    let request = client.request(method.clone(), &url);
+                    match &body {
+                        Some(body) => request.json(body),
+                        None => request,
+                    }
+                })
+                .await;
+
+            let event = match result {
+                Ok(response) if response.status().is_success() => {
+                    info!("Applied queued backend action {}", entry.id);
+                    "backend-action-applied"
+                }
+                // The backend understood and refused it; retrying won't help
+                Ok(response) if response.status().is_client_error() => {
+                    warn!(
+                        "Backend rejected queued action {}: {}",
+                        entry.id,
+                        response.status()
+                    );
+                    "backend-action-rejected"
+                }
+                // Unreachable or failing: keep the entry and try again later
+                _ => break,
+            };
+
+            if let Err(e) = outbox.remove(entry.id) {
+                error!("{}", e);
+                break;
+            }
+            events::emit(&app, event, &entry);
+        }
+    }
+}
+
+/// Reflect the backend's state in the tray menu; backend pages are only enabled while it runs
+pub(crate) fn set_tray_backend_state(app: &tauri::AppHandle, backend: TrayBackendState) {
+    let Some(items) = app.try_state::<TrayBackendItems>() else {
+        return;
+    };
+    let text = match backend {
+        TrayBackendState::Running => "Backend running",
+        TrayBackendState::Restarting => "Restarting backend…",
+        TrayBackendState::Failed => "Backend failed to start",
+    };
+    let ready = backend == TrayBackendState::Running;
+    let _ = items
+        .status
+        .set_text(text::fit(app, text::Surface::MenuItem, text, text::Ellipsis::End));
+    for item in &items.pages {
+        let _ = item.set_enabled(ready);
+    }
+}
+
+/// The startup stages that follow the shell: start the backend, read its capabilities and
+/// navigate to it, then start the background pollers and file watchers and check the OS
+/// registrations. Everything after the backend runs even when it failed to start.
+async fn run_startup(app: tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    // The tray has to be up before the sidecar is spawned
+    state.startup.finished(startup::Stage::Shell).await;
+
+    if state.unclean_shutdown.load(Ordering::SeqCst) {
+        // Before the sidecar starts, so a stale discovery file doesn't make us a viewer
+        let handle = app.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || {
+            runtime_state::verify(&handle, true)
+        })
+        .await;
+    }
+
+    state.startup.begin(startup::Stage::Backend);
+    supervisor::spawn(
+        &app,
+        "backend-log",
+        supervisor::RestartPolicy::POLLER,
+        backend_log::flush,
+    );
+    // A token from an earlier pairing, if the service requires one
+    let handle = app.clone();
+    let _ = tauri::async_runtime::spawn_blocking(move || {
+        let port = handle.state::<AppState>().config().service_port;
+        pairing::load_into_client(&handle, port)
+    })
+    .await;
+    info!("Starting backend...");
+    let port = match start_sidecar(&app, &state).await {
+        Ok(port) => {
+            state.startup.finish(startup::Stage::Backend);
+            Some(port)
+        }
+        Err(e) => {
+            error!("Failed to start backend: {}", e);
+            state.startup.fail(startup::Stage::Backend, e.to_string());
+            set_tray_backend_state(&app, TrayBackendState::Failed);
+            None
+        }
+    };
+
+    if let Some(port) = port {
+        state.startup.begin(startup::Stage::Capabilities);
+        info!("Backend ready on port {}, navigating to server...", port);
+        state.backend_ready.store(true, Ordering::SeqCst);
+        refresh_capabilities(&app).await;
+        palette::notify_actions_changed(&app);
+        set_tray_backend_state(&app, TrayBackendState::Running);
+        state.startup.finish(startup::Stage::Capabilities);
+
+        // Navigate to the SSR server instead of using static assets
+        renavigate_main_window(&app, port);
+        if args::current().headless {
+            info!("Backend serving at {}", http::local_url(port, "/"));
+        }
+    }
+
+    state.startup.begin(startup::Stage::Pollers);
+    let poller = supervisor::RestartPolicy::POLLER;
+    supervisor::spawn(&app, "outbox", poller, drain_outbox);
+    supervisor::spawn(&app, "restore-reaper", poller, restore::reap_idle);
+    supervisor::spawn(&app, "config-drift", poller, drift::watch_config);
+    supervisor::spawn(&app, "backend-health", poller, monitor::watch);
+    supervisor::spawn(&app, "heartbeat", poller, heartbeat::monitor);
+    supervisor::spawn(&app, "time-zone", poller, timezone::watch);
+    supervisor::spawn(&app, "retention", poller, retention::run);
+    supervisor::spawn(&app, "backend-token", poller, pairing::keep_fresh);
+    supervisor::spawn(&app, "bandwidth-schedule", poller, bandwidth::run);
+    supervisor::spawn(&app, "accessibility", poller, accessibility::watch);
+    let settings = app.state::<settings::SettingsStore>().get();
+    if settings.metrics_enabled {
+        let port = settings.metrics_port.unwrap_or(metrics::DEFAULT_PORT);
+        supervisor::spawn(&app, "metrics", poller, move |app| {
+            metrics::serve(app, port)
+        });
+    }
+    if state.connection_mode.lock().is_viewer() {
+        supervisor::spawn(&app, "backend-owner", poller, watch_backend_owner);
+    }
+    state.startup.finish(startup::Stage::Pollers);
+
+    state.startup.begin(startup::Stage::Watchers);
+    watch::restore_all(app.clone()).await;
+    state.startup.finish(startup::Stage::Watchers);
+
+    // An in-place update may have moved the install away from what the OS launches
+    state.startup.begin(startup::Stage::Registrations);
+    let finder = app.clone();
+    let stale = tauri::async_runtime::spawn_blocking(move || registrations::find_stale(&finder));
+    match stale.await {
+        Ok(stale) if !stale.is_empty() => {
+            for registration in &stale {
+                warn!(
+                    "Stale {:?} registration: {} (expected {})",
+                    registration.kind,
+                    registration.recorded,
+                    registration.expected.display()
+                );
+            }
+            events::emit(&app, "stale-registrations-detected", &stale);
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to check registrations: {}", e),
+    }
+    state.startup.finish(startup::Stage::Registrations);
+
+    // Offer to bring over data from a pre-desktop install, until a migration has run
+    state.startup.begin(startup::Stage::Legacy);
+    let report = state.paths().data_dir.join(legacy::REPORT_FILE);
+    let found = tauri::async_runtime::spawn_blocking(move || {
+        legacy::load_report(&report)
+            .is_none()
+            .then(|| legacy::detect(&legacy::SystemLocations))
+            .flatten()
+    });
+    match found.await {
+        Ok(Some(install)) => {
+            info!("Found legacy install at {}", install.root.display());
+            events::emit(&app, "legacy-install-detected", &install);
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to look for a legacy install: {}", e),
+    }
+    state.startup.finish(startup::Stage::Legacy);
+}
+
+/// Create the tray icon and its menu. Backend pages stay disabled until the backend is up.
+fn create_tray(app: &tauri::App) -> tauri::Result<()> {
+    let status = MenuItem::with_id(app, "status", "Starting…", false, None::<&str>)?;
+    let show = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+    let quick_actions = MenuItem::with_id(
+        app,
+        "quick_actions",
+        "Quick actions…",
+        true,
+        Some("CmdOrCtrl+Shift+Space"),
+    )?;
+    let separator1 = MenuItem::with_id(app, "sep1", "────────────", false, None::<&str>)?;
+    let volumes = MenuItem::with_id(app, "volumes", "Volumes", false, None::<&str>)?;
+    let repositories = MenuItem::with_id(app, "repositories", "Repositories", false, None::<&str>)?;
+    let backups = MenuItem::with_id(app, "backups", "Backups", false, None::<&str>)?;
+    let notifications =
+        MenuItem::with_id(app, "notifications", "Notifications", false, None::<&str>)?;
+    let settings = MenuItem::with_id(app, "settings", "Settings", false, None::<&str>)?;
+    let separator2 = MenuItem::with_id(app, "sep2", "────────────", false, None::<&str>)?;
+    let battery_saver = CheckMenuItem::with_id(
+        app,
+        "battery_saver",
+        "Battery saver",
+        true,
+        app.state::<AppState>().activity.mode() == activity::ActivityMode::Suspended,
+        None::<&str>,
+    )?;
+    app.manage(battery_saver.clone());
+    let bandwidth = Submenu::with_id(app, "bandwidth", "Bandwidth", true)?;
+    app.manage(bandwidth::BandwidthMenu(bandwidth.clone()));
+    bandwidth::rebuild_tray(app.handle());
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+
+    let menu = Menu::with_items(
+        app,
+        &[
+            &status,
+            &show,
+            &quick_actions,
+            &separator1,
+            &volumes,
+            &repositories,
+            &backups,
+            &notifications,
+            &settings,
+            &separator2,
+            &battery_saver,
+            &bandwidth,
+            &quit,
+        ],
+    )?;
+
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
+        .icon(app.default_window_icon().unwrap().clone())
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .tooltip(profile::app_title())
+        .on_menu_event(|app, event| match event.id.as_ref() {
+            "show" => show_main_window(app),
+            "quick_actions" => {
+                if let Err(e) = palette::toggle(app) {
+                    error!("Failed to open quick actions palette: {}", e);
+                }
+            }
+            "volumes" | "repositories" | "backups" | "notifications" | "settings" => {
+                navigate_main_window(app, event.id.as_ref());
+            }
+            "battery_saver" => {
+                let mode = match app.state::<AppState>().activity.mode() {
+                    activity::ActivityMode::Suspended => activity::ActivityMode::Normal,
+                    _ => activity::ActivityMode::Suspended,
+                };
+                if let Err(e) = set_background_activity(app, mode) {
+                    error!("Failed to change background activity: {}", e);
+                }
+            }
+            id if id.starts_with(bandwidth::MENU_PREFIX) => {
+                bandwidth::on_menu_event(app, &id[bandwidth::MENU_PREFIX.len()..]);
+            }
+            "quit" => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    if confirm_quit(&app).await {
+                        quit_app(&app);
+                    }
+                });
+            }
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        })
+        .build(app)?;
+    app.manage(TrayBackendItems {
+        status,
+        pages: vec![volumes, repositories, backups, notifications, settings],
+    });
+    refresh_tray_tooltip(app.handle());
+    Ok(())
+}
+
+/// The main window, created from its entry in `tauri.conf.json` if it doesn't exist yet. It
+/// isn't created on its own, so a headless run has none until something asks to show it.
+/// It's always created hidden, so starting minimized never flashes it; callers that want it
+/// seen show it.
+pub fn ensure_main_window(app: &tauri::AppHandle) -> tauri::Result<tauri::WebviewWindow> {
+    if let Some(window) = app.get_webview_window("main") {
+        return Ok(window);
+    }
+    let config = app
+        .config()
+        .app
+        .windows
+        .iter()
+        .find(|window| window.label == "main")
+        .ok_or(tauri::Error::WindowNotFound)?;
+    let window = tauri::WebviewWindowBuilder::from_config(app, config)?
+        .visible(false)
+        .build()?;
+    if !profile::is_default() {
+        let _ = window.set_title(&profile::app_title());
+    }
+    Ok(window)
+}
+
+/// Show the main window and bring it to focus, creating it first in a headless run
+pub fn show_main_window(app: &tauri::AppHandle) {
+    let created = app.get_webview_window("main").is_none();
+    let window = match ensure_main_window(app) {
+        Ok(window) => window,
+        Err(e) => {
+            error!("Failed to create the main window: {}", e);
+            return;
+        }
+    };
+    let _ = window.show();
+    let _ = window.set_focus();
+    // A new window starts on the bundled loading page
+    let state = app.state::<AppState>();
+    if created && state.backend_ready.load(Ordering::SeqCst) {
+        renavigate_main_window(app, state.backend_port.load(Ordering::SeqCst));
+    }
+}
+
+/// Show the main window and navigate it to a page served by the backend
+pub fn navigate_main_window(app: &tauri::AppHandle, route: &str) {
+    let state = app.state::<AppState>();
+    *state.last_route.lock() = route.to_string();
+    show_main_window(app);
+    renavigate_main_window(app, state.backend_port.load(Ordering::SeqCst));
+}
+
+/// A launch found this instance running and handed it `argv` and its working directory `cwd`:
+/// open the file and the `--page` it names, and bring the window up unless it asked to stay
+/// minimized
+fn on_second_instance(app: &tauri::AppHandle, argv: Vec<String>, cwd: String) {
+    let options = match args::parse(argv.into_iter().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => return,
+        Err(e) => {
+            // The launch checked its arguments before handing them over, so this is unlikely
+            warn!("Ignoring the arguments of a second launch: {}", e);
+            show_main_window(app);
+            return;
+        }
+    };
+    info!("Another launch found this instance running");
+    if let Some(file) = options.file {
+        open_file(app, std::path::Path::new(&cwd).join(file));
+    }
+    if let Some(page) = options.page {
+        let state = app.state::<AppState>();
+        *state.last_route.lock() = page;
+        // Until the backend is up, startup navigates to it
+        if state.backend_ready.load(Ordering::SeqCst) {
+            renavigate_main_window(app, state.backend_port.load(Ordering::SeqCst));
+        }
+    }
+    if !options.minimized {
+        show_main_window(app);
+    }
+}
+
+/// Tell the UI to open `path`, given on the command line
+fn open_file(app: &tauri::AppHandle, path: std::path::PathBuf) {
+    info!("Opening {}", path.display());
+    events::emit(app, "open-file", path);
+}
+
+/// Point the main window at the page it was last sent to (see `last_route`) on the backend
+/// at `port`, once that backend is ready. A window hidden in the tray is navigated as well,
+/// so it doesn't show a dead page when it's opened again.
+pub fn renavigate_main_window(app: &tauri::AppHandle, port: u16) {
+    let Some(window) = app.get_webview_window("main") else {
+        // Headless runs only get a window once something asks to show it
+        if !args::current().headless {
+            error!("Could not get main window");
+        }
+        return;
+    };
+    let route = app.state::<AppState>().last_route.lock().clone();
+    let url = http::local_url(port, &format!("/{}", route));
+    info!("Navigating main window to {}", url);
+    if let Err(e) = window.navigate(url.parse().unwrap()) {
+        error!("Failed to navigate to {}: {}", url, e);
+    }
+}
+
+/// Whether quitting may go ahead: asks first if backups are running on a sidecar we own,
+/// since quitting stops them
+async fn confirm_quit(app: &tauri::AppHandle) -> bool {
+    let state = app.state::<AppState>();
+    let owns_sidecar = state.backend_ready.load(Ordering::SeqCst)
+        && !state.using_service.load(Ordering::SeqCst)
+        && !state.connection_mode.lock().is_viewer();
+    if !owns_sidecar || !drain::supported(state.capabilities()) {
+        return true;
+    }
+    let backend = drain::HttpBackend {
+        http: &state.http,
+        port: state.backend_port.load(Ordering::SeqCst),
+    };
+    let running = match drain::DrainBackend::running_jobs(&backend).await {
+        Ok(jobs) => jobs.len(),
+        Err(_) => return true,
+    };
+    running == 0
+        || dialogs::confirm(app, dialogs::Prompt::QuitDuringBackup { running }, false, None)
+            .await
+            .is_ok()
+}
+
+/// Stop the sidecar and exit the app
+/// Goes through `ExitRequested` like every other exit, so the shared shutdown runs once
+pub fn quit_app(app: &tauri::AppHandle) {
+    app.exit(0);
+}
+
+/// The shared shutdown sequence: interrupt outbound calls, end restore sessions and stop the
+/// sidecar, bounded by the shutdown barrier
+async fn run_shutdown(app: &tauri::AppHandle, reason: shutdown::ExitReason) {
+    info!("Shutting down ({:?})", reason);
+    let state = app.state::<AppState>();
+    state.shutdown_requested.store(true, Ordering::SeqCst);
+    let barrier = shutdown::barrier(state.config().shutdown_grace());
+    shutdown::arm_hard_exit(barrier + shutdown::HARD_EXIT_AFTER, reason.exit_code());
+    events::emit(app, "quitting", reason);
+    refresh_tray_tooltip(app);
+    if let Some(items) = app.try_state::<TrayBackendItems>() {
+        let text = "Shutting down...";
+        let _ = items
+            .status
+            .set_text(text::fit(app, text::Surface::MenuItem, text, text::Ellipsis::End));
+    }
+
+    // Interrupt pollers and probes so nothing holds up the exit
+    state.http.cancel_all();
+
+    if reason.forced() {
+        info!("Forced exit, not waiting for running backups");
+    }
+
+    let stop = async {
+        restore::end_all(app, restore::EndReason::Shutdown).await;
+        stop_sidecar(&state).await
+    };
+    match tokio::time::timeout(barrier, stop).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Failed to stop sidecar: {}", e),
+        Err(_) => warn!("Shutdown did not finish within {:?}, exiting anyway", barrier),
+    }
+    state.shutdown.finish();
+    runtime_state::clear_running(&state.paths().data_dir);
+}
+
+/// Shut down and relaunch into a freshly installed update
+/// The next start is marked as post-update so update follow-ups run right away
+pub async fn restart_for_update(app: &tauri::AppHandle) {
+    if let Err(e) = app
+        .state::<settings::SettingsStore>()
+        .update(|settings| settings.post_update = true)
+    {
+        warn!("Failed to mark the next start as post-update: {}", e);
+    }
+
+    let state = app.state::<AppState>();
+    if state.shutdown.begin() {
+        run_shutdown(app, shutdown::ExitReason::Update).await;
+    }
+    app.restart();
+}
+
+/// Last chance to stop the sidecar for exits that never asked first: a restart through the
+/// process plugin, or an event loop that ends on its own. Blocks until it has stopped.
+fn stop_sidecar_on_exit(app: &tauri::AppHandle) {
+    let grace = app.state::<AppState>().config().shutdown_grace();
+    let stopped = shutdown_quietly(app, grace, shutdown::barrier(grace));
+    if tauri::async_runtime::block_on(stopped) {
+        warn!("Exited without the shutdown sequence, stopped the backend on the way out");
+    }
+}
+
+/// The shutdown sequence without the tray and window updates, for exits that come after the
+/// windows are gone or while the main thread waits on it. The sidecar gets `grace` and the
+/// whole sequence `barrier`. Returns false, doing nothing, if shutdown had already begun.
+pub(crate) async fn shutdown_quietly(
+    app: &tauri::AppHandle,
+    grace: Duration,
+    barrier: Duration,
+) -> bool {
+    let state = app.state::<AppState>();
+    if !state.shutdown.begin() {
+        return false;
+    }
+    state.shutdown_requested.store(true, Ordering::SeqCst);
+    state.http.cancel_all();
+    let stop = async {
+        restore::end_all(app, restore::EndReason::Shutdown).await;
+        stop_sidecar_within(&state, grace).await
+    };
+    match tokio::time::timeout(barrier, stop).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Failed to stop sidecar: {}", e),
+        Err(_) => warn!("Shutdown did not finish within {:?}, exiting anyway", barrier),
+    }
+    state.shutdown.finish();
+    runtime_state::clear_running(&state.paths().data_dir);
+    true
+}
+
+/// Hold back the first exit request until the shared shutdown sequence has run
+fn handle_run_event(app: &tauri::AppHandle, event: tauri::RunEvent) {
+    match event {
+        tauri::RunEvent::ExitRequested { code, api, .. } => {
+            let step = app.state::<AppState>().shutdown.on_exit_requested();
+            if step == shutdown::ExitStep::Proceed {
+                return;
+            }
+
+            api.prevent_exit();
+            if step == shutdown::ExitStep::Wait {
+                // Already shutting down; that sequence exits when it's done
+                return;
+            }
+
+            let reason = shutdown::ExitReason::classify(code);
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                run_shutdown(&app, reason).await;
+                app.exit(reason.exit_code());
+            });
+        }
+        tauri::RunEvent::Exit => {
+            stop_sidecar_on_exit(app);
+            info!("Exiting");
+        }
+        _ => {}
+    }
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    logging::init();
+
+    let launch = args::from_env();
+    if launch.profile != profile::DEFAULT {
+        info!("Running profile {}", launch.profile);
+    }
+    if launch.headless {
+        info!("Running headless");
+    }
+    let port_override = launch.port;
+    let page = launch.page.clone().unwrap_or_default();
+    profile::set(launch.profile.clone());
+    args::set(launch);
+    let state = AppState {
+        port_override,
+        last_route: parking_lot::Mutex::new(page),
+        ..AppState::default()
+    };
+    if let Some(port) = port_override {
+        info!("Sidecar port overridden to {}", port);
+        state.backend_port.store(port, Ordering::SeqCst);
+    }
+
+    let mut builder = tauri::Builder::default().plugin(tauri_plugin_notification::init());
+    // Single instance plugin must be registered first. It's scoped to the app identifier, so
+    // only the default profile uses it; named profiles lock their data directory in setup.
+    if profile::is_default() {
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            on_second_instance(app, argv, cwd);
+        }));
+    }
+    builder
+        .plugin(tauri_plugin_shell::init())
+        // Tell every page in the main window which desktop features this shell offers
+        .on_page_load(|webview, payload| {
+            if payload.event() != PageLoadEvent::Started {
+                return;
+            }
+            accessibility::on_page_load(webview, payload.url());
+            if webview.label() != "main" {
+                return;
+            }
+            let mode = webview
+                .state::<AppState>()
+                .connection_mode
+                .lock()
+                .clone();
+            let _ = webview.eval(desktop::init_script(&mode));
+            let _ = webview.eval(heartbeat::script());
+            status_server::on_page_load(webview, payload.url());
+        })
+        .plugin(tauri_plugin_process::init())
+        .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_autostart::init(
             tauri_plugin_autostart::MacosLauncher::LaunchAgent,
-            Some(vec!["--minimized"]),
+            Some(vec![args::MINIMIZED_FLAG]),
         ))
-        .manage(AppState::default())
-        .invoke_handler(tauri::generate_handler![
-            commands::get_backend_url,
-            commands::get_backend_info,
-            commands::show_window,
-            commands::service::get_service_status,
-            commands::service::install_service,
-            commands::service::uninstall_service,
-            commands::service::start_service,
-            commands::service::stop_service,
-            commands::service::is_service_running,
-        ])
+        .manage(state)
+        .manage(discovery::DiscoveryControl::default())
+        .manage(status_server::StatusServer::default())
+        .manage(estimate::Estimator::default())
+        .manage(bandwidth::BandwidthState::default())
+        .manage(supervisor::TaskHealth::default())
+        .manage(accessibility::AccessibilityState::default())
+        .manage(protection::ProtectionCache::default())
+        .manage(actions::PlanCache::default())
+        .invoke_handler({
+            let handler: fn(tauri::ipc::Invoke) -> bool = tauri::generate_handler![
+                commands::get_backend_url,
+                commands::get_backend_info,
+                commands::restart_backend,
+                commands::force_restart_backend,
+                commands::pair_with_backend,
+                commands::get_recent_backend_logs,
+                commands::get_backend_logs,
+                commands::list_crash_reports,
+                commands::read_crash_report,
+                commands::open_backend_log_folder,
+                commands::get_task_health,
+                commands::verify_runtime_state,
+                commands::config::get_settings,
+                commands::config::update_settings,
+                commands::config::get_data_directory,
+                commands::config::set_data_directory,
+                commands::get_accessibility_preferences,
+                commands::show_window,
+                commands::actions::list_actions,
+                commands::actions::execute_action,
+                commands::actions::toggle_palette,
+                commands::actions::hide_palette,
+                commands::schedule::get_schedule_conflicts,
+                commands::estimate::estimate_backup_size,
+                commands::estimate::cancel_backup_estimate,
+                commands::schedule::get_time_zones,
+                commands::schedule::convert_backend_times,
+                commands::access::check_path_access,
+                commands::access::grant_path_access,
+                commands::access::get_controlled_folder_access,
+                commands::access::request_cfa_allowlist,
+                commands::service::get_service_status,
+                commands::service::install_service,
+                commands::service::uninstall_service,
+                commands::service::start_service,
+                commands::service::stop_service,
+                commands::service::is_service_running,
+                commands::service::get_service_capabilities,
+                commands::service::get_elevation_status,
+                commands::service::switch_backend_mode,
+                commands::get_app_info,
+                commands::get_connection_mode,
+                commands::devtools::get_devtools_status,
+                commands::devtools::toggle_devtools,
+                commands::outbox::queue_backend_action,
+                commands::outbox::get_pending_backend_actions,
+                commands::discovery::discover_backends,
+                commands::discovery::cancel_discovery,
+                commands::set_background_activity,
+                commands::passphrase::prompt_repository_passphrase,
+                commands::passphrase::set_remember_passphrase,
+                commands::redaction::preview_redaction,
+                commands::redaction::set_redaction_rules,
+                commands::restore::start_restore_session,
+                commands::restore::list_restore_sessions,
+                commands::restore::end_restore_session,
+                commands::watch::watch_paths_for_plan,
+                commands::watch::unwatch_plan,
+                commands::watch::list_active_watchers,
+                commands::get_startup_stages,
+                commands::sync_events,
+                commands::get_desktop_capabilities,
+                commands::cancel_drain,
+                commands::set_fault_profile,
+                commands::get_system_report,
+                commands::run_protection_audit,
+                commands::get_health_history,
+                commands::get_metrics_snapshot,
+                commands::webview_heartbeat,
+                commands::scratch::get_scratch_usage,
+                commands::scratch::clear_scratch,
+                commands::retention::preview_retention_cleanup,
+                commands::retention::run_retention_cleanup,
+                commands::retention::set_retention_rules,
+                commands::bandwidth::get_bandwidth_profiles,
+                commands::bandwidth::set_active_bandwidth_profile,
+                commands::bandwidth::set_bandwidth_schedule,
+                commands::get_backend_process_info,
+                commands::get_backend_status,
+                commands::get_backend_mode,
+                commands::get_versions,
+                commands::get_launch_options,
+                commands::get_backend_resource_usage,
+                commands::set_backend_sandbox,
+                commands::set_backend_priority,
+                commands::get_log_level,
+                commands::get_desktop_log_path,
+                commands::set_log_level,
+                commands::registrations::get_stale_registrations,
+                commands::registrations::repair_registrations,
+                commands::legacy::get_legacy_install,
+                commands::legacy::get_legacy_migration_report,
+                commands::legacy::migrate_from_legacy,
+                commands::legacy::cleanup_legacy_install,
+                commands::notifications::mute_plan_notifications,
+                commands::notifications::unmute_plan_notifications,
+                commands::notifications::list_notification_mutes,
+            ];
+            // Central read-only gate: a viewer can't run commands that change backend state
+            move |invoke| {
+                let command = invoke.message.command().to_string();
+                let mode = invoke
+                    .message
+                    .webview()
+                    .state::<AppState>()
+                    .connection_mode
+                    .lock()
+                    .clone();
+                if let Err(e) = mode.check_command(&command) {
+                    warn!("Refused {} in viewer mode", command);
+                    invoke.resolver.reject(e);
+                    return true;
+                }
+                handler(invoke)
+            }
+        })
         .setup(|app| {
             let app_handle = app.handle().clone();
+            app.state::<AppState>().startup.begin(startup::Stage::Shell);
+            let launch = args::current();
+            if !launch.headless {
+                ensure_main_window(app.handle())?;
+            }
+
+            // Resolve and validate every app directory once, up front
+            let app_paths = paths::Paths::resolve(app.handle()).map_err(|e| {
+                error!("{}", e);
+                e
+            })?;
+            // Before anything starts the backend, so its failures are in the file too
+            if let Err(e) = logging::open_file(&app_paths.log_dir) {
+                warn!("{}", e);
+            }
+            info!("Application paths: {:?}", app_paths);
+            if !profile::is_default() {
+                if let Err(e) = profile::lock_instance(&app_paths.data_dir) {
+                    warn!("{}, exiting", e);
+                    std::process::exit(0);
+                }
+            }
+            let settings_path = app_paths.config_dir.join(settings::SETTINGS_FILE);
+            let config_path = app_paths.config_dir.join(config::CONFIG_FILE);
+            let outbox_path = app_paths.data_dir.join(outbox::OUTBOX_FILE);
+            let watchers_path = app_paths.data_dir.join(watch::WATCHERS_FILE);
+            let health_path = app_paths.data_dir.join(health::HISTORY_FILE);
+            let cache_dir = app_paths.cache_dir.clone();
+            let profile_backend_dir = profile::backend_data_dir(&app_paths.data_dir);
+            if runtime_state::mark_running(&app_paths.data_dir) {
+                warn!("The previous run did not shut down cleanly");
+                app.state::<AppState>()
+                    .unclean_shutdown
+                    .store(true, Ordering::SeqCst);
+            }
+            let _ = app.state::<AppState>().paths.set(app_paths);
+
+            // Ports and timeouts, before settings.json drops the close-to-tray choice it used
+            // to hold
+            let mut desktop_config = config::load_or_create(&config_path, &settings_path);
+            // A named profile's sidecar must not share the default profile's database
+            if let (true, Some(dir)) = (desktop_config.data_dir.is_none(), profile_backend_dir) {
+                desktop_config.data_dir = Some(dir);
+                if let Err(e) = config::save(&config_path, &desktop_config) {
+                    warn!("{}", e);
+                }
+            }
+            *app.state::<AppState>().config.write() = desktop_config;
+            let service_port = app.state::<AppState>().config().service_port;
+            if app.state::<AppState>().port_override == Some(service_port) {
+                let message = format!(
+                    "Port {} is the Windows Service's port (service_port in {}); choose another",
+                    service_port,
+                    config_path.display()
+                );
+                error!("{}", message);
+                return Err(message.into());
+            }
+
+            // Load persisted settings, recovering from a damaged file if needed
+            let fresh_install = !settings_path.exists()
+                && !persist::backup_path(&settings_path).exists();
+            let (settings_store, recovered) = settings::SettingsStore::load(settings_path);
+            if fresh_install {
+                // Nothing to review yet, so retention may clean up from the start
+                let confirmed =
+                    settings_store.update(|settings| settings.retention_confirmed = true);
+                if let Err(e) = confirmed {
+                    warn!("Failed to enable automatic retention: {}", e);
+                }
+            }
+            if let Some(recovered) = recovered {
+                warn!(
+                    "Settings file was damaged, loaded from {} ({:?})",
+                    recovered.file, recovered.source
+                );
+                events::emit(app.handle(), "settings-recovered", recovered);
+            }
+            if let Some(directive) = settings_store.get().log_level {
+                match logging::set(&directive) {
+                    Ok(()) => info!("Log level: {}", directive),
+                    Err(e) => warn!("Ignoring the saved log level: {}", e),
+                }
+            }
+            app.state::<AppState>()
+                .activity
+                .set_mode(settings_store.get().background_activity);
+            let (redactor, invalid_rules) =
+                redact::Redactor::new(&settings_store.get().redaction_rules);
+            for rule in invalid_rules {
+                warn!("Ignoring invalid redaction rule {:?}: {}", rule.pattern, rule.error);
+            }
+            *app.state::<AppState>().redactor.write() = redactor;
+            if settings_store.get().post_update {
+                info!("First start after an update");
+                events::emit(app.handle(), "post-update", ());
+                if let Err(e) = settings_store.update(|settings| settings.post_update = false) {
+                    warn!("Failed to clear the post-update marker: {}", e);
+                }
+            }
+            let scratch =
+                scratch::Scratch::new(&cache_dir, settings_store.get().scratch_quota_bytes);
+            let health_retention = settings_store
+                .get()
+                .health_history_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60))
+                .unwrap_or(health::DEFAULT_RETENTION);
+            app.manage(settings_store);
+
+            // Crashes from earlier runs still count towards crash-loop detection and use up
+            // crash restarts
+            let health_log = health::HealthLog::load(health_path, health_retention);
+            let history = health_log.snapshot();
+            if history.crash_loop(health::now()) {
+                warn!(
+                    "Backend crashed {} times in the last {:?}, counting earlier runs",
+                    history.crashes_within(health::now(), health::CRASH_LOOP_WINDOW),
+                    health::CRASH_LOOP_WINDOW
+                );
+            }
+            *app.state::<AppState>().recovery.lock() =
+                recovery::CrashRecovery::from_history(&history, health::now());
+            app.manage(health_log);
+            app.manage(scratch);
+            app.manage(metrics::MetricsState::default());
+
+            // Trim scratch files left by earlier runs back under the quota
+            let scratch_handle = app.handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                scratch_handle.state::<scratch::Scratch>().reserve(0);
+            });
+
+            // Backend actions queued while the backend was down survive restarts
+            let pending_outbox = outbox::Outbox::load(outbox_path);
+            if !pending_outbox.pending().is_empty() {
+                info!(
+                    "{} queued backend action(s) waiting to be applied",
+                    pending_outbox.pending().len()
+                );
+            }
+            app.manage(pending_outbox);
 
-            // Check if --minimized flag is passed (autostart mode)
-            let start_minimized = std::env::args().any(|arg| arg == "--minimized");
-            if start_minimized {
+            // File watchers for "on change" plans, re-registered in a later startup stage
+            app.manage(watch::WatcherManager::load(watchers_path));
+
+            // OS shutdown and logoff don't close the window or ask to exit
+            if let Err(e) = session_end::install(app.handle()) {
+                warn!("Failed to watch for the session ending: {}", e);
+            }
+
+            if launch.minimized {
                 info!("Starting minimized (autostart mode)");
             }
+            if let Some(file) = &launch.file {
+                let cwd = std::env::current_dir().unwrap_or_default();
+                open_file(app.handle(), cwd.join(file));
+            }
 
-            // Open devtools in debug mode only
+            // Open devtools in debug mode only, and not at login, where they'd show up on
+            // their own
             #[cfg(debug_assertions)]
-            if let Some(window) = app.get_webview_window("main") {
-                window.open_devtools();
-            }
-
-            // Create system tray menu
-            let show = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
-            let separator1 = MenuItem::with_id(app, "sep1", "────────────", false, None::<&str>)?;
-            let volumes = MenuItem::with_id(app, "volumes", "Volumes", true, None::<&str>)?;
-            let repositories =
-                MenuItem::with_id(app, "repositories", "Repositories", true, None::<&str>)?;
-            let backups = MenuItem::with_id(app, "backups", "Backups", true, None::<&str>)?;
-            let notifications =
-                MenuItem::with_id(app, "notifications", "Notifications", true, None::<&str>)?;
-            let settings = MenuItem::with_id(app, "settings", "Settings", true, None::<&str>)?;
-            let separator2 = MenuItem::with_id(app, "sep2", "────────────", false, None::<&str>)?;
-            let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-
-            let menu = Menu::with_items(
-                app,
-                &[
-                    &show,
-                    &separator1,
-                    &volumes,
-                    &repositories,
-                    &backups,
-                    &notifications,
-                    &settings,
-                    &separator2,
-                    &quit,
-                ],
-            )?;
-
-            let _tray = TrayIconBuilder::new()
-                .icon(app.default_window_icon().unwrap().clone())
-                .menu(&menu)
-                .show_menu_on_left_click(false)
-                .tooltip("C3i Backup ONE")
-                .on_menu_event(|app, event| {
-                    let window = app.get_webview_window("main");
-                    match event.id.as_ref() {
-                        "show" => {
-                            if let Some(window) = window {
-                                let _ = window.show();
-                                let _ = window.set_focus();
-                            }
-                        }
-                        "volumes" | "repositories" | "backups" | "notifications" | "settings" => {
-                            if let Some(window) = window {
-                                let _ = window.show();
-                                let _ = window.set_focus();
-                                let state = app.state::<AppState>();
-                                let port = state.backend_port.load(Ordering::SeqCst);
-                                let url =
-                                    format!("http://localhost:{}/{}", port, event.id.as_ref());
-                                let _ = window.navigate(url.parse().unwrap());
-                            }
-                        }
-                        "quit" => {
-                            let state = app.state::<AppState>();
-                            tauri::async_runtime::block_on(async {
-                                if let Err(e) = stop_sidecar(&state).await {
-                                    error!("Failed to stop sidecar: {}", e);
-                                }
-                            });
-                            app.exit(0);
-                        }
-                        _ => {}
-                    }
-                })
-                .on_tray_icon_event(|tray, event| {
-                    if let TrayIconEvent::Click {
-                        button: MouseButton::Left,
-                        button_state: MouseButtonState::Up,
-                        ..
-                    } = event
-                    {
-                        let app = tray.app_handle();
-                        if let Some(window) = app.get_webview_window("main") {
-                            let _ = window.show();
-                            let _ = window.set_focus();
-                        }
-                    }
-                })
-                .build(app)?;
+            if !launch.minimized {
+                if let Some(window) = app.get_webview_window("main") {
+                    window.open_devtools();
+                }
+            }
 
-            // Start the sidecar and navigate to server
-            tauri::async_runtime::spawn(async move {
-                info!("Starting backend...");
-
-                let state = app_handle.state::<AppState>();
-                let port = match start_sidecar(&app_handle, &state).await {
-                    Ok(port) => port,
-                    Err(e) => {
-                        error!("Failed to start backend: {}", e);
-                        return;
-                    }
+            // Summon the quick actions palette from anywhere
+            #[cfg(desktop)]
+            {
+                use tauri_plugin_global_shortcut::{
+                    Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState,
                 };
 
-                info!("Backend ready on port {}, navigating to server...", port);
+                // The tray lists it as CmdOrCtrl+Shift+Space
+                #[cfg(target_os = "macos")]
+                let primary = Modifiers::SUPER;
+                #[cfg(not(target_os = "macos"))]
+                let primary = Modifiers::CONTROL;
+                let palette_shortcut = Shortcut::new(Some(primary | Modifiers::SHIFT), Code::Space);
+                app.handle().plugin(
+                    tauri_plugin_global_shortcut::Builder::new()
+                        .with_handler(move |app, shortcut, event| {
+                            if shortcut == &palette_shortcut
+                                && event.state() == ShortcutState::Pressed
+                            {
+                                if let Err(e) = palette::toggle(app) {
+                                    error!("Failed to toggle quick actions palette: {}", e);
+                                }
+                            }
+                        })
+                        .build(),
+                )?;
+                if let Err(e) = app.global_shortcut().register(palette_shortcut) {
+                    warn!("Failed to register quick actions shortcut: {}", e);
+                }
+            }
 
-                // Navigate to the SSR server instead of using static assets
-                if let Some(window) = app_handle.get_webview_window("main") {
-                    let url = format!("http://localhost:{}/", port);
-                    info!("Navigating to SSR server at {}", url);
-                    if let Err(e) = window.navigate(url.parse().unwrap()) {
-                        error!("Failed to navigate: {}", e);
-                    }
+            if !launch.no_tray {
+                create_tray(app)?;
+            }
 
-                    // Show window only if not in minimized/autostart mode
-                    if !start_minimized {
-                        let _ = window.show();
-                        let _ = window.set_focus();
+            // The window shell shows the bundled loading page until the backend is up
+            if !launch.minimized && !launch.headless {
+                show_main_window(app.handle());
+            }
+            // Without a window or tray, Ctrl+C in the terminal is how a headless run is quit
+            if launch.headless {
+                let handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        info!("Interrupted, quitting");
+                        quit_app(&handle);
                     }
-                } else {
-                    error!("Could not get main window");
-                }
-            });
+                });
+            }
+            app.state::<AppState>().startup.finish(startup::Stage::Shell);
+
+            // Everything heavier runs in stages once the tray is up
+            tauri::async_runtime::spawn(run_startup(app_handle));
 
             Ok(())
         })
-        .on_window_event(|window, event| {
-            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
-                // Minimize to tray instead of quitting
+        .on_window_event(|window, event| match event {
+            tauri::WindowEvent::CloseRequested { api, .. } if window.label() == "main" => {
                 api.prevent_close();
+                if window.state::<AppState>().config().close_to_tray {
+                    if args::current().no_tray {
+                        // Without a tray a hidden window couldn't be brought back
+                        let _ = window.minimize();
+                        info!("Window minimized");
+                    } else {
+                        // Minimize to tray instead of quitting
+                        let _ = window.hide();
+                        info!("Window minimized to tray");
+                    }
+                } else {
+                    quit_app(window.app_handle());
+                }
+            }
+            tauri::WindowEvent::Focused(false) if window.label() == palette::PALETTE_LABEL => {
+                // The palette is transient, dismiss it as soon as it loses focus
                 let _ = window.hide();
-                info!("Window minimized to tray");
             }
+            tauri::WindowEvent::ThemeChanged(_) => {
+                let app = window.app_handle().clone();
+                tauri::async_runtime::spawn_blocking(move || accessibility::refresh(&app));
+            }
+            tauri::WindowEvent::Focused(true) => {
+                if let Some(id) = restore::session_for_window(window.label()) {
+                    window.state::<AppState>().restore_sessions.touch(id);
+                }
+            }
+            tauri::WindowEvent::Destroyed => {
+                // Closing a restore window ends its session
+                if let Some(id) = restore::session_for_window(window.label()) {
+                    let app = window.app_handle().clone();
+                    tauri::async_runtime::spawn(async move {
+                        let _ = restore::end(&app, id, restore::EndReason::WindowClosed).await;
+                    });
+                }
+            }
+            _ => {}
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// The sidecar bookkeeping of the app, with exits delivered whenever the test says
+    #[derive(Default)]
+    struct Lifecycle {
+        generation: u64,
+        handle: Option<u64>,
+        live: BTreeSet<u64>,
+        /// Killed instances whose exit hasn't been seen yet
+        exiting: Vec<u64>,
+        recoveries: usize,
+    }
+
+    impl Lifecycle {
+        fn spawn(&mut self) {
+            self.generation += 1;
+            self.handle = Some(self.generation);
+            self.live.insert(self.generation);
+        }
+
+        /// A planned restart: take the handle, kill the instance, spawn the next one without
+        /// waiting for the old exit to be reported
+        fn restart(&mut self) {
+            if let Some(old) = self.handle.take() {
+                self.exiting.push(old);
+            }
+            self.spawn();
+        }
+
+        fn exit(&mut self, generation: u64) -> SidecarExit {
+            self.live.remove(&generation);
+            let exit = classify_exit(generation, self.generation, self.handle);
+            if exit == SidecarExit::Crashed {
+                self.handle = None;
+                self.recoveries += 1;
+                self.spawn();
+            }
+            exit
+        }
+
+        fn deliver_exits(&mut self) -> Vec<SidecarExit> {
+            let exiting = std::mem::take(&mut self.exiting);
+            exiting.into_iter().map(|g| self.exit(g)).collect()
+        }
+    }
+
+    #[test]
+    fn late_exits_after_rapid_restarts_are_ignored() {
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.spawn();
+        for _ in 0..50 {
+            lifecycle.restart();
+        }
+        // Every old instance reports its exit only after its replacement is up
+        let exits = lifecycle.deliver_exits();
+
+        assert!(exits.iter().all(|exit| *exit == SidecarExit::Superseded));
+        assert_eq!(lifecycle.live, BTreeSet::from([51]));
+        assert_eq!(lifecycle.recoveries, 0);
+    }
+
+    #[test]
+    fn exits_interleaved_with_restarts_are_ignored() {
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.spawn();
+        for round in 0..20 {
+            lifecycle.restart();
+            lifecycle.restart();
+            // Out of order: the newest replaced instance reports before the older one
+            lifecycle.exiting.reverse();
+            if round % 2 == 0 {
+                lifecycle.deliver_exits();
+            }
+        }
+        lifecycle.deliver_exits();
+
+        assert_eq!(lifecycle.live.len(), 1);
+        assert_eq!(lifecycle.live.first(), Some(&lifecycle.generation));
+        assert_eq!(lifecycle.recoveries, 0);
+    }
+
+    #[test]
+    fn current_instance_dying_is_a_crash() {
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.spawn();
+        lifecycle.restart();
+
+        assert_eq!(lifecycle.exit(2), SidecarExit::Crashed);
+        assert_eq!(lifecycle.recoveries, 1);
+        // The crashed instance's predecessor exiting afterwards changes nothing
+        assert_eq!(lifecycle.deliver_exits(), vec![SidecarExit::Superseded]);
+        assert_eq!(lifecycle.live, BTreeSet::from([3]));
+    }
+
+    #[test]
+    fn stopped_instance_exiting_is_not_a_crash() {
+        assert_eq!(classify_exit(4, 4, None), SidecarExit::Stopped);
+        assert_eq!(classify_exit(4, 4, Some(4)), SidecarExit::Crashed);
+        assert_eq!(classify_exit(3, 4, Some(4)), SidecarExit::Superseded);
+        assert_eq!(classify_exit(3, 4, None), SidecarExit::Superseded);
+    }
+
+    #[tokio::test]
+    async fn only_a_reported_exit_counts_as_graceful() {
+        let (tx, mut exited) = tokio::sync::oneshot::channel();
+        tx.send(Some(0)).unwrap();
+        assert_eq!(
+            wait_for_exit(&mut exited, Duration::from_secs(1)).await,
+            Some(Some(0))
+        );
+
+        let (_tx, mut exited) = tokio::sync::oneshot::channel::<Option<i32>>();
+        assert_eq!(
+            wait_for_exit(&mut exited, Duration::from_millis(10)).await,
+            None
+        );
+
+        let (tx, mut exited) = tokio::sync::oneshot::channel::<Option<i32>>();
+        drop(tx);
+        assert_eq!(
+            wait_for_exit(&mut exited, Duration::from_secs(1)).await,
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sidecar_is_killed_when_the_output_task_dies_first() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let reaper = std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+        let tree = proctree::ProcessTree::adopt(pid);
+
+        // The output task panics and drops its sender while the child keeps running
+        let (exit_tx, mut exited) = tokio::sync::oneshot::channel::<Option<i32>>();
+        tokio::spawn(async move {
+            let _exit_tx = exit_tx;
+            panic!("output task died");
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            wait_for_exit(&mut exited, Duration::from_secs(1)).await,
+            None
+        );
+        assert!(proctree::is_alive(pid));
+        let report = tree.kill();
+        assert!(report.survivors.is_empty(), "{:?}", report.survivors);
+        reaper.join().unwrap();
+        assert!(!proctree::is_alive(pid));
+    }
 }
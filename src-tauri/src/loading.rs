@@ -0,0 +1,126 @@
+//! What the loading screen shows while the backend comes up.
+//!
+//! `loading-status` carries a [`LoadingStatus`] so the UI can pick its own (localized) text
+//! instead of matching English sentences. While the `legacy_loading_status` setting is on it
+//! still carries the old English text instead, for frontends that match on it; the setting
+//! goes away in the next release.
+
+use crate::error::AppError;
+use crate::settings::SettingsStore;
+use serde::Serialize;
+use tauri::Manager;
+
+/// Payload of `loading-status`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LoadingStatus {
+    /// Looking for the Windows Service
+    CheckingService,
+    /// Stopping the backend before starting it again or switching away from it
+    StoppingBackend,
+    /// The configured sidecar port is taken by another program
+    PortTaken {
+        port: u16,
+    },
+    /// Spawning the sidecar; `attempt` counts from 1 up to `max`
+    StartingSidecar {
+        attempt: u32,
+        max: u32,
+    },
+    /// Waiting for the server to answer its healthcheck
+    WaitingForServer {
+        attempt: u32,
+    },
+    Ready {
+        port: u16,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl LoadingStatus {
+    pub fn error(error: &AppError) -> Self {
+        LoadingStatus::Error {
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LoadingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadingStatus::CheckingService => write!(f, "Checking the Windows Service"),
+            LoadingStatus::StoppingBackend => write!(f, "Stopping the backend"),
+            LoadingStatus::PortTaken { port } => write!(
+                f,
+                "Port {} is in use by another application; starting on another port",
+                port
+            ),
+            LoadingStatus::StartingSidecar { attempt, .. } if *attempt <= 1 => {
+                write!(f, "Starting the backend")
+            }
+            LoadingStatus::StartingSidecar { attempt, max } => {
+                write!(f, "Retrying backend start ({}/{})…", attempt - 1, max - 1)
+            }
+            LoadingStatus::WaitingForServer { attempt } => {
+                write!(f, "Waiting for the backend (attempt {})", attempt)
+            }
+            LoadingStatus::Ready { port } => write!(f, "Backend ready on port {}", port),
+            LoadingStatus::Error { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+/// What `loading-status` carries for `status`: the old text when `legacy` asks for it
+fn payload(status: LoadingStatus, legacy: bool) -> serde_json::Value {
+    if legacy {
+        serde_json::Value::String(status.to_string())
+    } else {
+        serde_json::to_value(status).unwrap_or(serde_json::Value::Null)
+    }
+}
+
+/// Emit `status` as `loading-status`, in the form the compatibility setting asks for
+pub fn emit(app: &tauri::AppHandle, status: LoadingStatus) {
+    let legacy = app.state::<SettingsStore>().get().legacy_loading_status;
+    crate::events::emit(app, "loading-status", payload(status, legacy));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn legacy_setting_sends_the_old_text_on_the_same_event() {
+        let status = LoadingStatus::StartingSidecar { attempt: 2, max: 4 };
+        assert_eq!(
+            payload(status.clone(), true),
+            json!("Retrying backend start (1/3)…")
+        );
+        assert_eq!(
+            payload(status, false),
+            json!({ "kind": "starting_sidecar", "attempt": 2, "max": 4 })
+        );
+    }
+
+    #[test]
+    fn errors_keep_their_code_in_the_structured_form() {
+        let status = LoadingStatus::error(&AppError::PortInUse(4096));
+        assert_eq!(
+            payload(status.clone(), false),
+            json!({
+                "kind": "error",
+                "code": "port_in_use",
+                "message": "Port 4096 is in use by another application",
+            })
+        );
+        assert_eq!(
+            payload(status, true),
+            json!("Port 4096 is in use by another application")
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Where the desktop's own log goes, and its filter, changeable while the app runs.
+//!
+//! The filter starts as `RUST_LOG` plus [`DEFAULT_DIRECTIVES`] and sits behind a reload
+//! layer, so `set_log_level` can swap it without a relaunch. The chosen directive is saved in
+//! the settings and applied again once they are loaded at the next start. A filter more
+//! verbose than info also makes the sidecar verbose through [`SIDECAR_LEVEL_ENV`], from the
+//! next time it starts.
+//!
+//! Besides the console, which nobody sees in a bundled app, lines go to a daily file in the
+//! app log directory once setup has resolved it with [`open_file`], before the backend is
+//! started. Only the newest [`KEEP_FILES`] files are kept.
+
+use parking_lot::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{self, Rotation};
+use tracing_subscriber::fmt::writer::{MakeWriter, OptionalWriter};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Directives the filter starts with, on top of `RUST_LOG`
+pub const DEFAULT_DIRECTIVES: &str = "zerobyte=info,tauri=info";
+
+/// Environment variable that sets the sidecar's log level
+pub const SIDECAR_LEVEL_ENV: &str = "ZEROBYTE_LOG";
+
+/// Start of the log files' names; a named profile adds its name. The date and `.log` follow.
+pub const FILE_PREFIX: &str = "desktop";
+
+/// Daily log files kept, the current one included
+pub const KEEP_FILES: usize = 14;
+
+/// Writer of the log file, and the guard that flushes it when the process exits
+static FILE: OnceLock<(NonBlocking, WorkerGuard, PathBuf)> = OnceLock::new();
+
+static HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// The directive in effect, and the most verbose level it lets through
+static CURRENT: Mutex<Option<(String, Option<LevelFilter>)>> = Mutex::new(None);
+
+/// Install the global subscriber: the reloadable filter, the console and file output and the
+/// error ring of the system report
+pub fn init() {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let mut filter = EnvFilter::from_default_env();
+    for directive in DEFAULT_DIRECTIVES.split(',') {
+        filter = filter.add_directive(directive.parse().unwrap());
+    }
+    *CURRENT.lock() = Some((filter.to_string(), filter.max_level_hint()));
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = HANDLE.set(handle);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(LogFile),
+        )
+        // Keeps the last error lines for the system report
+        .with(crate::report::RecentErrors)
+        .init();
+}
+
+/// Writes to the log file once it's open, and nowhere before
+struct LogFile;
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = OptionalWriter<NonBlocking>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        FILE.get().map(|(writer, _, _)| writer.clone()).into()
+    }
+}
+
+fn file_prefix() -> String {
+    if crate::profile::is_default() {
+        FILE_PREFIX.to_string()
+    } else {
+        format!("{}-{}", FILE_PREFIX, crate::profile::current())
+    }
+}
+
+/// Start writing the log to daily files in `dir`. Lines logged before this only reach the
+/// console.
+pub fn open_file(dir: &Path) -> Result<(), String> {
+    let appender = rolling::Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(file_prefix())
+        .filename_suffix("log")
+        .max_log_files(KEEP_FILES)
+        .build(dir)
+        .map_err(|e| format!("Failed to open the log in {}: {}", dir.display(), e))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let _ = FILE.set((writer, guard, dir.to_path_buf()));
+    Ok(())
+}
+
+/// The log file written to today, or the newest one; None before [`open_file`]
+pub fn file_path() -> Option<PathBuf> {
+    let (_, _, dir) = FILE.get()?;
+    let prefix = format!("{}.", file_prefix());
+    let newest = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(".log")
+        })
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())?;
+    Some(newest.path())
+}
+
+/// Check a filter directive such as `debug` or `zerobyte=debug,tauri=info`
+pub fn parse(directive: &str) -> Result<EnvFilter, String> {
+    let directive = directive.trim();
+    if directive.is_empty() {
+        return Err("The log level can't be empty".to_string());
+    }
+    EnvFilter::try_new(directive).map_err(|e| format!("Invalid log level {:?}: {}", directive, e))
+}
+
+/// Swap the filter for `directive`
+pub fn set(directive: &str) -> Result<(), String> {
+    let filter = parse(directive)?;
+    let hint = filter.max_level_hint();
+    let handle = HANDLE.get().ok_or("Logging isn't initialized")?;
+    handle
+        .reload(filter)
+        .map_err(|e| format!("Failed to change the log level: {}", e))?;
+    *CURRENT.lock() = Some((directive.trim().to_string(), hint));
+    Ok(())
+}
+
+/// The directive in effect
+pub fn current() -> String {
+    CURRENT
+        .lock()
+        .as_ref()
+        .map(|(directive, _)| directive.clone())
+        .unwrap_or_else(|| DEFAULT_DIRECTIVES.to_string())
+}
+
+/// Level to start the sidecar with: `debug` or `trace` when the filter lets those through,
+/// None to leave the sidecar at its default
+pub fn sidecar_level() -> Option<&'static str> {
+    let hint = CURRENT.lock().as_ref()?.1?;
+    if hint >= LevelFilter::TRACE {
+        Some("trace")
+    } else if hint > LevelFilter::INFO {
+        Some("debug")
+    } else {
+        None
+    }
+}
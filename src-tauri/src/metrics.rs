@@ -0,0 +1,523 @@
+//! Prometheus metrics for people who already run a monitoring stack.
+//!
+//! Off by default. When `metrics_enabled` is set, a plain HTTP listener on 127.0.0.1 (never
+//! any other interface) answers `GET /metrics` in the Prometheus text format; the same values
+//! are available to the UI as JSON through `get_metrics_snapshot`. The encoder is a few
+//! functions over [`Metric`] rather than a client library, and doesn't touch Tauri, so the
+//! service binary can serve the same format.
+//!
+//! Values are collected when scraped. Backend latency is measured then too: each scrape
+//! times one healthcheck and adds it to a histogram kept for the life of the app.
+
+use crate::health::{CrashKind, HealthEvent, HealthLog};
+use crate::http::HttpPolicy;
+use crate::AppState;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+/// Port used when settings don't set one
+pub const DEFAULT_PORT: u16 = 9464;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the healthcheck latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 8] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+    Histogram,
+}
+
+impl MetricKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricKind::Gauge => "gauge",
+            MetricKind::Counter => "counter",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// One line of a metric: full sample name, labels and value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+/// A metric family with its help text and samples
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub samples: Vec<Sample>,
+}
+
+impl Metric {
+    pub fn new(name: &'static str, help: &'static str, kind: MetricKind) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add a sample named after the metric itself
+    pub fn sample(mut self, labels: &[(&str, &str)], value: f64) -> Self {
+        self.samples.push(Sample {
+            name: self.name.to_string(),
+            labels: labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            value,
+        });
+        self
+    }
+
+    /// A single unlabelled value
+    pub fn single(name: &'static str, help: &'static str, kind: MetricKind, value: f64) -> Self {
+        Self::new(name, help, kind).sample(&[], value)
+    }
+
+    /// A histogram metric from `histogram`'s buckets, sum and count
+    pub fn histogram(name: &'static str, help: &'static str, histogram: &Histogram) -> Self {
+        let mut metric = Self::new(name, help, MetricKind::Histogram);
+        let bucket = format!("{}_bucket", name);
+        let bounds = histogram.bounds.iter().map(|bound| format_value(*bound));
+        let bounds = bounds.chain(std::iter::once("+Inf".to_string()));
+        for (bound, count) in bounds.zip(histogram.cumulative()) {
+            metric.samples.push(Sample {
+                name: bucket.clone(),
+                labels: vec![("le".to_string(), bound)],
+                value: count as f64,
+            });
+        }
+        for (suffix, value) in [("_sum", histogram.sum), ("_count", histogram.count as f64)] {
+            metric.samples.push(Sample {
+                name: format!("{}{}", name, suffix),
+                labels: Vec::new(),
+                value,
+            });
+        }
+        metric
+    }
+}
+
+/// Observations counted into fixed buckets
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// Per bucket (not cumulative), with one extra for values above every bound
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// A histogram with the given upper bounds, which must be in increasing order
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Counts of observations at or below each bound, ending with the total
+    fn cumulative(&self) -> Vec<u64> {
+        self.counts
+            .iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect()
+    }
+}
+
+/// Format a value the way Prometheus parses it
+pub fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Encode `metrics` in the Prometheus text exposition format
+pub fn encode(metrics: &[Metric]) -> String {
+    let mut out = String::new();
+    for metric in metrics {
+        let _ = writeln!(out, "# HELP {} {}", metric.name, escape_help(metric.help));
+        let _ = writeln!(out, "# TYPE {} {}", metric.name, metric.kind.as_str());
+        for sample in &metric.samples {
+            out.push_str(&sample.name);
+            if !sample.labels.is_empty() {
+                let labels: Vec<String> = sample
+                    .labels
+                    .iter()
+                    .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                    .collect();
+                let _ = write!(out, "{{{}}}", labels.join(","));
+            }
+            let _ = writeln!(out, " {}", format_value(sample.value));
+        }
+    }
+    out
+}
+
+/// Whether an HTTP request line asks for the metrics
+pub fn wants_metrics(request_line: &str) -> bool {
+    let mut parts = request_line.split_whitespace();
+    parts.next() == Some("GET") && parts.next() == Some("/metrics")
+}
+
+/// A complete HTTP response to `request_line`, with `metrics` as the body when they were
+/// asked for. Shared by every metrics listener.
+pub fn respond(request_line: &str, metrics: &str) -> String {
+    let (status, content_type, body) = if wants_metrics(request_line) {
+        ("200 OK", CONTENT_TYPE, metrics)
+    } else if request_line.starts_with("GET ") {
+        ("404 Not Found", "text/plain", "Not found\n")
+    } else {
+        ("405 Method Not Allowed", "text/plain", "Method not allowed\n")
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Resident memory of this process in bytes, where the platform makes it easy to get
+pub fn resident_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::ProcessStatus::{
+            K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+        };
+        use windows::Win32::System::Threading::GetCurrentProcess;
+
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        // SAFETY: the pseudo handle is always valid and `counters` is sized as declared
+        let ok = unsafe { K32GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) };
+        ok.as_bool().then_some(counters.WorkingSetSize as u64)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        let output = std::process::Command::new("ps")
+            .args(["-o", "rss=", "-p", &std::process::id().to_string()])
+            .output()
+            .ok()?;
+        let kib: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+        Some(kib * 1024)
+    }
+}
+
+/// Values kept between scrapes
+pub struct MetricsState {
+    started: Instant,
+    healthcheck_latency: Mutex<Histogram>,
+}
+
+impl Default for MetricsState {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            healthcheck_latency: Mutex::new(Histogram::new(&LATENCY_BUCKETS)),
+        }
+    }
+}
+
+/// Time one healthcheck; None if the backend didn't answer
+async fn probe_latency(state: &AppState) -> Option<Duration> {
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let started = Instant::now();
+    let response = state
+        .http
+        .healthcheck(port, HttpPolicy::WATCHDOG)
+        .await
+        .ok()?;
+    response.status().is_success().then(|| started.elapsed())
+}
+
+/// Gather the desktop's metrics
+pub async fn collect(app: &tauri::AppHandle) -> Vec<Metric> {
+    let state = app.state::<AppState>();
+    let metrics = app.state::<MetricsState>();
+
+    let latency = probe_latency(&state).await;
+    if let Some(latency) = latency {
+        metrics
+            .healthcheck_latency
+            .lock()
+            .observe(latency.as_secs_f64());
+    }
+
+    let history = app.state::<HealthLog>().snapshot();
+    let mut crashes = Metric::new(
+        "zerobyte_backend_crashes",
+        "Backend crashes within the kept health history, by kind",
+        MetricKind::Gauge,
+    );
+    for kind in CrashKind::ALL {
+        let count = history
+            .entries
+            .iter()
+            .filter(|entry| {
+                matches!(&entry.event, HealthEvent::Crash { kind: crashed, .. } if *crashed == kind)
+            })
+            .count();
+        crashes = crashes.sample(&[("kind", kind.as_str())], count as f64);
+    }
+
+    let mode = if state.using_service.load(Ordering::SeqCst) {
+        "service"
+    } else {
+        "sidecar"
+    };
+    let mut out = vec![
+        Metric::new(
+            "zerobyte_desktop_info",
+            "Desktop version and how it reaches the backend",
+            MetricKind::Gauge,
+        )
+        .sample(
+            &[("version", env!("CARGO_PKG_VERSION")), ("mode", mode)],
+            1.0,
+        ),
+        Metric::single(
+            "zerobyte_backend_up",
+            "Whether the backend answered a healthcheck during this scrape",
+            MetricKind::Gauge,
+            if latency.is_some() { 1.0 } else { 0.0 },
+        ),
+        Metric::single(
+            "zerobyte_sidecar_starts_total",
+            "Sidecar processes started since the app launched",
+            MetricKind::Counter,
+            state.sidecar_generation.load(Ordering::SeqCst) as f64,
+        ),
+        crashes,
+        Metric::histogram(
+            "zerobyte_backend_healthcheck_seconds",
+            "Healthcheck latency measured at each scrape",
+            &metrics.healthcheck_latency.lock(),
+        ),
+        Metric::single(
+            "zerobyte_desktop_uptime_seconds",
+            "Seconds since the desktop app started",
+            MetricKind::Gauge,
+            metrics.started.elapsed().as_secs_f64(),
+        ),
+    ];
+    if let Some(skew_ms) = state.clock_skew.lock().skew_ms() {
+        out.push(Metric::single(
+            "zerobyte_backend_clock_skew_seconds",
+            "Estimated offset of the backend's clock from this machine's",
+            MetricKind::Gauge,
+            skew_ms as f64 / 1000.0,
+        ));
+    }
+    if let Some(bytes) = resident_bytes() {
+        out.push(Metric::single(
+            "zerobyte_desktop_resident_memory_bytes",
+            "Resident memory of the desktop process",
+            MetricKind::Gauge,
+            bytes as f64,
+        ));
+    }
+    out
+}
+
+/// Serve `/metrics` on 127.0.0.1:`port` until the app quits
+pub async fn serve(app: tauri::AppHandle, port: u16) {
+    let listener = match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to start the metrics endpoint on port {}: {}", port, e);
+            return;
+        }
+    };
+    info!("Serving metrics on http://127.0.0.1:{}/metrics", port);
+    let cancel = app.state::<AppState>().http.child_token();
+    loop {
+        let (mut stream, _) = tokio::select! {
+            _ = cancel.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Metrics endpoint failed to accept a connection: {}", e);
+                    continue;
+                }
+            },
+        };
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut buffer = [0u8; 1024];
+            let Ok(read) = stream.read(&mut buffer).await else {
+                return;
+            };
+            let request = String::from_utf8_lossy(&buffer[..read]);
+            let request_line = request.lines().next().unwrap_or_default().to_string();
+            let metrics = if wants_metrics(&request_line) {
+                encode(&collect(&app).await)
+            } else {
+                String::new()
+            };
+            let response = respond(&request_line, &metrics);
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The metrics `collect` produces, with fixed values
+    fn sample_metrics() -> Vec<Metric> {
+        let mut latency = Histogram::new(&LATENCY_BUCKETS);
+        // Exactly representable, so the sum is exact too
+        for value in [0.00390625, 0.015625, 0.015625, 0.25, 2.0] {
+            latency.observe(value);
+        }
+        let mut crashes = Metric::new(
+            "zerobyte_backend_crashes",
+            "Backend crashes within the kept health history, by kind",
+            MetricKind::Gauge,
+        );
+        for (kind, count) in CrashKind::ALL.iter().zip([0.0, 2.0, 1.0, 0.0]) {
+            crashes = crashes.sample(&[("kind", kind.as_str())], count);
+        }
+
+        vec![
+            Metric::new(
+                "zerobyte_desktop_info",
+                "Desktop version and how it reaches the backend",
+                MetricKind::Gauge,
+            )
+            .sample(&[("version", "0.1.0"), ("mode", "sidecar")], 1.0),
+            Metric::single(
+                "zerobyte_backend_up",
+                "Whether the backend answered a healthcheck during this scrape",
+                MetricKind::Gauge,
+                1.0,
+            ),
+            Metric::single(
+                "zerobyte_sidecar_starts_total",
+                "Sidecar processes started since the app launched",
+                MetricKind::Counter,
+                3.0,
+            ),
+            crashes,
+            Metric::histogram(
+                "zerobyte_backend_healthcheck_seconds",
+                "Healthcheck latency measured at each scrape",
+                &latency,
+            ),
+            Metric::single(
+                "zerobyte_backend_clock_skew_seconds",
+                "Estimated offset of the backend's clock from this machine's",
+                MetricKind::Gauge,
+                -1.5,
+            ),
+            Metric::new(
+                "zerobyte_test_escaping",
+                "Help with a backslash \\ and a\nline break",
+                MetricKind::Gauge,
+            )
+            .sample(&[("path", "C:\\Backups\\\"NAS\"\n")], f64::NAN)
+            .sample(&[("path", "inf")], f64::INFINITY)
+            .sample(&[("path", "-inf")], f64::NEG_INFINITY),
+        ]
+    }
+
+    #[test]
+    fn encoding_matches_the_golden_file() {
+        let golden = include_str!("../testdata/metrics.prom");
+        assert_eq!(encode(&sample_metrics()), golden.replace("\r\n", "\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::new(&[1.0, 2.0]);
+        for value in [0.5, 1.0, 1.5, 3.0, 4.0] {
+            histogram.observe(value);
+        }
+        assert_eq!(histogram.cumulative(), vec![2, 3, 5]);
+        assert_eq!(histogram.count, 5);
+    }
+
+    #[test]
+    fn empty_histogram_still_lists_every_bucket() {
+        let metric = Metric::histogram("h", "help", &Histogram::new(&LATENCY_BUCKETS));
+        // One per bound, +Inf, _sum and _count
+        assert_eq!(metric.samples.len(), LATENCY_BUCKETS.len() + 3);
+        assert!(metric.samples.iter().all(|sample| sample.value == 0.0));
+    }
+
+    #[test]
+    fn only_get_metrics_is_served() {
+        let ok = respond("GET /metrics HTTP/1.1", "up 1\n");
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(ok.contains(&format!("Content-Type: {}\r\n", CONTENT_TYPE)));
+        assert!(ok.ends_with("Content-Length: 5\r\nConnection: close\r\n\r\nup 1\n"));
+
+        assert!(respond("GET / HTTP/1.1", "").starts_with("HTTP/1.1 404"));
+        assert!(respond("POST /metrics HTTP/1.1", "").starts_with("HTTP/1.1 405"));
+        assert!(!wants_metrics("GET /metrics/extra HTTP/1.1"));
+    }
+}
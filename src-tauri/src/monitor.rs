@@ -0,0 +1,191 @@
+//! Checking on the backend after it came up.
+//!
+//! Startup waits for the healthcheck once; after that a hung backend looks exactly like a
+//! healthy one. [`watch`] asks `/healthcheck` every 15 seconds, on the sidecar or the service
+//! alike, and reports each answer as `backend-health`. After `unresponsive_after` misses in a
+//! row (from `zerobyte.toml`) while the process is still there, it reports
+//! `backend-unresponsive` once and records a watchdog incident. Unless
+//! `restart_when_unresponsive` is turned off, a hung sidecar is then killed and started again:
+//! its last output goes into a crash report first, and `backend-watchdog-restart` carries the
+//! report's id. The service has a watchdog of its own and is never restarted from here.
+//! Nothing is checked while the backend isn't ready, a restart is under way or the app quits.
+
+use crate::crashes::{self, CrashCause};
+use crate::http::HttpPolicy;
+use crate::AppState;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// How often the backend is checked
+pub const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Payload of `backend-health`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BackendHealth {
+    pub healthy: bool,
+    /// None when the backend didn't answer
+    pub latency_ms: Option<u64>,
+    pub consecutive_failures: u32,
+}
+
+/// Payload of `backend-unresponsive`
+#[derive(Debug, Clone, Serialize)]
+pub struct Unresponsive {
+    pub port: u16,
+    pub consecutive_failures: u32,
+    pub using_service: bool,
+    /// Whether the sidecar is being restarted because of it
+    pub restarting: bool,
+}
+
+/// Payload of `backend-watchdog-restart`
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchdogRestart {
+    pub port: u16,
+    pub consecutive_failures: u32,
+    /// Crash report with the hung sidecar's last output; None if it couldn't be written
+    pub report_id: Option<String>,
+}
+
+/// Consecutive misses
+#[derive(Debug, Default)]
+pub struct HealthTracker {
+    failures: u32,
+}
+
+impl HealthTracker {
+    /// Account for one healthcheck; the bool is true on the `threshold`th miss in a row, which
+    /// makes the backend count as unresponsive, so each incident is reported once
+    pub fn observe(&mut self, latency: Option<Duration>, threshold: u32) -> (BackendHealth, bool) {
+        match latency {
+            Some(_) => self.failures = 0,
+            None => self.failures += 1,
+        }
+        let health = BackendHealth {
+            healthy: latency.is_some(),
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+            consecutive_failures: self.failures,
+        };
+        (health, self.failures == threshold)
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// Time one healthcheck; None if the backend didn't answer. A service waiting to be paired
+/// answers 401/403, which still shows it's responsive.
+pub async fn probe(state: &AppState, port: u16) -> Option<Duration> {
+    let started = Instant::now();
+    let response = state
+        .http
+        .healthcheck(port, HttpPolicy::WATCHDOG)
+        .await
+        .ok()?;
+    let status = response.status();
+    let answered = status.is_success()
+        || status == reqwest::StatusCode::UNAUTHORIZED
+        || status == reqwest::StatusCode::FORBIDDEN;
+    answered.then(|| started.elapsed())
+}
+
+/// Whether the backend process is still there: the sidecar we hold, or the service, whose
+/// process the desktop can't see
+async fn process_alive(state: &AppState) -> bool {
+    if state.using_service.load(Ordering::SeqCst) {
+        return true;
+    }
+    match state.sidecar_handle.lock().await.as_ref() {
+        Some(process) => crate::proctree::is_alive(process.child.pid()),
+        None => false,
+    }
+}
+
+/// Check the backend for as long as the app runs
+pub async fn watch(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "backend-health",
+        crate::activity::TaskClass::Critical,
+        POLL_INTERVAL,
+    );
+    let mut tracker = HealthTracker::default();
+    loop {
+        ticker.tick().await;
+
+        let state = app.state::<AppState>();
+        let restarting = state.lifecycle.try_lock().is_err();
+        if restarting
+            || !state.backend_ready.load(Ordering::SeqCst)
+            || state.shutdown_requested.load(Ordering::SeqCst)
+        {
+            tracker.reset();
+            continue;
+        }
+
+        let port = state.backend_port.load(Ordering::SeqCst);
+        let latency = probe(&state, port).await;
+        let threshold = state.config().unresponsive_after;
+        let (health, unresponsive) = tracker.observe(latency, threshold);
+        crate::events::emit(&app, "backend-health", health);
+        if !unresponsive || !process_alive(&state).await {
+            // A process that's gone is handled as a crash by the output task
+            continue;
+        }
+
+        let using_service = state.using_service.load(Ordering::SeqCst);
+        let restart = !using_service && state.config().restart_when_unresponsive;
+        warn!(
+            "Backend on port {} missed {} healthchecks in a row{}",
+            port,
+            health.consecutive_failures,
+            if restart { ", restarting it" } else { "" }
+        );
+        app.state::<crate::health::HealthLog>()
+            .record(crate::health::HealthEvent::WatchdogIncident {
+                detail: format!(
+                    "no healthcheck answer on port {} {} times in a row",
+                    port, health.consecutive_failures
+                ),
+            });
+        crate::events::emit(
+            &app,
+            "backend-unresponsive",
+            Unresponsive {
+                port,
+                consecutive_failures: health.consecutive_failures,
+                using_service,
+                restarting: restart,
+            },
+        );
+        if restart {
+            // A hung sidecar would only let a graceful shutdown time out, so it's killed
+            let running = state
+                .sidecar_handle
+                .lock()
+                .await
+                .as_ref()
+                .map(|process| (process.generation, process.started.elapsed()));
+            let report_id = running.and_then(|(generation, uptime)| {
+                crashes::save(&app, generation, uptime, None, CrashCause::Unresponsive)
+            });
+            crate::events::emit(
+                &app,
+                "backend-watchdog-restart",
+                WatchdogRestart {
+                    port,
+                    consecutive_failures: health.consecutive_failures,
+                    report_id,
+                },
+            );
+            match crate::force_restart_sidecar(&app).await {
+                Ok(port) => info!("Backend restarted on port {} after it hung", port),
+                Err(e) => warn!("Failed to restart the unresponsive backend: {}", e),
+            }
+            tracker.reset();
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! Desktop notifications, grouped so repeated events collapse into one.
+//!
+//! Each notification belongs to a [`Category`]. Categories that tend to repeat (backup
+//! failures overnight) collapse: while a category's window is open, a new event updates the
+//! existing toast with a running count ("3 backups failed") instead of adding another one.
+//!
+//! On Windows toasts are sent through WinRT with a stable group and tag per category, so the
+//! update replaces the toast in Action Center, where it stays until its category's expiry.
+//! Clicking one opens the notifications page. Elsewhere, or with `persist_toasts` turned off,
+//! every event is a separate notification through the notification plugin, as before.
+//!
+//! Plans can be muted one at a time (a backup to a USB drive that's usually unplugged fails
+//! every day). [`notify_plan`] drops events for a muted plan, and a single-plan failure toast
+//! offers to mute its plan for a week. Mutes live in settings with their end time and are
+//! checked when an event arrives, so they run out on time across restarts; expired entries are
+//! dropped whenever the list is next changed or read.
+
+use crate::settings::SettingsStore;
+use crate::text::{self, Ellipsis, Surface};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// How many item names a collapsed toast lists
+const LISTED_ITEMS: usize = 3;
+
+/// How long the toast's mute action silences a plan
+pub const TOAST_MUTE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Toast activation arguments asking to mute the plan that follows
+const MUTE_ARGUMENT: &str = "mute-plan:";
+
+/// Kinds of notification, each with its own grouping and expiry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    BackupFailed,
+    RepositoryUnreachable,
+    BackupCompleted,
+    General,
+}
+
+impl Category {
+    /// Toast group, stable across runs
+    pub fn group(self) -> &'static str {
+        match self {
+            Category::BackupFailed => "backup-failed",
+            Category::RepositoryUnreachable => "repository-unreachable",
+            Category::BackupCompleted => "backup-completed",
+            Category::General => "general",
+        }
+    }
+
+    /// How long events keep collapsing into the same toast, or None if they never do
+    pub fn collapse_window(self) -> Option<Duration> {
+        match self {
+            Category::BackupFailed | Category::RepositoryUnreachable => {
+                Some(Duration::from_secs(12 * 60 * 60))
+            }
+            Category::BackupCompleted => Some(Duration::from_secs(60 * 60)),
+            Category::General => None,
+        }
+    }
+
+    /// How long the toast stays in Action Center
+    pub fn expiry(self) -> Duration {
+        match self {
+            Category::BackupFailed | Category::RepositoryUnreachable => {
+                Duration::from_secs(3 * 24 * 60 * 60)
+            }
+            Category::BackupCompleted => Duration::from_secs(12 * 60 * 60),
+            Category::General => Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Title of a toast standing for `count` events
+    fn summary_title(self, count: usize) -> String {
+        match self {
+            Category::BackupFailed => format!("{} backups failed", count),
+            Category::RepositoryUnreachable => format!("{} repositories unreachable", count),
+            Category::BackupCompleted => format!("{} backups completed", count),
+            Category::General => format!("{} notifications", count),
+        }
+    }
+}
+
+/// Whether an event shows a new toast or updates the one already shown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToastDecision {
+    New { tag: String },
+    Update { tag: String, count: usize },
+}
+
+#[derive(Debug)]
+struct OpenGroup {
+    tag: String,
+    opened: Instant,
+    count: usize,
+    /// Most recent item names, newest last
+    recent: Vec<String>,
+}
+
+/// Open collapsible toasts per category
+#[derive(Debug, Default)]
+pub struct ToastLedger {
+    groups: Mutex<HashMap<Category, OpenGroup>>,
+    next_tag: Mutex<u64>,
+}
+
+impl ToastLedger {
+    fn new_tag(&self, category: Category) -> String {
+        let mut next = self.next_tag.lock();
+        *next += 1;
+        format!("{}-{}", category.group(), next)
+    }
+
+    /// Decide how to show an event about `item` at `now`
+    pub fn decide(&self, category: Category, item: &str, now: Instant) -> ToastDecision {
+        let Some(window) = category.collapse_window() else {
+            return ToastDecision::New {
+                tag: self.new_tag(category),
+            };
+        };
+
+        let mut groups = self.groups.lock();
+        match groups.get_mut(&category) {
+            Some(group) if now.duration_since(group.opened) < window => {
+                group.count += 1;
+                group.recent.push(item.to_string());
+                if group.recent.len() > LISTED_ITEMS {
+                    group.recent.remove(0);
+                }
+                ToastDecision::Update {
+                    tag: group.tag.clone(),
+                    count: group.count,
+                }
+            }
+            _ => {
+                let tag = self.new_tag(category);
+                groups.insert(
+                    category,
+                    OpenGroup {
+                        tag: tag.clone(),
+                        opened: now,
+                        count: 1,
+                        recent: vec![item.to_string()],
+                    },
+                );
+                ToastDecision::New { tag }
+            }
+        }
+    }
+
+    /// Item names to list in a collapsed toast, newest first
+    pub fn recent(&self, category: Category) -> Vec<String> {
+        self.groups
+            .lock()
+            .get(&category)
+            .map(|group| group.recent.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget a category's open toast, e.g. once the user has looked at it
+    pub fn dismiss(&self, category: Category) {
+        self.groups.lock().remove(&category);
+    }
+}
+
+/// Title and body for an event given the decision made for it
+pub fn compose(
+    ledger: &ToastLedger,
+    category: Category,
+    decision: &ToastDecision,
+    title: &str,
+    body: &str,
+) -> (String, String) {
+    match decision {
+        ToastDecision::New { .. } => (title.to_string(), body.to_string()),
+        ToastDecision::Update { count, .. } => {
+            let mut names = ledger.recent(category).join(", ");
+            if *count > LISTED_ITEMS {
+                names.push_str(", …");
+            }
+            (category.summary_title(*count), names)
+        }
+    }
+}
+
+/// A plan whose notifications are silenced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanMute {
+    pub plan_id: String,
+    /// Seconds since the Unix epoch, or None until unmuted
+    pub until: Option<u64>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether `plan_id` is muted at `now` (seconds since the Unix epoch)
+pub fn is_muted(mutes: &HashMap<String, Option<u64>>, plan_id: &str, now: u64) -> bool {
+    match mutes.get(plan_id) {
+        Some(Some(until)) => *until > now,
+        Some(None) => true,
+        None => false,
+    }
+}
+
+/// Drop mutes that ended before `now`
+pub fn prune_mutes(mutes: &mut HashMap<String, Option<u64>>, now: u64) {
+    mutes.retain(|_, until| !matches!(until, Some(until) if *until <= now));
+}
+
+/// Mute `plan_id` for `duration`, or until unmuted if None. Returns when the mute ends.
+pub fn mute_plan(
+    settings: &SettingsStore,
+    plan_id: &str,
+    duration: Option<Duration>,
+) -> Result<Option<u64>, String> {
+    let now = now_secs();
+    let until = duration.map(|duration| now + duration.as_secs());
+    settings.update(|settings| {
+        prune_mutes(&mut settings.muted_plans, now);
+        settings.muted_plans.insert(plan_id.to_string(), until);
+    })?;
+    info!("Muted notifications for plan {} until {:?}", plan_id, until);
+    Ok(until)
+}
+
+/// Lift the mute on `plan_id`; returns whether it was muted
+pub fn unmute_plan(settings: &SettingsStore, plan_id: &str) -> Result<bool, String> {
+    let mut removed = false;
+    settings.update(|settings| {
+        prune_mutes(&mut settings.muted_plans, now_secs());
+        removed = settings.muted_plans.remove(plan_id).is_some();
+    })?;
+    Ok(removed)
+}
+
+/// Current mutes, soonest to end first
+pub fn list_mutes(settings: &SettingsStore) -> Result<Vec<PlanMute>, String> {
+    let now = now_secs();
+    let mut mutes = settings.get().muted_plans;
+    if mutes.values().any(|until| until.is_some_and(|until| until <= now)) {
+        mutes = settings
+            .update(|settings| prune_mutes(&mut settings.muted_plans, now))?
+            .muted_plans;
+    }
+    let mut mutes: Vec<PlanMute> = mutes
+        .into_iter()
+        .map(|(plan_id, until)| PlanMute { plan_id, until })
+        .collect();
+    mutes.sort_by_key(|mute| (mute.until.is_none(), mute.until, mute.plan_id.clone()));
+    Ok(mutes)
+}
+
+/// Notify about `item` (a plan or repository name) in `category`
+pub fn notify(app: &tauri::AppHandle, category: Category, item: &str, title: &str, body: &str) {
+    deliver(app, category, item, None, title, body);
+}
+
+/// Notify about the plan `plan_id` (shown as `plan_name`), unless it's muted
+pub fn notify_plan(
+    app: &tauri::AppHandle,
+    category: Category,
+    plan_id: &str,
+    plan_name: &str,
+    title: &str,
+    body: &str,
+) {
+    use tauri::Manager;
+
+    let mutes = app.state::<SettingsStore>().get().muted_plans;
+    if is_muted(&mutes, plan_id, now_secs()) {
+        debug!("Plan {} is muted, not notifying: {}", plan_id, title);
+        return;
+    }
+    let mute_action = (category == Category::BackupFailed).then_some(plan_id);
+    deliver(app, category, plan_name, mute_action, title, body);
+}
+
+fn deliver(
+    app: &tauri::AppHandle,
+    category: Category,
+    item: &str,
+    mute_action: Option<&str>,
+    title: &str,
+    body: &str,
+) {
+    use tauri::Manager;
+
+    let persist = app.state::<SettingsStore>().get().persist_toasts;
+    if cfg!(target_os = "windows") && persist {
+        let state = app.state::<crate::AppState>();
+        let ledger = &state.toasts;
+        let decision = ledger.decide(category, item, Instant::now());
+        let (title, body) = compose(ledger, category, &decision, title, body);
+        // A collapsed toast stands for several plans, so it can't offer to mute one
+        let (tag, mute_action) = match &decision {
+            ToastDecision::New { tag } => (tag.clone(), mute_action),
+            ToastDecision::Update { tag, .. } => (tag.clone(), None),
+        };
+        match show_toast(app, category, &tag, &title, &body, mute_action) {
+            Ok(()) => return,
+            Err(e) => warn!("Failed to show toast, falling back to a plain notification: {}", e),
+        }
+    }
+
+    show_plain(app, title, body);
+}
+
+/// Handle a click on a toast or one of its buttons
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn on_activated(app: &tauri::AppHandle, category: Category, arguments: &str) {
+    use tauri::Manager;
+
+    if let Some(plan_id) = arguments.strip_prefix(MUTE_ARGUMENT) {
+        match mute_plan(&app.state::<SettingsStore>(), plan_id, Some(TOAST_MUTE)) {
+            Ok(_) => crate::events::emit(app, "notification-mutes-changed", plan_id),
+            Err(e) => warn!("Failed to mute plan {} from a toast: {}", plan_id, e),
+        }
+        return;
+    }
+    app.state::<crate::AppState>().toasts.dismiss(category);
+    crate::navigate_main_window(app, "notifications");
+}
+
+fn show_plain(app: &tauri::AppHandle, title: &str, body: &str) {
+    use tauri_plugin_notification::NotificationExt;
+
+    let title = text::fit(app, Surface::NotificationTitle, title, Ellipsis::Middle);
+    let body = text::fit(app, Surface::NotificationBody, body, Ellipsis::End);
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        warn!("Failed to show notification: {}", e);
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Toast XML for a title and body, with a button muting `mute_plan` if given. `long` keeps
+/// the toast on screen for the long duration instead of the default few seconds.
+pub fn toast_xml(title: &str, body: &str, mute_plan: Option<&str>, long: bool) -> String {
+    let actions = mute_plan
+        .map(|plan_id| {
+            format!(
+                concat!(
+                    r#"<actions><action content="Mute this plan for a week" "#,
+                    r#"arguments="{}{}" activationType="foreground"/></actions>"#,
+                ),
+                MUTE_ARGUMENT,
+                escape_xml(plan_id)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        concat!(
+            r#"<toast launch="notifications"{}><visual><binding template="ToastGeneric">"#,
+            "<text>{}</text><text>{}</text>",
+            "</binding></visual>{}</toast>",
+        ),
+        if long { r#" duration="long""# } else { "" },
+        escape_xml(title),
+        escape_xml(body),
+        actions
+    )
+}
+
+/// Show (or replace, for the same group and tag) a toast in Action Center
+#[cfg(target_os = "windows")]
+fn show_toast(
+    app: &tauri::AppHandle,
+    category: Category,
+    tag: &str,
+    title: &str,
+    body: &str,
+    mute_plan: Option<&str>,
+) -> windows::core::Result<()> {
+    use tauri::Manager;
+    use windows::core::{IInspectable, Interface, HSTRING};
+    use windows::Data::Xml::Dom::XmlDocument;
+    use windows::Foundation::{DateTime, IReference, PropertyValue, TypedEventHandler};
+    use windows::UI::Notifications::{
+        ToastActivatedEventArgs, ToastNotification, ToastNotificationManager,
+    };
+
+    let title = text::fit(app, Surface::NotificationTitle, title, Ellipsis::Middle);
+    let body = text::fit(app, Surface::NotificationBody, body, Ellipsis::End);
+
+    let document = XmlDocument::new()?;
+    // Without animations a toast is easy to miss, so it stays up longer
+    let long = app
+        .state::<crate::accessibility::AccessibilityState>()
+        .current()
+        .reduced_motion;
+    document.LoadXml(&HSTRING::from(toast_xml(&title, &body, mute_plan, long)))?;
+    let toast = ToastNotification::CreateToastNotification(&document)?;
+    toast.SetGroup(&HSTRING::from(category.group()))?;
+    toast.SetTag(&HSTRING::from(tag))?;
+
+    // DateTime counts 100 ns ticks since 1601-01-01
+    const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+    let expires = std::time::SystemTime::now() + category.expiry();
+    let since_epoch = expires
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let expiration = PropertyValue::CreateDateTime(DateTime {
+        UniversalTime: UNIX_EPOCH_TICKS + (since_epoch.as_nanos() / 100) as i64,
+    })?;
+    toast.SetExpirationTime(&expiration.cast::<IReference<DateTime>>()?)?;
+
+    let activated = app.clone();
+    toast.Activated(&TypedEventHandler::new(
+        move |_, args: &Option<IInspectable>| {
+            let arguments = args
+                .as_ref()
+                .and_then(|args| args.cast::<ToastActivatedEventArgs>().ok())
+                .and_then(|args| args.Arguments().ok())
+                .map(|arguments| arguments.to_string())
+                .unwrap_or_default();
+            on_activated(&activated, category, &arguments);
+            Ok(())
+        },
+    ))?;
+
+    let app_id = HSTRING::from(app.config().identifier.as_str());
+    ToastNotificationManager::CreateToastNotifierWithId(&app_id)?.Show(&toast)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn show_toast(
+    _app: &tauri::AppHandle,
+    _category: Category,
+    _tag: &str,
+    _title: &str,
+    _body: &str,
+    _mute_plan: Option<&str>,
+) -> Result<(), String> {
+    Err("Toasts are only available on Windows".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn repeated_failures_update_one_toast() {
+        let ledger = ToastLedger::default();
+        let start = Instant::now();
+
+        let first = ledger.decide(Category::BackupFailed, "Documents", start);
+        let ToastDecision::New { tag } = first else {
+            panic!("first failure should open a toast, got {:?}", first);
+        };
+        assert!(tag.starts_with("backup-failed-"));
+
+        for (i, plan) in ["Photos", "Mail"].iter().enumerate() {
+            let decision = ledger.decide(Category::BackupFailed, plan, start + HOUR * i as u32);
+            assert_eq!(
+                decision,
+                ToastDecision::Update {
+                    tag: tag.clone(),
+                    count: i + 2
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn categories_collapse_separately() {
+        let ledger = ToastLedger::default();
+        let now = Instant::now();
+        let failed = ledger.decide(Category::BackupFailed, "Documents", now);
+        let unreachable = ledger.decide(Category::RepositoryUnreachable, "NAS", now);
+        assert!(matches!(failed, ToastDecision::New { .. }));
+        assert!(matches!(unreachable, ToastDecision::New { .. }));
+        assert_ne!(failed, unreachable);
+    }
+
+    #[test]
+    fn closed_window_opens_a_new_toast() {
+        let ledger = ToastLedger::default();
+        let start = Instant::now();
+        let window = Category::BackupCompleted.collapse_window().unwrap();
+
+        let first = ledger.decide(Category::BackupCompleted, "Documents", start);
+        let late = ledger.decide(Category::BackupCompleted, "Photos", start + window);
+        assert!(matches!(late, ToastDecision::New { .. }));
+        assert_ne!(first, late);
+        // The new toast counts from one again
+        assert!(matches!(
+            ledger.decide(Category::BackupCompleted, "Mail", start + window),
+            ToastDecision::Update { count: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn general_notifications_never_collapse() {
+        let ledger = ToastLedger::default();
+        let now = Instant::now();
+        let first = ledger.decide(Category::General, "a", now);
+        let second = ledger.decide(Category::General, "b", now);
+        assert!(matches!(first, ToastDecision::New { .. }));
+        assert!(matches!(second, ToastDecision::New { .. }));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn dismissing_starts_the_next_event_afresh() {
+        let ledger = ToastLedger::default();
+        let now = Instant::now();
+        ledger.decide(Category::BackupFailed, "Documents", now);
+        ledger.decide(Category::BackupFailed, "Photos", now);
+        ledger.dismiss(Category::BackupFailed);
+        assert!(matches!(
+            ledger.decide(Category::BackupFailed, "Mail", now),
+            ToastDecision::New { .. }
+        ));
+    }
+
+    #[test]
+    fn collapsed_toast_lists_the_newest_items() {
+        let ledger = ToastLedger::default();
+        let now = Instant::now();
+        let mut decision = None;
+        for plan in ["Documents", "Photos", "Mail", "Music"] {
+            decision = Some(ledger.decide(Category::BackupFailed, plan, now));
+        }
+
+        let (title, body) = compose(
+            &ledger,
+            Category::BackupFailed,
+            &decision.unwrap(),
+            "Backup failed",
+            "Music: repository locked",
+        );
+        assert_eq!(title, "4 backups failed");
+        assert_eq!(body, "Music, Mail, Photos, …");
+    }
+
+    #[test]
+    fn new_toast_keeps_its_own_text() {
+        let ledger = ToastLedger::default();
+        let decision = ledger.decide(Category::BackupFailed, "Documents", Instant::now());
+        let (title, body) = compose(
+            &ledger,
+            Category::BackupFailed,
+            &decision,
+            "Backup failed",
+            "Documents: repository locked",
+        );
+        assert_eq!(title, "Backup failed");
+        assert_eq!(body, "Documents: repository locked");
+    }
+
+    #[test]
+    fn toast_xml_escapes_user_text() {
+        let xml = toast_xml("Plan <A> & \"B\"", "x", Some("id&1"), true);
+        assert!(xml.contains("<text>Plan &lt;A&gt; &amp; &quot;B&quot;</text>"));
+        assert!(xml.contains(r#"arguments="mute-plan:id&amp;1""#));
+        assert!(xml.contains(r#"duration="long""#));
+
+        let plain = toast_xml("t", "b", None, false);
+        assert!(!plain.contains("<actions>"));
+        assert!(!plain.contains("duration"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Sidecars left running by a run of the app that crashed.
+//!
+//! The sidecar isn't always taken down with the app: off Windows nothing kills it, and on
+//! Windows only when its Job Object could be set up. The next start would then find a server
+//! on the port with no handle to stop it by. [`record`] writes the sidecar's PID, port and
+//! token to [`PID_FILE`] in the data directory when it's spawned; at the next start a record
+//! whose process still serves zerobyte is adopted (see `adopt_orphan` in `lib.rs`) so quitting
+//! stops it again, and a record whose process is gone is removed. `stop_sidecar` removes the
+//! file once the sidecar is stopped.
+
+use crate::persist;
+use crate::proctree::ProcessTree;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// File name of the record in the data directory
+pub const PID_FILE: &str = "sidecar.pid";
+
+/// The sidecar this app last spawned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SidecarRecord {
+    pub pid: u32,
+    pub port: u16,
+    /// Shared secret it was started with
+    pub token: Option<String>,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+}
+
+impl SidecarRecord {
+    pub fn new(pid: u32, port: u16, token: Option<String>) -> Self {
+        Self {
+            pid,
+            port,
+            token,
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// A sidecar from an earlier run that this run took over
+pub struct AdoptedSidecar {
+    pub port: u16,
+    pub tree: ProcessTree,
+}
+
+fn path(data_dir: &Path) -> PathBuf {
+    data_dir.join(PID_FILE)
+}
+
+pub fn record(data_dir: &Path, record: &SidecarRecord) {
+    let written = serde_json::to_vec(record)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| {
+            persist::atomic_write(&path(data_dir), &bytes).map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        warn!("Failed to write {}: {}", path(data_dir).display(), e);
+    }
+}
+
+pub fn read(data_dir: &Path) -> Option<SidecarRecord> {
+    let contents = std::fs::read(path(data_dir)).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+pub fn remove(data_dir: &Path) {
+    match std::fs::remove_file(path(data_dir)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            warn!("Failed to remove {}: {}", path(data_dir).display(), e);
+        }
+        _ => {}
+    }
+}
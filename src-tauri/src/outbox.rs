@@ -0,0 +1,364 @@
+//! Durable queue for backend mutations requested while the backend is unreachable.
+//!
+//! Desktop-initiated actions (pause/resume from the tray, acknowledging a failure, client
+//! state) are queued with a TTL in `outbox.json` and replayed in order once the backend is
+//! healthy, so clicking "Pause backups" during a restart isn't lost. Only idempotent actions
+//! can be expressed here: triggering a backup is deliberately not a `BackendAction`, since
+//! replaying it late (or twice) would run an unexpected backup.
+
+use crate::capabilities::Feature;
+use crate::persist;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// File name of the outbox document in the app data directory
+pub const OUTBOX_FILE: &str = "outbox.json";
+
+/// TTL used when the caller doesn't pick one
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Longest TTL an entry may have; anything older is no longer what the user meant
+pub const MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An idempotent backend mutation that may be replayed later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendAction {
+    PauseBackups,
+    ResumeBackups,
+    AcknowledgeFailure { event_id: String },
+    SetClientState { key: String, value: serde_json::Value },
+}
+
+impl BackendAction {
+    /// Actions with the same key replace each other; only the latest intent is kept.
+    /// Pause and resume share a key, so a resume supersedes a pending pause.
+    pub fn supersede_key(&self) -> String {
+        match self {
+            BackendAction::PauseBackups | BackendAction::ResumeBackups => "backups:paused".into(),
+            BackendAction::AcknowledgeFailure { event_id } => format!("ack:{}", event_id),
+            BackendAction::SetClientState { key, .. } => format!("client_state:{}", key),
+        }
+    }
+
+    /// Backend feature the action depends on
+    pub fn feature(&self) -> Feature {
+        match self {
+            BackendAction::PauseBackups | BackendAction::ResumeBackups => Feature::TrayPause,
+            BackendAction::AcknowledgeFailure { .. } => Feature::AcknowledgeFailure,
+            BackendAction::SetClientState { .. } => Feature::ClientState,
+        }
+    }
+
+    /// HTTP method, API path and body used to apply the action
+    pub fn request(&self) -> (reqwest::Method, String, Option<serde_json::Value>) {
+        match self {
+            BackendAction::PauseBackups => (
+                reqwest::Method::POST,
+                "/api/v1/system/backups/pause".into(),
+                None,
+            ),
+            BackendAction::ResumeBackups => (
+                reqwest::Method::POST,
+                "/api/v1/system/backups/resume".into(),
+                None,
+            ),
+            BackendAction::AcknowledgeFailure { event_id } => (
+                reqwest::Method::POST,
+                format!("/api/v1/events/{}/acknowledge", event_id),
+                None,
+            ),
+            BackendAction::SetClientState { key, value } => (
+                reqwest::Method::PUT,
+                format!("/api/v1/system/client-state/{}", key),
+                Some(serde_json::json!({ "value": value })),
+            ),
+        }
+    }
+}
+
+/// A queued action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingAction {
+    pub id: u64,
+    pub action: BackendAction,
+    /// Seconds since the Unix epoch
+    pub enqueued_at: u64,
+    pub expires_at: u64,
+}
+
+impl PendingAction {
+    pub fn expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct OutboxDocument {
+    next_id: u64,
+    entries: Vec<PendingAction>,
+}
+
+impl OutboxDocument {
+    /// Queue `action`, dropping pending entries it supersedes. Returns the new entry and
+    /// the dropped ones.
+    fn enqueue(
+        &mut self,
+        action: BackendAction,
+        ttl: Duration,
+        now: u64,
+    ) -> (PendingAction, Vec<PendingAction>) {
+        let key = action.supersede_key();
+        let (superseded, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.action.supersede_key() == key);
+        self.entries = kept;
+
+        self.next_id += 1;
+        let entry = PendingAction {
+            id: self.next_id,
+            action,
+            enqueued_at: now,
+            expires_at: now + ttl.min(MAX_TTL).as_secs(),
+        };
+        self.entries.push(entry.clone());
+        (entry, superseded)
+    }
+
+    /// Remove and return entries whose TTL has passed
+    fn take_expired(&mut self, now: u64) -> Vec<PendingAction> {
+        let (expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.expired(now));
+        self.entries = kept;
+        expired
+    }
+}
+
+/// The persisted queue, saved back on every change
+pub struct Outbox {
+    path: PathBuf,
+    document: Mutex<OutboxDocument>,
+}
+
+impl Outbox {
+    /// Load the queue from `path`; a damaged file yields an empty queue
+    pub fn load(path: PathBuf) -> Self {
+        let (document, source) = persist::load_json::<OutboxDocument>(&path);
+        if source.recovered() {
+            tracing::warn!("Outbox {} was damaged ({:?})", path.display(), source);
+        }
+        Self {
+            path,
+            document: Mutex::new(document),
+        }
+    }
+
+    /// Entries in replay order
+    pub fn pending(&self) -> Vec<PendingAction> {
+        self.document.lock().entries.clone()
+    }
+
+    /// The next entry to replay
+    pub fn front(&self) -> Option<PendingAction> {
+        self.document.lock().entries.first().cloned()
+    }
+
+    /// Queue an action. Returns the new entry and any pending entries it superseded.
+    pub fn enqueue(
+        &self,
+        action: BackendAction,
+        ttl: Duration,
+        now: u64,
+    ) -> Result<(PendingAction, Vec<PendingAction>), String> {
+        self.modify(|document| document.enqueue(action, ttl, now))
+    }
+
+    /// Drop and return entries whose TTL has passed
+    pub fn take_expired(&self, now: u64) -> Result<Vec<PendingAction>, String> {
+        self.modify(|document| document.take_expired(now))
+    }
+
+    /// Remove an entry once it was applied (or permanently rejected)
+    pub fn remove(&self, id: u64) -> Result<(), String> {
+        self.modify(|document| document.entries.retain(|entry| entry.id != id))
+    }
+
+    /// Drop expired entries and entries with a duplicate id, and write the queue back
+    /// (over a damaged file too). Returns the expired entries.
+    pub fn repair(&self, now: u64) -> Result<Vec<PendingAction>, String> {
+        self.modify(|document| {
+            let expired = document.take_expired(now);
+            let mut seen = HashSet::new();
+            document.entries.retain(|entry| seen.insert(entry.id));
+            let highest = document.entries.iter().map(|entry| entry.id).max();
+            document.next_id = document.next_id.max(highest.unwrap_or(0));
+            expired
+        })
+    }
+
+    /// Apply a change and persist it. The in-memory queue only changes if the write succeeds.
+    fn modify<T>(&self, change: impl FnOnce(&mut OutboxDocument) -> T) -> Result<T, String> {
+        let mut current = self.document.lock();
+        let mut updated = current.clone();
+        let result = change(&mut updated);
+
+        persist::save_json(&self.path, &updated)
+            .map_err(|e| format!("Failed to save {}: {}", self.path.display(), e))?;
+
+        *current = updated;
+        Ok(result)
+    }
+}
+
+/// Check a saved queue: Err if it doesn't parse, otherwise the invariants it breaks
+pub fn verify(bytes: &[u8], now: u64) -> Result<Vec<String>, String> {
+    let document: OutboxDocument = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+    let expired = document.entries.iter().filter(|entry| entry.expired(now)).count();
+    if expired > 0 {
+        problems.push(format!("{} expired entries still queued", expired));
+    }
+    let mut seen = HashSet::new();
+    for entry in &document.entries {
+        if !seen.insert(entry.id) {
+            problems.push(format!("entry id {} is used more than once", entry.id));
+        }
+        if entry.id > document.next_id {
+            problems.push(format!("entry id {} is ahead of the id counter", entry.id));
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn ack(event_id: &str) -> BackendAction {
+        BackendAction::AcknowledgeFailure {
+            event_id: event_id.to_string(),
+        }
+    }
+
+    fn actions(document: &OutboxDocument) -> Vec<BackendAction> {
+        document.entries.iter().map(|e| e.action.clone()).collect()
+    }
+
+    #[test]
+    fn resume_supersedes_a_pending_pause() {
+        let mut document = OutboxDocument::default();
+        let (pause, _) = document.enqueue(BackendAction::PauseBackups, DEFAULT_TTL, NOW);
+        let (_, superseded) = document.enqueue(BackendAction::ResumeBackups, DEFAULT_TTL, NOW + 1);
+
+        assert_eq!(superseded, vec![pause]);
+        assert_eq!(actions(&document), vec![BackendAction::ResumeBackups]);
+    }
+
+    #[test]
+    fn unrelated_actions_are_kept_in_order() {
+        let mut document = OutboxDocument::default();
+        document.enqueue(ack("a"), DEFAULT_TTL, NOW);
+        document.enqueue(BackendAction::PauseBackups, DEFAULT_TTL, NOW);
+        let (_, superseded) = document.enqueue(ack("b"), DEFAULT_TTL, NOW);
+
+        assert!(superseded.is_empty());
+        assert_eq!(
+            actions(&document),
+            vec![ack("a"), BackendAction::PauseBackups, ack("b")]
+        );
+    }
+
+    #[test]
+    fn superseding_entry_moves_to_the_back() {
+        let mut document = OutboxDocument::default();
+        document.enqueue(BackendAction::PauseBackups, DEFAULT_TTL, NOW);
+        document.enqueue(ack("a"), DEFAULT_TTL, NOW);
+        document.enqueue(BackendAction::ResumeBackups, DEFAULT_TTL, NOW);
+
+        // The resume is replayed after the acknowledgement queued before it
+        assert_eq!(
+            actions(&document),
+            vec![ack("a"), BackendAction::ResumeBackups]
+        );
+    }
+
+    #[test]
+    fn client_state_is_superseded_per_key() {
+        let set = |key: &str, value: i64| BackendAction::SetClientState {
+            key: key.to_string(),
+            value: value.into(),
+        };
+        let mut document = OutboxDocument::default();
+        document.enqueue(set("theme", 1), DEFAULT_TTL, NOW);
+        document.enqueue(set("layout", 1), DEFAULT_TTL, NOW);
+        let (_, superseded) = document.enqueue(set("theme", 2), DEFAULT_TTL, NOW);
+
+        assert_eq!(superseded.len(), 1);
+        assert_eq!(superseded[0].action, set("theme", 1));
+        assert_eq!(actions(&document), vec![set("layout", 1), set("theme", 2)]);
+    }
+
+    #[test]
+    fn ids_keep_increasing_across_supersession() {
+        let mut document = OutboxDocument::default();
+        let (first, _) = document.enqueue(BackendAction::PauseBackups, DEFAULT_TTL, NOW);
+        let (second, _) = document.enqueue(BackendAction::ResumeBackups, DEFAULT_TTL, NOW);
+        assert!(second.id > first.id);
+    }
+
+    #[test]
+    fn entries_expire_at_their_ttl() {
+        let mut document = OutboxDocument::default();
+        document.enqueue(ack("short"), Duration::from_secs(60), NOW);
+        document.enqueue(ack("long"), Duration::from_secs(600), NOW);
+
+        assert!(document.take_expired(NOW + 59).is_empty());
+        let expired = document.take_expired(NOW + 60);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].action, ack("short"));
+        assert_eq!(actions(&document), vec![ack("long")]);
+    }
+
+    #[test]
+    fn ttl_is_capped() {
+        let mut document = OutboxDocument::default();
+        let (entry, _) = document.enqueue(ack("a"), Duration::from_secs(7 * 24 * 60 * 60), NOW);
+        assert_eq!(entry.expires_at, NOW + MAX_TTL.as_secs());
+    }
+
+    #[test]
+    fn trigger_backup_cannot_be_queued() {
+        let trigger = r#"{"type":"trigger_backup","schedule_id":1}"#;
+        assert!(serde_json::from_str::<BackendAction>(trigger).is_err());
+        assert!(serde_json::from_str::<BackendAction>(r#"{"type":"pause_backups"}"#).is_ok());
+    }
+
+    #[test]
+    fn queue_survives_a_reload() {
+        let path = std::env::temp_dir().join(format!("outbox-{}-reload.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+
+        let outbox = Outbox::load(path.clone());
+        outbox
+            .enqueue(BackendAction::PauseBackups, DEFAULT_TTL, NOW)
+            .unwrap();
+        outbox.enqueue(ack("a"), DEFAULT_TTL, NOW).unwrap();
+        let pending = outbox.pending();
+
+        let reloaded = Outbox::load(path.clone());
+        assert_eq!(reloaded.pending(), pending);
+        let (next, _) = reloaded.enqueue(ack("b"), DEFAULT_TTL, NOW).unwrap();
+        assert!(pending.iter().all(|entry| entry.id < next.id));
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+    }
+}
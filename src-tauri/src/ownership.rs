@@ -0,0 +1,297 @@
+//! Which desktop instance owns the sidecar backend.
+//!
+//! The instance that spawns the sidecar records itself in a machine-wide discovery file.
+//! Another session that finds the backend already running under a different owner attaches
+//! as a read-only viewer: commands that change backend state are rejected until the owner
+//! releases the backend.
+
+use crate::error::AppError;
+use crate::persist;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Commands a viewer may still run: they only read state, or only affect this desktop's own
+/// windows. Everything else is refused in viewer mode, so a command added later is refused
+/// until it's listed here.
+pub const VIEWER_COMMANDS: &[&str] = &[
+    "get_backend_url",
+    "get_backend_info",
+    "get_recent_backend_logs",
+    "get_backend_logs",
+    "list_crash_reports",
+    "read_crash_report",
+    "open_backend_log_folder",
+    "get_task_health",
+    "get_settings",
+    "get_data_directory",
+    "get_accessibility_preferences",
+    "show_window",
+    "list_actions",
+    "toggle_palette",
+    "hide_palette",
+    "get_schedule_conflicts",
+    "estimate_backup_size",
+    "cancel_backup_estimate",
+    "get_time_zones",
+    "convert_backend_times",
+    "check_path_access",
+    "get_controlled_folder_access",
+    "get_service_status",
+    "is_service_running",
+    "get_service_capabilities",
+    "get_elevation_status",
+    "get_app_info",
+    "get_connection_mode",
+    "get_devtools_status",
+    "get_pending_backend_actions",
+    "discover_backends",
+    "cancel_discovery",
+    "preview_redaction",
+    "list_restore_sessions",
+    "list_active_watchers",
+    "get_startup_stages",
+    "sync_events",
+    "get_desktop_capabilities",
+    "get_system_report",
+    "run_protection_audit",
+    "get_health_history",
+    "get_metrics_snapshot",
+    "webview_heartbeat",
+    "get_scratch_usage",
+    "preview_retention_cleanup",
+    "get_bandwidth_profiles",
+    "get_backend_process_info",
+    "get_backend_status",
+    "get_backend_mode",
+    "get_versions",
+    "get_launch_options",
+    "get_backend_resource_usage",
+    "get_log_level",
+    "get_desktop_log_path",
+    "get_stale_registrations",
+    "get_legacy_install",
+    "get_legacy_migration_report",
+    "list_notification_mutes",
+];
+
+/// Whether a viewer may run `command`
+pub fn allowed_for_viewer(command: &str) -> bool {
+    VIEWER_COMMANDS.contains(&command)
+}
+
+/// What kind of backend the desktop is talking to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendMode {
+    /// A sidecar this instance started
+    Sidecar,
+    /// The Windows Service
+    Service,
+    /// A server this instance didn't start: another session's or an earlier run's sidecar,
+    /// or a dev server
+    External,
+}
+
+impl BackendMode {
+    pub fn of(using_service: bool, owns_sidecar: bool) -> Self {
+        match (using_service, owns_sidecar) {
+            (true, _) => BackendMode::Service,
+            (false, true) => BackendMode::Sidecar,
+            (false, false) => BackendMode::External,
+        }
+    }
+}
+
+/// The desktop instance that spawned the running sidecar
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackendOwner {
+    /// Human-readable session label (user name)
+    pub session: String,
+    pub pid: u32,
+    pub port: u16,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+}
+
+impl BackendOwner {
+    /// Whether this record was written by the current process
+    pub fn is_current_process(&self) -> bool {
+        self.pid == std::process::id()
+    }
+}
+
+/// How this instance is connected to the backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ConnectionMode {
+    /// We own the backend (or it's the service / a dev server) and may change it
+    Owner,
+    /// Another session owns the backend; only viewer commands are allowed
+    Viewer { owner: String },
+}
+
+impl ConnectionMode {
+    pub fn is_viewer(&self) -> bool {
+        matches!(self, ConnectionMode::Viewer { .. })
+    }
+
+    /// Tray tooltip describing the connection
+    pub fn tooltip(&self) -> String {
+        match self {
+            ConnectionMode::Owner => crate::profile::app_title(),
+            ConnectionMode::Viewer { owner } => {
+                format!(
+                    "{} - Viewing backend owned by {}",
+                    crate::profile::app_title(),
+                    owner
+                )
+            }
+        }
+    }
+
+    /// Refuse `command` if we're only viewing, unless it's in [`VIEWER_COMMANDS`]
+    pub fn check_command(&self, command: &str) -> Result<(), AppError> {
+        match self {
+            ConnectionMode::Viewer { owner } if !allowed_for_viewer(command) => {
+                Err(AppError::ReadOnlyMode(owner.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Label for the current session, as shown to other sessions
+pub fn current_session() -> String {
+    std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "another user".to_string())
+}
+
+/// Machine-wide location of the discovery file, readable from every session. Each named
+/// profile has its own, since its sidecar is a separate backend.
+pub fn discovery_path() -> PathBuf {
+    let file_name = if crate::profile::is_default() {
+        "desktop-owner.json".to_string()
+    } else {
+        format!("desktop-owner-{}.json", crate::profile::current())
+    };
+
+    #[cfg(target_os = "windows")]
+    {
+        let program_data =
+            std::env::var("PROGRAMDATA").unwrap_or_else(|_| r"C:\ProgramData".to_string());
+        PathBuf::from(program_data)
+            .join("C3i Backup ONE")
+            .join(file_name)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        std::env::temp_dir().join(format!("zerobyte-{}", file_name))
+    }
+}
+
+/// Read the current owner record, if any
+pub fn read_owner() -> Option<BackendOwner> {
+    let contents = std::fs::read_to_string(discovery_path()).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Record this process as the owner of the sidecar on `port`
+pub fn claim(port: u16) -> Result<(), String> {
+    let owner = BackendOwner {
+        session: current_session(),
+        pid: std::process::id(),
+        port,
+        started_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    };
+
+    let path = discovery_path();
+    let json = serde_json::to_vec_pretty(&owner)
+        .map_err(|e| format!("Failed to serialize owner record: {}", e))?;
+    persist::atomic_write(&path, &json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Remove the owner record if this process wrote it
+pub fn release() {
+    if read_owner().is_some_and(|owner| owner.is_current_process()) {
+        let _ = std::fs::remove_file(discovery_path());
+    }
+}
+
+/// The owner of a backend found already running, if it's another instance
+pub fn foreign_owner() -> Option<BackendOwner> {
+    read_owner().filter(|owner| !owner.is_current_process())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Command names in `run()`'s `generate_handler!`
+    fn registered_commands() -> Vec<&'static str> {
+        let source = include_str!("lib.rs");
+        let start = source
+            .find("generate_handler![")
+            .expect("no generate_handler!");
+        let block = &source[start..];
+        let block = &block[..block.find(']').unwrap()];
+        block
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.trim().strip_suffix(','))
+            .filter_map(|path| path.rsplit("::").next())
+            .collect()
+    }
+
+    #[test]
+    fn viewer_commands_are_registered() {
+        let registered = registered_commands();
+        assert!(registered.len() > VIEWER_COMMANDS.len());
+        for command in VIEWER_COMMANDS {
+            assert!(registered.contains(command), "{} isn't registered", command);
+        }
+    }
+
+    #[test]
+    fn feature_commands_are_registered() {
+        let registered = registered_commands();
+        for feature in crate::desktop::FEATURES {
+            for command in feature.commands {
+                assert!(registered.contains(command), "{} isn't registered", command);
+            }
+        }
+    }
+
+    #[test]
+    fn viewer_is_refused_anything_not_listed() {
+        let viewer = ConnectionMode::Viewer {
+            owner: "alice".to_string(),
+        };
+        assert!(viewer.check_command("get_settings").is_ok());
+        for command in [
+            "update_settings",
+            "execute_action",
+            "toggle_devtools",
+            "new_command",
+        ] {
+            match viewer.check_command(command) {
+                Err(AppError::ReadOnlyMode(owner)) => assert_eq!(owner, "alice"),
+                other => panic!("{} gave {:?}", command, other),
+            }
+        }
+    }
+
+    #[test]
+    fn owner_may_run_anything() {
+        assert!(ConnectionMode::Owner
+            .check_command("update_settings")
+            .is_ok());
+        assert!(ConnectionMode::Owner.check_command("get_settings").is_ok());
+    }
+}
@@ -0,0 +1,585 @@
+//! Pairing with a service backend that requires authentication.
+//!
+//! Some deployments protect every route, `/healthcheck` included. A 401 or 403 there still
+//! means a backend is listening, so the desktop must not start a competing sidecar; it
+//! connects in service mode, marks the backend as needing pairing and waits for the user to
+//! pair. Pairing trades credentials (or an existing API token) for a desktop token that is
+//! kept in the OS keyring and sent as a bearer token on every call to the local backend.
+//! Tokens with an expiry are refreshed shortly before they run out.
+
+use crate::http::{HttpClient, HttpPolicy};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+/// Keyring service name, shared with stored repository passphrases
+const KEYRING_SERVICE: &str = "C3i Backup ONE";
+
+/// Endpoint that exchanges credentials for a desktop token
+pub const TOKEN_PATH: &str = "/api/v1/auth/desktop-token";
+
+/// Endpoint that exchanges a refresh token for a new desktop token
+pub const REFRESH_PATH: &str = "/api/v1/auth/desktop-token/refresh";
+
+/// How often the stored token's expiry is checked
+pub const REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long before expiry a token is refreshed
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// What a healthcheck says about the service backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceProbe {
+    /// Nothing answered, or it answered with an unrelated error
+    Absent,
+    Ready,
+    /// A backend answered but wants credentials first
+    NeedsPairing,
+}
+
+pub fn classify(status: Option<reqwest::StatusCode>) -> ServiceProbe {
+    match status {
+        Some(status) if status.is_success() => ServiceProbe::Ready,
+        Some(reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => {
+            ServiceProbe::NeedsPairing
+        }
+        _ => ServiceProbe::Absent,
+    }
+}
+
+/// A desktop token as kept in the keyring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredToken {
+    pub token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// Unix seconds
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl StoredToken {
+    /// Whether the token should be refreshed at `now` (Unix seconds)
+    pub fn due_for_refresh(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(at) if now + REFRESH_MARGIN.as_secs() >= at)
+    }
+}
+
+/// What the token endpoints answer
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponse {
+    token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// Seconds from now
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+impl TokenResponse {
+    fn into_stored(self, now: u64) -> StoredToken {
+        StoredToken {
+            token: self.token,
+            refresh_token: self.refresh_token,
+            expires_at: self.expires_in.map(|seconds| now + seconds),
+        }
+    }
+}
+
+/// What the user pairs with: an account's credentials or an API token issued by the backend
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Credentials {
+    Password {
+        username: String,
+        password: String,
+    },
+    Token {
+        token: String,
+    },
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn keyring_entry(port: u16) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(
+        KEYRING_SERVICE,
+        &format!("backend-token:localhost:{}", port),
+    )
+    .map_err(|e| format!("Keyring unavailable: {}", e))
+}
+
+/// Read the token stored for the backend on `port`, if there is one
+pub fn load_stored(port: u16) -> Result<Option<StoredToken>, String> {
+    match keyring_entry(port)?.get_password() {
+        Ok(json) => {
+            let json = Zeroizing::new(json);
+            serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| format!("Stored backend token is unreadable: {}", e))
+        }
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read from keyring: {}", e)),
+    }
+}
+
+pub fn store(port: u16, token: &StoredToken) -> Result<(), String> {
+    let json = Zeroizing::new(
+        serde_json::to_string(token).map_err(|e| format!("Failed to encode token: {}", e))?,
+    );
+    keyring_entry(port)?
+        .set_password(&json)
+        .map_err(|e| format!("Failed to save to keyring: {}", e))
+}
+
+pub fn forget(port: u16) -> Result<(), String> {
+    match keyring_entry(port)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to remove from keyring: {}", e)),
+    }
+}
+
+/// Load the stored token for `port` into the HTTP client. Returns whether there was one.
+pub fn load_into_client(app: &tauri::AppHandle, port: u16) -> bool {
+    match load_stored(port) {
+        Ok(Some(stored)) => {
+            app.state::<AppState>().http.set_bearer(Some(stored.token));
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            warn!("{}", e);
+            false
+        }
+    }
+}
+
+/// POST `body` to a token endpoint of the backend on `port` and use the token it answers
+async fn fetch_token(
+    http: &HttpClient,
+    port: u16,
+    path: &str,
+    body: &serde_json::Value,
+) -> Result<StoredToken, String> {
+    let url = format!("http://localhost:{}{}", port, path);
+    let response = http
+        .send(HttpPolicy::INTERACTIVE, |client| client.post(&url).json(body))
+        .await
+        .map_err(|e| format!("Failed to reach the backend: {}", e))?;
+    let status = response.status();
+    if matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    ) {
+        return Err("The backend rejected these credentials".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("Pairing failed with HTTP {}", status));
+    }
+    let stored = response
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| format!("Unexpected token response: {}", e))?
+        .into_stored(now_secs());
+    http.set_bearer(Some(stored.token.clone()));
+    Ok(stored)
+}
+
+/// Fetch a token from an endpoint of the backend on `port` and store it
+async fn request_token(
+    app: &tauri::AppHandle,
+    port: u16,
+    path: &str,
+    body: &serde_json::Value,
+) -> Result<StoredToken, String> {
+    let stored = fetch_token(&app.state::<AppState>().http, port, path, body).await?;
+    store(port, &stored)?;
+    Ok(stored)
+}
+
+/// Healthcheck the backend on `port` with whatever token `http` holds
+pub async fn check(http: &HttpClient, port: u16) -> ServiceProbe {
+    let status = http
+        .healthcheck(port, HttpPolicy::STARTUP)
+        .await
+        .ok()
+        .map(|response| response.status());
+    classify(status)
+}
+
+/// Healthcheck the backend on `port` with whatever token the client holds
+pub async fn probe(app: &tauri::AppHandle, port: u16) -> ServiceProbe {
+    check(&app.state::<AppState>().http, port).await
+}
+
+/// Run `probe`. If the backend rejects a token we hold, it may just have expired: `refresh`
+/// it once and probe again.
+pub async fn probe_refreshing<P, PF, R, RF>(
+    holds_token: bool,
+    mut probe: P,
+    refresh: R,
+) -> ServiceProbe
+where
+    P: FnMut() -> PF,
+    PF: Future<Output = ServiceProbe>,
+    R: FnOnce() -> RF,
+    RF: Future<Output = Result<(), String>>,
+{
+    let first = probe().await;
+    if first != ServiceProbe::NeedsPairing || !holds_token {
+        return first;
+    }
+    match refresh().await {
+        Ok(()) => probe().await,
+        Err(_) => first,
+    }
+}
+
+/// Pair with the backend on `port` and clear the pairing-required state
+pub async fn pair(
+    app: &tauri::AppHandle,
+    port: u16,
+    credentials: Credentials,
+) -> Result<(), String> {
+    match credentials {
+        Credentials::Password { username, password } => {
+            let password = Zeroizing::new(password);
+            let body = serde_json::json!({
+                "username": username,
+                "password": password.as_str(),
+                "client": "desktop",
+            });
+            request_token(app, port, TOKEN_PATH, &body).await?;
+        }
+        Credentials::Token { token } => {
+            let token = Zeroizing::new(token);
+            app.state::<AppState>()
+                .http
+                .set_bearer(Some(token.to_string()));
+            if probe(app, port).await != ServiceProbe::Ready {
+                app.state::<AppState>().http.set_bearer(None);
+                return Err("The backend rejected this token".to_string());
+            }
+            store(
+                port,
+                &StoredToken {
+                    token: token.to_string(),
+                    refresh_token: None,
+                    expires_at: None,
+                },
+            )?;
+        }
+    }
+
+    app.state::<AppState>()
+        .needs_pairing
+        .store(false, Ordering::SeqCst);
+    info!(target: "zerobyte::audit", "Paired with the backend on port {}", port);
+    Ok(())
+}
+
+/// Swap the refresh token of `stored` for a new desktop token
+async fn fetch_refreshed(
+    http: &HttpClient,
+    port: u16,
+    stored: Option<StoredToken>,
+) -> Result<StoredToken, String> {
+    let Some(refresh_token) = stored.and_then(|stored| stored.refresh_token) else {
+        return Err("No refresh token stored".to_string());
+    };
+    let body = serde_json::json!({ "refreshToken": refresh_token });
+    fetch_token(http, port, REFRESH_PATH, &body).await
+}
+
+/// Swap the stored refresh token for a new desktop token
+pub async fn refresh(app: &tauri::AppHandle, port: u16) -> Result<(), String> {
+    let http = &app.state::<AppState>().http;
+    let stored = fetch_refreshed(http, port, load_stored(port)?).await?;
+    store(port, &stored)?;
+    info!("Refreshed the desktop token for port {}", port);
+    Ok(())
+}
+
+/// Refresh the desktop token before it expires, for as long as the app runs
+pub async fn keep_fresh(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "backend-token",
+        crate::activity::TaskClass::Standard,
+        REFRESH_CHECK_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+        let port = app.state::<AppState>().backend_port.load(Ordering::SeqCst);
+        let due = matches!(
+            load_stored(port),
+            Ok(Some(stored)) if stored.due_for_refresh(now_secs())
+        );
+        if !due {
+            continue;
+        }
+        if let Err(e) = refresh(&app, port).await {
+            warn!("Failed to refresh the desktop token: {}", e);
+            mark_needs_pairing(&app, port);
+        }
+    }
+}
+
+/// Flag that the backend on `port` wants pairing and tell the webviews
+pub fn mark_needs_pairing(app: &tauri::AppHandle, port: u16) {
+    let state = app.state::<AppState>();
+    if !state.needs_pairing.swap(true, Ordering::SeqCst) {
+        warn!("Backend on port {} requires pairing", port);
+        crate::events::emit(
+            app,
+            "backend-pairing-required",
+            serde_json::json!({ "port": port }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Requests seen by the mock backend: path and Authorization header
+    type Seen = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    /// A local backend that answers each request with `answer(n, path, authorization)`,
+    /// where `n` counts requests from 0
+    async fn mock_backend(
+        answer: impl Fn(usize, &str, Option<&str>) -> (u16, String) + Send + Sync + 'static,
+    ) -> (u16, Seen) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let seen: Seen = Arc::default();
+        let log = seen.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let read = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..read]).into_owned();
+                let path = request
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let authorization = request.lines().find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("authorization")
+                        .then(|| value.trim().to_string())
+                });
+                let n = {
+                    let mut log = log.lock();
+                    log.push((path.clone(), authorization.clone()));
+                    log.len() - 1
+                };
+                let (status, body) = answer(n, &path, authorization.as_deref());
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (port, seen)
+    }
+
+    fn expiring(refresh_token: Option<&str>) -> StoredToken {
+        StoredToken {
+            token: "old".to_string(),
+            refresh_token: refresh_token.map(str::to_string),
+            expires_at: Some(0),
+        }
+    }
+
+    /// Startup's probe: refresh the held token once if the service rejects it
+    async fn startup_probe(http: &HttpClient, port: u16, stored: StoredToken) -> ServiceProbe {
+        probe_refreshing(
+            http.has_bearer(),
+            || check(http, port),
+            || async { fetch_refreshed(http, port, Some(stored)).await.map(|_| ()) },
+        )
+        .await
+    }
+
+    #[test]
+    fn healthcheck_status_decides_the_startup_path() {
+        use reqwest::StatusCode;
+        let cases = [
+            (None, ServiceProbe::Absent),
+            (Some(StatusCode::OK), ServiceProbe::Ready),
+            (Some(StatusCode::NO_CONTENT), ServiceProbe::Ready),
+            (Some(StatusCode::UNAUTHORIZED), ServiceProbe::NeedsPairing),
+            (Some(StatusCode::FORBIDDEN), ServiceProbe::NeedsPairing),
+            (Some(StatusCode::NOT_FOUND), ServiceProbe::Absent),
+            (
+                Some(StatusCode::INTERNAL_SERVER_ERROR),
+                ServiceProbe::Absent,
+            ),
+            (Some(StatusCode::SERVICE_UNAVAILABLE), ServiceProbe::Absent),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(classify(status), expected, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn refresh_is_due_within_the_margin_of_expiry() {
+        let margin = REFRESH_MARGIN.as_secs();
+        let token = |expires_at| StoredToken {
+            token: "t".to_string(),
+            refresh_token: None,
+            expires_at,
+        };
+        assert!(!token(None).due_for_refresh(u64::MAX / 2));
+        assert!(!token(Some(10_000)).due_for_refresh(10_000 - margin - 1));
+        assert!(token(Some(10_000)).due_for_refresh(10_000 - margin));
+        assert!(token(Some(10_000)).due_for_refresh(20_000));
+    }
+
+    #[test]
+    fn token_responses_become_stored_tokens() {
+        let response: TokenResponse =
+            serde_json::from_str(r#"{"token":"t","refreshToken":"r","expiresIn":3600}"#).unwrap();
+        let stored = response.into_stored(1_000);
+        assert_eq!(stored.token, "t");
+        assert_eq!(stored.refresh_token.as_deref(), Some("r"));
+        assert_eq!(stored.expires_at, Some(4_600));
+
+        let response: TokenResponse = serde_json::from_str(r#"{"token":"t"}"#).unwrap();
+        let stored = response.into_stored(1_000);
+        assert_eq!((stored.refresh_token, stored.expires_at), (None, None));
+    }
+
+    #[test]
+    fn credentials_accept_a_password_or_a_token() {
+        let password: Credentials =
+            serde_json::from_str(r#"{"username":"me","password":"pw"}"#).unwrap();
+        assert!(matches!(password, Credentials::Password { username, .. } if username == "me"));
+        let token: Credentials = serde_json::from_str(r#"{"token":"abc"}"#).unwrap();
+        assert!(matches!(token, Credentials::Token { token } if token == "abc"));
+        assert!(serde_json::from_str::<Credentials>(r#"{"username":"me"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn a_healthcheck_behind_auth_means_the_service_needs_pairing() {
+        let (port, _) = mock_backend(|_, _, _| (401, String::new())).await;
+        assert_eq!(
+            check(&HttpClient::default(), port).await,
+            ServiceProbe::NeedsPairing
+        );
+    }
+
+    #[tokio::test]
+    async fn an_expired_token_is_refreshed_and_the_service_probed_again() {
+        let (port, seen) = mock_backend(|_, path, authorization| match path {
+            "/healthcheck" if authorization == Some("Bearer new") => (200, String::new()),
+            "/healthcheck" => (401, String::new()),
+            REFRESH_PATH => (
+                200,
+                r#"{"token":"new","refreshToken":"r2","expiresIn":3600}"#.to_string(),
+            ),
+            _ => (404, String::new()),
+        })
+        .await;
+        let http = HttpClient::default();
+        http.set_bearer(Some("old".to_string()));
+
+        let probe = startup_probe(&http, port, expiring(Some("r1"))).await;
+        assert_eq!(probe, ServiceProbe::Ready);
+        let seen = seen.lock().clone();
+        let paths: Vec<&str> = seen.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["/healthcheck", REFRESH_PATH, "/healthcheck"]);
+        assert_eq!(seen[0].1.as_deref(), Some("Bearer old"));
+        assert_eq!(seen[2].1.as_deref(), Some("Bearer new"));
+    }
+
+    #[tokio::test]
+    async fn a_rejected_refresh_leaves_the_service_needing_pairing() {
+        let (port, seen) = mock_backend(|_, _, _| (401, String::new())).await;
+        let http = HttpClient::default();
+        http.set_bearer(Some("old".to_string()));
+
+        let probe = startup_probe(&http, port, expiring(Some("r1"))).await;
+        assert_eq!(probe, ServiceProbe::NeedsPairing);
+        assert_eq!(seen.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn without_a_token_nothing_is_refreshed() {
+        let (port, seen) = mock_backend(|_, _, _| (401, String::new())).await;
+        let probe = startup_probe(&HttpClient::default(), port, expiring(Some("r1"))).await;
+        assert_eq!(probe, ServiceProbe::NeedsPairing);
+        assert_eq!(seen.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn without_a_refresh_token_nothing_is_requested() {
+        let (port, seen) = mock_backend(|_, _, _| (401, String::new())).await;
+        let http = HttpClient::default();
+        http.set_bearer(Some("old".to_string()));
+        let probe = startup_probe(&http, port, expiring(None)).await;
+        assert_eq!(probe, ServiceProbe::NeedsPairing);
+        assert_eq!(seen.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_service_answering_401_then_200_is_ready_after_pairing() {
+        // The first healthcheck comes before pairing; the token request pairs
+        let (port, seen) = mock_backend(|n, path, authorization| match path {
+            TOKEN_PATH => (200, r#"{"token":"paired"}"#.to_string()),
+            "/healthcheck" if n > 0 && authorization == Some("Bearer paired") => {
+                (200, String::new())
+            }
+            _ => (401, String::new()),
+        })
+        .await;
+        let http = HttpClient::default();
+        assert_eq!(check(&http, port).await, ServiceProbe::NeedsPairing);
+
+        let body = serde_json::json!({ "username": "me", "password": "pw" });
+        let stored = fetch_token(&http, port, TOKEN_PATH, &body).await.unwrap();
+        assert_eq!(stored.token, "paired");
+        assert!(http.has_bearer());
+        assert_eq!(check(&http, port).await, ServiceProbe::Ready);
+        assert_eq!(seen.lock().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials_keep_the_old_token() {
+        let (port, _) = mock_backend(|_, _, _| (403, String::new())).await;
+        let http = HttpClient::default();
+        let body = serde_json::json!({ "username": "me", "password": "wrong" });
+        let error = fetch_token(&http, port, TOKEN_PATH, &body)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "The backend rejected these credentials");
+        assert!(!http.has_bearer());
+
+        let (port, _) = mock_backend(|_, _, _| (200, "not json".to_string())).await;
+        let error = fetch_token(&http, port, TOKEN_PATH, &body)
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("Unexpected token response"), "{}", error);
+        assert!(!http.has_bearer());
+    }
+}
@@ -0,0 +1,58 @@
+//! Quick actions palette: a small frameless window listing every desktop action.
+
+use tauri::{Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+
+/// Window label of the palette
+pub const PALETTE_LABEL: &str = "palette";
+
+/// Show the palette, creating it on first use, or hide it if it's already visible
+pub fn toggle(app: &tauri::AppHandle) -> tauri::Result<()> {
+    if let Some(window) = app.get_webview_window(PALETTE_LABEL) {
+        if window.is_visible()? {
+            window.hide()?;
+        } else {
+            window.center()?;
+            window.show()?;
+            window.set_focus()?;
+            // Availability may have changed while it was hidden
+            notify_actions_changed(app);
+            refresh_plans(app);
+        }
+        return Ok(());
+    }
+
+    WebviewWindowBuilder::new(app, PALETTE_LABEL, WebviewUrl::App("palette.html".into()))
+        .title("Quick actions")
+        .inner_size(560.0, 360.0)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .center()
+        .focused(true)
+        .build()?;
+    refresh_plans(app);
+
+    Ok(())
+}
+
+/// Fetch the backup plans behind the palette's "back up" entries; the open palette is told
+/// once they're in
+fn refresh_plans(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { crate::actions::refresh_plans(&app).await });
+}
+
+/// Hide the palette if it's open
+pub fn hide(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window(PALETTE_LABEL) {
+        let _ = window.hide();
+    }
+}
+
+/// Tell an open palette to refresh its list because action availability changed
+pub fn notify_actions_changed(app: &tauri::AppHandle) {
+    if app.get_webview_window(PALETTE_LABEL).is_some() {
+        let _ = app.emit_to(PALETTE_LABEL, "actions-changed", ());
+    }
+}
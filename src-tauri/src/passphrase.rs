@@ -0,0 +1,249 @@
+//! Native passphrase prompt for unlocking encrypted repositories.
+//!
+//! The passphrase is read through a native masked-input dialog, sent straight to the
+//! backend's unlock endpoint and optionally kept in the OS keyring; it never passes through
+//! the webview and is never logged. Every copy we own lives in a `Zeroizing` buffer that is
+//! wiped when dropped.
+
+use std::process::Command;
+use zeroize::Zeroizing;
+
+/// Keyring service name for stored repository passphrases
+const KEYRING_SERVICE: &str = "C3i Backup ONE";
+
+/// Where remembered passphrases are kept
+pub trait SecretStore: Send + Sync + 'static {
+    /// The stored passphrase, if there is one
+    fn load(&self, repo_id: &str) -> Result<Option<Zeroizing<String>>, String>;
+    fn store(&self, repo_id: &str, passphrase: &str) -> Result<(), String>;
+    /// Remove the stored passphrase; not having one is fine
+    fn forget(&self, repo_id: &str) -> Result<(), String>;
+}
+
+/// The OS keyring
+pub struct Keyring;
+
+impl Keyring {
+    fn entry(repo_id: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, &format!("repository:{}", repo_id))
+            .map_err(|e| format!("Keyring unavailable: {}", e))
+    }
+}
+
+impl SecretStore for Keyring {
+    fn load(&self, repo_id: &str) -> Result<Option<Zeroizing<String>>, String> {
+        match Self::entry(repo_id)?.get_password() {
+            Ok(passphrase) => Ok(Some(Zeroizing::new(passphrase))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Failed to read from keyring: {}", e)),
+        }
+    }
+
+    fn store(&self, repo_id: &str, passphrase: &str) -> Result<(), String> {
+        Self::entry(repo_id)?
+            .set_password(passphrase)
+            .map_err(|e| format!("Failed to save to keyring: {}", e))
+    }
+
+    fn forget(&self, repo_id: &str) -> Result<(), String> {
+        match Self::entry(repo_id)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to remove from keyring: {}", e)),
+        }
+    }
+}
+
+/// JSON body for the unlock endpoint. It is built in a buffer that is wiped once the last
+/// request carrying it is dropped; sending it, or retrying, doesn't copy it.
+pub fn unlock_body(passphrase: &str) -> Result<bytes::Bytes, String> {
+    #[derive(serde::Serialize)]
+    struct UnlockBody<'a> {
+        passphrase: &'a str,
+    }
+
+    serde_json::to_vec(&UnlockBody { passphrase })
+        .map(|body| bytes::Bytes::from_owner(Zeroizing::new(body)))
+        .map_err(|e| format!("Failed to encode unlock request: {}", e))
+}
+
+/// Take a dialog's stdout as the passphrase, dropping the trailing newline. Returns None for
+/// an empty answer.
+fn passphrase_from_output(stdout: Vec<u8>) -> Result<Option<Zeroizing<String>>, String> {
+    let stdout = Zeroizing::new(stdout);
+    let text = std::str::from_utf8(&stdout).map_err(|_| "Passphrase is not valid UTF-8")?;
+    let passphrase = text.trim_end_matches(['\r', '\n']);
+    Ok((!passphrase.is_empty()).then(|| Zeroizing::new(passphrase.to_string())))
+}
+
+/// The dialogs that can ask for the passphrase, in order of preference; the first one that
+/// starts is used
+fn dialog_commands(title: &str, message: &str) -> Vec<Command> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+
+        let mut command = Command::new("powershell");
+        command
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                &powershell_script(title, message),
+            ])
+            .creation_flags(0x08000000); // CREATE_NO_WINDOW
+        vec![command]
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = Command::new("osascript");
+        command.args(["-e", &applescript(title, message)]);
+        vec![command]
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        // zenity's --password dialog has no text, so use a masked entry instead
+        let mut zenity = Command::new("zenity");
+        zenity.args(["--entry", "--hide-text", "--title", title, "--text", message]);
+        let mut kdialog = Command::new("kdialog");
+        kdialog.args(["--title", title, "--password", message]);
+        vec![zenity, kdialog]
+    }
+}
+
+/// The native credential dialog; only the password field is used
+#[cfg(any(target_os = "windows", test))]
+fn powershell_script(title: &str, message: &str) -> String {
+    let escape = |s: &str| s.replace('\'', "''");
+    format!(
+        "$c = $host.UI.PromptForCredential('{}', '{}', 'repository', ''); \
+         if ($c) {{ [Console]::Out.Write($c.GetNetworkCredential().Password) }}",
+        escape(title),
+        escape(message)
+    )
+}
+
+#[cfg(any(target_os = "macos", test))]
+fn applescript(title: &str, message: &str) -> String {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    format!(
+        "text returned of (display dialog \"{}\" with title \"{}\" default answer \"\" \
+         with hidden answer buttons {{\"Cancel\", \"Unlock\"}} default button \"Unlock\")",
+        escape(message),
+        escape(title)
+    )
+}
+
+/// Show a native masked-input dialog. Blocks until the user answers; returns None if they
+/// cancelled.
+pub fn prompt(repo_name: &str, reason: &str) -> Result<Option<Zeroizing<String>>, String> {
+    let title = "Unlock repository";
+    let message = format!("Enter the passphrase for \"{}\".\n\n{}", repo_name, reason);
+
+    let mut failure = None;
+    let mut output = None;
+    for mut command in dialog_commands(title, &message) {
+        match command.output() {
+            Ok(answered) => {
+                output = Some(answered);
+                break;
+            }
+            Err(e) => failure = Some(e),
+        }
+    }
+    let Some(output) = output else {
+        let reason = failure.map(|e| e.to_string()).unwrap_or_default();
+        return Err(format!("Failed to show passphrase dialog: {}", reason));
+    };
+    if !output.status.success() {
+        // Every dialog exits non-zero when cancelled
+        return Ok(None);
+    }
+    passphrase_from_output(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    #[test]
+    fn every_linux_dialog_shows_the_message_and_masks_the_input() {
+        let commands = dialog_commands("Unlock repository", "Enter the passphrase for \"a\".");
+        let args = |command: &Command| -> Vec<String> {
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        let programs: Vec<_> = commands
+            .iter()
+            .map(|c| c.get_program().to_owned())
+            .collect();
+        assert_eq!(programs, ["zenity", "kdialog"]);
+        assert_eq!(
+            args(&commands[0]),
+            [
+                "--entry",
+                "--hide-text",
+                "--title",
+                "Unlock repository",
+                "--text",
+                "Enter the passphrase for \"a\"."
+            ]
+        );
+        assert_eq!(
+            args(&commands[1]),
+            [
+                "--title",
+                "Unlock repository",
+                "--password",
+                "Enter the passphrase for \"a\"."
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_in_names_are_escaped_for_each_script() {
+        let script = powershell_script("Unlock", "Repo 'home'");
+        assert!(
+            script.contains("PromptForCredential('Unlock', 'Repo ''home''',"),
+            "{}",
+            script
+        );
+
+        let script = applescript("Unlock", "Repo \"home\" at C:\\x");
+        assert!(
+            script.contains(r#"display dialog "Repo \"home\" at C:\\x" with title "Unlock""#),
+            "{}",
+            script
+        );
+    }
+
+    #[test]
+    fn dialog_output_becomes_the_passphrase() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"secret\n", Some("secret")),
+            (b"secret\r\n", Some("secret")),
+            (b" spaced \n", Some(" spaced ")),
+            (b"\n", None),
+            (b"", None),
+        ];
+        for (stdout, expected) in cases {
+            let passphrase = passphrase_from_output(stdout.to_vec()).unwrap();
+            assert_eq!(passphrase.as_deref().map(String::as_str), *expected);
+        }
+        assert!(passphrase_from_output(vec![0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn unlock_body_is_json_and_shared_rather_than_copied() {
+        let body = unlock_body("p\"ss").unwrap();
+        let decoded: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(decoded, serde_json::json!({ "passphrase": "p\"ss" }));
+        // Each attempt sends a handle to the same wiped-on-drop buffer
+        let retry = body.clone();
+        assert_eq!(retry.as_ptr(), body.as_ptr());
+    }
+}
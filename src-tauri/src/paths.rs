@@ -0,0 +1,330 @@
+//! Application directories, resolved and validated once at startup.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+/// Which directory a path error is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathKind {
+    Resource,
+    Config,
+    Data,
+    Log,
+    Cache,
+}
+
+impl fmt::Display for PathKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PathKind::Resource => "resource",
+            PathKind::Config => "config",
+            PathKind::Data => "data",
+            PathKind::Log => "log",
+            PathKind::Cache => "cache",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PathError {
+    #[error("Failed to resolve the {kind} directory: {reason}")]
+    Unresolved { kind: PathKind, reason: String },
+    #[error("The {kind} directory {path} is not usable: {reason}")]
+    Unusable {
+        kind: PathKind,
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+/// Every directory the desktop app reads or writes
+#[derive(Debug, Clone, Serialize)]
+pub struct Paths {
+    /// Bundled resources; the sidecar's working directory
+    pub resource_dir: PathBuf,
+    pub config_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    /// Bundled helper executables (e.g. the Windows Service binary)
+    pub binaries_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolve all directories through the Tauri path resolver and make sure they're usable.
+    /// A named profile gets its own config and data directories (see `profile`).
+    pub fn resolve(app: &tauri::AppHandle) -> Result<Self, PathError> {
+        let resolver = app.path();
+        let unresolved = |kind| {
+            move |e: tauri::Error| PathError::Unresolved {
+                kind,
+                reason: e.to_string(),
+            }
+        };
+
+        let resource_dir = resolver
+            .resource_dir()
+            .map_err(unresolved(PathKind::Resource))?;
+        let paths = Self {
+            binaries_dir: resource_dir.join("binaries"),
+            resource_dir,
+            config_dir: crate::profile::scope(
+                resolver
+                    .app_config_dir()
+                    .map_err(unresolved(PathKind::Config))?,
+            ),
+            data_dir: crate::profile::scope(
+                resolver
+                    .app_data_dir()
+                    .map_err(unresolved(PathKind::Data))?,
+            ),
+            log_dir: resolver.app_log_dir().map_err(unresolved(PathKind::Log))?,
+            cache_dir: resolver
+                .app_cache_dir()
+                .map_err(unresolved(PathKind::Cache))?,
+        };
+
+        paths.validate()?;
+        Ok(paths)
+    }
+
+    /// Check that bundled directories exist and that writable ones exist or can be created
+    pub fn validate(&self) -> Result<(), PathError> {
+        ensure_exists(PathKind::Resource, &self.resource_dir)?;
+        ensure_writable(PathKind::Config, &self.config_dir)?;
+        ensure_writable(PathKind::Data, &self.data_dir)?;
+        ensure_writable(PathKind::Log, &self.log_dir)?;
+        ensure_writable(PathKind::Cache, &self.cache_dir)?;
+        Ok(())
+    }
+}
+
+fn ensure_exists(kind: PathKind, path: &Path) -> Result<(), PathError> {
+    if path.is_dir() {
+        Ok(())
+    } else {
+        Err(PathError::Unusable {
+            kind,
+            path: path.to_path_buf(),
+            reason: "directory does not exist".to_string(),
+        })
+    }
+}
+
+/// Create the directory if needed and prove we can write a file into it
+pub fn ensure_writable(kind: PathKind, path: &Path) -> Result<(), PathError> {
+    let unusable = |reason: String| PathError::Unusable {
+        kind,
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    std::fs::create_dir_all(path).map_err(|e| unusable(format!("cannot create: {}", e)))?;
+    if let Some(e) = crate::faults::disk_full(path) {
+        return Err(unusable(format!("not writable: {}", e)));
+    }
+
+    let probe = path.join(format!(".write-test-{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(|e| unusable(format!("not writable: {}", e)))?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// Environment variable the server takes its data directory from
+pub const DATA_DIR_ENV: &str = "ZEROBYTE_DATA_DIR";
+
+/// The sidecar's own data directory (where its database and repository passwords live):
+/// [`DATA_DIR_ENV`] in our environment, else `configured` (`data_dir` in `zerobyte.toml`),
+/// else the server's default
+pub fn backend_data_dir(configured: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = configured {
+        return Some(dir.to_path_buf());
+    }
+    default_backend_data_dir()
+}
+
+/// The configured data directory to hand the sidecar in [`DATA_DIR_ENV`]; None when the server
+/// should use its default, or when our environment sets one the sidecar inherits anyway
+pub fn sidecar_data_dir(configured: Option<&Path>) -> Option<PathBuf> {
+    if std::env::var_os(DATA_DIR_ENV).is_some() {
+        return None;
+    }
+    configured.map(Path::to_path_buf)
+}
+
+/// Where the server keeps its data when nothing says otherwise, mirroring its
+/// `getZerobytePath()` in desktop mode
+pub fn default_backend_data_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    {
+        std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("C3i Backup ONE"))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join("Library/Application Support/C3i Backup ONE"))
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        Some(PathBuf::from("/var/lib/c3i-backup-one"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("paths-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn paths_under(root: &Path) -> Paths {
+        Paths {
+            resource_dir: root.join("resources"),
+            config_dir: root.join("config"),
+            data_dir: root.join("data"),
+            log_dir: root.join("logs"),
+            cache_dir: root.join("cache"),
+            binaries_dir: root.join("resources").join("binaries"),
+        }
+    }
+
+    fn unusable_kind(result: Result<(), PathError>) -> (PathKind, String) {
+        match result {
+            Err(PathError::Unusable { kind, reason, .. }) => (kind, reason),
+            other => panic!("expected an unusable directory, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_creates_the_writable_directories() {
+        let root = temp_root("create");
+        let paths = paths_under(&root);
+        std::fs::create_dir_all(&paths.resource_dir).unwrap();
+
+        let result = paths.validate();
+        let created = [
+            &paths.config_dir,
+            &paths.data_dir,
+            &paths.log_dir,
+            &paths.cache_dir,
+        ]
+        .map(|dir| dir.is_dir());
+        let leftovers = std::fs::read_dir(&paths.config_dir).unwrap().count();
+        let _ = std::fs::remove_dir_all(&root);
+
+        result.unwrap();
+        assert_eq!(created, [true; 4]);
+        // The write probe cleans up after itself
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn missing_resource_dir_is_not_created() {
+        let root = temp_root("resources");
+        let paths = paths_under(&root);
+
+        let result = paths.validate();
+        let created = paths.resource_dir.exists();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let (kind, reason) = unusable_kind(result);
+        assert_eq!(kind, PathKind::Resource);
+        assert_eq!(reason, "directory does not exist");
+        assert!(!created);
+    }
+
+    #[test]
+    fn config_dir_blocked_by_a_file_cannot_be_created() {
+        let root = temp_root("blocked");
+        let paths = paths_under(&root);
+        std::fs::create_dir_all(&paths.resource_dir).unwrap();
+        std::fs::write(&paths.config_dir, b"").unwrap();
+
+        let result = paths.validate();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let (kind, reason) = unusable_kind(result);
+        assert_eq!(kind, PathKind::Config);
+        assert!(reason.starts_with("cannot create"), "{}", reason);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_config_dir_is_not_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = temp_root("read-only");
+        let config = root.join("config");
+        std::fs::create_dir_all(&config).unwrap();
+        std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Root ignores permission bits, so find out whether the directory really is read-only
+        let probe = config.join("probe");
+        let enforced = std::fs::write(&probe, b"").is_err();
+
+        let result = ensure_writable(PathKind::Config, &config);
+        std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        if enforced {
+            let (kind, reason) = unusable_kind(result);
+            assert_eq!(kind, PathKind::Config);
+            assert!(reason.starts_with("not writable"), "{}", reason);
+        } else {
+            result.unwrap();
+        }
+    }
+
+    #[test]
+    fn errors_name_the_directory() {
+        let error = PathError::Unusable {
+            kind: PathKind::Cache,
+            path: PathBuf::from("/x/cache"),
+            reason: "directory does not exist".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "The cache directory /x/cache is not usable: directory does not exist"
+        );
+        let error = PathError::Unresolved {
+            kind: PathKind::Log,
+            reason: "no home".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Failed to resolve the log directory: no home"
+        );
+    }
+
+    #[test]
+    fn configured_data_dir_is_used_unless_the_environment_overrides_it() {
+        // Tests don't set the variable; skip rather than race a developer's shell
+        if std::env::var_os(DATA_DIR_ENV).is_some() {
+            return;
+        }
+        let configured = Path::new("/srv/zerobyte");
+        assert_eq!(
+            backend_data_dir(Some(configured)),
+            Some(configured.to_path_buf())
+        );
+        assert_eq!(backend_data_dir(None), default_backend_data_dir());
+        assert_eq!(
+            sidecar_data_dir(Some(configured)),
+            Some(configured.to_path_buf())
+        );
+        assert_eq!(sidecar_data_dir(None), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Crash-safe persistence for small JSON documents (settings and other runtime state).
+//!
+//! Writes go to a temp file that is fsynced and atomically renamed over the target, so a
+//! power cut leaves either the old or the new version on disk. The last version that parsed
+//! is kept as a `.bak` sibling and restored automatically when the main file is unreadable.
+//! Writers hold an exclusive advisory lock on a `.lock` sibling so several processes
+//! (profiles, a second instance) sharing a file never interleave.
+
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where a loaded document came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadSource {
+    /// The main file parsed normally
+    Primary,
+    /// Neither file existed; defaults were used
+    Missing,
+    /// The main file was missing or corrupt and the backup was restored
+    Backup,
+    /// The main file was corrupt and no usable backup existed; defaults were used
+    Defaults,
+}
+
+impl LoadSource {
+    /// Whether loading had to recover from a damaged file
+    pub fn recovered(&self) -> bool {
+        matches!(self, LoadSource::Backup | LoadSource::Defaults)
+    }
+}
+
+/// Path of a sibling file with `suffix` appended to the file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Path of the last-known-good backup of `path`
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+/// Write `bytes` to `path` so that readers only ever see the old or the complete new content
+pub fn atomic_write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = sibling(path, &format!(".{}.tmp", std::process::id()));
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp, path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    // Persist the rename itself
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `f` while holding the exclusive writer lock for `path`
+pub fn with_write_lock<T>(path: &Path, f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, ".lock"))?;
+    lock.lock_exclusive()?;
+    let result = f();
+    let _ = FileExt::unlock(&lock);
+    result
+}
+
+/// Serialize `value` as pretty JSON and write it atomically, keeping the previous
+/// version as a backup if it was valid
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let bytes = serde_json::to_vec_pretty(value)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    with_write_lock(path, || {
+        if let Ok(current) = fs::read(path) {
+            if serde_json::from_slice::<serde_json::Value>(&current).is_ok() {
+                atomic_write(&backup_path(path), &current)?;
+            }
+        }
+        atomic_write(path, &bytes)
+    })
+}
+
+/// Load a JSON document, falling back to its backup and then to defaults.
+/// A corrupt main file is replaced by the restored backup (or moved aside as `.corrupt`).
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> (T, LoadSource) {
+    let primary = fs::read(path);
+    if let Ok(bytes) = &primary {
+        if let Ok(value) = serde_json::from_slice(bytes) {
+            return (value, LoadSource::Primary);
+        }
+    }
+
+    let backup = backup_path(path);
+    if let Ok(bytes) = fs::read(&backup) {
+        if let Ok(value) = serde_json::from_slice(&bytes) {
+            let _ = with_write_lock(path, || atomic_write(path, &bytes));
+            return (value, LoadSource::Backup);
+        }
+    }
+
+    match primary {
+        Ok(_) => {
+            let _ = fs::rename(path, sibling(path, ".corrupt"));
+            (T::default(), LoadSource::Defaults)
+        }
+        Err(_) => (T::default(), LoadSource::Missing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Doc {
+        version: u32,
+        name: String,
+    }
+
+    fn doc(version: u32) -> Doc {
+        Doc {
+            version,
+            name: format!("v{}", version),
+        }
+    }
+
+    /// An empty directory of its own for each test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persist-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saved_documents_load_back() {
+        let path = test_dir("round-trip").join("doc.json");
+        save_json(&path, &doc(1)).unwrap();
+        assert_eq!(load_json::<Doc>(&path), (doc(1), LoadSource::Primary));
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let path = test_dir("missing").join("doc.json");
+        assert_eq!(
+            load_json::<Doc>(&path),
+            (Doc::default(), LoadSource::Missing)
+        );
+        assert!(!LoadSource::Missing.recovered());
+    }
+
+    #[test]
+    fn killed_before_the_rename_keeps_the_old_version() {
+        let path = test_dir("killed-before-rename").join("doc.json");
+        save_json(&path, &doc(1)).unwrap();
+        // The temp file was written, but the writer died before renaming it
+        let tmp = sibling(&path, ".4242.tmp");
+        fs::write(&tmp, serde_json::to_vec(&doc(2)).unwrap()).unwrap();
+
+        assert_eq!(load_json::<Doc>(&path), (doc(1), LoadSource::Primary));
+        // The next write goes through regardless of the leftover
+        save_json(&path, &doc(3)).unwrap();
+        assert_eq!(load_json::<Doc>(&path).0, doc(3));
+    }
+
+    #[test]
+    fn torn_main_file_is_restored_from_the_backup() {
+        let path = test_dir("torn").join("doc.json");
+        save_json(&path, &doc(1)).unwrap();
+        save_json(&path, &doc(2)).unwrap();
+        // A write that bypassed the rename and was cut short
+        let full = fs::read(&path).unwrap();
+        fs::write(&path, &full[..full.len() / 2]).unwrap();
+
+        let (loaded, source) = load_json::<Doc>(&path);
+        assert_eq!(loaded, doc(1));
+        assert_eq!(source, LoadSource::Backup);
+        assert!(source.recovered());
+        // The restored version is written back over the damaged one
+        assert_eq!(load_json::<Doc>(&path), (doc(1), LoadSource::Primary));
+    }
+
+    #[test]
+    fn corrupt_file_without_backup_is_moved_aside() {
+        let path = test_dir("corrupt").join("doc.json");
+        fs::write(&path, b"{ not json").unwrap();
+
+        assert_eq!(
+            load_json::<Doc>(&path),
+            (Doc::default(), LoadSource::Defaults)
+        );
+        assert!(!path.exists());
+        assert_eq!(fs::read(sibling(&path, ".corrupt")).unwrap(), b"{ not json");
+    }
+
+    #[test]
+    fn concurrent_writers_never_leave_a_torn_file() {
+        let path = test_dir("concurrent").join("doc.json");
+        let writers: Vec<_> = (0..8)
+            .map(|version| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        save_json(&path, &doc(version)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let (loaded, source) = load_json::<Doc>(&path);
+        assert_eq!(source, LoadSource::Primary);
+        assert_eq!(loaded.name, format!("v{}", loaded.version));
+        let backup: Doc = serde_json::from_slice(&fs::read(backup_path(&path)).unwrap()).unwrap();
+        assert_eq!(backup.name, format!("v{}", backup.version));
+    }
+}
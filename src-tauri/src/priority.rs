@@ -0,0 +1,145 @@
+//! Running the sidecar below normal priority, so backups don't slow the machine down.
+//!
+//! The [`BackendPriority`] saved in the settings is applied right after the sidecar spawns, and
+//! `set_backend_priority` applies it to the running sidecar as well. Low is the below-normal
+//! priority class on Windows and nice [`LOW_NICE`] elsewhere, plus the lowest best-effort I/O
+//! priority on Linux when `ionice` is installed. Processes the sidecar starts later inherit it;
+//! on Unix the ones it already runs are changed with it. Unix doesn't let an unprivileged
+//! process go back to normal, so there that takes effect from the next sidecar start.
+
+use serde::{Deserialize, Serialize};
+
+/// How much of the machine the sidecar may take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendPriority {
+    #[default]
+    Normal,
+    Low,
+}
+
+/// Nice value of a low-priority sidecar
+pub const LOW_NICE: i32 = 10;
+
+/// Run `pid` (and on Unix, the processes it started) at `priority`
+pub fn apply(pid: u32, priority: BackendPriority) -> Result<(), String> {
+    platform::apply(pid, priority)
+}
+
+/// The priority `pid` runs at; None when it can't be read
+pub fn current(pid: u32) -> Option<BackendPriority> {
+    platform::current(pid)
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    use super::{BackendPriority, LOW_NICE};
+    use std::process::Command;
+
+    pub fn apply(pid: u32, priority: BackendPriority) -> Result<(), String> {
+        let mut pids = vec![pid];
+        pids.extend(crate::proctree::descendants_of(pid));
+        let pids: Vec<String> = pids.iter().map(|pid| pid.to_string()).collect();
+        let nice = match priority {
+            BackendPriority::Normal => 0,
+            BackendPriority::Low => LOW_NICE,
+        };
+        // The positional form sets an absolute value with both util-linux and BSD renice
+        let output = Command::new("renice")
+            .arg(nice.to_string())
+            .arg("-p")
+            .args(&pids)
+            .output()
+            .map_err(|e| format!("Failed to run renice: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "renice failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        #[cfg(target_os = "linux")]
+        set_io_priority(&pids, priority);
+        Ok(())
+    }
+
+    /// Best effort: ionice isn't installed everywhere, and CPU priority matters most
+    #[cfg(target_os = "linux")]
+    fn set_io_priority(pids: &[String], priority: BackendPriority) {
+        let class = match priority {
+            BackendPriority::Normal => ["-c", "0"].as_slice(),
+            BackendPriority::Low => ["-c", "2", "-n", "7"].as_slice(),
+        };
+        match Command::new("ionice")
+            .args(class)
+            .arg("-p")
+            .args(pids)
+            .output()
+        {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => tracing::debug!(
+                "ionice failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => tracing::debug!("Not changing I/O priority, no ionice: {}", e),
+        }
+    }
+
+    pub fn current(pid: u32) -> Option<BackendPriority> {
+        let output = Command::new("ps")
+            .args(["-o", "nice=", "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        let nice: i32 = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()?;
+        Some(if nice > 0 {
+            BackendPriority::Low
+        } else {
+            BackendPriority::Normal
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::BackendPriority;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        GetPriorityClass, OpenProcess, SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS,
+        IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, PROCESS_QUERY_LIMITED_INFORMATION,
+        PROCESS_SET_INFORMATION,
+    };
+
+    pub fn apply(pid: u32, priority: BackendPriority) -> Result<(), String> {
+        let class = match priority {
+            BackendPriority::Normal => NORMAL_PRIORITY_CLASS,
+            BackendPriority::Low => BELOW_NORMAL_PRIORITY_CLASS,
+        };
+        // SAFETY: the handle is only used for this call and closed right after
+        unsafe {
+            let handle = OpenProcess(PROCESS_SET_INFORMATION, false, pid)
+                .map_err(|e| format!("Failed to open pid {}: {}", pid, e))?;
+            let result = SetPriorityClass(handle, class);
+            let _ = CloseHandle(handle);
+            result.map_err(|e| format!("Failed to change the priority of pid {}: {}", pid, e))
+        }
+    }
+
+    pub fn current(pid: u32) -> Option<BackendPriority> {
+        // SAFETY: the handle is only used for this call and closed right after
+        let class = unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let class = GetPriorityClass(handle);
+            let _ = CloseHandle(handle);
+            class
+        };
+        if class == 0 {
+            None
+        } else if class == BELOW_NORMAL_PRIORITY_CLASS.0 || class == IDLE_PRIORITY_CLASS.0 {
+            Some(BackendPriority::Low)
+        } else {
+            Some(BackendPriority::Normal)
+        }
+    }
+}
@@ -0,0 +1,752 @@
+//! Stopping the sidecar together with every process it started.
+//!
+//! The server starts helpers of its own (the backup engine among them), and killing only the
+//! direct child leaves those holding repository locks and, sometimes, the backend port. On
+//! Windows the sidecar is put in a Job Object right after spawning; the job kills everything in
+//! it when terminated or when its last handle closes, so a crash of the app cleans up too.
+//! `taskkill /T` is the fallback when the job couldn't be set up. Elsewhere [`spawn`] starts
+//! the sidecar as the leader of its own process group, and the whole group is signalled: that
+//! reaches helpers which were reparented to init, and ones forked while the kill is under way.
+//! Trees whose root doesn't lead a group are found from a process snapshot taken just before
+//! the kill and signalled one by one.
+//!
+//! [`port_holders`] checks afterwards that nothing still listens on the backend port, so
+//! survivors show up in the log with their PIDs.
+
+use std::net::TcpListener;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use tauri_plugin_shell::process::CommandEvent;
+use tokio::sync::mpsc::Receiver;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+/// How long processes get between the polite and the forced signal
+pub const KILL_GRACE: Duration = Duration::from_secs(3);
+
+/// A spawned process and everything it starts
+pub struct ProcessTree {
+    pid: u32,
+    /// Whether the root leads its own process group, which its descendants stay in
+    #[cfg(not(target_os = "windows"))]
+    group: bool,
+    #[cfg(target_os = "windows")]
+    job: Option<job::Job>,
+}
+
+/// What a tree kill did
+#[derive(Debug, Clone, Default)]
+pub struct KillReport {
+    /// Processes signalled (on Windows, only known when the fallback ran)
+    pub signalled: Vec<u32>,
+    /// Processes of the tree still alive afterwards
+    pub survivors: Vec<u32>,
+}
+
+impl ProcessTree {
+    /// Track the tree rooted at `pid`; call right after spawning so helpers land in it too
+    pub fn adopt(pid: u32) -> Self {
+        #[cfg(target_os = "windows")]
+        {
+            let job = match job::Job::for_process(pid) {
+                Ok(job) => Some(job),
+                Err(e) => {
+                    tracing::warn!(
+                        "Couldn't put pid {} in a job object, will fall back to taskkill: {}",
+                        pid,
+                        e
+                    );
+                    None
+                }
+            };
+            ProcessTree { pid, job }
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            ProcessTree { pid, group: false }
+        }
+    }
+
+    /// Track the tree of a process started by [`spawn`], signalling its process group. A
+    /// process that doesn't lead its own group (one started by an older version of the app)
+    /// is tracked like [`ProcessTree::adopt`] does.
+    pub fn adopt_group(pid: u32) -> Self {
+        #[cfg(target_os = "windows")]
+        {
+            Self::adopt(pid)
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let group = snapshot()
+                .iter()
+                .any(|process| process.pid == pid && process.pgid == pid);
+            ProcessTree { pid, group }
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Kill the root and all its descendants. Blocks for up to [`KILL_GRACE`].
+    pub fn kill(&self) -> KillReport {
+        #[cfg(target_os = "windows")]
+        {
+            if let Some(job) = &self.job {
+                match job.terminate() {
+                    Ok(()) => return KillReport::default(),
+                    Err(e) => tracing::warn!("Terminating the job object failed: {}", e),
+                }
+            }
+            let mut command = Command::new("taskkill");
+            command
+                .args(["/PID", &self.pid.to_string(), "/T", "/F"])
+                .creation_flags(CREATE_NO_WINDOW);
+            match command.output() {
+                Ok(output) if !output.status.success() => tracing::warn!(
+                    "taskkill /T for pid {} failed: {}",
+                    self.pid,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => tracing::warn!("Failed to run taskkill: {}", e),
+                Ok(_) => {}
+            }
+            KillReport {
+                signalled: vec![self.pid],
+                survivors: Vec::new(),
+            }
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            let tree = if self.group {
+                group_members(self.pid)
+            } else {
+                let mut tree = vec![self.pid];
+                tree.extend(descendants(self.pid, &snapshot()));
+                tree
+            };
+            self.signal(&tree, "TERM");
+
+            let started = Instant::now();
+            let mut alive = self.alive(&tree);
+            while !alive.is_empty() && started.elapsed() < KILL_GRACE {
+                std::thread::sleep(Duration::from_millis(100));
+                alive = self.alive(&alive);
+            }
+            if !alive.is_empty() {
+                self.signal(&alive, "KILL");
+                std::thread::sleep(Duration::from_millis(100));
+                alive = self.alive(&alive);
+            }
+            KillReport {
+                signalled: tree,
+                survivors: alive,
+            }
+        }
+    }
+
+    /// Kill whatever is left of the tree after the root has already exited. The job object
+    /// or the process group can still reach those processes; a snapshot walk can't, as they
+    /// were reparented.
+    pub fn kill_leftovers(&self) {
+        #[cfg(target_os = "windows")]
+        if let Some(job) = &self.job {
+            if let Err(e) = job.terminate() {
+                tracing::warn!("Terminating the job object failed: {}", e);
+            }
+        }
+
+        // Like terminating the job, without a grace period: this runs on async tasks too
+        #[cfg(not(target_os = "windows"))]
+        if self.group && !group_members(self.pid).is_empty() {
+            self.signal(&[], "KILL");
+        }
+    }
+
+    /// Send `signal` to `pids`, or to the whole group when the root leads one
+    #[cfg(not(target_os = "windows"))]
+    fn signal(&self, pids: &[u32], signal: &str) {
+        if self.group {
+            let _ = Command::new("kill")
+                .args([
+                    format!("-{}", signal),
+                    "--".to_string(),
+                    format!("-{}", self.pid),
+                ])
+                .output();
+        } else {
+            self::signal(pids, signal);
+        }
+    }
+
+    /// Which of `pids` are still running; with a group, every live member of it, including
+    /// processes forked after `pids` was taken
+    #[cfg(not(target_os = "windows"))]
+    fn alive(&self, pids: &[u32]) -> Vec<u32> {
+        if self.group {
+            group_members(self.pid)
+        } else {
+            pids.iter().copied().filter(|pid| is_alive(*pid)).collect()
+        }
+    }
+}
+
+/// A sidecar started by [`spawn`]
+#[cfg(target_os = "windows")]
+pub type Child = tauri_plugin_shell::process::CommandChild;
+
+/// A sidecar started by [`spawn`]
+#[cfg(not(target_os = "windows"))]
+pub struct Child {
+    pid: u32,
+    /// Set once the process has been reaped, after which its PID may belong to another one
+    reaped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    // Held so the server's stdin stays open, as it does under the shell plugin
+    _stdin: Option<std::process::ChildStdin>,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Child {
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Kill the process itself; [`ProcessTree::kill`] takes care of the rest of the tree
+    pub fn kill(self) -> std::io::Result<()> {
+        if !self.reaped.load(std::sync::atomic::Ordering::SeqCst) {
+            signal(&[self.pid], "KILL");
+        }
+        Ok(())
+    }
+}
+
+/// Spawn `command` like [`tauri_plugin_shell::process::Command::spawn`] does, with its output
+/// delivered as the same events. Outside Windows the process leads a new process group, so
+/// [`ProcessTree::adopt_group`] can reach everything it starts.
+#[cfg(target_os = "windows")]
+pub fn spawn(
+    command: tauri_plugin_shell::process::Command,
+) -> Result<(Receiver<CommandEvent>, Child), tauri_plugin_shell::Error> {
+    command.spawn()
+}
+
+/// Spawn `command` like [`tauri_plugin_shell::process::Command::spawn`] does, with its output
+/// delivered as the same events. Outside Windows the process leads a new process group, so
+/// [`ProcessTree::adopt_group`] can reach everything it starts.
+#[cfg(not(target_os = "windows"))]
+pub fn spawn(
+    command: tauri_plugin_shell::process::Command,
+) -> Result<(Receiver<CommandEvent>, Child), tauri_plugin_shell::Error> {
+    Ok(spawn_grouped(command.into())?)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn spawn_grouped(
+    mut command: std::process::Command,
+) -> std::io::Result<(Receiver<CommandEvent>, Child)> {
+    use std::io::{BufRead, BufReader, Read};
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::process::Stdio;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tauri_plugin_shell::process::TerminatedPayload;
+    use tokio::sync::mpsc::Sender;
+
+    fn read_lines(
+        pipe: Option<impl Read + Send + 'static>,
+        tx: Sender<CommandEvent>,
+        event: fn(Vec<u8>) -> CommandEvent,
+    ) -> Option<std::thread::JoinHandle<()>> {
+        let mut reader = BufReader::new(pipe?);
+        Some(std::thread::spawn(move || loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    if tx.blocking_send(event(line)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.blocking_send(CommandEvent::Error(e.to_string()));
+                    break;
+                }
+            }
+        }))
+    }
+
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0);
+    let mut child = command.spawn()?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    let readers = [
+        read_lines(child.stdout.take(), tx.clone(), CommandEvent::Stdout),
+        read_lines(child.stderr.take(), tx.clone(), CommandEvent::Stderr),
+    ];
+    let pid = child.id();
+    let stdin = child.stdin.take();
+    let reaped = Arc::new(AtomicBool::new(false));
+    let waiter_reaped = reaped.clone();
+    std::thread::spawn(move || {
+        let status = child.wait();
+        waiter_reaped.store(true, Ordering::SeqCst);
+        // Output comes before the exit, as with the shell plugin
+        for reader in readers.into_iter().flatten() {
+            let _ = reader.join();
+        }
+        let event = match status {
+            Ok(status) => CommandEvent::Terminated(TerminatedPayload {
+                code: status.code(),
+                signal: status.signal(),
+            }),
+            Err(e) => CommandEvent::Error(e.to_string()),
+        };
+        let _ = tx.blocking_send(event);
+    });
+
+    Ok((
+        rx,
+        Child {
+            pid,
+            reaped,
+            _stdin: stdin,
+        },
+    ))
+}
+
+/// PIDs still listening on `port` on the loopback interface, waiting up to `wait` for it to
+/// be released. Empty means the port is free.
+pub fn port_holders(port: u16, wait: Duration) -> Vec<u32> {
+    let started = Instant::now();
+    loop {
+        if TcpListener::bind(("127.0.0.1", port)).is_ok() {
+            return Vec::new();
+        }
+        if started.elapsed() >= wait {
+            return listening_pids(port);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn listening_pids(port: u16) -> Vec<u32> {
+    let output = Command::new("netstat")
+        .args(["-ano", "-p", "TCP"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output();
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    let suffix = format!(":{}", port);
+    let mut pids: Vec<u32> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // "  TCP    127.0.0.1:4096    0.0.0.0:0    LISTENING    1234"
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [_, local, _, state, pid] if local.ends_with(&suffix) && *state == "LISTENING" => {
+                    pid.parse().ok()
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+#[cfg(not(target_os = "windows"))]
+fn listening_pids(port: u16) -> Vec<u32> {
+    let output = Command::new("lsof")
+        .args(["-nP", "-t", &format!("-iTCP:{}", port), "-sTCP:LISTEN"])
+        .output();
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+/// A row of the process table
+#[cfg(not(target_os = "windows"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Process {
+    pid: u32,
+    ppid: u32,
+    /// Process group
+    pgid: u32,
+    /// Exited but not yet reaped by its parent
+    zombie: bool,
+}
+
+/// Every process
+#[cfg(not(target_os = "windows"))]
+fn snapshot() -> Vec<Process> {
+    let Ok(output) = Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid=,pgid=,stat="])
+        .output()
+    else {
+        return Vec::new();
+    };
+    parse_snapshot(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Rows of `ps -o pid=,ppid=,pgid=,stat=` output
+#[cfg(not(target_os = "windows"))]
+fn parse_snapshot(output: &str) -> Vec<Process> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Process {
+                pid: fields.next()?.parse().ok()?,
+                ppid: fields.next()?.parse().ok()?,
+                pgid: fields.next()?.parse().ok()?,
+                zombie: fields.next().is_some_and(|stat| stat.starts_with('Z')),
+            })
+        })
+        .collect()
+}
+
+/// Every process `pid` started, and the ones those started, parents before their children
+#[cfg(not(target_os = "windows"))]
+pub fn descendants_of(pid: u32) -> Vec<u32> {
+    descendants(pid, &snapshot())
+}
+
+/// Every process below `root` in `processes`, parents before their children
+#[cfg(not(target_os = "windows"))]
+fn descendants(root: u32, processes: &[Process]) -> Vec<u32> {
+    let mut found = Vec::new();
+    let mut frontier = vec![root];
+    while let Some(parent) = frontier.pop() {
+        for process in processes.iter().filter(|process| process.ppid == parent) {
+            if process.pid != root && !found.contains(&process.pid) {
+                found.push(process.pid);
+                frontier.push(process.pid);
+            }
+        }
+    }
+    found
+}
+
+/// Running members of process group `pgid`
+#[cfg(not(target_os = "windows"))]
+fn group_members(pgid: u32) -> Vec<u32> {
+    snapshot()
+        .into_iter()
+        .filter(|process| process.pgid == pgid && !process.zombie)
+        .map(|process| process.pid)
+        .collect()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn signal(pids: &[u32], signal: &str) {
+    if pids.is_empty() {
+        return;
+    }
+    let _ = Command::new("kill")
+        .arg(format!("-{}", signal))
+        .args(pids.iter().map(|pid| pid.to_string()))
+        .output();
+}
+
+/// Whether a process with `pid` is still running
+#[cfg(not(target_os = "windows"))]
+pub fn is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Whether a process with `pid` is still running
+#[cfg(target_os = "windows")]
+pub fn is_alive(pid: u32) -> bool {
+    use windows::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+    use windows::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    unsafe {
+        let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+            return false;
+        };
+        let mut code = 0u32;
+        let running =
+            GetExitCodeProcess(process, &mut code).is_ok() && code == STILL_ACTIVE.0 as u32;
+        let _ = CloseHandle(process);
+        running
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod job {
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows::Win32::System::Threading::{OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE};
+
+    /// A kill-on-close job object holding one process tree
+    pub struct Job(HANDLE);
+
+    // The handle is only used through thread-safe kernel calls
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        pub fn for_process(pid: u32) -> windows::core::Result<Self> {
+            unsafe {
+                let job = Job(CreateJobObjectW(None, PCWSTR::null())?);
+
+                let mut limits = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+                limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &limits as *const _ as *const std::ffi::c_void,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                )?;
+
+                let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, false, pid)?;
+                let assigned = AssignProcessToJobObject(job.0, process);
+                let _ = CloseHandle(process);
+                assigned?;
+                Ok(job)
+            }
+        }
+
+        pub fn terminate(&self) -> windows::core::Result<()> {
+            unsafe { TerminateJobObject(self.0, 1) }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, ppid: u32, pgid: u32) -> Process {
+        Process {
+            pid,
+            ppid,
+            pgid,
+            zombie: false,
+        }
+    }
+
+    /// Start `script` in a process group of its own, reaped by a thread like the sidecar is
+    fn start_group(script: &str) -> (u32, std::thread::JoinHandle<()>) {
+        use std::os::unix::process::CommandExt;
+
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        let reaper = std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+        (pid, reaper)
+    }
+
+    /// Wait until group `pgid` has at least `count` running members
+    fn wait_for_members(pgid: u32, count: usize) -> Vec<u32> {
+        let started = Instant::now();
+        loop {
+            let members = group_members(pgid);
+            if members.len() >= count || started.elapsed() > Duration::from_secs(5) {
+                return members;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn snapshot_rows_are_parsed() {
+        let output = "    1     0     1 Ss\n  200     1   200 S\n  201   200   200 Z+\n garbage\n";
+        let mut zombie = process(201, 200, 200);
+        zombie.zombie = true;
+        assert_eq!(
+            parse_snapshot(output),
+            vec![process(1, 0, 1), process(200, 1, 200), zombie]
+        );
+    }
+
+    #[test]
+    fn descendants_come_parents_first_and_skip_other_trees() {
+        let processes = [
+            process(10, 1, 10),
+            process(11, 10, 10),
+            process(12, 11, 10),
+            process(13, 10, 10),
+            process(20, 1, 20),
+            process(21, 20, 20),
+        ];
+        let found = descendants(10, &processes);
+        assert_eq!(found.len(), 3);
+        for pid in [11, 12, 13] {
+            assert!(found.contains(&pid), "{:?}", found);
+        }
+        let position = |pid| found.iter().position(|p| *p == pid).unwrap();
+        assert!(position(11) < position(12));
+        assert!(descendants(21, &processes).is_empty());
+    }
+
+    #[test]
+    fn group_kill_reaches_reparented_and_newly_forked_processes() {
+        // The subshell exits at once, leaving its sleep reparented to init; the loop keeps
+        // forking while the kill is under way
+        let (pid, reaper) = start_group("(sleep 30 &); while :; do sleep 30 & sleep 0.05; done");
+        let members = wait_for_members(pid, 4);
+        assert!(members.len() >= 4, "{:?}", members);
+        let reparented: Vec<u32> = snapshot()
+            .into_iter()
+            .filter(|process| process.pgid == pid && process.ppid != pid && process.pid != pid)
+            .map(|process| process.pid)
+            .collect();
+        assert!(!reparented.is_empty());
+        // A walk from the root can't find those
+        let walked = descendants_of(pid);
+        assert!(reparented.iter().all(|orphan| !walked.contains(orphan)));
+
+        let tree = ProcessTree::adopt_group(pid);
+        assert!(tree.group);
+        let report = tree.kill();
+        assert!(report.survivors.is_empty(), "{:?}", report.survivors);
+        reaper.join().unwrap();
+        assert!(group_members(pid).is_empty());
+    }
+
+    /// Poll `condition` for up to five seconds
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        condition()
+    }
+
+    #[test]
+    fn leftovers_are_killed_after_the_leader_exits() {
+        // The leader leaves a sleep behind and exits once its stdin closes
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "(sleep 30 </dev/null >/dev/null 2>&1 &); read _; exit 0",
+        ]);
+        let (_rx, child) = spawn_grouped(command).unwrap();
+        let pid = child.pid();
+        assert!(wait_for_members(pid, 2).len() >= 2);
+        let tree = ProcessTree::adopt_group(pid);
+        assert!(tree.group);
+
+        drop(child);
+        assert!(eventually(|| snapshot()
+            .iter()
+            .all(|process| process.pid != pid)));
+        let leftovers = group_members(pid);
+        assert!(!leftovers.is_empty());
+        assert!(!leftovers.contains(&pid));
+
+        tree.kill_leftovers();
+        assert!(
+            eventually(|| group_members(pid).is_empty()),
+            "{:?}",
+            group_members(pid)
+        );
+    }
+
+    #[test]
+    fn processes_outside_a_group_of_their_own_are_walked() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let tree = ProcessTree::adopt_group(child.id());
+        assert!(!tree.group);
+        let report = tree.kill();
+        assert_eq!(report.signalled, vec![child.id()]);
+        child.wait().unwrap();
+    }
+
+    #[tokio::test]
+    async fn spawned_output_arrives_before_the_exit() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2; exit 3"]);
+        let (mut rx, child) = spawn_grouped(command).unwrap();
+        assert!(child.pid() > 0);
+
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        let (last, output) = events.split_last().unwrap();
+        let CommandEvent::Terminated(payload) = last else {
+            panic!("expected the exit last, got {:?}", last);
+        };
+        assert_eq!(payload.code, Some(3));
+        let mut lines: Vec<String> = output
+            .iter()
+            .map(|event| match event {
+                CommandEvent::Stdout(line) => format!("stdout {}", String::from_utf8_lossy(line)),
+                CommandEvent::Stderr(line) => format!("stderr {}", String::from_utf8_lossy(line)),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        lines.sort();
+        assert_eq!(lines, ["stderr err\n", "stdout out\n"]);
+    }
+
+    #[tokio::test]
+    async fn spawned_process_leads_its_own_group() {
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let (mut rx, child) = spawn_grouped(command).unwrap();
+        let pid = child.pid();
+        let tree = ProcessTree::adopt_group(pid);
+        assert!(tree.group);
+
+        let report = tree.kill();
+        assert!(report.survivors.is_empty(), "{:?}", report.survivors);
+        let mut terminated = None;
+        while let Some(event) = rx.recv().await {
+            if let CommandEvent::Terminated(payload) = event {
+                terminated = Some(payload.signal);
+            }
+        }
+        assert_eq!(terminated, Some(Some(15)));
+        // Already reaped, so there's nothing left to signal
+        child.kill().unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+//! Named profiles: isolated desktop instances for separate sets of backups.
+//!
+//! `--profile <name>` starts an instance with its own `zerobyte.toml` and data directory
+//! (under `profiles/<name>` in the app's config and data directories), its own sidecar data
+//! directory and a sidecar port picked by the OS, so it runs alongside the default profile and
+//! other profiles. The Windows Service belongs to the default profile; other profiles never
+//! look for it. The single-instance plugin is scoped to the app identifier, so it only covers
+//! the default profile; a named profile holds [`LOCK_FILE`] in its data directory instead.
+
+use fs2::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the profile used without `--profile`
+pub const DEFAULT: &str = "default";
+
+/// File a named profile's running instance holds locked, in the profile's data directory
+pub const LOCK_FILE: &str = "instance.lock";
+
+const MAX_NAME_LEN: usize = 32;
+
+static CURRENT: OnceLock<String> = OnceLock::new();
+static INSTANCE_LOCK: OnceLock<std::fs::File> = OnceLock::new();
+
+/// Check a profile name given with `--profile`
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!(
+            "Profile names are 1 to {} letters, digits, '-' or '_', not {:?}",
+            MAX_NAME_LEN, name
+        ));
+    }
+    Ok(())
+}
+
+/// Make `name` this process's profile; only the first call counts
+pub fn set(name: String) {
+    let _ = CURRENT.set(name);
+}
+
+pub fn current() -> &'static str {
+    CURRENT.get().map(String::as_str).unwrap_or(DEFAULT)
+}
+
+pub fn is_default() -> bool {
+    current() == DEFAULT
+}
+
+/// `dir` for the default profile, `dir/profiles/<name>` for the others
+pub fn scope(dir: PathBuf) -> PathBuf {
+    if is_default() {
+        dir
+    } else {
+        dir.join("profiles").join(current())
+    }
+}
+
+/// App name with the profile, for the window title and tray tooltip
+pub fn app_title() -> String {
+    if is_default() {
+        "C3i Backup ONE".to_string()
+    } else {
+        format!("C3i Backup ONE ({})", current())
+    }
+}
+
+/// Where a named profile's sidecar keeps its data unless configured otherwise; None for the
+/// default profile, whose sidecar uses the server's default
+pub fn backend_data_dir(desktop_data_dir: &Path) -> Option<PathBuf> {
+    (!is_default()).then(|| desktop_data_dir.join("backend"))
+}
+
+/// Lock this profile's instance for as long as the process runs. Fails if another instance
+/// of the profile holds the lock.
+pub fn lock_instance(data_dir: &Path) -> Result<(), String> {
+    let path = data_dir.join(LOCK_FILE);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    file.try_lock_exclusive()
+        .map_err(|_| format!("Profile {} is already running", current()))?;
+    let _ = INSTANCE_LOCK.set(file);
+    Ok(())
+}
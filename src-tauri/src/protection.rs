@@ -0,0 +1,778 @@
+//! "Are my backups actually protected?" answered in one checklist.
+//!
+//! The facts come from places the desktop already looks at: backend readiness and the crash
+//! history, the backend's plans and repositories, free space on the data volume, the Windows
+//! Service and the autostart entry. [`gather`] collects them into an [`AuditInput`]; each
+//! [`Rule`] in [`RULES`] turns that into a pass/warn/fail item with the id of a quick action
+//! (see `actions.rs`) that fixes it. The score weighs the items by how much they matter.
+//!
+//! The last audit is kept in [`ProtectionCache`] so the system report can include it without
+//! waiting on the backend.
+
+use crate::http::{self, HttpPolicy};
+use crate::AppState;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use tauri::Manager;
+use tracing::info;
+
+/// Backend endpoint listing the repositories
+const REPOSITORIES_PATH: &str = "/api/v1/repositories";
+
+/// Free space on the data volume below which the audit warns
+pub const LOW_SPACE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Free space on the data volume below which the audit fails
+pub const CRITICAL_SPACE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Slack on top of a plan's interval before its last run counts as overdue
+pub const OVERDUE_GRACE_SECS: u64 = 60 * 60;
+
+const DAY_MINUTES: u32 = crate::schedule::MINUTES_PER_DAY;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// One line of the checklist
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditItem {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// Quick action that fixes it; None when it passes or there's nothing to click
+    pub action: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtectionAudit {
+    /// 0-100, weighted by [`Rule::weight`]
+    pub score: u32,
+    /// The worst item status
+    pub status: CheckStatus,
+    pub items: Vec<AuditItem>,
+    /// Milliseconds since the Unix epoch
+    pub generated_at_ms: u64,
+}
+
+/// A backup plan, reduced to what the audit needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanFacts {
+    pub name: String,
+    pub enabled: bool,
+    /// Longest gap between two scheduled runs; None without a (valid) schedule
+    pub interval_minutes: Option<u32>,
+    /// Seconds since the Unix epoch
+    pub last_run_at: Option<u64>,
+    pub last_succeeded: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepositoryFacts {
+    pub name: String,
+    pub reachable: bool,
+}
+
+/// Everything the rules look at. Lists the backend couldn't provide are None.
+#[derive(Debug, Clone, Default)]
+pub struct AuditInput {
+    /// Seconds since the Unix epoch
+    pub now: u64,
+    pub backend_ready: bool,
+    pub crash_loop: bool,
+    pub plans: Option<Vec<PlanFacts>>,
+    pub repositories: Option<Vec<RepositoryFacts>>,
+    pub disk_available: Option<u64>,
+    pub service_installed: bool,
+    pub service_running: bool,
+    pub autostart: Option<bool>,
+}
+
+/// What a rule concluded
+pub struct Finding {
+    pub status: CheckStatus,
+    pub detail: String,
+    pub action: Option<&'static str>,
+}
+
+impl Finding {
+    fn pass(detail: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            action: None,
+        }
+    }
+
+    fn warn(detail: impl Into<String>, action: Option<&'static str>) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            action,
+        }
+    }
+
+    fn fail(detail: impl Into<String>, action: Option<&'static str>) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            action,
+        }
+    }
+}
+
+/// One checklist item and how to decide it
+pub struct Rule {
+    pub id: &'static str,
+    pub title: &'static str,
+    /// How much the item counts towards the score
+    pub weight: u32,
+    evaluate: fn(&AuditInput) -> Finding,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "backend_healthy",
+        title: "Backend is running",
+        weight: 3,
+        evaluate: backend_healthy,
+    },
+    Rule {
+        id: "daily_schedule",
+        title: "A plan runs at least daily",
+        weight: 3,
+        evaluate: daily_schedule,
+    },
+    Rule {
+        id: "recent_success",
+        title: "Every plan succeeded recently",
+        weight: 3,
+        evaluate: recent_success,
+    },
+    Rule {
+        id: "repositories_reachable",
+        title: "Repositories are reachable",
+        weight: 2,
+        evaluate: repositories_reachable,
+    },
+    Rule {
+        id: "free_space",
+        title: "Enough free space for the data directory",
+        weight: 1,
+        evaluate: free_space,
+    },
+    Rule {
+        id: "keeps_running",
+        title: "Backups run without the window open",
+        weight: 2,
+        evaluate: keeps_running,
+    },
+];
+
+fn backend_healthy(input: &AuditInput) -> Finding {
+    if !input.backend_ready {
+        Finding::fail("The backend is not responding", Some("backend:restart"))
+    } else if input.crash_loop {
+        Finding::warn("The backend crashed repeatedly in the last hour", Some("backend:restart"))
+    } else {
+        Finding::pass("The backend is up")
+    }
+}
+
+fn enabled_plans(plans: &[PlanFacts]) -> impl Iterator<Item = &PlanFacts> {
+    plans.iter().filter(|plan| plan.enabled)
+}
+
+fn daily_schedule(input: &AuditInput) -> Finding {
+    let Some(plans) = &input.plans else {
+        return Finding::warn("Backup plans could not be read", Some("page:backups"));
+    };
+    let daily = enabled_plans(plans)
+        .filter(|plan| plan.interval_minutes.is_some_and(|gap| gap <= DAY_MINUTES))
+        .count();
+    match daily {
+        0 => Finding::fail("No enabled plan runs at least once a day", Some("page:backups")),
+        n => Finding::pass(format!("{} plan(s) run at least once a day", n)),
+    }
+}
+
+/// Why `plan` doesn't count as recently successful, if it doesn't
+fn plan_problem(plan: &PlanFacts, now: u64) -> Option<String> {
+    let Some(last_run_at) = plan.last_run_at else {
+        return Some(format!("{} has never run", plan.name));
+    };
+    if plan.last_succeeded == Some(false) {
+        return Some(format!("{} failed its last run", plan.name));
+    }
+    let interval = plan.interval_minutes? as u64 * 60;
+    (now.saturating_sub(last_run_at) > interval + OVERDUE_GRACE_SECS)
+        .then(|| format!("{} is overdue", plan.name))
+}
+
+fn recent_success(input: &AuditInput) -> Finding {
+    let Some(plans) = &input.plans else {
+        return Finding::warn("Backup plans could not be read", Some("page:backups"));
+    };
+    let enabled: Vec<&PlanFacts> = enabled_plans(plans).collect();
+    if enabled.is_empty() {
+        return Finding::fail("No backup plan is enabled", Some("page:backups"));
+    }
+    let problems: Vec<String> = enabled
+        .iter()
+        .filter_map(|plan| plan_problem(plan, input.now))
+        .collect();
+    if problems.is_empty() {
+        Finding::pass(format!("{} plan(s) succeeded on schedule", enabled.len()))
+    } else {
+        Finding::fail(problems.join("; "), Some("page:backups"))
+    }
+}
+
+fn repositories_reachable(input: &AuditInput) -> Finding {
+    let Some(repositories) = &input.repositories else {
+        return Finding::warn("Repositories could not be read", Some("page:repositories"));
+    };
+    if repositories.is_empty() {
+        return Finding::fail("No repository is configured", Some("page:repositories"));
+    }
+    let unreachable: Vec<&str> = repositories
+        .iter()
+        .filter(|repository| !repository.reachable)
+        .map(|repository| repository.name.as_str())
+        .collect();
+    if unreachable.is_empty() {
+        Finding::pass(format!("{} repositor(y/ies) reachable", repositories.len()))
+    } else {
+        Finding::fail(
+            format!("Not reachable: {}", unreachable.join(", ")),
+            Some("page:repositories"),
+        )
+    }
+}
+
+fn free_space(input: &AuditInput) -> Finding {
+    let gib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+    match input.disk_available {
+        None => Finding::warn("Free space could not be read", None),
+        Some(bytes) if bytes < CRITICAL_SPACE_BYTES => {
+            Finding::fail(format!("Only {:.1} GiB free", gib(bytes)), None)
+        }
+        Some(bytes) if bytes < LOW_SPACE_BYTES => {
+            Finding::warn(format!("Only {:.1} GiB free", gib(bytes)), None)
+        }
+        Some(bytes) => Finding::pass(format!("{:.1} GiB free", gib(bytes))),
+    }
+}
+
+/// With the service installed it has to be running; otherwise the app has to start with the OS
+fn keeps_running(input: &AuditInput) -> Finding {
+    if input.service_installed {
+        return if input.service_running {
+            Finding::pass("The Windows Service is running")
+        } else {
+            Finding::fail("The Windows Service is installed but stopped", Some("service:start"))
+        };
+    }
+    match input.autostart {
+        Some(true) => Finding::pass("Zerobyte starts with the OS"),
+        Some(false) => Finding::warn(
+            "Zerobyte doesn't start with the OS, so backups only run while it's open",
+            Some("page:settings"),
+        ),
+        None => Finding::warn("The autostart entry could not be read", Some("page:settings")),
+    }
+}
+
+/// Run every rule against `input`
+pub fn evaluate(input: &AuditInput, generated_at_ms: u64) -> ProtectionAudit {
+    let items: Vec<AuditItem> = RULES
+        .iter()
+        .map(|rule| {
+            let finding = (rule.evaluate)(input);
+            AuditItem {
+                id: rule.id,
+                title: rule.title,
+                status: finding.status,
+                detail: finding.detail,
+                action: finding.action,
+            }
+        })
+        .collect();
+
+    // A pass counts fully, a warning half
+    let total: u32 = RULES.iter().map(|rule| rule.weight * 2).sum();
+    let earned: u32 = RULES
+        .iter()
+        .zip(&items)
+        .map(|(rule, item)| match item.status {
+            CheckStatus::Pass => rule.weight * 2,
+            CheckStatus::Warn => rule.weight,
+            CheckStatus::Fail => 0,
+        })
+        .sum();
+
+    ProtectionAudit {
+        score: (earned * 100).checked_div(total).unwrap_or(100),
+        status: items
+            .iter()
+            .map(|item| item.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass),
+        items,
+        generated_at_ms,
+    }
+}
+
+/// A backup plan as the backend lists it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct BackendPlan {
+    name: String,
+    enabled: Option<bool>,
+    cron_expression: Option<String>,
+    /// Milliseconds since the Unix epoch or an RFC 3339 string
+    last_backup_at: Option<serde_json::Value>,
+    /// "success", "error", ...
+    last_backup_status: Option<String>,
+}
+
+/// A repository as the backend lists it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct BackendRepository {
+    name: String,
+    /// "healthy", "error", ...
+    status: Option<String>,
+}
+
+/// Seconds since the Unix epoch from a backend timestamp
+fn timestamp_secs(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(ms) => ms.as_u64().map(|ms| ms / 1000),
+        serde_json::Value::String(text) => chrono::DateTime::parse_from_rfc3339(text)
+            .ok()
+            .and_then(|at| u64::try_from(at.timestamp()).ok()),
+        _ => None,
+    }
+}
+
+impl From<BackendPlan> for PlanFacts {
+    fn from(plan: BackendPlan) -> Self {
+        let interval_minutes = plan
+            .cron_expression
+            .as_deref()
+            .and_then(|expr| crate::schedule::CronSchedule::parse(expr).ok())
+            .and_then(|cron| cron.longest_gap());
+        PlanFacts {
+            name: plan.name,
+            enabled: plan.enabled.unwrap_or(true),
+            interval_minutes,
+            last_run_at: plan.last_backup_at.as_ref().and_then(timestamp_secs),
+            last_succeeded: plan
+                .last_backup_status
+                .as_deref()
+                .map(|status| status != "error"),
+        }
+    }
+}
+
+async fn fetch_list<T: serde::de::DeserializeOwned>(
+    state: &AppState,
+    port: u16,
+    path: &str,
+) -> Option<Vec<T>> {
+    let url = http::local_url(port, path);
+    let response = state
+        .http
+        .send(HttpPolicy::INTERACTIVE, |client| client.get(&url))
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.json::<Vec<T>>().await.ok()
+}
+
+/// Collect the facts the rules look at
+pub async fn gather(app: &tauri::AppHandle) -> AuditInput {
+    use tauri_plugin_autostart::ManagerExt;
+
+    let state = app.state::<AppState>();
+    let backend_ready = state.backend_ready.load(Ordering::SeqCst);
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let now = crate::health::now();
+
+    let (plans, repositories) = if backend_ready {
+        let plans = fetch_list::<BackendPlan>(&state, port, crate::actions::PLANS_PATH).await;
+        let repositories = fetch_list::<BackendRepository>(&state, port, REPOSITORIES_PATH).await;
+        (
+            plans.map(|plans| plans.into_iter().map(PlanFacts::from).collect()),
+            repositories.map(|repositories| {
+                repositories
+                    .into_iter()
+                    .map(|repository| RepositoryFacts {
+                        reachable: repository.status.as_deref() != Some("error"),
+                        name: repository.name,
+                    })
+                    .collect()
+            }),
+        )
+    } else {
+        (None, None)
+    };
+
+    let service = crate::commands::service::get_service_status().await.ok();
+    AuditInput {
+        now,
+        backend_ready,
+        crash_loop: app
+            .state::<crate::health::HealthLog>()
+            .snapshot()
+            .crash_loop(now),
+        plans,
+        repositories,
+        disk_available: fs2::available_space(&state.paths().data_dir).ok(),
+        service_installed: service.as_ref().is_some_and(|status| status.installed),
+        service_running: service.as_ref().is_some_and(|status| status.running),
+        autostart: app.autolaunch().is_enabled().ok(),
+    }
+}
+
+/// The last audit, for the system report
+#[derive(Default)]
+pub struct ProtectionCache(Mutex<Option<ProtectionAudit>>);
+
+impl ProtectionCache {
+    pub fn last(&self) -> Option<ProtectionAudit> {
+        self.0.lock().clone()
+    }
+}
+
+/// Gather, evaluate and remember an audit
+pub async fn run(app: &tauri::AppHandle) -> ProtectionAudit {
+    let input = gather(app).await;
+    let audit = evaluate(&input, input.now * 1000);
+    info!("Protection audit scored {} ({:?})", audit.score, audit.status);
+    *app.state::<ProtectionCache>().0.lock() = Some(audit.clone());
+    audit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const HOUR: u64 = 60 * 60;
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn plan(name: &str, interval_minutes: Option<u32>, ago: Option<u64>) -> PlanFacts {
+        PlanFacts {
+            name: name.to_string(),
+            enabled: true,
+            interval_minutes,
+            last_run_at: ago.map(|ago| NOW - ago),
+            last_succeeded: ago.map(|_| true),
+        }
+    }
+
+    /// A machine where every rule passes
+    fn protected() -> AuditInput {
+        AuditInput {
+            now: NOW,
+            backend_ready: true,
+            crash_loop: false,
+            plans: Some(vec![plan("Documents", Some(DAY_MINUTES), Some(2 * HOUR))]),
+            repositories: Some(vec![RepositoryFacts {
+                name: "NAS".to_string(),
+                reachable: true,
+            }]),
+            disk_available: Some(100 * GIB),
+            service_installed: false,
+            service_running: false,
+            autostart: Some(true),
+        }
+    }
+
+    fn item<'a>(audit: &'a ProtectionAudit, id: &str) -> &'a AuditItem {
+        audit.items.iter().find(|item| item.id == id).unwrap()
+    }
+
+    #[test]
+    fn a_protected_machine_passes_everything() {
+        let audit = evaluate(&protected(), 42);
+        assert_eq!(audit.score, 100);
+        assert_eq!(audit.status, CheckStatus::Pass);
+        assert_eq!(audit.generated_at_ms, 42);
+        assert_eq!(audit.items.len(), RULES.len());
+        assert!(audit.items.iter().all(|item| item.action.is_none()));
+    }
+
+    #[test]
+    fn rules_table_is_well_formed() {
+        let mut ids: Vec<&str> = RULES.iter().map(|rule| rule.id).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), RULES.len());
+        assert!(RULES.iter().all(|rule| rule.weight > 0));
+    }
+
+    type Change = fn(&mut AuditInput);
+
+    #[test]
+    fn each_rule_flags_its_problem() {
+        // (what's wrong, rule, status, remediation)
+        let cases: &[(Change, &str, CheckStatus, Option<&str>)] = &[
+            (
+                |i| i.backend_ready = false,
+                "backend_healthy",
+                CheckStatus::Fail,
+                Some("backend:restart"),
+            ),
+            (
+                |i| i.crash_loop = true,
+                "backend_healthy",
+                CheckStatus::Warn,
+                Some("backend:restart"),
+            ),
+            (
+                |i| i.plans = None,
+                "daily_schedule",
+                CheckStatus::Warn,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans = None,
+                "recent_success",
+                CheckStatus::Warn,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans = Some(vec![plan("Weekly", Some(7 * DAY_MINUTES), Some(HOUR))]),
+                "daily_schedule",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans = Some(vec![plan("Manual", None, Some(HOUR))]),
+                "daily_schedule",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans = Some(vec![]),
+                "recent_success",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans.as_mut().unwrap()[0].enabled = false,
+                "daily_schedule",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans.as_mut().unwrap()[0].last_succeeded = Some(false),
+                "recent_success",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans.as_mut().unwrap().push(plan("New", Some(60), None)),
+                "recent_success",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.plans.as_mut().unwrap()[0].last_run_at = Some(NOW - 26 * HOUR),
+                "recent_success",
+                CheckStatus::Fail,
+                Some("page:backups"),
+            ),
+            (
+                |i| i.repositories = None,
+                "repositories_reachable",
+                CheckStatus::Warn,
+                Some("page:repositories"),
+            ),
+            (
+                |i| i.repositories = Some(vec![]),
+                "repositories_reachable",
+                CheckStatus::Fail,
+                Some("page:repositories"),
+            ),
+            (
+                |i| i.repositories.as_mut().unwrap()[0].reachable = false,
+                "repositories_reachable",
+                CheckStatus::Fail,
+                Some("page:repositories"),
+            ),
+            (
+                |i| i.disk_available = None,
+                "free_space",
+                CheckStatus::Warn,
+                None,
+            ),
+            (
+                |i| i.disk_available = Some(3 * GIB),
+                "free_space",
+                CheckStatus::Warn,
+                None,
+            ),
+            (
+                |i| i.disk_available = Some(GIB / 2),
+                "free_space",
+                CheckStatus::Fail,
+                None,
+            ),
+            (
+                |i| i.autostart = Some(false),
+                "keeps_running",
+                CheckStatus::Warn,
+                Some("page:settings"),
+            ),
+            (
+                |i| i.autostart = None,
+                "keeps_running",
+                CheckStatus::Warn,
+                Some("page:settings"),
+            ),
+            (
+                |i| i.service_installed = true,
+                "keeps_running",
+                CheckStatus::Fail,
+                Some("service:start"),
+            ),
+        ];
+        for (n, (change, id, status, action)) in cases.iter().enumerate() {
+            let mut input = protected();
+            change(&mut input);
+            let audit = evaluate(&input, 0);
+            let flagged = item(&audit, id);
+            assert_eq!(flagged.status, *status, "case {}: {}", n, flagged.detail);
+            assert_eq!(flagged.action, *action, "case {}", n);
+            if let Some(action) = action {
+                assert!(
+                    crate::actions::find(action).is_some(),
+                    "unknown action {}",
+                    action
+                );
+            }
+            assert_eq!(audit.status, *status, "case {}", n);
+            assert!(audit.score < 100, "case {}", n);
+        }
+    }
+
+    #[test]
+    fn plans_within_their_interval_pass() {
+        let mut input = protected();
+        input.plans = Some(vec![
+            // Overdue only after the interval plus the grace period
+            plan(
+                "Daily",
+                Some(DAY_MINUTES),
+                Some(24 * HOUR + OVERDUE_GRACE_SECS),
+            ),
+            plan("Hourly", Some(60), Some(HOUR)),
+            PlanFacts {
+                enabled: false,
+                ..plan("Retired", None, None)
+            },
+        ]);
+        let audit = evaluate(&input, 0);
+        assert_eq!(item(&audit, "recent_success").status, CheckStatus::Pass);
+        assert_eq!(
+            item(&audit, "daily_schedule").detail,
+            "2 plan(s) run at least once a day"
+        );
+    }
+
+    #[test]
+    fn a_running_service_stands_in_for_autostart() {
+        let mut input = protected();
+        input.service_installed = true;
+        input.service_running = true;
+        input.autostart = Some(false);
+        assert_eq!(
+            item(&evaluate(&input, 0), "keeps_running").status,
+            CheckStatus::Pass
+        );
+    }
+
+    #[test]
+    fn score_weighs_warnings_at_half() {
+        let total: u32 = RULES.iter().map(|rule| rule.weight).sum();
+        let weight = |id: &str| RULES.iter().find(|rule| rule.id == id).unwrap().weight;
+
+        let mut input = protected();
+        input.disk_available = Some(3 * GIB);
+        let audit = evaluate(&input, 0);
+        assert_eq!(
+            audit.score,
+            (2 * total - weight("free_space")) * 100 / (2 * total)
+        );
+
+        input.backend_ready = false;
+        let audit = evaluate(&input, 0);
+        let earned = 2 * total - weight("free_space") - 2 * weight("backend_healthy");
+        assert_eq!(audit.score, earned * 100 / (2 * total));
+        assert_eq!(audit.status, CheckStatus::Fail);
+
+        let nothing = AuditInput {
+            service_installed: true,
+            disk_available: Some(0),
+            plans: Some(vec![]),
+            repositories: Some(vec![]),
+            ..AuditInput::default()
+        };
+        assert_eq!(evaluate(&nothing, 0).score, 0);
+    }
+
+    #[test]
+    fn backend_plans_become_facts() {
+        let plans: Vec<BackendPlan> = serde_json::from_str(
+            r#"[
+                {"name":"A","cronExpression":"0 2 * * *","lastBackupAt":1700000000000,
+                 "lastBackupStatus":"success"},
+                {"name":"B","enabled":false,"cronExpression":"not cron",
+                 "lastBackupAt":"2023-11-14T22:13:20Z","lastBackupStatus":"error"},
+                {"name":"C"}
+            ]"#,
+        )
+        .unwrap();
+        let facts: Vec<PlanFacts> = plans.into_iter().map(PlanFacts::from).collect();
+        assert_eq!(
+            facts,
+            [
+                PlanFacts {
+                    name: "A".to_string(),
+                    enabled: true,
+                    interval_minutes: Some(DAY_MINUTES),
+                    last_run_at: Some(1_700_000_000),
+                    last_succeeded: Some(true),
+                },
+                PlanFacts {
+                    name: "B".to_string(),
+                    enabled: false,
+                    interval_minutes: None,
+                    last_run_at: Some(1_700_000_000),
+                    last_succeeded: Some(false),
+                },
+                PlanFacts {
+                    name: "C".to_string(),
+                    enabled: true,
+                    interval_minutes: None,
+                    last_run_at: None,
+                    last_succeeded: None,
+                },
+            ]
+        );
+        assert_eq!(timestamp_secs(&serde_json::json!(true)), None);
+        assert_eq!(timestamp_secs(&serde_json::json!(-5)), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Readiness waiting shared by the desktop app and the Windows Service.
+//!
+//! A server that reports startup progress (e.g. long first-start database migrations) through
+//! `/healthcheck?detail=1` keeps getting more time as long as that progress advances, instead
+//! of being declared dead after a fixed number of attempts.
+//! Probes start quickly and back off (see [`Backoff`]), so a fast server is noticed right
+//! away without a slow one being hammered.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Startup progress reported by the server while it isn't ready yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartupProgress {
+    #[serde(default)]
+    pub stage: Option<String>,
+    #[serde(default)]
+    pub percent: Option<f64>,
+}
+
+impl fmt::Display for StartupProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stage.as_deref().unwrap_or("starting"))?;
+        if let Some(percent) = self.percent {
+            write!(f, " ({:.0}%)", percent)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct HealthDetail {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    progress: Option<StartupProgress>,
+}
+
+/// What a healthcheck response says about the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthState {
+    Ready,
+    /// Responding but not ready, possibly with progress details
+    Starting(Option<StartupProgress>),
+}
+
+/// Interpret a healthcheck response. Servers that don't report details are ready on any
+/// success status; servers that do are ready unless they say they're still starting.
+pub fn parse_health_response(success: bool, body: &str) -> HealthState {
+    let detail: Option<HealthDetail> = serde_json::from_str(body).ok();
+    let starting = detail.as_ref().and_then(|d| d.status.as_deref()) == Some("starting");
+
+    if success && !starting {
+        HealthState::Ready
+    } else {
+        HealthState::Starting(detail.and_then(|d| d.progress))
+    }
+}
+
+/// Response header a zerobyte server carries its version in
+pub const IDENTITY_HEADER: &str = "x-zerobyte-version";
+
+/// Who answers the healthcheck on a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerIdentity {
+    Zerobyte { version: Option<String> },
+    /// Answers, but doesn't say it's a zerobyte server
+    Foreign,
+}
+
+#[derive(Deserialize)]
+struct IdentityBody {
+    #[serde(default)]
+    app: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+/// Identify a server from its healthcheck: the [`IDENTITY_HEADER`], or a JSON body with
+/// `"app": "zerobyte"` (e.g. `{"app":"zerobyte","version":"0.9.0"}`)
+pub fn identify(header: Option<&str>, body: &str) -> ServerIdentity {
+    if let Some(version) = header {
+        return ServerIdentity::Zerobyte {
+            version: Some(version.to_string()),
+        };
+    }
+    match serde_json::from_str::<IdentityBody>(body) {
+        Ok(IdentityBody {
+            app: Some(app),
+            version,
+        }) if app.eq_ignore_ascii_case("zerobyte") => ServerIdentity::Zerobyte { version },
+        _ => ServerIdentity::Foreign,
+    }
+}
+
+/// How waiting for a server to become ready ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerWait {
+    Ready,
+    /// Nothing ready answered before the deadline
+    TimedOut,
+    /// Something answers on the port, but it isn't a zerobyte server
+    WrongServer,
+    /// The app started quitting
+    Cancelled,
+}
+
+/// Delays between probes, doubling from `initial` up to `max`
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { next: initial, max }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+}
+
+/// A deadline that moves forward while the server reports advancing progress
+#[derive(Debug, Clone)]
+pub struct ReadinessDeadline {
+    deadline: Instant,
+    max_deadline: Instant,
+    extension: Duration,
+    last: Option<StartupProgress>,
+}
+
+impl ReadinessDeadline {
+    /// `base` applies to a server that reports nothing, `extension` is granted on each
+    /// progress step, and `max` bounds the total wait regardless of progress
+    pub fn new(base: Duration, extension: Duration, max: Duration) -> Self {
+        Self::starting_at(Instant::now(), base, extension, max)
+    }
+
+    pub fn starting_at(now: Instant, base: Duration, extension: Duration, max: Duration) -> Self {
+        Self {
+            deadline: now + base.min(max),
+            max_deadline: now + max,
+            extension,
+            last: None,
+        }
+    }
+
+    /// Record reported progress. Returns true (and extends the deadline) if it advanced
+    /// since the last report: a higher percentage or a new stage.
+    pub fn observe(&mut self, now: Instant, progress: &StartupProgress) -> bool {
+        let advanced = match &self.last {
+            None => true,
+            Some(prev) => progress.percent > prev.percent || progress.stage != prev.stage,
+        };
+
+        if advanced {
+            self.deadline = (now + self.extension)
+                .max(self.deadline)
+                .min(self.max_deadline);
+            self.last = Some(progress.clone());
+        }
+
+        advanced
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.deadline.saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn progress(stage: &str, percent: Option<f64>) -> StartupProgress {
+        StartupProgress {
+            stage: Some(stage.to_string()),
+            percent,
+        }
+    }
+
+    /// Feed `script` to a deadline one report per `interval`, returning how many seconds in
+    /// the wait expired, if it did
+    fn run_script(
+        deadline: &mut ReadinessDeadline,
+        start: Instant,
+        interval: Duration,
+        script: &[Option<StartupProgress>],
+    ) -> Option<u64> {
+        for (i, report) in script.iter().enumerate() {
+            let now = start + interval * (i as u32 + 1);
+            if deadline.expired(now) {
+                return Some((now - start).as_secs());
+            }
+            if let Some(report) = report {
+                deadline.observe(now, report);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn silent_server_keeps_the_base_budget() {
+        let start = Instant::now();
+        let mut deadline =
+            ReadinessDeadline::starting_at(start, 15 * SECOND, 60 * SECOND, 600 * SECOND);
+
+        assert_eq!(
+            run_script(&mut deadline, start, SECOND, &vec![None; 30]),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn advancing_progress_extends_the_deadline() {
+        let start = Instant::now();
+        let mut deadline =
+            ReadinessDeadline::starting_at(start, 15 * SECOND, 60 * SECOND, 600 * SECOND);
+
+        // A migration reporting 1% every 10s runs well past the base budget
+        let script: Vec<_> = (0..30)
+            .map(|i| Some(progress("migrating", Some(i as f64))))
+            .collect();
+        assert_eq!(run_script(&mut deadline, start, 10 * SECOND, &script), None);
+        assert_eq!(deadline.remaining(start + 300 * SECOND), 60 * SECOND);
+    }
+
+    #[test]
+    fn stalled_progress_expires_one_extension_after_the_last_step() {
+        let start = Instant::now();
+        let mut deadline =
+            ReadinessDeadline::starting_at(start, 15 * SECOND, 60 * SECOND, 600 * SECOND);
+
+        let mut script = vec![
+            Some(progress("migrating", Some(10.0))),
+            Some(progress("migrating", Some(20.0))),
+        ];
+        script.extend(vec![Some(progress("migrating", Some(20.0))); 20]);
+        // The last step was at 20s
+        assert_eq!(
+            run_script(&mut deadline, start, 10 * SECOND, &script),
+            Some(80)
+        );
+    }
+
+    #[test]
+    fn new_stage_counts_as_progress_but_going_backwards_does_not() {
+        let start = Instant::now();
+        let mut deadline =
+            ReadinessDeadline::starting_at(start, 15 * SECOND, 60 * SECOND, 600 * SECOND);
+
+        assert!(deadline.observe(start, &progress("migrating", Some(100.0))));
+        assert!(deadline.observe(start, &progress("indexing", None)));
+        assert!(!deadline.observe(start, &progress("indexing", None)));
+        assert!(deadline.observe(start, &progress("indexing", Some(5.0))));
+        assert!(!deadline.observe(start, &progress("indexing", Some(4.0))));
+    }
+
+    #[test]
+    fn progress_never_extends_past_the_cap() {
+        let start = Instant::now();
+        let mut deadline =
+            ReadinessDeadline::starting_at(start, 15 * SECOND, 60 * SECOND, 120 * SECOND);
+
+        let script: Vec<_> = (0..30)
+            .map(|i| Some(progress("migrating", Some(i as f64))))
+            .collect();
+        assert_eq!(
+            run_script(&mut deadline, start, 10 * SECOND, &script),
+            Some(120)
+        );
+    }
+
+    #[test]
+    fn base_budget_is_bounded_by_the_cap() {
+        let start = Instant::now();
+        let deadline = ReadinessDeadline::starting_at(start, 30 * SECOND, 60 * SECOND, 10 * SECOND);
+
+        assert!(!deadline.expired(start + 9 * SECOND));
+        assert!(deadline.expired(start + 10 * SECOND));
+    }
+
+    #[test]
+    fn health_response_reports_starting_with_progress() {
+        let body = r#"{"status":"starting","progress":{"stage":"migrating","percent":42}}"#;
+        assert_eq!(
+            parse_health_response(true, body),
+            HealthState::Starting(Some(progress("migrating", Some(42.0))))
+        );
+        assert_eq!(
+            parse_health_response(false, body),
+            HealthState::Starting(Some(progress("migrating", Some(42.0))))
+        );
+    }
+
+    #[test]
+    fn health_response_without_details_is_ready_on_success() {
+        assert_eq!(parse_health_response(true, "OK"), HealthState::Ready);
+        assert_eq!(
+            parse_health_response(true, r#"{"status":"ok"}"#),
+            HealthState::Ready
+        );
+        assert_eq!(
+            parse_health_response(false, "Service Unavailable"),
+            HealthState::Starting(None)
+        );
+    }
+}
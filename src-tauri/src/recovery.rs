@@ -0,0 +1,111 @@
+//! Restarting the sidecar after it crashes.
+//!
+//! A crash is an exit with a non-zero code while the desktop still holds the process handle
+//! and isn't quitting. The sidecar is started again after a short delay, a few times at
+//! most; a sidecar that stayed up long enough earns a fresh set of attempts, so a crash
+//! loop gives up quickly while an occasional crash days apart is always recovered from.
+//! Crashes are kept in the health history, so a loop that spans relaunches of the app is
+//! caught too: [`CrashRecovery::from_history`] starts from the crashes recorded lately.
+
+use crate::health::{HealthHistory, CRASH_LOOP_WINDOW};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Restarts attempted before giving up
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before each restart
+pub const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Uptime after which earlier crashes are forgotten
+pub const RESET_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Payload of `backend-restarting`
+#[derive(Debug, Clone, Serialize)]
+pub struct Restarting {
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_secs: u64,
+    pub exit_code: Option<i32>,
+}
+
+/// Consecutive crash restarts
+#[derive(Debug, Default)]
+pub struct CrashRecovery {
+    attempts: u32,
+}
+
+impl CrashRecovery {
+    /// Start counting from the crashes in `history` in the [`CRASH_LOOP_WINDOW`] before
+    /// `now`. They only hold back restarts until the sidecar stays up for [`RESET_AFTER`].
+    pub fn from_history(history: &HealthHistory, now: u64) -> Self {
+        let crashes = history.crashes_within(now, CRASH_LOOP_WINDOW);
+        Self {
+            attempts: u32::try_from(crashes).unwrap_or(u32::MAX).min(MAX_ATTEMPTS),
+        }
+    }
+
+    /// Account for a crash after `uptime`; returns the restart attempt to make, or None if
+    /// the attempts are used up
+    pub fn on_crash(&mut self, uptime: Duration) -> Option<u32> {
+        if uptime >= RESET_AFTER {
+            self.attempts = 0;
+        }
+        if self.attempts >= MAX_ATTEMPTS {
+            return None;
+        }
+        self.attempts += 1;
+        Some(self.attempts)
+    }
+
+    /// Forget earlier crashes, e.g. after the user restarted the backend by hand
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{CrashKind, HealthEntry, HealthEvent};
+
+    fn crash_at(at: u64) -> HealthEntry {
+        HealthEntry {
+            at,
+            event: HealthEvent::Crash {
+                kind: CrashKind::ErrorExit,
+                generation: 1,
+                code: Some(1),
+            },
+        }
+    }
+
+    #[test]
+    fn recent_crashes_from_earlier_runs_use_up_attempts() {
+        let now = 100_000;
+        let history = HealthHistory {
+            entries: vec![
+                crash_at(now - 4000),
+                crash_at(now - 600),
+                crash_at(now - 60),
+            ],
+        };
+        let mut recovery = CrashRecovery::from_history(&history, now);
+        assert_eq!(
+            recovery.on_crash(Duration::from_secs(30)),
+            Some(MAX_ATTEMPTS)
+        );
+        assert_eq!(recovery.on_crash(Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn staying_up_forgets_seeded_crashes() {
+        let now = 100_000;
+        let history = HealthHistory {
+            entries: (1..=5).map(|i| crash_at(now - i * 60)).collect(),
+        };
+        let mut recovery = CrashRecovery::from_history(&history, now);
+        assert_eq!(recovery.on_crash(Duration::from_secs(30)), None);
+        assert_eq!(recovery.on_crash(RESET_AFTER), Some(1));
+    }
+}
@@ -0,0 +1,437 @@
+//! Detection and repair of OS registrations that point at an old install location.
+//!
+//! In-place updates can move the install directory (per-user to per-machine, or just a change
+//! in casing), while the autostart entry and the Windows service keep the old path and fail
+//! silently at the next boot. At startup the recorded paths are compared with where the app
+//! actually runs; stale ones are reported with `stale-registrations-detected` and fixed by
+//! `repair_registrations`, the service through an elevated script.
+//!
+//! On Windows paths compare case-insensitively, and short (8.3) names are expanded by
+//! canonicalizing whichever side still exists.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Name of the Windows service, as created by `install_service`
+#[cfg(any(target_os = "windows", test))]
+const SERVICE_NAME: &str = "C3iBackupONE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationKind {
+    /// Launch at login (registry Run key, LaunchAgent or XDG autostart entry)
+    Autostart,
+    /// Binary path of the installed Windows service
+    Service,
+}
+
+/// A registration whose path no longer matches the install
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StaleRegistration {
+    pub kind: RegistrationKind,
+    pub recorded: String,
+    pub expected: PathBuf,
+    /// Fixing it needs administrator approval
+    pub needs_elevation: bool,
+}
+
+/// Outcome of one repair step
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairResult {
+    pub kind: RegistrationKind,
+    pub repaired: bool,
+    pub error: Option<String>,
+}
+
+/// The executable in a command line such as `"C:\Program Files\App\app.exe" --minimized` or
+/// an unquoted `C:\Program Files\App\app.exe --minimized`
+pub fn executable_from_command_line(command_line: &str) -> &str {
+    split_command_line(command_line).0
+}
+
+/// Split a command line into its executable and the arguments after it
+fn split_command_line(command_line: &str) -> (&str, &str) {
+    let command_line = command_line.trim();
+    if let Some(quoted) = command_line.strip_prefix('"') {
+        return match quoted.split_once('"') {
+            Some((exe, arguments)) => (exe, arguments.trim_start()),
+            None => (quoted, ""),
+        };
+    }
+    if let Some(end) = command_line.to_ascii_lowercase().find(".exe") {
+        let (exe, arguments) = command_line.split_at(end + ".exe".len());
+        return (exe, arguments.trim_start());
+    }
+    match command_line.find(" --") {
+        Some(end) => (&command_line[..end], command_line[end..].trim_start()),
+        None => (command_line, ""),
+    }
+}
+
+/// `recorded` with its executable replaced by `exe`, keeping the arguments (such as the
+/// service's owner) as they were
+pub fn repaired_command_line(recorded: &str, exe: &Path) -> String {
+    match split_command_line(recorded).1 {
+        "" => format!("\"{}\"", exe.display()),
+        arguments => format!("\"{}\" {}", exe.display(), arguments),
+    }
+}
+
+/// Normalized form of `path` for comparison: canonical if it exists, otherwise lexically
+/// cleaned up; lowercased with backslashes on Windows
+pub fn normalize(path: &Path) -> String {
+    let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let text = resolved.to_string_lossy().into_owned();
+
+    #[cfg(target_os = "windows")]
+    {
+        let text = text.replace('/', "\\");
+        let text = text
+            .strip_prefix(r"\\?\UNC\")
+            .map(|rest| format!(r"\\{}", rest))
+            .unwrap_or_else(|| text.trim_start_matches(r"\\?\").to_string());
+        text.trim_end_matches('\\').to_lowercase()
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        text.trim_end_matches('/').to_string()
+    }
+}
+
+/// Whether two paths name the same file
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    normalize(a) == normalize(b)
+}
+
+/// Command line recorded for launching at login, if autostart is registered
+fn autostart_command(app: &tauri::AppHandle) -> Option<String> {
+    let name = app.package_info().name.clone();
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+
+        let output = std::process::Command::new("reg")
+            .args([
+                "query",
+                r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run",
+                "/v",
+                &name,
+            ])
+            .creation_flags(0x08000000) // CREATE_NO_WINDOW
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // "    <name>    REG_SZ    <command line>"
+        String::from_utf8_lossy(&output.stdout).lines().find_map(|line| {
+            let (_, value) = line.split_once("REG_SZ")?;
+            Some(value.trim().to_string())
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let plist = PathBuf::from(std::env::var_os("HOME")?)
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", name));
+        let content = std::fs::read_to_string(plist).ok()?;
+        // The first <string> under ProgramArguments is the executable
+        let arguments = content.split("<key>ProgramArguments</key>").nth(1)?;
+        let start = arguments.find("<string>")? + "<string>".len();
+        let end = start + arguments[start..].find("</string>")?;
+        Some(arguments[start..end].to_string())
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        let entry = config.join("autostart").join(format!("{}.desktop", name));
+        let content = std::fs::read_to_string(entry).ok()?;
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix("Exec="))
+            .map(str::to_string)
+    }
+}
+
+/// Binary path the installed Windows service runs, if it is installed
+#[cfg(target_os = "windows")]
+fn service_binary_path() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+
+    let output = std::process::Command::new("sc")
+        .args(["qc", SERVICE_NAME])
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "BINARY_PATH_NAME").then(|| value.trim().to_string())
+    })
+}
+
+/// The registration of `kind` if its recorded command line doesn't run `expected`
+fn check(
+    kind: RegistrationKind,
+    recorded: String,
+    expected: PathBuf,
+) -> Option<StaleRegistration> {
+    let recorded_exe = executable_from_command_line(&recorded);
+    if same_path(Path::new(recorded_exe), &expected) {
+        return None;
+    }
+    Some(StaleRegistration {
+        kind,
+        recorded,
+        expected,
+        needs_elevation: kind == RegistrationKind::Service,
+    })
+}
+
+/// Compare every registration with the running install
+pub fn find_stale(app: &tauri::AppHandle) -> Vec<StaleRegistration> {
+    let mut stale = Vec::new();
+
+    if let Ok(exe) = std::env::current_exe() {
+        if let Some(recorded) = autostart_command(app) {
+            stale.extend(check(RegistrationKind::Autostart, recorded, exe));
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    if let Some(recorded) = service_binary_path() {
+        use tauri::Manager;
+        let expected = app
+            .state::<crate::AppState>()
+            .paths()
+            .binaries_dir
+            .join("zerobyte-service.exe");
+        stale.extend(check(RegistrationKind::Service, recorded, expected));
+    }
+
+    stale
+}
+
+/// Rewrite the autostart entry with the current executable
+pub fn repair_autostart(app: &tauri::AppHandle) -> Result<(), String> {
+    use tauri_plugin_autostart::ManagerExt;
+
+    let autolaunch = app.autolaunch();
+    autolaunch
+        .disable()
+        .map_err(|e| format!("Failed to remove the old autostart entry: {}", e))?;
+    autolaunch
+        .enable()
+        .map_err(|e| format!("Failed to register autostart: {}", e))
+}
+
+/// Batch script pointing the service at `command_line`, logging its outcome to `log`
+#[cfg(any(target_os = "windows", test))]
+fn service_repair_script(command_line: &str, log: &Path) -> String {
+    format!(
+        r#"@echo off
+echo Updating service path... > "{log}"
+sc config {name} binPath= "{command}" >> "{log}" 2>&1
+if %errorlevel% neq 0 (
+    echo ERROR: Failed to update service path >> "{log}"
+    exit /b %errorlevel%
+)
+echo Repair complete >> "{log}"
+"#,
+        name = SERVICE_NAME,
+        command = command_line.replace('"', "\\\""),
+        log = log.display()
+    )
+}
+
+/// Point the Windows service recorded as `recorded` at `exe`, keeping its arguments. Prompts
+/// for administrator approval.
+#[cfg(target_os = "windows")]
+pub async fn repair_service(recorded: String, exe: PathBuf) -> Result<(), String> {
+    let log_path = std::env::temp_dir().join("zerobyte_service_repair.log");
+    let _ = std::fs::remove_file(&log_path);
+
+    let script = service_repair_script(&repaired_command_line(&recorded, &exe), &log_path);
+    crate::commands::service::execute_elevated_script(
+        "zerobyte_repair_service.bat",
+        script,
+        &log_path,
+        "Repair complete",
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+pub async fn repair_service(_recorded: String, _exe: PathBuf) -> Result<(), String> {
+    Err("Windows Service is only supported on Windows".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_lines_are_split_into_executable_and_arguments() {
+        let cases = [
+            (
+                r#""C:\Program Files\App\app.exe" --minimized"#,
+                r"C:\Program Files\App\app.exe",
+                "--minimized",
+            ),
+            (
+                r"C:\Program Files\App\app.exe --minimized",
+                r"C:\Program Files\App\app.exe",
+                "--minimized",
+            ),
+            (r"C:\APP\APP.EXE", r"C:\APP\APP.EXE", ""),
+            (
+                r#"  "C:\svc\zerobyte-service.exe" --owner S-1-5-21-1  "#,
+                r"C:\svc\zerobyte-service.exe",
+                "--owner S-1-5-21-1",
+            ),
+            (
+                r#""C:\unterminated\app.exe"#,
+                r"C:\unterminated\app.exe",
+                "",
+            ),
+            (
+                "/usr/bin/zerobyte --minimized",
+                "/usr/bin/zerobyte",
+                "--minimized",
+            ),
+            (
+                "/opt/C3i Backup ONE/zerobyte",
+                "/opt/C3i Backup ONE/zerobyte",
+                "",
+            ),
+        ];
+        for (command_line, exe, arguments) in cases {
+            assert_eq!(split_command_line(command_line), (exe, arguments));
+            assert_eq!(executable_from_command_line(command_line), exe);
+        }
+    }
+
+    #[test]
+    fn repaired_command_lines_keep_their_arguments() {
+        let exe = Path::new(r"C:\Program Files\C3i\zerobyte-service.exe");
+        assert_eq!(
+            repaired_command_line(r"C:\OLD\ZEROBY~1.EXE --owner S-1-5-21-7", exe),
+            r#""C:\Program Files\C3i\zerobyte-service.exe" --owner S-1-5-21-7"#
+        );
+        assert_eq!(
+            repaired_command_line(r#""C:\old\zerobyte-service.exe""#, exe),
+            r#""C:\Program Files\C3i\zerobyte-service.exe""#
+        );
+    }
+
+    #[test]
+    fn service_repair_script_escapes_the_binary_path() {
+        let script = service_repair_script(
+            r#""C:\Program Files\C3i\zerobyte-service.exe" --owner S-1-5-21-7"#,
+            Path::new(r"C:\Temp\repair.log"),
+        );
+        let lines: Vec<_> = script.lines().collect();
+        assert_eq!(
+            lines[2],
+            r#"sc config C3iBackupONE binPath= "\"C:\Program Files\C3i\zerobyte-service.exe\" --owner S-1-5-21-7" >> "C:\Temp\repair.log" 2>&1"#
+        );
+        // execute_elevated_script looks for this marker in the log
+        assert_eq!(
+            *lines.last().unwrap(),
+            r#"echo Repair complete >> "C:\Temp\repair.log""#
+        );
+    }
+
+    #[test]
+    fn normalized_paths_ignore_trailing_separators() {
+        let dir = std::env::temp_dir().join(format!("registrations-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let with_slash = PathBuf::from(format!("{}/", dir.display()));
+        let missing = dir.join("missing").join("app");
+        let missing_with_slash = PathBuf::from(format!("{}/", missing.display()));
+
+        let existing_same = same_path(&dir, &with_slash);
+        let missing_same = same_path(&missing, &missing_with_slash);
+        let normalized = normalize(&dir);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(existing_same);
+        assert!(missing_same);
+        assert!(!normalized.ends_with('/'));
+        assert!(!same_path(&dir, &missing));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn existing_paths_are_compared_canonically() {
+        let dir = std::env::temp_dir().join(format!("registrations-link-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let install = dir.join("install");
+        std::fs::create_dir_all(&install).unwrap();
+        std::fs::write(install.join("zerobyte"), b"").unwrap();
+        std::os::unix::fs::symlink(&install, dir.join("current")).unwrap();
+
+        let through_link = same_path(&dir.join("current/zerobyte"), &install.join("zerobyte"));
+        let through_dots = same_path(
+            &install.join("../install/zerobyte"),
+            &install.join("zerobyte"),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(through_link);
+        assert!(through_dots);
+    }
+
+    #[test]
+    fn registrations_running_another_executable_are_stale() {
+        let dir = std::env::temp_dir().join(format!("registrations-stale-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exe = dir.join("zerobyte");
+        std::fs::write(&exe, b"").unwrap();
+
+        let current = check(
+            RegistrationKind::Autostart,
+            format!("{} --minimized", exe.display()),
+            exe.clone(),
+        );
+        let quoted = check(
+            RegistrationKind::Autostart,
+            format!(r#""{}/" --minimized"#, exe.display()),
+            exe.clone(),
+        );
+        let moved = check(
+            RegistrationKind::Service,
+            "/opt/old/zerobyte --owner S-1-5-21-7".to_string(),
+            exe.clone(),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(current, None);
+        assert_eq!(quoted, None);
+        assert_eq!(
+            moved,
+            Some(StaleRegistration {
+                kind: RegistrationKind::Service,
+                recorded: "/opt/old/zerobyte --owner S-1-5-21-7".to_string(),
+                expected: exe,
+                needs_elevation: true,
+            })
+        );
+        let autostart = check(
+            RegistrationKind::Autostart,
+            "/opt/old/zerobyte".to_string(),
+            PathBuf::from("/opt/new/zerobyte"),
+        );
+        assert!(!autostart.unwrap().needs_elevation);
+    }
+}
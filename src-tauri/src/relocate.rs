@@ -0,0 +1,196 @@
+//! Moving the sidecar's data to another directory.
+//!
+//! `set_data_directory` checks the new directory with [`check_target`], stops the sidecar,
+//! moves the data with [`move_contents`] if asked to and starts the sidecar again with the new
+//! `data_dir` from `zerobyte.toml`. Everything is copied before anything is removed from the
+//! old directory, so a copy that fails halfway is cleaned up and leaves the old data in place.
+
+use crate::paths::{self, PathKind};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Check that `target` can take the data: absolute and writable and, when the data in
+/// `source` is to be moved there, empty and not nested with `source`
+pub fn check_target(target: &Path, source: Option<&Path>) -> Result<(), String> {
+    if !target.is_absolute() {
+        return Err("The data directory must be an absolute path".to_string());
+    }
+    paths::ensure_writable(PathKind::Data, target).map_err(|e| e.to_string())?;
+    let Some(source) = source else {
+        return Ok(());
+    };
+    if target.starts_with(source) || source.starts_with(target) {
+        return Err(format!(
+            "{} and {} are inside one another",
+            target.display(),
+            source.display()
+        ));
+    }
+    let occupied = std::fs::read_dir(target)
+        .map_err(|e| format!("Failed to read {}: {}", target.display(), e))?
+        .next()
+        .is_some();
+    if occupied {
+        return Err(format!(
+            "{} must be empty to move the data there",
+            target.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Copy everything in `source` to `target`, then remove it from `source`. Returns the number
+/// of bytes moved. The backend must not be running.
+pub fn move_contents(source: &Path, target: &Path) -> std::io::Result<u64> {
+    if !source.is_dir() {
+        return Ok(0);
+    }
+    let bytes = match copy_tree(source, target) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            remove_children(target);
+            return Err(e);
+        }
+    };
+    info!(
+        "Copied {} bytes of backend data from {} to {}",
+        bytes,
+        source.display(),
+        target.display()
+    );
+    // The copy is complete; leftovers in the old directory only cost disk space
+    remove_children(source);
+    Ok(bytes)
+}
+
+fn copy_tree(source: &Path, target: &Path) -> std::io::Result<u64> {
+    std::fs::create_dir_all(target)?;
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = target.join(entry.file_name());
+        if file_type.is_dir() {
+            bytes += copy_tree(&entry.path(), &destination)?;
+        } else if file_type.is_file() {
+            bytes += std::fs::copy(entry.path(), &destination)?;
+        } else {
+            warn!("Not moving {}: not a regular file", entry.path().display());
+        }
+    }
+    Ok(bytes)
+}
+
+fn remove_children(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let removed = if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        if let Err(e) = removed {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("relocate-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn targets_are_checked_before_moving() {
+        let root = temp_root("check");
+        let source = root.join("source");
+        let occupied = root.join("occupied");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(&occupied).unwrap();
+        std::fs::write(occupied.join("db.sqlite"), b"x").unwrap();
+
+        let results = [
+            check_target(Path::new("relative/data"), None),
+            check_target(&source.join("nested"), Some(&source)),
+            check_target(&root, Some(&source)),
+            check_target(&occupied, Some(&source)),
+            // Pointing at a directory that already has data is fine without a move
+            check_target(&occupied, None),
+            check_target(&root.join("fresh"), Some(&source)),
+        ];
+        let _ = std::fs::remove_dir_all(&root);
+
+        let [relative, nested, parent, occupied_move, occupied_keep, fresh] = results;
+        assert!(relative.unwrap_err().contains("absolute"));
+        assert!(nested.unwrap_err().contains("inside one another"));
+        assert!(parent.unwrap_err().contains("inside one another"));
+        assert!(occupied_move.unwrap_err().contains("must be empty"));
+        occupied_keep.unwrap();
+        fresh.unwrap();
+    }
+
+    #[test]
+    fn contents_are_moved_with_their_layout() {
+        let root = temp_root("move");
+        let source = root.join("source");
+        let target = root.join("target");
+        std::fs::create_dir_all(source.join("repositories/local")).unwrap();
+        std::fs::write(source.join("db.sqlite"), b"12345").unwrap();
+        std::fs::write(source.join("repositories/local/key"), b"abc").unwrap();
+
+        let moved = move_contents(&source, &target);
+        let db = std::fs::read(target.join("db.sqlite"));
+        let key = std::fs::read(target.join("repositories/local/key"));
+        let left = std::fs::read_dir(&source).unwrap().count();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(moved.unwrap(), 8);
+        assert_eq!(db.unwrap(), b"12345");
+        assert_eq!(key.unwrap(), b"abc");
+        // The old directory itself stays, emptied
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn missing_source_moves_nothing() {
+        let root = temp_root("missing");
+        let moved = move_contents(&root.join("none"), &root.join("target"));
+        let created = root.join("target").exists();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(moved.unwrap(), 0);
+        assert!(!created);
+    }
+
+    #[test]
+    fn failed_move_leaves_the_source_in_place() {
+        let root = temp_root("failed");
+        let source = root.join("source");
+        std::fs::create_dir_all(source.join("repositories")).unwrap();
+        std::fs::write(source.join("db.sqlite"), b"12345").unwrap();
+        // A file where the nested target directory would go makes the copy fail halfway
+        let target = root.join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("repositories"), b"").unwrap();
+
+        let moved = move_contents(&source, &target);
+        let kept = std::fs::read(source.join("db.sqlite"));
+        let target_left = std::fs::read_dir(&target).unwrap().count();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(moved.is_err());
+        assert_eq!(kept.unwrap(), b"12345");
+        // The partial copy is cleaned up
+        assert_eq!(target_left, 0);
+    }
+}
@@ -0,0 +1,310 @@
+//! One-shot system report for the settings "System" page and support.
+//!
+//! Everything comes from state the app already holds (connection mode, banner facts, startup
+//! timings, capabilities, the last protection audit) plus one free-space query on the data
+//! volume, so building it never waits on the backend or the service manager. Error-level log
+//! lines are kept in a small ring by [`RecentErrors`], a tracing layer installed at startup.
+//! The payload carries a schema version; bump it when its shape changes.
+
+use crate::banner::BannerFacts;
+use crate::devtools::DevtoolsPermission;
+use crate::ownership::ConnectionMode;
+use crate::protection::ProtectionAudit;
+use crate::sandbox::Confinement;
+use crate::startup::StageRecord;
+use crate::AppState;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Bumped when the report's shape changes
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Error lines kept for the report
+const RECENT_ERRORS: usize = 10;
+
+/// An error-level log line
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorLine {
+    /// Milliseconds since the Unix epoch
+    pub at_ms: u64,
+    pub target: String,
+    pub message: String,
+}
+
+static ERRORS: Mutex<VecDeque<ErrorLine>> = Mutex::new(VecDeque::new());
+
+/// Tracing layer remembering the last few error-level events
+pub struct RecentErrors;
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for RecentErrors {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != tracing::Level::ERROR {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let line = ErrorLine {
+            at_ms: now_ms(),
+            target: event.metadata().target().to_string(),
+            message: visitor.0,
+        };
+        let mut errors = ERRORS.lock();
+        errors.push_back(line);
+        while errors.len() > RECENT_ERRORS {
+            errors.pop_front();
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSection {
+    pub name: String,
+    pub version: String,
+    pub platform: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendSection {
+    pub port: u16,
+    pub ready: bool,
+    pub using_service: bool,
+    pub connection_mode: ConnectionMode,
+    /// What the sidecar reported at startup (its version among it)
+    pub banner: BannerFacts,
+    pub capabilities: Vec<&'static str>,
+    pub clock_skew_ms: Option<i64>,
+    pub sidecar_generation: u64,
+    pub confinement: Confinement,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskSection {
+    pub path: String,
+    pub available_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+}
+
+/// Restrictions coming from machine policy or another session
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicySection {
+    pub devtools: DevtoolsPermission,
+    /// Another session owns the backend, so changes are refused
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemReport {
+    pub schema_version: u32,
+    /// Milliseconds since the Unix epoch
+    pub generated_at_ms: u64,
+    pub app: AppSection,
+    pub backend: BackendSection,
+    /// Stage timings of this run's startup
+    pub startup: Vec<StageRecord>,
+    pub data_disk: DiskSection,
+    pub policy: PolicySection,
+    /// Most recent error-level log lines, oldest first (redacted)
+    pub recent_errors: Vec<ErrorLine>,
+    /// The last protection audit, if one ran
+    pub protection: Option<ProtectionAudit>,
+}
+
+/// Assemble the report from cached state
+pub fn build(app: &tauri::AppHandle) -> SystemReport {
+    let state = app.state::<AppState>();
+    let package = app.package_info();
+    let connection_mode = state.connection_mode.lock().clone();
+    // Read under locks up front; guards in the struct expression would outlive `state`
+    let banner = state.banner.lock().clone();
+    let clock_skew_ms = state.clock_skew.lock().skew_ms();
+    let confinement = state.confinement.lock().clone();
+    let data_dir = state.paths().data_dir.clone();
+
+    let recent_errors = {
+        let redactor = state.redactor.read();
+        ERRORS
+            .lock()
+            .iter()
+            .map(|line| ErrorLine {
+                message: redactor.redact(&line.message).into_owned(),
+                ..line.clone()
+            })
+            .collect()
+    };
+
+    SystemReport {
+        schema_version: SCHEMA_VERSION,
+        generated_at_ms: now_ms(),
+        app: AppSection {
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            platform: std::env::consts::OS,
+        },
+        backend: BackendSection {
+            port: state.backend_port.load(Ordering::SeqCst),
+            ready: state.backend_ready.load(Ordering::SeqCst),
+            using_service: state.using_service.load(Ordering::SeqCst),
+            connection_mode: connection_mode.clone(),
+            banner,
+            capabilities: state.capabilities().names(),
+            clock_skew_ms,
+            sidecar_generation: state.sidecar_generation.load(Ordering::SeqCst),
+            confinement,
+        },
+        startup: state.startup.records(),
+        data_disk: DiskSection {
+            path: data_dir.display().to_string(),
+            available_bytes: fs2::available_space(&data_dir).ok(),
+            total_bytes: fs2::total_space(&data_dir).ok(),
+        },
+        policy: PolicySection {
+            devtools: crate::devtools::permission(),
+            read_only: connection_mode.is_viewer(),
+        },
+        recent_errors,
+        protection: app.state::<crate::protection::ProtectionCache>().last(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protection::{AuditItem, CheckStatus};
+    use crate::startup::{Stage, StageState};
+    use std::path::PathBuf;
+
+    /// A report with every section filled in, for the golden file
+    fn sample_report() -> SystemReport {
+        SystemReport {
+            schema_version: SCHEMA_VERSION,
+            generated_at_ms: 1_760_000_000_000,
+            app: AppSection {
+                name: "zerobyte".to_string(),
+                version: "0.4.2".to_string(),
+                platform: "linux",
+            },
+            backend: BackendSection {
+                port: 4096,
+                ready: true,
+                using_service: false,
+                connection_mode: ConnectionMode::Viewer {
+                    owner: "bob".to_string(),
+                },
+                banner: BannerFacts {
+                    port: Some(4096),
+                    version: Some("0.9.1".to_string()),
+                    data_dir: Some(PathBuf::from("/var/lib/c3i-backup-one")),
+                },
+                capabilities: vec!["pause", "job_queue"],
+                clock_skew_ms: Some(-1500),
+                sidecar_generation: 2,
+                confinement: Confinement {
+                    systemd_scope: Some("zerobyte-server-42.scope".to_string()),
+                    scope_properties: vec!["TasksMax=512".to_string()],
+                    mount_namespace: true,
+                    writable_paths: vec![PathBuf::from("/var/lib/c3i-backup-one")],
+                },
+            },
+            startup: vec![
+                StageRecord {
+                    stage: Stage::Shell,
+                    state: StageState::Done,
+                    started_ms: Some(0),
+                    finished_ms: Some(120),
+                    error: None,
+                },
+                StageRecord {
+                    stage: Stage::Backend,
+                    state: StageState::Failed,
+                    started_ms: Some(120),
+                    finished_ms: Some(3400),
+                    error: Some("port 4096 is in use".to_string()),
+                },
+            ],
+            data_disk: DiskSection {
+                path: "/home/alice/.local/share/zerobyte".to_string(),
+                available_bytes: Some(52_000_000_000),
+                total_bytes: None,
+            },
+            policy: PolicySection {
+                devtools: DevtoolsPermission::NotEnabled,
+                read_only: true,
+            },
+            recent_errors: vec![ErrorLine {
+                at_ms: 1_759_999_999_000,
+                target: "zerobyte_lib".to_string(),
+                message: "Backend exited with code 1".to_string(),
+            }],
+            protection: Some(ProtectionAudit {
+                score: 60,
+                status: CheckStatus::Warn,
+                items: vec![AuditItem {
+                    id: "recent_backup",
+                    title: "Recent backup",
+                    status: CheckStatus::Warn,
+                    detail: "Last successful backup was 3 days ago".to_string(),
+                    action: Some("run_backup_now"),
+                }],
+                generated_at_ms: 1_759_990_000_000,
+            }),
+        }
+    }
+
+    #[test]
+    fn report_matches_the_golden_file() {
+        // Changing the golden file means the payload changed: bump SCHEMA_VERSION too
+        let golden = include_str!("../testdata/system-report.json");
+        let actual = serde_json::to_string_pretty(&sample_report()).unwrap() + "\n";
+        assert_eq!(actual, golden.replace("\r\n", "\n"));
+    }
+
+    #[test]
+    fn only_the_last_error_lines_are_kept() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = tracing_subscriber::registry().with(RecentErrors);
+        tracing::subscriber::with_default(subscriber, || {
+            for n in 0..RECENT_ERRORS + 2 {
+                tracing::error!("report test error {}", n);
+            }
+            tracing::warn!("report test warning");
+        });
+
+        let errors = ERRORS.lock().clone();
+        let messages: Vec<_> = errors.iter().map(|line| line.message.as_str()).collect();
+        let expected: Vec<_> = (2..RECENT_ERRORS + 2)
+            .map(|n| format!("report test error {}", n))
+            .collect();
+        assert_eq!(messages, expected);
+        assert!(errors.iter().all(|line| line.target == module_path!()));
+    }
+}
@@ -0,0 +1,504 @@
+//! Short-lived backend instances for browsing and restoring from a snapshot.
+//!
+//! Mounting a snapshot must not lock the live backend's database, so each restore session
+//! runs a second sidecar on an ephemeral port, started in read-only restore mode against one
+//! snapshot, and gets a window of its own. The instance is torn down when its window closes,
+//! when it has sat idle for [`IDLE_TIMEOUT`], when the process exits by itself, or when the
+//! app quits.
+
+use crate::proctree::{self, KillReport, ProcessTree};
+use crate::readiness::ServerWait;
+use crate::{activity, assets, settings, AppState};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{Manager, WebviewUrl, WebviewWindowBuilder};
+use tauri_plugin_shell::process::CommandEvent;
+use tauri_plugin_shell::ShellExt;
+use tracing::{error, info, warn};
+
+/// Puts the server in read-only restore mode
+pub const RESTORE_MODE_ENV: &str = "ZEROBYTE_RESTORE_MODE";
+
+/// Repository the restore instance serves
+pub const RESTORE_REPOSITORY_ENV: &str = "ZEROBYTE_RESTORE_REPOSITORY";
+
+/// Snapshot the restore instance serves
+pub const RESTORE_SNAPSHOT_ENV: &str = "ZEROBYTE_RESTORE_SNAPSHOT";
+
+/// A session whose window hasn't been focused for this long is shut down
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// How often idle sessions are looked for
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Each session is a full server process, so keep their number small
+const MAX_SESSIONS: usize = 3;
+
+/// How long a restore instance gets to become ready
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Window labels of restore sessions are this prefix plus the session id
+const WINDOW_PREFIX: &str = "restore-";
+
+/// A running restore session, as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreSession {
+    pub id: u64,
+    pub repo_id: String,
+    pub snapshot_id: String,
+    pub port: u16,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    /// Seconds since the session's window was last in use
+    pub idle_secs: u64,
+}
+
+/// Why a session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    Requested,
+    WindowClosed,
+    Idle,
+    /// The instance exited on its own
+    Exited,
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SessionEnded {
+    id: u64,
+    reason: EndReason,
+}
+
+/// A restore instance's server process
+pub struct Instance {
+    child: proctree::Child,
+    /// The instance and the helpers it starts (mounts, the backup engine)
+    tree: ProcessTree,
+}
+
+struct ActiveSession<P> {
+    session: RestoreSession,
+    process: P,
+    last_activity: Instant,
+}
+
+/// Bookkeeping for every running restore session
+pub struct RestoreSessions<P = Instance> {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, ActiveSession<P>>>,
+}
+
+impl<P> Default for RestoreSessions<P> {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<P> RestoreSessions<P> {
+    /// Id for a new session, or an error when [`MAX_SESSIONS`] are already running
+    fn reserve(&self) -> Result<u64, String> {
+        if self.sessions.lock().len() >= MAX_SESSIONS {
+            return Err(format!(
+                "At most {} restore sessions can run at once; close one first",
+                MAX_SESSIONS
+            ));
+        }
+        Ok(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn insert(&self, session: RestoreSession, process: P) {
+        self.sessions.lock().insert(
+            session.id,
+            ActiveSession {
+                session,
+                process,
+                last_activity: Instant::now(),
+            },
+        );
+    }
+
+    pub fn list(&self) -> Vec<RestoreSession> {
+        let now = Instant::now();
+        let mut sessions: Vec<_> = self
+            .sessions
+            .lock()
+            .values()
+            .map(|active| RestoreSession {
+                idle_secs: now.duration_since(active.last_activity).as_secs(),
+                ..active.session.clone()
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.id);
+        sessions
+    }
+
+    /// Record that the session's window is in use
+    pub fn touch(&self, id: u64) {
+        if let Some(active) = self.sessions.lock().get_mut(&id) {
+            active.last_activity = Instant::now();
+        }
+    }
+
+    fn take(&self, id: u64) -> Option<(RestoreSession, P)> {
+        let active = self.sessions.lock().remove(&id)?;
+        Some((active.session, active.process))
+    }
+
+    fn ids(&self) -> Vec<u64> {
+        self.sessions.lock().keys().copied().collect()
+    }
+
+    /// Ids of sessions unused for longer than `timeout`
+    fn idle(&self, now: Instant, timeout: Duration) -> Vec<u64> {
+        self.sessions
+            .lock()
+            .iter()
+            .filter(|(_, active)| now.duration_since(active.last_activity) >= timeout)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// Label of the window showing session `id`
+pub fn window_label(id: u64) -> String {
+    format!("{}{}", WINDOW_PREFIX, id)
+}
+
+/// Session shown by the window labelled `label`, if it is a restore window
+pub fn session_for_window(label: &str) -> Option<u64> {
+    label.strip_prefix(WINDOW_PREFIX)?.parse().ok()
+}
+
+/// Ask the OS for a free local port. The listener is dropped before the server binds it, so
+/// a startup failure is retried by the caller rather than guaranteed against here.
+pub(crate) fn allocate_port() -> std::io::Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Start a restore instance for `snapshot_id` of `repo_id` and open a window on it
+pub async fn start(
+    app: &tauri::AppHandle,
+    repo_id: String,
+    snapshot_id: String,
+) -> Result<RestoreSession, String> {
+    let state = app.state::<AppState>();
+    let id = state.restore_sessions.reserve()?;
+    let port = allocate_port().map_err(|e| format!("Failed to find a free port: {}", e))?;
+
+    crate::verify_sidecar_binary(app).await.map_err(|e| e.to_string())?;
+    let resource_dir = state.paths().resource_dir.clone();
+    let asset_dir = app
+        .state::<settings::SettingsStore>()
+        .get()
+        .asset_dir_override
+        .unwrap_or_else(|| resource_dir.clone());
+
+    info!(
+        "Starting restore session {} for snapshot {} of repository {} on port {}",
+        id, snapshot_id, repo_id, port
+    );
+    let mut command = app
+        .shell()
+        .sidecar("zerobyte-server")
+        .map_err(|e| format!("Failed to prepare restore instance: {}", e))?
+        .current_dir(resource_dir)
+        .env(assets::ASSET_DIR_ENV, &asset_dir)
+        .env("PORT", port.to_string())
+        .env(RESTORE_MODE_ENV, "1")
+        .env(RESTORE_REPOSITORY_ENV, &repo_id)
+        .env(RESTORE_SNAPSHOT_ENV, &snapshot_id);
+    // Restore instances read the same database as the sidecar
+    if let Some(dir) = crate::paths::sidecar_data_dir(state.config().data_dir.as_deref()) {
+        command = command.env(crate::paths::DATA_DIR_ENV, dir);
+    }
+    let (mut rx, child) = proctree::spawn(command)
+        .map_err(|e| format!("Failed to start restore instance: {}", e))?;
+    let tree = ProcessTree::adopt_group(child.pid());
+
+    let session = RestoreSession {
+        id,
+        repo_id,
+        snapshot_id,
+        port,
+        started_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        idle_secs: 0,
+    };
+    state
+        .restore_sessions
+        .insert(session.clone(), Instance { child, tree });
+
+    let app_handle = app.clone();
+    crate::supervisor::spawn_once(app, format!("restore-{}-output", id), async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                CommandEvent::Stdout(line) => {
+                    let line_str = String::from_utf8_lossy(&line);
+                    let state = app_handle.state::<AppState>();
+                    let redactor = state.redactor.read();
+                    info!("[restore {} stdout] {}", id, redactor.redact(&line_str));
+                }
+                CommandEvent::Stderr(line) => {
+                    let line_str = String::from_utf8_lossy(&line);
+                    let state = app_handle.state::<AppState>();
+                    let redactor = state.redactor.read();
+                    warn!("[restore {} stderr] {}", id, redactor.redact(&line_str));
+                }
+                CommandEvent::Error(err) => {
+                    error!("[restore {} error] {}", id, err);
+                }
+                CommandEvent::Terminated(payload) => {
+                    info!("[restore {}] Process terminated with code: {:?}", id, payload.code);
+                    // Still registered means nobody asked it to stop
+                    let state = app_handle.state::<AppState>();
+                    if let Some((_, instance)) = state.restore_sessions.take(id) {
+                        instance.tree.kill_leftovers();
+                        close_window(&app_handle, id);
+                        notify_ended(&app_handle, id, EndReason::Exited);
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+    });
+
+    if crate::wait_for_server(app, port, STARTUP_TIMEOUT).await != ServerWait::Ready {
+        let _ = end(app, id, EndReason::Exited).await;
+        return Err("Restore instance failed to start".into());
+    }
+
+    let url = format!("http://localhost:{}/", port);
+    let window = WebviewWindowBuilder::new(
+        app,
+        window_label(id),
+        WebviewUrl::External(url.parse().unwrap()),
+    )
+    .title(format!("Restore from snapshot {}", session.snapshot_id))
+    .inner_size(1100.0, 750.0)
+    .center()
+    .focused(true)
+    .build();
+    if let Err(e) = window {
+        let _ = end(app, id, EndReason::Exited).await;
+        return Err(format!("Failed to open restore window: {}", e));
+    }
+
+    info!("Restore session {} ready at {}", id, url);
+    crate::events::emit(app, "restore-session-started", &session);
+    Ok(session)
+}
+
+/// Stop session `id`'s instance and close its window
+pub async fn end(app: &tauri::AppHandle, id: u64, reason: EndReason) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let (session, instance) = state
+        .restore_sessions
+        .take(id)
+        .ok_or_else(|| format!("No restore session {}", id))?;
+
+    info!("Ending restore session {} ({:?})", id, reason);
+    close_window(app, id);
+    let report = stop_instance(&state.http, session.port, instance).await;
+    if !report.survivors.is_empty() {
+        warn!(
+            "Restore session {} processes survived the kill: {:?}",
+            id, report.survivors
+        );
+    }
+
+    notify_ended(app, id, reason);
+    Ok(())
+}
+
+/// Ask the instance on `port` to shut down, then kill whatever is left of it
+async fn stop_instance(
+    http: &crate::http::HttpClient,
+    port: u16,
+    instance: Instance,
+) -> KillReport {
+    if crate::request_graceful_shutdown(http, port).await {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    let Instance { child, tree } = instance;
+    let report = tokio::task::spawn_blocking(move || tree.kill())
+        .await
+        .unwrap_or_default();
+    let _ = child.kill();
+    report
+}
+
+/// End every session; part of the quit sequence
+pub async fn end_all(app: &tauri::AppHandle, reason: EndReason) {
+    let ids = app.state::<AppState>().restore_sessions.ids();
+    for id in ids {
+        let _ = end(app, id, reason).await;
+    }
+}
+
+/// Shut down sessions nobody has looked at for [`IDLE_TIMEOUT`]. A focused window counts as
+/// in use even without focus changes.
+pub async fn reap_idle(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "restore_idle",
+        activity::TaskClass::Critical,
+        IDLE_CHECK_INTERVAL,
+    );
+    loop {
+        ticker.tick().await;
+
+        let state = app.state::<AppState>();
+        for id in state.restore_sessions.ids() {
+            let focused = app
+                .get_webview_window(&window_label(id))
+                .and_then(|window| window.is_focused().ok())
+                .unwrap_or(false);
+            if focused {
+                state.restore_sessions.touch(id);
+            }
+        }
+
+        for id in state.restore_sessions.idle(Instant::now(), IDLE_TIMEOUT) {
+            let _ = end(&app, id, EndReason::Idle).await;
+        }
+    }
+}
+
+fn close_window(app: &tauri::AppHandle, id: u64) {
+    if let Some(window) = app.get_webview_window(&window_label(id)) {
+        let _ = window.destroy();
+    }
+}
+
+fn notify_ended(app: &tauri::AppHandle, id: u64, reason: EndReason) {
+    crate::events::emit(app, "restore-session-ended", SessionEnded { id, reason });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: u64, port: u16) -> RestoreSession {
+        RestoreSession {
+            id,
+            repo_id: "repo".to_string(),
+            snapshot_id: format!("snap{}", id),
+            port,
+            started_at: 0,
+            idle_secs: 0,
+        }
+    }
+
+    #[test]
+    fn allocated_port_is_free_to_bind() {
+        let port = allocate_port().unwrap();
+        assert_ne!(port, 0);
+        std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+    }
+
+    #[test]
+    fn sessions_are_capped() {
+        let sessions = RestoreSessions::<()>::default();
+        for expected in 1..=MAX_SESSIONS as u64 {
+            let id = sessions.reserve().unwrap();
+            assert_eq!(id, expected);
+            sessions.insert(session(id, 0), ());
+        }
+        assert!(sessions.reserve().unwrap_err().contains("close one first"));
+
+        let (ended, ()) = sessions.take(2).unwrap();
+        assert_eq!(ended.snapshot_id, "snap2");
+        assert!(sessions.take(2).is_none());
+        // Ids aren't reused
+        assert_eq!(sessions.reserve(), Ok(MAX_SESSIONS as u64 + 1));
+    }
+
+    #[test]
+    fn untouched_sessions_go_idle() {
+        let sessions = RestoreSessions::<()>::default();
+        sessions.insert(session(1, 0), ());
+        sessions.insert(session(2, 0), ());
+        let timeout = Duration::from_millis(50);
+        assert!(sessions.idle(Instant::now(), timeout).is_empty());
+
+        std::thread::sleep(timeout);
+        sessions.touch(2);
+        // Touching an unknown session is harmless
+        sessions.touch(9);
+        assert_eq!(sessions.idle(Instant::now(), timeout), [1]);
+        assert_eq!(
+            sessions.list().iter().map(|s| s.id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        let later = Instant::now() + IDLE_TIMEOUT;
+        assert_eq!(sessions.idle(later, IDLE_TIMEOUT).len(), 2);
+    }
+
+    #[test]
+    fn window_labels_round_trip() {
+        assert_eq!(window_label(7), "restore-7");
+        assert_eq!(session_for_window(&window_label(7)), Some(7));
+        assert_eq!(session_for_window("main"), None);
+        assert_eq!(session_for_window("restore-x"), None);
+    }
+
+    /// A restore instance ends through its shutdown endpoint, and whatever it started is
+    /// killed with it
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn ending_a_session_shuts_down_its_instance_and_helpers() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // The instance's HTTP side: records the request line and acknowledges it
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let read = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..read]).into_owned();
+                seen.lock()
+                    .push(request.lines().next().unwrap_or_default().to_string());
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+
+        // Its process side ignores the request, like a wedged server with a helper running
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "sleep 30 & wait"]);
+        let (mut rx, child) = proctree::spawn_grouped(command).unwrap();
+        let tree = ProcessTree::adopt_group(child.pid());
+
+        let sessions = RestoreSessions::default();
+        let id = sessions.reserve().unwrap();
+        sessions.insert(session(id, port), Instance { child, tree });
+        let (ended, instance) = sessions.take(id).unwrap();
+        let report = stop_instance(&crate::http::HttpClient::default(), ended.port, instance).await;
+
+        assert_eq!(*requests.lock(), ["POST /api/shutdown HTTP/1.1"]);
+        assert!(report.signalled.len() >= 2, "{:?}", report.signalled);
+        assert!(report.survivors.is_empty(), "{:?}", report.survivors);
+        let mut terminated = false;
+        while let Some(event) = rx.recv().await {
+            terminated |= matches!(event, CommandEvent::Terminated(_));
+        }
+        assert!(terminated);
+        assert!(sessions.list().is_empty());
+    }
+}
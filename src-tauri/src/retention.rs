@@ -0,0 +1,457 @@
+//! Retention for the files the desktop leaves behind.
+//!
+//! Crash reports, diagnostics, exported bundles, audit logs and quarantined documents (the
+//! `.corrupt` copies `persist` moves aside) would otherwise pile up forever. Each class has
+//! an age cap and a count cap; a file goes when it breaks either, newest files being kept
+//! first. Settings can override the caps per class, e.g. to keep audit logs longer.
+//! Quarantined files are evidence of data loss and are never deleted automatically unless
+//! the user opted in.
+//!
+//! Cleanup runs at startup and then daily. On an install that predates retention nothing
+//! is deleted automatically until the user has looked at `preview_retention_cleanup` and run
+//! a cleanup once; until then each automatic run only logs what it would delete.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// How often retention runs after the startup pass
+pub const INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Directory in the app data directory that crash reports are written to
+pub const CRASH_REPORTS_DIR: &str = "crash-reports";
+
+/// Directory in the app data directory that exported bundles are written to
+pub const EXPORTS_DIR: &str = "exports";
+
+/// Suffix of quarantined documents
+const QUARANTINE_SUFFIX: &str = ".corrupt";
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactClass {
+    CrashReports,
+    Diagnostics,
+    ExportedBundles,
+    AuditLogs,
+    Quarantined,
+}
+
+impl ArtifactClass {
+    pub const ALL: [ArtifactClass; 5] = [
+        ArtifactClass::CrashReports,
+        ArtifactClass::Diagnostics,
+        ArtifactClass::ExportedBundles,
+        ArtifactClass::AuditLogs,
+        ArtifactClass::Quarantined,
+    ];
+
+    /// Caps used when settings don't override them
+    pub fn default_rule(self) -> RetentionRule {
+        let (days, count) = match self {
+            ArtifactClass::CrashReports => (30, 20),
+            ArtifactClass::Diagnostics => (14, 10),
+            ArtifactClass::ExportedBundles => (30, 10),
+            ArtifactClass::AuditLogs => (90, 90),
+            ArtifactClass::Quarantined => (90, 20),
+        };
+        RetentionRule {
+            max_age_days: Some(days),
+            max_count: Some(count),
+        }
+    }
+
+    /// Whether files of this class are only deleted when the user opted in
+    pub fn protected(self) -> bool {
+        self == ArtifactClass::Quarantined
+    }
+}
+
+/// Caps for one class; None means unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionRule {
+    pub max_age_days: Option<u64>,
+    pub max_count: Option<usize>,
+}
+
+/// A file subject to retention
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub class: ArtifactClass,
+    pub bytes: u64,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    TooOld,
+    OverCount,
+}
+
+/// A file retention would delete, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedDeletion {
+    pub path: PathBuf,
+    pub class: ArtifactClass,
+    pub bytes: u64,
+    pub reason: Reason,
+}
+
+/// What a cleanup would do
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionPlan {
+    pub deletions: Vec<PlannedDeletion>,
+    /// Files past their caps that are kept because their class is protected
+    pub protected: Vec<PathBuf>,
+}
+
+/// Which of `artifacts` break their class's rule at `now`, with the reason. Age is checked
+/// first; the count cap then applies to what's left, newest first. Protected classes are
+/// returned separately unless `include_protected` is set.
+pub fn select(
+    artifacts: &[Artifact],
+    rules: &HashMap<ArtifactClass, RetentionRule>,
+    now: SystemTime,
+    include_protected: bool,
+) -> (Vec<(usize, Reason)>, Vec<usize>) {
+    let mut selected = Vec::new();
+    let mut protected = Vec::new();
+    for class in ArtifactClass::ALL {
+        let rule = rules
+            .get(&class)
+            .copied()
+            .unwrap_or_else(|| class.default_rule());
+        let mut members: Vec<usize> = (0..artifacts.len())
+            .filter(|index| artifacts[*index].class == class)
+            .collect();
+        members.sort_by_key(|index| std::cmp::Reverse(artifacts[*index].modified));
+
+        let mut kept = 0;
+        let mut expired = Vec::new();
+        for index in members {
+            let age = now
+                .duration_since(artifacts[index].modified)
+                .unwrap_or_default();
+            if matches!(rule.max_age_days, Some(days) if age.as_secs() > days * DAY) {
+                expired.push((index, Reason::TooOld));
+            } else if matches!(rule.max_count, Some(count) if kept >= count) {
+                expired.push((index, Reason::OverCount));
+            } else {
+                kept += 1;
+            }
+        }
+
+        if class.protected() && !include_protected {
+            protected.extend(expired.into_iter().map(|(index, _)| index));
+        } else {
+            selected.extend(expired);
+        }
+    }
+    (selected, protected)
+}
+
+fn files_in(dir: &Path, class: ArtifactClass, filter: impl Fn(&str) -> bool) -> Vec<Artifact> {
+    let Ok(children) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    children
+        .flatten()
+        .filter(|child| filter(&child.file_name().to_string_lossy()))
+        .filter_map(|child| {
+            let metadata = child.metadata().ok()?;
+            metadata.is_file().then(|| Artifact {
+                path: child.path(),
+                class,
+                bytes: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            })
+        })
+        .collect()
+}
+
+/// Every file retention looks after
+pub fn discover(paths: &crate::paths::Paths) -> Vec<Artifact> {
+    let diagnostics = paths
+        .cache_dir
+        .join(crate::scratch::SCRATCH_DIR)
+        .join(crate::scratch::ScratchPurpose::Diagnostics.dir_name());
+    let quarantined = |name: &str| name.ends_with(QUARANTINE_SUFFIX);
+    [
+        files_in(
+            &paths.data_dir.join(CRASH_REPORTS_DIR),
+            ArtifactClass::CrashReports,
+            |_| true,
+        ),
+        files_in(&diagnostics, ArtifactClass::Diagnostics, |_| true),
+        files_in(
+            &paths.data_dir.join(EXPORTS_DIR),
+            ArtifactClass::ExportedBundles,
+            |_| true,
+        ),
+        files_in(&paths.log_dir, ArtifactClass::AuditLogs, |name| {
+            name.contains("audit")
+        }),
+        files_in(&paths.config_dir, ArtifactClass::Quarantined, quarantined),
+        files_in(&paths.data_dir, ArtifactClass::Quarantined, quarantined),
+    ]
+    .concat()
+}
+
+/// What a cleanup under the current settings would delete
+pub fn plan(app: &tauri::AppHandle) -> RetentionPlan {
+    let settings = app.state::<crate::settings::SettingsStore>().get();
+    let artifacts = discover(app.state::<crate::AppState>().paths());
+    let (selected, protected) = select(
+        &artifacts,
+        &settings.retention_rules,
+        SystemTime::now(),
+        settings.retention_delete_quarantined,
+    );
+    RetentionPlan {
+        deletions: selected
+            .into_iter()
+            .map(|(index, reason)| {
+                let artifact = &artifacts[index];
+                PlannedDeletion {
+                    path: artifact.path.clone(),
+                    class: artifact.class,
+                    bytes: artifact.bytes,
+                    reason,
+                }
+            })
+            .collect(),
+        protected: protected
+            .into_iter()
+            .map(|index| artifacts[index].path.clone())
+            .collect(),
+    }
+}
+
+/// Delete what `plan` selected, logging each deletion to the audit trail. Returns the
+/// number of files deleted.
+pub fn apply(plan: &RetentionPlan) -> usize {
+    let mut deleted = 0;
+    for deletion in &plan.deletions {
+        match std::fs::remove_file(&deletion.path) {
+            Ok(()) => {
+                deleted += 1;
+                info!(
+                    target: "zerobyte::audit",
+                    "Retention deleted {} ({:?}, {:?}, {} bytes)",
+                    deletion.path.display(),
+                    deletion.class,
+                    deletion.reason,
+                    deletion.bytes
+                );
+            }
+            Err(e) => warn!("Failed to delete {}: {}", deletion.path.display(), e),
+        }
+    }
+    deleted
+}
+
+/// Apply retention at startup and then daily, for as long as the app runs
+pub async fn run(app: tauri::AppHandle) {
+    let mut ticker = app.state::<crate::AppState>().activity.register(
+        "retention",
+        crate::activity::TaskClass::Optional,
+        INTERVAL,
+    );
+    loop {
+        let confirmed = app
+            .state::<crate::settings::SettingsStore>()
+            .get()
+            .retention_confirmed;
+        let handle = app.clone();
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            let plan = plan(&handle);
+            if !confirmed {
+                return (plan.deletions.len(), None);
+            }
+            (plan.deletions.len(), Some(apply(&plan)))
+        })
+        .await;
+        match result {
+            Ok((planned, None)) if planned > 0 => info!(
+                "Retention would delete {} file(s); waiting for the user to review the cleanup",
+                planned
+            ),
+            Ok((_, Some(deleted))) if deleted > 0 => {
+                info!("Retention deleted {} file(s)", deleted)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Retention run failed: {}", e),
+        }
+        ticker.tick().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1000 * DAY)
+    }
+
+    /// A file of `class` last modified `age_days` ago
+    fn file(name: &str, class: ArtifactClass, age_days: u64) -> Artifact {
+        Artifact {
+            path: PathBuf::from(name),
+            class,
+            bytes: 1024,
+            modified: now() - Duration::from_secs(age_days * DAY),
+        }
+    }
+
+    fn rules(
+        class: ArtifactClass,
+        max_age_days: Option<u64>,
+        max_count: Option<usize>,
+    ) -> HashMap<ArtifactClass, RetentionRule> {
+        HashMap::from([(
+            class,
+            RetentionRule {
+                max_age_days,
+                max_count,
+            },
+        )])
+    }
+
+    /// Selected names with their reasons, in a stable order
+    fn names(artifacts: &[Artifact], selected: &[(usize, Reason)]) -> Vec<(String, Reason)> {
+        let mut names: Vec<_> = selected
+            .iter()
+            .map(|(index, reason)| (artifacts[*index].path.display().to_string(), *reason))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        names
+    }
+
+    #[test]
+    fn old_files_go_regardless_of_count() {
+        let artifacts = vec![
+            file("a", ArtifactClass::CrashReports, 1),
+            file("b", ArtifactClass::CrashReports, 30),
+            file("c", ArtifactClass::CrashReports, 31),
+        ];
+        let (selected, protected) = select(&artifacts, &HashMap::new(), now(), false);
+        // Exactly at the cap is still kept
+        assert_eq!(
+            names(&artifacts, &selected),
+            vec![("c".into(), Reason::TooOld)]
+        );
+        assert!(protected.is_empty());
+    }
+
+    #[test]
+    fn count_cap_keeps_the_newest() {
+        let artifacts: Vec<_> = [5, 1, 4, 2, 3]
+            .iter()
+            .map(|age| file(&format!("day-{}", age), ArtifactClass::Diagnostics, *age))
+            .collect();
+        let rules = rules(ArtifactClass::Diagnostics, None, Some(3));
+        let (selected, _) = select(&artifacts, &rules, now(), false);
+        assert_eq!(
+            names(&artifacts, &selected),
+            vec![
+                ("day-4".into(), Reason::OverCount),
+                ("day-5".into(), Reason::OverCount),
+            ]
+        );
+    }
+
+    #[test]
+    fn expired_files_dont_use_up_the_count() {
+        // Two fresh files and three old ones, keep at most three: the old ones go for their
+        // age, and the fresh ones fit the count
+        let artifacts = vec![
+            file("fresh-1", ArtifactClass::ExportedBundles, 1),
+            file("fresh-2", ArtifactClass::ExportedBundles, 2),
+            file("old-1", ArtifactClass::ExportedBundles, 40),
+            file("old-2", ArtifactClass::ExportedBundles, 50),
+            file("old-3", ArtifactClass::ExportedBundles, 60),
+        ];
+        let rules = rules(ArtifactClass::ExportedBundles, Some(30), Some(3));
+        let (selected, _) = select(&artifacts, &rules, now(), false);
+        let reasons: Vec<_> = names(&artifacts, &selected);
+        assert_eq!(reasons.len(), 3);
+        assert!(reasons
+            .iter()
+            .all(|(name, reason)| name.starts_with("old") && *reason == Reason::TooOld));
+    }
+
+    #[test]
+    fn classes_are_capped_separately() {
+        let artifacts = vec![
+            file("crash", ArtifactClass::CrashReports, 1),
+            file("diag-1", ArtifactClass::Diagnostics, 1),
+            file("diag-2", ArtifactClass::Diagnostics, 2),
+        ];
+        let mut rules = rules(ArtifactClass::Diagnostics, None, Some(1));
+        rules.insert(
+            ArtifactClass::CrashReports,
+            RetentionRule {
+                max_age_days: None,
+                max_count: Some(1),
+            },
+        );
+        let (selected, _) = select(&artifacts, &rules, now(), false);
+        assert_eq!(
+            names(&artifacts, &selected),
+            vec![("diag-2".into(), Reason::OverCount)]
+        );
+    }
+
+    #[test]
+    fn unlimited_rule_keeps_everything() {
+        let artifacts: Vec<_> = (0..500)
+            .map(|age| file(&age.to_string(), ArtifactClass::AuditLogs, age))
+            .collect();
+        let rules = rules(ArtifactClass::AuditLogs, None, None);
+        assert!(select(&artifacts, &rules, now(), false).0.is_empty());
+    }
+
+    #[test]
+    fn quarantined_files_need_the_opt_in() {
+        let artifacts = vec![
+            file("settings.json.corrupt", ArtifactClass::Quarantined, 200),
+            file("report", ArtifactClass::CrashReports, 200),
+        ];
+        let (selected, protected) = select(&artifacts, &HashMap::new(), now(), false);
+        assert_eq!(
+            names(&artifacts, &selected),
+            vec![("report".into(), Reason::TooOld)]
+        );
+        assert_eq!(protected, vec![0]);
+
+        let (selected, protected) = select(&artifacts, &HashMap::new(), now(), true);
+        assert_eq!(selected.len(), 2);
+        assert!(protected.is_empty());
+    }
+
+    #[test]
+    fn files_from_the_future_count_as_new() {
+        // A clock set back must not make every file look ancient
+        let mut artifact = file("report", ArtifactClass::CrashReports, 0);
+        artifact.modified = now() + Duration::from_secs(365 * DAY);
+        assert!(select(&[artifact], &HashMap::new(), now(), false)
+            .0
+            .is_empty());
+    }
+
+    #[test]
+    fn only_quarantined_classes_are_protected() {
+        for class in ArtifactClass::ALL {
+            assert_eq!(class.protected(), class == ArtifactClass::Quarantined);
+        }
+    }
+}
@@ -0,0 +1,490 @@
+//! Checking and repairing the files the desktop keeps while it runs.
+//!
+//! The discovery file, the outbox and the watcher registrations are rewritten while the app
+//! runs, so a crash or a power cut can leave them truncated or pointing at a process that no
+//! longer exists. Each file is described by an [`Artifact`] in [`ARTIFACTS`]: how to find it,
+//! how to check it, and how to put it right. A repair moves a file that doesn't parse to
+//! `runtime-recovered/` in the data directory before writing a good one in its place.
+//!
+//! The check runs from `verify_runtime_state`, and with repair on at startup when the
+//! marker written by the previous run is still there, i.e. it never reached the end of the
+//! shutdown sequence.
+
+use crate::AppState;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// Directory under the data directory that damaged files are moved to
+pub const RECOVERED_DIR: &str = "runtime-recovered";
+
+/// Present in the data directory while the app runs; removed once shutdown completes
+pub const RUNNING_MARKER: &str = "running.marker";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactStatus {
+    Ok,
+    /// Not there, which is fine for all of them
+    Missing,
+    /// Doesn't parse
+    Corrupt,
+    /// Parses, but breaks an invariant
+    Inconsistent,
+}
+
+/// What the check found for one file, and what a repair did about it
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtifactReport {
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub status: ArtifactStatus,
+    pub problems: Vec<String>,
+    pub repaired: bool,
+    /// Where the damaged file was moved
+    pub quarantined_to: Option<PathBuf>,
+}
+
+/// A file kept while the app runs
+pub struct Artifact {
+    pub name: &'static str,
+    path: fn(&tauri::AppHandle) -> PathBuf,
+    /// Err if the contents don't parse, otherwise the invariants they break
+    check: fn(&tauri::AppHandle, &[u8]) -> Result<Vec<String>, String>,
+    /// Write a good file in place of a damaged one
+    repair: fn(&tauri::AppHandle) -> Result<(), String>,
+}
+
+pub const ARTIFACTS: &[Artifact] = &[
+    Artifact {
+        name: "discovery",
+        path: |_| crate::ownership::discovery_path(),
+        check: check_discovery,
+        repair: repair_discovery,
+    },
+    Artifact {
+        name: "outbox",
+        path: |app| data_file(app, crate::outbox::OUTBOX_FILE),
+        check: |_, bytes| crate::outbox::verify(bytes, now_secs()),
+        repair: repair_outbox,
+    },
+    Artifact {
+        name: "watchers",
+        path: |app| data_file(app, crate::watch::WATCHERS_FILE),
+        check: |_, bytes| crate::watch::verify(bytes),
+        repair: repair_watchers,
+    },
+];
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn data_file(app: &tauri::AppHandle, name: &str) -> PathBuf {
+    app.state::<AppState>().paths().data_dir.join(name)
+}
+
+/// Port of the sidecar this instance runs itself, if it runs one
+fn owns_sidecar(app: &tauri::AppHandle) -> Option<u16> {
+    let state = app.state::<AppState>();
+    let running = state.sidecar_handle.blocking_lock().is_some()
+        || state.adopted_sidecar.lock().is_some();
+    (running && !state.using_service.load(Ordering::SeqCst))
+        .then(|| state.backend_port.load(Ordering::SeqCst))
+}
+
+fn check_discovery(app: &tauri::AppHandle, bytes: &[u8]) -> Result<Vec<String>, String> {
+    let owner: crate::ownership::BackendOwner =
+        serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+    if owner.is_current_process() {
+        if owns_sidecar(app).is_none() {
+            problems.push("names this instance, which runs no sidecar".to_string());
+        }
+    } else if !crate::proctree::is_alive(owner.pid) {
+        problems.push(format!("owner process {} is no longer running", owner.pid));
+    }
+    Ok(problems)
+}
+
+/// Claim the file again if we run the sidecar, otherwise remove it
+fn repair_discovery(app: &tauri::AppHandle) -> Result<(), String> {
+    if let Some(port) = owns_sidecar(app) {
+        return crate::ownership::claim(port);
+    }
+    let path = crate::ownership::discovery_path();
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to remove {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn repair_outbox(app: &tauri::AppHandle) -> Result<(), String> {
+    let expired = app.state::<crate::outbox::Outbox>().repair(now_secs())?;
+    for entry in expired {
+        warn!("Backend action {} expired before it could be applied", entry.id);
+        crate::events::emit(app, "backend-action-expired", entry);
+    }
+    Ok(())
+}
+
+fn repair_watchers(app: &tauri::AppHandle) -> Result<(), String> {
+    let dropped = app.state::<crate::watch::WatcherManager>().repair()?;
+    if dropped > 0 {
+        info!("Dropped {} invalid watcher registration(s)", dropped);
+    }
+    Ok(())
+}
+
+/// Move a damaged file into the recovered directory `dir`
+fn quarantine(dir: &Path, name: &str, path: &Path) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let target = dir.join(format!("{}-{}", name, now_secs()));
+    // A rename fails across volumes (the discovery file lives outside the data directory)
+    std::fs::rename(path, &target)
+        .or_else(|_| std::fs::copy(path, &target).and_then(|_| std::fs::remove_file(path)))
+        .map_err(|e| format!("Failed to move {} aside: {}", path.display(), e))?;
+    Ok(target)
+}
+
+/// Check the file `name` at `path` and, with `repair`, put it right: a file that doesn't
+/// parse is moved to `recovered` first, then `fix` writes a good one
+fn verify_file(
+    name: &'static str,
+    path: PathBuf,
+    recovered: &Path,
+    repair: bool,
+    check: impl FnOnce(&[u8]) -> Result<Vec<String>, String>,
+    fix: impl FnOnce() -> Result<(), String>,
+) -> ArtifactReport {
+    let (status, mut problems) = match std::fs::read(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (ArtifactStatus::Missing, vec![]),
+        Err(e) => (ArtifactStatus::Corrupt, vec![format!("unreadable: {}", e)]),
+        Ok(bytes) => match check(&bytes) {
+            Err(e) => (ArtifactStatus::Corrupt, vec![e]),
+            Ok(problems) if problems.is_empty() => (ArtifactStatus::Ok, problems),
+            Ok(problems) => (ArtifactStatus::Inconsistent, problems),
+        },
+    };
+
+    let mut report = ArtifactReport {
+        name,
+        path: path.clone(),
+        status,
+        problems: Vec::new(),
+        repaired: false,
+        quarantined_to: None,
+    };
+    if repair && matches!(status, ArtifactStatus::Corrupt | ArtifactStatus::Inconsistent) {
+        let quarantined = match status {
+            ArtifactStatus::Corrupt => quarantine(recovered, name, &path).map(Some),
+            _ => Ok(None),
+        };
+        match quarantined.and_then(|to| fix().map(|()| to)) {
+            Ok(to) => {
+                report.repaired = true;
+                report.quarantined_to = to;
+            }
+            Err(e) => problems.push(format!("repair failed: {}", e)),
+        }
+    }
+    report.problems = problems;
+    report
+}
+
+fn verify_artifact(app: &tauri::AppHandle, artifact: &Artifact, repair: bool) -> ArtifactReport {
+    verify_file(
+        artifact.name,
+        (artifact.path)(app),
+        &data_file(app, RECOVERED_DIR),
+        repair,
+        |bytes| (artifact.check)(app, bytes),
+        || (artifact.repair)(app),
+    )
+}
+
+/// Check every runtime file and, with `repair`, fix the ones that are damaged
+pub fn verify(app: &tauri::AppHandle, repair: bool) -> Vec<ArtifactReport> {
+    let reports: Vec<_> = ARTIFACTS
+        .iter()
+        .map(|artifact| verify_artifact(app, artifact, repair))
+        .collect();
+    for report in &reports {
+        match report.status {
+            ArtifactStatus::Ok | ArtifactStatus::Missing => {}
+            _ => warn!(
+                "Runtime file {} ({}) is {:?}: {}{}",
+                report.name,
+                report.path.display(),
+                report.status,
+                report.problems.join("; "),
+                if report.repaired { ", repaired" } else { "" }
+            ),
+        }
+    }
+    if repair {
+        info!(
+            target: "zerobyte::audit",
+            "Runtime files verified with repair ({} repaired)",
+            reports.iter().filter(|report| report.repaired).count()
+        );
+    }
+    reports
+}
+
+/// Write the running marker; returns whether the previous run left its marker behind
+pub fn mark_running(data_dir: &Path) -> bool {
+    let marker = data_dir.join(RUNNING_MARKER);
+    let unclean = marker.exists();
+    if let Err(e) = std::fs::write(&marker, std::process::id().to_string()) {
+        warn!("Failed to write {}: {}", marker.display(), e);
+    }
+    unclean
+}
+
+/// Remove the running marker once shutdown completed
+pub fn clear_running(data_dir: &Path) {
+    let _ = std::fs::remove_file(data_dir.join(RUNNING_MARKER));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000;
+
+    fn outbox_check(bytes: &[u8]) -> Result<Vec<String>, String> {
+        crate::outbox::verify(bytes, NOW)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("runtime-state-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const OUTBOX_OK: &str = r#"{"next_id":2,"entries":[
+        {"id":1,"action":{"type":"pause_backups"},"enqueued_at":900,"expires_at":2000},
+        {"id":2,"action":{"type":"acknowledge_failure","event_id":"e1"},
+         "enqueued_at":900,"expires_at":2000}
+    ]}"#;
+    const OUTBOX_EXPIRED: &str = r#"{"next_id":2,"entries":[
+        {"id":1,"action":{"type":"pause_backups"},"enqueued_at":0,"expires_at":500},
+        {"id":2,"action":{"type":"resume_backups"},"enqueued_at":900,"expires_at":2000}
+    ]}"#;
+    const OUTBOX_DUPLICATE: &str = r#"{"next_id":1,"entries":[
+        {"id":1,"action":{"type":"pause_backups"},"enqueued_at":900,"expires_at":2000},
+        {"id":1,"action":{"type":"resume_backups"},"enqueued_at":900,"expires_at":2000},
+        {"id":5,"action":{"type":"resume_backups"},"enqueued_at":900,"expires_at":2000}
+    ]}"#;
+    const WATCHERS_OK: &str = r#"{"registrations":[
+        {"plan_id":1,"paths":["/a"],"debounce_secs":30},
+        {"plan_id":2,"paths":["/b"],"debounce_secs":30}
+    ]}"#;
+    const WATCHERS_DUPLICATE: &str = r#"{"registrations":[
+        {"plan_id":1,"paths":["/a"],"debounce_secs":30},
+        {"plan_id":1,"paths":["/b"],"debounce_secs":60},
+        {"plan_id":2,"paths":[],"debounce_secs":30}
+    ]}"#;
+
+    type Check = fn(&[u8]) -> Result<Vec<String>, String>;
+
+    #[test]
+    fn validators_classify_fixtures() {
+        use ArtifactStatus::*;
+        // (validator, fixture, status, problems)
+        let cases: &[(Check, &str, ArtifactStatus, &[&str])] = &[
+            (outbox_check, OUTBOX_OK, Ok, &[]),
+            (outbox_check, "{}", Ok, &[]),
+            (outbox_check, "", Corrupt, &[]),
+            (outbox_check, &OUTBOX_OK[..40], Corrupt, &[]),
+            (outbox_check, r#"{"entries":[{"id":"x"}]}"#, Corrupt, &[]),
+            (outbox_check, "\0\0\0\0", Corrupt, &[]),
+            (
+                outbox_check,
+                OUTBOX_EXPIRED,
+                Inconsistent,
+                &["1 expired entries still queued"],
+            ),
+            (
+                outbox_check,
+                OUTBOX_DUPLICATE,
+                Inconsistent,
+                &[
+                    "entry id 1 is used more than once",
+                    "entry id 5 is ahead of the id counter",
+                ],
+            ),
+            (crate::watch::verify, WATCHERS_OK, Ok, &[]),
+            (crate::watch::verify, &WATCHERS_OK[..30], Corrupt, &[]),
+            (
+                crate::watch::verify,
+                r#"{"registrations":{}}"#,
+                Corrupt,
+                &[],
+            ),
+            (
+                crate::watch::verify,
+                WATCHERS_DUPLICATE,
+                Inconsistent,
+                &["plan 1 is registered more than once", "plan 2 has no paths"],
+            ),
+        ];
+        let dir = temp_dir("fixtures");
+        for (n, (check, fixture, status, problems)) in cases.iter().enumerate() {
+            let path = dir.join(format!("fixture-{}.json", n));
+            std::fs::write(&path, fixture).unwrap();
+            let report = verify_file("fixture", path, &dir, false, check, || {
+                panic!("repaired without being asked")
+            });
+            assert_eq!(report.status, *status, "fixture {}: {}", n, fixture);
+            if *status != Corrupt {
+                assert_eq!(report.problems, *problems, "fixture {}", n);
+            } else {
+                assert_eq!(report.problems.len(), 1, "fixture {}", n);
+            }
+            assert!(!report.repaired);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_files_are_fine_and_left_alone() {
+        let dir = temp_dir("missing");
+        let report = verify_file(
+            "outbox",
+            dir.join("outbox.json"),
+            &dir.join(RECOVERED_DIR),
+            true,
+            outbox_check,
+            || panic!("a missing file needs no repair"),
+        );
+        assert_eq!(report.status, ArtifactStatus::Missing);
+        assert!(!report.repaired);
+        assert!(!dir.join(RECOVERED_DIR).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_outbox_is_quarantined_and_rewritten() {
+        let dir = temp_dir("corrupt-outbox");
+        let path = dir.join("outbox.json");
+        std::fs::write(&path, OUTBOX_OK).unwrap();
+        let outbox = crate::outbox::Outbox::load(path.clone());
+        // Torn after the queue was loaded
+        let damaged = &OUTBOX_OK[..60];
+        std::fs::write(&path, damaged).unwrap();
+        let recovered = dir.join(RECOVERED_DIR);
+
+        let report = verify_file(
+            "outbox",
+            path.clone(),
+            &recovered,
+            true,
+            outbox_check,
+            || outbox.repair(NOW).map(|_| ()),
+        );
+        assert_eq!(report.status, ArtifactStatus::Corrupt);
+        assert!(report.repaired, "{:?}", report.problems);
+        let quarantined = report.quarantined_to.unwrap();
+        assert!(quarantined.starts_with(&recovered));
+        assert_eq!(std::fs::read_to_string(&quarantined).unwrap(), damaged);
+
+        let again = verify_file("outbox", path, &recovered, false, outbox_check, || Ok(()));
+        assert_eq!(again.status, ArtifactStatus::Ok);
+        assert_eq!(outbox.pending().len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn inconsistent_files_are_repaired_in_place() {
+        let dir = temp_dir("inconsistent");
+        let recovered = dir.join(RECOVERED_DIR);
+
+        let outbox_path = dir.join("outbox.json");
+        std::fs::write(&outbox_path, OUTBOX_DUPLICATE).unwrap();
+        let outbox = crate::outbox::Outbox::load(outbox_path.clone());
+        let report = verify_file(
+            "outbox",
+            outbox_path.clone(),
+            &recovered,
+            true,
+            outbox_check,
+            || outbox.repair(NOW).map(|_| ()),
+        );
+        assert_eq!(report.status, ArtifactStatus::Inconsistent);
+        assert!(report.repaired);
+        assert_eq!(report.quarantined_to, None);
+        let ids: Vec<u64> = outbox.pending().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [1, 5]);
+
+        let watchers_path = dir.join("watchers.json");
+        std::fs::write(&watchers_path, WATCHERS_DUPLICATE).unwrap();
+        let watchers = crate::watch::WatcherManager::load(watchers_path.clone());
+        let report = verify_file(
+            "watchers",
+            watchers_path.clone(),
+            &recovered,
+            true,
+            crate::watch::verify,
+            || watchers.repair().map(|_| ()),
+        );
+        assert!(report.repaired);
+        let kept: Vec<(u64, u64)> = watchers
+            .registrations()
+            .iter()
+            .map(|r| (r.plan_id, r.debounce_secs))
+            .collect();
+        assert_eq!(kept, [(1, 60)]);
+
+        for (path, check) in [
+            (outbox_path, outbox_check as Check),
+            (watchers_path, crate::watch::verify),
+        ] {
+            let again = verify_file("again", path, &recovered, false, check, || Ok(()));
+            assert_eq!(again.status, ArtifactStatus::Ok, "{:?}", again.problems);
+        }
+        assert!(!recovered.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_repair_is_reported() {
+        let dir = temp_dir("failed");
+        let path = dir.join("watchers.json");
+        std::fs::write(&path, WATCHERS_DUPLICATE).unwrap();
+        let report = verify_file("watchers", path, &dir, true, crate::watch::verify, || {
+            Err("disk full".to_string())
+        });
+        assert!(!report.repaired);
+        assert_eq!(
+            report.problems.last().map(String::as_str),
+            Some("repair failed: disk full")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn marker_left_behind_means_an_unclean_shutdown() {
+        let dir = temp_dir("marker");
+        assert!(!mark_running(&dir));
+        assert!(dir.join(RUNNING_MARKER).exists());
+        // The previous run never cleared it
+        assert!(mark_running(&dir));
+        clear_running(&dir);
+        assert!(!dir.join(RUNNING_MARKER).exists());
+        assert!(!mark_running(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
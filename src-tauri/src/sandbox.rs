@@ -0,0 +1,459 @@
+//! Opt-in confinement of the sidecar on Linux.
+//!
+//! With the `sandbox` setting on, the sidecar is launched in layers:
+//! - under systemd, in a transient user scope (`systemd-run --user --scope`) whose cgroup only
+//!   allows traffic to loopback and the configured repository hosts, with a task limit;
+//! - where unprivileged user namespaces work, in a private mount namespace (`unshare`) with its
+//!   own `/tmp`, the root mount read-only and only the data directory writable.
+//!
+//! Scopes only accept resource-control properties, so the filesystem part comes from the
+//! namespace layer rather than `ProtectSystem`. Whatever layers are available are used; if
+//! none are, the sidecar runs unconfined and a `sandbox-unavailable` event says why.
+
+use serde::Serialize;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+
+/// Most processes and threads the sidecar may have inside the scope
+const TASKS_MAX: u32 = 512;
+
+/// What the settings ask the sandbox to allow
+#[derive(Debug, Clone, Default)]
+pub struct SandboxPolicy {
+    /// Repository hosts the sidecar may reach besides loopback (names or addresses)
+    pub allowed_hosts: Vec<String>,
+    /// Directories the sidecar may write to
+    pub writable_paths: Vec<PathBuf>,
+}
+
+/// Confinement the running sidecar actually got
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Confinement {
+    /// Transient systemd scope the sidecar runs in
+    pub systemd_scope: Option<String>,
+    /// Properties set on that scope
+    pub scope_properties: Vec<String>,
+    /// Whether it runs in a private mount namespace with a read-only root
+    pub mount_namespace: bool,
+    /// Directories left writable inside the namespace
+    pub writable_paths: Vec<PathBuf>,
+}
+
+impl Confinement {
+    pub fn is_confined(&self) -> bool {
+        self.systemd_scope.is_some() || self.mount_namespace
+    }
+}
+
+/// Which sandboxing tools this system offers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SandboxSupport {
+    pub systemd_scope: bool,
+    pub user_namespaces: bool,
+}
+
+impl SandboxSupport {
+    pub fn any(self) -> bool {
+        self.systemd_scope || self.user_namespaces
+    }
+}
+
+/// How to launch the sidecar under confinement
+#[derive(Debug, Clone)]
+pub struct LaunchPlan {
+    pub program: String,
+    pub args: Vec<String>,
+    pub confinement: Confinement,
+}
+
+/// Scope properties for `policy`. Host names are resolved now, since `IPAddressAllow` only
+/// takes addresses; names that don't resolve are skipped with a warning.
+pub fn scope_properties(policy: &SandboxPolicy) -> Vec<String> {
+    let mut properties = vec![
+        "IPAddressDeny=any".to_string(),
+        "IPAddressAllow=localhost".to_string(),
+    ];
+    for host in &policy.allowed_hosts {
+        match (host.as_str(), 0).to_socket_addrs() {
+            Ok(addrs) => {
+                let mut ips: Vec<_> = addrs.map(|addr| addr.ip()).collect();
+                ips.sort();
+                ips.dedup();
+                properties.extend(ips.into_iter().map(|ip| format!("IPAddressAllow={}", ip)));
+            }
+            Err(e) => tracing::warn!("Sandbox: cannot resolve allowed host {}: {}", host, e),
+        }
+    }
+    properties.push(format!("TasksMax={}", TASKS_MAX));
+    properties
+}
+
+/// Quote `value` for `sh`
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Shell script run inside the mount namespace before exec'ing the server: a private `/tmp`,
+/// bind mounts keeping the writable paths writable, then the root mount read-only. Writable
+/// paths that can't be created (e.g. a system directory the user can't write) are skipped.
+fn namespace_script(server: &Path, writable: &[PathBuf]) -> String {
+    let mut lines = vec![
+        "set -e".to_string(),
+        "mount --make-rprivate /".to_string(),
+        "mount -t tmpfs tmpfs /tmp".to_string(),
+    ];
+    for path in writable {
+        let path = shell_quote(&path.to_string_lossy());
+        lines.push(format!(
+            "if mkdir -p {0} 2>/dev/null; then mount --bind {0} {0}; fi",
+            path
+        ));
+    }
+    lines.push("mount -o remount,bind,ro /".to_string());
+    lines.push(format!("exec {}", shell_quote(&server.to_string_lossy())));
+    lines.join("\n")
+}
+
+/// Build the launch command for `server` from the policy and what the system supports.
+/// Returns None when nothing can be confined.
+pub fn plan(
+    server: &Path,
+    policy: &SandboxPolicy,
+    support: SandboxSupport,
+) -> Option<LaunchPlan> {
+    if !support.any() {
+        return None;
+    }
+
+    let mut program = None;
+    let mut args = Vec::new();
+    let mut confinement = Confinement::default();
+
+    if support.systemd_scope {
+        let unit = format!("zerobyte-server-{}.scope", std::process::id());
+        program = Some("systemd-run".to_string());
+        args.extend(["--user", "--scope", "--quiet", "--collect"].map(String::from));
+        args.push(format!("--unit={}", unit));
+        let properties = scope_properties(policy);
+        for property in &properties {
+            args.push("-p".to_string());
+            args.push(property.clone());
+        }
+        args.push("--".to_string());
+        confinement.systemd_scope = Some(unit);
+        confinement.scope_properties = properties;
+    }
+
+    if support.user_namespaces {
+        let unshare = ["unshare", "--user", "--map-current-user", "--mount"].map(String::from);
+        if program.is_none() {
+            program = Some(unshare[0].clone());
+            args.extend(unshare[1..].iter().cloned());
+        } else {
+            args.extend(unshare);
+        }
+        args.extend(["sh", "-c"].map(String::from));
+        args.push(namespace_script(server, &policy.writable_paths));
+        confinement.mount_namespace = true;
+        confinement.writable_paths = policy.writable_paths.clone();
+    } else {
+        args.push(server.to_string_lossy().into_owned());
+    }
+
+    Some(LaunchPlan {
+        program: program?,
+        args,
+        confinement,
+    })
+}
+
+/// Find `program` on PATH
+#[cfg(target_os = "linux")]
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Detect the sandboxing tools this system offers
+#[cfg(target_os = "linux")]
+pub fn detect() -> SandboxSupport {
+    // A user manager is reachable when systemd is PID 1 and we have a runtime dir
+    let systemd_scope = Path::new("/run/systemd/system").exists()
+        && std::env::var_os("XDG_RUNTIME_DIR").is_some()
+        && on_path("systemd-run");
+
+    // Debian-style kernels can switch unprivileged user namespaces off
+    let userns_enabled = std::fs::read_to_string("/proc/sys/kernel/unprivileged_userns_clone")
+        .map(|value| value.trim() != "0")
+        .unwrap_or(true);
+    let user_namespaces = userns_enabled && on_path("unshare") && on_path("mount");
+
+    SandboxSupport {
+        systemd_scope,
+        user_namespaces,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect() -> SandboxSupport {
+    SandboxSupport::default()
+}
+
+/// Directories the sidecar writes to: its data directory (see the server's
+/// `getZerobytePath()`, or `configured_data_dir` from `zerobyte.toml`) and the desktop's own
+/// data directory
+pub fn writable_paths(desktop_data_dir: &Path, configured_data_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut paths = vec![desktop_data_dir.to_path_buf()];
+    if let Some(dir) = std::env::var_os(crate::paths::DATA_DIR_ENV) {
+        paths.push(PathBuf::from(dir));
+    } else if let Some(dir) = configured_data_dir {
+        paths.push(dir.to_path_buf());
+    } else {
+        paths.push(PathBuf::from("/var/lib/c3i-backup-one"));
+        if let Some(home) = std::env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".local/share/c3i-backup-one"));
+        }
+    }
+    paths
+}
+
+/// Path of the bundled sidecar binary, which Tauri installs next to the app executable
+pub fn sidecar_path() -> std::io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    let dir = exe
+        .parent()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "executable has no parent directory",
+            )
+        })?;
+    Ok(dir.join(format!("zerobyte-server{}", std::env::consts::EXE_SUFFIX)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOTH: SandboxSupport = SandboxSupport {
+        systemd_scope: true,
+        user_namespaces: true,
+    };
+
+    fn policy(hosts: &[&str], writable: &[&str]) -> SandboxPolicy {
+        SandboxPolicy {
+            allowed_hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            writable_paths: writable.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn scope_properties_allow_loopback_and_the_repository_hosts() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&[], &[]),
+            (&["192.0.2.10"], &["IPAddressAllow=192.0.2.10"]),
+            // Hosts keep their configured order
+            (
+                &["198.51.100.7", "192.0.2.10"],
+                &["IPAddressAllow=198.51.100.7", "IPAddressAllow=192.0.2.10"],
+            ),
+            (&["2001:db8::1"], &["IPAddressAllow=2001:db8::1"]),
+        ];
+        for (hosts, allowed) in cases {
+            let mut expected = vec!["IPAddressDeny=any", "IPAddressAllow=localhost"];
+            expected.extend(allowed.iter());
+            let tasks = format!("TasksMax={}", TASKS_MAX);
+            expected.push(&tasks);
+            assert_eq!(
+                scope_properties(&policy(hosts, &[])),
+                expected,
+                "{:?}",
+                hosts
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_is_planned_without_any_support() {
+        let plan = plan(
+            Path::new("/opt/zerobyte-server"),
+            &policy(&[], &["/data"]),
+            SandboxSupport::default(),
+        );
+        assert!(plan.is_none());
+    }
+
+    #[test]
+    fn scope_alone_runs_the_server_directly() {
+        let support = SandboxSupport {
+            systemd_scope: true,
+            user_namespaces: false,
+        };
+        let plan = plan(
+            Path::new("/opt/zerobyte-server"),
+            &policy(&[], &[]),
+            support,
+        )
+        .unwrap();
+        assert_eq!(plan.program, "systemd-run");
+        assert_eq!(
+            &plan.args[..4],
+            ["--user", "--scope", "--quiet", "--collect"]
+        );
+        let unit = plan.confinement.systemd_scope.clone().unwrap();
+        assert!(plan.args.contains(&format!("--unit={}", unit)));
+        // Each property follows its own -p
+        for property in &plan.confinement.scope_properties {
+            let at = plan.args.iter().position(|arg| arg == property).unwrap();
+            assert_eq!(plan.args[at - 1], "-p");
+        }
+        assert_eq!(
+            plan.args[plan.args.len() - 2..],
+            ["--", "/opt/zerobyte-server"]
+        );
+        assert!(!plan.confinement.mount_namespace);
+        assert!(plan.confinement.is_confined());
+    }
+
+    #[test]
+    fn namespace_alone_wraps_the_server_in_unshare() {
+        let support = SandboxSupport {
+            systemd_scope: false,
+            user_namespaces: true,
+        };
+        let plan = plan(
+            Path::new("/opt/zerobyte-server"),
+            &policy(&[], &["/data"]),
+            support,
+        )
+        .unwrap();
+        assert_eq!(plan.program, "unshare");
+        assert_eq!(
+            plan.args[..5],
+            ["--user", "--map-current-user", "--mount", "sh", "-c"]
+        );
+        assert_eq!(plan.args.len(), 6);
+        assert!(plan.confinement.systemd_scope.is_none());
+        assert_eq!(plan.confinement.writable_paths, [PathBuf::from("/data")]);
+    }
+
+    #[test]
+    fn both_layers_nest_the_namespace_inside_the_scope() {
+        let plan = plan(
+            Path::new("/opt/zerobyte-server"),
+            &policy(&["192.0.2.10"], &["/data"]),
+            BOTH,
+        )
+        .unwrap();
+        assert_eq!(plan.program, "systemd-run");
+        let separator = plan.args.iter().position(|arg| arg == "--").unwrap();
+        assert_eq!(
+            plan.args[separator + 1..separator + 6],
+            ["unshare", "--user", "--map-current-user", "--mount", "sh"]
+        );
+        assert!(plan
+            .confinement
+            .scope_properties
+            .contains(&"IPAddressAllow=192.0.2.10".to_string()));
+        assert!(plan.confinement.mount_namespace);
+    }
+
+    #[test]
+    fn namespace_script_quotes_paths_and_ends_read_only() {
+        let script = namespace_script(
+            Path::new("/opt/it's/zerobyte-server"),
+            &[PathBuf::from("/home/a b/data")],
+        );
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "set -e",
+                "mount --make-rprivate /",
+                "mount -t tmpfs tmpfs /tmp",
+                "if mkdir -p '/home/a b/data' 2>/dev/null; then \
+                 mount --bind '/home/a b/data' '/home/a b/data'; fi",
+                "mount -o remount,bind,ro /",
+                r"exec '/opt/it'\''s/zerobyte-server'",
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_data_dir_is_writable_when_no_override_is_set() {
+        if std::env::var_os(crate::paths::DATA_DIR_ENV).is_some() {
+            return;
+        }
+        let paths = writable_paths(Path::new("/desktop"), Some(Path::new("/srv/zerobyte")));
+        assert_eq!(
+            paths,
+            [PathBuf::from("/desktop"), PathBuf::from("/srv/zerobyte")]
+        );
+        let defaults = writable_paths(Path::new("/desktop"), None);
+        assert!(defaults.contains(&PathBuf::from("/var/lib/c3i-backup-one")));
+    }
+
+    /// Launches a stand-in server under the namespace layer and checks what it may write.
+    /// Needs unprivileged user namespaces: `cargo test --features sandbox-integration`.
+    #[cfg(all(target_os = "linux", feature = "sandbox-integration"))]
+    #[test]
+    fn confined_server_writes_only_where_allowed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Not under /tmp, which the namespace replaces with an empty tmpfs
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(format!("sandbox-{}", std::process::id()));
+        let writable = root.join("data");
+        let other = root.join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        let private_tmp = format!("/tmp/sandbox-probe-{}", std::process::id());
+
+        let server = root.join("server.sh");
+        std::fs::write(
+            &server,
+            format!(
+                "#!/bin/sh\n\
+                 touch '{}/ok'\n\
+                 touch '{}/escaped' 2>/dev/null && echo other-writable\n\
+                 touch '{}' && echo tmp-writable\n",
+                writable.display(),
+                other.display(),
+                private_tmp
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&server, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let support = SandboxSupport {
+            systemd_scope: false,
+            ..detect()
+        };
+        assert!(support.user_namespaces, "user namespaces are unavailable");
+        let plan = plan(
+            &server,
+            &policy(&[], &[writable.to_str().unwrap()]),
+            support,
+        )
+        .unwrap();
+        let output = std::process::Command::new(&plan.program)
+            .args(&plan.args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let wrote_data = writable.join("ok").exists();
+        let escaped = other.join("escaped").exists();
+        let leaked_tmp = Path::new(&private_tmp).exists();
+        let _ = std::fs::remove_dir_all(&root);
+        let _ = std::fs::remove_file(&private_tmp);
+
+        assert!(output.status.success(), "{}", stderr);
+        assert!(wrote_data, "{}", stderr);
+        assert!(!escaped);
+        // /tmp is private: writable inside, gone outside
+        assert_eq!(stdout.trim(), "tmp-writable");
+        assert!(!leaked_tmp);
+    }
+}
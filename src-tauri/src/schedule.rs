@@ -0,0 +1,429 @@
+//! Overlap detection between backup schedules (cron expressions) and OS maintenance windows.
+//!
+//! Everything works on "minute of week" (0 = Sunday 00:00) in the desktop's local time, so
+//! windows that wrap past midnight or past Saturday night are handled with plain modulo math.
+
+use serde::Serialize;
+
+pub const MINUTES_PER_DAY: u32 = 24 * 60;
+pub const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+
+/// Bitmask with every weekday set (bit 0 = Sunday)
+pub const ALL_DAYS: u8 = 0x7f;
+
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A recurring period in local time during which the OS may restart or run heavy maintenance
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceWindow {
+    /// Short machine-readable origin, e.g. "automatic-maintenance"
+    pub source: String,
+    pub description: String,
+    pub start_minute: u32,
+    pub duration_minutes: u32,
+    /// Days the window starts on (bit 0 = Sunday)
+    pub days: u8,
+}
+
+impl MaintenanceWindow {
+    pub fn daily(source: &str, description: &str, start_minute: u32, duration_minutes: u32) -> Self {
+        Self {
+            source: source.to_string(),
+            description: description.to_string(),
+            start_minute: start_minute % MINUTES_PER_DAY,
+            duration_minutes,
+            days: ALL_DAYS,
+        }
+    }
+
+    /// Whether a minute of the week falls inside the window, including spill-over past midnight
+    pub fn contains(&self, minute_of_week: u32) -> bool {
+        let minute_of_week = minute_of_week % MINUTES_PER_WEEK;
+        (0..7u32)
+            .filter(|day| self.days & (1 << day) != 0)
+            .any(|day| {
+                let start = day * MINUTES_PER_DAY + self.start_minute;
+                let offset = (minute_of_week + MINUTES_PER_WEEK - start) % MINUTES_PER_WEEK;
+                offset < self.duration_minutes
+            })
+    }
+}
+
+/// The parts of a cron expression that decide when in the week it fires
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    weekdays: u8,
+    /// Days the schedule can go without firing because it's restricted to some days of the
+    /// month (31) or some months (366); 0 when it fires every week
+    calendar_gap_days: u32,
+}
+
+impl CronSchedule {
+    /// Parse a standard 5-field cron expression (or @hourly/@daily/@weekly...)
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Expected 5 cron fields, found {} in \"{}\"",
+                fields.len(),
+                expr
+            ));
+        }
+
+        let minutes = parse_field(fields[0], 0, 59, &[])?;
+        let hours = parse_field(fields[1], 0, 23, &[])? as u32;
+        // Day of month and month only need to be valid: they decide which weeks the
+        // schedule fires in, not when within a week
+        parse_field(fields[2], 1, 31, &[])?;
+        parse_field(fields[3], 1, 12, &MONTH_NAMES)?;
+        let dow = parse_field(fields[4], 0, 7, &WEEKDAY_NAMES)?;
+        // 7 is an alias for Sunday
+        let dow = ((dow & 0x7f) | (dow >> 7)) as u8;
+
+        // Cron ORs day-of-month and day-of-week when both are restricted, and a
+        // day-of-month restriction alone can land on any weekday
+        let weekdays = if fields[4] == "*" || fields[2] != "*" {
+            ALL_DAYS
+        } else {
+            dow
+        };
+
+        let calendar_gap_days = if fields[3] != "*" {
+            366
+        } else if fields[2] != "*" {
+            31
+        } else {
+            0
+        };
+
+        Ok(Self {
+            minutes,
+            hours,
+            weekdays,
+            calendar_gap_days,
+        })
+    }
+
+    /// Longest stretch in minutes the schedule can go without firing, or None if it never
+    /// fires. Schedules restricted by day of month or month count as a month or a year.
+    pub fn longest_gap(&self) -> Option<u32> {
+        let occurrences: Vec<u32> = self.occurrences().collect();
+        let (first, last) = (*occurrences.first()?, *occurrences.last()?);
+        let within_week = occurrences
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .max()
+            .unwrap_or(0);
+        let wrapped = first + MINUTES_PER_WEEK - last;
+        Some(
+            within_week
+                .max(wrapped)
+                .max(self.calendar_gap_days * MINUTES_PER_DAY),
+        )
+    }
+
+    /// Every minute of the week this schedule can fire at
+    pub fn occurrences(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..7u32)
+            .filter(move |day| self.weekdays & (1 << day) != 0)
+            .flat_map(move |day| {
+                (0..24u32)
+                    .filter(move |hour| self.hours & (1 << hour) != 0)
+                    .flat_map(move |hour| {
+                        (0..60u32)
+                            .filter(move |minute| self.minutes & (1 << minute) != 0)
+                            .map(move |minute| day * MINUTES_PER_DAY + hour * 60 + minute)
+                    })
+            })
+    }
+}
+
+/// Parse one cron field into a bitset of allowed values
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |token: &str| -> Result<u32, String> {
+        let lower = token.to_ascii_lowercase();
+        if let Some(idx) = names.iter().position(|name| *name == lower) {
+            // Month names are 1-based, weekday names 0-based
+            return Ok(idx as u32 + min);
+        }
+        token
+            .parse::<u32>()
+            .ok()
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(|| format!("Invalid cron value \"{}\" (expected {}-{})", token, min, max))
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("Invalid cron step \"{}\"", step))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (value(a)?, value(b)?)
+        } else {
+            let v = value(range)?;
+            // "5/15" means "from 5 to the end, every 15"
+            (v, if part.contains('/') { max } else { v })
+        };
+
+        if start > end {
+            return Err(format!("Invalid cron range \"{}\"", range));
+        }
+
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+
+    Ok(bits)
+}
+
+/// A backup schedule as provided by the caller
+#[derive(Debug, Clone)]
+pub struct NamedSchedule {
+    pub name: String,
+    pub cron: CronSchedule,
+}
+
+/// A schedule time that falls inside a maintenance window
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleConflict {
+    pub schedule: String,
+    pub window_source: String,
+    pub window_description: String,
+    /// Local time of day the schedule fires at, "HH:MM"
+    pub time: String,
+    /// Weekdays on which the conflict happens
+    pub days: Vec<String>,
+    /// Nearby times of day that avoid every known window
+    pub suggestions: Vec<String>,
+}
+
+/// Find every schedule time that falls inside a maintenance window.
+/// `offset_minutes` is added to schedule times to convert them into local time.
+pub fn find_conflicts(
+    schedules: &[NamedSchedule],
+    windows: &[MaintenanceWindow],
+    offset_minutes: i32,
+) -> Vec<ScheduleConflict> {
+    let mut conflicts: Vec<ScheduleConflict> = Vec::new();
+    let shift = |minute: u32| {
+        (minute as i64 + offset_minutes as i64).rem_euclid(MINUTES_PER_WEEK as i64) as u32
+    };
+
+    for schedule in schedules {
+        let occurrences: Vec<u32> = schedule.cron.occurrences().map(shift).collect();
+
+        for window in windows {
+            for &minute in occurrences.iter().filter(|m| window.contains(**m)) {
+                let time = format_time_of_day(minute % MINUTES_PER_DAY);
+                let day = WEEKDAY_NAMES[(minute / MINUTES_PER_DAY) as usize].to_string();
+
+                // Collapse the same time of day across days into one conflict
+                if let Some(existing) = conflicts.iter_mut().find(|c| {
+                    c.schedule == schedule.name && c.window_source == window.source && c.time == time
+                }) {
+                    existing.days.push(day);
+                    continue;
+                }
+
+                conflicts.push(ScheduleConflict {
+                    schedule: schedule.name.clone(),
+                    window_source: window.source.clone(),
+                    window_description: window.description.clone(),
+                    suggestions: suggest_slots(minute, windows),
+                    time,
+                    days: vec![day],
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Nearest times of day (alternating later/earlier in 30 minute steps) that are outside
+/// every window on every day of the week
+fn suggest_slots(minute_of_week: u32, windows: &[MaintenanceWindow]) -> Vec<String> {
+    let time_of_day = minute_of_week % MINUTES_PER_DAY;
+    let free_all_week = |tod: u32| {
+        (0..7u32).all(|day| {
+            let minute = day * MINUTES_PER_DAY + tod;
+            !windows.iter().any(|w| w.contains(minute))
+        })
+    };
+
+    (1..=24i64)
+        .flat_map(|step| [step * 30, -step * 30])
+        .map(|delta| (time_of_day as i64 + delta).rem_euclid(MINUTES_PER_DAY as i64) as u32)
+        .filter(|tod| free_all_week(*tod))
+        .take(2)
+        .map(format_time_of_day)
+        .collect()
+}
+
+fn format_time_of_day(minute_of_day: u32) -> String {
+    format!("{:02}:{:02}", minute_of_day / 60, minute_of_day % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> u32 {
+        day * MINUTES_PER_DAY + hour * 60 + minute
+    }
+
+    fn named(name: &str, expr: &str) -> NamedSchedule {
+        NamedSchedule {
+            name: name.to_string(),
+            cron: CronSchedule::parse(expr).unwrap(),
+        }
+    }
+
+    /// Windows' automatic maintenance: 02:00 for an hour, every day
+    fn nightly() -> MaintenanceWindow {
+        MaintenanceWindow::daily("automatic-maintenance", "Automatic maintenance", 120, 60)
+    }
+
+    #[test]
+    fn window_wraps_past_midnight() {
+        let window = MaintenanceWindow::daily("updates", "Active hours end", 23 * 60, 120);
+        assert!(window.contains(at(2, 23, 30)));
+        assert!(window.contains(at(3, 0, 59)));
+        assert!(!window.contains(at(3, 1, 0)));
+        assert!(!window.contains(at(2, 22, 59)));
+    }
+
+    #[test]
+    fn window_wraps_past_saturday_night() {
+        let window = MaintenanceWindow {
+            days: 1 << 6,
+            ..MaintenanceWindow::daily("updates", "Saturday updates", 23 * 60, 120)
+        };
+        assert!(window.contains(at(6, 23, 30)));
+        assert!(window.contains(at(0, 0, 30)));
+        // Wraps around the end of the week as well as a minute count past it
+        assert!(window.contains(MINUTES_PER_WEEK + at(0, 0, 30)));
+        assert!(!window.contains(at(5, 23, 30)));
+    }
+
+    #[test]
+    fn cron_parses_steps_ranges_names_and_aliases() {
+        let every_15 = CronSchedule::parse("*/15 9-10 * * mon-fri").unwrap();
+        let occurrences: Vec<u32> = every_15.occurrences().collect();
+        assert_eq!(occurrences.len(), 4 * 2 * 5);
+        assert_eq!(occurrences[0], at(1, 9, 0));
+        assert_eq!(*occurrences.last().unwrap(), at(5, 10, 45));
+
+        // 7 and 0 are both Sunday
+        assert_eq!(
+            CronSchedule::parse("0 0 * * 7").unwrap(),
+            CronSchedule::parse("@weekly").unwrap()
+        );
+        assert!(CronSchedule::parse("0 0 * *").is_err());
+        assert!(CronSchedule::parse("60 0 * * *").is_err());
+        assert!(CronSchedule::parse("0 5-1 * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn longest_gap_spans_the_week_and_the_calendar() {
+        assert_eq!(
+            CronSchedule::parse("@daily").unwrap().longest_gap(),
+            Some(MINUTES_PER_DAY)
+        );
+        // Monday and Friday: the gap from Monday to Friday is the longest
+        assert_eq!(
+            CronSchedule::parse("0 12 * * 1,5").unwrap().longest_gap(),
+            Some(4 * MINUTES_PER_DAY)
+        );
+        assert_eq!(
+            CronSchedule::parse("@monthly").unwrap().longest_gap(),
+            Some(31 * MINUTES_PER_DAY)
+        );
+    }
+
+    #[test]
+    fn daily_schedule_in_the_window_conflicts_every_day() {
+        let conflicts = find_conflicts(&[named("Documents", "30 2 * * *")], &[nightly()], 0);
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.schedule, "Documents");
+        assert_eq!(conflict.time, "02:30");
+        assert_eq!(conflict.days.len(), 7);
+        assert_eq!(conflict.suggestions, vec!["03:00", "03:30"]);
+    }
+
+    #[test]
+    fn schedule_outside_every_window_has_no_conflict() {
+        let schedules = [named("Photos", "0 3 * * *"), named("Mail", "59 1 * * *")];
+        assert!(find_conflicts(&schedules, &[nightly()], 0).is_empty());
+    }
+
+    #[test]
+    fn multi_day_schedule_only_conflicts_on_its_days() {
+        let window = MaintenanceWindow {
+            days: (1 << 2) | (1 << 3),
+            ..MaintenanceWindow::daily("updates", "Patch days", 22 * 60, 4 * 60)
+        };
+        let conflicts = find_conflicts(&[named("Nightly", "0 1 * * 1-5")], &[window], 0);
+        assert_eq!(conflicts.len(), 1);
+        // The window starting Tuesday and Wednesday evening covers Wednesday and Thursday 01:00
+        assert_eq!(conflicts[0].days, vec!["wed", "thu"]);
+    }
+
+    #[test]
+    fn backend_times_are_shifted_into_local_time() {
+        // The backend runs on UTC and fires at 01:30; here that's 02:30 (UTC+1)
+        let schedules = [named("Documents", "30 1 * * *")];
+        assert!(find_conflicts(&schedules, &[nightly()], 0).is_empty());
+        let conflicts = find_conflicts(&schedules, &[nightly()], 60);
+        assert_eq!(conflicts[0].time, "02:30");
+
+        // A negative offset moves Sunday's run back into Saturday
+        let sunday = [named("Weekly", "30 3 * * 0")];
+        let conflicts = find_conflicts(&sunday, &[nightly()], -60);
+        assert_eq!(conflicts[0].days, vec!["sun"]);
+        let conflicts = find_conflicts(&[named("Weekly", "30 0 * * 0")], &[nightly()], 120);
+        assert_eq!(conflicts[0].days, vec!["sun"]);
+        let late = MaintenanceWindow::daily("late", "Late maintenance", 23 * 60, 60);
+        let conflicts = find_conflicts(&[named("Weekly", "30 0 * * 0")], &[late], -60);
+        assert_eq!(conflicts[0].days, vec!["sat"]);
+    }
+
+    #[test]
+    fn suggestions_avoid_every_window() {
+        let windows = [
+            nightly(),
+            MaintenanceWindow::daily("defrag", "Optimize drives", 180, 60),
+        ];
+        let conflicts = find_conflicts(&[named("Documents", "0 2 * * *")], &windows, 0);
+        assert_eq!(conflicts[0].suggestions, vec!["01:30", "01:00"]);
+    }
+}
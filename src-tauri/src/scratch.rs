@@ -0,0 +1,449 @@
+//! Managed scratch space for restore previews, diagnostics and downloads.
+//!
+//! Everything temporary goes in `scratch/<purpose>` under the app cache directory instead of
+//! the system temp dir, so it can be measured, capped and cleared from the storage settings.
+//! The whole area is held to a quota (2 GB unless settings say otherwise): at startup, and
+//! whenever a feature is about to write something large, the least recently used entries are
+//! deleted until it fits. Entries modified in the last few minutes are left alone since
+//! something is probably still writing them.
+//!
+//! Elevated operations keep writing their scripts and logs to the system temp dir; that flow
+//! has its own handling and isn't moved here.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Name of the scratch area in the app cache directory
+pub const SCRATCH_DIR: &str = "scratch";
+
+/// Quota used when settings don't set one
+pub const DEFAULT_QUOTA: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Entries touched this recently are never evicted
+const IN_USE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScratchPurpose {
+    RestorePreview,
+    Diagnostics,
+    Updates,
+    Transfers,
+}
+
+impl ScratchPurpose {
+    pub const ALL: [ScratchPurpose; 4] = [
+        ScratchPurpose::RestorePreview,
+        ScratchPurpose::Diagnostics,
+        ScratchPurpose::Updates,
+        ScratchPurpose::Transfers,
+    ];
+
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            ScratchPurpose::RestorePreview => "restore-preview",
+            ScratchPurpose::Diagnostics => "diagnostics",
+            ScratchPurpose::Updates => "updates",
+            ScratchPurpose::Transfers => "transfers",
+        }
+    }
+}
+
+/// A file or directory directly inside a purpose directory
+#[derive(Debug, Clone)]
+pub struct ScratchEntry {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Most recent modification anywhere inside the entry
+    pub last_used: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PurposeUsage {
+    pub purpose: ScratchPurpose,
+    pub bytes: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScratchUsage {
+    pub quota_bytes: u64,
+    pub total_bytes: u64,
+    pub purposes: Vec<PurposeUsage>,
+}
+
+/// Entries to delete, least recently used first, so that `reserve` more bytes fit in `quota`.
+/// Entries used after `in_use_since` are skipped, so the result may not free enough.
+pub fn select_evictions(
+    entries: &[ScratchEntry],
+    quota: u64,
+    reserve: u64,
+    in_use_since: SystemTime,
+) -> Vec<usize> {
+    let mut total: u64 = entries.iter().map(|entry| entry.bytes).sum();
+    let mut order: Vec<usize> = (0..entries.len())
+        .filter(|index| entries[*index].last_used < in_use_since)
+        .collect();
+    order.sort_by_key(|index| entries[*index].last_used);
+
+    let mut evicted = Vec::new();
+    for index in order {
+        if total.saturating_add(reserve) <= quota {
+            break;
+        }
+        total -= entries[index].bytes;
+        evicted.push(index);
+    }
+    evicted
+}
+
+/// Size and latest modification time of a file or directory tree
+fn measure(path: &Path) -> (u64, SystemTime) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return (0, SystemTime::UNIX_EPOCH);
+    };
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    if !metadata.is_dir() {
+        return (metadata.len(), modified);
+    }
+    let Ok(children) = std::fs::read_dir(path) else {
+        return (0, modified);
+    };
+    children
+        .flatten()
+        .map(|child| measure(&child.path()))
+        .fold((0, modified), |(bytes, latest), (size, time)| {
+            (bytes + size, latest.max(time))
+        })
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// The scratch area under the app cache directory
+pub struct Scratch {
+    root: PathBuf,
+    quota: u64,
+}
+
+impl Scratch {
+    pub fn new(cache_dir: &Path, quota: Option<u64>) -> Self {
+        Self {
+            root: cache_dir.join(SCRATCH_DIR),
+            quota: quota.unwrap_or(DEFAULT_QUOTA),
+        }
+    }
+
+    /// Directory for `purpose`, created if needed
+    pub fn dir(&self, purpose: ScratchPurpose) -> std::io::Result<PathBuf> {
+        let dir = self.root.join(purpose.dir_name());
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn entries(&self, purpose: ScratchPurpose) -> Vec<ScratchEntry> {
+        let Ok(children) = std::fs::read_dir(self.root.join(purpose.dir_name())) else {
+            return Vec::new();
+        };
+        children
+            .flatten()
+            .map(|child| {
+                let path = child.path();
+                let (bytes, last_used) = measure(&path);
+                ScratchEntry {
+                    path,
+                    bytes,
+                    last_used,
+                }
+            })
+            .collect()
+    }
+
+    pub fn usage(&self) -> ScratchUsage {
+        let purposes: Vec<PurposeUsage> = ScratchPurpose::ALL
+            .iter()
+            .map(|purpose| {
+                let entries = self.entries(*purpose);
+                PurposeUsage {
+                    purpose: *purpose,
+                    bytes: entries.iter().map(|entry| entry.bytes).sum(),
+                    entries: entries.len(),
+                }
+            })
+            .collect();
+        ScratchUsage {
+            quota_bytes: self.quota,
+            total_bytes: purposes.iter().map(|purpose| purpose.bytes).sum(),
+            purposes,
+        }
+    }
+
+    /// Delete everything for `purpose`, or the whole area if None. Returns the bytes freed.
+    pub fn clear(&self, purpose: Option<ScratchPurpose>) -> Result<u64, String> {
+        let purposes = match purpose {
+            Some(purpose) => vec![purpose],
+            None => ScratchPurpose::ALL.to_vec(),
+        };
+        let mut freed = 0;
+        for purpose in purposes {
+            for entry in self.entries(purpose) {
+                remove(&entry.path)
+                    .map_err(|e| format!("Failed to delete {}: {}", entry.path.display(), e))?;
+                freed += entry.bytes;
+            }
+        }
+        info!("Cleared {} bytes of scratch space", freed);
+        Ok(freed)
+    }
+
+    /// Evict least recently used entries so `bytes` more fit within the quota. Returns
+    /// whether they do afterwards.
+    pub fn reserve(&self, bytes: u64) -> bool {
+        self.reserve_unused_since(bytes, SystemTime::now() - IN_USE)
+    }
+
+    fn reserve_unused_since(&self, bytes: u64, in_use_since: SystemTime) -> bool {
+        let entries: Vec<ScratchEntry> = ScratchPurpose::ALL
+            .iter()
+            .flat_map(|purpose| self.entries(*purpose))
+            .collect();
+        let mut total: u64 = entries.iter().map(|entry| entry.bytes).sum();
+
+        for index in select_evictions(&entries, self.quota, bytes, in_use_since) {
+            let entry = &entries[index];
+            match remove(&entry.path) {
+                Ok(()) => {
+                    info!(
+                        "Evicted {} ({} bytes) from scratch space",
+                        entry.path.display(),
+                        entry.bytes
+                    );
+                    total -= entry.bytes;
+                }
+                Err(e) => warn!("Failed to evict {}: {}", entry.path.display(), e),
+            }
+        }
+        total.saturating_add(bytes) <= self.quota
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// A fixed point in time `minutes_ago` before "now"
+    fn since(minutes_ago: u32) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(100_000) - MINUTE * minutes_ago
+    }
+
+    fn entry(name: &str, bytes: u64, minutes_ago: u32) -> ScratchEntry {
+        ScratchEntry {
+            path: PathBuf::from(name),
+            bytes,
+            last_used: since(minutes_ago),
+        }
+    }
+
+    fn scratch_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("scratch-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    /// Write `bytes` into `relative` under the purpose directory, pausing so each file gets a
+    /// later modification time than the one before
+    fn fabricate(scratch: &Scratch, purpose: ScratchPurpose, relative: &str, bytes: usize) {
+        let path = scratch.dir(purpose).unwrap().join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0u8; bytes]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    fn names(scratch: &Scratch, purpose: ScratchPurpose) -> Vec<String> {
+        let mut names: Vec<String> = scratch
+            .entries(purpose)
+            .iter()
+            .map(|entry| {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn evictions_take_the_least_recently_used_first() {
+        let entries = [
+            entry("newest", 40, 20),
+            entry("oldest", 30, 90),
+            entry("middle", 30, 60),
+            entry("in-use", 50, 1),
+        ];
+        // (quota, reserve, expected evictions)
+        let cases: [(u64, u64, &[usize]); 6] = [
+            (150, 0, &[]),
+            (150, 10, &[1]),
+            (100, 0, &[1, 2]),
+            (100, 10, &[1, 2]),
+            (60, 0, &[1, 2, 0]),
+            // The in-use entry alone is over quota; everything else goes and it still won't fit
+            (10, 0, &[1, 2, 0]),
+        ];
+        for (quota, reserve, expected) in cases {
+            assert_eq!(
+                select_evictions(&entries, quota, reserve, since(10)),
+                expected,
+                "quota {} reserving {}",
+                quota,
+                reserve
+            );
+        }
+    }
+
+    #[test]
+    fn huge_reservations_do_not_overflow() {
+        let entries = [entry("old", 10, 90)];
+        assert_eq!(
+            select_evictions(&entries, 100, u64::MAX, since(10)),
+            vec![0]
+        );
+        assert!(select_evictions(&[], 100, 0, since(10)).is_empty());
+    }
+
+    #[test]
+    fn directories_are_measured_recursively() {
+        let root = scratch_root("measure");
+        let scratch = Scratch::new(&root, None);
+        fabricate(
+            &scratch,
+            ScratchPurpose::RestorePreview,
+            "snapshot/a.txt",
+            100,
+        );
+        fabricate(
+            &scratch,
+            ScratchPurpose::RestorePreview,
+            "snapshot/nested/b.txt",
+            250,
+        );
+        fabricate(&scratch, ScratchPurpose::RestorePreview, "loose.bin", 7);
+        let newest = std::fs::metadata(
+            scratch
+                .dir(ScratchPurpose::RestorePreview)
+                .unwrap()
+                .join("snapshot/nested/b.txt"),
+        )
+        .unwrap()
+        .modified()
+        .unwrap();
+
+        let entries = scratch.entries(ScratchPurpose::RestorePreview);
+        let usage = scratch.usage();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let snapshot = entries
+            .iter()
+            .find(|entry| entry.path.ends_with("snapshot"))
+            .unwrap();
+        assert_eq!(snapshot.bytes, 350);
+        assert!(snapshot.last_used >= newest);
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(usage.quota_bytes, DEFAULT_QUOTA);
+        assert_eq!(usage.total_bytes, 357);
+        let restore = &usage.purposes[0];
+        assert_eq!(restore.purpose, ScratchPurpose::RestorePreview);
+        assert_eq!((restore.bytes, restore.entries), (357, 2));
+        assert!(usage.purposes[1..]
+            .iter()
+            .all(|purpose| purpose.entries == 0));
+    }
+
+    #[test]
+    fn reserving_evicts_across_purposes_oldest_first() {
+        let root = scratch_root("reserve");
+        let scratch = Scratch::new(&root, Some(1000));
+        fabricate(&scratch, ScratchPurpose::Diagnostics, "report-1.zip", 300);
+        fabricate(
+            &scratch,
+            ScratchPurpose::Updates,
+            "installer/setup.exe",
+            300,
+        );
+        fabricate(&scratch, ScratchPurpose::Transfers, "upload.part", 300);
+
+        // Everything fits, nothing goes
+        let fits = scratch.reserve_unused_since(100, SystemTime::now() + IN_USE);
+        let untouched = scratch.usage().total_bytes;
+        // Two entries have to go for 700 more bytes
+        let freed = scratch.reserve_unused_since(700, SystemTime::now() + IN_USE);
+        let diagnostics = names(&scratch, ScratchPurpose::Diagnostics);
+        let updates = names(&scratch, ScratchPurpose::Updates);
+        let transfers = names(&scratch, ScratchPurpose::Transfers);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(fits);
+        assert_eq!(untouched, 900);
+        assert!(freed);
+        assert!(diagnostics.is_empty());
+        assert!(updates.is_empty());
+        assert_eq!(transfers, ["upload.part"]);
+    }
+
+    #[test]
+    fn recently_used_entries_survive_a_reservation() {
+        let root = scratch_root("in-use");
+        let scratch = Scratch::new(&root, Some(500));
+        fabricate(&scratch, ScratchPurpose::Diagnostics, "report.zip", 400);
+
+        let fits = scratch.reserve(200);
+        let remaining = names(&scratch, ScratchPurpose::Diagnostics);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(!fits);
+        assert_eq!(remaining, ["report.zip"]);
+    }
+
+    #[test]
+    fn clearing_one_purpose_leaves_the_others() {
+        let root = scratch_root("clear");
+        let scratch = Scratch::new(&root, None);
+        fabricate(&scratch, ScratchPurpose::Diagnostics, "report.zip", 40);
+        fabricate(&scratch, ScratchPurpose::Updates, "installer/setup.exe", 60);
+        fabricate(&scratch, ScratchPurpose::Updates, "notes.txt", 5);
+
+        let diagnostics = scratch.clear(Some(ScratchPurpose::Diagnostics));
+        let after_diagnostics = scratch.usage().total_bytes;
+        let everything = scratch.clear(None);
+        let after_everything = scratch.usage().total_bytes;
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(diagnostics, Ok(40));
+        assert_eq!(after_diagnostics, 65);
+        assert_eq!(everything, Ok(65));
+        assert_eq!(after_everything, 0);
+    }
+
+    #[test]
+    fn a_missing_area_is_empty() {
+        let scratch = Scratch::new(&scratch_root("missing"), Some(10));
+        assert_eq!(scratch.usage().total_bytes, 0);
+        assert_eq!(scratch.clear(None), Ok(0));
+        assert!(scratch.reserve(10));
+        assert!(!scratch.reserve(11));
+    }
+}
@@ -0,0 +1,387 @@
+//! Which Windows Service actions the current user can take, and how.
+//!
+//! Whether Install/Start/Stop/Uninstall prompt for administrator approval, or can't work at
+//! all, depends on the process token, on UAC being enabled (`EnableLUA`), on the service
+//! control manager being reachable and on machine policy (`ZEROBYTE_ALLOW_SERVICE_CONTROL=0`
+//! forbids service control outright). [`probe`] gathers those facts and [`decide`] turns
+//! them into a verdict per action, so the settings page can annotate its buttons and the
+//! commands can refuse with the same reason before anything prompts.
+
+use crate::error::AppError;
+use serde::Serialize;
+
+/// Environment variable that allows (`1`/`true`) or forbids (`0`/`false`) service control
+pub const ALLOW_SERVICE_CONTROL_ENV: &str = "ZEROBYTE_ALLOW_SERVICE_CONTROL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceAction {
+    Install,
+    Uninstall,
+    Start,
+    Stop,
+}
+
+impl ServiceAction {
+    pub const ALL: [ServiceAction; 4] = [
+        ServiceAction::Install,
+        ServiceAction::Uninstall,
+        ServiceAction::Start,
+        ServiceAction::Stop,
+    ];
+}
+
+/// Why an action is refused or will prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityReason {
+    /// Not Windows
+    Unsupported,
+    /// Machine policy forbids service control
+    ForbiddenByPolicy,
+    /// The service control manager refused even a plain connection
+    ScmUnreachable,
+    /// Standard user and UAC is off, so there's no way to elevate
+    ElevationUnavailable,
+    /// Allowed after the user approves the UAC prompt
+    RequiresApproval,
+}
+
+impl CapabilityReason {
+    /// Stable identifier the web UI maps to a localized message
+    pub fn code(self) -> &'static str {
+        match self {
+            CapabilityReason::Unsupported => "service_unsupported",
+            CapabilityReason::ForbiddenByPolicy => "service_forbidden_by_policy",
+            CapabilityReason::ScmUnreachable => "service_scm_unreachable",
+            CapabilityReason::ElevationUnavailable => "service_elevation_unavailable",
+            CapabilityReason::RequiresApproval => "service_requires_approval",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            CapabilityReason::Unsupported => "Windows Service is only supported on Windows",
+            CapabilityReason::ForbiddenByPolicy => "Service control is disabled by machine policy",
+            CapabilityReason::ScmUnreachable => "The Windows service manager is not reachable",
+            CapabilityReason::ElevationUnavailable => {
+                "Administrator rights are required and UAC is disabled for this account"
+            }
+            CapabilityReason::RequiresApproval => "Windows will ask for administrator approval",
+        }
+    }
+}
+
+/// Facts about the environment the verdicts are computed from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessProbe {
+    pub windows: bool,
+    /// The process token is elevated
+    pub elevated: bool,
+    pub uac_enabled: bool,
+    pub scm_reachable: bool,
+    /// Raw value of [`ALLOW_SERVICE_CONTROL_ENV`]
+    pub policy: Option<String>,
+}
+
+/// The verdict for one action, as `get_service_capabilities` reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionCapability {
+    pub action: ServiceAction,
+    pub allowed: bool,
+    pub needs_elevation: bool,
+    pub blocked_by_policy: bool,
+    pub reason: Option<CapabilityReason>,
+    pub message: Option<&'static str>,
+}
+
+fn forbidden_by_policy(policy: Option<&str>) -> bool {
+    let policy = policy.map(|value| value.trim().to_ascii_lowercase());
+    matches!(policy.as_deref(), Some("0" | "false" | "no" | "off"))
+}
+
+/// Decide whether `action` can be taken given `probe`. Every action needs administrator
+/// rights today; the action is kept in the verdict so that can differ later.
+pub fn decide(action: ServiceAction, probe: &AccessProbe) -> ActionCapability {
+    let (allowed, needs_elevation, reason) = if !probe.windows {
+        (false, false, Some(CapabilityReason::Unsupported))
+    } else if forbidden_by_policy(probe.policy.as_deref()) {
+        (false, !probe.elevated, Some(CapabilityReason::ForbiddenByPolicy))
+    } else if !probe.scm_reachable {
+        (false, !probe.elevated, Some(CapabilityReason::ScmUnreachable))
+    } else if probe.elevated {
+        (true, false, None)
+    } else if probe.uac_enabled {
+        (true, true, Some(CapabilityReason::RequiresApproval))
+    } else {
+        (false, true, Some(CapabilityReason::ElevationUnavailable))
+    };
+    ActionCapability {
+        action,
+        allowed,
+        needs_elevation,
+        blocked_by_policy: reason == Some(CapabilityReason::ForbiddenByPolicy),
+        reason,
+        message: reason.map(CapabilityReason::message),
+    }
+}
+
+/// Verdicts for every action
+pub fn matrix(probe: &AccessProbe) -> Vec<ActionCapability> {
+    ServiceAction::ALL
+        .iter()
+        .map(|action| decide(*action, probe))
+        .collect()
+}
+
+/// Refuse `action` if it can't be taken right now
+pub fn check(action: ServiceAction) -> Result<(), AppError> {
+    check_with(action, &probe())
+}
+
+/// Refuse `action` if `probe` rules it out, with the reason the matrix reports
+fn check_with(action: ServiceAction, probe: &AccessProbe) -> Result<(), AppError> {
+    let capability = decide(action, probe);
+    match capability.reason {
+        Some(reason) if !capability.allowed => Err(AppError::ServiceActionRefused(reason)),
+        _ => Ok(()),
+    }
+}
+
+/// Probe the running process and machine
+pub fn probe() -> AccessProbe {
+    AccessProbe {
+        windows: cfg!(target_os = "windows"),
+        elevated: platform::elevated(),
+        uac_enabled: platform::uac_enabled(),
+        scm_reachable: platform::scm_reachable(),
+        policy: std::env::var(ALLOW_SERVICE_CONTROL_ENV).ok(),
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::ffi::c_void;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{CloseHandle, ERROR_SUCCESS, HANDLE};
+    use windows::Win32::Security::{
+        GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY,
+    };
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD};
+    use windows::Win32::System::Services::{CloseServiceHandle, OpenSCManagerW, SC_MANAGER_CONNECT};
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    pub fn elevated() -> bool {
+        unsafe {
+            let mut token = HANDLE::default();
+            if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_err() {
+                return false;
+            }
+            let mut elevation = TOKEN_ELEVATION::default();
+            let mut size = 0u32;
+            let queried = GetTokenInformation(
+                token,
+                TokenElevation,
+                Some(&mut elevation as *mut TOKEN_ELEVATION as *mut c_void),
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut size,
+            )
+            .is_ok();
+            let _ = CloseHandle(token);
+            queried && elevation.TokenIsElevated != 0
+        }
+    }
+
+    /// UAC counts as enabled unless `EnableLUA` is explicitly 0
+    pub fn uac_enabled() -> bool {
+        let mut data: u32 = 1;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_LOCAL_MACHINE,
+                w!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Policies\\System"),
+                w!("EnableLUA"),
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut data as *mut u32 as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        status != ERROR_SUCCESS || data != 0
+    }
+
+    pub fn scm_reachable() -> bool {
+        unsafe {
+            match OpenSCManagerW(PCWSTR::null(), PCWSTR::null(), SC_MANAGER_CONNECT) {
+                Ok(manager) => {
+                    let _ = CloseServiceHandle(manager);
+                    true
+                }
+                Err(_) => false,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod platform {
+    pub fn elevated() -> bool {
+        false
+    }
+
+    pub fn uac_enabled() -> bool {
+        false
+    }
+
+    pub fn scm_reachable() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(elevated: bool, uac_enabled: bool, policy: Option<&str>) -> AccessProbe {
+        AccessProbe {
+            windows: true,
+            elevated,
+            uac_enabled,
+            scm_reachable: true,
+            policy: policy.map(str::to_string),
+        }
+    }
+
+    /// Case name, probe, then the expected allowed, needs_elevation, blocked_by_policy, reason
+    type Case = (
+        &'static str,
+        AccessProbe,
+        bool,
+        bool,
+        bool,
+        Option<CapabilityReason>,
+    );
+
+    #[test]
+    fn matrix_covers_each_kind_of_account() {
+        use CapabilityReason::*;
+        let unreachable = AccessProbe {
+            scm_reachable: false,
+            ..windows(false, true, None)
+        };
+        let cases: &[Case] = &[
+            ("admin", windows(true, true, None), true, false, false, None),
+            (
+                "admin without UAC",
+                windows(true, false, None),
+                true,
+                false,
+                false,
+                None,
+            ),
+            (
+                "standard user with UAC",
+                windows(false, true, None),
+                true,
+                true,
+                false,
+                Some(RequiresApproval),
+            ),
+            (
+                "standard user without UAC",
+                windows(false, false, None),
+                false,
+                true,
+                false,
+                Some(ElevationUnavailable),
+            ),
+            (
+                "admin, policy-locked",
+                windows(true, true, Some("0")),
+                false,
+                false,
+                true,
+                Some(ForbiddenByPolicy),
+            ),
+            (
+                "standard user, policy-locked",
+                windows(false, true, Some("false")),
+                false,
+                true,
+                true,
+                Some(ForbiddenByPolicy),
+            ),
+            (
+                "policy explicitly allows",
+                windows(false, true, Some("1")),
+                true,
+                true,
+                false,
+                Some(RequiresApproval),
+            ),
+            (
+                "SCM unreachable",
+                unreachable,
+                false,
+                true,
+                false,
+                Some(ScmUnreachable),
+            ),
+            (
+                "not Windows",
+                AccessProbe::default(),
+                false,
+                false,
+                false,
+                Some(Unsupported),
+            ),
+        ];
+        for (case, probe, allowed, needs_elevation, blocked_by_policy, reason) in cases {
+            let matrix = matrix(probe);
+            assert_eq!(matrix.len(), ServiceAction::ALL.len(), "{}", case);
+            for (capability, action) in matrix.iter().zip(ServiceAction::ALL) {
+                assert_eq!(capability.action, action, "{}", case);
+                assert_eq!(capability.allowed, *allowed, "{}: {:?}", case, action);
+                assert_eq!(capability.needs_elevation, *needs_elevation, "{}", case);
+                assert_eq!(capability.blocked_by_policy, *blocked_by_policy, "{}", case);
+                assert_eq!(capability.reason, *reason, "{}", case);
+                assert_eq!(capability.message, reason.map(CapabilityReason::message));
+            }
+        }
+    }
+
+    #[test]
+    fn policy_values_that_forbid_service_control() {
+        for value in ["0", "false", "FALSE", " no ", "Off"] {
+            assert!(forbidden_by_policy(Some(value)), "{:?}", value);
+        }
+        for value in ["1", "true", "yes", "", "maybe"] {
+            assert!(!forbidden_by_policy(Some(value)), "{:?}", value);
+        }
+        assert!(!forbidden_by_policy(None));
+    }
+
+    #[test]
+    fn refusals_carry_the_matrix_reason_code() {
+        let probes = [
+            windows(true, true, None),
+            windows(false, true, None),
+            windows(false, false, None),
+            windows(true, true, Some("off")),
+            AccessProbe::default(),
+        ];
+        for probe in &probes {
+            for action in ServiceAction::ALL {
+                let capability = decide(action, probe);
+                match check_with(action, probe) {
+                    Ok(()) => assert!(capability.allowed, "{:?} {:?}", probe, action),
+                    Err(error) => {
+                        assert!(!capability.allowed);
+                        assert_eq!(Some(error.code()), capability.reason.map(|r| r.code()));
+                        assert_eq!(Some(error.to_string().as_str()), capability.message);
+                    }
+                }
+            }
+        }
+    }
+}
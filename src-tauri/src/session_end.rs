@@ -0,0 +1,279 @@
+//! Stopping the backend when the OS session ends.
+//!
+//! Windows shutdown and logoff don't close the window or ask the app to exit: the OS sends
+//! `WM_QUERYENDSESSION` and `WM_ENDSESSION` to every top-level window and ends the process
+//! soon after, which used to kill the sidecar in the middle of a write. [`install`] catches
+//! those messages on the main window (or a hidden one of its own when running headless), and
+//! SIGTERM on Linux and macOS, and runs a short shutdown: ask the sidecar to shut down, give
+//! it [`GRACE`], kill it if it's still there, all within the [`BUDGET`] the OS allows. The
+//! wait goes through [`GracefulWait`] like the service's stop, and meanwhile Windows shows
+//! what's going on in its shutdown screen, starting with [`BLOCK_REASON`].
+
+use crate::graceful::{ExitProbe, GracefulWait, WaitOutcome, WaitProgress};
+use crate::shutdown::ShutdownState;
+use crate::AppState;
+use std::time::Duration;
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// Time the OS leaves an app once the session ends before it's killed
+pub const BUDGET: Duration = Duration::from_secs(4);
+
+/// Time the sidecar gets to exit on its own, leaving the rest of the budget for the kill
+pub const GRACE: Duration = Duration::from_secs(3);
+
+/// Shown by Windows next to the app once the session starts ending, until [`shut_down`]
+/// reports its progress
+pub const BLOCK_REASON: &str = "Finishing backup…";
+
+/// Waiting for the shutdown within [`BUDGET`], with a progress report every second
+const WAIT: GracefulWait = GracefulWait {
+    poll_interval: Duration::from_millis(100),
+    progress_interval: Duration::from_secs(1),
+    hard_cap: BUDGET,
+};
+
+/// The shutdown sequence, which counts as exited once it has run
+struct ShutdownDone<'a>(&'a ShutdownState);
+
+impl ExitProbe for ShutdownDone<'_> {
+    fn has_exited(&mut self) -> bool {
+        self.0.is_complete()
+    }
+}
+
+/// Stop the backend within [`BUDGET`], or wait for the shutdown already under way, calling
+/// `on_progress` with a reason for the OS to show while waiting. Blocks the calling thread,
+/// which the OS holds up until the app is done anyway.
+pub fn shut_down(app: &tauri::AppHandle, mut on_progress: impl FnMut(&str)) {
+    info!("Session ending, stopping the backend");
+    let stopping = app.clone();
+    tauri::async_runtime::spawn(async move {
+        // Does nothing if shutdown had already begun; the wait below covers that one too
+        crate::shutdown_quietly(&stopping, GRACE, BUDGET).await;
+    });
+    let state = app.state::<AppState>();
+    let outcome = WAIT.wait(&mut ShutdownDone(&state.shutdown), |progress| {
+        on_progress(&progress_reason(progress))
+    });
+    if let WaitOutcome::CapReached { elapsed, .. } = outcome {
+        warn!(
+            "Shutdown still running after {:?} as the session ends",
+            elapsed
+        );
+    }
+}
+
+/// What the app is doing `progress` into the shutdown, as Windows shows it
+fn progress_reason(progress: &WaitProgress) -> String {
+    match GRACE.checked_sub(progress.elapsed) {
+        Some(left) if !left.is_zero() => {
+            format!("Finishing backup… ({}s left)", left.as_secs_f64().ceil())
+        }
+        _ => "Stopping the backend…".to_string(),
+    }
+}
+
+/// Watch for the session ending
+#[cfg(windows)]
+pub fn install(app: &tauri::AppHandle) -> Result<(), String> {
+    platform::install(app)
+}
+
+/// Watch for SIGTERM, which is how logoff and system shutdown end processes here
+#[cfg(unix)]
+pub fn install(app: &tauri::AppHandle) -> Result<(), String> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                warn!("Failed to watch for SIGTERM: {}", e);
+                return;
+            }
+        };
+        terminate.recv().await;
+        info!("Received SIGTERM");
+        let stopping = app.clone();
+        let _ = tauri::async_runtime::spawn_blocking(move || shut_down(&stopping, |_| {})).await;
+        app.exit(0);
+    });
+    Ok(())
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn install(_app: &tauri::AppHandle) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::BLOCK_REASON;
+    use std::sync::OnceLock;
+    use tauri::Manager;
+    use windows::core::{w, HSTRING};
+    use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::System::Shutdown::{ShutdownBlockReasonCreate, ShutdownBlockReasonDestroy};
+    use windows::Win32::UI::Shell::{DefSubclassProc, SetWindowSubclass};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, MSG,
+        WINDOW_EX_STYLE, WM_ENDSESSION, WM_QUERYENDSESSION, WNDCLASSW, WS_OVERLAPPED,
+    };
+
+    /// Identifies this subclass among others on the same window
+    const SUBCLASS_ID: usize = 0x7a62_5345;
+
+    /// The window procedure has no way to be handed the app
+    static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+    /// Hook the main window's messages. Without one (`--headless`), listen on a hidden
+    /// top-level window of our own instead: a message-only window would miss the session
+    /// messages, which are only sent to top-level windows.
+    pub fn install(app: &tauri::AppHandle) -> Result<(), String> {
+        let _ = APP.set(app.clone());
+        let Some(window) = app.get_webview_window("main") else {
+            return install_hidden_window();
+        };
+        let hwnd = window.hwnd().map_err(|e| e.to_string())?;
+        let hwnd = HWND(hwnd.0 as _);
+        let installed = unsafe { SetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, 0) };
+        if !installed.as_bool() {
+            return Err("Failed to hook the main window's messages".to_string());
+        }
+        Ok(())
+    }
+
+    /// Create a window that's never shown, on a thread that pumps its messages
+    fn install_hidden_window() -> Result<(), String> {
+        let (created, result) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("session-end".into())
+            .spawn(move || unsafe {
+                let hwnd = match create_hidden_window() {
+                    Ok(hwnd) => {
+                        let _ = created.send(Ok(()));
+                        hwnd
+                    }
+                    Err(e) => {
+                        let _ = created.send(Err(e));
+                        return;
+                    }
+                };
+                let mut msg = MSG::default();
+                while GetMessageW(&mut msg, hwnd, 0, 0).as_bool() {
+                    DispatchMessageW(&msg);
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        result
+            .recv()
+            .map_err(|_| "The session watcher thread stopped".to_string())?
+    }
+
+    unsafe fn create_hidden_window() -> Result<HWND, String> {
+        let instance: HINSTANCE = GetModuleHandleW(None).map_err(|e| e.to_string())?.into();
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: w!("ZerobyteSessionEnd"),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err("Failed to register the session watcher window".to_string());
+        }
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            class.lpszClassName,
+            w!("Zerobyte"),
+            WS_OVERLAPPED,
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            instance,
+            None,
+        )
+        .map_err(|e| format!("Failed to create the session watcher window: {}", e))
+    }
+
+    unsafe extern "system" fn subclass_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: usize,
+        _data: usize,
+    ) -> LRESULT {
+        on_message(hwnd, msg, wparam);
+        DefSubclassProc(hwnd, msg, wparam, lparam)
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        on_message(hwnd, msg, wparam);
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+
+    unsafe fn on_message(hwnd: HWND, msg: u32, wparam: WPARAM) {
+        match msg {
+            WM_QUERYENDSESSION => {
+                let _ = ShutdownBlockReasonCreate(hwnd, &HSTRING::from(BLOCK_REASON));
+            }
+            // The process may be ended as soon as this returns, so stop the backend before
+            // returning. A wparam of 0 means the session isn't ending after all.
+            WM_ENDSESSION => {
+                if wparam.0 != 0 {
+                    if let Some(app) = APP.get() {
+                        super::shut_down(app, |reason| {
+                            let _ = ShutdownBlockReasonCreate(hwnd, &HSTRING::from(reason));
+                        });
+                    }
+                }
+                let _ = ShutdownBlockReasonDestroy(hwnd);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress_at(elapsed: Duration) -> WaitProgress {
+        WaitProgress {
+            checkpoint: 1,
+            elapsed,
+            remaining: BUDGET - elapsed,
+        }
+    }
+
+    #[test]
+    fn reason_counts_down_the_grace_then_reports_the_kill() {
+        assert_eq!(
+            progress_reason(&progress_at(Duration::from_millis(1000))),
+            "Finishing backup… (2s left)"
+        );
+        assert_eq!(
+            progress_reason(&progress_at(Duration::from_millis(2100))),
+            "Finishing backup… (1s left)"
+        );
+        assert_eq!(
+            progress_reason(&progress_at(GRACE)),
+            "Stopping the backend…"
+        );
+        assert_eq!(
+            progress_reason(&progress_at(BUDGET)),
+            "Stopping the backend…"
+        );
+    }
+}
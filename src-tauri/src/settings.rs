@@ -0,0 +1,155 @@
+//! Desktop settings stored as `settings.json` in the app config directory.
+
+use crate::activity::ActivityMode;
+use crate::bandwidth::BandwidthSchedule;
+use crate::persist::{self, LoadSource};
+use crate::priority::BackendPriority;
+use crate::retention::{ArtifactClass, RetentionRule};
+use crate::text::TextLimits;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// File name of the settings document
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Persisted desktop preferences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Serve the web client from this directory instead of the bundled one (for developers
+    /// pointing the app at a local dist build)
+    pub asset_dir_override: Option<PathBuf>,
+    /// Battery saver mode for background polling
+    pub background_activity: ActivityMode,
+    /// Set before relaunching into an update; cleared on the next start
+    pub post_update: bool,
+    /// Repositories whose passphrase is kept in the OS keyring
+    pub remembered_passphrases: Vec<String>,
+    /// Extra regexes redacted from logs and diagnostics, on top of the built-in rules
+    pub redaction_rules: Vec<String>,
+    /// Confine the sidecar (Linux only; applies the next time it starts)
+    pub sandbox: bool,
+    /// Repository hosts a sandboxed sidecar may reach besides loopback
+    pub sandbox_allowed_hosts: Vec<String>,
+    /// Overrides for how long menu items, tooltips and notifications may get
+    pub text_limits: TextLimits,
+    /// How long to wait for running backups before an update or mode switch stops the backend
+    pub drain_deadline_secs: Option<u64>,
+    /// Keep notifications in Action Center, collapsing repeats into one toast (Windows only)
+    pub persist_toasts: bool,
+    /// Backup plans whose notifications are silenced, with the time the mute ends (seconds
+    /// since the Unix epoch; None until unmuted)
+    pub muted_plans: HashMap<String, Option<u64>>,
+    /// How many days of backend health history to keep (a week if unset)
+    pub health_history_days: Option<u64>,
+    /// Cap on the scratch area for previews, diagnostics and downloads (2 GB if unset)
+    pub scratch_quota_bytes: Option<u64>,
+    /// Serve Prometheus metrics on 127.0.0.1 (applies the next time the app starts)
+    pub metrics_enabled: bool,
+    /// Port of the metrics endpoint (9464 if unset)
+    pub metrics_port: Option<u16>,
+    /// Per-class overrides of how long desktop artifacts are kept
+    pub retention_rules: HashMap<ArtifactClass, RetentionRule>,
+    /// Let retention delete quarantined documents too
+    pub retention_delete_quarantined: bool,
+    /// Automatic retention may delete files. Installs that predate retention start without
+    /// it, until the user has reviewed and run a cleanup once.
+    pub retention_confirmed: bool,
+    /// Switch the backend's bandwidth profile between work hours and the rest of the day
+    pub bandwidth_schedule: Option<BandwidthSchedule>,
+    /// Send `loading-status` updates as English text instead of structured statuses, for
+    /// frontends that still match on it. Removed in the next release.
+    pub legacy_loading_status: bool,
+    /// Log filter directive chosen in the app (e.g. `zerobyte=debug`); None for the default
+    pub log_level: Option<String>,
+    /// CPU and I/O priority the sidecar runs at
+    pub backend_priority: BackendPriority,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            asset_dir_override: None,
+            background_activity: ActivityMode::Normal,
+            post_update: false,
+            remembered_passphrases: Vec::new(),
+            redaction_rules: Vec::new(),
+            sandbox: false,
+            sandbox_allowed_hosts: Vec::new(),
+            text_limits: TextLimits::default(),
+            drain_deadline_secs: None,
+            persist_toasts: true,
+            muted_plans: HashMap::new(),
+            health_history_days: None,
+            scratch_quota_bytes: None,
+            metrics_enabled: false,
+            metrics_port: None,
+            retention_rules: HashMap::new(),
+            retention_delete_quarantined: false,
+            retention_confirmed: false,
+            bandwidth_schedule: None,
+            legacy_loading_status: true,
+            log_level: None,
+            backend_priority: BackendPriority::Normal,
+        }
+    }
+}
+
+/// Payload of the `settings-recovered` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsRecovered {
+    /// The file the settings were actually loaded from
+    pub file: String,
+    pub source: LoadSource,
+}
+
+/// Settings loaded at startup and saved back on every change
+pub struct SettingsStore {
+    path: PathBuf,
+    current: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Load settings from `path`, recovering from a corrupt file if needed
+    pub fn load(path: PathBuf) -> (Self, Option<SettingsRecovered>) {
+        let (settings, source) = persist::load_json::<Settings>(&path);
+
+        let recovered = source.recovered().then(|| SettingsRecovered {
+            file: match source {
+                LoadSource::Backup => persist::backup_path(&path).display().to_string(),
+                _ => "defaults".to_string(),
+            },
+            source,
+        });
+
+        let store = Self {
+            path,
+            current: Mutex::new(settings),
+        };
+        (store, recovered)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Snapshot of the current settings
+    pub fn get(&self) -> Settings {
+        self.current.lock().clone()
+    }
+
+    /// Apply a change and persist it. The in-memory settings only change if the write succeeds.
+    pub fn update(&self, change: impl FnOnce(&mut Settings)) -> Result<Settings, String> {
+        let mut current = self.current.lock();
+        let mut updated = current.clone();
+        change(&mut updated);
+
+        persist::save_json(&self.path, &updated)
+            .map_err(|e| format!("Failed to save {}: {}", self.path.display(), e))?;
+
+        *current = updated.clone();
+        Ok(updated)
+    }
+}
@@ -0,0 +1,304 @@
+//! One shutdown path for every way the app can exit.
+//!
+//! Tray quit, closing the window, OS-initiated exits (Cmd+Q, macOS logout), plugin restarts and
+//! update relaunches all end up in `RunEvent::ExitRequested`. The first request is held back
+//! while the shared async sequence stops the sidecar; the exit then proceeds once that
+//! sequence finishes or its barrier times out. Windows session end and SIGTERM never get
+//! there and take the shorter route in `session_end`.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Time the shutdown sequence gets on top of the sidecar's grace period, for ending restore
+/// sessions and killing whatever didn't exit
+pub const CLEANUP_ALLOWANCE: Duration = Duration::from_secs(5);
+
+/// Longest the exit waits for the shutdown sequence when the sidecar gets `grace` to exit
+pub fn barrier(grace: Duration) -> Duration {
+    grace + CLEANUP_ALLOWANCE
+}
+
+/// How long past the barrier the process exits regardless, should the async runtime be too
+/// busy or stuck to act on the barrier itself
+pub const HARD_EXIT_AFTER: Duration = Duration::from_secs(10);
+
+/// End the process with `code` once `after` has passed, from a plain thread so it doesn't
+/// depend on the async runtime making progress
+pub fn arm_hard_exit(after: Duration, code: i32) {
+    std::thread::spawn(move || {
+        std::thread::sleep(after);
+        tracing::error!("Still not exited {:?} after quitting began, exiting now", after);
+        std::process::exit(code);
+    });
+}
+
+/// Why the app is exiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", content = "code", rename_all = "snake_case")]
+pub enum ExitReason {
+    /// The app asked to exit (tray quit, window close, `process.exit` from the UI)
+    Requested(i32),
+    /// The runtime wants to exit on its own: last window gone, OS quit, session end
+    System,
+    /// Relaunching into a freshly installed update
+    Update,
+}
+
+impl ExitReason {
+    /// Classify an `ExitRequested` event by its exit code
+    pub fn classify(code: Option<i32>) -> Self {
+        match code {
+            Some(code) => ExitReason::Requested(code),
+            None => ExitReason::System,
+        }
+    }
+
+    /// Process exit code to use once shutdown is done
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ExitReason::Requested(code) => code,
+            ExitReason::System | ExitReason::Update => 0,
+        }
+    }
+
+    /// Whether the exit should skip guards that would otherwise hold it back (e.g. a backup
+    /// in progress). Only an update relaunch forces its way through.
+    pub fn forced(self) -> bool {
+        self == ExitReason::Update
+    }
+}
+
+/// What to do with a `RunEvent::ExitRequested`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStep {
+    /// The sequence has finished; let the exit through
+    Proceed,
+    /// Hold the exit back and run the sequence, which exits again when it's done
+    RunSequence,
+    /// Hold the exit back; the sequence already running exits when it's done
+    Wait,
+}
+
+/// Progress of the shutdown sequence
+#[derive(Default)]
+pub struct ShutdownState {
+    started: AtomicBool,
+    complete: AtomicBool,
+}
+
+impl ShutdownState {
+    /// Claim the shutdown sequence. Returns false if it's already running.
+    pub fn begin(&self) -> bool {
+        !self.started.swap(true, Ordering::SeqCst)
+    }
+
+    /// Mark the sequence as done so the next exit request goes through
+    pub fn finish(&self) {
+        self.complete.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::SeqCst)
+    }
+
+    /// Decide what an exit request does, claiming the sequence if it hasn't started
+    pub fn on_exit_requested(&self) -> ExitStep {
+        if self.is_complete() {
+            ExitStep::Proceed
+        } else if self.begin() {
+            ExitStep::RunSequence
+        } else {
+            ExitStep::Wait
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_exit_request_runs_the_sequence_and_later_ones_wait() {
+        let shutdown = ShutdownState::default();
+        assert_eq!(shutdown.on_exit_requested(), ExitStep::RunSequence);
+        assert_eq!(shutdown.on_exit_requested(), ExitStep::Wait);
+        assert!(!shutdown.begin());
+        shutdown.finish();
+        assert_eq!(shutdown.on_exit_requested(), ExitStep::Proceed);
+        assert_eq!(shutdown.on_exit_requested(), ExitStep::Proceed);
+    }
+
+    #[test]
+    fn exit_codes_follow_the_reason() {
+        assert_eq!(ExitReason::classify(Some(3)), ExitReason::Requested(3));
+        assert_eq!(ExitReason::classify(None), ExitReason::System);
+        assert_eq!(ExitReason::Requested(3).exit_code(), 3);
+        assert_eq!(ExitReason::System.exit_code(), 0);
+        assert_eq!(ExitReason::Update.exit_code(), 0);
+        assert!(ExitReason::Update.forced());
+        assert!(!ExitReason::System.forced());
+    }
+
+    /// The app's exit handling around a real dummy sidecar: `handle_run_event`, the shared
+    /// sequence, and the quiet stop that `RunEvent::Exit` and session end use
+    #[cfg(unix)]
+    struct App {
+        shutdown: ShutdownState,
+        sidecar: Option<(crate::proctree::ProcessTree, std::thread::JoinHandle<()>)>,
+        pid: u32,
+        stops: usize,
+    }
+
+    #[cfg(unix)]
+    impl App {
+        fn start() -> Self {
+            let mut child = std::process::Command::new("sleep")
+                .arg("30")
+                .spawn()
+                .unwrap();
+            let pid = child.id();
+            // The shell plugin reaps the real sidecar; without this the dummy stays a zombie
+            let reaper = std::thread::spawn(move || {
+                let _ = child.wait();
+            });
+            assert!(crate::proctree::is_alive(pid));
+            App {
+                shutdown: ShutdownState::default(),
+                sidecar: Some((crate::proctree::ProcessTree::adopt(pid), reaper)),
+                pid,
+                stops: 0,
+            }
+        }
+
+        fn stop_sidecar(&mut self) {
+            if let Some((tree, reaper)) = self.sidecar.take() {
+                let report = tree.kill();
+                assert!(report.survivors.is_empty(), "{:?}", report.survivors);
+                reaper.join().unwrap();
+            }
+            self.stops += 1;
+        }
+
+        /// `RunEvent::ExitRequested`; returns the exit code once the exit goes through
+        fn exit_requested(&mut self, code: Option<i32>) -> Option<i32> {
+            match self.shutdown.on_exit_requested() {
+                ExitStep::Proceed => Some(code.unwrap_or(0)),
+                ExitStep::Wait => None,
+                ExitStep::RunSequence => {
+                    self.stop_sidecar();
+                    self.shutdown.finish();
+                    // The sequence ends with app.exit(reason.exit_code())
+                    self.exit_requested(Some(ExitReason::classify(code).exit_code()))
+                }
+            }
+        }
+
+        /// `RunEvent::Exit` and session end, through `shutdown_quietly`
+        fn exit(&mut self) {
+            if self.shutdown.begin() {
+                self.stop_sidecar();
+                self.shutdown.finish();
+            }
+        }
+
+        /// `restart_for_update`: the sequence, then a restart that requests an exit
+        fn update_relaunch(&mut self) -> Option<i32> {
+            if self.shutdown.begin() {
+                self.stop_sidecar();
+                self.shutdown.finish();
+            }
+            self.exit_requested(None)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sidecar_is_stopped_once_on_every_exit_path() {
+        type ExitPath = fn(&mut App) -> Option<i32>;
+        let paths: &[(&str, ExitPath, Option<i32>)] = &[
+            (
+                "tray quit",
+                |app| {
+                    let code = app.exit_requested(Some(0));
+                    app.exit();
+                    code
+                },
+                Some(0),
+            ),
+            (
+                "app.exit from the frontend",
+                |app| {
+                    let code = app.exit_requested(Some(2));
+                    app.exit();
+                    code
+                },
+                Some(2),
+            ),
+            (
+                "last window closed or OS logout",
+                |app| {
+                    let code = app.exit_requested(None);
+                    app.exit();
+                    code
+                },
+                Some(0),
+            ),
+            (
+                "process plugin restart",
+                |app| {
+                    app.exit();
+                    Some(0)
+                },
+                Some(0),
+            ),
+            (
+                "Windows session end, then the event loop ends",
+                |app| {
+                    app.exit();
+                    app.exit();
+                    Some(0)
+                },
+                Some(0),
+            ),
+            (
+                "update relaunch",
+                |app| {
+                    let code = app.update_relaunch();
+                    app.exit();
+                    code
+                },
+                Some(0),
+            ),
+        ];
+        for (name, path, code) in paths {
+            let mut app = App::start();
+            assert_eq!(path(&mut app), *code, "{}", name);
+            assert!(app.sidecar.is_none(), "{}: sidecar left running", name);
+            assert!(
+                !crate::proctree::is_alive(app.pid),
+                "{}: pid {} alive",
+                name,
+                app.pid
+            );
+            assert_eq!(app.stops, 1, "{}", name);
+            assert!(app.shutdown.is_complete(), "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_requests_during_the_sequence_are_held_back() {
+        let mut app = App::start();
+        assert!(app.shutdown.begin());
+        // A second quit while the sequence runs neither exits nor stops again
+        assert_eq!(app.exit_requested(Some(0)), None);
+        assert_eq!(app.stops, 0);
+        app.stop_sidecar();
+        app.shutdown.finish();
+        assert_eq!(app.exit_requested(Some(0)), Some(0));
+        app.exit();
+        assert_eq!(app.stops, 1);
+        assert!(!crate::proctree::is_alive(app.pid));
+    }
+}
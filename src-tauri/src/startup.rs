@@ -0,0 +1,234 @@
+//! Staged startup.
+//!
+//! Setup only brings up the window shell and the tray; everything heavier (starting or
+//! finding the backend, reading its capabilities, background pollers, file watchers) runs
+//! afterwards as ordered stages on a background task. Each stage's timing is recorded here so
+//! slow starts can be diagnosed with `get_startup_stages`. The background task waits for
+//! [`Stage::Shell`] to finish, so the tray is up before the sidecar is spawned.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::time::Instant;
+use tokio::sync::watch;
+
+/// Startup stages, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Settings, window and tray
+    Shell,
+    /// Service probe, asset checks and starting the sidecar
+    Backend,
+    /// Reading the backend's capabilities and enabling the tray entries that need it
+    Capabilities,
+    /// Outbox replay, ownership watch and restore-session reaper
+    Pollers,
+    /// Re-registering persisted file watchers
+    Watchers,
+    /// Checking autostart and service registrations against the install location
+    Registrations,
+    /// Looking for a legacy install to migrate from
+    Legacy,
+}
+
+const STAGES: &[Stage] = &[
+    Stage::Shell,
+    Stage::Backend,
+    Stage::Capabilities,
+    Stage::Pollers,
+    Stage::Watchers,
+    Stage::Registrations,
+    Stage::Legacy,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// When a stage ran, in milliseconds since the process started
+#[derive(Debug, Clone, Serialize)]
+pub struct StageRecord {
+    pub stage: Stage,
+    pub state: StageState,
+    pub started_ms: Option<u64>,
+    pub finished_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Records when each stage started and finished
+pub struct StartupTracker {
+    origin: Instant,
+    stages: Mutex<Vec<StageRecord>>,
+    /// Bumped whenever a stage changes state
+    changed: watch::Sender<u64>,
+}
+
+impl Default for StartupTracker {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+            stages: Mutex::new(
+                STAGES
+                    .iter()
+                    .map(|stage| StageRecord {
+                        stage: *stage,
+                        state: StageState::Pending,
+                        started_ms: None,
+                        finished_ms: None,
+                        error: None,
+                    })
+                    .collect(),
+            ),
+            changed: watch::channel(0).0,
+        }
+    }
+}
+
+impl StartupTracker {
+    fn elapsed_ms(&self) -> u64 {
+        self.origin.elapsed().as_millis() as u64
+    }
+
+    fn update(&self, stage: Stage, change: impl FnOnce(&mut StageRecord, u64)) {
+        let now = self.elapsed_ms();
+        if let Some(record) = self
+            .stages
+            .lock()
+            .iter_mut()
+            .find(|record| record.stage == stage)
+        {
+            change(record, now);
+        }
+        self.changed.send_modify(|generation| *generation += 1);
+    }
+
+    fn state(&self, stage: Stage) -> Option<StageState> {
+        self.stages
+            .lock()
+            .iter()
+            .find(|record| record.stage == stage)
+            .map(|record| record.state)
+    }
+
+    /// Wait until `stage` is done or has failed
+    pub async fn finished(&self, stage: Stage) {
+        let mut changed = self.changed.subscribe();
+        while !matches!(
+            self.state(stage),
+            Some(StageState::Done | StageState::Failed)
+        ) {
+            if changed.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn begin(&self, stage: Stage) {
+        tracing::info!("Startup stage {:?} started", stage);
+        self.update(stage, |record, now| {
+            record.state = StageState::Running;
+            record.started_ms = Some(now);
+        });
+    }
+
+    pub fn finish(&self, stage: Stage) {
+        self.update(stage, |record, now| {
+            record.state = StageState::Done;
+            record.finished_ms = Some(now);
+            tracing::info!(
+                "Startup stage {:?} done after {} ms",
+                stage,
+                now - record.started_ms.unwrap_or(now)
+            );
+        });
+    }
+
+    pub fn fail(&self, stage: Stage, error: String) {
+        self.update(stage, |record, now| {
+            record.state = StageState::Failed;
+            record.finished_ms = Some(now);
+            record.error = Some(error);
+        });
+    }
+
+    pub fn records(&self) -> Vec<StageRecord> {
+        self.stages.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn stages_start_pending_in_order() {
+        let tracker = StartupTracker::default();
+        let records = tracker.records();
+        let stages: Vec<Stage> = records.iter().map(|record| record.stage).collect();
+        assert_eq!(stages, STAGES);
+        assert!(records
+            .iter()
+            .all(|record| record.state == StageState::Pending));
+    }
+
+    #[test]
+    fn stage_records_track_state_and_timing() {
+        let tracker = StartupTracker::default();
+        tracker.begin(Stage::Backend);
+        assert_eq!(tracker.state(Stage::Backend), Some(StageState::Running));
+        tracker.fail(Stage::Backend, "no free port".to_string());
+
+        let record = tracker
+            .records()
+            .into_iter()
+            .find(|record| record.stage == Stage::Backend)
+            .unwrap();
+        assert_eq!(record.state, StageState::Failed);
+        assert!(record.started_ms <= record.finished_ms);
+        assert_eq!(record.error.as_deref(), Some("no free port"));
+    }
+
+    /// Setup and the background startup task, recording what each did in order
+    #[tokio::test]
+    async fn tray_is_ready_before_the_sidecar_spawns() {
+        let tracker = Arc::new(StartupTracker::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        // The background task may be scheduled before setup gets to the tray
+        let background = {
+            let (tracker, events) = (tracker.clone(), events.clone());
+            tokio::spawn(async move {
+                tracker.finished(Stage::Shell).await;
+                tracker.begin(Stage::Backend);
+                events.lock().push("sidecar spawned");
+            })
+        };
+        tokio::task::yield_now().await;
+        tracker.begin(Stage::Shell);
+        tokio::task::yield_now().await;
+        events.lock().push("tray ready");
+        assert_eq!(*events.lock(), ["tray ready"]);
+        tracker.finish(Stage::Shell);
+
+        background.await.unwrap();
+        assert_eq!(*events.lock(), ["tray ready", "sidecar spawned"]);
+    }
+
+    #[tokio::test]
+    async fn failed_shell_doesnt_hold_startup_forever() {
+        let tracker = StartupTracker::default();
+        tracker.fail(Stage::Shell, "no tray".to_string());
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            tracker.finished(Stage::Shell),
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+//! A loopback HTTP fallback for the bundled splash and error pages.
+//!
+//! Some WebView2 versions come up with IPC broken, and then a bundled page can't even show
+//! why the app is stuck. While a bundled page is displayed in the main window, the desktop
+//! listens on a random loopback port and serves `/status` (the health part of the system
+//! report) and `/logs/tail` (its recent error lines). The page learns the address and a
+//! per-session token from a script evaluated when it loads; every request must carry the
+//! token as `?token=`. The listener closes as soon as the window navigates anywhere else.
+
+use crate::report::{AppSection, BackendSection, ErrorLine};
+use crate::startup::StageRecord;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Global the bundled pages read the listener's address and token from
+pub const WINDOW_GLOBAL: &str = "__ZEROBYTE_STATUS__";
+
+/// Bytes of random data in a session token
+const TOKEN_BYTES: usize = 32;
+
+/// Pause after a failed accept, so a persistent error (e.g. out of descriptors) doesn't spin
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Payload of `/status`
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPayload {
+    pub app: AppSection,
+    pub backend: BackendSection,
+    pub startup: Vec<StageRecord>,
+}
+
+/// What a request asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Status,
+    LogsTail,
+    /// Missing or wrong token
+    Unauthorized,
+    NotFound,
+}
+
+/// Whether `url` is one of the pages bundled with the app rather than the backend's UI
+pub fn is_bundled_page(url: &tauri::Url) -> bool {
+    url.scheme() == "tauri" || url.host_str() == Some("tauri.localhost")
+}
+
+/// A new random session token, hex encoded
+pub fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("No randomness available: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Compare tokens without stopping at the first difference
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Route an HTTP request line such as `GET /status?token=... HTTP/1.1`
+pub fn route(request_line: &str, token: &str) -> Route {
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Route::NotFound;
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let authorized = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .any(|(key, value)| key == "token" && token_matches(token, value));
+    match path {
+        "/status" | "/logs/tail" if !authorized => Route::Unauthorized,
+        "/status" => Route::Status,
+        "/logs/tail" => Route::LogsTail,
+        _ => Route::NotFound,
+    }
+}
+
+fn response(status: &str, body: &str) -> String {
+    // Bundled pages are served from another origin; the token is what guards access
+    format!(
+        concat!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            "Access-Control-Allow-Origin: *\r\nCache-Control: no-store\r\n",
+            "Connection: close\r\n\r\n{}"
+        ),
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn render(app: &tauri::AppHandle, route: Route) -> String {
+    match route {
+        Route::Status => {
+            let report = crate::report::build(app);
+            let payload = StatusPayload {
+                app: report.app,
+                backend: report.backend,
+                startup: report.startup,
+            };
+            response(
+                "200 OK",
+                &serde_json::to_string(&payload).unwrap_or_default(),
+            )
+        }
+        Route::LogsTail => {
+            let lines: Vec<ErrorLine> = crate::report::build(app).recent_errors;
+            response("200 OK", &serde_json::to_string(&lines).unwrap_or_default())
+        }
+        Route::Unauthorized => response("403 Forbidden", "{\"error\":\"forbidden\"}"),
+        Route::NotFound => response("404 Not Found", "{\"error\":\"not found\"}"),
+    }
+}
+
+/// Script telling a bundled page where the listener is
+pub fn page_script(port: u16, token: &str) -> String {
+    format!(
+        "window.{} = Object.freeze({{ url: 'http://127.0.0.1:{}', token: '{}' }});",
+        WINDOW_GLOBAL, port, token
+    )
+}
+
+struct Running {
+    port: u16,
+    token: String,
+    cancel: CancellationToken,
+}
+
+/// The listener, while a bundled page is displayed
+#[derive(Default)]
+pub struct StatusServer {
+    running: Mutex<Option<Running>>,
+}
+
+impl StatusServer {
+    /// Start listening unless already running; returns the port and token
+    pub fn start(&self, app: &tauri::AppHandle) -> Result<(u16, String), String> {
+        let mut running = self.running.lock();
+        if let Some(running) = running.as_ref() {
+            return Ok((running.port, running.token.clone()));
+        }
+
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            .map_err(|e| format!("Failed to open the status listener: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to read the status listener's port: {}", e))?
+            .port();
+        let token = new_token()?;
+        let cancel = CancellationToken::new();
+        crate::supervisor::spawn_once(
+            app,
+            "status-server",
+            serve(app.clone(), listener, token.clone(), cancel.clone()),
+        );
+        info!("Status listener open on 127.0.0.1:{}", port);
+        *running = Some(Running {
+            port,
+            token: token.clone(),
+            cancel,
+        });
+        Ok((port, token))
+    }
+
+    /// Port of the listener while it's open
+    pub fn port(&self) -> Option<u16> {
+        self.running.lock().as_ref().map(|running| running.port)
+    }
+
+    /// Close the listener if it's open
+    pub fn stop(&self) {
+        if let Some(running) = self.running.lock().take() {
+            running.cancel.cancel();
+            info!("Status listener on port {} closed", running.port);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.lock().is_some()
+    }
+}
+
+async fn serve(
+    app: tauri::AppHandle,
+    listener: std::net::TcpListener,
+    token: String,
+    cancel: CancellationToken,
+) {
+    let listener = match tokio::net::TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to start the status listener: {}", e);
+            return;
+        }
+    };
+    loop {
+        let (mut stream, _) = tokio::select! {
+            _ = cancel.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Status listener failed to accept a connection: {}", e);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            },
+        };
+        let app = app.clone();
+        let token = token.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut buffer = [0u8; 2048];
+            let Ok(read) = stream.read(&mut buffer).await else {
+                return;
+            };
+            let request = String::from_utf8_lossy(&buffer[..read]);
+            let request_line = request.lines().next().unwrap_or_default();
+            let response = render(&app, route(request_line, &token));
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+/// Open or close the listener for a page that started loading in the main window
+pub fn on_page_load(webview: &tauri::Webview, url: &tauri::Url) {
+    use tauri::Manager;
+
+    let server = webview.state::<StatusServer>();
+    if !is_bundled_page(url) {
+        server.stop();
+        return;
+    }
+    match server.start(webview.app_handle()) {
+        Ok((port, token)) => {
+            let _ = webview.eval(page_script(port, &token));
+        }
+        Err(e) => warn!("{}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn url(s: &str) -> tauri::Url {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn bundled_pages_open_the_listener_and_backend_pages_close_it() {
+        assert!(is_bundled_page(&url("tauri://localhost/splash.html")));
+        assert!(is_bundled_page(&url("http://tauri.localhost/error.html")));
+        assert!(!is_bundled_page(&url("http://127.0.0.1:4096/")));
+        assert!(!is_bundled_page(&url(
+            "http://localhost:4096/tauri.localhost"
+        )));
+        assert!(!is_bundled_page(&url(
+            "https://tauri.localhost.example.com/"
+        )));
+    }
+
+    #[test]
+    fn routes_require_the_session_token() {
+        let line = |target: &str| format!("GET {} HTTP/1.1", target);
+        let ok = format!("token={}", TOKEN);
+        assert_eq!(
+            route(&line(&format!("/status?{}", ok)), TOKEN),
+            Route::Status
+        );
+        assert_eq!(
+            route(&line(&format!("/logs/tail?x=1&{}", ok)), TOKEN),
+            Route::LogsTail
+        );
+        assert_eq!(route(&line("/status"), TOKEN), Route::Unauthorized);
+        assert_eq!(route(&line("/status?token="), TOKEN), Route::Unauthorized);
+        assert_eq!(
+            route(&line("/logs/tail?token=0123456789abcdeX"), TOKEN),
+            Route::Unauthorized
+        );
+        assert_eq!(
+            route(&line(&format!("/status?token={}0", TOKEN)), TOKEN),
+            Route::Unauthorized
+        );
+        assert_eq!(
+            route(&line(&format!("/status?tok={}", TOKEN)), TOKEN),
+            Route::Unauthorized
+        );
+    }
+
+    #[test]
+    fn unknown_paths_and_methods_are_not_found() {
+        let ok = format!("token={}", TOKEN);
+        assert_eq!(
+            route(&format!("GET /other?{} HTTP/1.1", ok), TOKEN),
+            Route::NotFound
+        );
+        assert_eq!(
+            route(&format!("POST /status?{} HTTP/1.1", ok), TOKEN),
+            Route::NotFound
+        );
+        assert_eq!(route("", TOKEN), Route::NotFound);
+        assert_eq!(route("GET", TOKEN), Route::NotFound);
+    }
+
+    #[test]
+    fn token_comparison_checks_every_byte() {
+        assert!(token_matches(TOKEN, TOKEN));
+        assert!(!token_matches(TOKEN, "1123456789abcdef"));
+        assert!(!token_matches(TOKEN, "0123456789abcdee"));
+        assert!(!token_matches(TOKEN, "0123456789abcde"));
+        assert!(!token_matches(TOKEN, ""));
+    }
+
+    #[test]
+    fn tokens_are_fresh_hex() {
+        let first = new_token().unwrap();
+        let second = new_token().unwrap();
+        assert_eq!(first.len(), TOKEN_BYTES * 2);
+        assert!(first.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn page_script_exposes_the_address_and_token() {
+        assert_eq!(
+            page_script(51234, TOKEN),
+            format!(
+                "window.{} = Object.freeze({{ url: 'http://127.0.0.1:51234', token: '{}' }});",
+                WINDOW_GLOBAL, TOKEN
+            )
+        );
+    }
+
+    #[test]
+    fn stop_cancels_the_listener_once() {
+        let server = StatusServer::default();
+        server.stop();
+        assert!(!server.is_running());
+
+        let cancel = CancellationToken::new();
+        *server.running.lock() = Some(Running {
+            port: 4242,
+            token: TOKEN.to_string(),
+            cancel: cancel.clone(),
+        });
+        assert!(server.is_running());
+        assert_eq!(server.port(), Some(4242));
+
+        server.stop();
+        assert!(cancel.is_cancelled());
+        assert!(!server.is_running());
+        assert_eq!(server.port(), None);
+        server.stop();
+    }
+
+    #[test]
+    fn responses_are_complete_http() {
+        let body = "{\"error\":\"forbidden\"}";
+        let forbidden = response("403 Forbidden", body);
+        assert!(forbidden.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(forbidden.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(forbidden.contains("Cache-Control: no-store\r\n"));
+        assert!(forbidden.contains("Connection: close\r\n"));
+        assert!(forbidden.ends_with(&format!("\r\n\r\n{}", body)));
+    }
+}
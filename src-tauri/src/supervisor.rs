@@ -0,0 +1,466 @@
+//! Supervision for long-lived background tasks.
+//!
+//! A panic in a spawned task is otherwise swallowed by the runtime: the poller silently
+//! stops and the UI keeps claiming everything is fine. Supervised tasks run in their own
+//! tokio task whose join result is inspected. A panic is logged, written to the crash
+//! reports directory and recorded in the [`TaskHealth`] registry (`get_task_health`); the
+//! task is then restarted with exponential backoff if its policy allows, or left degraded.
+//! Supervisors never hold anything the shutdown sequence waits on, and stop restarting as
+//! soon as the app starts quitting, so quitting works with degraded subsystems.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Whether and how a task is started again after it panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    Backoff {
+        initial: Duration,
+        max: Duration,
+        /// Panics tolerated before the task is left degraded
+        max_restarts: u32,
+    },
+}
+
+impl RestartPolicy {
+    /// Pollers and samplers: 1 s doubling up to 5 min, at most 10 restarts
+    pub const POLLER: RestartPolicy = RestartPolicy::Backoff {
+        initial: Duration::from_secs(1),
+        max: Duration::from_secs(5 * 60),
+        max_restarts: 10,
+    };
+
+    /// Delay before restart number `restarts + 1`, or None if the task stays down
+    pub fn delay(&self, restarts: u32) -> Option<Duration> {
+        match *self {
+            RestartPolicy::Never => None,
+            RestartPolicy::Backoff {
+                initial,
+                max,
+                max_restarts,
+            } => (restarts < max_restarts)
+                .then(|| initial.saturating_mul(1 << restarts.min(16)).min(max)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Running,
+    /// Panicked and waiting to be started again
+    Restarting,
+    /// Panicked and won't be restarted
+    Degraded,
+    /// Returned normally
+    Finished,
+}
+
+/// One supervised task as `get_task_health` reports it
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRecord {
+    pub name: String,
+    pub status: TaskStatus,
+    pub panics: u32,
+    pub last_panic: Option<String>,
+    /// Seconds since the Unix epoch
+    pub last_panic_at: Option<u64>,
+}
+
+/// The state of every supervised task
+#[derive(Default)]
+pub struct TaskHealth {
+    tasks: Mutex<BTreeMap<String, TaskRecord>>,
+}
+
+impl TaskHealth {
+    fn set_status(&self, name: &str, status: TaskStatus) {
+        self.tasks
+            .lock()
+            .entry(name.to_string())
+            .or_insert_with(|| TaskRecord {
+                name: name.to_string(),
+                status,
+                panics: 0,
+                last_panic: None,
+                last_panic_at: None,
+            })
+            .status = status;
+    }
+
+    fn record_panic(&self, name: &str, message: &str, at: u64) {
+        if let Some(record) = self.tasks.lock().get_mut(name) {
+            record.panics += 1;
+            record.last_panic = Some(message.to_string());
+            record.last_panic_at = Some(at);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<TaskRecord> {
+        self.tasks.lock().values().cloned().collect()
+    }
+
+    /// Names of tasks that panicked and are not running
+    pub fn degraded(&self) -> Vec<String> {
+        self.tasks
+            .lock()
+            .values()
+            .filter(|record| record.status == TaskStatus::Degraded)
+            .map(|record| record.name.clone())
+            .collect()
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The message a panic was raised with
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "panic with a non-string payload".to_string()),
+    }
+}
+
+/// Write a short report about a panicked task to the crash reports directory
+fn write_crash_report(app: &tauri::AppHandle, name: &str, message: &str, at: u64) {
+    let state = app.state::<crate::AppState>();
+    let dir = state.paths().data_dir.join(crate::retention::CRASH_REPORTS_DIR);
+    let message = state.redactor.read().redact(message).into_owned();
+    let report = format!(
+        "task: {}\nat: {}\nversion: {}\npanic: {}\n",
+        name,
+        at,
+        app.package_info().version,
+        message
+    );
+    let path = dir.join(format!("task-{}-{}.txt", name, at));
+    if let Err(e) = std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, report)) {
+        warn!("Failed to write crash report {}: {}", path.display(), e);
+    }
+}
+
+/// Where a supervisor reports what happens to its task
+trait Reporter: Send + Sync + 'static {
+    fn status(&self, name: &str, status: TaskStatus);
+    fn panicked(&self, name: &str, message: &str);
+}
+
+impl Reporter for tauri::AppHandle {
+    fn status(&self, name: &str, status: TaskStatus) {
+        self.state::<TaskHealth>().set_status(name, status);
+        if status == TaskStatus::Degraded {
+            crate::events::emit(self, "task-degraded", name);
+        }
+    }
+
+    /// Log, record and report a panic of `name`
+    fn panicked(&self, name: &str, message: &str) {
+        let at = now_secs();
+        error!("Background task {} panicked: {}", name, message);
+        self.state::<TaskHealth>().record_panic(name, message, at);
+        write_crash_report(self, name, message, at);
+    }
+}
+
+/// Run the task `make` builds until it returns, building a new one after each panic as
+/// `policy` allows. Restarting stops once `quitting` is cancelled.
+async fn supervise<R, F, Fut>(
+    reporter: R,
+    name: String,
+    policy: RestartPolicy,
+    quitting: CancellationToken,
+    make: F,
+) where
+    R: Reporter,
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut restarts = 0;
+    loop {
+        reporter.status(&name, TaskStatus::Running);
+        let error = match tokio::spawn(make()).await {
+            Ok(()) => {
+                reporter.status(&name, TaskStatus::Finished);
+                return;
+            }
+            Err(error) => error,
+        };
+        if !error.is_panic() {
+            // Cancelled along with the runtime
+            reporter.status(&name, TaskStatus::Finished);
+            return;
+        }
+        reporter.panicked(&name, &panic_message(error.into_panic()));
+
+        let Some(delay) = policy.delay(restarts).filter(|_| !quitting.is_cancelled()) else {
+            reporter.status(&name, TaskStatus::Degraded);
+            return;
+        };
+        reporter.status(&name, TaskStatus::Restarting);
+        tokio::select! {
+            _ = quitting.cancelled() => {
+                reporter.status(&name, TaskStatus::Degraded);
+                return;
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+        restarts += 1;
+        info!("Restarting background task {} (restart {})", name, restarts);
+    }
+}
+
+/// Run `task` until it returns; a panic leaves it degraded
+async fn supervise_once<R, Fut>(reporter: R, name: String, task: Fut)
+where
+    R: Reporter,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    match tokio::spawn(task).await {
+        Err(error) if error.is_panic() => {
+            reporter.panicked(&name, &panic_message(error.into_panic()));
+            reporter.status(&name, TaskStatus::Degraded);
+        }
+        _ => reporter.status(&name, TaskStatus::Finished),
+    }
+}
+
+/// Run the task `make` builds under supervision, building a new one after each panic as
+/// `policy` allows
+pub fn spawn<F, Fut>(
+    app: &tauri::AppHandle,
+    name: impl Into<String>,
+    policy: RestartPolicy,
+    make: F,
+) where
+    F: Fn(tauri::AppHandle) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let app = app.clone();
+    let name = name.into();
+    let quitting = app.state::<crate::AppState>().http.child_token();
+    tauri::async_runtime::spawn(async move {
+        let handle = app.clone();
+        supervise(app, name, policy, quitting, move || make(handle.clone())).await
+    });
+}
+
+/// Run a task that can't be rebuilt (it owns a channel or a child's output) under
+/// supervision; a panic leaves it degraded
+pub fn spawn_once<Fut>(app: &tauri::AppHandle, name: impl Into<String>, task: Fut)
+where
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let app = app.clone();
+    let name = name.into();
+    app.status(&name, TaskStatus::Running);
+    tauri::async_runtime::spawn(supervise_once(app, name, task));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Reports into a registry and keeps every status change of the task
+    #[derive(Default)]
+    struct Recorder {
+        health: TaskHealth,
+        history: Mutex<Vec<TaskStatus>>,
+    }
+
+    impl Reporter for Arc<Recorder> {
+        fn status(&self, name: &str, status: TaskStatus) {
+            self.health.set_status(name, status);
+            self.history.lock().push(status);
+        }
+
+        fn panicked(&self, name: &str, message: &str) {
+            self.health.record_panic(name, message, 1_000);
+        }
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn backoff(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy::Backoff {
+            initial: SECOND,
+            max: 4 * SECOND,
+            max_restarts,
+        }
+    }
+
+    /// Supervise a task that panics on its first `panics` runs and then returns. Returns the
+    /// recorder and how many times the task ran.
+    async fn run(
+        policy: RestartPolicy,
+        panics: u32,
+        quitting: CancellationToken,
+    ) -> (Arc<Recorder>, u32) {
+        let recorder = Arc::new(Recorder::default());
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        supervise(
+            recorder.clone(),
+            "poller".to_string(),
+            policy,
+            quitting,
+            move || {
+                let run = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if run < panics {
+                        panic!("poller broke on run {}", run);
+                    }
+                }
+            },
+        )
+        .await;
+        let runs = runs.load(Ordering::SeqCst);
+        (recorder, runs)
+    }
+
+    fn history(recorder: &Recorder) -> Vec<TaskStatus> {
+        recorder.history.lock().clone()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_gives_up() {
+        let policy = backoff(5);
+        let delays: Vec<Option<Duration>> = (0..6).map(|n| policy.delay(n)).collect();
+        assert_eq!(
+            delays,
+            [
+                Some(SECOND),
+                Some(2 * SECOND),
+                Some(4 * SECOND),
+                Some(4 * SECOND),
+                Some(4 * SECOND),
+                None
+            ]
+        );
+        assert_eq!(RestartPolicy::Never.delay(0), None);
+        assert_eq!(RestartPolicy::POLLER.delay(40), None);
+        assert_eq!(
+            RestartPolicy::POLLER.delay(9),
+            Some(Duration::from_secs(5 * 60))
+        );
+    }
+
+    #[test]
+    fn panic_messages_are_recovered_from_the_payload() {
+        assert_eq!(panic_message(Box::new("static")), "static");
+        assert_eq!(panic_message(Box::new("owned".to_string())), "owned");
+        assert_eq!(
+            panic_message(Box::new(42)),
+            "panic with a non-string payload"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicking_task_is_restarted_with_backoff() {
+        let started = tokio::time::Instant::now();
+        let (recorder, runs) = run(backoff(5), 2, CancellationToken::new()).await;
+        assert_eq!(runs, 3);
+        assert_eq!(started.elapsed(), 3 * SECOND);
+        use TaskStatus::*;
+        assert_eq!(
+            history(&recorder),
+            [Running, Restarting, Running, Restarting, Running, Finished]
+        );
+        let records = recorder.health.snapshot();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].status, Finished);
+        assert_eq!(records[0].panics, 2);
+        assert_eq!(
+            records[0].last_panic.as_deref(),
+            Some("poller broke on run 1")
+        );
+        assert_eq!(records[0].last_panic_at, Some(1_000));
+        assert!(recorder.health.degraded().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_is_degraded_once_restarts_run_out() {
+        let (recorder, runs) = run(backoff(2), u32::MAX, CancellationToken::new()).await;
+        assert_eq!(runs, 3);
+        assert_eq!(recorder.health.degraded(), ["poller"]);
+        assert_eq!(recorder.health.snapshot()[0].panics, 3);
+        assert_eq!(history(&recorder).last(), Some(&TaskStatus::Degraded));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_without_restarts_is_degraded_after_one_panic() {
+        let (recorder, runs) = run(RestartPolicy::Never, u32::MAX, CancellationToken::new()).await;
+        assert_eq!(runs, 1);
+        assert_eq!(
+            history(&recorder),
+            [TaskStatus::Running, TaskStatus::Degraded]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quitting_stops_a_pending_restart() {
+        let quitting = CancellationToken::new();
+        let cancel = quitting.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(SECOND / 2).await;
+            cancel.cancel();
+        });
+        let started = tokio::time::Instant::now();
+        let (recorder, runs) = run(backoff(5), u32::MAX, quitting).await;
+        assert_eq!(runs, 1);
+        assert_eq!(started.elapsed(), SECOND / 2);
+        assert_eq!(recorder.health.degraded(), ["poller"]);
+
+        // Nothing is restarted once quitting has begun
+        let quitting = CancellationToken::new();
+        quitting.cancel();
+        let (recorder, runs) = run(backoff(5), u32::MAX, quitting).await;
+        assert_eq!(runs, 1);
+        assert_eq!(
+            history(&recorder),
+            [TaskStatus::Running, TaskStatus::Degraded]
+        );
+    }
+
+    #[tokio::test]
+    async fn one_shot_tasks_are_degraded_by_a_panic() {
+        let recorder = Arc::new(Recorder::default());
+        // `spawn_once` registers the task as running before it starts
+        recorder.status("output", TaskStatus::Running);
+        recorder.status("watchdog", TaskStatus::Running);
+        supervise_once(recorder.clone(), "output".to_string(), async {}).await;
+        supervise_once(recorder.clone(), "watchdog".to_string(), async {
+            panic!("watchdog broke")
+        })
+        .await;
+        let records = recorder.health.snapshot();
+        let summary: Vec<(&str, TaskStatus, u32)> = records
+            .iter()
+            .map(|record| (record.name.as_str(), record.status, record.panics))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("output", TaskStatus::Finished, 0),
+                ("watchdog", TaskStatus::Degraded, 1)
+            ]
+        );
+        assert_eq!(recorder.health.degraded(), ["watchdog"]);
+    }
+}
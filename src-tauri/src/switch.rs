@@ -0,0 +1,141 @@
+//! Moving between the sidecar and the Windows Service while the app runs.
+//!
+//! At startup the desktop uses the service if it answers and its own sidecar otherwise, so
+//! a service installed from the Settings page used to wait for the next launch. [`to_service`]
+//! stops the sidecar once the service answers its healthcheck; [`to_sidecar`] starts the
+//! sidecar and leaves the service running, and later starts and crash restarts stay on the
+//! sidecar until the app is relaunched. Neither direction switches while the backend it
+//! leaves is running backups, since the other one keeps its own schedule and state.
+//! Progress is reported as `loading-status`.
+
+use crate::capabilities::{self, Feature};
+use crate::drain::{DrainBackend, HttpBackend};
+use crate::error::AppError;
+use crate::loading::{self, LoadingStatus};
+use crate::ownership::BackendMode;
+use crate::{backend_token, events, pairing, profile, AppState, TrayBackendState};
+use std::sync::atomic::Ordering;
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// Switch to the backend `target` names and return the port the UI now talks to
+pub async fn switch(app: &tauri::AppHandle, target: BackendMode) -> Result<u16, AppError> {
+    match target {
+        BackendMode::Service => to_service(app).await,
+        BackendMode::Sidecar => to_sidecar(app).await,
+        BackendMode::External => {
+            Err("Only the sidecar and the Windows Service can be switched to".into())
+        }
+    }
+}
+
+/// Refuse while the connected backend runs backups. Backends that can't list their jobs
+/// are taken to be idle.
+async fn ensure_idle(state: &AppState) -> Result<(), AppError> {
+    let availability = capabilities::availability(Feature::JobQueue, state.capabilities());
+    if availability != capabilities::Availability::Available {
+        return Ok(());
+    }
+    let backend = HttpBackend {
+        http: &state.http,
+        port: state.backend_port.load(Ordering::SeqCst),
+    };
+    let jobs = backend.running_jobs().await.map_err(AppError::Message)?;
+    if jobs.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::BackupsRunning(jobs.len()))
+    }
+}
+
+/// Stop the sidecar and use the Windows Service, which must already be running
+pub async fn to_service(app: &tauri::AppHandle) -> Result<u16, AppError> {
+    let state = app.state::<AppState>();
+    let service_port = state.config().service_port;
+    if !profile::is_default() {
+        return Err("Only the default profile can use the Windows Service".into());
+    }
+    if state.using_service.load(Ordering::SeqCst) {
+        return Ok(service_port);
+    }
+    let _lifecycle = state
+        .lifecycle
+        .try_lock()
+        .map_err(|_| AppError::RestartInProgress)?;
+    ensure_idle(&state).await?;
+
+    loading::emit(app, LoadingStatus::CheckingService);
+    let sidecar_token = state.http.backend_token();
+    state
+        .http
+        .set_backend_token(backend_token::read_service_token());
+    let probe = crate::probe_service(app).await;
+    state.http.set_backend_token(sidecar_token);
+    if probe == pairing::ServiceProbe::Absent {
+        return Err(AppError::ServiceNotRunning(service_port));
+    }
+
+    info!("Switching to the Windows Service on port {}", service_port);
+    loading::emit(app, LoadingStatus::StoppingBackend);
+    state.backend_ready.store(false, Ordering::SeqCst);
+    crate::set_tray_backend_state(app, TrayBackendState::Restarting);
+    if let Err(e) = crate::stop_sidecar(&state).await {
+        warn!("Failed to stop the sidecar: {}", e);
+    }
+    state
+        .http
+        .set_backend_token(backend_token::read_service_token());
+    state.sidecar_preferred.store(false, Ordering::SeqCst);
+    state.using_service.store(true, Ordering::SeqCst);
+    state.backend_port.store(service_port, Ordering::SeqCst);
+    if probe == pairing::ServiceProbe::NeedsPairing {
+        pairing::mark_needs_pairing(app, service_port);
+    } else {
+        state.needs_pairing.store(false, Ordering::SeqCst);
+    }
+    state.backend_ready.store(true, Ordering::SeqCst);
+    crate::refresh_capabilities(app).await;
+    crate::set_tray_backend_state(app, TrayBackendState::Running);
+    crate::announce_backend_mode(app).await;
+    events::emit(app, "backend-restarted", service_port);
+    crate::renavigate_main_window(app, service_port);
+    Ok(service_port)
+}
+
+/// Start the sidecar and use it instead of the Windows Service, which keeps running
+pub async fn to_sidecar(app: &tauri::AppHandle) -> Result<u16, AppError> {
+    let state = app.state::<AppState>();
+    if !state.using_service.load(Ordering::SeqCst) {
+        return Ok(state.backend_port.load(Ordering::SeqCst));
+    }
+    let _lifecycle = state
+        .lifecycle
+        .try_lock()
+        .map_err(|_| AppError::RestartInProgress)?;
+    ensure_idle(&state).await?;
+
+    info!("Switching from the Windows Service to the sidecar");
+    state.backend_ready.store(false, Ordering::SeqCst);
+    crate::set_tray_backend_state(app, TrayBackendState::Restarting);
+    state.sidecar_preferred.store(true, Ordering::SeqCst);
+    state.using_service.store(false, Ordering::SeqCst);
+    state.needs_pairing.store(false, Ordering::SeqCst);
+    match crate::bring_up_sidecar(app).await {
+        Ok(port) => Ok(port),
+        Err(e) => {
+            // Go back to the service, which is still running
+            warn!("Failed to start the sidecar, staying on the service: {}", e);
+            let service_port = state.config().service_port;
+            state.sidecar_preferred.store(false, Ordering::SeqCst);
+            state.using_service.store(true, Ordering::SeqCst);
+            state.backend_port.store(service_port, Ordering::SeqCst);
+            state
+                .http
+                .set_backend_token(backend_token::read_service_token());
+            state.backend_ready.store(true, Ordering::SeqCst);
+            crate::set_tray_backend_state(app, TrayBackendState::Running);
+            crate::announce_backend_mode(app).await;
+            Err(e)
+        }
+    }
+}
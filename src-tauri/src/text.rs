@@ -0,0 +1,270 @@
+//! Fitting dynamic text (plan and repository names, status lines) into size-limited UI
+//! surfaces.
+//!
+//! Limits are counted in UTF-16 code units because that's what Windows measures, and
+//! truncation only ever cuts between grapheme clusters, so an emoji, a combining sequence or a
+//! surrogate pair is never split. Windows drops tray tooltips longer than its limit instead of
+//! cutting them, so that one is a hard cap even when settings ask for more.
+
+use crate::settings::SettingsStore;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use tauri::Manager;
+use unicode_segmentation::UnicodeSegmentation;
+
+const ELLIPSIS: &str = "…";
+
+/// Where a string is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    MenuItem,
+    Tooltip,
+    NotificationTitle,
+    NotificationBody,
+}
+
+/// Which part of a too-long string is replaced by the ellipsis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ellipsis {
+    /// Keep the start: "Quarterly finance export to Syn…"
+    End,
+    /// Keep both ends, for names that often share a long prefix: "Quarterly fin…(encrypted)"
+    Middle,
+}
+
+/// Windows tray tooltips hold 127 characters plus the terminator
+#[cfg(target_os = "windows")]
+const TOOLTIP_MAX: usize = 127;
+#[cfg(not(target_os = "windows"))]
+const TOOLTIP_MAX: usize = 256;
+
+/// Default limit for each surface on this platform
+pub fn default_limit(surface: Surface) -> usize {
+    match surface {
+        Surface::MenuItem => 48,
+        Surface::Tooltip => TOOLTIP_MAX,
+        #[cfg(target_os = "windows")]
+        Surface::NotificationTitle => 60,
+        #[cfg(not(target_os = "windows"))]
+        Surface::NotificationTitle => 80,
+        #[cfg(target_os = "windows")]
+        Surface::NotificationBody => 200,
+        #[cfg(not(target_os = "windows"))]
+        Surface::NotificationBody => 300,
+    }
+}
+
+/// Per-surface overrides from settings; unset fields use the platform default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextLimits {
+    pub menu_item: Option<usize>,
+    pub tooltip: Option<usize>,
+    pub notification_title: Option<usize>,
+    pub notification_body: Option<usize>,
+}
+
+impl TextLimits {
+    pub fn limit(&self, surface: Surface) -> usize {
+        let configured = match surface {
+            Surface::MenuItem => self.menu_item,
+            Surface::Tooltip => self.tooltip,
+            Surface::NotificationTitle => self.notification_title,
+            Surface::NotificationBody => self.notification_body,
+        };
+        let limit = configured.unwrap_or_else(|| default_limit(surface));
+        match surface {
+            Surface::Tooltip => limit.min(TOOLTIP_MAX),
+            _ => limit,
+        }
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Take graphemes from `graphemes` while they fit in `budget` UTF-16 units
+fn take_fitting<'a>(graphemes: impl Iterator<Item = &'a str>, budget: usize) -> Vec<&'a str> {
+    let mut used = 0;
+    graphemes
+        .take_while(|grapheme| {
+            used += utf16_len(grapheme);
+            used <= budget
+        })
+        .collect()
+}
+
+/// Shorten `text` to at most `limit` UTF-16 units, ellipsis included. Text that already
+/// fits is returned unchanged.
+pub fn truncate(text: &str, limit: usize, ellipsis: Ellipsis) -> Cow<'_, str> {
+    if utf16_len(text) <= limit {
+        return Cow::Borrowed(text);
+    }
+    let Some(budget) = limit.checked_sub(utf16_len(ELLIPSIS)) else {
+        return Cow::Owned(String::new());
+    };
+
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    match ellipsis {
+        Ellipsis::End => {
+            let head = take_fitting(graphemes.iter().copied(), budget).concat();
+            Cow::Owned(format!("{}{}", head.trim_end(), ELLIPSIS))
+        }
+        Ellipsis::Middle => {
+            // The start usually identifies the kind of thing, so it gets the extra unit
+            let tail_budget = budget / 2;
+            let head = take_fitting(graphemes.iter().copied(), budget - tail_budget);
+            let mut tail = take_fitting(graphemes.iter().rev().copied(), tail_budget);
+            tail.reverse();
+            Cow::Owned(format!(
+                "{}{}{}",
+                head.concat().trim_end(),
+                ELLIPSIS,
+                tail.concat().trim_start()
+            ))
+        }
+    }
+}
+
+/// Fit `text` to `surface` using the limits from settings
+pub fn fit(app: &tauri::AppHandle, surface: Surface, text: &str, ellipsis: Ellipsis) -> String {
+    let limit = app
+        .try_state::<SettingsStore>()
+        .map(|settings| settings.get().text_limits.limit(surface))
+        .unwrap_or_else(|| default_limit(surface));
+    truncate(text, limit, ellipsis).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = "Quarterly finance export to Synology NAS in the basement (encrypted)";
+
+    fn end(text: &str, limit: usize) -> String {
+        truncate(text, limit, Ellipsis::End).into_owned()
+    }
+
+    fn middle(text: &str, limit: usize) -> String {
+        truncate(text, limit, Ellipsis::Middle).into_owned()
+    }
+
+    #[test]
+    fn text_that_fits_is_borrowed() {
+        assert!(matches!(
+            truncate("Documents", 9, Ellipsis::End),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            truncate("", 0, Ellipsis::Middle),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn end_ellipsis_keeps_the_start() {
+        assert_eq!(end(PLAN, 20), "Quarterly finance e…");
+        // Trailing whitespace before the ellipsis is dropped
+        assert_eq!(end(PLAN, 11), "Quarterly…");
+    }
+
+    #[test]
+    fn middle_ellipsis_keeps_the_distinguishing_tail() {
+        let a = middle("Nightly backup of the shared drive (office A)", 24);
+        let b = middle("Nightly backup of the shared drive (office B)", 24);
+        assert_eq!(a, "Nightly back…(office A)");
+        assert_ne!(a, b);
+        assert!(utf16_len(&a) <= 24);
+    }
+
+    #[test]
+    fn surrogate_pairs_are_never_split() {
+        // Each emoji is two UTF-16 units; an odd budget can't cut one in half
+        let text = "💾💾💾💾💾";
+        assert_eq!(end(text, 6), "💾💾…");
+        assert_eq!(end(text, 5), "💾💾…");
+        assert_eq!(end(text, 4), "💾…");
+        assert_eq!(middle(text, 6), "💾…💾");
+        assert_eq!(middle(text, 8), "💾💾…💾");
+    }
+
+    #[test]
+    fn grapheme_clusters_stay_whole() {
+        // Family emoji: four people joined by zero-width joiners, one grapheme of 11 units
+        let family = "👨‍👩‍👧‍👦";
+        assert_eq!(utf16_len(family), 11);
+        assert_eq!(
+            end(&format!("{}{}", family, family), 12),
+            format!("{}…", family)
+        );
+        assert_eq!(end(&format!("{}{}", family, family), 11), "…");
+
+        // Decomposed é (e + combining acute) is never left as a bare e
+        let decomposed = "Cafe\u{301} cre\u{300}me";
+        assert_eq!(end(decomposed, 5), "Caf…");
+        assert_eq!(end(decomposed, 6), "Cafe\u{301}…");
+
+        // A flag is two regional indicators
+        let flags = "🇪🇸🇫🇷🇩🇪";
+        assert_eq!(end(flags, 6), "🇪🇸…");
+        assert_eq!(end(flags, 8), "🇪🇸…");
+    }
+
+    #[test]
+    fn wide_scripts_count_code_units_not_bytes() {
+        let text = "経理部の四半期バックアップ";
+        assert_eq!(end(text, 5), "経理部の…");
+        assert_eq!(middle(text, 7), "経理部…アップ");
+    }
+
+    #[test]
+    fn tiny_limits_degrade_to_ellipsis_or_nothing() {
+        assert_eq!(end(PLAN, 1), "…");
+        assert_eq!(middle(PLAN, 1), "…");
+        assert_eq!(end(PLAN, 0), "");
+        assert_eq!(middle(PLAN, 0), "");
+    }
+
+    #[test]
+    fn results_always_fit_and_end_on_grapheme_boundaries() {
+        let samples = [
+            PLAN,
+            "💾 Fotos de la familia 👨‍👩‍👧‍👦 🇪🇸",
+            "Cafe\u{301}\u{301}\u{301}x",
+        ];
+        for sample in samples {
+            let graphemes: Vec<&str> = sample.graphemes(true).collect();
+            for limit in 0..=utf16_len(sample) + 1 {
+                for ellipsis in [Ellipsis::End, Ellipsis::Middle] {
+                    let fitted = truncate(sample, limit, ellipsis);
+                    assert!(utf16_len(&fitted) <= limit, "{:?} at {}", fitted, limit);
+                    let head = fitted.split(ELLIPSIS).next().unwrap();
+                    let mut rebuilt = String::new();
+                    for grapheme in &graphemes {
+                        if rebuilt.len() >= head.len() {
+                            break;
+                        }
+                        rebuilt.push_str(grapheme);
+                    }
+                    assert_eq!(rebuilt.trim_end(), head, "{:?} at {}", fitted, limit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn settings_override_limits_but_not_the_tooltip_cap() {
+        let limits = TextLimits {
+            menu_item: Some(20),
+            tooltip: Some(10_000),
+            ..TextLimits::default()
+        };
+        assert_eq!(limits.limit(Surface::MenuItem), 20);
+        assert_eq!(limits.limit(Surface::Tooltip), TOOLTIP_MAX);
+        assert_eq!(
+            limits.limit(Surface::NotificationBody),
+            default_limit(Surface::NotificationBody)
+        );
+    }
+}
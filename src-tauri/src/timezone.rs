@@ -0,0 +1,382 @@
+//! Showing backend schedule times in the desktop's current time zone.
+//!
+//! The backend reports times either as absolute timestamps (with an offset) or as wall-clock
+//! times in its own zone, which it names in its capabilities or healthcheck payload. Both are
+//! converted to the zone the desktop is in right now, so "Next backup: 03:00" stays right
+//! after travelling or a DST change. Wall-clock times that don't exist (skipped by a DST
+//! jump) move forward to the first time that does, as cron does; times that happen twice
+//! resolve to the first occurrence. Either case is reported so the UI can say so.
+//!
+//! The desktop zone is checked once a minute. When its name or offset changes,
+//! `time-zone-changed` tells the webviews to render their times again.
+
+use crate::AppState;
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, LocalResult, NaiveDateTime, Offset, TimeZone,
+    Utc,
+};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::Manager;
+use tracing::info;
+
+/// How often the desktop's zone is compared with the last one seen
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest DST jump searched for when a wall-clock time doesn't exist
+const MAX_GAP_MINUTES: i64 = 3 * 60;
+
+/// Wall-clock formats accepted for zone-less backend times
+const WALL_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"];
+
+/// How a backend time mapped onto a real instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Resolution {
+    Exact,
+    /// The wall-clock time was skipped by a DST jump and moved forward by this much
+    Gap { shifted_minutes: i64 },
+    /// The wall-clock time happens twice; the first occurrence was used
+    Overlap,
+}
+
+/// Parse an IANA zone name such as "Europe/Madrid"
+pub fn parse_zone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// The zone the desktop is in, or UTC if the OS doesn't say
+pub fn desktop_zone() -> Tz {
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| parse_zone(&name))
+        .unwrap_or(Tz::UTC)
+}
+
+/// The instant a wall-clock time in `zone` refers to
+pub fn resolve_wall_time(wall: NaiveDateTime, zone: Tz) -> Option<(DateTime<Utc>, Resolution)> {
+    match zone.from_local_datetime(&wall) {
+        LocalResult::Single(time) => Some((time.with_timezone(&Utc), Resolution::Exact)),
+        LocalResult::Ambiguous(earliest, _) => {
+            Some((earliest.with_timezone(&Utc), Resolution::Overlap))
+        }
+        LocalResult::None => (1..=MAX_GAP_MINUTES).find_map(|minutes| {
+            let shifted = wall + ChronoDuration::minutes(minutes);
+            match zone.from_local_datetime(&shifted) {
+                LocalResult::Single(time) => Some((
+                    time.with_timezone(&Utc),
+                    Resolution::Gap {
+                        shifted_minutes: minutes,
+                    },
+                )),
+                _ => None,
+            }
+        }),
+    }
+}
+
+/// The instant a backend time refers to. Times with an offset are absolute; times without
+/// one are wall-clock times in `backend_zone` (UTC if it's unknown).
+pub fn parse_backend_time(
+    value: &str,
+    backend_zone: Option<Tz>,
+) -> Result<(DateTime<Utc>, Resolution), String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value.trim()) {
+        return Ok((time.with_timezone(&Utc), Resolution::Exact));
+    }
+    let wall = WALL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value.trim(), format).ok())
+        .ok_or_else(|| format!("Unrecognized time \"{}\"", value))?;
+    let zone = backend_zone.unwrap_or(Tz::UTC);
+    resolve_wall_time(wall, zone)
+        .ok_or_else(|| format!("\"{}\" doesn't exist in {}", value, zone.name()))
+}
+
+/// Short rendering relative to `now`: "03:00", "Tomorrow 03:00", "Tue 03:00" within the
+/// week, otherwise "12 Mar 03:00"
+pub fn format_relative(time: DateTime<Tz>, now: DateTime<Tz>) -> String {
+    let days = (time.date_naive() - now.date_naive()).num_days();
+    let clock = time.format("%H:%M");
+    match days {
+        0 => clock.to_string(),
+        1 => format!("Tomorrow {}", clock),
+        2..=6 => format!("{} {}", time.format("%a"), clock),
+        _ if time.year() == now.year() => time.format("%-d %b %H:%M").to_string(),
+        _ => time.format("%-d %b %Y %H:%M").to_string(),
+    }
+}
+
+/// How far `to` is ahead of `from` at `at`, in minutes
+pub fn offset_minutes(from: Tz, to: Tz, at: DateTime<Utc>) -> i32 {
+    let offset = |zone: Tz| zone.offset_from_utc_datetime(&at.naive_utc()).fix();
+    (offset(to).local_minus_utc() - offset(from).local_minus_utc()) / 60
+}
+
+/// A backend time as the desktop shows it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConvertedTime {
+    pub input: String,
+    /// RFC 3339 in the desktop's zone
+    pub local: String,
+    pub display: String,
+    pub resolution: Resolution,
+}
+
+pub fn convert(
+    value: &str,
+    backend_zone: Option<Tz>,
+    desktop: Tz,
+    now: DateTime<Utc>,
+) -> Result<ConvertedTime, String> {
+    let (instant, resolution) = parse_backend_time(value, backend_zone)?;
+    let local = instant.with_timezone(&desktop);
+    Ok(ConvertedTime {
+        input: value.to_string(),
+        local: local.to_rfc3339(),
+        display: format_relative(local, now.with_timezone(&desktop)),
+        resolution,
+    })
+}
+
+/// The desktop's zone at one moment; payload of `time-zone-changed`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ZoneSnapshot {
+    pub name: String,
+    /// Minutes ahead of UTC
+    pub offset_minutes: i32,
+}
+
+impl ZoneSnapshot {
+    pub fn current() -> Self {
+        let zone = desktop_zone();
+        Self {
+            name: zone.name().to_string(),
+            offset_minutes: offset_minutes(Tz::UTC, zone, Utc::now()),
+        }
+    }
+}
+
+/// The zone a backend names in its capabilities or healthcheck body, if any
+pub fn reported_zone(body: &serde_json::Value) -> Option<String> {
+    ["timeZone", "timezone", "time_zone"]
+        .iter()
+        .find_map(|key| body.get(key)?.as_str())
+        .filter(|name| parse_zone(name).is_some())
+        .map(str::to_string)
+}
+
+/// Tell the webviews to re-render times whenever the desktop's zone or offset changes
+pub async fn watch(app: tauri::AppHandle) {
+    let mut ticker = app.state::<AppState>().activity.register(
+        "time-zone",
+        crate::activity::TaskClass::Optional,
+        POLL_INTERVAL,
+    );
+    let mut last = ZoneSnapshot::current();
+    loop {
+        ticker.tick().await;
+        let current = ZoneSnapshot::current();
+        if current != last {
+            info!(
+                "Time zone changed from {} ({:+} min) to {} ({:+} min)",
+                last.name, last.offset_minutes, current.name, current.offset_minutes
+            );
+            crate::events::emit(&app, "time-zone-changed", current.clone());
+            last = current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(name: &str) -> Tz {
+        parse_zone(name).unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn wall_times_resolve_across_zones_and_dst() {
+        // (zone, wall-clock time, instant, resolution)
+        let cases = [
+            (
+                "UTC",
+                "2024-03-31T02:30",
+                "2024-03-31T02:30:00Z",
+                Resolution::Exact,
+            ),
+            (
+                "Asia/Kolkata",
+                "2024-03-31T02:30",
+                "2024-03-30T21:00:00Z",
+                Resolution::Exact,
+            ),
+            // Spring forward: 02:00 jumps to 03:00
+            (
+                "Europe/Madrid",
+                "2024-03-31T02:30",
+                "2024-03-31T01:00:00Z",
+                Resolution::Gap {
+                    shifted_minutes: 30,
+                },
+            ),
+            (
+                "America/New_York",
+                "2024-03-10T02:00",
+                "2024-03-10T07:00:00Z",
+                Resolution::Gap {
+                    shifted_minutes: 60,
+                },
+            ),
+            // Lord Howe moves its clocks by half an hour
+            (
+                "Australia/Lord_Howe",
+                "2024-10-06T02:15",
+                "2024-10-05T15:30:00Z",
+                Resolution::Gap {
+                    shifted_minutes: 15,
+                },
+            ),
+            // Fall back: the first (summer time) occurrence wins
+            (
+                "Europe/Madrid",
+                "2024-10-27T02:30",
+                "2024-10-27T00:30:00Z",
+                Resolution::Overlap,
+            ),
+            (
+                "America/New_York",
+                "2024-11-03T01:30",
+                "2024-11-03T05:30:00Z",
+                Resolution::Overlap,
+            ),
+            (
+                "Australia/Lord_Howe",
+                "2024-04-07T01:45",
+                "2024-04-06T14:45:00Z",
+                Resolution::Overlap,
+            ),
+            // Southern hemisphere: DST ends in April, the day before is still summer time
+            (
+                "Australia/Sydney",
+                "2024-04-06T03:00",
+                "2024-04-05T16:00:00Z",
+                Resolution::Exact,
+            ),
+        ];
+        for (name, wall, expected, resolution) in cases {
+            let (instant, actual) = parse_backend_time(wall, Some(zone(name))).unwrap();
+            assert_eq!(
+                (instant, actual),
+                (utc(expected), resolution),
+                "{} in {}",
+                wall,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn skipped_day_is_an_error() {
+        // Samoa skipped 30 December 2011 entirely when it crossed the date line
+        let result = parse_backend_time("2011-12-30T12:00", Some(zone("Pacific/Apia")));
+        assert!(result.unwrap_err().contains("Pacific/Apia"));
+    }
+
+    #[test]
+    fn times_with_an_offset_ignore_the_backend_zone() {
+        let (instant, resolution) =
+            parse_backend_time("2024-03-31T02:30:00+02:00", Some(zone("America/New_York")))
+                .unwrap();
+        assert_eq!(instant, utc("2024-03-31T00:30:00Z"));
+        assert_eq!(resolution, Resolution::Exact);
+    }
+
+    #[test]
+    fn unknown_backend_zone_means_utc() {
+        let (instant, _) = parse_backend_time("2024-06-01 03:00:00", None).unwrap();
+        assert_eq!(instant, utc("2024-06-01T03:00:00Z"));
+        assert!(parse_backend_time("next tuesday", None).is_err());
+    }
+
+    #[test]
+    fn convert_renders_in_the_desktop_zone() {
+        // Backend in New York, desktop travelled to Madrid
+        let now = utc("2024-06-10T12:00:00Z");
+        let converted = convert(
+            "2024-06-10T21:00",
+            Some(zone("America/New_York")),
+            zone("Europe/Madrid"),
+            now,
+        )
+        .unwrap();
+        assert_eq!(converted.local, "2024-06-11T03:00:00+02:00");
+        assert_eq!(converted.display, "Tomorrow 03:00");
+    }
+
+    #[test]
+    fn relative_formatting() {
+        let madrid = zone("Europe/Madrid");
+        let now = utc("2024-06-10T12:00:00Z").with_timezone(&madrid);
+        let at = |value: &str| utc(value).with_timezone(&madrid);
+        let cases = [
+            ("2024-06-10T20:00:00Z", "22:00"),
+            ("2024-06-11T01:00:00Z", "Tomorrow 03:00"),
+            ("2024-06-14T01:00:00Z", "Fri 03:00"),
+            ("2024-07-01T01:00:00Z", "1 Jul 03:00"),
+            ("2025-01-02T02:00:00Z", "2 Jan 2025 03:00"),
+        ];
+        for (time, expected) in cases {
+            assert_eq!(format_relative(at(time), now), expected, "{}", time);
+        }
+    }
+
+    #[test]
+    fn offsets_follow_dst_on_both_sides() {
+        let madrid = zone("Europe/Madrid");
+        let kolkata = zone("Asia/Kolkata");
+        assert_eq!(
+            offset_minutes(madrid, kolkata, utc("2024-07-01T00:00:00Z")),
+            210
+        );
+        assert_eq!(
+            offset_minutes(madrid, kolkata, utc("2024-01-01T00:00:00Z")),
+            270
+        );
+        assert_eq!(
+            offset_minutes(
+                zone("America/New_York"),
+                madrid,
+                utc("2024-03-20T00:00:00Z")
+            ),
+            // New York already on summer time, Madrid not yet
+            300
+        );
+    }
+
+    #[test]
+    fn reported_zone_reads_known_keys_and_valid_names() {
+        let zone_of = |body: serde_json::Value| reported_zone(&body);
+        assert_eq!(
+            zone_of(serde_json::json!({ "timeZone": "Europe/Madrid" })).as_deref(),
+            Some("Europe/Madrid")
+        );
+        assert_eq!(
+            zone_of(serde_json::json!({ "time_zone": "UTC" })).as_deref(),
+            Some("UTC")
+        );
+        assert_eq!(
+            zone_of(serde_json::json!({ "timezone": "Mars/Olympus" })),
+            None
+        );
+        assert_eq!(zone_of(serde_json::json!({ "tz": "UTC" })), None);
+    }
+}
@@ -0,0 +1,800 @@
+//! Resumable, checksum-verified downloads and chunked uploads of large files.
+//!
+//! Downloads go to `<dest>.part` and are only renamed into place once the SHA-256 of the
+//! whole file matches. A dropped connection resumes from the end of the partial file with a
+//! `Range` request instead of starting over, and so does a later call for the same
+//! destination: pausing a transfer is cancelling its token, resuming is calling
+//! [`download_file`] again, and [`discard_partial`] abandons it for good. Servers that ignore
+//! `Range` are handled by starting from scratch.
+//!
+//! The reqwest client here has no overall timeout (a service binary can take minutes on a slow
+//! link); stalls are caught by the read timeout instead. Quitting the app cancels transfers
+//! through the token from [`crate::http::HttpClient::child_token`].
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A connection that delivers nothing for this long is treated as interrupted
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interruptions tolerated in a row (without any progress in between) before giving up
+const MAX_RESUMES: u32 = 5;
+const RESUME_DELAY: Duration = Duration::from_secs(2);
+/// Minimum gap between progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// Upload chunk size
+pub const UPLOAD_CHUNK: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("Transfer cancelled")]
+    Cancelled,
+    #[error("Not enough disk space to write {}", .0.display())]
+    DiskFull(PathBuf),
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("Server answered HTTP {0}")]
+    Status(u16),
+    #[error("Transfer kept failing: {0}")]
+    Interrupted(String),
+    #[error("{0}")]
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for TransferError {
+    fn from(error: std::io::Error) -> Self {
+        TransferError::Io(error)
+    }
+}
+
+/// Whether `error` means the volume is full
+fn is_disk_full(error: &std::io::Error) -> bool {
+    #[cfg(target_os = "windows")]
+    const CODES: &[i32] = &[39, 112]; // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+    #[cfg(not(target_os = "windows"))]
+    const CODES: &[i32] = &[28]; // ENOSPC
+
+    error.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+/// Turn a write error on `path` into a typed one
+fn write_error(path: &Path, error: std::io::Error) -> TransferError {
+    if is_disk_full(&error) {
+        TransferError::DiskFull(path.to_path_buf())
+    } else {
+        TransferError::Io(error)
+    }
+}
+
+/// Progress of one transfer
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferProgress {
+    pub bytes: u64,
+    pub total: Option<u64>,
+    pub bytes_per_sec: u64,
+    pub eta_secs: Option<u64>,
+}
+
+/// Reports progress to every webview as `transfer-progress`, tagged with `id`
+pub fn progress_emitter(app: &tauri::AppHandle, id: &str) -> impl Fn(TransferProgress) {
+    let app = app.clone();
+    let id = id.to_string();
+    move |progress| {
+        crate::events::emit(
+            &app,
+            "transfer-progress",
+            serde_json::json!({ "id": id, "progress": progress }),
+        );
+    }
+}
+
+/// Rate and ETA over the bytes moved since the transfer (re)started
+struct Meter {
+    started: Instant,
+    initial: u64,
+    last_report: Option<Instant>,
+}
+
+impl Meter {
+    fn new(initial: u64) -> Self {
+        Self {
+            started: Instant::now(),
+            initial,
+            last_report: None,
+        }
+    }
+
+    /// A progress report, if one is due (or `force`)
+    fn report(&mut self, bytes: u64, total: Option<u64>, force: bool) -> Option<TransferProgress> {
+        let now = Instant::now();
+        if !force && self.last_report.is_some_and(|last| now - last < PROGRESS_INTERVAL) {
+            return None;
+        }
+        self.last_report = Some(now);
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let bytes_per_sec = if elapsed > 0.0 {
+            (bytes.saturating_sub(self.initial) as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let eta_secs = total
+            .filter(|_| bytes_per_sec > 0)
+            .map(|total| total.saturating_sub(bytes) / bytes_per_sec);
+        Some(TransferProgress {
+            bytes,
+            total,
+            bytes_per_sec,
+            eta_secs,
+        })
+    }
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Where a download in progress is kept
+pub fn partial_path(dest: &Path) -> PathBuf {
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Abandon a paused download
+pub fn discard_partial(dest: &Path) {
+    let _ = std::fs::remove_file(partial_path(dest));
+}
+
+/// Lowercase hex SHA-256 of a file
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// How one attempt at fetching the rest of the file ended
+enum Attempt {
+    Complete,
+    /// The connection dropped; `progressed` says whether any bytes arrived first
+    Interrupted { progressed: bool, reason: String },
+}
+
+/// Fetch `url` into `dest`, resuming a partial download if there is one, and verify it
+/// against `expected_sha256` (hex) before moving it into place
+pub async fn download_file(
+    url: &str,
+    dest: &Path,
+    expected_sha256: &str,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(TransferProgress),
+) -> Result<(), TransferError> {
+    let part = partial_path(dest);
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| write_error(parent, e))?;
+    }
+
+    let client = client();
+    let mut failures = 0;
+    loop {
+        if cancel.is_cancelled() {
+            return Err(TransferError::Cancelled);
+        }
+        match fetch_remaining(&client, url, &part, cancel, &on_progress).await? {
+            Attempt::Complete => break,
+            Attempt::Interrupted { progressed, reason } => {
+                failures = if progressed { 1 } else { failures + 1 };
+                if failures > MAX_RESUMES {
+                    return Err(TransferError::Interrupted(reason));
+                }
+                warn!("Download of {} interrupted ({}), resuming", url, reason);
+                tokio::select! {
+                    _ = cancel.cancelled() => return Err(TransferError::Cancelled),
+                    _ = tokio::time::sleep(RESUME_DELAY) => {}
+                }
+            }
+        }
+    }
+
+    let hashed = part.clone();
+    let actual = tokio::task::spawn_blocking(move || sha256_file(&hashed))
+        .await
+        .map_err(|e| TransferError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))??;
+    if !actual.eq_ignore_ascii_case(expected_sha256) {
+        // Resuming can't fix a bad file; the next attempt has to start over
+        let _ = std::fs::remove_file(&part);
+        return Err(TransferError::ChecksumMismatch {
+            expected: expected_sha256.to_lowercase(),
+            actual,
+        });
+    }
+
+    std::fs::rename(&part, dest).map_err(|e| write_error(dest, e))?;
+    info!("Downloaded {} to {}", url, dest.display());
+    Ok(())
+}
+
+/// Request everything past the end of `part` and append it
+async fn fetch_remaining(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    cancel: &CancellationToken,
+    on_progress: &impl Fn(TransferProgress),
+) -> Result<Attempt, TransferError> {
+    let mut offset = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+
+    let response = tokio::select! {
+        _ = cancel.cancelled() => return Err(TransferError::Cancelled),
+        response = request.send() => response,
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(Attempt::Interrupted {
+                progressed: false,
+                reason: e.to_string(),
+            })
+        }
+    };
+
+    let status = response.status();
+    let total = match status {
+        reqwest::StatusCode::PARTIAL_CONTENT => response.content_length().map(|len| offset + len),
+        // The partial file already holds everything the server has
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(Attempt::Complete),
+        status if status.is_success() => {
+            if offset > 0 {
+                info!("Server ignored the range request for {}, starting over", url);
+                offset = 0;
+            }
+            response.content_length()
+        }
+        status if status.is_server_error() => {
+            return Ok(Attempt::Interrupted {
+                progressed: false,
+                reason: format!("HTTP {}", status.as_u16()),
+            })
+        }
+        status => return Err(TransferError::Status(status.as_u16())),
+    };
+
+    if let Some(e) = crate::faults::disk_full(part) {
+        return Err(write_error(part, e));
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(part)
+        .map_err(|e| write_error(part, e))?;
+    file.set_len(offset).map_err(|e| write_error(part, e))?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut meter = Meter::new(offset);
+    let mut written = offset;
+    let mut response = response;
+    loop {
+        let chunk = tokio::select! {
+            _ = cancel.cancelled() => {
+                file.flush()?;
+                return Err(TransferError::Cancelled);
+            }
+            chunk = response.chunk() => chunk,
+        };
+        match chunk {
+            Ok(Some(bytes)) => {
+                file.write_all(&bytes).map_err(|e| write_error(part, e))?;
+                written += bytes.len() as u64;
+                if let Some(progress) = meter.report(written, total, false) {
+                    on_progress(progress);
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                file.flush()?;
+                return Ok(Attempt::Interrupted {
+                    progressed: written > offset,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    file.sync_all().map_err(|e| write_error(part, e))?;
+
+    // A body that ends early without an error is an interruption too
+    if let Some(total) = total.filter(|total| written < *total) {
+        return Ok(Attempt::Interrupted {
+            progressed: written > offset,
+            reason: format!("connection closed at {} of {} bytes", written, total),
+        });
+    }
+    if let Some(progress) = meter.report(written, total, true) {
+        on_progress(progress);
+    }
+    Ok(Attempt::Complete)
+}
+
+/// Send `path` to `url` in `UPLOAD_CHUNK`-sized `PUT` requests carrying `Content-Range`,
+/// retrying a failed chunk rather than the whole file
+pub async fn upload_file(
+    url: &str,
+    path: &Path,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(TransferProgress),
+) -> Result<(), TransferError> {
+    let client = client();
+    let mut file = std::fs::File::open(path)?;
+    let total = file.metadata()?.len();
+    let mut meter = Meter::new(0);
+    let mut sent = 0u64;
+    let mut buffer = vec![0u8; UPLOAD_CHUNK];
+
+    while sent < total || total == 0 {
+        let length = file.read(&mut buffer)?;
+        let chunk = buffer[..length].to_vec();
+        let range = match length {
+            0 => format!("bytes */{}", total),
+            _ => format!("bytes {}-{}/{}", sent, sent + length as u64 - 1, total),
+        };
+
+        let mut failures = 0;
+        loop {
+            let request = client
+                .put(url)
+                .header(reqwest::header::CONTENT_RANGE, &range)
+                .body(chunk.clone())
+                .send();
+            let result = tokio::select! {
+                _ = cancel.cancelled() => return Err(TransferError::Cancelled),
+                result = request => result,
+            };
+            let reason = match result {
+                Ok(response) if response.status().is_success() => break,
+                Ok(response) if !response.status().is_server_error() => {
+                    return Err(TransferError::Status(response.status().as_u16()))
+                }
+                Ok(response) => format!("HTTP {}", response.status().as_u16()),
+                Err(e) => e.to_string(),
+            };
+            failures += 1;
+            if failures > MAX_RESUMES {
+                return Err(TransferError::Interrupted(reason));
+            }
+            warn!("Upload of {} interrupted ({}), retrying chunk", path.display(), reason);
+            tokio::select! {
+                _ = cancel.cancelled() => return Err(TransferError::Cancelled),
+                _ = tokio::time::sleep(RESUME_DELAY) => {}
+            }
+        }
+
+        if length == 0 {
+            break;
+        }
+        sent += length as u64;
+        if let Some(progress) = meter.report(sent, Some(total), sent == total) {
+            on_progress(progress);
+        }
+    }
+    info!("Uploaded {} to {}", path.display(), url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// What the test server does with the n-th request (from 0)
+    #[derive(Clone, Copy)]
+    enum Reply {
+        /// Serve the requested range (or everything), honouring `Range`
+        Serve,
+        /// Serve the whole body with a 200, ignoring `Range`
+        IgnoreRange,
+        /// Announce the requested range but drop the connection after this many bytes
+        Cut(usize),
+        Status(u16),
+    }
+
+    /// A request as the test server saw it
+    #[derive(Debug, Clone, PartialEq)]
+    struct Seen {
+        method: String,
+        range: Option<String>,
+        body: Vec<u8>,
+    }
+
+    type Log = Arc<Mutex<Vec<Seen>>>;
+
+    async fn read_request(stream: &mut TcpStream) -> Option<Seen> {
+        let mut buf = Vec::new();
+        let header_end = loop {
+            let mut byte = [0u8; 1];
+            if stream.read(&mut byte).await.ok()? == 0 {
+                return None;
+            }
+            buf.push(byte[0]);
+            if buf.ends_with(b"\r\n\r\n") {
+                break buf.len();
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+        let header = |name: &str| {
+            head.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_string())
+            })
+        };
+        let length: usize = header("content-length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).await.ok()?;
+        Some(Seen {
+            method: head
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            range: header("range").or_else(|| header("content-range")),
+            body,
+        })
+    }
+
+    /// A local server for `body` that answers the n-th request with `reply(n)`. Returns its
+    /// URL and the requests it received.
+    async fn server(
+        body: Vec<u8>,
+        reply: impl Fn(usize) -> Reply + Send + 'static,
+    ) -> (String, Log) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/file",
+            listener.local_addr().unwrap().port()
+        );
+        let log: Log = Arc::default();
+        let seen = log.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let Some(request) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let start = request
+                    .range
+                    .as_deref()
+                    .and_then(|range| range.strip_prefix("bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                let n = {
+                    let mut seen = seen.lock();
+                    seen.push(request);
+                    seen.len() - 1
+                };
+                let (status, from) = match (reply(n), start) {
+                    (Reply::Status(status), _) => (status, body.len()),
+                    (Reply::IgnoreRange, _) | (_, None) => (200, 0),
+                    (_, Some(start)) if start >= body.len() => (416, body.len()),
+                    (_, Some(start)) => (206, start),
+                };
+                let rest = &body[from..];
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    rest.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let sent = match reply(n) {
+                    Reply::Cut(bytes) => &rest[..bytes.min(rest.len())],
+                    _ => rest,
+                };
+                let _ = stream.write_all(sent).await;
+                let _ = stream.flush().await;
+            }
+        });
+        (url, log)
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256(bytes: &[u8]) -> String {
+        Sha256::digest(bytes)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn temp_dest(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("transfer-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("file.bin")
+    }
+
+    fn ranges(log: &Log) -> Vec<Option<String>> {
+        log.lock().iter().map(|seen| seen.range.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn dropped_download_resumes_where_it_stopped() {
+        let body = data(50_000);
+        let (url, log) = server(body.clone(), |n| {
+            if n == 0 {
+                Reply::Cut(20_000)
+            } else {
+                Reply::Serve
+            }
+        })
+        .await;
+        let dest = temp_dest("resume");
+        let reports = Mutex::new(Vec::new());
+
+        let result = download_file(
+            &url,
+            &dest,
+            &sha256(&body),
+            &CancellationToken::new(),
+            |p| reports.lock().push(p),
+        )
+        .await;
+        let written = std::fs::read(&dest);
+        let part_left = partial_path(&dest).exists();
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), body);
+        assert!(!part_left);
+        assert_eq!(ranges(&log), [None, Some("bytes=20000-".to_string())]);
+        let last = reports.lock().last().cloned().unwrap();
+        assert_eq!((last.bytes, last.total), (50_000, Some(50_000)));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let body = data(1_000);
+        let (url, log) = server(body.clone(), |n| {
+            if n == 0 {
+                Reply::Status(503)
+            } else {
+                Reply::Serve
+            }
+        })
+        .await;
+        let dest = temp_dest("retry");
+
+        let result = download_file(
+            &url,
+            &dest,
+            &sha256(&body),
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        let written = std::fs::read(&dest);
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), body);
+        assert_eq!(log.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn partial_file_from_an_earlier_call_is_resumed() {
+        let body = data(10_000);
+        let (url, log) = server(body.clone(), |_| Reply::Serve).await;
+        let dest = temp_dest("paused");
+        std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        std::fs::write(partial_path(&dest), &body[..4_000]).unwrap();
+
+        let result = download_file(
+            &url,
+            &dest,
+            &sha256(&body),
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        let written = std::fs::read(&dest);
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), body);
+        assert_eq!(ranges(&log), [Some("bytes=4000-".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn complete_partial_file_is_only_verified() {
+        let body = data(3_000);
+        let (url, log) = server(body.clone(), |_| Reply::Serve).await;
+        let dest = temp_dest("complete");
+        std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        std::fs::write(partial_path(&dest), &body).unwrap();
+
+        let result = download_file(
+            &url,
+            &dest,
+            &sha256(&body),
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        let written = std::fs::read(&dest);
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), body);
+        assert_eq!(ranges(&log), [Some("bytes=3000-".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn server_ignoring_ranges_starts_over() {
+        let body = data(8_000);
+        let (url, _) = server(body.clone(), |_| Reply::IgnoreRange).await;
+        let dest = temp_dest("ignore");
+        std::fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        std::fs::write(partial_path(&dest), vec![0xffu8; 5_000]).unwrap();
+
+        let result = download_file(
+            &url,
+            &dest,
+            &sha256(&body),
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        let written = std::fs::read(&dest);
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(written.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_discards_the_download() {
+        let body = data(2_000);
+        let (url, _) = server(body, |_| Reply::Serve).await;
+        let dest = temp_dest("mismatch");
+
+        let result = download_file(
+            &url,
+            &dest,
+            &"AB".repeat(32),
+            &CancellationToken::new(),
+            |_| {},
+        )
+        .await;
+        let kept = (dest.exists(), partial_path(&dest).exists());
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        let Err(TransferError::ChecksumMismatch { expected, .. }) = result else {
+            panic!("expected a checksum mismatch, got {:?}", result);
+        };
+        assert_eq!(expected, "ab".repeat(32));
+        assert_eq!(kept, (false, false));
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (url, log) = server(data(10), |_| Reply::Status(404)).await;
+        let dest = temp_dest("missing");
+
+        let result = download_file(&url, &dest, "", &CancellationToken::new(), |_| {}).await;
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        assert!(
+            matches!(result, Err(TransferError::Status(404))),
+            "{:?}",
+            result
+        );
+        assert_eq!(log.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_download_keeps_its_partial_file() {
+        let body = data(10_000);
+        let (url, log) = server(body.clone(), |_| Reply::Cut(6_000)).await;
+        let dest = temp_dest("cancel");
+        let cancel = CancellationToken::new();
+
+        // Cancel while the first interruption waits to resume
+        let checksum = sha256(&body);
+        let download = download_file(&url, &dest, &checksum, &cancel, |_| {});
+        let canceller = async {
+            while log.lock().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        };
+        let (result, ()) = tokio::join!(download, canceller);
+        let partial = std::fs::read(partial_path(&dest));
+        let _ = std::fs::remove_dir_all(dest.parent().unwrap());
+
+        assert!(
+            matches!(result, Err(TransferError::Cancelled)),
+            "{:?}",
+            result
+        );
+        assert_eq!(partial.unwrap(), &body[..6_000]);
+        assert_eq!(log.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn upload_retries_only_the_failed_chunk() {
+        let body = data(UPLOAD_CHUNK + 1_000);
+        let (url, log) = server(Vec::new(), |n| {
+            if n == 1 {
+                Reply::Status(502)
+            } else {
+                Reply::Status(200)
+            }
+        })
+        .await;
+        let path = temp_dest("upload");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &body).unwrap();
+
+        let result = upload_file(&url, &path, &CancellationToken::new(), |_| {}).await;
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        result.unwrap();
+        let seen = log.lock().clone();
+        let last = UPLOAD_CHUNK + 999;
+        let total = body.len();
+        assert_eq!(
+            seen.iter()
+                .map(|s| (s.method.as_str(), s.range.clone().unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                ("PUT", format!("bytes 0-{}/{}", UPLOAD_CHUNK - 1, total)),
+                ("PUT", format!("bytes {}-{}/{}", UPLOAD_CHUNK, last, total)),
+                ("PUT", format!("bytes {}-{}/{}", UPLOAD_CHUNK, last, total)),
+            ]
+        );
+        assert_eq!(seen[0].body, &body[..UPLOAD_CHUNK]);
+        assert_eq!(seen[2].body, &body[UPLOAD_CHUNK..]);
+    }
+
+    #[tokio::test]
+    async fn empty_upload_sends_one_empty_chunk() {
+        let (url, log) = server(Vec::new(), |_| Reply::Status(200)).await;
+        let path = temp_dest("empty");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"").unwrap();
+
+        let result = upload_file(&url, &path, &CancellationToken::new(), |_| {}).await;
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        result.unwrap();
+        assert_eq!(ranges(&log), [Some("bytes */0".to_string())]);
+    }
+}
@@ -0,0 +1,189 @@
+//! CPU and memory use of a backend process, for the Settings page's backend panel.
+//!
+//! Read straight from the platform like [`crate::metrics::resident_bytes`]: `/proc` on Linux,
+//! the process APIs on Windows and `ps` elsewhere. CPU use is measured over a short window by
+//! reading the process's CPU time twice, so [`sample`] blocks for that long.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long CPU time is measured for
+pub const CPU_WINDOW: Duration = Duration::from_millis(500);
+
+/// Resource use of one process
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsage {
+    pub pid: u32,
+    /// CPU time used over the sample window as a share of one core, so 200 means two busy
+    /// cores; None where it can't be read
+    pub cpu_percent: Option<f64>,
+    /// Resident set size (working set on Windows)
+    pub memory_bytes: Option<u64>,
+    /// Open file descriptors (handles on Windows); None where it can't be read
+    pub open_handles: Option<u64>,
+    /// How long CPU time was measured for
+    pub sample_ms: u64,
+}
+
+/// Measure the process `pid` over [`CPU_WINDOW`]. None if there's no such process or it
+/// can't be read (it may belong to another user).
+pub fn sample(pid: u32) -> Option<ResourceUsage> {
+    let started = Instant::now();
+    let cpu_before = platform::cpu_time(pid);
+    if cpu_before.is_none() && !platform::exists(pid) {
+        return None;
+    }
+    std::thread::sleep(CPU_WINDOW);
+    let cpu_after = platform::cpu_time(pid);
+    let elapsed = started.elapsed();
+    let cpu_percent = match (cpu_before, cpu_after) {
+        (Some(before), Some(after)) if !elapsed.is_zero() => {
+            Some(after.saturating_sub(before).as_secs_f64() / elapsed.as_secs_f64() * 100.0)
+        }
+        _ => None,
+    };
+    Some(ResourceUsage {
+        pid,
+        cpu_percent,
+        memory_bytes: platform::resident_bytes(pid),
+        open_handles: platform::open_handles(pid),
+        sample_ms: elapsed.as_millis() as u64,
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::time::Duration;
+
+    /// Clock ticks per second in `/proc/<pid>/stat`; fixed at 100 by the kernel ABI
+    const USER_HZ: u64 = 100;
+
+    pub fn exists(pid: u32) -> bool {
+        std::path::Path::new(&format!("/proc/{}", pid)).exists()
+    }
+
+    pub fn cpu_time(pid: u32) -> Option<Duration> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name is in parentheses and may contain spaces. Counting from the state
+        // (field 3) after it, utime and stime (fields 14 and 15) are at indices 11 and 12
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        Some(Duration::from_millis((utime + stime) * 1000 / USER_HZ))
+    }
+
+    pub fn resident_bytes(pid: u32) -> Option<u64> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    pub fn open_handles(pid: u32) -> Option<u64> {
+        let entries = std::fs::read_dir(format!("/proc/{}/fd", pid)).ok()?;
+        Some(entries.count() as u64)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::time::Duration;
+    use windows::Win32::Foundation::{CloseHandle, FILETIME, HANDLE};
+    use windows::Win32::System::ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use windows::Win32::System::Threading::{
+        GetProcessHandleCount, GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    /// Run `read` with a query handle to `pid`
+    fn with_process<T>(pid: u32, read: impl FnOnce(HANDLE) -> Option<T>) -> Option<T> {
+        // SAFETY: the handle is only used inside `read` and closed right after
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+            let value = read(handle);
+            let _ = CloseHandle(handle);
+            value
+        }
+    }
+
+    fn filetime_ticks(time: FILETIME) -> u64 {
+        (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime)
+    }
+
+    pub fn exists(pid: u32) -> bool {
+        with_process(pid, |_| Some(())).is_some()
+    }
+
+    pub fn cpu_time(pid: u32) -> Option<Duration> {
+        with_process(pid, |handle| {
+            let (mut created, mut exited) = (FILETIME::default(), FILETIME::default());
+            let (mut kernel, mut user) = (FILETIME::default(), FILETIME::default());
+            // SAFETY: `handle` is open for querying and every out pointer is valid
+            unsafe { GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user) }
+                .ok()?;
+            // FILETIME counts 100ns intervals
+            let ticks = filetime_ticks(kernel) + filetime_ticks(user);
+            Some(Duration::from_nanos(ticks * 100))
+        })
+    }
+
+    pub fn resident_bytes(pid: u32) -> Option<u64> {
+        with_process(pid, |handle| {
+            let mut counters = PROCESS_MEMORY_COUNTERS::default();
+            let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+            // SAFETY: `handle` is open for querying and `counters` is sized as declared
+            let ok = unsafe { K32GetProcessMemoryInfo(handle, &mut counters, size) };
+            ok.as_bool().then_some(counters.WorkingSetSize as u64)
+        })
+    }
+
+    pub fn open_handles(pid: u32) -> Option<u64> {
+        with_process(pid, |handle| {
+            let mut count = 0u32;
+            // SAFETY: `handle` is open for querying and `count` is a valid out pointer
+            unsafe { GetProcessHandleCount(handle, &mut count) }.ok()?;
+            Some(u64::from(count))
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use std::time::Duration;
+
+    /// One `ps` field of `pid`, trimmed; None if the process is gone
+    fn ps_field(pid: u32, field: &str) -> Option<String> {
+        let output = std::process::Command::new("ps")
+            .args(["-o", field, "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !value.is_empty()).then_some(value)
+    }
+
+    pub fn exists(pid: u32) -> bool {
+        ps_field(pid, "pid=").is_some()
+    }
+
+    pub fn cpu_time(pid: u32) -> Option<Duration> {
+        // "[[dd-]hh:]mm:ss.cc"
+        let time = ps_field(pid, "time=")?;
+        let (days, clock) = match time.split_once('-') {
+            Some((days, clock)) => (days.parse::<u64>().ok()?, clock.to_string()),
+            None => (0, time),
+        };
+        let mut secs = 0.0;
+        for part in clock.split(':') {
+            secs = secs * 60.0 + part.parse::<f64>().ok()?;
+        }
+        Some(Duration::from_secs_f64(days as f64 * 86_400.0 + secs))
+    }
+
+    pub fn resident_bytes(pid: u32) -> Option<u64> {
+        let kib: u64 = ps_field(pid, "rss=")?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    pub fn open_handles(_pid: u32) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,109 @@
+//! Whether the backend the desktop talks to is the one it was built with.
+//!
+//! After an update, release builds can find the previous version's server still answering on
+//! the sidecar port and reuse it; the UI then talks to an API it wasn't built for and breaks
+//! in small ways. Startup compares any server it's about to reuse with [`bundled_backend`]
+//! and replaces one this app started with the bundled sidecar. When it can't (the Windows
+//! Service, or a sidecar another session owns), `version-mismatch` tells the UI.
+
+use crate::http::{self, HttpClient, HttpPolicy};
+use crate::ownership::BackendMode;
+use serde::Serialize;
+
+/// Version of the bundled sidecar, when the release build names it; otherwise it's the app's
+/// own version, which both are released under
+pub const BUNDLED_BACKEND_ENV: Option<&str> = option_env!("ZEROBYTE_SIDECAR_VERSION");
+
+/// Result of `get_versions`
+#[derive(Debug, Clone, Serialize)]
+pub struct Versions {
+    pub app: String,
+    pub bundled_backend: String,
+    /// What the connected backend reports; None when it doesn't say
+    pub backend: Option<String>,
+}
+
+/// Payload of `version-mismatch`
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionMismatch {
+    pub expected: String,
+    pub actual: String,
+    pub mode: BackendMode,
+}
+
+/// The backend version this build ships
+pub fn bundled_backend(app: &tauri::AppHandle) -> String {
+    BUNDLED_BACKEND_ENV
+        .map(str::to_string)
+        .unwrap_or_else(|| app.package_info().version.to_string())
+}
+
+/// Compare versions ignoring a leading `v` and surrounding whitespace
+pub fn same(a: &str, b: &str) -> bool {
+    let normalize = |version: &str| version.trim().trim_start_matches('v').to_string();
+    normalize(a) == normalize(b)
+}
+
+/// Whether `text` reads as a version number rather than, say, an error page
+fn looks_like_version(text: &str) -> bool {
+    text.len() <= 64
+        && text
+            .trim_start_matches('v')
+            .starts_with(|c: char| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-+".contains(c))
+}
+
+/// Ask the backend on `port` for its version at `/api/version`, which answers either
+/// `{"version": "..."}` or the bare version
+pub async fn fetch(http: &HttpClient, port: u16) -> Option<String> {
+    let url = http::local_url(port, "/api/version");
+    let response = http
+        .send(HttpPolicy::STARTUP, |client| client.get(&url))
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let body = response.text().await.ok()?;
+    let version = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Object(object)) => object.get("version")?.as_str()?.to_string(),
+        Ok(serde_json::Value::String(version)) => version,
+        _ => body.trim().to_string(),
+    };
+    looks_like_version(version.trim()).then(|| version.trim().to_string())
+}
+
+/// The version of the backend on `port` if it differs from the bundled one, asking
+/// `/api/version` first and falling back to `reported` (e.g. from its healthcheck). Backends
+/// that give no version can't be compared and count as matching.
+pub async fn mismatch(
+    app: &tauri::AppHandle,
+    http: &HttpClient,
+    port: u16,
+    reported: Option<String>,
+) -> Option<String> {
+    let actual = fetch(http, port).await.or(reported)?;
+    (!same(&actual, &bundled_backend(app))).then_some(actual)
+}
+
+/// Tell the UI the backend runs `actual` and nothing here can change that
+pub fn report(app: &tauri::AppHandle, actual: String, mode: BackendMode) {
+    let expected = bundled_backend(app);
+    tracing::warn!(
+        "The {:?} backend runs version {}, this app bundles {}",
+        mode,
+        actual,
+        expected
+    );
+    crate::events::emit(
+        app,
+        "version-mismatch",
+        VersionMismatch {
+            expected,
+            actual,
+            mode,
+        },
+    );
+}
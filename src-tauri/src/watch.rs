@@ -0,0 +1,703 @@
+//! Filesystem watching for "on change" backup plans.
+//!
+//! The backend can only poll for changes; the desktop gets real filesystem events. Each plan
+//! registers recursive watchers on its source paths, bursts of changes are coalesced into one
+//! batch, and when the batch settles the plan's run endpoint is called with a summary of what
+//! changed. Registrations are persisted in `watchers.json` and re-registered on the next start.
+//!
+//! Every watched directory costs an OS watch descriptor, so the total is capped: a path whose
+//! tree would exceed the cap is skipped and reported with a `watcher-overflow` event.
+
+use crate::http::HttpPolicy;
+use crate::{persist, AppState};
+use notify::Watcher;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::Manager;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// File name of the watcher registrations in the app data directory
+pub const WATCHERS_FILE: &str = "watchers.json";
+
+/// Debounce used when the caller doesn't pick one
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(30);
+
+const MIN_DEBOUNCE: Duration = Duration::from_secs(2);
+const MAX_DEBOUNCE: Duration = Duration::from_secs(60 * 60);
+
+/// A burst that never settles still triggers after this many debounce windows
+const MAX_BATCH_WINDOWS: u32 = 10;
+
+/// Directories watched across all plans
+pub const MAX_WATCHED_DIRS: usize = 20_000;
+
+/// Changed paths listed in a trigger; the rest are only counted
+const MAX_SUMMARY_PATHS: usize = 50;
+
+/// A plan's watched paths, as persisted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchRegistration {
+    pub plan_id: u64,
+    pub paths: Vec<PathBuf>,
+    pub debounce_secs: u64,
+}
+
+impl WatchRegistration {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce_secs).clamp(MIN_DEBOUNCE, MAX_DEBOUNCE)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct WatchDocument {
+    registrations: Vec<WatchRegistration>,
+}
+
+/// What changed during one batch, sent along with the trigger
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChangeSummary {
+    /// Up to [`MAX_SUMMARY_PATHS`] distinct changed paths, sorted
+    pub paths: Vec<PathBuf>,
+    /// Number of change events in the batch
+    pub total: usize,
+    /// Whether more distinct paths changed than are listed
+    pub truncated: bool,
+}
+
+/// Changes collected since the batch opened
+#[derive(Debug)]
+pub struct ChangeBatch {
+    paths: BTreeSet<PathBuf>,
+    total: usize,
+    truncated: bool,
+    opened: Instant,
+    last_change: Instant,
+}
+
+impl ChangeBatch {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            paths: BTreeSet::new(),
+            total: 0,
+            truncated: false,
+            opened: now,
+            last_change: now,
+        }
+    }
+
+    pub fn record(&mut self, path: PathBuf, now: Instant) {
+        self.total += 1;
+        self.last_change = now;
+        if self.paths.len() < MAX_SUMMARY_PATHS {
+            self.paths.insert(path);
+        } else if !self.paths.contains(&path) {
+            self.truncated = true;
+        }
+    }
+
+    /// When the batch closes: `debounce` after the last change, but no later than
+    /// [`MAX_BATCH_WINDOWS`] windows after it opened
+    pub fn deadline(&self, debounce: Duration) -> Instant {
+        (self.last_change + debounce).min(self.opened + debounce * MAX_BATCH_WINDOWS)
+    }
+
+    /// Keep the changes for another window, e.g. while the backend is unreachable
+    pub fn postpone(&mut self, now: Instant) {
+        self.opened = now;
+        self.last_change = now;
+    }
+
+    pub fn summary(&self) -> ChangeSummary {
+        ChangeSummary {
+            paths: self.paths.iter().cloned().collect(),
+            total: self.total,
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// A plan's watcher, as reported to the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct WatcherStatus {
+    pub plan_id: u64,
+    pub paths: Vec<PathBuf>,
+    pub debounce_secs: u64,
+    /// Directories holding a watch descriptor
+    pub watched_dirs: usize,
+    /// Paths left unwatched because their tree would exceed the descriptor cap
+    pub skipped_paths: Vec<PathBuf>,
+    /// Changes waiting for the current batch to close
+    pub pending_changes: usize,
+    /// Seconds since the Unix epoch
+    pub last_triggered_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct WatcherOverflow {
+    plan_id: u64,
+    path: PathBuf,
+    limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct WatcherTriggered {
+    plan_id: u64,
+    changes: ChangeSummary,
+}
+
+struct ActiveWatcher {
+    // Dropping the watcher closes the channel, which ends the plan's task
+    _watcher: notify::RecommendedWatcher,
+    status: Arc<Mutex<WatcherStatus>>,
+}
+
+/// Persisted registrations and the watchers running for them
+pub struct WatcherManager {
+    path: PathBuf,
+    document: Mutex<WatchDocument>,
+    active: Mutex<HashMap<u64, ActiveWatcher>>,
+}
+
+impl WatcherManager {
+    /// Load registrations from `path`; a damaged file yields none
+    pub fn load(path: PathBuf) -> Self {
+        let (document, source) = persist::load_json::<WatchDocument>(&path);
+        if source.recovered() {
+            warn!("Watcher registrations {} were damaged ({:?})", path.display(), source);
+        }
+        Self {
+            path,
+            document: Mutex::new(document),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn registrations(&self) -> Vec<WatchRegistration> {
+        self.document.lock().registrations.clone()
+    }
+
+    pub fn list(&self) -> Vec<WatcherStatus> {
+        let mut statuses: Vec<_> = self
+            .active
+            .lock()
+            .values()
+            .map(|active| active.status.lock().clone())
+            .collect();
+        statuses.sort_by_key(|status| status.plan_id);
+        statuses
+    }
+
+    /// Stop watching for `plan_id` and forget its registration
+    pub fn unwatch(&self, plan_id: u64) -> Result<bool, String> {
+        let removed = self.active.lock().remove(&plan_id).is_some();
+        self.modify(|document| document.registrations.retain(|r| r.plan_id != plan_id))?;
+        Ok(removed)
+    }
+
+    /// Drop registrations without paths and all but the latest registration of each plan,
+    /// and write the rest back (over a damaged file too). Returns how many were dropped.
+    pub fn repair(&self) -> Result<usize, String> {
+        self.modify(|document| {
+            let before = document.registrations.len();
+            let mut seen = HashSet::new();
+            let mut kept: Vec<_> = std::mem::take(&mut document.registrations)
+                .into_iter()
+                .rev()
+                .filter(|r| !r.paths.is_empty() && seen.insert(r.plan_id))
+                .collect();
+            kept.reverse();
+            document.registrations = kept;
+            before - document.registrations.len()
+        })
+    }
+
+    /// Descriptors still available to a plan replacing `plan_id`'s watcher
+    fn remaining_budget(&self, plan_id: u64) -> usize {
+        let used: usize = self
+            .active
+            .lock()
+            .iter()
+            .filter(|(id, _)| **id != plan_id)
+            .map(|(_, active)| active.status.lock().watched_dirs)
+            .sum();
+        MAX_WATCHED_DIRS.saturating_sub(used)
+    }
+
+    fn modify<T>(&self, change: impl FnOnce(&mut WatchDocument) -> T) -> Result<T, String> {
+        let mut current = self.document.lock();
+        let mut updated = current.clone();
+        let result = change(&mut updated);
+
+        persist::save_json(&self.path, &updated)
+            .map_err(|e| format!("Failed to save {}: {}", self.path.display(), e))?;
+
+        *current = updated;
+        Ok(result)
+    }
+}
+
+/// Check saved registrations: Err if they don't parse, otherwise the invariants they break
+pub fn verify(bytes: &[u8]) -> Result<Vec<String>, String> {
+    let document: WatchDocument = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+    for registration in &document.registrations {
+        if !seen.insert(registration.plan_id) {
+            problems.push(format!("plan {} is registered more than once", registration.plan_id));
+        }
+        if registration.paths.is_empty() {
+            problems.push(format!("plan {} has no paths", registration.plan_id));
+        }
+    }
+    Ok(problems)
+}
+
+/// Directories the backend and the desktop write to themselves; changes there never trigger
+fn ignored_roots(app: &tauri::AppHandle) -> Vec<PathBuf> {
+    let state = app.state::<AppState>();
+    let mut roots = vec![state.paths().data_dir.clone()];
+    if let Some(dir) = std::env::var_os(crate::paths::DATA_DIR_ENV) {
+        roots.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = state.config().data_dir {
+        roots.push(dir);
+    }
+
+    // Same defaults as the server's getZerobytePath()
+    #[cfg(target_os = "windows")]
+    {
+        if let Some(appdata) = std::env::var_os("APPDATA") {
+            roots.push(PathBuf::from(appdata).join("C3i Backup ONE"));
+        }
+        let programdata =
+            std::env::var_os("PROGRAMDATA").unwrap_or_else(|| "C:\\ProgramData".into());
+        roots.push(PathBuf::from(programdata).join("C3i Backup ONE"));
+    }
+    #[cfg(target_os = "macos")]
+    if let Some(home) = std::env::var_os("HOME") {
+        roots.push(PathBuf::from(home).join("Library/Application Support/C3i Backup ONE"));
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        roots.push(PathBuf::from("/var/lib/c3i-backup-one"));
+        if let Some(home) = std::env::var_os("HOME") {
+            roots.push(PathBuf::from(home).join(".local/share/c3i-backup-one"));
+        }
+    }
+    roots
+}
+
+/// Count the directories under `root` (itself included), skipping symlinks and ignored
+/// trees. Returns None as soon as the count exceeds `limit`.
+pub fn count_dirs(root: &Path, ignored: &[PathBuf], limit: usize) -> Option<usize> {
+    let mut count = 0;
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        count += 1;
+        if count > limit {
+            return None;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            let path = entry.path();
+            if is_dir && !ignored.iter().any(|root| path.starts_with(root)) {
+                stack.push(path);
+            }
+        }
+    }
+    Some(count)
+}
+
+/// Watch `registration`'s paths, replacing any watcher the plan already had, and persist it
+pub async fn watch(
+    app: &tauri::AppHandle,
+    registration: WatchRegistration,
+) -> Result<WatcherStatus, String> {
+    let plan_id = registration.plan_id;
+    let manager = app.state::<WatcherManager>();
+    let ignored = ignored_roots(app);
+
+    for path in &registration.paths {
+        if !path.is_dir() {
+            return Err(format!("{} is not a directory", path.display()));
+        }
+        if ignored.iter().any(|root| path.starts_with(root)) {
+            return Err(format!("{} is inside the app's own data directory", path.display()));
+        }
+    }
+
+    // Walking large trees is slow; keep it off the async runtime
+    let budget = manager.remaining_budget(plan_id);
+    let paths = registration.paths.clone();
+    let walk_ignored = ignored.clone();
+    let counts = tauri::async_runtime::spawn_blocking(move || {
+        let mut remaining = budget;
+        paths
+            .into_iter()
+            .map(|path| {
+                let count = count_dirs(&path, &walk_ignored, remaining);
+                if let Some(count) = count {
+                    remaining -= count;
+                }
+                (path, count)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| format!("Failed to scan watched paths: {}", e))?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+
+    let mut watched_dirs = 0;
+    let mut skipped_paths = Vec::new();
+    for (path, count) in counts {
+        let Some(count) = count else {
+            warn!(
+                "Not watching {} for plan {}: more than {} directories",
+                path.display(),
+                plan_id,
+                MAX_WATCHED_DIRS
+            );
+            crate::events::emit(
+                app,
+                "watcher-overflow",
+                WatcherOverflow {
+                    plan_id,
+                    path: path.clone(),
+                    limit: MAX_WATCHED_DIRS,
+                },
+            );
+            skipped_paths.push(path);
+            continue;
+        };
+        watcher
+            .watch(&path, notify::RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", path.display(), e))?;
+        watched_dirs += count;
+    }
+    if watched_dirs == 0 {
+        return Err("None of the paths can be watched".into());
+    }
+
+    let status = Arc::new(Mutex::new(WatcherStatus {
+        plan_id,
+        paths: registration.paths.clone(),
+        debounce_secs: registration.debounce().as_secs(),
+        watched_dirs,
+        skipped_paths,
+        pending_changes: 0,
+        last_triggered_at: None,
+        last_error: None,
+    }));
+    crate::supervisor::spawn_once(
+        app,
+        format!("watch-plan-{}", plan_id),
+        run_plan(
+            app.clone(),
+            plan_id,
+            registration.debounce(),
+            rx,
+            status.clone(),
+            ignored,
+        ),
+    );
+
+    manager.modify(|document| {
+        document.registrations.retain(|r| r.plan_id != plan_id);
+        document.registrations.push(registration);
+    })?;
+    let snapshot = status.lock().clone();
+    manager.active.lock().insert(
+        plan_id,
+        ActiveWatcher {
+            _watcher: watcher,
+            status,
+        },
+    );
+
+    info!(
+        "Watching {} director(ies) for plan {}",
+        snapshot.watched_dirs, plan_id
+    );
+    Ok(snapshot)
+}
+
+/// Re-register persisted watchers after a restart
+pub async fn restore_all(app: tauri::AppHandle) {
+    let registrations = app.state::<WatcherManager>().registrations();
+    for registration in registrations {
+        let plan_id = registration.plan_id;
+        if let Err(e) = watch(&app, registration).await {
+            warn!("Failed to restore watcher for plan {}: {}", plan_id, e);
+        }
+    }
+}
+
+enum TriggerOutcome {
+    Triggered,
+    /// The backend refused (e.g. the plan is gone); the batch is dropped
+    Rejected(String),
+    /// Unreachable or failing; the batch is kept for another window
+    Unavailable(String),
+}
+
+async fn trigger(
+    app: &tauri::AppHandle,
+    plan_id: u64,
+    changes: &ChangeSummary,
+) -> TriggerOutcome {
+    let state = app.state::<AppState>();
+    if !state.backend_ready.load(Ordering::SeqCst) {
+        return TriggerOutcome::Unavailable("backend is not ready".into());
+    }
+    let port = state.backend_port.load(Ordering::SeqCst);
+    let url = format!("http://localhost:{}/api/v1/backups/{}/run", port, plan_id);
+    let body = serde_json::json!({ "reason": "file_change", "changes": changes });
+
+    match state
+        .http
+        .send(HttpPolicy::BACKGROUND, |client| client.post(&url).json(&body))
+        .await
+    {
+        Ok(response) if response.status().is_success() => TriggerOutcome::Triggered,
+        Ok(response) if response.status().is_client_error() => {
+            TriggerOutcome::Rejected(format!("backend answered {}", response.status()))
+        }
+        Ok(response) => {
+            TriggerOutcome::Unavailable(format!("backend answered {}", response.status()))
+        }
+        Err(e) => TriggerOutcome::Unavailable(e.to_string()),
+    }
+}
+
+/// Coalesce one plan's filesystem events and trigger the plan when a batch closes
+async fn run_plan(
+    app: tauri::AppHandle,
+    plan_id: u64,
+    debounce: Duration,
+    mut rx: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    status: Arc<Mutex<WatcherStatus>>,
+    ignored: Vec<PathBuf>,
+) {
+    let mut batch: Option<ChangeBatch> = None;
+    loop {
+        let deadline = batch.as_ref().map(|batch| batch.deadline(debounce));
+        let closed = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            event = rx.recv() => match event {
+                // Watcher dropped: the plan was unwatched or replaced
+                None => return,
+                Some(Ok(event)) => {
+                    if event.kind.is_access() {
+                        continue;
+                    }
+                    let now = Instant::now();
+                    for path in event.paths {
+                        if ignored.iter().any(|root| path.starts_with(root)) {
+                            continue;
+                        }
+                        batch.get_or_insert_with(|| ChangeBatch::new(now)).record(path, now);
+                    }
+                    if let Some(batch) = &batch {
+                        status.lock().pending_changes = batch.total;
+                    }
+                }
+                Some(Err(e)) => {
+                    warn!("File watcher error for plan {}: {}", plan_id, e);
+                    status.lock().last_error = Some(e.to_string());
+                }
+            },
+            _ = closed => {
+                let Some(current) = batch.as_mut() else {
+                    continue;
+                };
+                let changes = current.summary();
+                match trigger(&app, plan_id, &changes).await {
+                    TriggerOutcome::Triggered => {
+                        info!("Triggered plan {} after {} change(s)", plan_id, changes.total);
+                        let mut status = status.lock();
+                        status.pending_changes = 0;
+                        status.last_error = None;
+                        status.last_triggered_at = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .ok();
+                        batch = None;
+                        let triggered = WatcherTriggered { plan_id, changes };
+                        crate::events::emit(&app, "watcher-triggered", triggered);
+                    }
+                    TriggerOutcome::Rejected(reason) => {
+                        warn!("Dropping changes for plan {}: {}", plan_id, reason);
+                        let mut status = status.lock();
+                        status.pending_changes = 0;
+                        status.last_error = Some(reason);
+                        batch = None;
+                    }
+                    TriggerOutcome::Unavailable(reason) => {
+                        info!("Holding changes for plan {}: {}", plan_id, reason);
+                        status.lock().last_error = Some(reason);
+                        current.postpone(Instant::now());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn temp_tree(name: &str, dirs: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("watch-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in dirs {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn each_change_pushes_the_deadline_back() {
+        let start = Instant::now();
+        let mut batch = ChangeBatch::new(start);
+        batch.record("a.txt".into(), start);
+        assert_eq!(batch.deadline(30 * SECOND), start + 30 * SECOND);
+
+        batch.record("b.txt".into(), start + 20 * SECOND);
+        assert_eq!(batch.deadline(30 * SECOND), start + 50 * SECOND);
+    }
+
+    #[test]
+    fn endless_burst_still_closes_after_the_window_cap() {
+        let start = Instant::now();
+        let mut batch = ChangeBatch::new(start);
+        // A change every 10s never lets a 30s debounce settle
+        for i in 0..100 {
+            batch.record(format!("{}.log", i).into(), start + 10 * SECOND * i);
+        }
+        assert_eq!(
+            batch.deadline(30 * SECOND),
+            start + 30 * SECOND * MAX_BATCH_WINDOWS
+        );
+    }
+
+    #[test]
+    fn repeated_changes_to_a_path_are_coalesced() {
+        let start = Instant::now();
+        let mut batch = ChangeBatch::new(start);
+        for _ in 0..5 {
+            batch.record("notes.txt".into(), start);
+        }
+        batch.record("a.txt".into(), start);
+
+        assert_eq!(
+            batch.summary(),
+            ChangeSummary {
+                paths: vec!["a.txt".into(), "notes.txt".into()],
+                total: 6,
+                truncated: false,
+            }
+        );
+    }
+
+    #[test]
+    fn summary_lists_a_bounded_number_of_paths() {
+        let start = Instant::now();
+        let mut batch = ChangeBatch::new(start);
+        for i in 0..MAX_SUMMARY_PATHS {
+            batch.record(format!("{:03}", i).into(), start);
+        }
+        // A listed path changing again doesn't count as overflow
+        batch.record("000".into(), start);
+        assert!(!batch.summary().truncated);
+
+        batch.record("new".into(), start);
+        let summary = batch.summary();
+        assert!(summary.truncated);
+        assert_eq!(summary.paths.len(), MAX_SUMMARY_PATHS);
+        assert_eq!(summary.total, MAX_SUMMARY_PATHS + 2);
+    }
+
+    #[test]
+    fn postponed_batch_keeps_its_changes_for_another_window() {
+        let start = Instant::now();
+        let mut batch = ChangeBatch::new(start);
+        batch.record("a.txt".into(), start);
+
+        let retry = start + 300 * SECOND;
+        batch.postpone(retry);
+        assert_eq!(batch.deadline(30 * SECOND), retry + 30 * SECOND);
+        assert_eq!(batch.summary().total, 1);
+    }
+
+    #[test]
+    fn debounce_is_clamped() {
+        let registration = |debounce_secs| WatchRegistration {
+            plan_id: 1,
+            paths: vec![],
+            debounce_secs,
+        };
+        assert_eq!(registration(0).debounce(), MIN_DEBOUNCE);
+        assert_eq!(registration(45).debounce(), 45 * SECOND);
+        assert_eq!(registration(u64::MAX).debounce(), MAX_DEBOUNCE);
+    }
+
+    #[test]
+    fn count_dirs_stops_at_the_limit() {
+        let root = temp_tree("limit", &["a/b/c", "d", "e/f"]);
+        assert_eq!(count_dirs(&root, &[], 100), Some(7));
+        assert_eq!(count_dirs(&root, &[], 7), Some(7));
+        assert_eq!(count_dirs(&root, &[], 6), None);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn count_dirs_skips_ignored_trees() {
+        let root = temp_tree("ignored", &["docs/2024", "data/repo/snapshots"]);
+        let ignored = vec![root.join("data")];
+        assert_eq!(count_dirs(&root, &ignored, 100), Some(3));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn verify_reports_duplicate_and_empty_registrations() {
+        let bytes = br#"{"registrations":[
+            {"plan_id":1,"paths":["/a"],"debounce_secs":30},
+            {"plan_id":1,"paths":["/b"],"debounce_secs":30},
+            {"plan_id":2,"paths":[],"debounce_secs":30}
+        ]}"#;
+        assert_eq!(
+            verify(bytes).unwrap(),
+            vec![
+                "plan 1 is registered more than once".to_string(),
+                "plan 2 has no paths".to_string(),
+            ]
+        );
+        assert!(verify(b"{").is_err());
+    }
+}